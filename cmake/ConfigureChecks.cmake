@@ -58,35 +58,6 @@ if (DISABLE_STATIC_DESTRUCTORS)
 endif()
 
 
-# Try using CMake's own logic to locate curses/ncurses
-find_package(Curses)
-if(NOT ${CURSES_FOUND})
-    # CMake has trouble finding platform-specific system libraries
-    # installed to multiarch paths (e.g. /usr/lib/x86_64-linux-gnu)
-    # if not symlinked or passed in as a manual define.
-    message("Falling back to pkg-config for (n)curses detection")
-    include(FindPkgConfig)
-    pkg_search_module(CURSES REQUIRED ncurses curses)
-    set(CURSES_CURSES_LIBRARY ${CURSES_LIBRARIES})
-    set(CURSES_LIBRARY ${CURSES_LIBRARIES})
-endif()
-# Set up extra include directories for CheckIncludeFile
-list(APPEND CMAKE_REQUIRED_INCLUDES ${CURSES_INCLUDE_DIRS})
-
-# Fix undefined reference to tparm on RHEL 6 and potentially others
-# If curses is found via CMake, it also links against tinfo if it exists. But if we use our
-# fallback pkg-config logic above, we need to do this manually.
-find_library(CURSES_TINFO tinfo)
-if (CURSES_TINFO)
-    set(CURSES_LIBRARY ${CURSES_LIBRARY} ${CURSES_TINFO})
-else()
-    # on NetBSD, libtinfo has a longer name (libterminfo)
-    find_library(CURSES_TINFO terminfo)
-    if (CURSES_TINFO)
-        set(CURSES_LIBRARY ${CURSES_LIBRARY} ${CURSES_TINFO})
-    endif()
-endif()
-
 # Get threads.
 set(THREADS_PREFER_PTHREAD_FLAG ON)
 find_package(Threads REQUIRED)
@@ -123,21 +94,13 @@ check_include_file_cxx(execinfo.h HAVE_EXECINFO_H)
 check_cxx_symbol_exists(flock sys/file.h HAVE_FLOCK)
 check_cxx_symbol_exists(getpwent pwd.h HAVE_GETPWENT)
 check_cxx_symbol_exists(getrusage sys/resource.h HAVE_GETRUSAGE)
-check_cxx_symbol_exists(gettext libintl.h HAVE_GETTEXT)
 check_cxx_symbol_exists(killpg "sys/types.h;signal.h" HAVE_KILLPG)
 # mkostemp is in stdlib in glibc and FreeBSD, but unistd on macOS
 check_cxx_symbol_exists(mkostemp "stdlib.h;unistd.h" HAVE_MKOSTEMP)
-set(HAVE_CURSES_H ${CURSES_HAVE_CURSES_H})
-set(HAVE_NCURSES_CURSES_H ${CURSES_HAVE_NCURSES_CURSES_H})
-set(HAVE_NCURSES_H ${CURSES_HAVE_NCURSES_H})
-if(HAVE_CURSES_H)
-    check_include_files("curses.h;term.h" HAVE_TERM_H)
-endif()
-if(NOT HAVE_TERM_H)
-    check_include_file_cxx("ncurses/term.h" HAVE_NCURSES_TERM_H)
-endif()
 check_include_file_cxx(siginfo.h HAVE_SIGINFO_H)
 check_include_file_cxx(spawn.h HAVE_SPAWN_H)
+check_cxx_symbol_exists(posix_spawn_file_actions_addfchdir_np spawn.h
+    HAVE_POSIX_SPAWN_FILE_ACTIONS_ADDFCHDIR_NP)
 check_struct_has_member("struct stat" st_ctime_nsec "sys/stat.h" HAVE_STRUCT_STAT_ST_CTIME_NSEC
     LANGUAGE CXX)
 check_struct_has_member("struct stat" st_mtimespec.tv_nsec "sys/stat.h"
@@ -156,6 +119,8 @@ check_include_files("sys/types.h;sys/sysctl.h" HAVE_SYS_SYSCTL_H)
 SET(CMAKE_C_FLAGS "${OLD_CMAKE_C_FLAGS}")
 
 check_cxx_symbol_exists(eventfd sys/eventfd.h HAVE_EVENTFD)
+check_cxx_symbol_exists(inotify_init1 sys/inotify.h HAVE_INOTIFY_INIT1)
+check_cxx_symbol_exists(kqueue "sys/types.h;sys/event.h" HAVE_KQUEUE)
 check_cxx_symbol_exists(pipe2 unistd.h HAVE_PIPE2)
 check_cxx_symbol_exists(wcscasecmp wchar.h HAVE_WCSCASECMP)
 check_cxx_symbol_exists(wcsncasecmp wchar.h HAVE_WCSNCASECMP)
@@ -188,59 +153,6 @@ cmake_pop_check_state()
 
 check_type_size("wchar_t[8]" WCHAR_T_BITS LANGUAGE CXX)
 
-set(TPARM_INCLUDES)
-if(HAVE_NCURSES_H)
-  set(TPARM_INCLUDES "${TPARM_INCLUDES}#include <ncurses.h>\n")
-elseif(HAVE_NCURSES_CURSES_H)
-  set(TPARM_INCLUDES "${TPARM_INCLUDES}#include <ncurses/curses.h>\n")
-else()
-  set(TPARM_INCLUDES "${TPARM_INCLUDES}#include <curses.h>\n")
-endif()
-
-if(HAVE_TERM_H)
-  set(TPARM_INCLUDES "${TPARM_INCLUDES}#include <term.h>\n")
-elseif(HAVE_NCURSES_TERM_H)
-  set(TPARM_INCLUDES "${TPARM_INCLUDES}#include <ncurses/term.h>\n")
-endif()
-
-cmake_push_check_state()
-list(APPEND CMAKE_REQUIRED_LIBRARIES ${CURSES_LIBRARY})
-# Solaris and X/Open-conforming systems have a fixed-args tparm
-check_cxx_source_compiles("
-#define TPARM_VARARGS
-${TPARM_INCLUDES}
-
-int main () {
-  tparm( \"\" );
-}
-"
-  TPARM_TAKES_VARARGS
-)
-
-
-# Check if tputs needs a function reading an int or char.
-# The only curses I can find that needs a char is OpenIndiana.
-check_cxx_source_compiles("
-#include <curses.h>
-#include <term.h>
-
-static int writer(int b) {
-    return b;
-}
-
-int main() {
-    return tputs(\"foo\", 5, writer);
-}"
-TPUTS_USES_INT_ARG
-)
-
-if(TPARM_TAKES_VARARGS)
-  set(TPARM_VARARGS 1)
-else()
-  set(TPARM_SOLARIS_KLUDGE 1)
-endif()
-cmake_pop_check_state()
-
 # Work around the fact that cmake does not propagate the language standard flag into
 # the CHECK_CXX_SOURCE_COMPILES function. See CMake issue #16456.
 # Ensure we do this after the FIND_PACKAGE calls which use C, and will error on a C++
@@ -19,6 +19,9 @@ file(RELATIVE_PATH rel_datadir ${CMAKE_INSTALL_PREFIX} ${datadir})
 
 set(docdir ${CMAKE_INSTALL_DOCDIR})
 
+set(includedir ${CMAKE_INSTALL_FULL_INCLUDEDIR})
+file(RELATIVE_PATH rel_includedir ${CMAKE_INSTALL_PREFIX} ${includedir})
+
 # Comment at the top of some .in files
 set(configure_input
 "This file was generated from a corresponding .in file.\
@@ -119,6 +122,10 @@ add_custom_target(build_fish_pc ALL DEPENDS fish.pc)
 install(FILES ${CMAKE_CURRENT_BINARY_DIR}/fish.pc
         DESTINATION ${rel_datadir}/pkgconfig)
 
+# The interface for native plugins.
+install(FILES src/fish_plugin.h
+        DESTINATION ${rel_includedir}/fish)
+
 install(DIRECTORY share/completions/
         DESTINATION ${rel_datadir}/fish/completions
         FILES_MATCHING PATTERN "*.fish")
@@ -138,9 +145,6 @@ install(DIRECTORY ${CMAKE_CURRENT_BINARY_DIR}/user_doc/man/man1/
         PATTERN "*.1"
         PATTERN ${CONDEMNED_PAGE} EXCLUDE)
 
-install(PROGRAMS share/tools/create_manpage_completions.py share/tools/deroff.py
-        DESTINATION ${rel_datadir}/fish/tools/)
-
 install(DIRECTORY share/tools/web_config
         DESTINATION ${rel_datadir}/fish/tools/
         FILES_MATCHING
@@ -35,6 +35,10 @@ if(DEFINED ASAN)
     list(APPEND CARGO_FLAGS "-Z" "build-std")
     list(APPEND FISH_CRATE_FEATURES "asan")
 endif()
+option(WITH_WASM_PLUGINS "support WebAssembly plugins (requires wasmtime)" OFF)
+if(WITH_WASM_PLUGINS)
+    list(APPEND FISH_CRATE_FEATURES "wasm-plugins")
+endif()
 
 corrosion_import_crate(
     MANIFEST_PATH "${CMAKE_SOURCE_DIR}/fish-rust/Cargo.toml"
@@ -51,12 +55,7 @@ else()
     corrosion_set_hostbuild(${fish_rust_target})
 endif()
 
-# Temporary hack to propogate CMake flags/options to build.rs. We need to get CMake to evaluate the
-# truthiness of the strings if they are set.
-set(CMAKE_WITH_GETTEXT "1")
-if(DEFINED WITH_GETTEXT AND NOT "${WITH_GETTEXT}")
-    set(CMAKE_WITH_GETTEXT "0")
-endif()
+include(GNUInstallDirs)
 
 # Tell Cargo where our build directory is so it can find config.h.
 corrosion_set_env_vars(${fish_rust_target}
@@ -64,8 +63,8 @@ corrosion_set_env_vars(${fish_rust_target}
     "FISH_AUTOCXX_GEN_DIR=${fish_autocxx_gen_dir}"
     "FISH_RUST_TARGET_DIR=${rust_target_dir}"
     "PREFIX=${CMAKE_INSTALL_PREFIX}"
-    # Temporary hack to propogate CMake flags/options to build.rs.
-    "CMAKE_WITH_GETTEXT=${CMAKE_WITH_GETTEXT}"
+    # Where the message catalogs are installed.
+    "LOCALEDIR=${CMAKE_INSTALL_FULL_LOCALEDIR}"
 )
 
 target_include_directories(${fish_rust_target} INTERFACE
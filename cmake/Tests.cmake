@@ -124,11 +124,14 @@ add_custom_target(tests_buildroot_target
                   # Put fish_test_helper there too:
                   COMMAND ${CMAKE_COMMAND} -E copy ${CMAKE_BINARY_DIR}/fish_test_helper
                           ${TEST_INSTALL_DIR}/${CMAKE_INSTALL_PREFIX}/bin
+                  # And the plugin for testing plugins:
+                  COMMAND ${CMAKE_COMMAND} -E copy $<TARGET_FILE:fish_test_plugin>
+                          ${TEST_INSTALL_DIR}/${CMAKE_INSTALL_PREFIX}/bin
                   # Also symlink fish to where the tests expect it to be:
                   COMMAND ${CMAKE_COMMAND} -E create_symlink
                           ${TEST_INSTALL_DIR}/${CMAKE_INSTALL_PREFIX}
                           ${TEST_ROOT_DIR}
-                  DEPENDS fish fish_test_helper)
+                  DEPENDS fish fish_test_helper fish_test_plugin)
 
 # CMake less than 3.9.0 "fully supports" setting an exit code to denote a skipped test, but then
 # it just goes ahead and reports it as failed. Really?
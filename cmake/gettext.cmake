@@ -2,26 +2,12 @@ set(languages de en fr pl pt_BR sv zh_CN)
 
 include(FeatureSummary)
 
-option(WITH_GETTEXT "translate messages if gettext is available" ON)
+# fish reads the message catalogs itself, so gettext's tools are only needed to build them.
+option(WITH_GETTEXT "build and install message translations if msgfmt is available" ON)
 if(WITH_GETTEXT)
-  if(APPLE)
-    # Fix for https://github.com/fish-shell/fish-shell/issues/5244
-    # via https://gitlab.kitware.com/cmake/cmake/-/issues/18921
-    set(CMAKE_FIND_FRAMEWORK_OLD ${CMAKE_FIND_FRAMEWORK})
-    set(CMAKE_FIND_FRAMEWORK NEVER)
-  endif()
-  find_package(Intl QUIET)
   find_package(Gettext)
-  if(GETTEXT_FOUND)
-      set(HAVE_GETTEXT 1)
-      include_directories(${Intl_INCLUDE_DIR})
-  endif()
-  if(APPLE)
-    set(CMAKE_FIND_FRAMEWORK ${CMAKE_FIND_FRAMEWORK_OLD})
-    unset(CMAKE_FIND_FRAMEWORK_OLD)
-  endif()
 endif()
-add_feature_info(gettext GETTEXT_FOUND "translate messages with gettext")
+add_feature_info(gettext GETTEXT_FOUND "build message translations with msgfmt")
 
 # Define translations
 if(GETTEXT_FOUND)
@@ -35,22 +21,3 @@ if(GETTEXT_FOUND)
   endforeach()
   set(CMAKE_FOLDER)
 endif()
-
-cmake_push_check_state()
-set(CMAKE_REQUIRED_INCLUDES ${CMAKE_REQUIRED_INCLUDES} ${Intl_INCLUDE_DIR})
-set(CMAKE_REQUIRED_LIBRARIES ${CMAKE_REQUIRED_LIBRARIES} ${Intl_LIBRARIES})
-# libintl.h can be compiled into the stdlib on some GLibC systems
-if(Intl_FOUND AND Intl_LIBRARIES)
-  set(LIBINTL_INCLUDE "#include <libintl.h>")
-endif()
-check_cxx_source_compiles("
-${LIBINTL_INCLUDE}
-#include <stdlib.h>
-int main () {
-    extern int  _nl_msg_cat_cntr;
-    int tmp = _nl_msg_cat_cntr;
-    exit(tmp);
-}
-"
-    HAVE__NL_MSG_CAT_CNTR)
-cmake_pop_check_state()
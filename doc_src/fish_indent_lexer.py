@@ -43,18 +43,22 @@ ROLE_TO_TOKEN = {
     "autosuggestion": Other,  # in practice won't be generated
     "selection": DEFAULT,
     "pager_progress": DEFAULT,
+    "pager_group": DEFAULT,
     "pager_background": DEFAULT,
     "pager_prefix": DEFAULT,
     "pager_completion": DEFAULT,
     "pager_description": DEFAULT,
+    "pager_match": DEFAULT,
     "pager_secondary_background": DEFAULT,
     "pager_secondary_prefix": DEFAULT,
     "pager_secondary_completion": DEFAULT,
     "pager_secondary_description": DEFAULT,
+    "pager_secondary_match": DEFAULT,
     "pager_selected_background": DEFAULT,
     "pager_selected_prefix": DEFAULT,
     "pager_selected_completion": DEFAULT,
     "pager_selected_description": DEFAULT,
+    "pager_selected_match": DEFAULT,
 }
 
 
@@ -34,14 +34,28 @@ fn main() {
     detector.set_verbose(true);
     detect_features(detector);
 
+    // Where the message catalogs are installed. CMake tells us; otherwise assume the usual place
+    // under the prefix.
+    rsconf::rebuild_if_env_changed("LOCALEDIR");
+    rsconf::rebuild_if_env_changed("PREFIX");
+    let localedir = std::env::var("LOCALEDIR").unwrap_or_else(|_| {
+        let prefix = std::env::var("PREFIX").unwrap_or("/usr/local".to_owned());
+        format!("{}/share/locale", prefix)
+    });
+    println!("cargo:rustc-env=LOCALEDIR={}", localedir);
+
     // Emit cxx junk.
     // This allows "Rust to be used from C++"
     // This must come before autocxx so that cxx can emit its cxx.h header.
     let source_files = vec![
         "src/abbrs.rs",
         "src/ast.rs",
+        "src/ast_cache.rs",
+        "src/bidi.rs",
         "src/builtins/shared.rs",
         "src/common.rs",
+        "src/crash_handler.rs",
+        "src/embed.rs",
         "src/env/env_ffi.rs",
         "src/env_dispatch.rs",
         "src/event.rs",
@@ -53,15 +67,22 @@ fn main() {
         "src/fish_indent.rs",
         "src/future_feature_flags.rs",
         "src/highlight.rs",
+        "src/history_crypt.rs",
         "src/job_group.rs",
+        "src/json.rs",
+        "src/kitty_keys.rs",
         "src/null_terminated_array.rs",
+        "src/output.rs",
         "src/parse_constants.rs",
         "src/parse_tree.rs",
         "src/parse_util.rs",
+        "src/plugins.rs",
         "src/redirection.rs",
         "src/signal.rs",
         "src/smoke.rs",
+        "src/terminal_probe.rs",
         "src/termsize.rs",
+        "src/text_object.rs",
         "src/threads.rs",
         "src/timer.rs",
         "src/tokenizer.rs",
@@ -69,6 +90,8 @@ fn main() {
         "src/trace.rs",
         "src/util.rs",
         "src/wait_handle.rs",
+        "src/watchdog.rs",
+        "src/wutil/gettext.rs",
     ];
     cxx_build::bridges(&source_files)
         .flag_if_supported("-std=c++11")
@@ -95,6 +118,20 @@ fn main() {
         .flag("-Wno-comment")
         .compile("fish-rust-autocxx");
     rsconf::rebuild_if_paths_changed(&source_files);
+
+    // A program embedding fish links us as an rlib, so it also needs the C++ half from the CMake
+    // build, which the fish executables get by linking us into it instead.
+    if std::env::var_os("CARGO_FEATURE_EMBED").is_some() {
+        rsconf::add_library_search_path(&fish_build_dir);
+        rsconf::link_libraries(&["fishlib"], LinkType::Static);
+        let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+        let cxx_runtime = if target_os == "macos" {
+            "c++"
+        } else {
+            "stdc++"
+        };
+        rsconf::link_libraries(&[cxx_runtime], LinkType::Default);
+    }
 }
 
 /// Dynamically enables certain features at build-time, without their having to be explicitly
@@ -114,7 +151,6 @@ fn detect_features(target: Target) {
             &(|_: &Target| Ok(false)) as &dyn Fn(&Target) -> Result<bool, Box<dyn Error>>,
         ),
         ("bsd", &detect_bsd),
-        ("gettext", &have_gettext),
     ] {
         match handler(&target) {
             Err(e) => rsconf::warn!("{}: {}", feature, e),
@@ -147,48 +183,3 @@ fn detect_bsd(_: &Target) -> Result<bool, Box<dyn Error>> {
     assert!(result, "Target incorrectly detected as not BSD!");
     Ok(result)
 }
-
-/// Detect libintl/gettext and its needed symbols to enable internationalization/localization
-/// support.
-fn have_gettext(target: &Target) -> Result<bool, Box<dyn Error>> {
-    // The following script correctly detects and links against gettext, but so long as we are using
-    // C++ and generate a static library linked into the C++ binary via CMake, we need to account
-    // for the CMake option WITH_GETTEXT being explicitly disabled.
-    rsconf::rebuild_if_env_changed("CMAKE_WITH_GETTEXT");
-    if let Some(with_gettext) = std::env::var_os("CMAKE_WITH_GETTEXT") {
-        if with_gettext.eq_ignore_ascii_case("0") {
-            return Ok(false);
-        }
-    }
-
-    // In order for fish to correctly operate, we need some way of notifying libintl to invalidate
-    // its localizations when the locale environment variables are modified. Without the libintl
-    // symbol _nl_msg_cat_cntr, we cannot use gettext even if we find it.
-    let mut libraries = Vec::new();
-    let mut found = 0;
-    let symbols = ["gettext", "_nl_msg_cat_cntr"];
-    for symbol in &symbols {
-        // Historically, libintl was required in order to use gettext() and co, but that
-        // functionality was subsumed by some versions of libc.
-        if target.has_symbol_in::<&str>(symbol, &[]) {
-            // No need to link anything special for this symbol
-            found += 1;
-            continue;
-        }
-        for library in ["intl", "gettextlib"] {
-            if target.has_symbol(symbol, library) {
-                libraries.push(library);
-                found += 1;
-                continue;
-            }
-        }
-    }
-    match found {
-        0 => Ok(false),
-        1 => Err(format!("gettext found but cannot be used without {}", symbols[1]).into()),
-        _ => {
-            rsconf::link_libraries(&libraries, LinkType::Default);
-            Ok(true)
-        }
-    }
-}
@@ -34,6 +34,8 @@ mod abbrs_ffi {
         is_function: bool,
         set_cursor_marker: UniquePtr<CxxWString>,
         has_cursor_marker: bool,
+        condition: UniquePtr<CxxWString>,
+        has_condition: bool,
     }
 
     struct abbrs_replacement_t {
@@ -41,6 +43,7 @@ mod abbrs_ffi {
         text: UniquePtr<CxxWString>,
         cursor: usize,
         has_cursor: bool,
+        placeholders: Vec<usize>,
     }
 
     struct abbreviation_t {
@@ -144,6 +147,9 @@ pub struct Abbreviation {
     /// If set, then move the cursor to the first instance of this string in the expansion.
     pub set_cursor_marker: Option<WString>,
 
+    /// If set, fish script that must succeed for the abbreviation to expand.
+    pub condition: Option<WString>,
+
     /// Mark if we came from a universal variable.
     pub from_universal: bool,
 }
@@ -166,6 +172,7 @@ impl Abbreviation {
             replacement_is_function: false,
             position,
             set_cursor_marker: None,
+            condition: None,
             from_universal,
         }
     }
@@ -204,16 +211,22 @@ pub struct Replacer {
 
     /// If set, the cursor should be moved to the first instance of this string in the expansion.
     set_cursor_marker: Option<WString>,
+
+    /// If set, fish script that must succeed for this replacer to be used.
+    condition: Option<WString>,
 }
 
 impl From<Replacer> for abbrs_replacer_t {
     fn from(value: Replacer) -> Self {
         let has_cursor_marker = value.set_cursor_marker.is_some();
+        let has_condition = value.condition.is_some();
         Self {
             replacement: value.replacement.to_ffi(),
             is_function: value.is_function,
             set_cursor_marker: value.set_cursor_marker.unwrap_or_default().to_ffi(),
             has_cursor_marker,
+            condition: value.condition.unwrap_or_default().to_ffi(),
+            has_condition,
         }
     }
 }
@@ -228,29 +241,49 @@ struct Replacement {
     /// The new cursor location, or none to use the default.
     /// This is relative to the original range.
     cursor: Option<usize>,
+
+    /// The locations of the placeholders that the cursor can move to after the expansion, in
+    /// order. These are also relative to the original range.
+    placeholders: Vec<usize>,
 }
 
 impl Replacement {
     /// Construct a replacement from a replacer.
     /// The \p range is the range of the text matched by the replacer in the command line.
     /// The text is passed in separately as it may be the output of the replacer's function.
+    /// The cursor goes to the first lone instance of the marker. Each instance of the marker
+    /// followed by `{}` is a placeholder; if there is no lone marker, the cursor goes to the first
+    /// of those instead.
     fn from(range: SourceRange, mut text: WString, set_cursor_marker: Option<WString>) -> Self {
         let mut cursor = None;
-        if let Some(set_cursor_marker) = set_cursor_marker {
-            let matched = text
-                .as_char_slice()
-                .windows(set_cursor_marker.len())
-                .position(|w| w == set_cursor_marker.as_char_slice());
-
-            if let Some(start) = matched {
-                text.replace_range(start..(start + set_cursor_marker.len()), L!(""));
-                cursor = Some(start + range.start as usize)
+        let mut placeholders = vec![];
+        if let Some(marker) = set_cursor_marker {
+            let mut placeholder = marker.clone();
+            placeholder.push_utfstr(L!("{}"));
+
+            // Erase the markers as we go, so the positions we record are those in the result.
+            let mut pos = 0;
+            while pos < text.len() {
+                let rest = &text.as_char_slice()[pos..];
+                if rest.starts_with(placeholder.as_char_slice()) {
+                    text.replace_range(pos..(pos + placeholder.len()), L!(""));
+                    placeholders.push(pos + range.start as usize);
+                } else if cursor.is_none() && rest.starts_with(marker.as_char_slice()) {
+                    text.replace_range(pos..(pos + marker.len()), L!(""));
+                    cursor = Some(pos + range.start as usize);
+                } else {
+                    pos += 1;
+                }
+            }
+            if cursor.is_none() && !placeholders.is_empty() {
+                cursor = Some(placeholders.remove(0));
             }
         }
         Self {
             range,
             text,
             cursor,
+            placeholders,
         }
     }
 }
@@ -278,6 +311,7 @@ impl AbbreviationSet {
                     replacement: abbr.replacement.clone(),
                     is_function: abbr.replacement_is_function,
                     set_cursor_marker: abbr.set_cursor_marker.clone(),
+                    condition: abbr.condition.clone(),
                 });
             }
         }
@@ -403,6 +437,7 @@ fn abbrs_replacement_from_ffi(
         text: replacement.text.to_ffi(),
         cursor: replacement.cursor.unwrap_or_default(),
         has_cursor: replacement.cursor.is_some(),
+        placeholders: replacement.placeholders,
     }
 }
 
@@ -450,6 +485,7 @@ add_test!("rename_abbrs", || {
                 replacement_is_function: false,
                 position,
                 set_cursor_marker: None,
+                condition: None,
                 from_universal: false,
             })
         };
@@ -470,3 +506,36 @@ add_test!("rename_abbrs", || {
         assert!(!abbrs_g.erase(L!("gcc")));
     })
 });
+
+add_test!("abbr_placeholders", || {
+    use crate::abbrs::Replacement;
+    use crate::parse_constants::SourceRange;
+    use crate::wchar::{WString, L};
+
+    let expand = |text: &str, marker: &str| {
+        let marker = Some(WString::from_str(marker));
+        Replacement::from(SourceRange::new(5, 3), WString::from_str(text), marker)
+    };
+
+    // The lone marker takes the cursor, wherever it is.
+    let r = expand("git commit -m \"%{}\" %", "%");
+    assert_eq!(r.text, L!("git commit -m \"\" "));
+    assert_eq!(r.cursor, Some(5 + 17));
+    assert_eq!(r.placeholders, vec![5 + 15]);
+
+    // Without one, the cursor goes to the first placeholder.
+    let r = expand("cp !{} !{}", "!");
+    assert_eq!(r.text, L!("cp  "));
+    assert_eq!(r.cursor, Some(5 + 3));
+    assert_eq!(r.placeholders, vec![5 + 4]);
+
+    // Only the first lone marker is erased.
+    let r = expand("a % b %", "%");
+    assert_eq!(r.text, L!("a  b %"));
+    assert_eq!(r.cursor, Some(5 + 2));
+    assert!(r.placeholders.is_empty());
+
+    let r = expand("echo %{}", "!");
+    assert_eq!(r.text, L!("echo %{}"));
+    assert_eq!(r.cursor, None);
+});
@@ -11,6 +11,7 @@
  */
 use crate::common::{unescape_string, UnescapeStringStyle};
 use crate::flog::FLOG;
+use crate::json::JsonWriter;
 use crate::parse_constants::{
     token_type_user_presentable_description, ParseError, ParseErrorCode, ParseErrorList,
     ParseErrorListFfi, ParseKeyword, ParseTokenType, ParseTreeFlags, SourceRange,
@@ -1818,6 +1819,9 @@ impl ConcreteNode for VariableAssignment {
     }
 }
 impl ConcreteNodeMut for VariableAssignment {
+    fn as_mut_leaf(&mut self) -> Option<&mut dyn Leaf> {
+        Some(self)
+    }
     fn as_mut_variable_assignment(&mut self) -> Option<&mut VariableAssignment> {
         Some(self)
     }
@@ -2330,7 +2334,7 @@ impl Ast {
     /// \return a textual representation of the tree.
     /// Pass the original source as \p orig.
     #[widestrs]
-    fn dump(&self, orig: &wstr) -> WString {
+    pub fn dump(&self, orig: &wstr) -> WString {
         let mut result = WString::new();
 
         let mut tv = self.walk();
@@ -2380,6 +2384,71 @@ impl Ast {
         }
         result
     }
+
+    /// \return the tree as JSON. Each node is an object with its "type", its source "range" as
+    /// [start, end] or null if it is unsourced, and either the "text" of a leaf or the "children"
+    /// of other nodes. Keywords and tokens also have their "keyword" or "token" type.
+    /// Pass the original source as \p orig.
+    pub fn to_json(&self, orig: &wstr) -> WString {
+        let mut out = JsonWriter::new();
+        node_to_json(self.top(), orig, &mut out);
+        out.contents().to_owned()
+    }
+}
+
+/// Collects the direct children of a node.
+struct ChildCollector<'a> {
+    children: Vec<&'a dyn Node>,
+}
+
+impl<'a> NodeVisitor<'a> for ChildCollector<'a> {
+    fn visit(&mut self, node: &'a dyn Node) {
+        self.children.push(node);
+    }
+}
+
+/// Write \p node and its children as JSON, for Ast::to_json().
+fn node_to_json(node: &dyn Node, orig: &wstr, out: &mut JsonWriter) {
+    out.begin_object();
+    out.key(L!("type"));
+    out.string(ast_type_to_string(node.typ()));
+    if let Some(n) = node.as_keyword() {
+        out.key(L!("keyword"));
+        out.string(n.keyword().into());
+    } else if let Some(n) = node.as_token() {
+        let token_type: &wstr = n.token_type().into();
+        let prefix = L!("ParseTokenType::");
+        out.key(L!("token"));
+        if token_type.starts_with(prefix) {
+            out.string(token_type.slice_from(prefix.len()));
+        } else {
+            out.string(token_type);
+        }
+    }
+    out.key(L!("range"));
+    match node.try_source_range() {
+        Some(range) => {
+            out.begin_array();
+            out.integer(range.start() as i64);
+            out.integer(range.end() as i64);
+            out.end_array();
+        }
+        None => out.null(),
+    }
+    if node.category() == Category::leaf {
+        out.key(L!("text"));
+        out.string(node.source(orig));
+    } else {
+        let mut collector = ChildCollector { children: vec![] };
+        node.accept(&mut collector, false);
+        out.key(L!("children"));
+        out.begin_array();
+        for child in collector.children {
+            node_to_json(child, orig, out);
+        }
+        out.end_array();
+    }
+    out.end_object();
 }
 
 // \return the depth of a node, i.e. number of parent links.
@@ -3815,23 +3884,496 @@ fn parse_from_top(
         errors: pops.errors,
     };
 
-    if top_type == Type::job_list {
-        // Set all parent nodes.
-        // It turns out to be more convenient to do this after the parse phase.
-        ast.top_mut()
-            .as_mut_job_list()
-            .as_mut()
-            .unwrap()
-            .set_parents();
-    } else {
-        ast.top_mut()
-            .as_mut_freestanding_argument_list()
-            .as_mut()
-            .unwrap()
-            .set_parents();
+    // Set all parent nodes.
+    // It turns out to be more convenient to do this after the parse phase.
+    ast.set_parents();
+    ast
+}
+
+impl Ast {
+    /// Set the parent fields of all nodes.
+    fn set_parents(&mut self) {
+        if self.top.typ() == Type::job_list {
+            self.top_mut().as_mut_job_list().unwrap().set_parents();
+        } else {
+            self.top_mut()
+                .as_mut_freestanding_argument_list()
+                .unwrap()
+                .set_parents();
+        }
     }
 
-    ast
+    /// \return the ast in a compact binary form, which deserialize() turns back into the same ast
+    /// given the same source.
+    pub fn serialize(&mut self) -> Vec<u8> {
+        let mut writer = AstWriter { out: vec![] };
+        writer.write_u8(if self.top.typ() == Type::job_list {
+            0
+        } else {
+            1
+        });
+        writer.write_u8(self.any_error.into());
+        writer.visit_mut(&mut *self.top);
+        for ranges in [
+            &self.extras.comments,
+            &self.extras.semis,
+            &self.extras.errors,
+        ] {
+            writer.write_count(ranges.len());
+            for range in ranges {
+                writer.write_range(Some(*range));
+            }
+        }
+        writer.out
+    }
+
+    /// Construct an ast from the output of serialize(), for the source \p src.
+    /// \return None if \p data is not a valid ast for a source of this length.
+    pub fn deserialize(data: &[u8], src: &wstr) -> Option<Self> {
+        let mut reader = AstReader {
+            data,
+            pos: 0,
+            src_len: src.len(),
+            ok: true,
+        };
+        let mut ast = Ast::default();
+        match reader.read_u8() {
+            0 => {
+                let mut list = Box::<JobList>::default();
+                reader.visit_mut(&mut *list);
+                ast.top = list;
+            }
+            1 => {
+                let mut list = Box::<FreestandingArgumentList>::default();
+                reader.visit_mut(&mut *list);
+                ast.top = list;
+            }
+            _ => return None,
+        }
+        ast.any_error = reader.read_u8() != 0;
+        for ranges in [
+            &mut ast.extras.comments,
+            &mut ast.extras.semis,
+            &mut ast.extras.errors,
+        ] {
+            for _ in 0..reader.read_count() {
+                match reader.read_range() {
+                    Some(range) => ranges.push(range),
+                    None => reader.ok = false,
+                }
+            }
+        }
+        if !reader.ok || reader.pos != data.len() {
+            return None;
+        }
+        ast.set_parents();
+        Some(ast)
+    }
+}
+
+/// \return the number of items in a list node.
+fn list_count(node: &dyn Node) -> usize {
+    match node.typ() {
+        Type::andor_job_list => node.as_andor_job_list().unwrap().count(),
+        Type::argument_list => node.as_argument_list().unwrap().count(),
+        Type::argument_or_redirection_list => {
+            node.as_argument_or_redirection_list().unwrap().count()
+        }
+        Type::case_item_list => node.as_case_item_list().unwrap().count(),
+        Type::elseif_clause_list => node.as_elseif_clause_list().unwrap().count(),
+        Type::job_conjunction_continuation_list => {
+            node.as_job_conjunction_continuation_list().unwrap().count()
+        }
+        Type::job_continuation_list => node.as_job_continuation_list().unwrap().count(),
+        Type::job_list => node.as_job_list().unwrap().count(),
+        Type::variable_assignment_list => node.as_variable_assignment_list().unwrap().count(),
+        _ => panic!("not a list"),
+    }
+}
+
+/// Writes an ast for Ast::serialize(). The nodes are visited in the order the Populator creates
+/// them in, so it's enough to record the choices it made: the source range of each leaf (and the
+/// keyword or token type), the length of each list, the variant of each union and whether each
+/// optional field is present.
+struct AstWriter {
+    out: Vec<u8>,
+}
+
+impl AstWriter {
+    fn write_u8(&mut self, value: u8) {
+        self.out.push(value);
+    }
+    fn write_u32(&mut self, value: u32) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+    fn write_count(&mut self, count: usize) {
+        self.write_u32(count.try_into().unwrap());
+    }
+    fn write_range(&mut self, range: Option<SourceRange>) {
+        let Some(range) = range else {
+            self.write_u8(0);
+            return;
+        };
+        self.write_u8(1);
+        self.write_count(range.start());
+        self.write_count(range.length());
+    }
+    fn write_optional<T: NodeMut>(&mut self, node: &mut Option<T>) {
+        match node {
+            Some(node) => {
+                self.write_u8(1);
+                self.visit_mut(node);
+            }
+            None => self.write_u8(0),
+        }
+    }
+}
+
+impl NodeVisitorMut for AstWriter {
+    fn visit_mut(&mut self, node: &mut dyn NodeMut) -> VisitResult {
+        match node.category() {
+            Category::leaf => {
+                self.write_range(node.as_leaf().unwrap().range());
+                if let Some(keyword) = node.as_keyword() {
+                    self.write_u8(keyword.keyword().repr);
+                } else if let Some(token) = node.as_token() {
+                    self.write_u8(token.token_type().repr as u8);
+                }
+            }
+            Category::branch => node.accept_mut(self, false),
+            Category::list => {
+                self.write_count(list_count(node.as_node()));
+                node.accept_mut(self, false);
+            }
+            _ => panic!(),
+        }
+        VisitResult::Continue(())
+    }
+
+    fn will_visit_fields_of(&mut self, _node: &mut dyn NodeMut) {}
+    fn did_visit_fields_of<'a>(&'a mut self, _node: &'a dyn NodeMut, _flow: VisitResult) {}
+
+    fn visit_argument_or_redirection(
+        &mut self,
+        node: &mut Box<ArgumentOrRedirectionVariant>,
+    ) -> VisitResult {
+        match &mut **node {
+            ArgumentOrRedirectionVariant::Argument(node) => {
+                self.write_u8(0);
+                self.visit_mut(node)
+            }
+            ArgumentOrRedirectionVariant::Redirection(node) => {
+                self.write_u8(1);
+                self.visit_mut(node)
+            }
+        }
+    }
+    fn visit_block_statement_header(
+        &mut self,
+        node: &mut Box<BlockStatementHeaderVariant>,
+    ) -> VisitResult {
+        match &mut **node {
+            BlockStatementHeaderVariant::None => {
+                self.write_u8(0);
+                VisitResult::Continue(())
+            }
+            BlockStatementHeaderVariant::ForHeader(node) => {
+                self.write_u8(1);
+                self.visit_mut(node)
+            }
+            BlockStatementHeaderVariant::WhileHeader(node) => {
+                self.write_u8(2);
+                self.visit_mut(node)
+            }
+            BlockStatementHeaderVariant::FunctionHeader(node) => {
+                self.write_u8(3);
+                self.visit_mut(node)
+            }
+            BlockStatementHeaderVariant::BeginHeader(node) => {
+                self.write_u8(4);
+                self.visit_mut(node)
+            }
+        }
+    }
+    fn visit_statement(&mut self, node: &mut Box<StatementVariant>) -> VisitResult {
+        match &mut **node {
+            StatementVariant::None => {
+                self.write_u8(0);
+                VisitResult::Continue(())
+            }
+            StatementVariant::NotStatement(node) => {
+                self.write_u8(1);
+                self.visit_mut(node)
+            }
+            StatementVariant::BlockStatement(node) => {
+                self.write_u8(2);
+                self.visit_mut(node)
+            }
+            StatementVariant::IfStatement(node) => {
+                self.write_u8(3);
+                self.visit_mut(node)
+            }
+            StatementVariant::SwitchStatement(node) => {
+                self.write_u8(4);
+                self.visit_mut(node)
+            }
+            StatementVariant::DecoratedStatement(node) => {
+                self.write_u8(5);
+                self.visit_mut(node)
+            }
+        }
+    }
+
+    fn visit_decorated_statement_decorator(
+        &mut self,
+        node: &mut Option<DecoratedStatementDecorator>,
+    ) {
+        self.write_optional(node);
+    }
+    fn visit_job_conjunction_decorator(&mut self, node: &mut Option<JobConjunctionDecorator>) {
+        self.write_optional(node);
+    }
+    fn visit_else_clause(&mut self, node: &mut Option<ElseClause>) {
+        self.write_optional(node);
+    }
+    fn visit_semi_nl(&mut self, node: &mut Option<SemiNl>) {
+        self.write_optional(node);
+    }
+    fn visit_time(&mut self, node: &mut Option<KeywordTime>) {
+        self.write_optional(node);
+    }
+    fn visit_token_background(&mut self, node: &mut Option<TokenBackground>) {
+        self.write_optional(node);
+    }
+}
+
+/// Reads what AstWriter wrote, filling in the nodes as the Populator would. Anything out of place,
+/// like a source range beyond the end of the source or a keyword where it can't be, marks the
+/// data as bad, after which we stop reading.
+struct AstReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// The length of the source the ast is for.
+    src_len: usize,
+    /// Whether the data made sense so far.
+    ok: bool,
+}
+
+impl<'a> AstReader<'a> {
+    fn read_u8(&mut self) -> u8 {
+        let Some(&value) = self.data.get(self.pos) else {
+            self.ok = false;
+            return 0;
+        };
+        self.pos += 1;
+        value
+    }
+    fn read_u32(&mut self) -> u32 {
+        let Some(bytes) = self.data.get(self.pos..self.pos + 4) else {
+            self.ok = false;
+            return 0;
+        };
+        self.pos += 4;
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+    fn read_count(&mut self) -> usize {
+        let count = self.read_u32() as usize;
+        // Every item takes at least a byte, so don't let a bogus count make us allocate a lot.
+        if count > self.data.len() - self.pos {
+            self.ok = false;
+            return 0;
+        }
+        count
+    }
+    fn read_range(&mut self) -> Option<SourceRange> {
+        match self.read_u8() {
+            0 => None,
+            1 => {
+                let start = self.read_u32() as usize;
+                let length = self.read_u32() as usize;
+                if start + length > self.src_len {
+                    self.ok = false;
+                    return None;
+                }
+                Some(SourceRange::new(start, length))
+            }
+            _ => {
+                self.ok = false;
+                None
+            }
+        }
+    }
+    fn read_optional<T: NodeMut + Default>(&mut self) -> Option<T> {
+        match self.read_u8() {
+            0 => None,
+            1 => {
+                let mut node = T::default();
+                self.visit_mut(&mut node);
+                Some(node)
+            }
+            _ => {
+                self.ok = false;
+                None
+            }
+        }
+    }
+    fn read_list<ListType: List>(&mut self, list: &mut ListType)
+    where
+        <ListType as List>::ContentsNode: NodeMut,
+    {
+        let count = self.read_count();
+        let mut contents = Vec::with_capacity(count);
+        for _ in 0..count {
+            if !self.ok {
+                break;
+            }
+            let mut item = Box::<ListType::ContentsNode>::default();
+            self.visit_mut(&mut *item);
+            contents.push(item);
+        }
+        *list.contents_mut() = contents;
+    }
+    /// Read a node for a union field, whose variant we have already read.
+    fn read_node<T: NodeMut + Default>(&mut self) -> T {
+        let mut node = T::default();
+        self.visit_mut(&mut node);
+        node
+    }
+}
+
+impl<'a> NodeVisitorMut for AstReader<'a> {
+    fn visit_mut(&mut self, node: &mut dyn NodeMut) -> VisitResult {
+        if !self.ok {
+            return VisitResult::Continue(());
+        }
+        match node.category() {
+            Category::leaf => {
+                let range = self.read_range();
+                match node.typ() {
+                    Type::keyword_base => {
+                        let keyword = node.as_mut_keyword().unwrap();
+                        let kw = ParseKeyword {
+                            repr: self.read_u8(),
+                        };
+                        if range.is_some() && !keyword.allows_keyword(kw) {
+                            self.ok = false;
+                        }
+                        *keyword.keyword_mut() = kw;
+                        *keyword.range_mut() = range;
+                    }
+                    Type::token_base => {
+                        let token = node.as_mut_token().unwrap();
+                        let typ = ParseTokenType {
+                            repr: self.read_u8().into(),
+                        };
+                        if range.is_some() && !token.allows_token(typ) {
+                            self.ok = false;
+                        }
+                        *token.token_type_mut() = typ;
+                        *token.range_mut() = range;
+                    }
+                    _ => *node.as_mut_leaf().unwrap().range_mut() = range,
+                }
+            }
+            Category::branch => node.accept_mut(self, false),
+            Category::list => match node.typ() {
+                Type::andor_job_list => self.read_list(node.as_mut_andor_job_list().unwrap()),
+                Type::argument_list => self.read_list(node.as_mut_argument_list().unwrap()),
+                Type::argument_or_redirection_list => {
+                    self.read_list(node.as_mut_argument_or_redirection_list().unwrap())
+                }
+                Type::case_item_list => self.read_list(node.as_mut_case_item_list().unwrap()),
+                Type::elseif_clause_list => {
+                    self.read_list(node.as_mut_elseif_clause_list().unwrap())
+                }
+                Type::job_conjunction_continuation_list => {
+                    self.read_list(node.as_mut_job_conjunction_continuation_list().unwrap())
+                }
+                Type::job_continuation_list => {
+                    self.read_list(node.as_mut_job_continuation_list().unwrap())
+                }
+                Type::job_list => self.read_list(node.as_mut_job_list().unwrap()),
+                Type::variable_assignment_list => {
+                    self.read_list(node.as_mut_variable_assignment_list().unwrap())
+                }
+                _ => panic!("not a list"),
+            },
+            _ => panic!(),
+        }
+        VisitResult::Continue(())
+    }
+
+    fn will_visit_fields_of(&mut self, _node: &mut dyn NodeMut) {}
+    fn did_visit_fields_of<'b>(&'b mut self, _node: &'b dyn NodeMut, _flow: VisitResult) {}
+
+    fn visit_argument_or_redirection(
+        &mut self,
+        node: &mut Box<ArgumentOrRedirectionVariant>,
+    ) -> VisitResult {
+        **node = match self.read_u8() {
+            0 => ArgumentOrRedirectionVariant::Argument(self.read_node()),
+            1 => ArgumentOrRedirectionVariant::Redirection(self.read_node()),
+            _ => {
+                self.ok = false;
+                return VisitResult::Continue(());
+            }
+        };
+        VisitResult::Continue(())
+    }
+    fn visit_block_statement_header(
+        &mut self,
+        node: &mut Box<BlockStatementHeaderVariant>,
+    ) -> VisitResult {
+        **node = match self.read_u8() {
+            0 => BlockStatementHeaderVariant::None,
+            1 => BlockStatementHeaderVariant::ForHeader(self.read_node()),
+            2 => BlockStatementHeaderVariant::WhileHeader(self.read_node()),
+            3 => BlockStatementHeaderVariant::FunctionHeader(self.read_node()),
+            4 => BlockStatementHeaderVariant::BeginHeader(self.read_node()),
+            _ => {
+                self.ok = false;
+                return VisitResult::Continue(());
+            }
+        };
+        VisitResult::Continue(())
+    }
+    fn visit_statement(&mut self, node: &mut Box<StatementVariant>) -> VisitResult {
+        **node = match self.read_u8() {
+            0 => StatementVariant::None,
+            1 => StatementVariant::NotStatement(self.read_node()),
+            2 => StatementVariant::BlockStatement(self.read_node()),
+            3 => StatementVariant::IfStatement(self.read_node()),
+            4 => StatementVariant::SwitchStatement(self.read_node()),
+            5 => StatementVariant::DecoratedStatement(self.read_node()),
+            _ => {
+                self.ok = false;
+                return VisitResult::Continue(());
+            }
+        };
+        VisitResult::Continue(())
+    }
+
+    fn visit_decorated_statement_decorator(
+        &mut self,
+        node: &mut Option<DecoratedStatementDecorator>,
+    ) {
+        *node = self.read_optional();
+    }
+    fn visit_job_conjunction_decorator(&mut self, node: &mut Option<JobConjunctionDecorator>) {
+        *node = self.read_optional();
+    }
+    fn visit_else_clause(&mut self, node: &mut Option<ElseClause>) {
+        *node = self.read_optional();
+    }
+    fn visit_semi_nl(&mut self, node: &mut Option<SemiNl>) {
+        *node = self.read_optional();
+    }
+    fn visit_time(&mut self, node: &mut Option<KeywordTime>) {
+        *node = self.read_optional();
+    }
+    fn visit_token_background(&mut self, node: &mut Option<TokenBackground>) {
+        *node = self.read_optional();
+    }
 }
 
 /// \return tokenizer flags corresponding to parse tree flags.
@@ -4012,6 +4554,8 @@ pub mod ast_ffi {
 
         #[cxx_name = "dump"]
         unsafe fn dump_ffi(self: &Ast, orig: &CxxWString) -> UniquePtr<CxxWString>;
+        #[cxx_name = "to_json"]
+        unsafe fn to_json_ffi(self: &Ast, orig: &CxxWString) -> UniquePtr<CxxWString>;
         #[cxx_name = "extras"]
         fn extras_ffi(self: &Ast) -> Box<ExtrasFFI<'_>>;
         unsafe fn comments<'a>(self: &'a ExtrasFFI<'a>) -> &'a [SourceRange];
@@ -4414,6 +4958,9 @@ impl Ast {
     fn dump_ffi(&self, orig: &CxxWString) -> UniquePtr<CxxWString> {
         self.dump(orig.as_wstr()).to_ffi()
     }
+    fn to_json_ffi(&self, orig: &CxxWString) -> UniquePtr<CxxWString> {
+        self.to_json(orig.as_wstr()).to_ffi()
+    }
 }
 
 fn ast_parse_ffi(src: &CxxWString, flags: u8, errors: *mut ParseErrorListFfi) -> Box<Ast> {
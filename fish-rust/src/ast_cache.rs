@@ -0,0 +1,366 @@
+//! A cache of parsed scripts on disk, so fish doesn't parse the same function files and conf.d
+//! snippets again in every new shell.
+//!
+//! Each sourced file gets a cache file in the "ast" directory under the fish cache directory, named
+//! after a hash of its path. It starts with a key made of the fish version, the feature flags, the
+//! path, the file's device, inode, size and modification time, and a hash of its contents, followed
+//! by the ast as written by Ast::serialize(). The cache file is mapped into memory and only used if
+//! the key matches. Only scripts without syntax errors are cached, so a hit also skips the error
+//! checks.
+//!
+//! The directory holds at most MAX_CACHE_FILES files. When a new file would go over that, the ones
+//! which were used least recently are removed.
+
+use crate::ast::Ast;
+use crate::common::wcs2string;
+use crate::flog::FLOG;
+use crate::future_feature_flags::feature_values;
+use crate::path::path_get_cache;
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::AsWstr;
+use crate::wutil::fileid::{file_id_for_fd, FileId, INVALID_FILE_ID};
+use cxx::CxxWString;
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime};
+
+#[cxx::bridge]
+mod ast_cache_ffi {
+    extern "C++" {
+        include!("ast.h");
+        type Ast = crate::ast::Ast;
+    }
+    extern "Rust" {
+        #[cxx_name = "ast_cache_load"]
+        fn ast_cache_load_ffi(
+            fd: i32,
+            path: &CxxWString,
+            src: &CxxWString,
+            found: &mut bool,
+        ) -> Box<Ast>;
+        #[cxx_name = "ast_cache_store"]
+        fn ast_cache_store_ffi(fd: i32, path: &CxxWString, src: &CxxWString, ast: Pin<&mut Ast>);
+    }
+}
+
+/// Identifies the cache format. Change this whenever the format of the key or of the serialized
+/// ast changes.
+const MAGIC: &[u8] = b"fish-ast-cache 2\n";
+
+/// The most files the cache directory holds.
+const MAX_CACHE_FILES: usize = 1024;
+
+/// How long a cache file may go without being marked as used. Marking it on every hit would mean
+/// writing to the disk every time a file is sourced.
+const TOUCH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A 64-bit FNV-1a hash. Unlike the hashers in std, this is guaranteed to stay the same.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// \return the key a cache file for \p src, read from \p path, must start with.
+fn cache_key(file_id: &FileId, path: &wstr, src: &wstr) -> Vec<u8> {
+    let mut key = MAGIC.to_vec();
+    key.extend_from_slice(env!("CARGO_PKG_VERSION").as_bytes());
+    key.push(b'\n');
+    // How scripts are tokenized and which errors are found depends on some of the features.
+    for (name, value) in feature_values() {
+        key.push(if value { b'+' } else { b'-' });
+        key.extend_from_slice(&wcs2string(name));
+        key.push(b'\n');
+    }
+    let path = wcs2string(path);
+    key.extend_from_slice(&(path.len() as u64).to_le_bytes());
+    key.extend_from_slice(&path);
+    for value in [file_id.device, file_id.inode, file_id.size] {
+        key.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [file_id.mod_seconds, file_id.mod_nanoseconds] {
+        key.extend_from_slice(&value.to_le_bytes());
+    }
+    key.extend_from_slice(&(src.len() as u64).to_le_bytes());
+    let hash = fnv1a(src.chars().flat_map(|c| u32::from(c).to_le_bytes()));
+    key.extend_from_slice(&hash.to_le_bytes());
+    key
+}
+
+/// \return the path of the cache file for the script at \p path, creating the directory if needed.
+fn cache_file_path(path: &wstr) -> Option<PathBuf> {
+    let mut dir = path_get_cache()?;
+    dir.push_utfstr(L!("/ast"));
+    let dir = PathBuf::from(OsString::from_vec(wcs2string(&dir)));
+    if let Err(err) = std::fs::create_dir(&dir) {
+        if err.kind() != std::io::ErrorKind::AlreadyExists {
+            return None;
+        }
+    }
+    let name = format!("{:016x}", fnv1a(wcs2string(path)));
+    Some(dir.join(name))
+}
+
+/// A file mapped into memory, read-only.
+struct MappedFile {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl MappedFile {
+    fn open(path: &PathBuf) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let len = usize::try_from(file.metadata().ok()?.len()).ok()?;
+        if len == 0 {
+            return None;
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+        // The mapping stays valid after the file is closed.
+        Some(MappedFile { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+/// \return the cached ast for the script \p src, which was read from \p fd, opened as \p path.
+/// \return None if there is no cache file or it is out of date.
+pub fn ast_cache_load(fd: RawFd, path: &wstr, src: &wstr) -> Option<Ast> {
+    let file_id = file_id_for_fd(fd);
+    if file_id == INVALID_FILE_ID {
+        return None;
+    }
+    let key = cache_key(&file_id, path, src);
+    let cache_path = cache_file_path(path)?;
+    let mapped = MappedFile::open(&cache_path)?;
+    let data = mapped.bytes();
+    if !data.starts_with(&key) {
+        return None;
+    }
+    let Some(ast) = Ast::deserialize(&data[key.len()..], src) else {
+        FLOG!(
+            config,
+            "Ignoring broken ast cache file",
+            cache_path.display()
+        );
+        return None;
+    };
+    FLOG!(ast_construction, "Using the cached ast of", path);
+    touch_if_old(&cache_path);
+    Some(ast)
+}
+
+/// Set the modification time of the cache file \p path to now, unless it was set recently.
+/// The modification time tells which files were used least recently.
+fn touch_if_old(path: &Path) {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified());
+    let Ok(modified) = modified else { return; };
+    if modified + TOUCH_INTERVAL > SystemTime::now() {
+        return;
+    }
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else { return; };
+    unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), std::ptr::null(), 0) };
+}
+
+/// Remove the least recently used files in the cache directory \p dir if it has more than
+/// \p max_files. This removes a quarter of them, so it doesn't have to be done for every new file.
+fn prune_cache_dir(dir: &Path, max_files: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return; };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect();
+    if files.len() <= max_files {
+        return;
+    }
+    files.sort_unstable();
+    let excess = files.len() - max_files / 4 * 3;
+    for (_, path) in &files[..excess] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Store the ast of the script \p src, which was read from \p fd, opened as \p path.
+/// The ast must not have any errors.
+pub fn ast_cache_store(fd: RawFd, path: &wstr, src: &wstr, ast: &mut Ast) {
+    let file_id = file_id_for_fd(fd);
+    if file_id == INVALID_FILE_ID {
+        return;
+    }
+    let Some(cache_path) = cache_file_path(path) else { return; };
+    let mut data = cache_key(&file_id, path, src);
+    data.extend_from_slice(&ast.serialize());
+
+    // Write to a temporary file and move it into place, so other shells never see half of it.
+    let is_new = !cache_path.exists();
+    let mut tmp_path = cache_path.clone().into_os_string();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    if std::fs::write(&tmp_path, &data).is_err() || std::fs::rename(&tmp_path, &cache_path).is_err()
+    {
+        let _ = std::fs::remove_file(&tmp_path);
+        return;
+    }
+    if is_new {
+        if let Some(dir) = cache_path.parent() {
+            prune_cache_dir(dir, MAX_CACHE_FILES);
+        }
+    }
+}
+
+fn ast_cache_load_ffi(fd: i32, path: &CxxWString, src: &CxxWString, found: &mut bool) -> Box<Ast> {
+    match ast_cache_load(fd, path.as_wstr(), src.as_wstr()) {
+        Some(ast) => {
+            *found = true;
+            Box::new(ast)
+        }
+        None => {
+            *found = false;
+            Box::default()
+        }
+    }
+}
+
+fn ast_cache_store_ffi(fd: i32, path: &CxxWString, src: &CxxWString, ast: Pin<&mut Ast>) {
+    ast_cache_store(fd, path.as_wstr(), src.as_wstr(), ast.get_mut());
+}
+
+use crate::ffi_tests::add_test;
+add_test!("test_ast_serialize", || {
+    use crate::parse_constants::ParseTreeFlags;
+    let src = WString::from_str(
+        "set -l x 1 # comment\n\
+         function f --argument a; echo $a >&2 | cat; end\n\
+         if not true; and false || time true &\n  echo a\n\
+         else if false\n  echo b\nelse\n  echo c\nend\n\
+         for i in 1 2 3; echo $i; end\n\
+         while false; end 2>/dev/null\n\
+         switch $x; case 1; echo one; case '*'; end\n\
+         begin; command ls; builtin echo; X=1 exec true; end\n\
+         ! true\n",
+    );
+    let mut ast = Ast::parse(&src, ParseTreeFlags::INCLUDE_COMMENTS, None);
+    assert!(!ast.errored());
+    assert!(!ast.extras.comments.is_empty());
+    let data = ast.serialize();
+    let copy = Ast::deserialize(&data, &src).expect("ast should deserialize");
+    assert_eq!(copy.dump(&src), ast.dump(&src));
+    assert_eq!(copy.extras.comments, ast.extras.comments);
+
+    // Argument lists, and trees with errors, survive too.
+    let src = L!("a 'b' c");
+    let mut ast = Ast::parse_argument_list(src, ParseTreeFlags::empty(), None);
+    let copy = Ast::deserialize(&ast.serialize(), src).unwrap();
+    assert_eq!(copy.dump(src), ast.dump(src));
+    let src = L!("if true; echo |");
+    let mut ast = Ast::parse(src, ParseTreeFlags::CONTINUE_AFTER_ERROR, None);
+    let copy = Ast::deserialize(&ast.serialize(), src).unwrap();
+    assert!(copy.errored());
+    assert_eq!(copy.dump(src), ast.dump(src));
+
+    // Data that is cut off, or that doesn't fit the source, is rejected.
+    for len in 0..data.len() {
+        assert!(Ast::deserialize(&data[..len], &src).is_none());
+    }
+    assert!(Ast::deserialize(&data, L!("echo")).is_none());
+    let mut extended = data.clone();
+    extended.push(0);
+    assert!(Ast::deserialize(&extended, &src).is_none());
+});
+
+add_test!("test_ast_cache_key", || {
+    use crate::future_feature_flags::{mutable_fish_features, FeatureFlag};
+    let file_id = FileId {
+        device: 1,
+        inode: 2,
+        size: 3,
+        change_seconds: 4,
+        change_nanoseconds: 5,
+        mod_seconds: 6,
+        mod_nanoseconds: 7,
+    };
+    let key = cache_key(&file_id, L!("/a/b.fish"), L!("echo"));
+    assert!(key.starts_with(MAGIC));
+    assert_eq!(key, cache_key(&file_id, L!("/a/b.fish"), L!("echo")));
+    // Anything that changes the key invalidates the cache.
+    assert_ne!(key, cache_key(&file_id, L!("/a/c.fish"), L!("echo")));
+    assert_ne!(key, cache_key(&file_id, L!("/a/b.fish"), L!("echO")));
+    let newer = FileId {
+        mod_nanoseconds: 8,
+        ..file_id
+    };
+    assert_ne!(key, cache_key(&newer, L!("/a/b.fish"), L!("echo")));
+    // So do the feature flags.
+    let features = unsafe { &mut *mutable_fish_features() };
+    let qmark_noglob = features.test(FeatureFlag::qmark_noglob);
+    features.set(FeatureFlag::qmark_noglob, !qmark_noglob);
+    let other_features = cache_key(&file_id, L!("/a/b.fish"), L!("echo"));
+    features.set(FeatureFlag::qmark_noglob, qmark_noglob);
+    assert_ne!(key, other_features);
+    assert_eq!(key, cache_key(&file_id, L!("/a/b.fish"), L!("echo")));
+    // The hash is stable.
+    assert_eq!(fnv1a(*b"fish"), 0x60ad7d8c3bbe4c08);
+});
+
+#[test]
+fn test_prune_cache_dir() {
+    let dir = std::env::temp_dir().join(format!("fish_ast_cache_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Files which were used longer ago have lower numbers.
+    for i in 0..10 {
+        let path = dir.join(format!("{}", i));
+        std::fs::write(&path, b"").unwrap();
+        let time = libc::timespec {
+            tv_sec: 1_000_000 + i,
+            tv_nsec: 0,
+        };
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let times = [time, time];
+        unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) };
+    }
+    let names = || {
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort_unstable_by_key(|n| n.parse::<u32>().unwrap());
+        names
+    };
+
+    // Nothing is removed while there are few enough files.
+    prune_cache_dir(&dir, 10);
+    assert_eq!(names().len(), 10);
+    // Otherwise the oldest go, until three quarters of the limit are left.
+    prune_cache_dir(&dir, 8);
+    assert_eq!(names(), ["4", "5", "6", "7", "8", "9"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,83 @@
+//! Bidirectional text: putting right-to-left scripts like Arabic and Hebrew in the order they are
+//! displayed in, following the Unicode Bidirectional Algorithm (UAX #9).
+//!
+//! Most terminals show characters in the order they are written, so fish reorders each screen line
+//! itself before drawing it. Terminals that do their own reordering get the text untouched, see
+//! $fish_bidi.
+
+use unicode_bidi::{BidiInfo, Level};
+
+#[cxx::bridge]
+mod bidi_ffi {
+    extern "Rust" {
+        fn bidi_visual_order(text: &[u32]) -> Vec<u32>;
+    }
+}
+
+/// Given the characters of a line in logical order, return the index of the character to show in
+/// each column from the left, or an empty vector if the line reads left to right as it is.
+/// The line is laid out as left-to-right text, so the prompt stays on the left.
+pub fn bidi_visual_order(text: &[u32]) -> Vec<u32> {
+    let s: String = text
+        .iter()
+        .map(|&c| char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    let info = BidiInfo::new(&s, Some(Level::ltr()));
+    if !info.has_rtl() {
+        return vec![];
+    }
+
+    // The algorithm works with byte offsets into the UTF-8 string, we want character indices.
+    let mut char_at_byte = vec![0; s.len()];
+    for (i, (offset, c)) in s.char_indices().enumerate() {
+        char_at_byte[offset..offset + c.len_utf8()].fill(i as u32);
+    }
+
+    let mut order = Vec::with_capacity(text.len());
+    for para in &info.paragraphs {
+        let (levels, runs) = info.visual_runs(para, para.range.clone());
+        for run in runs {
+            let chars = s[run.clone()]
+                .char_indices()
+                .map(|(offset, _)| char_at_byte[run.start + offset]);
+            if levels[run.start].is_rtl() {
+                order.extend(chars.rev());
+            } else {
+                order.extend(chars);
+            }
+        }
+    }
+    order
+}
+
+use crate::ffi_tests::add_test;
+add_test!("test_bidi_visual_order", || {
+    let order = |s: &str| -> Vec<u32> {
+        let chars: Vec<u32> = s.chars().map(|c| c as u32).collect();
+        bidi_visual_order(&chars)
+    };
+    let visual = |s: &str| -> String {
+        let chars: Vec<char> = s.chars().collect();
+        let order = order(s);
+        if order.is_empty() {
+            return s.to_owned();
+        }
+        order.iter().map(|&i| chars[i as usize]).collect()
+    };
+
+    // Nothing to do for left-to-right text.
+    assert!(order("ls -l /tmp").is_empty());
+    assert!(order("").is_empty());
+
+    // Hebrew is reversed, while the rest stays put.
+    assert_eq!(visual("cat שלום.txt"), "cat םולש.txt");
+    assert_eq!(order("ab אב"), [0, 1, 2, 4, 3]);
+    // Numbers in right-to-left text keep their order.
+    assert_eq!(visual("echo אב 12 גד"), "echo דג 12 בא");
+    // Arabic too.
+    assert_eq!(visual("cd مرحبا"), "cd ابحرم");
+    // Every character shows up once.
+    let mut all = order("x אבג y דה 3.14 z");
+    all.sort();
+    assert_eq!(all, (0..all.len() as u32).collect::<Vec<_>>());
+});
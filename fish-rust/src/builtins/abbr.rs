@@ -8,6 +8,7 @@ use crate::common::{escape, escape_string, valid_func_name, EscapeStringStyle};
 use crate::env::status::{ENV_NOT_FOUND, ENV_OK};
 use crate::env::EnvMode;
 use crate::ffi::parser_t;
+use crate::json::JsonWriter;
 use crate::re::{regex_make_anchored, to_boxed_chars};
 use crate::wchar::{wstr, L};
 use crate::wgetopt::{wgetopter_t, wopt, woption, woption_argument_t};
@@ -30,6 +31,8 @@ struct Options {
     regex_pattern: Option<WString>,
     position: Option<Position>,
     set_cursor_marker: Option<WString>,
+    condition: Option<WString>,
+    json: bool,
     args: Vec<WString>,
 }
 
@@ -111,6 +114,27 @@ impl Options {
             ));
             return false;
         }
+        if !self.add && self.condition.is_some() {
+            streams.err.append(wgettext_fmt!(
+                "%ls: --condition option requires --add\n",
+                CMD
+            ));
+            return false;
+        }
+        if self.condition.as_ref().map_or(false, |c| c.is_empty()) {
+            streams.err.append(wgettext_fmt!(
+                "%ls: --condition argument cannot be empty\n",
+                CMD
+            ));
+            return false;
+        }
+        if self.json && !(self.show || self.list) {
+            streams.err.append(wgettext_fmt!(
+                "%ls: --json option requires --show or --list\n",
+                CMD
+            ));
+            return false;
+        }
 
         return true;
     }
@@ -133,8 +157,56 @@ fn join(list: &[&wstr], sep: &wstr) -> WString {
     result
 }
 
+// Print abbreviations as a JSON array of objects.
+fn abbr_show_json(streams: &mut io_streams_t) -> Option<c_int> {
+    let mut w = JsonWriter::new();
+    w.begin_array();
+    abbrs::with_abbrs(|abbrs| {
+        for abbr in abbrs.list() {
+            w.begin_object();
+            w.key(L!("name"));
+            w.string(&abbr.name);
+            w.key(L!("regex"));
+            if abbr.is_regex() {
+                w.string(&abbr.key);
+            } else {
+                w.null();
+            }
+            w.key(L!("position"));
+            w.string(match abbr.position {
+                Position::Command => L!("command"),
+                Position::Anywhere => L!("anywhere"),
+            });
+            w.key(L!("replacement"));
+            w.string(&abbr.replacement);
+            w.key(L!("function"));
+            w.boolean(abbr.replacement_is_function);
+            w.key(L!("set_cursor"));
+            match abbr.set_cursor_marker {
+                Some(ref marker) => w.string(marker),
+                None => w.null(),
+            }
+            w.key(L!("condition"));
+            match abbr.condition {
+                Some(ref condition) => w.string(condition),
+                None => w.null(),
+            }
+            w.key(L!("from_universal"));
+            w.boolean(abbr.from_universal);
+            w.end_object();
+        }
+    });
+    w.end_array();
+    streams.out.append(w.contents());
+    streams.out.append1('\n');
+    return STATUS_CMD_OK;
+}
+
 // Print abbreviations in a fish-script friendly way.
-fn abbr_show(streams: &mut io_streams_t) -> Option<c_int> {
+fn abbr_show(opts: &Options, streams: &mut io_streams_t) -> Option<c_int> {
+    if opts.json {
+        return abbr_show_json(streams);
+    }
     let style = EscapeStringStyle::Script(Default::default());
 
     abbrs::with_abbrs(|abbrs| {
@@ -161,6 +233,10 @@ fn abbr_show(streams: &mut io_streams_t) -> Option<c_int> {
                 add_arg(L!("--set-cursor="));
                 add_arg(&escape_string(set_cursor_marker, style));
             }
+            if let Some(ref condition) = abbr.condition {
+                add_arg(L!("--condition"));
+                add_arg(&escape_string(condition, style));
+            }
             if abbr.replacement_is_function {
                 add_arg(L!("--function"));
                 add_arg(&escape_string(&abbr.replacement, style));
@@ -195,6 +271,20 @@ fn abbr_list(opts: &Options, streams: &mut io_streams_t) -> Option<c_int> {
         ));
         return STATUS_INVALID_ARGS;
     }
+    if opts.json {
+        let mut w = JsonWriter::new();
+        abbrs::with_abbrs(|abbrs| {
+            let names: Vec<&wstr> = abbrs
+                .list()
+                .iter()
+                .map(|abbr| abbr.name.as_utfstr())
+                .collect();
+            w.strings(&names);
+        });
+        streams.out.append(w.contents());
+        streams.out.append1('\n');
+        return STATUS_CMD_OK;
+    }
     abbrs::with_abbrs(|abbrs| {
         for abbr in abbrs.list() {
             let mut name = abbr.name.clone();
@@ -393,6 +483,7 @@ fn abbr_add(opts: &Options, streams: &mut io_streams_t) -> Option<c_int> {
             replacement_is_function: opts.function.is_some(),
             position,
             set_cursor_marker: opts.set_cursor_marker.clone(),
+            condition: opts.condition.clone(),
             from_universal: false,
         })
     });
@@ -442,6 +533,8 @@ pub fn abbr(
     const NON_OPTION_ARGUMENT: char = 1 as char;
     const SET_CURSOR_SHORT: char = 2 as char;
     const RENAME_SHORT: char = 3 as char;
+    const JSON_SHORT: char = 4 as char;
+    const CONDITION_SHORT: char = 5 as char;
 
     // Note the leading '-' causes wgetopter to return arguments in order, instead of permuting
     // them. We need this behavior for compatibility with pre-builtin abbreviations where options
@@ -458,6 +551,11 @@ pub fn abbr(
             SET_CURSOR_SHORT,
         ),
         wopt(L!("function"), woption_argument_t::required_argument, 'f'),
+        wopt(
+            L!("condition"),
+            woption_argument_t::required_argument,
+            CONDITION_SHORT,
+        ),
         wopt(L!("rename"), woption_argument_t::no_argument, RENAME_SHORT),
         wopt(L!("erase"), woption_argument_t::no_argument, 'e'),
         wopt(L!("query"), woption_argument_t::no_argument, 'q'),
@@ -466,6 +564,7 @@ pub fn abbr(
         wopt(L!("global"), woption_argument_t::no_argument, 'g'),
         wopt(L!("universal"), woption_argument_t::no_argument, 'U'),
         wopt(L!("help"), woption_argument_t::no_argument, 'h'),
+        wopt(L!("json"), woption_argument_t::no_argument, JSON_SHORT),
     ];
 
     let mut opts = Options::default();
@@ -537,7 +636,9 @@ pub fn abbr(
                     .insert(w.woptarg.unwrap_or(L!("%")).to_owned());
             }
             'f' => opts.function = w.woptarg.map(ToOwned::to_owned),
+            CONDITION_SHORT => opts.condition = w.woptarg.map(ToOwned::to_owned),
             RENAME_SHORT => opts.rename = true,
+            JSON_SHORT => opts.json = true,
             'e' => opts.erase = true,
             'q' => opts.query = true,
             's' => opts.show = true,
@@ -585,7 +686,7 @@ pub fn abbr(
         return abbr_add(&opts, streams);
     };
     if opts.show {
-        return abbr_show(streams);
+        return abbr_show(&opts, streams);
     };
     if opts.list {
         return abbr_list(&opts, streams);
@@ -0,0 +1,426 @@
+//! Implementation of the fish_command_not_found builtin, the default handler for unknown commands.
+//!
+//! It suggests similarly named commands from $PATH, and asks the package databases it finds which
+//! packages provide the command. The databases are asked in parallel, and any that doesn't answer
+//! within $fish_command_not_found_timeout milliseconds is abandoned. Answers are cached for the
+//! rest of the session.
+
+use std::collections::HashMap;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use libc::c_int;
+use once_cell::sync::Lazy;
+
+use crate::builtins::shared::{
+    builtin_missing_argument, builtin_print_help, builtin_unknown_option, io_streams_t,
+    BUILTIN_ERR_MIN_ARG_COUNT1, STATUS_CMD_OK, STATUS_CMD_UNKNOWN, STATUS_INVALID_ARGS,
+};
+use crate::common::{escape, str2wcstring, wcs2osstring, wcs2string};
+use crate::env::EnvMode;
+use crate::ffi::{parser_t, path_get_paths_ffi};
+use crate::threads::iothread_perform_cant_wait;
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::{WCharFromFFI, WCharToFFI};
+use crate::wgetopt::{wgetopter_t, wopt, woption, woption_argument_t};
+use crate::wutil::{fish_wcstoi, sprintf, wgettext_fmt};
+
+/// How long to wait for the package databases if $fish_command_not_found_timeout isn't set.
+const DEFAULT_TIMEOUT_MS: u64 = 500;
+
+/// The most similar commands to suggest.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// A package database which can tell which packages provide a command.
+trait Backend: Sync {
+    /// The name used in $fish_command_not_found_backends.
+    fn name(&self) -> &'static str;
+
+    /// The program to run to look up `cmd`, or None if this database isn't installed.
+    fn command(&self, parser: &mut parser_t, cmd: &wstr) -> Option<Command>;
+
+    /// The packages named in the program's output.
+    fn packages(&self, output: &str) -> Vec<String>;
+}
+
+/// Find `program` in $PATH.
+fn find_program(parser: &mut parser_t, program: &wstr) -> Option<WString> {
+    let paths: Vec<WString> = path_get_paths_ffi(&program.to_ffi(), parser).from_ffi();
+    paths.into_iter().next()
+}
+
+/// The helper behind Debian's and Ubuntu's command-not-found database.
+struct CommandNotFoundDb;
+
+impl Backend for CommandNotFoundDb {
+    fn name(&self) -> &'static str {
+        "command-not-found"
+    }
+
+    fn command(&self, _parser: &mut parser_t, cmd: &wstr) -> Option<Command> {
+        let helper = "/usr/lib/command-not-found";
+        std::fs::metadata(helper).ok()?;
+        let mut command = Command::new(helper);
+        command
+            .arg("--ignore-installed")
+            .arg("--no-failure-msg")
+            .arg("--")
+            .arg(wcs2osstring(cmd));
+        Some(command)
+    }
+
+    fn packages(&self, output: &str) -> Vec<String> {
+        // It suggests one "sudo apt install PACKAGE  # VERSION" line per package.
+        output
+            .lines()
+            .filter_map(|line| {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                let install = words.windows(3).position(|w| w[..2] == ["apt", "install"])?;
+                Some(words[install + 2].to_owned())
+            })
+            .collect()
+    }
+}
+
+/// Arch Linux's pkgfile.
+struct Pkgfile;
+
+impl Backend for Pkgfile {
+    fn name(&self) -> &'static str {
+        "pkgfile"
+    }
+
+    fn command(&self, parser: &mut parser_t, cmd: &wstr) -> Option<Command> {
+        let pkgfile = find_program(parser, L!("pkgfile"))?;
+        let mut command = Command::new(wcs2osstring(&pkgfile));
+        command.arg("--binaries").arg("--").arg(wcs2osstring(cmd));
+        Some(command)
+    }
+
+    fn packages(&self, output: &str) -> Vec<String> {
+        // One "REPOSITORY/PACKAGE" per line.
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+/// nix-index's nix-locate.
+struct NixIndex;
+
+impl Backend for NixIndex {
+    fn name(&self) -> &'static str {
+        "nix-index"
+    }
+
+    fn command(&self, parser: &mut parser_t, cmd: &wstr) -> Option<Command> {
+        let nix_locate = find_program(parser, L!("nix-locate"))?;
+        let mut path = wcs2string(L!("/bin/"));
+        path.extend(wcs2string(cmd));
+        let mut command = Command::new(wcs2osstring(&nix_locate));
+        command
+            .args(["--minimal", "--no-group", "--top-level", "--whole-name", "--at-root"])
+            .args(["--type", "x", "--type", "s"])
+            .arg(std::ffi::OsStr::from_bytes(&path));
+        Some(command)
+    }
+
+    fn packages(&self, output: &str) -> Vec<String> {
+        // One "ATTRIBUTE.OUTPUT" per line; the output is almost always "out".
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.strip_suffix(".out").unwrap_or(line).to_owned())
+            .collect()
+    }
+}
+
+/// All the backends, in the order their packages are listed.
+static BACKENDS: &[&dyn Backend] = &[&CommandNotFoundDb, &Pkgfile, &NixIndex];
+
+/// The packages each backend named for a command, by backend name and command.
+static PACKAGE_CACHE: Lazy<Mutex<HashMap<(&'static str, WString), Vec<WString>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The commands in each $PATH directory, and the directory's modification time when they were
+/// listed.
+static PATH_CACHE: Lazy<Mutex<HashMap<WString, (SystemTime, Vec<WString>)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The optimal string alignment distance between `a` and `b`: the number of characters which must
+/// be inserted, deleted, substituted or swapped with their neighbor to turn one into the other.
+fn edit_distance(a: &wstr, b: &wstr) -> usize {
+    let a = a.as_char_slice();
+    let b = b.as_char_slice();
+    // Three rows of the distance matrix: two back, one back and the current one.
+    let mut rows = vec![vec![0; b.len() + 1]; 3];
+    for (j, cell) in rows[1].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        rows[2][0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (rows[1][j] + 1)
+                .min(rows[2][j - 1] + 1)
+                .min(rows[1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(rows[0][j - 2] + 1);
+            }
+            rows[2][j] = d;
+        }
+        rows.rotate_left(1);
+    }
+    rows[1][b.len()]
+}
+
+/// The executables in `dir`, listed again only if it changed.
+fn commands_in_dir(dir: &wstr) -> Vec<WString> {
+    let path = wcs2osstring(dir);
+    let Ok(mtime) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+        return vec![];
+    };
+    let mut cache = PATH_CACHE.lock().unwrap();
+    if let Some((cached_mtime, commands)) = cache.get(dir) {
+        if *cached_mtime == mtime {
+            return commands.clone();
+        }
+    }
+    let mut commands = vec![];
+    if let Ok(entries) = std::fs::read_dir(&path) {
+        for entry in entries.flatten() {
+            let is_executable = std::fs::metadata(entry.path())
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+            if is_executable {
+                commands.push(str2wcstring(entry.file_name().as_bytes()));
+            }
+        }
+    }
+    cache.insert(dir.to_owned(), (mtime, commands.clone()));
+    commands
+}
+
+/// The commands in $PATH with names closest to `cmd`, best first.
+fn similar_commands(parser: &mut parser_t, cmd: &wstr) -> Vec<WString> {
+    // Short names are close to too many others, so only allow one typo in those.
+    let max_distance = if cmd.len() <= 4 { 1 } else { 2 };
+    let mut found: Vec<(usize, WString)> = vec![];
+    for dir in get_list(parser, L!("PATH")).unwrap_or_default() {
+        for command in commands_in_dir(&dir) {
+            if command.len().abs_diff(cmd.len()) > max_distance {
+                continue;
+            }
+            let distance = edit_distance(cmd, &command);
+            if distance <= max_distance && !found.iter().any(|(_, c)| *c == command) {
+                found.push((distance, command));
+            }
+        }
+    }
+    found.sort();
+    found.truncate(MAX_SUGGESTIONS);
+    found.into_iter().map(|(_, command)| command).collect()
+}
+
+/// Ask the backends which packages provide `cmd`, giving up on those still running after `timeout`.
+fn find_packages(
+    parser: &mut parser_t,
+    backends: &[&'static dyn Backend],
+    cmd: &wstr,
+    timeout: Duration,
+) -> Vec<WString> {
+    let mut results: HashMap<&'static str, Vec<WString>> = HashMap::new();
+    let (sender, receiver) = mpsc::channel();
+    let mut running: Vec<(&'static str, u32)> = vec![];
+    for backend in backends {
+        let key = (backend.name(), cmd.to_owned());
+        if let Some(packages) = PACKAGE_CACHE.lock().unwrap().get(&key) {
+            results.insert(backend.name(), packages.clone());
+            continue;
+        }
+        let Some(mut command) = backend.command(parser, cmd) else {
+            continue;
+        };
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let Ok(child) = command.spawn() else {
+            continue;
+        };
+        running.push((backend.name(), child.id()));
+        let sender = sender.clone();
+        let backend: &'static dyn Backend = *backend;
+        iothread_perform_cant_wait(move || {
+            let _ = sender.send((backend.name(), wait_for_packages(backend, child)));
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    while !running.is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Ok((name, packages)) = receiver.recv_timeout(remaining) else {
+            break;
+        };
+        running.retain(|(n, _)| *n != name);
+        PACKAGE_CACHE
+            .lock()
+            .unwrap()
+            .insert((name, cmd.to_owned()), packages.clone());
+        results.insert(name, packages);
+    }
+    // Don't leave the slow ones running.
+    for (_, pid) in running {
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+    }
+
+    let mut packages: Vec<WString> = vec![];
+    for backend in backends {
+        for package in results.remove(backend.name()).unwrap_or_default() {
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+    }
+    packages
+}
+
+fn wait_for_packages(backend: &dyn Backend, child: Child) -> Vec<WString> {
+    match child.wait_with_output() {
+        Ok(output) => {
+            let mut text = output.stdout;
+            text.extend(output.stderr);
+            let text = String::from_utf8_lossy(&text);
+            let packages = backend.packages(&text);
+            packages.iter().map(|p| str2wcstring(p.as_bytes())).collect()
+        }
+        Err(_) => vec![],
+    }
+}
+
+/// The value of the variable `name`, if it is set.
+fn get_list(parser: &mut parser_t, name: &wstr) -> Option<Vec<WString>> {
+    let var = parser
+        .get_var_stack()
+        .get_or_null(&name.to_ffi(), EnvMode::DEFAULT.bits());
+    Some(var.as_ref()?.as_list_ffi().as_ref().unwrap().from_ffi())
+}
+
+/// The backends named in $fish_command_not_found_backends, or all of them if it isn't set.
+fn selected_backends(parser: &mut parser_t) -> Vec<&'static dyn Backend> {
+    match get_list(parser, L!("fish_command_not_found_backends")) {
+        None => BACKENDS.to_vec(),
+        Some(names) => BACKENDS
+            .iter()
+            .copied()
+            .filter(|backend| names.iter().any(|name| name == backend.name()))
+            .collect(),
+    }
+}
+
+pub fn fish_command_not_found(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    argv: &mut [&wstr],
+) -> Option<c_int> {
+    let cmd = argv[0];
+    let print_hints = false;
+
+    const shortopts: &wstr = L!("+:h");
+    const longopts: &[woption] = &[wopt(L!("help"), woption_argument_t::no_argument, 'h')];
+
+    let mut w = wgetopter_t::new(shortopts, longopts, argv);
+    while let Some(c) = w.wgetopt_long() {
+        match c {
+            'h' => {
+                builtin_print_help(parser, streams, cmd);
+                return STATUS_CMD_OK;
+            }
+            ':' => {
+                builtin_missing_argument(parser, streams, cmd, argv[w.woptind - 1], print_hints);
+                return STATUS_INVALID_ARGS;
+            }
+            '?' => {
+                builtin_unknown_option(parser, streams, cmd, argv[w.woptind - 1], print_hints);
+                return STATUS_INVALID_ARGS;
+            }
+            _ => {
+                panic!("unexpected retval from wgeopter.next()");
+            }
+        }
+    }
+
+    let Some(missing) = argv.get(w.woptind).copied() else {
+        streams.err.append(wgettext_fmt!(
+            BUILTIN_ERR_MIN_ARG_COUNT1,
+            cmd,
+            1,
+            argv.len() - w.woptind
+        ));
+        return STATUS_INVALID_ARGS;
+    };
+
+    streams.err.append(wgettext_fmt!(
+        "fish: Unknown command: %ls\n",
+        escape(missing)
+    ));
+
+    // Paths can't be misspelled commands, or be in packages.
+    if missing.contains('/') {
+        return STATUS_CMD_UNKNOWN;
+    }
+
+    let suggestions = similar_commands(parser, missing);
+    if !suggestions.is_empty() {
+        let mut joined = WString::new();
+        for (i, suggestion) in suggestions.iter().enumerate() {
+            if i > 0 {
+                joined.push_str(", ");
+            }
+            joined.push_utfstr(&escape(suggestion));
+        }
+        streams
+            .err
+            .append(wgettext_fmt!("Did you mean: %ls?\n", joined));
+    }
+
+    let timeout = get_list(parser, L!("fish_command_not_found_timeout"))
+        .and_then(|values| values.into_iter().next())
+        .and_then(|value| fish_wcstoi(&value).ok())
+        .map(|ms| Duration::from_millis(ms.max(0) as u64))
+        .unwrap_or(Duration::from_millis(DEFAULT_TIMEOUT_MS));
+    let backends = selected_backends(parser);
+    let packages = find_packages(parser, &backends, missing, timeout);
+    if !packages.is_empty() {
+        streams.err.append(wgettext_fmt!(
+            "%ls may be found in the following packages:\n",
+            escape(missing)
+        ));
+        for package in packages {
+            streams.err.append(sprintf!("  %ls\n", package));
+        }
+    }
+
+    STATUS_CMD_UNKNOWN
+}
+
+use crate::ffi_tests::add_test;
+add_test!("test_command_not_found_edit_distance", || {
+    assert_eq!(edit_distance(L!("sl"), L!("ls")), 1);
+    assert_eq!(edit_distance(L!("gti"), L!("git")), 1);
+    assert_eq!(edit_distance(L!("pyhton3"), L!("python3")), 1);
+    assert_eq!(edit_distance(L!("grpe"), L!("grep")), 1);
+    assert_eq!(edit_distance(L!("fihs"), L!("fish")), 1);
+    assert_eq!(edit_distance(L!("mkdri"), L!("rmdir")), 3);
+    assert_eq!(edit_distance(L!(""), L!("cat")), 3);
+    assert_eq!(edit_distance(L!("cat"), L!("cat")), 0);
+    assert_eq!(edit_distance(L!("kitten"), L!("sitting")), 3);
+});
@@ -0,0 +1,434 @@
+//! Implementation of the fish_update_completions builtin, which generates completions from the
+//! installed man pages.
+//!
+//! The pages are parsed by the mangen module, on as many threads as there are CPUs. The main
+//! thread collects the results, so that only it writes to the builtin's output.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use libc::c_int;
+
+use crate::builtins::shared::{
+    builtin_missing_argument, builtin_print_help, builtin_unknown_option, io_streams_t,
+    BUILTIN_ERR_NOT_NUMBER, STATUS_CMD_ERROR, STATUS_CMD_OK, STATUS_INVALID_ARGS,
+};
+use crate::common::{str2wcstring, wcs2osstring, wcs2string};
+use crate::env::EnvMode;
+use crate::ffi::parser_t;
+use crate::mangen;
+use crate::signal::signal_check_cancel;
+use crate::threads;
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::{WCharFromFFI, WCharToFFI};
+use crate::wgetopt::{wgetopter_t, wopt, woption, woption_argument_t};
+use crate::wutil::{fish_wcstoi, wgettext_fmt};
+
+/// The sections of the manual with commands in them.
+const COMMAND_SECTIONS: &[&str] = &["man1", "man6", "man8"];
+
+/// What became of a man page.
+enum Outcome {
+    /// Its completions were written to the directory.
+    Written,
+    /// Its completions, to be printed with --stdout.
+    Printed(String),
+    /// None of the parsers found any options in it.
+    Unparsable,
+    /// It couldn't be read.
+    Unreadable(io::Error),
+    /// Its completions couldn't be written to this file.
+    Unwritable(PathBuf, io::Error),
+}
+
+/// The value of the variable `name`, if it is set.
+fn get_list(parser: &mut parser_t, name: &wstr) -> Option<Vec<WString>> {
+    let var = parser
+        .get_var_stack()
+        .get_or_null(&name.to_ffi(), EnvMode::DEFAULT.bits());
+    Some(var.as_ref()?.as_list_ffi().as_ref().unwrap().from_ffi())
+}
+
+/// The first value of the variable `name` as a path, if it is set and not empty.
+fn get_path(parser: &mut parser_t, name: &wstr) -> Option<PathBuf> {
+    let value = get_list(parser, name)?.into_iter().next()?;
+    (!value.is_empty()).then(|| PathBuf::from(wcs2osstring(&value)))
+}
+
+/// The directories of man pages: what man or manpath say, or else $MANPATH, or else what
+/// man.conf says, or else the usual ones.
+fn manpath(parser: &mut parser_t, streams: &mut io_streams_t) -> Vec<String> {
+    let manpath_var = get_list(parser, L!("MANPATH")).map(|dirs| {
+        dirs.iter()
+            .map(|dir| String::from_utf8_lossy(&wcs2string(dir)).into_owned())
+            .collect::<Vec<_>>()
+            .join(":")
+    });
+
+    // Most implementations of man support --path. Traditional ones have a manpath program
+    // instead. These know more directories than $MANPATH lists.
+    for program in [&["man", "--path"][..], &["manpath"]] {
+        let mut command = Command::new(program[0]);
+        command
+            .args(&program[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(manpath) = &manpath_var {
+            command.env("MANPATH", manpath);
+        }
+        match command.output() {
+            Ok(output) if output.status.success() => {
+                let dirs = String::from_utf8_lossy(&output.stdout);
+                return dirs.trim().split(':').map(str::to_owned).collect();
+            }
+            _ => continue,
+        }
+    }
+    if let Some(manpath) = manpath_var {
+        return manpath.split(':').map(str::to_owned).collect();
+    }
+
+    // With mandoc and NetBSD's man, the default manpath is only in /etc/man.conf.
+    let mut dirs = vec![];
+    if let Ok(conf) = std::fs::read_to_string("/etc/man.conf") {
+        for line in conf.lines() {
+            let Some((key, value)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            if key.eq_ignore_ascii_case("MANPATH") || key.eq_ignore_ascii_case("_default") {
+                dirs.push(value.trim().to_owned());
+            }
+        }
+    }
+    if dirs.is_empty() {
+        dirs = ["/usr/share/man", "/usr/local/man", "/usr/local/share/man"]
+            .map(str::to_owned)
+            .to_vec();
+        streams.err.append(wgettext_fmt!(
+            "Unable to get the manpath, falling back to %ls. Set $MANPATH to fix this.\n",
+            str2wcstring(dirs.join(":").as_bytes())
+        ));
+    }
+    dirs
+}
+
+/// The man pages of commands in the directories `dirs`.
+fn command_pages(dirs: &[String]) -> Vec<PathBuf> {
+    let mut result = vec![];
+    for dir in dirs.iter().filter(|dir| !dir.is_empty()) {
+        for section in COMMAND_SECTIONS {
+            let Ok(entries) = std::fs::read_dir(Path::new(dir).join(section)) else {
+                continue;
+            };
+            let mut pages: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+            pages.sort();
+            result.extend(pages);
+        }
+    }
+    result
+}
+
+/// Deletes the completions in `dir` that were generated from man pages.
+fn remove_autogenerated(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map_or(false, |ext| ext == "fish")
+            && std::fs::read_to_string(&path).map_or(false, |c| mangen::is_autogenerated(&c))
+        {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Generates the completions for the man page at `path`, which documents `cmd`, and writes them to
+/// `directory`, if there is one.
+fn process_page(path: &Path, cmd: &str, directory: Option<&Path>, deroff_only: bool) -> Outcome {
+    let page = match mangen::read_man_page(path) {
+        Ok(page) => page,
+        Err(err) => return Outcome::Unreadable(err),
+    };
+    let source = path.to_string_lossy();
+    let Some(completions) = mangen::generate_completions(cmd, &page, &source, deroff_only) else {
+        return Outcome::Unparsable;
+    };
+    let Some(directory) = directory else {
+        return Outcome::Printed(completions);
+    };
+    let file = directory.join(format!("{}.fish", cmd));
+    match std::fs::write(&file, completions) {
+        Ok(()) => Outcome::Written,
+        Err(err) => Outcome::Unwritable(file, err),
+    }
+}
+
+pub fn fish_update_completions(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    argv: &mut [&wstr],
+) -> Option<c_int> {
+    let cmd = argv[0];
+    let print_hints = false;
+    let mut cleanup_in: Vec<PathBuf> = vec![];
+    let mut directory: Option<PathBuf> = None;
+    let mut keep = false;
+    let mut use_manpath = false;
+    let mut progress = false;
+    let mut to_stdout = false;
+    let mut verbosity = 0;
+    let mut deroff_only = false;
+
+    const shortopts: &wstr = L!(":c:d:kmpsv:zh");
+    const longopts: &[woption] = &[
+        wopt(L!("cleanup-in"), woption_argument_t::required_argument, 'c'),
+        wopt(L!("directory"), woption_argument_t::required_argument, 'd'),
+        wopt(L!("keep"), woption_argument_t::no_argument, 'k'),
+        wopt(L!("manpath"), woption_argument_t::no_argument, 'm'),
+        wopt(L!("progress"), woption_argument_t::no_argument, 'p'),
+        wopt(L!("stdout"), woption_argument_t::no_argument, 's'),
+        wopt(L!("verbose"), woption_argument_t::required_argument, 'v'),
+        wopt(L!("deroff-only"), woption_argument_t::no_argument, 'z'),
+        wopt(L!("help"), woption_argument_t::no_argument, 'h'),
+    ];
+
+    let mut w = wgetopter_t::new(shortopts, longopts, argv);
+    while let Some(c) = w.wgetopt_long() {
+        match c {
+            'c' => cleanup_in.push(PathBuf::from(wcs2osstring(w.woptarg.unwrap()))),
+            'd' => directory = Some(PathBuf::from(wcs2osstring(w.woptarg.unwrap()))),
+            'k' => keep = true,
+            'm' => use_manpath = true,
+            'p' => progress = true,
+            's' => to_stdout = true,
+            'v' => {
+                let arg = w.woptarg.unwrap();
+                verbosity = match fish_wcstoi(arg) {
+                    Ok(level) => level,
+                    Err(_) => {
+                        streams
+                            .err
+                            .append(wgettext_fmt!(BUILTIN_ERR_NOT_NUMBER, cmd, arg));
+                        return STATUS_INVALID_ARGS;
+                    }
+                };
+            }
+            'z' => deroff_only = true,
+            'h' => {
+                builtin_print_help(parser, streams, cmd);
+                return STATUS_CMD_OK;
+            }
+            ':' => {
+                builtin_missing_argument(parser, streams, cmd, argv[w.woptind - 1], print_hints);
+                return STATUS_INVALID_ARGS;
+            }
+            '?' => {
+                builtin_unknown_option(parser, streams, cmd, argv[w.woptind - 1], print_hints);
+                return STATUS_INVALID_ARGS;
+            }
+            _ => {
+                panic!("unexpected retval from wgeopter.next()");
+            }
+        }
+    }
+
+    let mut paths: Vec<PathBuf> = argv[w.woptind..]
+        .iter()
+        .map(|path| PathBuf::from(wcs2osstring(path)))
+        .collect();
+    // Without any pages, update the completions for all of them, like fish does the first time it
+    // runs. This also removes what older versions of fish generated in the config directory.
+    if paths.is_empty() {
+        use_manpath = true;
+        progress = !to_stdout;
+        if let Some(config_dir) = get_path(parser, L!("__fish_config_dir")) {
+            cleanup_in.push(config_dir.join("generated_completions"));
+        }
+    }
+    if use_manpath {
+        let dirs = manpath(parser, streams);
+        paths.extend(command_pages(&dirs));
+    }
+
+    let directory = if to_stdout {
+        None
+    } else {
+        let directory = directory.or_else(|| {
+            get_path(parser, L!("__fish_user_data_dir"))
+                .map(|dir| dir.join("generated_completions"))
+        });
+        let Some(directory) = directory else {
+            streams.err.append(wgettext_fmt!(
+                "%ls: Unable to find the directory for completions\n",
+                cmd
+            ));
+            return STATUS_CMD_ERROR;
+        };
+        if let Err(err) = std::fs::create_dir_all(&directory) {
+            streams.err.append(wgettext_fmt!(
+                "%ls: Unable to create %ls: %ls\n",
+                cmd,
+                str2wcstring(directory.to_string_lossy().as_bytes()),
+                str2wcstring(err.to_string().as_bytes())
+            ));
+            return STATUS_CMD_ERROR;
+        }
+        Some(directory)
+    };
+
+    for dir in &cleanup_in {
+        remove_autogenerated(dir);
+    }
+    if let Some(directory) = &directory {
+        if !keep {
+            remove_autogenerated(directory);
+        }
+    }
+
+    // If several pages document a command, like in man1 and man8, the last one wins.
+    paths.sort();
+    let mut seen = HashSet::new();
+    let mut pages: Vec<(PathBuf, String)> = paths
+        .into_iter()
+        .rev()
+        .filter_map(|path| Some((mangen::command_name(&path)?, path)))
+        .filter(|(cmd, _)| seen.insert(cmd.clone()))
+        .map(|(cmd, path)| (path, cmd))
+        .collect();
+    pages.reverse();
+    if pages.is_empty() {
+        streams
+            .err
+            .append(wgettext_fmt!("%ls: No man pages found\n", cmd));
+        return STATUS_CMD_OK;
+    }
+
+    if progress {
+        if let Some(directory) = &directory {
+            streams.out.append(wgettext_fmt!(
+                "Parsing man pages and writing completions to %ls\n",
+                str2wcstring(directory.to_string_lossy().as_bytes())
+            ));
+        }
+    }
+
+    let pages = Arc::new(pages);
+    let next_page = Arc::new(AtomicUsize::new(0));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    let worker = {
+        let pages = Arc::clone(&pages);
+        let directory = directory.clone();
+        let cancelled = Arc::clone(&cancelled);
+        move || loop {
+            let index = next_page.fetch_add(1, Ordering::Relaxed);
+            if index >= pages.len() || cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            let (path, cmd) = &pages[index];
+            let outcome = process_page(path, cmd, directory.as_deref(), deroff_only);
+            if sender.send((index, outcome)).is_err() {
+                break;
+            }
+        }
+    };
+    let thread_count = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(pages.len());
+    let spawned = (0..thread_count)
+        .filter(|_| threads::spawn(worker.clone()))
+        .count();
+    if spawned == 0 {
+        // Do it all here then.
+        worker();
+    } else {
+        drop(worker);
+    }
+
+    let mut printed: Vec<Option<String>> = vec![None; pages.len()];
+    let mut done = 0;
+    let mut successes = 0;
+    let mut last_progress_len = 0;
+    let width = pages.len().to_string().len();
+    loop {
+        if signal_check_cancel() != 0 {
+            cancelled.store(true, Ordering::Relaxed);
+            break;
+        }
+        let (index, outcome) = match receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        done += 1;
+        let (path, _) = &pages[index];
+        let path_str = str2wcstring(path.to_string_lossy().as_bytes());
+        match outcome {
+            Outcome::Written => successes += 1,
+            Outcome::Printed(completions) => {
+                successes += 1;
+                printed[index] = Some(completions);
+            }
+            Outcome::Unparsable => {
+                if verbosity >= 1 {
+                    streams.err.append(wgettext_fmt!(
+                        "%ls contains no options or is unparsable\n",
+                        path_str
+                    ));
+                }
+            }
+            Outcome::Unreadable(err) => {
+                if verbosity >= 1 {
+                    streams.err.append(wgettext_fmt!(
+                        "Cannot open %ls: %ls\n",
+                        path_str,
+                        str2wcstring(err.to_string().as_bytes())
+                    ));
+                }
+            }
+            Outcome::Unwritable(file, err) => {
+                streams.err.append(wgettext_fmt!(
+                    "%ls: Unable to write %ls: %ls\n",
+                    cmd,
+                    str2wcstring(file.to_string_lossy().as_bytes()),
+                    str2wcstring(err.to_string().as_bytes())
+                ));
+            }
+        }
+
+        if progress {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let line = format!("  {:>width$} / {} : {}", done, pages.len(), name);
+            // Pad with spaces to overwrite what was there before.
+            let padded = format!("\r{:<last$}\r", line, last = last_progress_len);
+            last_progress_len = line.chars().count();
+            streams.out.append(str2wcstring(padded.as_bytes()));
+        }
+    }
+    if progress {
+        streams.out.append1('\n');
+    }
+
+    for completions in printed.into_iter().flatten() {
+        streams.out.append(str2wcstring(completions.as_bytes()));
+    }
+    if verbosity >= 1 {
+        streams.err.append(wgettext_fmt!(
+            "Successfully parsed %d / %d pages\n",
+            successes,
+            pages.len()
+        ));
+    }
+
+    if cancelled.load(Ordering::Relaxed) {
+        STATUS_CMD_ERROR
+    } else {
+        STATUS_CMD_OK
+    }
+}
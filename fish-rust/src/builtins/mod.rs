@@ -9,7 +9,10 @@ pub mod contains;
 pub mod echo;
 pub mod emit;
 pub mod exit;
+pub mod fish_command_not_found;
+pub mod fish_update_completions;
 pub mod math;
+pub mod plugin;
 pub mod printf;
 pub mod pwd;
 pub mod random;
@@ -0,0 +1,315 @@
+//! Implementation of the plugin builtin, and of the builtins that plugins provide.
+
+use autocxx::WithinUniquePtr;
+use libc::c_int;
+
+use crate::builtins::shared::{
+    builtin_missing_argument, builtin_print_error_trailer, builtin_print_help,
+    builtin_unknown_option, io_streams_t, BUILTIN_ERR_ARG_COUNT2, BUILTIN_ERR_COMBO,
+    BUILTIN_ERR_INVALID_SUBCMD, BUILTIN_ERR_MIN_ARG_COUNT1, BUILTIN_ERR_MISSING_SUBCMD,
+    STATUS_CMD_ERROR, STATUS_CMD_OK, STATUS_INVALID_ARGS,
+};
+use crate::common::escape;
+use crate::env::EnvMode;
+use crate::ffi::parser_t;
+use crate::plugins::{self, CallResult, Capabilities, Entry, EventHandler};
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::WCharToFFI;
+use crate::wgetopt::{wgetopter_t, wopt, woption, woption_argument_t};
+use crate::wutil::{sprintf, wgettext_fmt};
+
+#[derive(Default)]
+struct Options {
+    print_help: bool,
+    caps: Capabilities,
+}
+
+const short_options: &wstr = L!(":hr:w:d:");
+const long_options: &[woption] = &[
+    wopt(L!("help"), woption_argument_t::no_argument, 'h'),
+    wopt(L!("read-var"), woption_argument_t::required_argument, 'r'),
+    wopt(L!("write-var"), woption_argument_t::required_argument, 'w'),
+    wopt(L!("dir"), woption_argument_t::required_argument, 'd'),
+];
+
+fn parse_options(
+    args: &mut [&wstr],
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+) -> Result<(Options, usize), Option<c_int>> {
+    let cmd = args[0];
+    let mut opts = Options::default();
+    let mut w = wgetopter_t::new(short_options, long_options, args);
+    while let Some(c) = w.wgetopt_long() {
+        match c {
+            'h' => opts.print_help = true,
+            'r' => opts.caps.read_vars.push(w.woptarg.unwrap().to_owned()),
+            'w' => opts.caps.write_vars.push(w.woptarg.unwrap().to_owned()),
+            'd' => opts.caps.dirs.push(w.woptarg.unwrap().to_owned()),
+            ':' => {
+                builtin_missing_argument(parser, streams, cmd, args[w.woptind - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            '?' => {
+                builtin_unknown_option(parser, streams, cmd, args[w.woptind - 1], false);
+                return Err(STATUS_INVALID_ARGS);
+            }
+            _ => panic!("unexpected retval from wgetopt_long"),
+        }
+    }
+    Ok((opts, w.woptind))
+}
+
+/// Print what a plugin wrote and set the variables it set. \return its status.
+fn finish_call(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    cmd: &wstr,
+    result: CallResult,
+) -> Option<c_int> {
+    streams.out.append(&result.out);
+    streams.err.append(&result.err);
+    for (name, values) in &result.sets {
+        let values: Vec<&wstr> = values.iter().map(|v| v.as_utfstr()).collect();
+        if parser.set_var(name, &values, EnvMode::USER) != 0 {
+            streams.err.append(wgettext_fmt!(
+                "%ls: could not set variable '%ls'\n",
+                cmd,
+                name
+            ));
+        }
+    }
+    Some(result.status)
+}
+
+/// The commandline which adds a completion provided by a plugin for \p command.
+fn completion_command(command: &wstr) -> WString {
+    let provider = sprintf!(
+        "(plugin complete -- %ls (commandline -cp | string collect))",
+        escape(command)
+    );
+    sprintf!("complete -c %ls -a %ls", escape(command), escape(&provider))
+}
+
+/// The commandline which defines the function running the event handler \p handler.
+fn event_function(handler: &EventHandler) -> WString {
+    sprintf!(
+        "function %ls %ls %ls; plugin event %ls $argv; end",
+        handler.function,
+        handler.kind.function_option(),
+        escape(&handler.name),
+        handler.function
+    )
+}
+
+fn eval(parser: &mut parser_t, cmd: &wstr) {
+    parser
+        .pin()
+        .eval_string_ffi1(&cmd.to_ffi())
+        .within_unique_ptr();
+}
+
+fn load(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    cmd: &wstr,
+    path: &wstr,
+    caps: Capabilities,
+) -> Option<c_int> {
+    let (_, registrations, result) = match plugins::load(parser, path, caps) {
+        Ok(loaded) => loaded,
+        Err(msg) => {
+            streams.err.append(sprintf!("%ls: %ls\n", cmd, msg));
+            return STATUS_CMD_ERROR;
+        }
+    };
+    for command in &registrations.completions {
+        eval(parser, &completion_command(command));
+    }
+    for handler in &registrations.events {
+        eval(parser, &event_function(handler));
+    }
+    finish_call(parser, streams, cmd, result);
+    STATUS_CMD_OK
+}
+
+fn unload(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    cmd: &wstr,
+    names: &[&wstr],
+) -> Option<c_int> {
+    let mut retval = STATUS_CMD_OK;
+    for name in names {
+        let Some(registrations) = plugins::unload(name) else {
+            streams.err.append(wgettext_fmt!(
+                "%ls: no plugin named '%ls' is loaded\n",
+                cmd,
+                name
+            ));
+            retval = STATUS_CMD_ERROR;
+            continue;
+        };
+        for command in &registrations.completions {
+            eval(parser, &sprintf!("complete -e -c %ls", escape(command)));
+        }
+        for handler in &registrations.events {
+            eval(parser, &sprintf!("functions -e %ls", handler.function));
+        }
+    }
+    retval
+}
+
+fn list(streams: &mut io_streams_t) -> Option<c_int> {
+    for plugin in plugins::list() {
+        streams
+            .out
+            .append(sprintf!("%ls\t%ls\n", plugin.name, plugin.path));
+        let regs = &plugin.registrations;
+        for (kind, names) in [
+            (L!("builtin"), &regs.builtins),
+            (L!("completion"), &regs.completions),
+            (L!("prompt-segment"), &regs.segments),
+        ] {
+            for name in names {
+                streams.out.append(sprintf!("\t%ls\t%ls\n", kind, name));
+            }
+        }
+        for handler in &regs.events {
+            streams.out.append(sprintf!(
+                "\tevent\t%ls %ls\n",
+                handler.kind.function_option(),
+                handler.name
+            ));
+        }
+    }
+    STATUS_CMD_OK
+}
+
+/// Call the plugin which registered \p name as \p entry.
+fn call(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    cmd: &wstr,
+    entry: Entry,
+    name: &wstr,
+    inputs: &[&wstr],
+) -> Option<c_int> {
+    match plugins::call(parser, entry, name, inputs) {
+        Some(Ok(result)) => finish_call(parser, streams, cmd, result),
+        Some(Err(msg)) => {
+            streams.err.append(sprintf!("%ls: %ls\n", cmd, msg));
+            STATUS_CMD_ERROR
+        }
+        None => {
+            let msg = match entry {
+                Entry::Builtin => wgettext_fmt!("%ls: no plugin provides '%ls'\n", cmd, name),
+                Entry::Completion => {
+                    wgettext_fmt!("%ls: no plugin provides completions for '%ls'\n", cmd, name)
+                }
+                Entry::Segment => wgettext_fmt!(
+                    "%ls: no plugin provides the prompt segment '%ls'\n",
+                    cmd,
+                    name
+                ),
+                Entry::Event => {
+                    wgettext_fmt!("%ls: no plugin handles events with '%ls'\n", cmd, name)
+                }
+            };
+            streams.err.append(msg);
+            STATUS_CMD_ERROR
+        }
+    }
+}
+
+/// The plugin builtin, which loads and manages plugins.
+pub fn plugin(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    args: &mut [&wstr],
+) -> Option<c_int> {
+    let cmd = args[0];
+    let (opts, optind) = match parse_options(args, parser, streams) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+    if opts.print_help {
+        builtin_print_help(parser, streams, cmd);
+        return STATUS_CMD_OK;
+    }
+
+    let Some(&subcmd) = args.get(optind) else {
+        streams
+            .err
+            .append(wgettext_fmt!(BUILTIN_ERR_MISSING_SUBCMD, cmd));
+        builtin_print_error_trailer(parser, streams, cmd);
+        return STATUS_INVALID_ARGS;
+    };
+    let rest = &args[optind + 1..];
+
+    // Only load takes capabilities.
+    let has_caps = !opts.caps.read_vars.is_empty()
+        || !opts.caps.write_vars.is_empty()
+        || !opts.caps.dirs.is_empty();
+    if has_caps && subcmd != L!("load") {
+        streams.err.append(wgettext_fmt!(BUILTIN_ERR_COMBO, cmd));
+        builtin_print_error_trailer(parser, streams, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    let subcmd_name = subcmd.to_string();
+    let expected = match subcmd_name.as_str() {
+        "load" | "segment" => Some(1),
+        "complete" => Some(2),
+        "list" => Some(0),
+        "unload" | "event" => None,
+        _ => {
+            streams
+                .err
+                .append(wgettext_fmt!(BUILTIN_ERR_INVALID_SUBCMD, cmd, subcmd));
+            builtin_print_error_trailer(parser, streams, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+    };
+    if let Some(expected) = expected {
+        if rest.len() != expected {
+            streams.err.append(wgettext_fmt!(
+                BUILTIN_ERR_ARG_COUNT2,
+                cmd,
+                subcmd,
+                expected,
+                rest.len()
+            ));
+            return STATUS_INVALID_ARGS;
+        }
+    }
+
+    match subcmd_name.as_str() {
+        "load" => load(parser, streams, cmd, rest[0], opts.caps),
+        "unload" => unload(parser, streams, cmd, rest),
+        "list" => list(streams),
+        "complete" => call(parser, streams, cmd, Entry::Completion, rest[0], &rest[1..]),
+        "segment" => call(parser, streams, cmd, Entry::Segment, rest[0], &[]),
+        "event" if rest.is_empty() => {
+            streams.err.append(wgettext_fmt!(
+                BUILTIN_ERR_MIN_ARG_COUNT1,
+                cmd,
+                1,
+                rest.len()
+            ));
+            STATUS_INVALID_ARGS
+        }
+        "event" => call(parser, streams, cmd, Entry::Event, rest[0], &rest[1..]),
+        _ => unreachable!(),
+    }
+}
+
+/// Run a builtin provided by a plugin. The plugin handles its own options, including --help.
+pub fn plugin_builtin(
+    parser: &mut parser_t,
+    streams: &mut io_streams_t,
+    args: &mut [&wstr],
+) -> Option<c_int> {
+    let cmd = args[0];
+    call(parser, streams, cmd, Entry::Builtin, cmd, &args[1..])
+}
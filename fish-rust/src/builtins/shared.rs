@@ -44,6 +44,10 @@ pub const BUILTIN_ERR_ARG_COUNT2: &str = "%ls: %ls: expected %d arguments; got %
 pub const BUILTIN_ERR_MIN_ARG_COUNT1: &str = "%ls: expected >= %d arguments; got %d\n";
 pub const BUILTIN_ERR_MAX_ARG_COUNT1: &str = "%ls: expected <= %d arguments; got %d\n";
 
+/// Error messages for subcommands.
+pub const BUILTIN_ERR_MISSING_SUBCMD: &str = "%ls: missing subcommand\n";
+pub const BUILTIN_ERR_INVALID_SUBCMD: &str = "%ls: %ls: invalid subcommand\n";
+
 /// Error message on invalid combination of options.
 pub const BUILTIN_ERR_COMBO: &str = "%ls: invalid option combination\n";
 pub const BUILTIN_ERR_COMBO2: &str = "%ls: invalid option combination, %ls\n";
@@ -73,6 +77,8 @@ pub const STATUS_ILLEGAL_CMD: Option<c_int> = Some(123);
 pub const STATUS_READ_TOO_MUCH: Option<c_int> = Some(122);
 /// The status code when an expansion fails, for example, "$foo["
 pub const STATUS_EXPAND_ERROR: Option<c_int> = Some(121);
+/// The status code when a job was killed because it exceeded $fish_timeout.
+pub const STATUS_TIMED_OUT: Option<c_int> = Some(120);
 
 /// A wrapper around output_stream_t.
 pub struct output_stream_t(*mut ffi::output_stream_t);
@@ -178,7 +184,15 @@ pub fn run_builtin(
         RustBuiltin::Echo => super::echo::echo(parser, streams, args),
         RustBuiltin::Emit => super::emit::emit(parser, streams, args),
         RustBuiltin::Exit => super::exit::exit(parser, streams, args),
+        RustBuiltin::FishCommandNotFound => {
+            super::fish_command_not_found::fish_command_not_found(parser, streams, args)
+        }
+        RustBuiltin::FishUpdateCompletions => {
+            super::fish_update_completions::fish_update_completions(parser, streams, args)
+        }
         RustBuiltin::Math => super::math::math(parser, streams, args),
+        RustBuiltin::Plugin => super::plugin::plugin(parser, streams, args),
+        RustBuiltin::PluginBuiltin => super::plugin::plugin_builtin(parser, streams, args),
         RustBuiltin::Pwd => super::pwd::pwd(parser, streams, args),
         RustBuiltin::Random => super::random::random(parser, streams, args),
         RustBuiltin::Realpath => super::realpath::realpath(parser, streams, args),
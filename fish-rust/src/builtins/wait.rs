@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use libc::{c_int, pid_t};
 
 use crate::builtins::shared::{
@@ -139,6 +141,190 @@ fn wait_for_completion(
     }
 }
 
+/// How long to wait for foreign processes at once, before checking for signals.
+const FOREIGN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// \return true if a process with the given pid exists, whether or not it is our child.
+fn process_exists(pid: pid_t) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 || errno::errno().0 != libc::ESRCH }
+}
+
+/// \return the state of the process with the given pid from /proc, like 'R' or 'Z', or None if
+/// /proc can't tell.
+#[cfg(target_os = "linux")]
+fn proc_state(pid: pid_t) -> Option<u8> {
+    let stat = std::fs::read(format!("/proc/{pid}/stat")).ok()?;
+    // The state follows the command name in parentheses, which may itself contain parentheses.
+    let after_name = stat.iter().rposition(|&c| c == b')')? + 1;
+    stat[after_name..]
+        .iter()
+        .copied()
+        .find(|c| !c.is_ascii_whitespace())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_state(_pid: pid_t) -> Option<u8> {
+    None
+}
+
+/// \return true if the process with the given pid is still running, whether or not it is our
+/// child. A zombie has exited, even though kill() still finds it until its parent reaps it.
+fn process_running(pid: pid_t) -> bool {
+    // A child of ours stays a zombie until we reap it, which waitid() can tell without reaping.
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+    if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, options) } == 0 {
+        return unsafe { info.si_pid() } == 0;
+    }
+    match proc_state(pid) {
+        Some(state) => state != b'Z' && state != b'X',
+        // Without /proc, zombies of other processes can't be told apart from running ones.
+        None => process_exists(pid),
+    }
+}
+
+/// Wait up to \p timeout for any of the processes \p pids to exit, where they need not be our
+/// children. This uses a pidfd on Linux.
+/// \return the pids which have exited.
+#[cfg(target_os = "linux")]
+fn wait_for_foreign_exit(pids: &[pid_t], timeout: Duration) -> Vec<pid_t> {
+    let mut exited = Vec::new();
+    let mut pollfds = Vec::new();
+    let mut polled_pids = Vec::new();
+    for &pid in pids {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } as c_int;
+        if fd >= 0 {
+            pollfds.push(libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            polled_pids.push(pid);
+        } else if !process_running(pid) {
+            exited.push(pid);
+        }
+    }
+    if exited.is_empty() && !pollfds.is_empty() {
+        unsafe {
+            libc::poll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                timeout.as_millis() as c_int,
+            )
+        };
+        for (pfd, &pid) in pollfds.iter().zip(&polled_pids) {
+            if pfd.revents & libc::POLLIN != 0 {
+                exited.push(pid);
+            }
+        }
+    } else if exited.is_empty() {
+        // pidfds are unsupported (before Linux 5.3), so poll for existence.
+        std::thread::sleep(timeout);
+        exited.extend(pids.iter().filter(|&&pid| !process_running(pid)));
+    }
+    for pfd in pollfds {
+        unsafe { libc::close(pfd.fd) };
+    }
+    exited
+}
+
+/// Wait up to \p timeout for any of the processes \p pids to exit, where they need not be our
+/// children. This uses kqueue's EVFILT_PROC on BSD and macOS.
+/// \return the pids which have exited.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+fn wait_for_foreign_exit(pids: &[pid_t], timeout: Duration) -> Vec<pid_t> {
+    let mut exited = Vec::new();
+    let kq = unsafe { libc::kqueue() };
+    if kq < 0 {
+        std::thread::sleep(timeout);
+        exited.extend(pids.iter().filter(|&&pid| !process_running(pid)));
+        return exited;
+    }
+    let mut registered = 0;
+    for &pid in pids {
+        // A zombie might not report its exit again.
+        if !process_running(pid) {
+            exited.push(pid);
+            continue;
+        }
+        let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+        change.ident = pid as _;
+        change.filter = libc::EVFILT_PROC as _;
+        change.flags = (libc::EV_ADD | libc::EV_ONESHOT) as _;
+        change.fflags = libc::NOTE_EXIT as _;
+        let res = unsafe {
+            libc::kevent(kq, &change, 1, std::ptr::null_mut(), 0, std::ptr::null())
+        };
+        if res == 0 {
+            registered += 1;
+        } else {
+            // ESRCH means it is already gone.
+            exited.push(pid);
+        }
+    }
+    if exited.is_empty() && registered > 0 {
+        let mut events: Vec<libc::kevent> = vec![unsafe { std::mem::zeroed() }; registered];
+        let ts = libc::timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        };
+        let count = unsafe {
+            libc::kevent(kq, std::ptr::null(), 0, events.as_mut_ptr(), registered as _, &ts)
+        };
+        for event in events.iter().take(count.max(0) as usize) {
+            exited.push(event.ident as pid_t);
+        }
+    }
+    unsafe { libc::close(kq) };
+    exited
+}
+
+/// Wait up to \p timeout for any of the processes \p pids to exit, where they need not be our
+/// children. Without a way to be notified, this polls for their existence.
+/// \return the pids which have exited.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+)))]
+fn wait_for_foreign_exit(pids: &[pid_t], timeout: Duration) -> Vec<pid_t> {
+    let exited: Vec<pid_t> = pids
+        .iter()
+        .copied()
+        .filter(|&pid| !process_running(pid))
+        .collect();
+    if exited.is_empty() {
+        std::thread::sleep(timeout);
+    }
+    exited
+}
+
+/// Wait for processes which are not our children, such as daemons started elsewhere.
+/// If \p any_flag is set, wait for the first one; otherwise wait for all.
+/// Their exit status is not available to us, so this only reports whether we were interrupted.
+fn wait_for_foreign_pids(pids: &[pid_t], any_flag: bool) -> Option<c_int> {
+    let mut remaining = pids.to_vec();
+    let mut sigint = SigChecker::new_sighupint();
+    while !remaining.is_empty() {
+        let exited = wait_for_foreign_exit(&remaining, FOREIGN_POLL_INTERVAL);
+        if any_flag && !exited.is_empty() {
+            break;
+        }
+        remaining.retain(|pid| !exited.contains(pid));
+        if sigint.check() {
+            return Some(128 + libc::SIGINT);
+        }
+    }
+    STATUS_CMD_OK
+}
+
 #[widestrs]
 pub fn wait(
     parser: &mut parser_t,
@@ -148,12 +334,14 @@ pub fn wait(
     let cmd = argv[0];
     let argc = argv.len();
     let mut any_flag = false; // flag for -n option
+    let mut pid_flag = false; // flag for -p option
     let mut print_help = false;
     let print_hints = false;
 
-    const shortopts: &wstr = ":nh"L;
+    const shortopts: &wstr = ":nph"L;
     const longopts: &[woption] = &[
         wopt("any"L, woption_argument_t::no_argument, 'n'),
+        wopt("pid"L, woption_argument_t::no_argument, 'p'),
         wopt("help"L, woption_argument_t::no_argument, 'h'),
     ];
 
@@ -163,6 +351,9 @@ pub fn wait(
             'n' => {
                 any_flag = true;
             }
+            'p' => {
+                pid_flag = true;
+            }
             'h' => {
                 print_help = true;
             }
@@ -193,8 +384,34 @@ pub fn wait(
 
     // Get the list of wait handles for our waiting.
     let mut wait_handles: Vec<WaitHandleRef> = Vec::new();
+    // With --pid, processes which are not our children.
+    let mut foreign_pids: Vec<pid_t> = Vec::new();
     for i in w.woptind..argc {
-        if iswnumeric(argv[i]) {
+        if pid_flag {
+            let pid = match fish_wcstoi(argv[i]) {
+                Ok(pid) if pid > 0 => pid as pid_t,
+                _ => {
+                    streams.err.append(wgettext_fmt!(
+                        "%ls: '%ls' is not a valid process id\n",
+                        cmd,
+                        argv[i],
+                    ));
+                    continue;
+                }
+            };
+            if find_wait_handles(WaitHandleQuery::Pid(pid), parser, &mut wait_handles) {
+                continue;
+            }
+            if process_exists(pid) {
+                foreign_pids.push(pid);
+            } else {
+                streams.err.append(wgettext_fmt!(
+                    "%ls: Could not find a process with id '%d'\n",
+                    cmd,
+                    pid,
+                ));
+            }
+        } else if iswnumeric(argv[i]) {
             // argument is pid
             let mpid: Result<pid_t, wutil::Error> = fish_wcstoi(argv[i]);
             if mpid.is_err() || mpid.unwrap() <= 0 {
@@ -228,8 +445,14 @@ pub fn wait(
             }
         }
     }
-    if wait_handles.is_empty() {
+    if wait_handles.is_empty() && foreign_pids.is_empty() {
         return STATUS_INVALID_ARGS;
     }
-    return wait_for_completion(parser, &wait_handles, any_flag);
+    // Our own jobs are waited for first, since we reap them; then any foreign processes.
+    let status = wait_for_completion(parser, &wait_handles, any_flag);
+    if status != STATUS_CMD_OK || foreign_pids.is_empty() || (any_flag && !wait_handles.is_empty())
+    {
+        return status;
+    }
+    wait_for_foreign_pids(&foreign_pids, any_flag)
 }
@@ -988,6 +988,11 @@ pub fn get_obfuscation_read_char() -> char {
     char::from_u32(OBFUSCATION_READ_CHAR.load(Ordering::Relaxed)).unwrap()
 }
 
+/// Whether the locale's character encoding is UTF-8. Conversions between narrow and wide strings
+/// then decode and encode UTF-8 themselves, instead of calling mbrtowc() and wcrtomb() for every
+/// non-ASCII character.
+pub static LOCALE_IS_UTF8: RelaxedAtomicBool = RelaxedAtomicBool::new(false);
+
 /// Profiling flag. True if commands should be profiled.
 pub static PROFILING_ACTIVE: RelaxedAtomicBool = RelaxedAtomicBool::new(false);
 
@@ -1030,7 +1035,15 @@ pub fn str2wcstring(inp: &[u8]) -> WString {
     if inp.is_empty() {
         return WString::new();
     }
+    if LOCALE_IS_UTF8.load() {
+        str2wcstring_utf8(inp)
+    } else {
+        str2wcstring_locale(inp)
+    }
+}
 
+/// The str2wcstring() of other locales, which decodes with mbrtowc().
+fn str2wcstring_locale(inp: &[u8]) -> WString {
     let mut result = WString::new();
     result.reserve(inp.len());
     let mut pos = 0;
@@ -1106,6 +1119,38 @@ pub fn str2wcstring(inp: &[u8]) -> WString {
     result
 }
 
+/// The str2wcstring() of UTF-8 locales, which doesn't need to go through the C library.
+/// Like there, invalid sequences and characters which would be mistaken for our own encoded bytes
+/// are encoded byte by byte.
+fn str2wcstring_utf8(mut inp: &[u8]) -> WString {
+    let mut result = WString::new();
+    result.reserve(inp.len());
+    loop {
+        let (valid, rest) = match std::str::from_utf8(inp) {
+            Ok(valid) => (valid, &[][..]),
+            Err(err) => {
+                let (valid, rest) = inp.split_at(err.valid_up_to());
+                (unsafe { std::str::from_utf8_unchecked(valid) }, rest)
+            }
+        };
+        for c in valid.chars() {
+            if (c >= ENCODE_DIRECT_BASE && c < ENCODE_DIRECT_END) || c == INTERNAL_SEPARATOR {
+                let mut buff = [0_u8; 4];
+                for byte in c.encode_utf8(&mut buff).bytes() {
+                    result.push(encode_byte_to_char(byte));
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        // Skip one byte of an invalid or incomplete sequence, and decode the rest again.
+        let Some((&byte, rest)) = rest.split_first() else { break; };
+        result.push(encode_byte_to_char(byte));
+        inp = rest;
+    }
+    result
+}
+
 pub fn cstr2wcstring(input: &[u8]) -> WString {
     let strlen = input.iter().position(|c| *c == b'\0').unwrap();
     str2wcstring(&input[0..strlen])
@@ -1396,6 +1441,9 @@ pub fn fish_setlocale() {
     }
     PROFILING_ACTIVE.store(true);
 
+    let codeset = unsafe { CStr::from_ptr(libc::nl_langinfo(libc::CODESET)) };
+    LOCALE_IS_UTF8.store(matches!(codeset.to_bytes(), b"UTF-8" | b"utf8"));
+
     // Until no C++ code uses the variables init in the C++ version of fish_setlocale(), we need to
     // also call that one or otherwise we'll segfault trying to read those uninit values.
     extern "C" {
@@ -2083,10 +2131,10 @@ pub fn fputws(s: &wstr, fd: RawFd) {
 
 mod tests {
     use crate::common::{
-        escape_string, str2wcstring, wcs2string, EscapeStringStyle, ENCODE_DIRECT_BASE,
-        ENCODE_DIRECT_END,
+        escape_string, str2wcstring, str2wcstring_locale, str2wcstring_utf8, wcs2string,
+        EscapeStringStyle, ENCODE_DIRECT_BASE, ENCODE_DIRECT_END,
     };
-    use crate::wchar::widestrs;
+    use crate::wchar::{encode_byte_to_char, widestrs};
     use crate::wutil::encoding::{wcrtomb, zero_mbstate, AT_LEAST_MB_LEN_MAX};
     use rand::random;
 
@@ -2213,12 +2261,60 @@ mod tests {
             assert_eq!(wcs2string(&ws), s);
         }
     }
+
+    /// Verify that decoding UTF-8 without the C library agrees with mbrtowc().
+    pub fn test_convert_utf8() {
+        let decode = |s: &[u8]| {
+            str2wcstring_utf8(s)
+                .chars()
+                .map(u32::from)
+                .collect::<Vec<_>>()
+        };
+        let direct = |b: u8| u32::from(encode_byte_to_char(b));
+        assert_eq!(decode(b"ab\0c"), [0x61, 0x62, 0, 0x63]);
+        assert_eq!(
+            decode("Grüße €𝄞".as_bytes()),
+            "Grüße €𝄞".chars().map(u32::from).collect::<Vec<_>>()
+        );
+        // Invalid, incomplete, overlong and surrogate sequences are encoded byte by byte.
+        assert_eq!(decode(b"\xffa"), [direct(0xff), 0x61]);
+        assert_eq!(decode(b"a\xe2\x82"), [0x61, direct(0xe2), direct(0x82)]);
+        assert_eq!(decode(b"\xc0\xaf"), [direct(0xc0), direct(0xaf)]);
+        assert_eq!(
+            decode(b"\xed\xa0\x80"),
+            [direct(0xed), direct(0xa0), direct(0x80)]
+        );
+        // So are characters in our private use range.
+        let mut buff = [0_u8; 4];
+        let s = ENCODE_DIRECT_BASE.encode_utf8(&mut buff).as_bytes();
+        assert_eq!(decode(s), s.iter().map(|&b| direct(b)).collect::<Vec<_>>());
+
+        let utf8_locale = unsafe {
+            let codeset = std::ffi::CStr::from_ptr(libc::nl_langinfo(libc::CODESET));
+            matches!(codeset.to_bytes(), b"UTF-8" | b"utf8")
+        };
+        if !utf8_locale {
+            return;
+        }
+        for _ in 0..ESCAPE_TEST_COUNT {
+            let mut origin: Vec<u8> = vec![];
+            while (random::<usize>() % ESCAPE_TEST_LENGTH) != 0 {
+                // Favor bytes that start or continue multibyte sequences.
+                let byte: u8 = random();
+                origin.push(if byte < 0x40 { byte } else { byte | 0x80 });
+            }
+            let wide = str2wcstring_utf8(&origin);
+            assert_eq!(str2wcstring_locale(&origin), wide);
+            assert_eq!(wcs2string(&wide), origin);
+        }
+    }
 }
 
 crate::ffi_tests::add_test!("escape_string", tests::test_escape_string);
 crate::ffi_tests::add_test!("escape_string", tests::test_convert);
 crate::ffi_tests::add_test!("escape_string", tests::test_convert_ascii);
 crate::ffi_tests::add_test!("escape_string", tests::test_convert_private_use);
+crate::ffi_tests::add_test!("escape_string", tests::test_convert_utf8);
 
 #[cxx::bridge]
 mod common_ffi {
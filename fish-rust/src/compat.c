@@ -1,11 +1,8 @@
 #include <stdint.h>
 #include <stdlib.h>
-#include <term.h>
 
 size_t C_MB_CUR_MAX() { return MB_CUR_MAX; }
 
-int has_cur_term() { return cur_term != NULL; }
-
 uint64_t C_ST_LOCAL() {
 #if defined(ST_LOCAL)
     return ST_LOCAL;
@@ -0,0 +1,251 @@
+//! Writes a report when fish crashes, so users have something to attach to a bug report.
+//!
+//! On a fatal signal (like SIGSEGV or SIGABRT) or a Rust panic, we write the fish version, the
+//! backtrace, the value of `$TERM`, the last command line and the most recent FLOG output to
+//! `fish-crash-<pid>.txt` in the data directory, and print its path to stderr.
+//!
+//! Everything but the backtrace is rendered ahead of time, so the signal handler only needs to
+//! write out bytes. The last command line is left out if it would not be saved to disk by history,
+//! i.e. if it starts with a space or fish is in private mode.
+
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use cxx::CxxWString;
+use once_cell::sync::OnceCell;
+
+use crate::common::wcs2osstring;
+use crate::wchar_ffi::AsWstr;
+
+#[cxx::bridge]
+mod crash_handler_ffi {
+    extern "Rust" {
+        fn crash_handler_install(data_dir: &CxxWString, version: &CxxWString);
+        fn crash_handler_set_state(command: &CxxWString, redact: bool, term: &CxxWString);
+        fn crash_handler_note_flog(line: &CxxWString);
+    }
+}
+
+/// The signals which we write a report for.
+const CRASH_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+/// How many lines of FLOG output we keep.
+const FLOG_LINES: usize = 32;
+
+/// The most frames we put in a backtrace.
+const MAX_FRAMES: usize = 128;
+
+/// The size of the stack the signal handler runs on, so we can report stack overflows.
+const ALT_STACK_SIZE: usize = 64 * 1024;
+
+/// Set once a report has been written, so a crash while crashing doesn't write another.
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// Where the report goes, and the fixed header at its start.
+struct ReportConfig {
+    path: CString,
+    header: Vec<u8>,
+}
+
+static CONFIG: OnceCell<ReportConfig> = OnceCell::new();
+
+/// The session state, rendered to the lines of the report.
+static STATE: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// The most recent FLOG lines.
+static RECENT_FLOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+extern "C" {
+    fn backtrace(buffer: *mut *mut libc::c_void, size: libc::c_int) -> libc::c_int;
+    fn backtrace_symbols_fd(buffer: *const *mut libc::c_void, size: libc::c_int, fd: libc::c_int);
+}
+
+/// Install the crash handler. Reports are written to \p data_dir, or the temporary directory if it
+/// is empty. This must be called once, from the main thread.
+pub fn crash_handler_install(data_dir: &CxxWString, version: &CxxWString) {
+    let mut dir = wcs2osstring(data_dir.as_wstr());
+    if dir.is_empty() {
+        dir = std::env::temp_dir().into_os_string();
+    }
+    let path = std::path::Path::new(&dir).join(format!("fish-crash-{}.txt", std::process::id()));
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return;
+    };
+    let header = format!("fish crash report\nversion: {}\n", version.as_wstr()).into_bytes();
+    if CONFIG.set(ReportConfig { path, header }).is_err() {
+        return;
+    }
+
+    // backtrace() may allocate the first time it is called, which we can't do in the handler.
+    capture_frames(&mut [std::ptr::null_mut(); 1]);
+
+    // Run the handler on its own stack, so a stack overflow can be reported too. This is leaked.
+    let stack = Box::leak(vec![0u8; ALT_STACK_SIZE].into_boxed_slice());
+    let ss = libc::stack_t {
+        ss_sp: stack.as_mut_ptr() as *mut libc::c_void,
+        ss_flags: 0,
+        ss_size: stack.len(),
+    };
+    unsafe { libc::sigaltstack(&ss, std::ptr::null_mut()) };
+
+    let mut act: libc::sigaction = unsafe { std::mem::zeroed() };
+    act.sa_sigaction = crash_signal_handler as usize;
+    act.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_RESETHAND;
+    unsafe { libc::sigemptyset(&mut act.sa_mask) };
+    for sig in CRASH_SIGNALS {
+        unsafe { libc::sigaction(sig, &act, std::ptr::null_mut()) };
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if !CRASHED.swap(true, Ordering::SeqCst) {
+            let backtrace = std::backtrace::Backtrace::force_capture();
+            let detail = format!("panic: {}\n\nbacktrace:\n{}\n", info, backtrace);
+            write_report(detail.as_bytes(), false);
+        }
+        previous(info);
+    }));
+}
+
+/// Record the command line about to be run, and the terminal. If \p redact is set, the command
+/// line is left out of reports.
+pub fn crash_handler_set_state(command: &CxxWString, redact: bool, term: &CxxWString) {
+    let command = if redact {
+        "(redacted)".to_owned()
+    } else {
+        command.as_wstr().to_string()
+    };
+    let state = format!(
+        "TERM: {}\nlast command: {}\n",
+        term.as_wstr(),
+        command.replace('\n', "\n    ")
+    );
+    *STATE.lock().unwrap() = state.into_bytes();
+}
+
+fn crash_handler_note_flog(line: &CxxWString) {
+    note_flog(&line.as_wstr().to_string());
+}
+
+/// Remember a line of FLOG output for reports. This does nothing until the handler is installed.
+pub fn note_flog(line: &str) {
+    if CONFIG.get().is_none() {
+        return;
+    }
+    let mut recent = RECENT_FLOG.lock().unwrap();
+    if recent.len() == FLOG_LINES {
+        recent.pop_front();
+    }
+    recent.push_back(line.trim_end_matches('\n').to_owned());
+}
+
+extern "C" fn crash_signal_handler(
+    sig: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    if !CRASHED.swap(true, Ordering::SeqCst) {
+        let detail: &[u8] = match sig {
+            libc::SIGSEGV => b"signal: SIGSEGV\n\nbacktrace:\n",
+            libc::SIGBUS => b"signal: SIGBUS\n\nbacktrace:\n",
+            libc::SIGILL => b"signal: SIGILL\n\nbacktrace:\n",
+            libc::SIGFPE => b"signal: SIGFPE\n\nbacktrace:\n",
+            _ => b"signal: SIGABRT\n\nbacktrace:\n",
+        };
+        write_report(detail, true);
+    }
+    // SA_RESETHAND restored the default action; raise the signal again to get it.
+    unsafe { libc::raise(sig) };
+}
+
+/// Write the report, followed by a note on stderr. \p detail describes the crash. If \p
+/// with_frames is set, the backtrace of the calling thread is appended to it. This must be
+/// async-signal safe as long as the locks are not contended, so we use only try_lock and write().
+fn write_report(detail: &[u8], with_frames: bool) {
+    let Some(config) = CONFIG.get() else {
+        return;
+    };
+    let fd = unsafe {
+        libc::open(
+            config.path.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC,
+            0o600,
+        )
+    };
+    if fd < 0 {
+        return;
+    }
+    write_all(fd, &config.header);
+    write_all(fd, detail);
+    if with_frames {
+        let mut frames = [std::ptr::null_mut(); MAX_FRAMES];
+        let count = capture_frames(&mut frames);
+        write_frames(fd, &frames[..count]);
+    }
+    write_all(fd, b"\n");
+    match STATE.try_lock() {
+        Ok(state) if !state.is_empty() => write_all(fd, &state),
+        Ok(_) => write_all(fd, b"last command: (none)\n"),
+        Err(_) => write_all(fd, b"last command: (unavailable)\n"),
+    }
+    write_all(fd, b"\nrecent log output:\n");
+    if let Ok(recent) = RECENT_FLOG.try_lock() {
+        for line in recent.iter() {
+            write_all(fd, line.as_bytes());
+            write_all(fd, b"\n");
+        }
+    }
+    unsafe { libc::close(fd) };
+
+    write_all(
+        libc::STDERR_FILENO,
+        b"fish: crashed. A report was written to ",
+    );
+    write_all(libc::STDERR_FILENO, config.path.as_bytes());
+    write_all(libc::STDERR_FILENO, b"\n");
+}
+
+fn write_all(fd: libc::c_int, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        let written =
+            unsafe { libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+        if written < 0 && errno::errno().0 == libc::EINTR {
+            continue;
+        }
+        if written <= 0 {
+            return;
+        }
+        bytes = &bytes[written as usize..];
+    }
+}
+
+/// Fill \p frames with the return addresses of the calling thread. \return the number of frames.
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+fn capture_frames(frames: &mut [*mut libc::c_void]) -> usize {
+    unsafe { backtrace(frames.as_mut_ptr(), frames.len() as libc::c_int) }.max(0) as usize
+}
+
+#[cfg(not(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos")))]
+fn capture_frames(_frames: &mut [*mut libc::c_void]) -> usize {
+    0
+}
+
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+fn write_frames(fd: libc::c_int, frames: &[*mut libc::c_void]) {
+    unsafe { backtrace_symbols_fd(frames.as_ptr(), frames.len() as libc::c_int, fd) };
+}
+
+#[cfg(not(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos")))]
+fn write_frames(fd: libc::c_int, _frames: &[*mut libc::c_void]) {
+    write_all(fd, b"(backtraces are not available on this system)\n");
+}
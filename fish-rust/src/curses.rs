@@ -1,20 +1,21 @@
-//! A wrapper around the system's curses/ncurses library, exposing some lower-level functionality
-//! that's not directly exposed in any of the popular ncurses crates.
+//! The capabilities of the terminal fish runs in, behind the [`Term`] façade. This used to wrap the
+//! system's curses library, hence the name. Capabilities are now read from the terminfo database by
+//! our own [`TermInfo`] reader, including for the C++ side, so we don't go through curses' global
+//! `cur_term`.
 //!
-//! In addition to exposing the C library ffi calls, we also shim around some functionality that's
-//! only made available via the the ncurses headers to C code via macro magic, such as polyfilling
-//! missing capability strings to shoe-in missing support for certain terminal sequences.
-//!
-//! This is intentionally very bare bones and only implements the subset of curses functionality
-//! used by fish
+//! In addition to the terminfo entry, [`Term`] holds the capability strings fish polyfills to
+//! shoe-in missing support for certain terminal sequences, and the user's overrides.
 
-use self::sys::*;
+use crate::flog::FLOG;
+use crate::terminfo::{self, TermInfo};
+pub use crate::terminfo::{Override, Param};
 use std::ffi::{CStr, CString};
+use std::os::unix::ffi::OsStrExt;
 use std::sync::Arc;
 use std::sync::Mutex;
 
-/// The [`Term`] singleton, providing a façade around the system curses library. Initialized via a
-/// successful call to [`setup()`] and surfaced to the outside world via [`term()`].
+/// The [`Term`] singleton, providing a façade around the terminal's terminfo entry. Initialized via
+/// a successful call to [`setup()`] and surfaced to the outside world via [`term()`].
 ///
 /// It isn't guaranteed that fish will ever be able to successfully call `setup()`, so this must
 /// remain an `Option` instead of returning `Term` by default and just panicking if [`term()`] was
@@ -36,47 +37,25 @@ pub fn term() -> Option<Arc<Term>> {
         .map(Arc::clone)
 }
 
-/// Private module exposing system curses ffi.
-mod sys {
-    pub const OK: i32 = 0;
-    pub const ERR: i32 = -1;
-
-    extern "C" {
-        /// The ncurses `cur_term` TERMINAL pointer.
-        pub static mut cur_term: *const core::ffi::c_void;
-
-        /// setupterm(3) is a low-level call to begin doing any sort of `term.h`/`curses.h` work.
-        /// It's called internally by ncurses's `initscr()` and `newterm()`, but the C++ code called
-        /// it directly from [`initialize_curses_using_fallbacks()`].
-        pub fn setupterm(
-            term: *const libc::c_char,
-            filedes: libc::c_int,
-            errret: *mut libc::c_int,
-        ) -> libc::c_int;
-
-        /// Frees the `cur_term` TERMINAL  pointer.
-        pub fn del_curterm(term: *const core::ffi::c_void) -> libc::c_int;
-
-        /// Checks for the presence of a termcap flag identified by the first two characters of
-        /// `id`.
-        pub fn tgetflag(id: *const libc::c_char) -> libc::c_int;
-
-        /// Checks for the presence and value of a number capability in the termcap/termconf
-        /// database. A return value of `-1` indicates not found.
-        pub fn tgetnum(id: *const libc::c_char) -> libc::c_int;
-
-        pub fn tgetstr(
-            id: *const libc::c_char,
-            area: *mut *mut libc::c_char,
-        ) -> *const libc::c_char;
-    }
-}
-
-/// The safe wrapper around curses functionality, initialized by a successful call to [`setup()`]
-/// and obtained thereafter by calls to [`term()`].
+/// Compiled terminfo entries for common terminals, used by [`setup()`] when the system has no
+/// terminfo database (or none with the entry we're after), which is frequently the case in minimal
+/// containers.
 ///
-/// An extant `Term` instance means the curses `TERMINAL *cur_term` pointer is non-null. Any
-/// functionality that is normally performed using `cur_term` should be done via `Term` instead.
+/// These were compiled from the ncurses 6.4 entries with `infocmp -x $name | tic -x`, after
+/// capping `pairs` at 0x7fff so tic writes the legacy format that every ncurses version can read.
+const BUILTIN_TERMINFO: [(&str, &[u8]); 5] = [
+    (
+        "xterm-256color",
+        include_bytes!("../terminfo/xterm-256color"),
+    ),
+    ("screen", include_bytes!("../terminfo/screen")),
+    ("tmux", include_bytes!("../terminfo/tmux")),
+    ("linux", include_bytes!("../terminfo/linux")),
+    ("dumb", include_bytes!("../terminfo/dumb")),
+];
+
+/// The safe wrapper around the terminal's capabilities, initialized by a successful call to
+/// [`setup()`] and obtained thereafter by calls to [`term()`].
 pub struct Term {
     // String capabilities
     pub enter_italics_mode: Option<CString>,
@@ -88,186 +67,198 @@ pub struct Term {
 
     // Flag/boolean capabilities
     pub eat_newline_glitch: bool,
+
+    /// The parsed terminfo entry.
+    info: TermInfo,
+
+    /// The overrides applied with [`apply_override()`](Self::apply_override), which take
+    /// precedence over the entry.
+    overrides: Vec<Override>,
 }
 
 impl Term {
-    /// Initialize a new `Term` instance, prepopulating the values of all the curses string
-    /// capabilities we care about in the process.
-    fn new() -> Self {
+    /// Initialize a new `Term` instance, prepopulating the values of all the string capabilities
+    /// we care about in the process.
+    fn new(info: TermInfo) -> Self {
         Term {
             // String capabilities
-            enter_italics_mode: StringCap::new("ZH").lookup(),
-            exit_italics_mode: StringCap::new("ZR").lookup(),
-            enter_dim_mode: StringCap::new("mh").lookup(),
+            enter_italics_mode: info.get_string("sitm").map(CStr::to_owned),
+            exit_italics_mode: info.get_string("ritm").map(CStr::to_owned),
+            enter_dim_mode: info.get_string("dim").map(CStr::to_owned),
 
             // Number capabilities
-            max_colors: NumberCap::new("Co").lookup(),
+            max_colors: info.get_number("colors"),
 
             // Flag/boolean capabilities
-            eat_newline_glitch: FlagCap::new("xn").lookup(),
+            eat_newline_glitch: info.get_flag("xenl"),
+
+            info,
+            overrides: Vec::new(),
         }
     }
-}
 
-trait Capability {
-    type Result: Sized;
-    fn lookup(&self) -> Self::Result;
-}
+    /// Replaces the capability named by `o` with the user-provided value, for terminals with
+    /// missing or broken terminfo entries. A value of the wrong type removes the capability.
+    pub fn apply_override(&mut self, o: &Override) {
+        let string = || match o {
+            Override::String(_, s) => Some(s.clone()),
+            _ => None,
+        };
+        match o.name() {
+            "sitm" => self.enter_italics_mode = string(),
+            "ritm" => self.exit_italics_mode = string(),
+            "dim" => self.enter_dim_mode = string(),
+            "colors" => {
+                self.max_colors = match o {
+                    Override::Number(_, n) => Some(*n),
+                    _ => None,
+                }
+            }
+            "xenl" => self.eat_newline_glitch = matches!(o, Override::Flag(_)),
+            _ => (),
+        }
+        self.overrides.push(o.clone());
+    }
 
-impl Capability for StringCap {
-    type Result = Option<CString>;
+    /// Returns the last override of the capability `name`, if any.
+    fn find_override(&self, name: &str) -> Option<&Override> {
+        self.overrides.iter().rev().find(|o| o.name() == name)
+    }
 
-    fn lookup(&self) -> Self::Result {
-        unsafe {
-            const NULL: *const i8 = core::ptr::null();
-            match sys::tgetstr(self.code.as_ptr(), core::ptr::null_mut()) {
-                NULL => None,
-                // termcap spec says nul is not allowed in terminal sequences and must be encoded;
-                // so the terminating NUL is the end of the string.
-                result => Some(CStr::from_ptr(result).to_owned()),
-            }
+    /// Looks up the string capability `name` by its terminfo name, including the extended
+    /// (user-defined) capabilities that have no termcap code, e.g. `Smulx` for styled underlines.
+    /// Returns `None` if the capability is absent, cancelled, or not a string capability.
+    pub fn get_extended_str(&self, name: &str) -> Option<CString> {
+        if let Some(o) = self.find_override(name) {
+            return match o {
+                Override::String(_, s) => Some(s.clone()),
+                _ => None,
+            };
         }
+        self.info.get_string(name).map(CStr::to_owned)
     }
-}
 
-impl Capability for NumberCap {
-    type Result = Option<i32>;
+    /// Like [`get_extended_str()`](Self::get_extended_str), except that the capabilities we keep in
+    /// fields, like `sitm`, come from those. This includes what [`setup()`]'s `configure` callback
+    /// filled in for them.
+    pub fn get_str(&self, name: &str) -> Option<CString> {
+        match name {
+            "sitm" => self.enter_italics_mode.clone(),
+            "ritm" => self.exit_italics_mode.clone(),
+            "dim" => self.enter_dim_mode.clone(),
+            _ => self.get_extended_str(name),
+        }
+    }
 
-    fn lookup(&self) -> Self::Result {
-        unsafe {
-            match tgetnum(self.0.as_ptr()) {
-                -1 => None,
-                n => Some(n),
-            }
+    /// Looks up the number capability `name` by its terminfo name, like `colors`.
+    pub fn get_number(&self, name: &str) -> Option<i32> {
+        if name == "colors" {
+            return self.max_colors;
+        }
+        match self.find_override(name) {
+            Some(Override::Number(_, n)) => Some(*n),
+            Some(_) => None,
+            None => self.info.get_number(name),
         }
     }
-}
 
-impl Capability for FlagCap {
-    type Result = bool;
+    /// Looks up the flag capability `name` by its terminfo name, like `am`.
+    pub fn get_flag(&self, name: &str) -> bool {
+        if name == "xenl" {
+            return self.eat_newline_glitch;
+        }
+        match self.find_override(name) {
+            Some(o) => matches!(o, Override::Flag(_)),
+            None => self.info.get_flag(name),
+        }
+    }
+
+    /// Whether the terminal can hold back drawing while fish redraws the prompt and command line,
+    /// and then show the result at once (synchronized output, mode 2026). This is the case if the
+    /// terminfo entry has the extended `Sync` capability, or the terminal said so when probed.
+    /// Overriding `Sync` with anything but a string turns it off.
+    pub fn supports_synchronized_output(&self) -> bool {
+        if let Some(o) = self.find_override("Sync") {
+            return matches!(o, Override::String(..));
+        }
+        self.get_extended_str("Sync").is_some()
+            || crate::terminal_probe::terminal_features().map_or(false, |f| f.synchronized_output)
+    }
+
+    /// Like [`get_extended_str()`](Self::get_extended_str) but instantiates the parameterized
+    /// capability with `params`, e.g. `Smulx` with the underline style.
+    pub fn get_extended_str_with_params(&self, name: &str, params: &[Param]) -> Option<CString> {
+        self.expand(&self.get_extended_str(name)?, params)
+    }
 
-    fn lookup(&self) -> Self::Result {
-        unsafe { tgetflag(self.0.as_ptr()) != 0 }
+    /// Instantiates the parameterized capability string `cap` with `params`, like curses'
+    /// `tparm()` but without going through C varargs. For example, expanding `cup` with the
+    /// numbers 2 and 4 yields the sequence moving the cursor to the third row and fifth column.
+    ///
+    /// Returns `None` if `cap` is malformed or the result would contain a nul.
+    pub fn expand(&self, cap: &CStr, params: &[Param]) -> Option<CString> {
+        CString::new(terminfo::expand(cap.to_bytes(), params)?).ok()
     }
 }
 
-/// Calls the curses `setupterm()` function with the provided `$TERM` value `term` (or a null
-/// pointer in case `term` is null) for the file descriptor `fd`. Returns a reference to the newly
-/// initialized [`Term`] singleton on success or `None` if this failed.
+/// Reads the terminfo entry for the terminal `term`, or `$TERM` if that is `None`, with our own
+/// [`TermInfo`] reader. Returns a reference to the newly initialized [`Term`] singleton, or `None`
+/// if there is no usable entry for the terminal.
 ///
 /// The `configure` parameter may be set to a callback that takes an `&mut Term` reference to
-/// override any capabilities before the `Term` is permanently made immutable.
-///
-/// Note that the `errret` parameter is provided to the function, meaning curses will not write
-/// error output to stderr in case of failure.
+/// override any capabilities before the `Term` is permanently made immutable. The user's
+/// `overrides` are applied after that, so they win over anything `configure` does.
 ///
 /// Any existing references from `curses::term()` will be invalidated by this call!
-pub fn setup<F>(term: Option<&CStr>, fd: i32, configure: F) -> Option<Arc<Term>>
+pub fn setup<F>(term: Option<&CStr>, overrides: &[Override], configure: F) -> Option<Arc<Term>>
 where
     F: Fn(&mut Term),
 {
-    // For now, use the same TERM lock when using `cur_term` to prevent any race conditions in
-    // curses itself. We might split this to another lock in the future.
-    let mut global_term = TERM.lock().expect("Mutex poisoned!");
-
-    let result = unsafe {
-        // If cur_term is already initialized for a different $TERM value, calling setupterm() again
-        // will leak memory. Call del_curterm() first to free previously allocated resources.
-        let _ = sys::del_curterm(cur_term);
-
-        let mut err = 0;
-        if let Some(term) = term {
-            sys::setupterm(term.as_ptr(), fd, &mut err)
-        } else {
-            sys::setupterm(core::ptr::null(), fd, &mut err)
-        }
+    let name = match term {
+        Some(term) => Some(term.to_owned()),
+        None => std::env::var_os("TERM").and_then(|t| CString::new(t.as_bytes()).ok()),
     };
+    // If the system doesn't know this terminal, this finds it in our built-in entries.
+    let info = name.as_deref().and_then(load_terminfo);
 
     // Safely store the new Term instance or replace the old one. We have the lock so it's safe to
     // drop the old TERM value and have its refcount decremented - no one will be cloning it.
-    if result == sys::OK {
-        // Create a new `Term` instance, prepopulate the capabilities we care about, and allow the
-        // caller to override any as needed.
-        let mut term = Term::new();
-        (configure)(&mut term);
-
-        let term = Arc::new(term);
-        *global_term = Some(term.clone());
-        Some(term)
-    } else {
+    let mut global_term = TERM.lock().expect("Mutex poisoned!");
+    let Some(info) = info else {
         *global_term = None;
-        None
-    }
-}
-
-/// Resets the curses `cur_term` TERMINAL pointer. Subsequent calls to [`curses::term()`](term())
-/// will return `None`.
-pub fn reset() {
-    let mut term = TERM.lock().expect("Mutex poisoned!");
-    if term.is_some() {
-        unsafe {
-            // Ignore the result of del_curterm() as the only documented error is that
-            // `cur_term` was already null.
-            let _ = sys::del_curterm(cur_term);
-            sys::cur_term = core::ptr::null();
-        }
-        *term = None;
-    }
-}
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct Code {
-    /// The two-char termcap code for the capability, followed by a nul.
-    code: [u8; 3],
-}
-
-impl Code {
-    /// `code` is the two-digit termcap code. See termcap(5) for a reference.
-    ///
-    /// Panics if anything other than a two-ascii-character `code` is passed into the function. It
-    /// would take a hard-coded `[u8; 2]` parameter but that is less ergonomic. Since all our
-    /// termcap `Code`s are compile-time constants, the panic is a compile-time error, meaning
-    /// there's no harm to going this more ergonomic route.
-    const fn new(code: &str) -> Code {
-        let code = code.as_bytes();
-        if code.len() != 2 {
-            panic!("Invalid termcap code provided!");
-        }
-        Code {
-            code: [code[0], code[1], b'\0'],
-        }
-    }
+        return None;
+    };
 
-    /// The nul-terminated termcap id of the capability.
-    pub const fn as_ptr(&self) -> *const libc::c_char {
-        self.code.as_ptr().cast()
+    // Create a new `Term` instance, prepopulate the capabilities we care about, and allow the
+    // caller to override any as needed.
+    let mut term = Term::new(info);
+    (configure)(&mut term);
+    for o in overrides {
+        term.apply_override(o);
     }
-}
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct StringCap {
-    code: Code,
-}
-impl StringCap {
-    const fn new(code: &str) -> Self {
-        StringCap {
-            code: Code::new(code),
-        }
-    }
+    let term = Arc::new(term);
+    *global_term = Some(term.clone());
+    Some(term)
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct NumberCap(Code);
-impl NumberCap {
-    const fn new(code: &str) -> Self {
-        NumberCap(Code::new(code))
-    }
+/// Reads the terminfo entry for `name` from the terminfo directories, or from our
+/// [`BUILTIN_TERMINFO`] if it isn't there.
+fn load_terminfo(name: &CStr) -> Option<TermInfo> {
+    TermInfo::load(name).or_else(|| {
+        let (_, data) = BUILTIN_TERMINFO
+            .iter()
+            .find(|(builtin, _)| builtin.as_bytes() == name.to_bytes())?;
+        FLOG!(
+            term_support,
+            "Using the built-in terminfo entry for",
+            name.to_string_lossy()
+        );
+        TermInfo::parse(data)
+    })
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct FlagCap(Code);
-impl FlagCap {
-    const fn new(code: &str) -> Self {
-        FlagCap(Code::new(code))
-    }
+/// Forgets the terminal. Subsequent calls to [`curses::term()`](term()) will return `None`.
+pub fn reset() {
+    *TERM.lock().expect("Mutex poisoned!") = None;
 }
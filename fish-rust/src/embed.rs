@@ -0,0 +1,231 @@
+//! Evaluating fish script inside another program, without spawning a fish process.
+//!
+//! This is the API for programs that embed fish, like editors wanting to run a user's functions,
+//! configuration systems built on fish script, or test tools. A [`Shell`] is the core of a
+//! non-interactive fish: the parser, the expander, the variable stack and all the builtins. It
+//! evaluates strings, capturing what they print, and lets the program read, set and watch
+//! variables.
+//!
+//! ```ignore
+//! let mut shell = Shell::new(false);
+//! shell.set_var("name", &["world"]);
+//! let output = shell.eval("echo hello $name");
+//! assert_eq!(output.stdout, "hello world\n");
+//! ```
+//!
+//! Fish keeps its state in globals, so there is only one shell per process, and it must stay on
+//! the thread that created it, which fish treats as its main thread. External commands run as
+//! usual, in child processes; there is no job control and no terminal handling.
+//!
+//! To link an embedding program, build fish with CMake first, then depend on this crate with the
+//! `embed` feature and `FISH_BUILD_DIR` pointing at that build, so libfishlib.a is linked too.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use cxx::CxxWString;
+
+use crate::common::{str2wcstring, wcs2string};
+use crate::env::{EnvMode, EnvStack, Environment};
+use crate::ffi::{parser_t, Repin};
+use crate::wchar::{wstr, WString};
+use crate::wchar_ffi::{WCharFromFFI, WCharToFFI};
+
+#[cxx::bridge]
+mod embed_ffi {
+    extern "C++" {
+        include!("parser.h");
+        include!("embed.h");
+        type parser_t = crate::ffi::parser_t;
+    }
+    unsafe extern "C++" {
+        fn embed_init(read_config: bool) -> Pin<&'static mut parser_t>;
+        fn embed_eval(
+            parser: Pin<&mut parser_t>,
+            cmd: &CxxWString,
+            out: Pin<&mut CxxWString>,
+            err: Pin<&mut CxxWString>,
+        ) -> i32;
+    }
+}
+
+/// Whether a [`Shell`] exists right now.
+static SHELL_EXISTS: AtomicBool = AtomicBool::new(false);
+
+/// A function called when a variable changes, with the name and the new value, if it is set.
+type VarCallback = dyn Fn(&str, Option<Vec<String>>) + Send + Sync;
+
+/// The callbacks registered with [`Shell::on_var_change`], by variable name.
+static VAR_CALLBACKS: Mutex<Vec<(WString, Arc<VarCallback>)>> = Mutex::new(Vec::new());
+
+/// What evaluating a script produced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// The exit status of the last command.
+    pub status: i32,
+    /// Everything the script wrote to stdout.
+    pub stdout: String,
+    /// Everything the script wrote to stderr, including syntax errors.
+    pub stderr: String,
+}
+
+/// An embedded fish shell.
+pub struct Shell {
+    parser: Pin<&'static mut parser_t>,
+}
+
+impl Shell {
+    /// Start the shell. If `read_config` is set, universal variables and the config.fish files are
+    /// read, as they are by a fish started without `--no-config`.
+    ///
+    /// Panics if another shell exists.
+    pub fn new(read_config: bool) -> Shell {
+        let parser = Self::claim(|| embed_ffi::embed_init(read_config));
+        Shell { parser }
+    }
+
+    /// Take the principal parser of an already initialized fish, like the one fish_tests runs.
+    fn principal() -> Shell {
+        let parser = Self::claim(|| unsafe { (*parser_t::principal_parser_ffi()).pin() });
+        Shell { parser }
+    }
+
+    fn claim(get: impl FnOnce() -> Pin<&'static mut parser_t>) -> Pin<&'static mut parser_t> {
+        let was_taken = SHELL_EXISTS.swap(true, Ordering::SeqCst);
+        assert!(!was_taken, "Only one embedded shell may exist at a time");
+        get()
+    }
+
+    /// Evaluate `script`, returning what it printed and its exit status.
+    pub fn eval(&mut self, script: &str) -> Output {
+        let mut out = WString::new().to_ffi();
+        let mut err = WString::new().to_ffi();
+        let status = embed_ffi::embed_eval(
+            self.parser.as_mut(),
+            &str2wcstring(script.as_bytes()).to_ffi(),
+            out.pin_mut(),
+            err.pin_mut(),
+        );
+        Output {
+            status,
+            stdout: to_string(&out.from_ffi()),
+            stderr: to_string(&err.from_ffi()),
+        }
+    }
+
+    /// The value of the variable `name`, as the script would see it at the top level.
+    pub fn get_var(&mut self, name: &str) -> Option<Vec<String>> {
+        let name = str2wcstring(name.as_bytes());
+        let var = self
+            .parser
+            .as_mut()
+            .unpin()
+            .get_var_stack()
+            .get_or_null(&name.to_ffi(), EnvMode::DEFAULT.bits());
+        let values: Vec<WString> = var.as_ref()?.as_list_ffi().as_ref().unwrap().from_ffi();
+        Some(values.iter().map(|v| to_string(v)).collect())
+    }
+
+    /// Set the global variable `name` to `values`.
+    /// Returns false if it can't be set, e.g. because it is read-only.
+    pub fn set_var(&mut self, name: &str, values: &[&str]) -> bool {
+        let name = str2wcstring(name.as_bytes());
+        let values: Vec<WString> = values.iter().map(|v| str2wcstring(v.as_bytes())).collect();
+        let values: Vec<&wstr> = values.iter().map(|v| v.as_ref()).collect();
+        let parser = self.parser.as_mut().unpin();
+        parser.set_var(&name, &values, EnvMode::GLOBAL) == 0
+    }
+
+    /// Call `callback` whenever the variable `name` changes, whether the script or the program
+    /// changes it. The callback gets the name and the new value, or None if it was erased. It
+    /// runs while fish is evaluating, so it must not use the shell.
+    pub fn on_var_change(
+        &mut self,
+        name: &str,
+        callback: impl Fn(&str, Option<Vec<String>>) + Send + Sync + 'static,
+    ) {
+        let name = str2wcstring(name.as_bytes());
+        VAR_CALLBACKS.lock().unwrap().push((name, Arc::new(callback)));
+    }
+}
+
+impl Drop for Shell {
+    fn drop(&mut self) {
+        VAR_CALLBACKS.lock().unwrap().clear();
+        SHELL_EXISTS.store(false, Ordering::SeqCst);
+    }
+}
+
+fn to_string(s: &wstr) -> String {
+    String::from_utf8_lossy(&wcs2string(s)).into_owned()
+}
+
+/// Tell the embedding program that the variable `key` changed in `vars`.
+pub(crate) fn embed_var_changed(key: &wstr, vars: &EnvStack) {
+    // Collect the callbacks first, so they may register others.
+    let callbacks: Vec<Arc<VarCallback>> = VAR_CALLBACKS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(name, _)| name == key)
+        .map(|(_, callback)| callback.clone())
+        .collect();
+    if callbacks.is_empty() {
+        return;
+    }
+    let name = to_string(key);
+    let value: Option<Vec<String>> = vars
+        .get(key)
+        .map(|var| var.as_list().iter().map(|v| to_string(v)).collect());
+    for callback in callbacks {
+        callback(&name, value.clone());
+    }
+}
+
+use crate::ffi_tests::add_test;
+add_test!("test_embed", || {
+    let mut shell = Shell::principal();
+
+    assert!(shell.set_var("embed_name", &["world", "wide"]));
+    let output = shell.eval("echo hello $embed_name; echo oops >&2; false");
+    assert_eq!(
+        output,
+        Output {
+            status: 1,
+            stdout: "hello world wide\n".to_string(),
+            stderr: "oops\n".to_string(),
+        }
+    );
+
+    let output = shell.eval("echo (");
+    assert_eq!(output.status, 123);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.contains("Unexpected end of string"));
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen_in_callback = seen.clone();
+    shell.on_var_change("embed_watched", move |name, value| {
+        seen_in_callback
+            .lock()
+            .unwrap()
+            .push((name.to_string(), value));
+    });
+    shell.eval("set -g embed_watched a b; set -e embed_watched");
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            (
+                "embed_watched".to_string(),
+                Some(vec!["a".to_string(), "b".to_string()])
+            ),
+            ("embed_watched".to_string(), None),
+        ]
+    );
+
+    shell.eval("set -g embed_result (math 6 \\* 7)");
+    assert_eq!(shell.get_var("embed_result"), Some(vec!["42".to_string()]));
+    assert_eq!(shell.get_var("embed_unset"), None);
+
+    shell.eval("set -e embed_name embed_result");
+});
@@ -1,5 +1,5 @@
 use super::environment::{self, EnvNull, EnvStack, EnvStackRef, Environment};
-use super::var::{ElectricVar, EnvVar, EnvVarFlags, Statuses};
+use super::var::{ElectricVar, EnvVar, EnvVarFlags, ProcUsage, Statuses};
 use crate::env::EnvMode;
 use crate::event::Event;
 use crate::ffi::{event_list_ffi_t, wchar_t, wcharz_t, wcstring_list_ffi_t};
@@ -9,6 +9,7 @@ use crate::wchar_ffi::WCharToFFI;
 use crate::wchar_ffi::{AsWstr, WCharFromFFI};
 use cxx::{CxxVector, CxxWString, UniquePtr};
 use std::pin::Pin;
+use std::time::Duration;
 
 #[allow(clippy::module_inception)]
 #[cxx::bridge]
@@ -95,6 +96,10 @@ mod env_ffi {
 
         #[cxx_name = "get_kill_signal"]
         fn get_kill_signal_ffi(&self) -> i32;
+
+        /// Returns the usage of each process, flattened as in `set_last_statuses`.
+        #[cxx_name = "get_pipestatus_usage"]
+        fn get_pipestatus_usage_ffi(&self) -> Vec<i64>;
     }
 
     extern "Rust" {
@@ -111,7 +116,13 @@ mod env_ffi {
         fn get_names(&self, flags: u16, out: Pin<&mut wcstring_list_ffi_t>);
         fn is_principal(&self) -> bool;
         fn get_last_statuses(&self) -> Box<Statuses>;
-        fn set_last_statuses(&self, status: i32, kill_signal: i32, pipestatus: &CxxVector<i32>);
+        fn set_last_statuses(
+            &self,
+            status: i32,
+            kill_signal: i32,
+            pipestatus: &CxxVector<i32>,
+            usage: &CxxVector<i64>,
+        );
         fn set(
             &self,
             name: &CxxWString,
@@ -249,7 +260,18 @@ impl EnvStackRefFFI {
         Box::new(self.0.get_last_statuses())
     }
 
-    fn set_last_statuses(&self, status: i32, kill_signal: i32, pipestatus: &CxxVector<i32>) {
+    fn set_last_statuses(
+        &self,
+        status: i32,
+        kill_signal: i32,
+        pipestatus: &CxxVector<i32>,
+        usage: &CxxVector<i64>,
+    ) {
+        let pipestatus_usage = usage
+            .as_slice()
+            .chunks_exact(ProcUsage::FFI_FIELDS)
+            .map(ProcUsage::from_ffi)
+            .collect();
         let statuses = Statuses {
             status,
             kill_signal: if kill_signal == 0 {
@@ -258,6 +280,7 @@ impl EnvStackRefFFI {
                 Some(Signal::new(kill_signal))
             },
             pipestatus: pipestatus.as_slice().to_vec(),
+            pipestatus_usage,
         };
         self.0.set_last_statuses(statuses)
     }
@@ -313,6 +336,43 @@ impl Statuses {
             None => 0,
         }
     }
+
+    fn get_pipestatus_usage_ffi(&self) -> Vec<i64> {
+        self.pipestatus_usage
+            .iter()
+            .flat_map(ProcUsage::to_ffi)
+            .collect()
+    }
+}
+
+/// Process usage crosses the FFI as a flat list of integers: wall, user and system time in
+/// microseconds, max RSS in kilobytes, then input and output block counts.
+impl ProcUsage {
+    pub(crate) const FFI_FIELDS: usize = 6;
+
+    pub(crate) fn from_ffi(fields: &[i64]) -> Self {
+        let usec = |v: i64| Duration::from_micros(v.max(0) as u64);
+        let count = |v: i64| v.max(0) as u64;
+        ProcUsage {
+            wall: usec(fields[0]),
+            user: usec(fields[1]),
+            sys: usec(fields[2]),
+            max_rss_kb: count(fields[3]),
+            in_blocks: count(fields[4]),
+            out_blocks: count(fields[5]),
+        }
+    }
+
+    pub(crate) fn to_ffi(&self) -> [i64; Self::FFI_FIELDS] {
+        [
+            self.wall.as_micros() as i64,
+            self.user.as_micros() as i64,
+            self.sys.as_micros() as i64,
+            self.max_rss_kb as i64,
+            self.in_blocks as i64,
+            self.out_blocks as i64,
+        ]
+    }
 }
 
 fn env_get_globals_ffi() -> Box<EnvStackRefFFI> {
@@ -12,6 +12,7 @@ use crate::flog::FLOG;
 use crate::global_safety::RelaxedAtomicBool;
 use crate::null_terminated_array::OwningNullTerminatedArray;
 use crate::path::path_make_canonical;
+use crate::topic_monitor::{topic_monitor_principal, topic_t};
 use crate::wchar::{wstr, WExt, WString, L};
 use crate::wchar_ffi::{AsWstr, WCharFromFFI};
 use crate::wcstringutil::join_strings;
@@ -191,6 +192,7 @@ impl EnvStack {
         // Mark if we modified a uvar.
         if ret.uvar_modified {
             UVARS_LOCALLY_MODIFIED.store(true);
+            topic_monitor_principal().post(topic_t::uvar_change);
         }
         ret.status
     }
@@ -238,6 +240,7 @@ impl EnvStack {
         }
         if ret.uvar_modified {
             UVARS_LOCALLY_MODIFIED.store(true);
+            topic_monitor_principal().post(topic_t::uvar_change);
         }
         ret.status
     }
@@ -298,6 +301,11 @@ impl EnvStack {
             universal_notifier_t::default_notifier_ffi(std::pin::Pin::new(&mut unused))
                 .post_notification();
         }
+        // Let subscribers know about changes from other fish instances.
+        if sync_res.count() > 0 {
+            topic_monitor_principal().post(topic_t::uvar_change);
+        }
+
         // React internally to changes to special variables like LANG, and populate on-variable events.
         let mut result = Vec::new();
         #[allow(unreachable_code)]
@@ -6,6 +6,7 @@ use crate::env::{
 use crate::ffi::{self, env_universal_t};
 use crate::flog::FLOG;
 use crate::global_safety::RelaxedAtomicBool;
+use crate::intern::{intern, VarName};
 use crate::null_terminated_array::OwningNullTerminatedArray;
 use crate::threads::{is_forked_child, is_main_thread};
 use crate::wchar::{widestrs, wstr, WExt, WString, L};
@@ -16,6 +17,7 @@ use crate::wutil::{fish_wcstol_radix, sprintf};
 use autocxx::WithinUniquePtr;
 use cxx::UniquePtr;
 use lazy_static::lazy_static;
+use std::borrow::Cow;
 use std::cell::{RefCell, UnsafeCell};
 use std::collections::HashSet;
 use std::ffi::CString;
@@ -379,7 +381,8 @@ impl EnvScopedImpl {
                 return None;
             }
             let fish_history_var = self
-                .getf(L!("fish_history"), EnvMode::DEFAULT)
+                .getf(L!("fish_history_session"), EnvMode::DEFAULT)
+                .or_else(|| self.getf(L!("fish_history"), EnvMode::DEFAULT))
                 .map(|v| v.as_string());
             let history_session_id = fish_history_var
                 .as_ref()
@@ -400,6 +403,15 @@ impl EnvScopedImpl {
                 result.push(i.to_wstring());
             }
             Some(EnvVar::new_from_name_vec("pipestatus"L, result))
+        } else if key == "pipestatus_duration"L {
+            // Like $CMD_DURATION, this is reported in milliseconds.
+            let js = &self.perproc_data.statuses;
+            let result = js
+                .pipestatus_usage
+                .iter()
+                .map(|t| (t.wall.as_millis() as u64).to_wstring())
+                .collect();
+            Some(EnvVar::new_from_name_vec("pipestatus_duration"L, result))
         } else if key == "status"L {
             let js = &self.perproc_data.statuses;
             Some(EnvVar::new_from_name("status"L, js.status.to_wstring()))
@@ -516,7 +528,7 @@ impl EnvScopedImpl {
         let add_keys = |envs: &VarTable, names: &mut HashSet<WString>| {
             for (key, val) in envs.iter() {
                 if query.export_matches(val) {
-                    names.insert(key.clone());
+                    names.insert(key.clone().into_owned());
                 }
             }
         };
@@ -659,12 +671,12 @@ impl EnvScopedImpl {
                 .expect("Variable should be present in uvars");
             // Only insert if not already present, as uvars have lowest precedence.
             // TODO: a longstanding bug is that an unexported local variable will not mask an exported uvar.
-            vals.entry(key).or_insert(var);
+            vals.entry(Cow::Owned(key)).or_insert(var);
         }
 
         // Dorky way to add our single exported computed variable.
         vals.insert(
-            L!("PWD").to_owned(),
+            Cow::Borrowed(L!("PWD")),
             EnvVar::new_from_name(L!("PWD"), self.perproc_data.pwd.clone()),
         );
 
@@ -672,7 +684,7 @@ impl EnvScopedImpl {
         let mut export_list: Vec<CString> = Vec::new();
         export_list.reserve(vals.len());
         for (key, val) in vals.into_iter() {
-            let mut str = key;
+            let mut str = key.into_owned();
             str.push('=');
             str.push_utfstr(&val.as_string());
             export_list.push(wcs2zstring(&str));
@@ -927,10 +939,9 @@ impl EnvStackImpl {
 
     /// Pop the variable stack.
     /// Return a list of the names of variables which were modified.
-    /// TODO: We return the variable names because we may need to dispatch changes,
-    /// for example if there is a local change to LC_ALL; but that is rare. How can
-    /// we avoid these copies in the common case?
-    pub fn pop(&mut self) -> Vec<WString> {
+    /// We return the variable names because we may need to dispatch changes,
+    /// for example if there is a local change to LC_ALL; but that is rare.
+    pub fn pop(&mut self) -> Vec<VarName> {
         let popped: EnvNodeRef;
         if let Some(next) = self.base.locals.next() {
             popped = mem::replace(&mut self.base.locals, next);
@@ -1077,7 +1088,7 @@ impl EnvStackImpl {
     fn set_in_node(node: &mut EnvNodeRef, key: &wstr, mut val: Vec<WString>, flags: VarFlags) {
         // Read the var from the node. In C++ this was node->env[key] which establishes a default.
         let mut node_ref = node.borrow_mut();
-        let var = node_ref.env.entry(key.to_owned()).or_default();
+        let var = node_ref.env.entry(intern(key)).or_default();
 
         // Use an explicit exports, or inherit from the existing variable.
         let res_exports = match flags.exports {
@@ -18,7 +18,7 @@ pub const DEFAULT_READ_BYTE_LIMIT: usize = 100 * 1024 * 1024;
 #[no_mangle]
 pub static READ_BYTE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_READ_BYTE_LIMIT);
 
-/// The curses `cur_term` TERMINAL pointer has been set up.
+/// The terminal has been set up, and `curses::term()` reflects the current $TERM.
 #[no_mangle]
 pub static CURSES_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
@@ -1,3 +1,4 @@
+use crate::intern::VarName;
 use crate::signal::Signal;
 use crate::wchar::{widestrs, wstr, WString};
 use crate::wcstringutil::join_strings;
@@ -6,6 +7,7 @@ use lazy_static::lazy_static;
 use libc::c_int;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// The character used to delimit path and non-path variables in exporting and in string expansion.
 pub const PATH_ARRAY_SEP: char = ':';
@@ -97,6 +99,26 @@ pub struct Statuses {
 
     /// Pipestatus value.
     pub pipestatus: Vec<c_int>,
+
+    /// Resources used by each process of the pipeline, parallel to `pipestatus`.
+    pub pipestatus_usage: Vec<ProcUsage>,
+}
+
+/// Resources used by a single process in a pipeline, collected when it is reaped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProcUsage {
+    /// Elapsed wall time between launch and exit.
+    pub wall: Duration,
+    /// User CPU time. This and the remaining fields are only known for external processes.
+    pub user: Duration,
+    /// System CPU time.
+    pub sys: Duration,
+    /// Maximum resident set size, in kilobytes.
+    pub max_rss_kb: u64,
+    /// Number of block input operations.
+    pub in_blocks: u64,
+    /// Number of block output operations.
+    pub out_blocks: u64,
 }
 
 impl Statuses {
@@ -106,6 +128,7 @@ impl Statuses {
             status,
             kill_signal: None,
             pipestatus: vec![status],
+            pipestatus_usage: vec![ProcUsage::default()],
         }
     }
 }
@@ -264,7 +287,9 @@ impl EnvVar {
     }
 }
 
-pub type VarTable = HashMap<WString, EnvVar>;
+/// Variables by name. Copying a table shares the names of well-known variables instead of copying
+/// them.
+pub type VarTable = HashMap<VarName, EnvVar>;
 
 mod electric {
     pub(super) const READONLY: u8 = 1 << 0; // May not be modified by the user.
@@ -292,6 +317,7 @@ pub const ELECTRIC_VARIABLES: &[ElectricVar] = &[
     ElectricVar{name: "history"L, flags:electric::READONLY | electric::COMPUTED},
     ElectricVar{name: "hostname"L, flags:electric::READONLY},
     ElectricVar{name: "pipestatus"L, flags:electric::READONLY | electric::COMPUTED},
+    ElectricVar{name: "pipestatus_duration"L, flags:electric::READONLY | electric::COMPUTED},
     ElectricVar{name: "status"L, flags:electric::READONLY | electric::COMPUTED},
     ElectricVar{name: "status_generation"L, flags:electric::READONLY | electric::COMPUTED},
     ElectricVar{name: "umask"L, flags:electric::COMPUTED},
@@ -4,7 +4,7 @@ use crate::env::{setenv_lock, unsetenv_lock, EnvMode, EnvStack, Environment};
 use crate::env::{CURSES_INITIALIZED, READ_BYTE_LIMIT, TERM_HAS_XN};
 use crate::ffi::is_interactive_session;
 use crate::flog::FLOGF;
-use crate::output::ColorSupport;
+use crate::output::{ColorSupport, CwdReporting};
 use crate::wchar::L;
 use crate::wchar::{wstr, WString};
 use crate::wchar_ext::WExt;
@@ -12,13 +12,18 @@ use crate::wutil::fish_wcstoi;
 use crate::wutil::wgettext;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
 
 #[cxx::bridge]
 mod env_dispatch_ffi {
     extern "Rust" {
         fn env_dispatch_init_ffi();
+        fn env_ensure_curses_initialized();
         fn term_supports_setting_title() -> bool;
+        fn term_supports_prompt_marks() -> bool;
+        fn term_supports_iterm2_reporting() -> bool;
+        fn should_reorder_bidi() -> bool;
         fn use_posix_spawn() -> bool;
     }
 }
@@ -44,6 +49,20 @@ static USE_POSIX_SPAWN: AtomicBool = AtomicBool::new(false);
 /// Whether we think we can set the terminal title or not.
 static CAN_SET_TERM_TITLE: AtomicBool = AtomicBool::new(false);
 
+/// Whether the terminal understands the semantic prompt sequences (OSC 133), which mark the
+/// prompt, the commandline and the output of commands.
+static TERM_PROMPT_MARKS: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal understands iTerm2's sequences for reporting the user, host and current
+/// directory (OSC 1337).
+static TERM_ITERM2_REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// How to tell the terminal about the current directory, a [`CwdReporting`] as a number.
+static TERM_CWD_REPORTING: AtomicU8 = AtomicU8::new(CwdReporting::None as u8);
+
+/// Whether we put right-to-left text in display order ourselves, because the terminal doesn't.
+static REORDER_BIDI: AtomicBool = AtomicBool::new(true);
+
 /// The variable dispatch table. This is set at startup and cannot be modified after.
 static VAR_DISPATCH_TABLE: once_cell::sync::Lazy<VarDispatchTable> =
     once_cell::sync::Lazy::new(|| {
@@ -56,6 +75,7 @@ static VAR_DISPATCH_TABLE: once_cell::sync::Lazy<VarDispatchTable> =
         for name in CURSES_VARIABLES {
             table.add_anon(name, handle_curses_change);
         }
+        table.add_anon(L!("fish_terminfo_overrides"), handle_curses_change);
 
         table.add(L!("TZ"), handle_tz_change);
         table.add_anon(L!("fish_term256"), handle_fish_term_change);
@@ -69,6 +89,7 @@ static VAR_DISPATCH_TABLE: once_cell::sync::Lazy<VarDispatchTable> =
         table.add_anon(L!("fish_function_path"), handle_function_path_change);
         table.add_anon(L!("fish_read_limit"), handle_read_limit_change);
         table.add_anon(L!("fish_history"), handle_fish_history_change);
+        table.add_anon(L!("fish_history_session"), handle_fish_history_change);
         table.add_anon(
             L!("fish_autosuggestion_enabled"),
             handle_autosuggestion_change,
@@ -82,6 +103,17 @@ static VAR_DISPATCH_TABLE: once_cell::sync::Lazy<VarDispatchTable> =
             L!("fish_cursor_selection_mode"),
             handle_fish_cursor_selection_mode_change,
         );
+        table.add_anon(L!("fish_undo_coalesce"), handle_fish_undo_coalesce_change);
+        table.add_anon(
+            L!("fish_exit_job_policy"),
+            handle_fish_exit_job_policy_change,
+        );
+        table.add_anon(L!("fish_shell_integration"), update_shell_integration);
+        table.add_anon(L!("fish_bidi"), handle_fish_bidi_change);
+        table.add_anon(
+            L!("fish_continuation_indicator"),
+            handle_continuation_indicator_change,
+        );
 
         table
     });
@@ -216,6 +248,7 @@ pub fn env_dispatch_var_change(key: &wstr, vars: &EnvStack) {
     if let Some(dispatch_table) = Lazy::get(&VAR_DISPATCH_TABLE) {
         dispatch_table.dispatch(key, vars);
     }
+    crate::embed::embed_var_changed(key, vars);
 }
 
 fn handle_fish_term_change(vars: &EnvStack) {
@@ -240,7 +273,10 @@ fn handle_term_size_change(vars: &EnvStack) {
 }
 
 fn handle_fish_history_change(vars: &EnvStack) {
-    let fish_history = vars.get(L!("fish_history"));
+    // A session named with fish_history_session takes precedence over fish_history.
+    let fish_history = vars
+        .get(L!("fish_history_session"))
+        .or_else(|| vars.get(L!("fish_history")));
     let var = crate::env::env_var_to_ffi(fish_history);
     crate::ffi::reader_change_history(&crate::ffi::history_session_id(var));
 }
@@ -264,6 +300,54 @@ fn handle_fish_cursor_selection_mode_change(vars: &EnvStack) {
     crate::ffi::reader_change_cursor_selection_mode(mode);
 }
 
+fn handle_fish_undo_coalesce_change(vars: &EnvStack) {
+    // Keep in sync with undo_coalesce_t in reader.h.
+    const INSERT: u8 = 1 << 0;
+    const WORDS: u8 = 1 << 1;
+    const DELETE: u8 = 1 << 2;
+
+    let kinds = match vars.get(L!("fish_undo_coalesce")) {
+        None => INSERT,
+        Some(var) => var
+            .as_list()
+            .iter()
+            .map(|kind| match kind.to_string().as_str() {
+                "insert" => INSERT,
+                "words" => WORDS,
+                "delete" => DELETE,
+                _ => 0,
+            })
+            .fold(0, |kinds, kind| kinds | kind),
+    };
+    crate::ffi::reader_change_undo_coalesce(kinds);
+}
+
+fn handle_fish_exit_job_policy_change(vars: &EnvStack) {
+    // Keep in sync with exit_job_policy_t in reader.h.
+    const PROMPT: u8 = 0;
+    const KILL: u8 = 1;
+    const DISOWN: u8 = 2;
+
+    let policy = match vars.get_unless_empty(L!("fish_exit_job_policy")) {
+        None => PROMPT,
+        Some(var) => match var.as_string().to_string().as_str() {
+            "prompt" => PROMPT,
+            "kill" => KILL,
+            "disown" => DISOWN,
+            _ => {
+                // Warn once here rather than every time the policy is looked at on exit.
+                FLOGF!(
+                    warning,
+                    wgettext!("Invalid value for fish_exit_job_policy:"),
+                    var.as_string()
+                );
+                PROMPT
+            }
+        },
+    };
+    crate::ffi::reader_change_exit_job_policy(policy);
+}
+
 fn handle_autosuggestion_change(vars: &EnvStack) {
     // TODO: This was a call to reader_set_autosuggestion_enabled(vars) and
     // reader::check_autosuggestion_enabled() should be private to the `reader` module.
@@ -292,7 +376,10 @@ fn handle_locale_change(vars: &EnvStack) {
 
 fn handle_curses_change(vars: &EnvStack) {
     guess_emoji_width(vars);
-    init_curses(vars);
+    // If curses isn't set up yet, it picks up the new values when it is.
+    if CURSES_INITIALIZED.load(Ordering::Relaxed) {
+        init_curses(vars);
+    }
 }
 
 fn handle_fish_use_posix_spawn_change(vars: &EnvStack) {
@@ -360,19 +447,36 @@ pub fn env_dispatch_init_ffi() {
     env_dispatch_init(vars);
 }
 
+/// Initialize the curses subsystem if that hasn't happened yet. Only interactive shells do that at
+/// startup, so running a script or `fish -c` doesn't probe the terminal unless something like
+/// `set_color` needs it. This may be called from any thread.
+pub fn env_ensure_curses_initialized() {
+    static INIT_LOCK: Mutex<()> = Mutex::new(());
+    if CURSES_INITIALIZED.load(Ordering::Relaxed) {
+        return;
+    }
+    let _guard = INIT_LOCK.lock().unwrap();
+    if !CURSES_INITIALIZED.load(Ordering::Relaxed) {
+        init_curses(EnvStack::principal());
+    }
+}
+
 /// Runs the subset of dispatch functions that need to be called at startup.
 fn run_inits(vars: &EnvStack) {
     init_locale(vars);
-    init_curses(vars);
+    if is_interactive_session() {
+        init_curses(vars);
+    }
     guess_emoji_width(vars);
     update_wait_on_escape_ms(vars);
     handle_read_limit_change(vars);
     handle_fish_use_posix_spawn_change(vars);
     handle_fish_trace(vars);
+    handle_fish_exit_job_policy_change(vars);
 }
 
 /// Updates our idea of whether we support term256 and term24bit (see issue #10222).
-fn update_fish_color_support(vars: &EnvStack) {
+pub(crate) fn update_fish_color_support(vars: &EnvStack) {
     // Detect or infer term256 support. If fish_term256 is set, we respect it. Otherwise, infer it
     // from $TERM or use terminfo.
 
@@ -445,6 +549,10 @@ fn update_fish_color_support(vars: &EnvStack) {
             max_colors.unwrap(),
             "colors"
         );
+    } else if crate::terminal_probe::terminal_features().map_or(false, |f| f.truecolor) {
+        // The terminal told us itself.
+        supports_24bit = true;
+        FLOGF!(term_support, "True-color support: enabled per XTGETTCAP");
     } else if let Some(ct) = vars.get(L!("COLORTERM")).map(|v| v.as_string()) {
         // If someone sets $COLORTERM, that's the sort of color they want.
         if ct == "truecolor" || ct == "24bit" {
@@ -499,7 +607,7 @@ fn update_fish_color_support(vars: &EnvStack) {
 /// `$TERM` to our fallback. We're only doing this in the hope of getting a functional shell.
 /// If we launch an external command that uses `$TERM`, it should get the same value we were given,
 /// if any.
-fn initialize_curses_using_fallbacks(vars: &EnvStack) {
+fn initialize_curses_using_fallbacks(vars: &EnvStack, overrides: &[curses::Override]) {
     // xterm-256color is the most used terminal type by a massive margin, especially counting
     // terminals that are mostly compatible.
     const FALLBACKS: [&str; 4] = ["xterm-256color", "xterm", "ansi", "dumb"];
@@ -519,7 +627,7 @@ fn initialize_curses_using_fallbacks(vars: &EnvStack) {
         // `term` here is one of our hard-coded strings above; we can unwrap because we can
         // guarantee it doesn't contain any interior NULs.
         let term_cstr = CString::new(term).unwrap();
-        let success = curses::setup(Some(&term_cstr), libc::STDOUT_FILENO, |term| {
+        let success = curses::setup(Some(&term_cstr), overrides, |term| {
             apply_term_hacks(vars, term)
         })
         .is_some();
@@ -541,6 +649,22 @@ fn initialize_curses_using_fallbacks(vars: &EnvStack) {
     }
 }
 
+/// Returns the user's fixes for their terminal's capabilities from `$fish_terminfo_overrides`,
+/// skipping invalid entries with a warning.
+fn terminfo_overrides(vars: &EnvStack) -> Vec<curses::Override> {
+    let Some(var) = vars.get(L!("fish_terminfo_overrides")) else {
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+    for entry in var.as_list() {
+        match curses::Override::parse(&entry.to_string()) {
+            Some(o) => result.push(o),
+            None => FLOGF!(warning, wgettext!("Invalid terminfo override"), entry),
+        }
+    }
+    result
+}
+
 /// Apply any platform- or environment-specific hacks to our curses [`Term`] instance.
 fn apply_term_hacks(vars: &EnvStack, term: &mut Term) {
     if cfg!(target_os = "macos") {
@@ -648,11 +772,8 @@ fn init_curses(vars: &EnvStack) {
         }
     }
 
-    if curses::setup(None, libc::STDOUT_FILENO, |term| {
-        apply_term_hacks(vars, term)
-    })
-    .is_none()
-    {
+    let overrides = terminfo_overrides(vars);
+    if curses::setup(None, &overrides, |term| apply_term_hacks(vars, term)).is_none() {
         if is_interactive_session() {
             let term = vars.get_unless_empty(L!("TERM")).map(|v| v.as_string());
             FLOGF!(warning, wgettext!("Could not set up terminal."));
@@ -667,7 +788,7 @@ fn init_curses(vars: &EnvStack) {
             }
         }
 
-        initialize_curses_using_fallbacks(vars);
+        initialize_curses_using_fallbacks(vars, &overrides);
     }
 
     // Configure hacks that apply regardless of whether we successfully init curses or not.
@@ -675,6 +796,8 @@ fn init_curses(vars: &EnvStack) {
 
     // Store some global variables that reflect the term's capabilities
     CAN_SET_TERM_TITLE.store(does_term_support_setting_title(vars), Ordering::Relaxed);
+    update_shell_integration(vars);
+    update_bidi(vars);
     if let Some(term) = curses::term() {
         TERM_HAS_XN.store(term.eat_newline_glitch, Ordering::Relaxed);
     }
@@ -768,24 +891,115 @@ fn init_locale(vars: &EnvStack) {
         new_msg_locale.to_string_lossy()
     );
 
-    #[cfg(feature = "gettext")]
-    {
-        if old_msg_locale.as_c_str() != new_msg_locale {
-            // Make change known to GNU gettext.
-            extern "C" {
-                static mut _nl_msg_cat_cntr: libc::c_int;
-            }
-            unsafe {
-                _nl_msg_cat_cntr += 1;
-            }
-        }
-    }
+    // Switch message catalogs. LANGUAGE may have changed even if LC_MESSAGES didn't.
+    crate::wutil::gettext::update_locale();
 }
 
 pub fn use_posix_spawn() -> bool {
     USE_POSIX_SPAWN.load(Ordering::Relaxed)
 }
 
+/// Detect which shell-integration sequences the terminal understands. Setting
+/// $fish_shell_integration to 0 turns them off, and setting it to 1 sends the prompt marks even if
+/// we don't recognize the terminal.
+fn update_shell_integration(vars: &EnvStack) {
+    let get = |name: &wstr| {
+        vars.get(name)
+            .map(|v| v.as_string())
+            .unwrap_or_else(WString::new)
+    };
+    let term = get(L!("TERM"));
+    let term_program = get(L!("TERM_PROGRAM"));
+
+    let iterm2 = term_program == "iTerm.app";
+    let marks = iterm2
+        || [L!("WezTerm"), L!("vscode"), L!("ghostty")].contains(&term_program.as_utfstr())
+        || term == "xterm-kitty"
+        || term.starts_with(L!("foot"))
+        || vars.get(L!("KITTY_WINDOW_ID")).is_some()
+        || vars.get(L!("WT_SESSION")).is_some();
+    // iTerm2 gets the directory with its own sequence. ConEmu and Windows Terminal take OSC 9;9,
+    // the others OSC 7.
+    let vte_version = fish_wcstoi(&get(L!("VTE_VERSION"))).unwrap_or(0);
+    // Terminal.app's version looks like "440" or "445.1".
+    let terminal_app_version: WString = get(L!("TERM_PROGRAM_VERSION"))
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let terminal_app_version = fish_wcstoi(&terminal_app_version).unwrap_or(0);
+    let cwd = if get(L!("ConEmuANSI")) == "ON" || vars.get(L!("WT_SESSION")).is_some() {
+        CwdReporting::ConEmu
+    } else if vte_version >= 3405
+        || (term_program == "Apple_Terminal" && terminal_app_version >= 309)
+        || term_program == "WezTerm"
+        || term == "xterm-kitty"
+        || term.starts_with(L!("foot"))
+    {
+        CwdReporting::Osc7
+    } else {
+        CwdReporting::None
+    };
+
+    // Multiplexers swallow these sequences, but the variables of the outer terminal leak into them.
+    let multiplexed = vars.get(L!("TMUX")).is_some() || vars.get(L!("STY")).is_some();
+
+    let (marks, iterm2, cwd) = match get(L!("fish_shell_integration")).to_string().as_str() {
+        "0" => (false, false, CwdReporting::None),
+        "1" => {
+            let cwd = match cwd {
+                CwdReporting::None if !iterm2 => CwdReporting::Osc7,
+                cwd => cwd,
+            };
+            (true, iterm2 && !multiplexed, cwd)
+        }
+        _ if multiplexed => (false, false, CwdReporting::None),
+        _ => (marks, iterm2, cwd),
+    };
+    FLOGF!(
+        term_support,
+        "Shell integration: prompt marks",
+        marks,
+        "iTerm2 reporting",
+        iterm2,
+        "directory reporting",
+        format!("{:?}", cwd)
+    );
+    TERM_PROMPT_MARKS.store(marks, Ordering::Relaxed);
+    TERM_ITERM2_REPORTING.store(iterm2, Ordering::Relaxed);
+    TERM_CWD_REPORTING.store(cwd as u8, Ordering::Relaxed);
+}
+
+/// Decide whether we reorder bidirectional text. Terminals that do it themselves would undo our
+/// work, so we leave it to them. Setting $fish_bidi to 0 or 1 overrides the guess.
+fn update_bidi(vars: &EnvStack) {
+    let reorder = match vars.get_unless_empty(L!("fish_bidi")) {
+        Some(var) => crate::wcstringutil::bool_from_string(&var.as_string()),
+        None => {
+            // VTE has reordered text since 0.58, Konsole and mlterm for longer.
+            let vte_version = vars
+                .get(L!("VTE_VERSION"))
+                .and_then(|v| fish_wcstoi(&v.as_string()).ok())
+                .unwrap_or(0);
+            let terminal_does_bidi = vte_version >= 5800
+                || vars.get(L!("KONSOLE_VERSION")).is_some()
+                || vars.get(L!("MLTERM")).is_some();
+            !terminal_does_bidi
+        }
+    };
+    FLOGF!(term_support, "Reordering bidirectional text:", reorder);
+    REORDER_BIDI.store(reorder, Ordering::Relaxed);
+}
+
+fn handle_fish_bidi_change(vars: &EnvStack) {
+    update_bidi(vars);
+    crate::ffi::reader_schedule_prompt_repaint();
+}
+
+/// The screen reads the indicator when drawing, so it only needs to be drawn again.
+fn handle_continuation_indicator_change(_: &EnvStack) {
+    crate::ffi::reader_schedule_prompt_repaint();
+}
+
 /// Whether or not we are running on an OS where we allow ourselves to use `posix_spawn()`.
 const fn allow_use_posix_spawn() -> bool {
     #![allow(clippy::if_same_then_else)]
@@ -809,3 +1023,27 @@ const fn allow_use_posix_spawn() -> bool {
 pub fn term_supports_setting_title() -> bool {
     CAN_SET_TERM_TITLE.load(Ordering::Relaxed)
 }
+
+/// Returns true if we should mark the prompt, commandline and command output (OSC 133).
+pub fn term_supports_prompt_marks() -> bool {
+    TERM_PROMPT_MARKS.load(Ordering::Relaxed)
+}
+
+/// Returns true if we should put right-to-left text in display order before drawing it.
+pub fn should_reorder_bidi() -> bool {
+    REORDER_BIDI.load(Ordering::Relaxed)
+}
+
+/// Returns true if we should report the user, host and directory the iTerm2 way (OSC 1337).
+pub fn term_supports_iterm2_reporting() -> bool {
+    TERM_ITERM2_REPORTING.load(Ordering::Relaxed)
+}
+
+/// Returns how we should tell the terminal about the current directory.
+pub fn term_cwd_reporting() -> CwdReporting {
+    match TERM_CWD_REPORTING.load(Ordering::Relaxed) {
+        x if x == CwdReporting::Osc7 as u8 => CwdReporting::Osc7,
+        x if x == CwdReporting::ConEmu as u8 => CwdReporting::ConEmu,
+        _ => CwdReporting::None,
+    }
+}
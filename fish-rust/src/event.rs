@@ -9,12 +9,14 @@ use cxx::{CxxVector, CxxWString, UniquePtr};
 use libc::pid_t;
 use std::num::NonZeroU32;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use widestring_suffix::widestrs;
 
 use crate::builtins::shared::io_streams_t;
 use crate::common::{escape_string, scoped_push, EscapeFlags, EscapeStringStyle, ScopeGuard};
+use crate::env::{EnvMode, ProcUsage};
 use crate::ffi::{self, block_t, parser_t, Repin};
 use crate::flog::FLOG;
 use crate::job_group::{JobId, MaybeJobId};
@@ -64,9 +66,19 @@ mod event_ffi {
         fn new_event_variable_set(name: &CxxWString) -> Box<Event>;
         fn new_event_process_exit(pid: i32, status: i32) -> Box<Event>;
         fn new_event_job_exit(pgid: i32, jid: u64) -> Box<Event>;
+        fn new_event_job_started(command: &CxxWString, pids: &CxxVector<i32>) -> Box<Event>;
         fn new_event_caller_exit(internal_job_id: u64, job_id: i32) -> Box<Event>;
         #[cxx_name = "clone"]
         fn clone_ffi(self: &Event) -> Box<Event>;
+        #[cxx_name = "set_exit_payload"]
+        fn set_exit_payload_ffi(
+            self: &mut Event,
+            command: &CxxWString,
+            pids: &CxxVector<i32>,
+            status: i32,
+            signal: i32,
+            usage: &CxxVector<i64>,
+        );
 
         #[cxx_name = "event_add_handler"]
         fn event_add_handler_ffi(desc: &event_description_t, name: &CxxWString);
@@ -349,10 +361,28 @@ impl EventHandler {
     }
 }
 
+/// Structured information about an exited process or job. This is made available to event
+/// handlers as variables, see [`Event::set_exit_payload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExitPayload {
+    /// The command line of the process or job.
+    pub command: WString,
+    /// The pids of the external processes involved.
+    pub pids: Vec<pid_t>,
+    /// The exit status, as it would appear in $status.
+    pub status: i32,
+    /// The signal that killed the process, if any.
+    pub signal: Option<Signal>,
+    /// The resources it used. For a job, this combines all of its processes.
+    pub usage: ProcUsage,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Event {
     desc: EventDescription,
     arguments: Vec<WString>,
+    /// Variables to set for the duration of the handler, as (name, values) pairs.
+    variables: Vec<(WString, Vec<WString>)>,
 }
 
 impl Event {
@@ -362,6 +392,7 @@ impl Event {
                 typ: EventType::Generic { param: desc },
             },
             arguments: vec![],
+            variables: vec![],
         }
     }
 
@@ -371,6 +402,7 @@ impl Event {
                 typ: EventType::Variable { name: name.clone() },
             },
             arguments: vec!["VARIABLE".into(), "ERASE".into(), name],
+            variables: vec![],
         }
     }
 
@@ -380,6 +412,7 @@ impl Event {
                 typ: EventType::Variable { name: name.clone() },
             },
             arguments: vec!["VARIABLE".into(), "SET".into(), name],
+            variables: vec![],
         }
     }
 
@@ -393,6 +426,7 @@ impl Event {
                 pid.to_string().into(),
                 status.to_string().into(),
             ],
+            variables: vec![],
         }
     }
 
@@ -409,6 +443,27 @@ impl Event {
                 pgid.to_string().into(),
                 "0".into(), // historical
             ],
+            variables: vec![],
+        }
+    }
+
+    /// The `fish_job_started` event, for a job which launched its external processes. Handlers see
+    /// the command line and the pids as `$fish_event_command` and `$fish_event_pids`.
+    pub fn job_started(command: WString, pids: &[pid_t]) -> Self {
+        Self {
+            desc: EventDescription {
+                typ: EventType::Generic {
+                    param: "fish_job_started".into(),
+                },
+            },
+            arguments: vec![],
+            variables: vec![
+                ("fish_event_command".into(), vec![command]),
+                (
+                    "fish_event_pids".into(),
+                    pids.iter().map(|pid| pid.to_wstring()).collect(),
+                ),
+            ],
         }
     }
 
@@ -424,9 +479,42 @@ impl Event {
                 job_id.to_wstring(),
                 "0".into(), // historical
             ],
+            variables: vec![],
         }
     }
 
+    /// Attach information about an exited process or job. Handlers see it as the variables
+    /// `$fish_event_command`, `$fish_event_pids`, `$fish_event_status`, `$fish_event_signal`,
+    /// `$fish_event_duration`, `$fish_event_user_time`, `$fish_event_sys_time` (all three in
+    /// milliseconds) and `$fish_event_max_rss` (in kilobytes).
+    pub fn set_exit_payload(&mut self, payload: ExitPayload) {
+        let signal = match payload.signal {
+            Some(sig) => sig.name().into(),
+            None => WString::new(),
+        };
+        let millis = |d: Duration| vec![(d.as_millis() as u64).to_wstring()];
+        let usage = payload.usage;
+        self.variables = vec![
+            ("fish_event_command".into(), vec![payload.command]),
+            (
+                "fish_event_pids".into(),
+                payload.pids.iter().map(|pid| pid.to_wstring()).collect(),
+            ),
+            (
+                "fish_event_status".into(),
+                vec![payload.status.to_wstring()],
+            ),
+            ("fish_event_signal".into(), vec![signal]),
+            ("fish_event_duration".into(), millis(usage.wall)),
+            ("fish_event_user_time".into(), millis(usage.user)),
+            ("fish_event_sys_time".into(), millis(usage.sys)),
+            (
+                "fish_event_max_rss".into(),
+                vec![usage.max_rss_kb.to_wstring()],
+            ),
+        ];
+    }
+
     /// Test if specified event is blocked.
     fn is_blocked(&self, parser: &mut parser_t) -> bool {
         let mut i = 0;
@@ -461,6 +549,10 @@ fn new_event_job_exit(pgid: i32, jid: u64) -> Box<Event> {
     Box::new(Event::job_exit(pgid, jid))
 }
 
+fn new_event_job_started(command: &CxxWString, pids: &CxxVector<i32>) -> Box<Event> {
+    Box::new(Event::job_started(command.from_ffi(), pids.as_slice()))
+}
+
 fn new_event_caller_exit(internal_job_id: u64, job_id: i32) -> Box<Event> {
     Box::new(Event::caller_exit(
         internal_job_id,
@@ -478,22 +570,53 @@ impl Event {
     fn clone_ffi(&self) -> Box<Event> {
         Box::new(self.clone())
     }
+
+    fn set_exit_payload_ffi(
+        &mut self,
+        command: &CxxWString,
+        pids: &CxxVector<i32>,
+        status: i32,
+        signal: i32,
+        usage: &CxxVector<i64>,
+    ) {
+        self.set_exit_payload(ExitPayload {
+            command: command.from_ffi(),
+            pids: pids.as_slice().to_vec(),
+            status,
+            signal: if signal == 0 {
+                None
+            } else {
+                Some(Signal::new(signal))
+            },
+            usage: ProcUsage::from_ffi(usage.as_slice()),
+        });
+    }
 }
 
 fn event_add_handler_ffi(desc: &event_description_t, name: &CxxWString) {
     add_handler(EventHandler::new(desc.into(), Some(name.from_ffi())));
 }
 
-/// All the signals we are interested in are in the 1-32 range (with 32 being the typical SIGRTMAX),
-/// but we can expand it to 64 just to be safe. All code checks if a signal value is within bounds
-/// before handling it.
-const SIGNAL_COUNT: usize = 64;
+/// The classic signals are in the 1-31 range, and the realtime signals go up to SIGRTMAX, which is
+/// 64 on Linux. All code checks if a signal value is within bounds before handling it.
+const SIGNAL_COUNT: usize = 65;
+
+/// A signal which was received at least once since the last `acquire_pending()`.
+struct PendingSignal {
+    signal: Signal,
+    /// How many deliveries were coalesced into this one.
+    count: u32,
+    /// When it was last delivered, in microseconds since the epoch.
+    time_usec: u64,
+}
 
 struct PendingSignals {
     /// A counter that is incremented each time a pending signal is received.
     counter: AtomicU32,
-    /// List of pending signals.
-    received: [AtomicBool; SIGNAL_COUNT],
+    /// How often each signal was received since the last `acquire_pending()`.
+    received: [AtomicU32; SIGNAL_COUNT],
+    /// When each signal was last received, in microseconds since the epoch.
+    last_time: [AtomicU64; SIGNAL_COUNT],
     /// The last counter visible in `acquire_pending()`.
     /// This is not accessed from a signal handler.
     last_counter: Mutex<u32>,
@@ -503,14 +626,26 @@ impl PendingSignals {
     /// Mark a signal as pending. This may be called from a signal handler. We expect only one
     /// signal handler to execute at once. Also note that these may be coalesced.
     pub fn mark(&self, sig: libc::c_int) {
-        if let Some(received) = self.received.get(usize::try_from(sig).unwrap()) {
-            received.store(true, Ordering::Relaxed);
-            self.counter.fetch_add(1, Ordering::Relaxed);
+        let idx = usize::try_from(sig).unwrap();
+        if let (Some(received), Some(last_time)) = (self.received.get(idx), self.last_time.get(idx))
+        {
+            // clock_gettime() is async-signal-safe, unlike SystemTime::now() which may panic.
+            let mut ts = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut ts) } == 0 {
+                let usec = ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000;
+                last_time.store(usec, Ordering::Relaxed);
+            }
+            received.fetch_add(1, Ordering::Relaxed);
+            self.counter.fetch_add(1, Ordering::Release);
         }
     }
 
-    /// Return the list of signals that were set as the bits in a u64, clearing them.
-    pub fn acquire_pending(&self) -> u64 {
+    /// Return the signals that were received since the last call, in order of their number,
+    /// clearing them.
+    pub fn acquire_pending(&self) -> Vec<PendingSignal> {
         let mut current = self
             .last_counter
             .lock()
@@ -519,16 +654,20 @@ impl PendingSignals {
         // Check the counter first. If it hasn't changed, no signals have been received.
         let count = self.counter.load(Ordering::Acquire);
         if count == *current {
-            return 0;
+            return vec![];
         }
 
         // The signal count has changed. Store the new counter and fetch all set signals.
         *current = count;
-        let mut result = 0;
+        let mut result = vec![];
         for (i, received) in self.received.iter().enumerate() {
-            if received.load(Ordering::Relaxed) {
-                result |= 1_u64 << i;
-                received.store(false, Ordering::Relaxed);
+            let count = received.swap(0, Ordering::Relaxed);
+            if count > 0 {
+                result.push(PendingSignal {
+                    signal: Signal::new(i as i32),
+                    count,
+                    time_usec: self.last_time[i].load(Ordering::Relaxed),
+                });
             }
         }
 
@@ -538,13 +677,14 @@ impl PendingSignals {
 
 // Required until inline const is stabilized.
 #[allow(clippy::declare_interior_mutable_const)]
-const ATOMIC_BOOL_FALSE: AtomicBool = AtomicBool::new(false);
-#[allow(clippy::declare_interior_mutable_const)]
 const ATOMIC_U32_0: AtomicU32 = AtomicU32::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ATOMIC_U64_0: AtomicU64 = AtomicU64::new(0);
 
 static PENDING_SIGNALS: PendingSignals = PendingSignals {
     counter: AtomicU32::new(0),
-    received: [ATOMIC_BOOL_FALSE; SIGNAL_COUNT],
+    received: [ATOMIC_U32_0; SIGNAL_COUNT],
+    last_time: [ATOMIC_U64_0; SIGNAL_COUNT],
     last_counter: Mutex::new(0),
 };
 
@@ -748,6 +888,11 @@ fn fire_internal(parser: &mut parser_t, event: &Event) {
         let b = (*parser)
             .pin()
             .push_block(block_t::event_block((event as *const Event).cast()).within_unique_ptr());
+        // The event block has its own scope. Exported locals are visible in the handler function.
+        for (name, values) in &event.variables {
+            let values: Vec<&wstr> = values.iter().map(|v| v.as_utfstr()).collect();
+            (*parser).set_var(name, &values, EnvMode::LOCAL | EnvMode::EXPORT);
+        }
         (*parser)
             .pin()
             .eval_string_ffi1(&buffer.to_ffi())
@@ -782,12 +927,8 @@ pub fn fire_delayed(parser: &mut parser_t) {
     let mut to_send = std::mem::take(&mut *BLOCKED_EVENTS.lock().expect("Mutex poisoned!"));
 
     // Append all signal events to to_send.
-    // 'signals' contains a bit set for each signal that has been received.
-    let mut signals: u64 = PENDING_SIGNALS.acquire_pending();
-    while signals != 0 {
-        let sig = signals.trailing_zeros() as i32;
-        signals &= !(1_u64 << sig);
-        let sig = Signal::new(sig);
+    for pending in PENDING_SIGNALS.acquire_pending() {
+        let sig = pending.signal;
 
         // HACK: The only variables we change in response to a *signal* are $COLUMNS and $LINES.
         // Do that now.
@@ -799,6 +940,21 @@ pub fn fire_delayed(parser: &mut parser_t) {
                 typ: EventType::Signal { signal: sig },
             },
             arguments: vec![sig.name().into()],
+            variables: vec![
+                ("fish_event_signal".into(), vec![sig.name().into()]),
+                (
+                    "fish_event_signal_number".into(),
+                    vec![sig.code().to_wstring()],
+                ),
+                (
+                    "fish_event_signal_count".into(),
+                    vec![pending.count.to_wstring()],
+                ),
+                (
+                    "fish_event_signal_time".into(),
+                    vec![(pending.time_usec / 1000).to_wstring()],
+                ),
+            ],
         };
         to_send.push(event);
     }
@@ -920,6 +1076,7 @@ pub fn fire_generic(parser: &mut parser_t, name: WString, arguments: Vec<WString
                 typ: EventType::Generic { param: name },
             },
             arguments,
+            variables: vec![],
         },
     )
 }
@@ -0,0 +1,392 @@
+//! A readiness set backed by epoll on Linux and kqueue on macOS and FreeBSD, for the fd monitor.
+//!
+//! Fds stay registered with the kernel between waits, so only the fds which changed since the
+//! last wait cost a syscall. Timeouts are kernel timers in the same event loop (a timerfd with
+//! epoll, EVFILT_TIMER with kqueue), which keeps microsecond precision where epoll_wait() only
+//! has milliseconds, and lets the fd monitor serve pure timers without sleeping threads.
+//! If [`new`] fails, or on other systems, the caller should fall back to [`FdReadableSet`].
+//!
+//! [`new`]: EventLoop::new
+//! [`FdReadableSet`]: crate::fd_readable_set::FdReadableSet
+
+use std::collections::HashSet;
+use std::os::unix::io::RawFd;
+
+use libc::c_int;
+
+use crate::fd_readable_set::FdReadableSet;
+use crate::fds::AutoCloseFd;
+use crate::wchar::L;
+
+/// How many events to collect per wait.
+const MAX_EVENTS: usize = 64;
+
+const kUsecPerSec: u64 = 1_000_000;
+
+pub struct EventLoop {
+    /// The epoll or kqueue fd.
+    queue: AutoCloseFd,
+    /// The timerfd used for timeouts.
+    #[cfg(target_os = "linux")]
+    timer: AutoCloseFd,
+    /// The fds added since the last clear().
+    wanted: Vec<RawFd>,
+    /// The fds registered with the kernel.
+    registered: HashSet<RawFd>,
+    /// The fds found readable by the last check_readable().
+    readable: Vec<RawFd>,
+}
+
+impl EventLoop {
+    /// Set up the event loop. \return None if epoll or kqueue is unavailable.
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Option<Self> {
+        let queue = AutoCloseFd::new_tracked(
+            unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) },
+            L!("fd monitor epoll"),
+        );
+        if !queue.is_valid() {
+            return None;
+        }
+        let timer = AutoCloseFd::new_tracked(
+            unsafe {
+                libc::timerfd_create(
+                    libc::CLOCK_MONOTONIC,
+                    libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+                )
+            },
+            L!("fd monitor timer"),
+        );
+        if !timer.is_valid() {
+            return None;
+        }
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: timer.fd() as u64,
+        };
+        if unsafe { libc::epoll_ctl(queue.fd(), libc::EPOLL_CTL_ADD, timer.fd(), &mut event) } < 0 {
+            return None;
+        }
+        Some(EventLoop {
+            queue,
+            timer,
+            wanted: Vec::new(),
+            registered: HashSet::new(),
+            readable: Vec::new(),
+        })
+    }
+
+    /// Set up the event loop. \return None if epoll or kqueue is unavailable.
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    pub fn new() -> Option<Self> {
+        let queue = AutoCloseFd::new_tracked(unsafe { libc::kqueue() }, L!("fd monitor kqueue"));
+        if !queue.is_valid() {
+            return None;
+        }
+        Some(EventLoop {
+            queue,
+            wanted: Vec::new(),
+            registered: HashSet::new(),
+            readable: Vec::new(),
+        })
+    }
+
+    /// Reset back to an empty set. Fds stay registered, in case the same fds are added again.
+    pub fn clear(&mut self) {
+        self.wanted.clear();
+        self.readable.clear();
+    }
+
+    /// Add an fd to the set. The fd is ignored if negative.
+    pub fn add(&mut self, fd: RawFd) {
+        if fd >= 0 {
+            self.wanted.push(fd);
+        }
+    }
+
+    /// Returns `true` if the given `fd` was found readable (or HUP'd) by the last
+    /// check_readable().
+    pub fn test(&self, fd: RawFd) -> bool {
+        fd >= 0 && self.readable.contains(&fd)
+    }
+
+    /// Forget the registration of \p fd, which has been closed. Its number may be reused by a new
+    /// fd, which needs to be registered anew.
+    pub fn forget(&mut self, fd: RawFd) {
+        if self.registered.remove(&fd) {
+            // Closing the fd normally drops the registration already, but not if the file is still
+            // open elsewhere. Errors are expected and ignored.
+            self.unregister(fd);
+        }
+    }
+
+    /// Wait until an fd in the set is readable, or the timeout passes. The timeout may be
+    /// [`FdReadableSet::kNoTimeout`]. \return the number of readable fds, or -1 on error, with
+    /// errno set.
+    pub fn check_readable(&mut self, timeout_usec: u64) -> c_int {
+        self.wanted.sort_unstable();
+        self.wanted.dedup();
+
+        // Unregister fds which are no longer wanted, and register new ones. Fds which can't be
+        // registered, like regular files with epoll, are always readable.
+        let unwanted: Vec<RawFd> = self
+            .registered
+            .iter()
+            .copied()
+            .filter(|fd| self.wanted.binary_search(fd).is_err())
+            .collect();
+        for fd in unwanted {
+            self.registered.remove(&fd);
+            self.unregister(fd);
+        }
+        for i in 0..self.wanted.len() {
+            let fd = self.wanted[i];
+            if !self.registered.contains(&fd) {
+                if self.register(fd) {
+                    self.registered.insert(fd);
+                } else {
+                    self.readable.push(fd);
+                }
+            }
+        }
+
+        let timeout_usec = if self.readable.is_empty() {
+            timeout_usec
+        } else {
+            0
+        };
+        if self.wait(timeout_usec) < 0 {
+            return -1;
+        }
+        self.readable.sort_unstable();
+        self.readable.dedup();
+        self.readable.len() as c_int
+    }
+
+    #[cfg(target_os = "linux")]
+    fn register(&mut self, fd: RawFd) -> bool {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: fd as u64,
+        };
+        unsafe { libc::epoll_ctl(self.queue.fd(), libc::EPOLL_CTL_ADD, fd, &mut event) == 0 }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn unregister(&mut self, fd: RawFd) {
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        unsafe { libc::epoll_ctl(self.queue.fd(), libc::EPOLL_CTL_DEL, fd, &mut event) };
+    }
+
+    #[cfg(target_os = "linux")]
+    fn wait(&mut self, timeout_usec: u64) -> c_int {
+        // A zero timeout is a plain poll; otherwise the timerfd wakes us. An it_value of zero
+        // disarms it.
+        let epoll_timeout = if timeout_usec == 0 { 0 } else { -1 };
+        let mut spec: libc::itimerspec = unsafe { std::mem::zeroed() };
+        if timeout_usec != 0 && timeout_usec != FdReadableSet::kNoTimeout {
+            spec.it_value.tv_sec = (timeout_usec / kUsecPerSec) as libc::time_t;
+            spec.it_value.tv_nsec = ((timeout_usec % kUsecPerSec) * 1000) as libc::c_long;
+        }
+        if unsafe { libc::timerfd_settime(self.timer.fd(), 0, &spec, std::ptr::null_mut()) } < 0 {
+            return -1;
+        }
+
+        let mut events: [libc::epoll_event; MAX_EVENTS] = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            libc::epoll_wait(
+                self.queue.fd(),
+                events.as_mut_ptr(),
+                MAX_EVENTS as c_int,
+                epoll_timeout,
+            )
+        };
+        if ret < 0 {
+            return -1;
+        }
+        for event in &events[..ret as usize] {
+            let fd = event.u64 as RawFd;
+            if fd == self.timer.fd() {
+                let mut expirations = [0u8; 8];
+                unsafe {
+                    libc::read(
+                        fd,
+                        expirations.as_mut_ptr() as *mut libc::c_void,
+                        expirations.len(),
+                    )
+                };
+            } else if self.wanted.binary_search(&fd).is_ok() {
+                // A stale registration of a closed fd may report under a reused number; only
+                // trust fds we asked about.
+                self.readable.push(fd);
+            }
+        }
+        0
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    fn change(&self, ident: usize, filter: i16, flags: u16, fflags: u32, data: i64) -> bool {
+        let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+        change.ident = ident as _;
+        change.filter = filter as _;
+        change.flags = flags as _;
+        change.fflags = fflags as _;
+        change.data = data as _;
+        unsafe {
+            libc::kevent(
+                self.queue.fd(),
+                &change,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            ) == 0
+        }
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    fn register(&mut self, fd: RawFd) -> bool {
+        self.change(fd as usize, libc::EVFILT_READ as i16, libc::EV_ADD, 0, 0)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    fn unregister(&mut self, fd: RawFd) {
+        self.change(fd as usize, libc::EVFILT_READ as i16, libc::EV_DELETE, 0, 0);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    fn wait(&mut self, timeout_usec: u64) -> c_int {
+        // Timer idents are separate from fds; we only ever need the one.
+        const TIMER_IDENT: usize = 0;
+        let timer = libc::EVFILT_TIMER as i16;
+        let zero = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let mut wait_timeout: *const libc::timespec = std::ptr::null();
+        if timeout_usec == 0 {
+            wait_timeout = &zero;
+        } else if timeout_usec == FdReadableSet::kNoTimeout {
+            // Deleting a timer which already fired fails, which is fine.
+            self.change(TIMER_IDENT, timer, libc::EV_DELETE, 0, 0);
+        } else {
+            // Re-adding a timer resets it.
+            let armed = self.change(
+                TIMER_IDENT,
+                timer,
+                libc::EV_ADD | libc::EV_ONESHOT,
+                libc::NOTE_USECONDS,
+                timeout_usec.min(i64::MAX as u64) as i64,
+            );
+            if !armed {
+                return -1;
+            }
+        }
+
+        let mut events: [libc::kevent; MAX_EVENTS] = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            libc::kevent(
+                self.queue.fd(),
+                std::ptr::null(),
+                0,
+                events.as_mut_ptr(),
+                MAX_EVENTS as c_int,
+                wait_timeout,
+            )
+        };
+        if ret < 0 {
+            return -1;
+        }
+        for event in &events[..ret as usize] {
+            if event.filter == timer {
+                continue;
+            }
+            let fd = event.ident as RawFd;
+            if self.wanted.binary_search(&fd).is_ok() {
+                self.readable.push(fd);
+            }
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventLoop;
+    use crate::fd_readable_set::FdReadableSet;
+    use crate::fds::AutoCloseFd;
+    use std::time::{Duration, Instant};
+
+    fn make_pipe() -> (AutoCloseFd, AutoCloseFd) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        (AutoCloseFd::new(fds[0]), AutoCloseFd::new(fds[1]))
+    }
+
+    fn write_byte(fd: &AutoCloseFd) {
+        let written = unsafe { libc::write(fd.fd(), b"x".as_ptr().cast(), 1) };
+        assert_eq!(written, 1);
+    }
+
+    #[test]
+    fn test_fd_readiness() {
+        let mut set = EventLoop::new().expect("Could not create event loop");
+        let (read1, write1) = make_pipe();
+        let (read2, write2) = make_pipe();
+        let check = |set: &mut EventLoop, timeout_usec| {
+            set.clear();
+            set.add(read1.fd());
+            set.add(read2.fd());
+            set.add(-1);
+            set.check_readable(timeout_usec)
+        };
+
+        assert_eq!(check(&mut set, 0), 0);
+        assert!(!set.test(read1.fd()) && !set.test(read2.fd()));
+
+        // Only the fd with data is readable.
+        write_byte(&write2);
+        assert_eq!(check(&mut set, FdReadableSet::kNoTimeout), 1);
+        assert!(!set.test(read1.fd()) && set.test(read2.fd()));
+
+        // It stays readable until the data is read, and a pipe whose writer is gone is readable.
+        drop(write1);
+        assert_eq!(check(&mut set, FdReadableSet::kNoTimeout), 2);
+        assert!(set.test(read1.fd()) && set.test(read2.fd()));
+        assert!(!set.test(-1));
+    }
+
+    #[test]
+    fn test_timer_ordering() {
+        let mut set = EventLoop::new().expect("Could not create event loop");
+        let (read, write) = make_pipe();
+
+        // Each wait gets its own timeout, whether it is shorter or longer than the last one, and
+        // an expiry from an earlier wait does not end a later one early.
+        for timeout_usec in [30_000, 10_000, 20_000] {
+            set.clear();
+            set.add(read.fd());
+            let start = Instant::now();
+            assert_eq!(set.check_readable(timeout_usec), 0);
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_micros(timeout_usec));
+            assert!(elapsed < Duration::from_micros(timeout_usec) + Duration::from_millis(500));
+        }
+
+        // A readable fd ends the wait before the timer, and the timer does not fire later.
+        write_byte(&write);
+        set.clear();
+        set.add(read.fd());
+        let start = Instant::now();
+        assert_eq!(set.check_readable(10_000_000), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let mut byte = [0u8];
+        assert_eq!(
+            unsafe { libc::read(read.fd(), byte.as_mut_ptr().cast(), 1) },
+            1
+        );
+        set.clear();
+        set.add(read.fd());
+        assert_eq!(set.check_readable(0), 0);
+    }
+}
@@ -44,6 +44,9 @@ bitflags! {
         /// Do expansions specifically to support external command completions. This means using PATH as
         /// a list of potential working directories.
         const SPECIAL_FOR_COMMAND = 1 << 13;
+        /// Also allow case-insensitive subsequence matches, and rank subsequence matches by how
+        /// close they are. Only applicable if fuzzy_match is set.
+        const FUZZY_SUBSEQUENCE = 1 << 14;
     }
 }
 
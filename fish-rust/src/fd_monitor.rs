@@ -5,10 +5,19 @@ use std::time::{Duration, Instant};
 
 pub use self::fd_monitor_ffi::ItemWakeReason;
 pub use self::fd_monitor_ffi::{new_fd_event_signaller, FdEventSignaller};
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+use crate::event_loop::EventLoop;
 use crate::fd_readable_set::FdReadableSet;
 use crate::fds::AutoCloseFd;
 use crate::ffi::void_ptr;
 use crate::flog::FLOG;
+#[cfg(target_os = "linux")]
+use crate::io_uring::IoUringReadableSet;
 use crate::threads::assert_is_background_thread;
 use crate::wutil::perror;
 use cxx::SharedPtr;
@@ -78,6 +87,14 @@ mod fd_monitor_ffi {
             param: *const u8,
         ) -> u64;
 
+        #[cxx_name = "add_timer"]
+        fn add_timer_ffi(
+            &mut self,
+            timeout_usecs: u64,
+            callback: *const u8,
+            param: *const u8,
+        ) -> u64;
+
         #[cxx_name = "poke_item"]
         fn poke_item_ffi(&self, item_id: u64);
 
@@ -126,9 +143,10 @@ enum FdMonitorCallback {
 }
 
 /// An item containing an fd and callback, which can be monitored to watch when it becomes readable
-/// and invoke the callback.
+/// and invoke the callback. An item without an fd is a timer, which is invoked once its timeout
+/// passes and then removed.
 pub struct FdMonitorItem {
-    /// The fd to monitor
+    /// The fd to monitor, or an empty fd for a timer.
     fd: AutoCloseFd,
     /// A callback to be invoked when the fd is readable, or when we are timed out. If we time out,
     /// then timed_out will be true. If the fd is invalid on return from the function, then the item
@@ -143,6 +161,82 @@ pub struct FdMonitorItem {
     item_id: FdMonitorItemId,
 }
 
+/// The set of fds the background thread waits on: epoll or kqueue where available, otherwise
+/// io_uring, otherwise poll() or select().
+enum ReadinessSet {
+    Readable(FdReadableSet),
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    ))]
+    EventLoop(EventLoop),
+    #[cfg(target_os = "linux")]
+    IoUring(IoUringReadableSet),
+}
+
+/// Evaluate `$body` with `$set` bound to the set in use by a [`ReadinessSet`], so the platform
+/// conditions are only spelled out here.
+macro_rules! with_set {
+    ($readiness:expr, $set:ident => $body:expr) => {
+        match $readiness {
+            ReadinessSet::Readable($set) => $body,
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd"
+            ))]
+            ReadinessSet::EventLoop($set) => $body,
+            #[cfg(target_os = "linux")]
+            ReadinessSet::IoUring($set) => $body,
+        }
+    };
+}
+
+impl ReadinessSet {
+    fn new() -> Self {
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd"
+        ))]
+        if let Some(set) = EventLoop::new() {
+            FLOG!(fd_monitor, "Using epoll or kqueue");
+            return ReadinessSet::EventLoop(set);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(set) = IoUringReadableSet::new() {
+            FLOG!(fd_monitor, "Using io_uring");
+            return ReadinessSet::IoUring(set);
+        }
+        ReadinessSet::Readable(FdReadableSet::new())
+    }
+
+    fn clear(&mut self) {
+        with_set!(self, set => set.clear())
+    }
+
+    fn add(&mut self, fd: RawFd) {
+        with_set!(self, set => set.add(fd))
+    }
+
+    fn test(&self, fd: RawFd) -> bool {
+        with_set!(self, set => set.test(fd))
+    }
+
+    /// Note that \p fd was closed, so its number may be reused for a different file.
+    fn forget(&mut self, fd: RawFd) {
+        with_set!(self, set => set.forget(fd))
+    }
+
+    fn check_readable(&mut self, timeout_usec: u64) -> libc::c_int {
+        with_set!(self, set => set.check_readable(timeout_usec))
+    }
+}
+
 /// Unlike C++, rust's `Vec` has `Vec::retain()` instead of `std::remove_if(...)` with the inverse
 /// logic. It's hard to keep track of which bool means what across the different layers, so be more
 /// explicit.
@@ -175,7 +269,7 @@ impl FdMonitorItem {
     /// Invoke this item's callback if its value (when its value is set in the fd or has timed out).
     /// Returns `true` if the item should be retained or `false` if it should be removed from the
     /// set.
-    fn service_item(&mut self, fds: &FdReadableSet, now: &Instant) -> ItemAction {
+    fn service_item(&mut self, fds: &ReadinessSet, now: &Instant) -> ItemAction {
         let mut result = ItemAction::Retain;
         let readable = fds.test(self.fd.as_raw_fd());
         let timed_out = !readable && self.remaining_time(now) == Some(Duration::ZERO);
@@ -350,7 +444,10 @@ impl FdMonitor {
 
     /// Add an item to the monitor. Returns the [`FdMonitorItemId`] assigned to the item.
     pub fn add(&self, mut item: FdMonitorItem) -> FdMonitorItemId {
-        assert!(item.fd.is_valid());
+        assert!(
+            item.fd.is_valid() || item.timeout.is_some(),
+            "Item needs an fd or a timeout!"
+        );
         assert!(item.timeout != Some(Duration::ZERO), "Invalid timeout!");
         assert!(
             item.item_id == FdMonitorItemId(0),
@@ -383,6 +480,9 @@ impl FdMonitor {
             crate::threads::spawn(move || {
                 background_monitor.run();
             });
+        } else {
+            // Tickle our signaller, so the new item is waited on, and its timeout respected.
+            self.change_signaller.post();
         }
 
         item_id
@@ -411,6 +511,26 @@ impl FdMonitor {
         self.add(item).0
     }
 
+    /// Add a timer which invokes `callback` with [`ItemWakeReason::Timeout`] once `timeout` has
+    /// passed, or with [`ItemWakeReason::Poke`] if poked before, and is then removed. Returns the
+    /// [`FdMonitorItemId`] assigned to the timer.
+    pub fn add_timer(&self, timeout: Duration, callback: NativeCallback) -> FdMonitorItemId {
+        self.add(FdMonitorItem::new(
+            AutoCloseFd::empty(),
+            Some(timeout),
+            Some(callback),
+        ))
+    }
+
+    fn add_timer_ffi(&mut self, timeout_usecs: u64, callback: *const u8, param: *const u8) -> u64 {
+        // Safety: see add_item_ffi().
+        let callback = unsafe { std::mem::transmute(callback) };
+        let mut item = FdMonitorItem::default();
+        item.callback = FdMonitorCallback::Ffi(callback, param.into());
+        item.timeout = Some(Duration::from_micros(timeout_usecs.max(1)));
+        self.add(item).0
+    }
+
     /// Mark that the item with the given ID needs to be woken up explicitly.
     pub fn poke_item(&self, item_id: FdMonitorItemId) {
         assert!(item_id.0 > 0, "Invalid item id!");
@@ -454,12 +574,12 @@ impl BackgroundFdMonitor {
         assert_is_background_thread();
 
         let mut pokelist: Vec<FdMonitorItemId> = Vec::new();
-        let mut fds = FdReadableSet::new();
+        let mut fds = ReadinessSet::new();
 
         loop {
             // Poke any items that need it
             if !pokelist.is_empty() {
-                self.poke(&pokelist);
+                self.poke(&pokelist, &mut fds);
                 pokelist.clear();
             }
             fds.clear();
@@ -477,7 +597,7 @@ impl BackgroundFdMonitor {
                 if item.last_time.is_none() {
                     item.last_time = Some(now);
                 }
-                timeout = timeout.min(item.timeout.unwrap_or(Duration::MAX));
+                timeout = timeout.min(item.remaining_time(&now).unwrap_or(Duration::MAX));
             }
 
             // If we have no items, then we wish to allow the thread to exit, but after a time, so
@@ -515,10 +635,14 @@ impl BackgroundFdMonitor {
             now = Instant::now();
 
             // A predicate which services each item in turn, returning true if it should be removed
-            let servicer = |item: &mut FdMonitorItem| {
+            let mut closed_fds = Vec::new();
+            let mut servicer = |item: &mut FdMonitorItem| {
                 let fd = item.fd.as_raw_fd();
                 if item.service_item(&fds, &now) == ItemAction::Remove {
-                    FLOG!(fd_monitor, "Removing fd", fd);
+                    if fd >= 0 {
+                        FLOG!(fd_monitor, "Removing fd", fd);
+                        closed_fds.push(fd);
+                    }
                     return ItemAction::Remove;
                 }
                 return ItemAction::Retain;
@@ -529,6 +653,9 @@ impl BackgroundFdMonitor {
 
             self.items
                 .retain_mut(|item| servicer(item) == ItemAction::Retain);
+            for fd in closed_fds {
+                fds.forget(fd);
+            }
 
             // Handle any changes if the change signaller was set. Alternatively, this may be the
             // wait lap, in which case we might want to commit to exiting.
@@ -571,11 +698,13 @@ impl BackgroundFdMonitor {
 
     /// Poke items in the poke list, removing any items that close their fd in their callback. The
     /// poke list is consumed after this. This is only called from the background thread.
-    fn poke(&mut self, pokelist: &[FdMonitorItemId]) {
+    fn poke(&mut self, pokelist: &[FdMonitorItemId], fds: &mut ReadinessSet) {
         self.items.retain_mut(|item| {
+            let fd = item.fd.as_raw_fd();
             let action = item.maybe_poke_item(pokelist);
-            if action == ItemAction::Remove {
-                FLOG!(fd_monitor, "Removing fd", item.fd.as_raw_fd());
+            if action == ItemAction::Remove && fd >= 0 {
+                FLOG!(fd_monitor, "Removing fd", fd);
+                fds.forget(fd);
             }
             return action == ItemAction::Retain;
         });
@@ -83,6 +83,9 @@ impl fd_readable_set_t {
         fd >= 0 && unsafe { libc::FD_ISSET(fd, &self.fdset_) }
     }
 
+    /// Note that \p fd was closed. Nothing needs to be done, as select() keeps no state.
+    pub fn forget(&mut self, _fd: RawFd) {}
+
     /// Call `select()` or `poll()`, according to FISH_READABLE_SET_USE_POLL. Note this
     /// destructively modifies the set. Returns the result of `select()` or `poll()`.
     pub fn check_readable(&mut self, timeout_usec: u64) -> c_int {
@@ -187,6 +190,9 @@ impl fd_readable_set_t {
         return false;
     }
 
+    /// Note that \p fd was closed. Nothing needs to be done, as poll() keeps no state.
+    pub fn forget(&mut self, _fd: RawFd) {}
+
     /// Convert from usecs to poll-friendly msecs.
     fn usec_to_poll_msec(timeout_usec: u64) -> c_int {
         let mut timeout_msec: u64 = timeout_usec / kUsecPerMsec;
@@ -1,13 +1,18 @@
-use crate::common::wcs2zstring;
+use crate::common::{str2wcstring, wcs2zstring};
 use crate::ffi;
-use crate::wchar::{wstr, L};
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::{AsWstr, WCharToFFI};
 use crate::wutil::perror;
+use cxx::{CxxWString, UniquePtr};
 use libc::EINTR;
 use libc::O_CLOEXEC;
 use nix::unistd;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
 use std::ffi::CStr;
 use std::io::{Read, Write};
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::Mutex;
 
 pub const PIPE_ERROR: &wstr = L!("An error occurred while setting up pipe");
 
@@ -64,7 +69,93 @@ mod autoclose_fd_t {
         fn is_valid(&self) -> bool;
         fn close(&mut self);
         fn fd(&self) -> i32;
+
+        #[cxx_name = "track_fd"]
+        fn track_fd_ffi(fd: i32, purpose: &CxxWString);
+        #[cxx_name = "pass_fd"]
+        fn pass_fd_ffi(fd: i32, purpose: &CxxWString) -> bool;
+        #[cxx_name = "tracked_fd_purpose"]
+        fn tracked_fd_purpose_ffi(fd: i32) -> UniquePtr<CxxWString>;
+    }
+}
+
+/// An fd which fish holds open on purpose, recorded so that `status fds` can explain it.
+struct TrackedFd {
+    /// What the fd is for, like "universal variable notifier".
+    purpose: WString,
+    /// The device and inode the fd referred to when it was tracked. fds are not untracked when
+    /// closed, so this tells whether the number has since been reused for something else.
+    dev: u64,
+    ino: u64,
+}
+
+static TRACKED_FDS: Lazy<Mutex<BTreeMap<RawFd, TrackedFd>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// \return the device and inode that \p fd refers to, or None if it is not open.
+#[allow(clippy::unnecessary_cast)]
+fn fd_identity(fd: RawFd) -> Option<(u64, u64)> {
+    let mut buf: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut buf) } < 0 {
+        return None;
+    }
+    Some((buf.st_dev as u64, buf.st_ino as u64))
+}
+
+/// Record that fish holds \p fd open for \p purpose. This also marks it close-on-exec, so a
+/// tracked fd is never leaked into child processes, except through an explicit redirection.
+/// The fds fish opens with open_cloexec() and make_autoclose_pipes() are tracked already, so this
+/// only needs to be called to give an fd a more specific purpose, or for fds created otherwise.
+pub fn track_fd(fd: RawFd, purpose: &wstr) {
+    set_tracked(fd, purpose, true);
+}
+
+/// Record that fish holds \p fd open for \p purpose, and clear its close-on-exec flag, so that
+/// all commands fish runs inherit it on purpose. This is how `exec --open --inherit` passes fds.
+/// \return false if \p fd is not open.
+pub fn pass_fd(fd: RawFd, purpose: &wstr) -> bool {
+    set_tracked(fd, purpose, false)
+}
+
+fn set_tracked(fd: RawFd, purpose: &wstr, cloexec: bool) -> bool {
+    let Some((dev, ino)) = fd_identity(fd) else {
+        return false;
+    };
+    let flags = if cloexec { libc::FD_CLOEXEC } else { 0 };
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } < 0 {
+        return false;
     }
+    let tracked = TrackedFd {
+        purpose: purpose.to_owned(),
+        dev,
+        ino,
+    };
+    TRACKED_FDS.lock().unwrap().insert(fd, tracked);
+    true
+}
+
+/// \return what \p fd was tracked for, or None if it is not tracked, or has been closed and its
+/// number reused since.
+pub fn tracked_fd_purpose(fd: RawFd) -> Option<WString> {
+    let mut tracked_fds = TRACKED_FDS.lock().unwrap();
+    let tracked = tracked_fds.get(&fd)?;
+    if fd_identity(fd) != Some((tracked.dev, tracked.ino)) {
+        tracked_fds.remove(&fd);
+        return None;
+    }
+    Some(tracked.purpose.clone())
+}
+
+fn track_fd_ffi(fd: i32, purpose: &CxxWString) {
+    track_fd(fd, purpose.as_wstr());
+}
+
+fn pass_fd_ffi(fd: i32, purpose: &CxxWString) -> bool {
+    pass_fd(fd, purpose.as_wstr())
+}
+
+fn tracked_fd_purpose_ffi(fd: i32) -> UniquePtr<CxxWString> {
+    tracked_fd_purpose(fd).unwrap_or_default().to_ffi()
 }
 
 impl AutoCloseFd {
@@ -107,6 +198,16 @@ impl AutoCloseFd {
         AutoCloseFd { fd_: fd }
     }
 
+    // Create a new AutoCloseFd taking ownership of the passed fd, and track it for \p purpose,
+    // which also makes it close-on-exec. Use this for fds not made by open_cloexec() or
+    // make_autoclose_pipes(), which track theirs already.
+    pub fn new_tracked(fd: RawFd, purpose: &wstr) -> Self {
+        if fd >= 0 {
+            track_fd(fd, purpose);
+        }
+        AutoCloseFd { fd_: fd }
+    }
+
     // Create a new AutoCloseFd without an open fd
     pub fn empty() -> Self {
         AutoCloseFd { fd_: -1 }
@@ -165,14 +266,18 @@ pub fn make_autoclose_pipes() -> Option<AutoClosePipes> {
 }
 
 /// Wide character version of open() that also sets the close-on-exec flag (atomically when
-/// possible).
+/// possible). The fd is tracked with the path as its purpose.
 pub fn wopen_cloexec(pathname: &wstr, flags: i32, mode: libc::c_int) -> RawFd {
     open_cloexec(wcs2zstring(pathname).as_c_str(), flags, mode)
 }
 
 /// Narrow versions of wopen_cloexec.
 pub fn open_cloexec(path: &CStr, flags: i32, mode: libc::c_int) -> RawFd {
-    unsafe { libc::open(path.as_ptr(), flags | O_CLOEXEC, mode) }
+    let fd = unsafe { libc::open(path.as_ptr(), flags | O_CLOEXEC, mode) };
+    if fd >= 0 {
+        track_fd(fd, &str2wcstring(path.to_bytes()));
+    }
+    fd
 }
 
 /// Close a file descriptor \p fd, retrying on EINTR.
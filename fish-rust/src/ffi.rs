@@ -77,7 +77,6 @@ include_cpp! {
     generate!("fish_wcswidth")
 
     generate!("wildcard_match")
-    generate!("wgettext_ptr")
 
     generate!("block_t")
     generate!("parser_t")
@@ -145,6 +144,8 @@ include_cpp! {
     generate!("reader_change_history")
     generate!("history_session_id")
     generate!("reader_change_cursor_selection_mode")
+    generate!("reader_change_undo_coalesce")
+    generate!("reader_change_exit_job_policy")
     generate!("reader_set_autosuggestion_enabled_ffi")
     generate!("function_invalidate_path")
     generate!("complete_invalidate_path")
@@ -48,7 +48,9 @@ impl<'a> NodeVisitor<'a> for &mut PrettyPrinter<'a> {
             Type::begin_header => {
                 // 'begin' does not require a newline after it, but we insert one.
                 node.accept(self, false);
-                self.companion.as_mut().visit_begin_header();
+                self.companion.as_mut().visit_begin_header(
+                    (node.as_begin_header().unwrap() as *const ast::BeginHeader).cast(),
+                );
             }
             _ => {
                 // For branch and list nodes, default is to visit their children.
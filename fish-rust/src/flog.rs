@@ -135,6 +135,7 @@ pub mod categories {
         (path, "path"L, "Searching/using paths"L);
 
         (screen, "screen"L, "Screen repaints"L);
+        (stall, "stall"L, "Reports of the main thread being stuck"L);
     );
 }
 
@@ -162,6 +163,7 @@ pub trait FloggableDebug: std::fmt::Debug {
 
 /// Write to our FLOG file.
 pub fn flog_impl(s: &str) {
+    crate::crash_handler::note_flog(s);
     let fd = get_flog_file_fd().0 as RawFd;
     if fd < 0 {
         return;
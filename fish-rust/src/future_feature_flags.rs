@@ -218,6 +218,14 @@ pub fn feature_test(flag: FeatureFlag) -> bool {
     unsafe { &*(*global_features).get() }.test(flag)
 }
 
+/// Return the name and value of each flag in the global set of features, in a fixed order.
+pub fn feature_values() -> Vec<(&'static wstr, bool)> {
+    metadata
+        .iter()
+        .map(|md| (md.name, feature_test(md.flag)))
+        .collect()
+}
+
 /// Return the global set of features for fish, but mutable. In general fish features should be set
 /// at startup only.
 pub fn mutable_fish_features() -> *mut Features {
@@ -0,0 +1,240 @@
+//! Encryption of history items at rest.
+//!
+//! With `$fish_history_encrypt` set, the text of each history item - the command, the directory it
+//! was run in and the paths it referenced - is stored encrypted with ChaCha20-Poly1305. The
+//! timestamps stay readable, since other sessions use them to find the items they should see.
+//!
+//! An encrypted value looks like `!fish-enc:ID:DATA`. ID identifies the key, so items encrypted
+//! with another key (or when the key isn't known) can be skipped instead of failing to decrypt,
+//! and DATA is the base64 of the nonce followed by the ciphertext.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use std::sync::Mutex;
+
+#[cxx::bridge]
+mod history_crypt_ffi {
+    extern "Rust" {
+        fn history_crypt_set_key(hex: &[u8]) -> bool;
+        fn history_crypt_encrypt(value: &[u8]) -> Vec<u8>;
+        fn history_crypt_decrypt(value: &[u8], out: &mut Vec<u8>) -> bool;
+        fn history_crypt_can_read(value: &[u8]) -> bool;
+    }
+}
+
+const PREFIX: &[u8] = b"!fish-enc:";
+const NONCE_LEN: usize = 12;
+
+struct HistoryKey {
+    cipher: ChaCha20Poly1305,
+    id: Vec<u8>,
+}
+
+static KEY: Mutex<Option<HistoryKey>> = Mutex::new(None);
+
+/// Sets the key to encrypt history with, given as 64 hex digits. An empty key turns encryption
+/// off. Returns false if the key is invalid, which also turns encryption off.
+pub fn history_crypt_set_key(hex: &[u8]) -> bool {
+    let mut key = KEY.lock().unwrap();
+    *key = None;
+    if hex.is_empty() {
+        return true;
+    }
+    let Some(bytes) = decode_hex(hex).filter(|bytes| bytes.len() == 32) else {
+        return false;
+    };
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&bytes));
+    // The tag of nothing, under a nonce that random nonces won't hit, tells keys apart without
+    // giving them away.
+    let tag = cipher
+        .encrypt(Nonce::from_slice(&[0xFF; NONCE_LEN]), &b""[..])
+        .expect("Encrypting nothing should not fail");
+    let id = encode_hex(&tag[..4]);
+    *key = Some(HistoryKey { cipher, id });
+    true
+}
+
+/// Encrypts a value of a history item, or returns it as is if there is no key.
+pub fn history_crypt_encrypt(value: &[u8]) -> Vec<u8> {
+    let key = KEY.lock().unwrap();
+    let Some(key) = key.as_ref() else {
+        return value.to_vec();
+    };
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = key
+        .cipher
+        .encrypt(Nonce::from_slice(&nonce), value)
+        .expect("Encrypting a history item should not fail");
+
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    let mut result = PREFIX.to_vec();
+    result.extend_from_slice(&key.id);
+    result.push(b':');
+    result.extend_from_slice(&encode_base64(&data));
+    result
+}
+
+/// Decrypts a value of a history item into `out`, or copies it if it isn't encrypted. Returns false
+/// if it can't be decrypted.
+pub fn history_crypt_decrypt(value: &[u8], out: &mut Vec<u8>) -> bool {
+    out.clear();
+    let Some(rest) = value.strip_prefix(PREFIX) else {
+        out.extend_from_slice(value);
+        return true;
+    };
+    let key = KEY.lock().unwrap();
+    let Some(key) = key.as_ref() else {
+        return false;
+    };
+    let Some(data) = rest
+        .strip_prefix(&key.id[..])
+        .and_then(|data| data.strip_prefix(b":"))
+        .and_then(decode_base64)
+    else {
+        return false;
+    };
+    if data.len() < NONCE_LEN {
+        return false;
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    match key.cipher.decrypt(Nonce::from_slice(nonce), ciphertext) {
+        Ok(plaintext) => {
+            *out = plaintext;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns whether a value of a history item can be decrypted, without doing so: whether it is not
+/// encrypted or encrypted with the current key.
+pub fn history_crypt_can_read(value: &[u8]) -> bool {
+    let Some(rest) = value.strip_prefix(PREFIX) else {
+        return true;
+    };
+    let key = KEY.lock().unwrap();
+    match key.as_ref().and_then(|key| rest.strip_prefix(&key.id[..])) {
+        Some(data) => data.starts_with(b":"),
+        None => false,
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> Vec<u8> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut result = Vec::with_capacity(bytes.len() * 2);
+    for &b in bytes {
+        result.push(DIGITS[usize::from(b >> 4)]);
+        result.push(DIGITS[usize::from(b & 0xF)]);
+    }
+    result
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| {
+            let digit = |c: u8| char::from(c).to_digit(16);
+            Some((digit(pair[0])? * 16 + digit(pair[1])?) as u8)
+        })
+        .collect()
+}
+
+const BASE64_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_DIGITS[((n >> (18 - 6 * i)) & 0x3F) as usize]);
+            } else {
+                result.push(b'=');
+            }
+        }
+    }
+    result
+}
+
+fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    if text.len() % 4 != 0 {
+        return None;
+    }
+    let mut result = Vec::with_capacity(text.len() / 4 * 3);
+    for chunk in text.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let digit = BASE64_DIGITS.iter().position(|&d| d == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            result.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        let tests: &[(&[u8], &[u8])] = &[
+            (b"", b""),
+            (b"f", b"Zg=="),
+            (b"fo", b"Zm8="),
+            (b"foo", b"Zm9v"),
+            (b"foob", b"Zm9vYg=="),
+        ];
+        for &(bytes, text) in tests {
+            assert_eq!(encode_base64(bytes), text);
+            assert_eq!(decode_base64(text).as_deref(), Some(bytes));
+        }
+        assert_eq!(decode_base64(b"Zm9"), None);
+        assert_eq!(decode_base64(b"Zm!v"), None);
+    }
+
+    #[test]
+    fn test_history_crypt() {
+        let key = b"000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let mut out = Vec::new();
+
+        assert!(history_crypt_set_key(key));
+        let encrypted = history_crypt_encrypt(b"echo secret");
+        assert!(encrypted.starts_with(PREFIX));
+        assert!(!encrypted.windows(6).any(|w| w == b"secret"));
+        assert!(history_crypt_can_read(&encrypted));
+        assert!(history_crypt_decrypt(&encrypted, &mut out));
+        assert_eq!(out, b"echo secret");
+        // Plain values are still readable.
+        assert!(history_crypt_decrypt(b"echo plain", &mut out));
+        assert_eq!(out, b"echo plain");
+
+        // Another key can't read it.
+        assert!(history_crypt_set_key(&[b'f'; 64]));
+        assert!(!history_crypt_can_read(&encrypted));
+        assert!(!history_crypt_decrypt(&encrypted, &mut out));
+
+        // Without a key, values are not encrypted.
+        assert!(history_crypt_set_key(b""));
+        assert_eq!(history_crypt_encrypt(b"echo plain"), b"echo plain");
+        assert!(!history_crypt_can_read(&encrypted));
+
+        assert!(!history_crypt_set_key(b"not hex"));
+        assert!(!history_crypt_set_key(b"0011"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Shared copies of the names of well-known variables.
+//!
+//! Variable tables are copied often, and most of the names in them are those of variables fish
+//! itself uses. Those names are kept here once, in a fixed set, and tables share them instead of
+//! copying them. Other names are copied as usual, so that names which are only used for a while,
+//! like those from `set var_$i` or argparse's `_flag_*`, go away with their variables.
+
+use crate::env::ELECTRIC_VARIABLES;
+use crate::wchar::{wstr, L};
+use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// The name of a variable, which is shared if it is well-known.
+pub type VarName = Cow<'static, wstr>;
+
+/// Variables which fish, its functions and most configurations use, besides the electric ones.
+const COMMON_VARIABLES: &[&wstr] = &[
+    L!("CDPATH"),
+    L!("COLUMNS"),
+    L!("HOME"),
+    L!("LANG"),
+    L!("LC_ALL"),
+    L!("LC_CTYPE"),
+    L!("LINES"),
+    L!("OLDPWD"),
+    L!("PATH"),
+    L!("SHELL"),
+    L!("TERM"),
+    L!("USER"),
+    L!("__fish_config_dir"),
+    L!("__fish_data_dir"),
+    L!("__fish_initialized"),
+    L!("argv"),
+    L!("dirprev"),
+    L!("fish_color_normal"),
+    L!("fish_complete_path"),
+    L!("fish_features"),
+    L!("fish_function_path"),
+    L!("fish_key_bindings"),
+    L!("fish_user_paths"),
+];
+
+static WELL_KNOWN: Lazy<HashSet<&'static wstr>> = Lazy::new(|| {
+    let electric = ELECTRIC_VARIABLES.iter().map(|var| var.name);
+    electric.chain(COMMON_VARIABLES.iter().copied()).collect()
+});
+
+/// \return \p name, shared if it is well-known and copied otherwise.
+pub fn intern(name: &wstr) -> VarName {
+    match WELL_KNOWN.get(name) {
+        Some(&shared) => Cow::Borrowed(shared),
+        None => Cow::Owned(name.to_owned()),
+    }
+}
+
+use crate::ffi_tests::add_test;
+add_test!("test_intern", || {
+    use crate::wchar::WString;
+    let a = intern(L!("PATH"));
+    let b = intern(&WString::from_str("PATH"));
+    assert_eq!(a, L!("PATH"));
+    // Well-known names are shared.
+    match (&a, &b) {
+        (Cow::Borrowed(a), Cow::Borrowed(b)) => assert!(std::ptr::eq(*a, *b)),
+        _ => panic!("PATH should be shared"),
+    }
+    assert!(matches!(intern(L!("status")), Cow::Borrowed(_)));
+    // Others are copied.
+    let c = intern(L!("fish_intern_test"));
+    assert!(matches!(c, Cow::Owned(_)));
+    assert_eq!(c, L!("fish_intern_test"));
+    assert!(matches!(intern(L!("")), Cow::Owned(_)));
+});
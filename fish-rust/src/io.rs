@@ -946,8 +946,8 @@ const NOCLOB_ERROR: &wstr = L!("The file '%ls' already exists");
 /// Base open mode to pass to calls to open.
 const OPEN_MASK: libc::c_int = 0o666;
 
-/// Provide the fd monitor used for background fillthread operations.
-fn fd_monitor() -> &'static mut FdMonitor {
+/// Provide the fd monitor used for background fillthread operations and timers.
+pub fn fd_monitor() -> &'static mut FdMonitor {
     // Deliberately leaked to avoid shutdown dtors.
     static mut FDM: *const UnsafeCell<FdMonitor> = std::ptr::null();
     unsafe {
@@ -0,0 +1,418 @@
+//! A readiness set backed by io_uring, for the fd monitor on Linux.
+//!
+//! Unlike with poll(), a poll request for an fd stays armed in the kernel until the fd becomes
+//! readable, so waiting again on the same long-lived fds only submits what changed, and the
+//! submissions and the wait share a single syscall. This needs Linux 5.11 or later, for the
+//! timeout argument to io_uring_enter; on older kernels, or where io_uring is disabled, [`new`]
+//! returns None and the caller should fall back to [`FdReadableSet`].
+//!
+//! [`new`]: IoUringReadableSet::new
+//! [`FdReadableSet`]: crate::fd_readable_set::FdReadableSet
+
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use libc::c_int;
+
+use crate::fd_readable_set::FdReadableSet;
+use crate::fds::AutoCloseFd;
+use crate::wchar::L;
+
+const IORING_OP_POLL_ADD: u8 = 6;
+const IORING_OP_POLL_REMOVE: u8 = 7;
+const IORING_ENTER_GETEVENTS: u32 = 1 << 0;
+const IORING_ENTER_EXT_ARG: u32 = 1 << 3;
+const IORING_FEAT_EXT_ARG: u32 = 1 << 8;
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+
+/// How many submissions fit in the ring. More than this are submitted in batches.
+const RING_ENTRIES: u32 = 256;
+
+/// The user data of poll removals, whose completions are not interesting.
+const REMOVAL_USER_DATA: u64 = u64::MAX;
+
+const kUsecPerSec: u64 = 1_000_000;
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct IoUringParams {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+/// A submission queue entry, with the fields we use.
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    /// For polls, the events to wait for.
+    op_flags: u32,
+    user_data: u64,
+    pad: [u64; 3],
+}
+
+/// A completion queue entry.
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct GeteventsArg {
+    sigmask: u64,
+    sigmask_sz: u32,
+    pad: u32,
+    ts: u64,
+}
+
+#[repr(C)]
+struct KernelTimespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+/// A shared memory mapping of part of the ring, unmapped when dropped.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: RawFd, len: usize, offset: libc::off_t) -> Option<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+        Some(Mapping {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// \return a pointer to the value at \p offset bytes into the mapping.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        assert!(offset as usize + std::mem::size_of::<T>() <= self.len);
+        unsafe { self.ptr.add(offset as usize) as *mut T }
+    }
+
+    /// \return the atomic u32 at \p offset bytes into the mapping, like a ring head or tail.
+    fn atomic(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.at::<AtomicU32>(offset) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut _, self.len) };
+    }
+}
+
+/// A set of fds to wait on for readability, like [`FdReadableSet`], using io_uring.
+pub struct IoUringReadableSet {
+    ring_fd: AutoCloseFd,
+    params: IoUringParams,
+    sq_ring: Mapping,
+    cq_ring: Mapping,
+    sqes: Mapping,
+    /// Entries queued in the submission ring but not yet passed to the kernel.
+    unsubmitted: u32,
+    /// The fds added since the last clear().
+    wanted: Vec<RawFd>,
+    /// The fds which have a poll armed in the kernel, with the generation of that poll. The
+    /// generation tells apart completions of a poll which has since been forgotten.
+    armed: HashMap<RawFd, u32>,
+    next_generation: u32,
+    /// The fds found readable by the last check_readable().
+    readable: Vec<RawFd>,
+}
+
+impl IoUringReadableSet {
+    /// Set up a ring. \return None if io_uring is unavailable or too old.
+    pub fn new() -> Option<Self> {
+        let mut params = IoUringParams::default();
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                RING_ENTRIES,
+                &mut params as *mut IoUringParams,
+            )
+        } as c_int;
+        if fd < 0 {
+            return None;
+        }
+        // The kernel makes the ring close-on-exec.
+        let ring_fd = AutoCloseFd::new_tracked(fd, L!("fd monitor io_uring"));
+        if params.features & IORING_FEAT_EXT_ARG == 0 {
+            return None;
+        }
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize
+            + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+        let sq_ring = Mapping::new(fd, sq_len, IORING_OFF_SQ_RING)?;
+        let cq_ring = Mapping::new(fd, cq_len, IORING_OFF_CQ_RING)?;
+        let sqes = Mapping::new(fd, sqes_len, IORING_OFF_SQES)?;
+        Some(IoUringReadableSet {
+            ring_fd,
+            params,
+            sq_ring,
+            cq_ring,
+            sqes,
+            unsubmitted: 0,
+            wanted: Vec::new(),
+            armed: HashMap::new(),
+            next_generation: 0,
+            readable: Vec::new(),
+        })
+    }
+
+    /// Reset back to an empty set. Polls stay armed, in case the same fds are added again.
+    pub fn clear(&mut self) {
+        self.wanted.clear();
+        self.readable.clear();
+    }
+
+    /// Add an fd to the set. The fd is ignored if negative.
+    pub fn add(&mut self, fd: RawFd) {
+        if fd >= 0 {
+            self.wanted.push(fd);
+        }
+    }
+
+    /// Returns `true` if the given `fd` was found readable (or HUP'd) by the last
+    /// check_readable().
+    pub fn test(&self, fd: RawFd) -> bool {
+        fd >= 0 && self.readable.contains(&fd)
+    }
+
+    /// Forget the poll armed for \p fd, which has been closed. Its number may be reused by a new
+    /// fd, which needs a poll of its own.
+    pub fn forget(&mut self, fd: RawFd) {
+        if let Some(generation) = self.armed.remove(&fd) {
+            self.queue_poll_remove(fd, generation);
+        }
+    }
+
+    /// Wait until an fd in the set is readable, or the timeout passes. The timeout may be
+    /// [`FdReadableSet::kNoTimeout`]. \return the number of readable fds, or -1 on error, with
+    /// errno set.
+    pub fn check_readable(&mut self, timeout_usec: u64) -> c_int {
+        self.wanted.sort_unstable();
+        self.wanted.dedup();
+
+        // Disarm polls for fds which are no longer wanted, and arm them for new ones.
+        let unwanted: Vec<(RawFd, u32)> = self
+            .armed
+            .iter()
+            .filter(|(fd, _)| self.wanted.binary_search(fd).is_err())
+            .map(|(&fd, &generation)| (fd, generation))
+            .collect();
+        for (fd, generation) in unwanted {
+            self.armed.remove(&fd);
+            self.queue_poll_remove(fd, generation);
+        }
+        for i in 0..self.wanted.len() {
+            let fd = self.wanted[i];
+            if !self.armed.contains_key(&fd) {
+                let generation = self.next_generation;
+                self.next_generation = self.next_generation.wrapping_add(1);
+                self.armed.insert(fd, generation);
+                self.queue_poll_add(fd, generation);
+            }
+        }
+
+        // Submit and wait in one go.
+        let ts = KernelTimespec {
+            tv_sec: (timeout_usec / kUsecPerSec) as i64,
+            tv_nsec: ((timeout_usec % kUsecPerSec) * 1000) as i64,
+        };
+        let arg = GeteventsArg {
+            sigmask: 0,
+            sigmask_sz: 0,
+            pad: 0,
+            ts: if timeout_usec == FdReadableSet::kNoTimeout {
+                0
+            } else {
+                &ts as *const KernelTimespec as u64
+            },
+        };
+        let ret = self.enter(
+            1,
+            IORING_ENTER_GETEVENTS | IORING_ENTER_EXT_ARG,
+            &arg as *const GeteventsArg as *const libc::c_void,
+            std::mem::size_of::<GeteventsArg>(),
+        );
+        if ret < 0 && errno::errno().0 != libc::ETIME {
+            return -1;
+        }
+
+        self.reap_completions();
+        self.readable.len() as c_int
+    }
+
+    /// Call io_uring_enter, submitting everything queued.
+    /// \return the result of the syscall.
+    fn enter(
+        &mut self,
+        min_complete: u32,
+        flags: u32,
+        arg: *const libc::c_void,
+        argsz: usize,
+    ) -> c_int {
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_enter,
+                self.ring_fd.fd(),
+                self.unsubmitted,
+                min_complete,
+                flags,
+                arg,
+                argsz,
+            )
+        } as c_int;
+        if ret >= 0 {
+            self.unsubmitted -= std::cmp::min(ret as u32, self.unsubmitted);
+        }
+        ret
+    }
+
+    /// Process the completion queue, noting which armed polls have fired.
+    fn reap_completions(&mut self) {
+        let cq_off = self.params.cq_off;
+        let head_atomic = self.cq_ring.atomic(cq_off.head);
+        let tail = self.cq_ring.atomic(cq_off.tail).load(Ordering::Acquire);
+        let mask = unsafe { *self.cq_ring.at::<u32>(cq_off.ring_mask) };
+        let mut head = head_atomic.load(Ordering::Relaxed);
+        while head != tail {
+            let cqe = unsafe {
+                &*self
+                    .cq_ring
+                    .at::<Cqe>(cq_off.cqes)
+                    .add((head & mask) as usize)
+            };
+            head = head.wrapping_add(1);
+            if cqe.user_data == REMOVAL_USER_DATA {
+                continue;
+            }
+            let fd = (cqe.user_data & 0xffff_ffff) as RawFd;
+            let generation = (cqe.user_data >> 32) as u32;
+            if self.armed.get(&fd) != Some(&generation) {
+                // A completion for a poll we have disarmed.
+                continue;
+            }
+            // The poll fired, so it is no longer armed. A negative result is an error like EBADF,
+            // which is reported as readable so the owner of the fd notices.
+            self.armed.remove(&fd);
+            if cqe.res != 0 && self.wanted.binary_search(&fd).is_ok() {
+                self.readable.push(fd);
+            }
+        }
+        head_atomic.store(head, Ordering::Release);
+    }
+
+    fn queue_poll_add(&mut self, fd: RawFd, generation: u32) {
+        self.queue(Sqe {
+            opcode: IORING_OP_POLL_ADD,
+            fd,
+            op_flags: libc::POLLIN as u32,
+            user_data: poll_user_data(fd, generation),
+            ..Default::default()
+        });
+    }
+
+    fn queue_poll_remove(&mut self, fd: RawFd, generation: u32) {
+        self.queue(Sqe {
+            opcode: IORING_OP_POLL_REMOVE,
+            fd: -1,
+            addr: poll_user_data(fd, generation),
+            user_data: REMOVAL_USER_DATA,
+            ..Default::default()
+        });
+    }
+
+    /// Put an entry in the submission ring, submitting what is queued if it is full.
+    fn queue(&mut self, sqe: Sqe) {
+        let sq_off = self.params.sq_off;
+        let mask = unsafe { *self.sq_ring.at::<u32>(sq_off.ring_mask) };
+        let tail = self.sq_ring.atomic(sq_off.tail).load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.sq_ring.atomic(sq_off.head).load(Ordering::Acquire))
+            >= self.params.sq_entries
+        {
+            self.enter(0, 0, std::ptr::null(), 0);
+        }
+        let index = tail & mask;
+        unsafe {
+            self.sqes.at::<Sqe>(0).add(index as usize).write(sqe);
+            self.sq_ring
+                .at::<u32>(sq_off.array)
+                .add(index as usize)
+                .write(index);
+        }
+        self.sq_ring
+            .atomic(sq_off.tail)
+            .store(tail.wrapping_add(1), Ordering::Release);
+        self.unsubmitted += 1;
+    }
+}
+
+/// \return the user data identifying a poll for \p fd, armed as \p generation.
+fn poll_user_data(fd: RawFd, generation: u32) -> u64 {
+    (u64::from(generation) << 32) | u64::from(fd as u32)
+}
@@ -0,0 +1,201 @@
+//! A writer for the JSON output of builtins, like `set --json` or `functions --json`.
+//!
+//! Output is compact, with no whitespace between tokens, so builtins which print one document per
+//! line (like `jobs --json`) can use it too. Callers are responsible for nesting values correctly;
+//! the writer only takes care of quoting and of the separators between values.
+
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::{AsWstr, WCharToFFI};
+use cxx::{CxxWString, UniquePtr};
+
+#[cxx::bridge]
+mod json_ffi {
+    extern "Rust" {
+        type JsonWriter;
+
+        fn new_json_writer() -> Box<JsonWriter>;
+        fn begin_object(self: &mut JsonWriter);
+        fn end_object(self: &mut JsonWriter);
+        fn begin_array(self: &mut JsonWriter);
+        fn end_array(self: &mut JsonWriter);
+        #[cxx_name = "key"]
+        fn key_ffi(self: &mut JsonWriter, key: &CxxWString);
+        #[cxx_name = "string"]
+        fn string_ffi(self: &mut JsonWriter, value: &CxxWString);
+        fn integer(self: &mut JsonWriter, value: i64);
+        fn decimal(self: &mut JsonWriter, value: f64, precision: usize);
+        fn boolean(self: &mut JsonWriter, value: bool);
+        fn null(self: &mut JsonWriter);
+        #[cxx_name = "contents"]
+        fn contents_ffi(self: &JsonWriter) -> UniquePtr<CxxWString>;
+    }
+}
+
+/// Builds a JSON document.
+#[derive(Default)]
+pub struct JsonWriter {
+    out: WString,
+    /// For each open object or array, whether it has a value already.
+    has_values: Vec<bool>,
+    /// Whether we just wrote a key, so the next value needs no separator.
+    after_key: bool,
+}
+
+fn new_json_writer() -> Box<JsonWriter> {
+    Box::default()
+}
+
+impl JsonWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the separator needed before a value, if any.
+    fn separate(&mut self) {
+        if std::mem::take(&mut self.after_key) {
+            return;
+        }
+        if let Some(has_values) = self.has_values.last_mut() {
+            if *has_values {
+                self.out.push(',');
+            }
+            *has_values = true;
+        }
+    }
+
+    pub fn begin_object(&mut self) {
+        self.separate();
+        self.out.push('{');
+        self.has_values.push(false);
+    }
+
+    pub fn end_object(&mut self) {
+        self.has_values.pop();
+        self.out.push('}');
+    }
+
+    pub fn begin_array(&mut self) {
+        self.separate();
+        self.out.push('[');
+        self.has_values.push(false);
+    }
+
+    pub fn end_array(&mut self) {
+        self.has_values.pop();
+        self.out.push(']');
+    }
+
+    /// Write the key of an object member. The value must follow.
+    pub fn key(&mut self, key: &wstr) {
+        self.separate();
+        json_quote_into(&mut self.out, key);
+        self.out.push(':');
+        self.after_key = true;
+    }
+
+    pub fn string(&mut self, value: &wstr) {
+        self.separate();
+        json_quote_into(&mut self.out, value);
+    }
+
+    /// Write an array of strings.
+    pub fn strings<S: AsRef<wstr>>(&mut self, values: &[S]) {
+        self.begin_array();
+        for value in values {
+            self.string(value.as_ref());
+        }
+        self.end_array();
+    }
+
+    pub fn integer(&mut self, value: i64) {
+        self.separate();
+        self.out.push_str(&value.to_string());
+    }
+
+    /// Write a number with \p precision digits after the decimal point. JSON has no infinities or
+    /// NaN, so those are written as null.
+    pub fn decimal(&mut self, value: f64, precision: usize) {
+        if !value.is_finite() {
+            return self.null();
+        }
+        self.separate();
+        self.out.push_str(&format!("{:.*}", precision, value));
+    }
+
+    pub fn boolean(&mut self, value: bool) {
+        self.separate();
+        let literal = if value { L!("true") } else { L!("false") };
+        self.out.push_utfstr(literal);
+    }
+
+    pub fn null(&mut self) {
+        self.separate();
+        self.out.push_utfstr(L!("null"));
+    }
+
+    /// \return the document so far.
+    pub fn contents(&self) -> &wstr {
+        &self.out
+    }
+
+    fn key_ffi(&mut self, key: &CxxWString) {
+        self.key(key.as_wstr());
+    }
+
+    fn string_ffi(&mut self, value: &CxxWString) {
+        self.string(value.as_wstr());
+    }
+
+    fn contents_ffi(&self) -> UniquePtr<CxxWString> {
+        self.out.to_ffi()
+    }
+}
+
+/// Append \p s to \p out as a JSON string literal, including the surrounding quotes.
+pub fn json_quote_into(out: &mut WString, s: &wstr) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c < '\x20' || c == '\x7f' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[test]
+fn test_json_writer() {
+    let mut w = JsonWriter::new();
+    w.begin_array();
+    w.begin_object();
+    w.key(L!("name"));
+    w.string(L!("a \"b\"\n\x01"));
+    w.key(L!("values"));
+    w.strings(&[L!("1"), L!("2")]);
+    w.key(L!("empty"));
+    w.begin_array();
+    w.end_array();
+    w.key(L!("n"));
+    w.integer(-3);
+    w.key(L!("f"));
+    w.decimal(1.5, 3);
+    w.key(L!("inf"));
+    w.decimal(f64::INFINITY, 3);
+    w.key(L!("ok"));
+    w.boolean(true);
+    w.end_object();
+    w.begin_object();
+    w.end_object();
+    w.end_array();
+    assert_eq!(
+        w.contents(),
+        L!(
+            r#"[{"name":"a \"b\"\n\u0001","values":["1","2"],"empty":[],"n":-3,"f":1.500,"inf":null,"ok":true},{}]"#
+        )
+    );
+}
@@ -0,0 +1,139 @@
+//! Decoding keys reported with the kitty keyboard protocol.
+//!
+//! When the terminal supports it, fish turns on the protocol's "disambiguate escape codes"
+//! enhancement. The terminal then reports keys that are ambiguous in the legacy encoding, like
+//! ctrl-i (which is also Tab) or shift-enter (which is also Enter), as `CSI code;modifiers u`.
+//!
+//! Most of these keys have a legacy encoding that existing bindings use, e.g. `\ca` for ctrl-a
+//! or `\ea` for alt-a. Those are translated back, so the bindings keep working. The keys that
+//! have no legacy encoding of their own are put into a canonical form, which is what `bind -k`
+//! names like `ctrl-i` and `shift-enter` stand for.
+
+#[cxx::bridge]
+mod kitty_keys_ffi {
+    extern "Rust" {
+        fn kitty_key_sequence(seq: &[u8]) -> Vec<u8>;
+    }
+}
+
+const SHIFT: u32 = 1;
+const ALT: u32 = 2;
+const CTRL: u32 = 4;
+/// Caps Lock and Num Lock, which don't change which key was pressed.
+const LOCKS: u32 = 64 | 128;
+
+/// Translates a `CSI u` key sequence, e.g. `\e[97;5u` for ctrl-a, to what fish binds to. This is
+/// the legacy encoding if there is an unambiguous one (here `\x01`), or else the canonical form
+/// `\e[code;modifiersu`. Returns `None` if `seq` isn't a key reported by the protocol.
+pub fn translate_key(seq: &[u8]) -> Option<Vec<u8>> {
+    let body = seq.strip_prefix(b"\x1B[")?.strip_suffix(b"u")?;
+    let mut fields = body.split(|&c| c == b';');
+    // The key code may be followed by the shifted and base layout keys, which we don't need.
+    let code = parse_number(fields.next()?.split(|&c| c == b':').next()?)?;
+    // The modifiers may be followed by the event type, which is always a press here since we
+    // don't ask for repeats and releases. Any text that follows is ignored as well.
+    let mods = match fields.next() {
+        Some(field) => parse_number(field.split(|&c| c == b':').next()?)?,
+        None => 1,
+    };
+    let mods = mods.checked_sub(1)? & !LOCKS;
+
+    let legacy = if mods & !(SHIFT | ALT | CTRL) != 0 {
+        // Super, hyper and meta have no legacy encoding.
+        None
+    } else {
+        legacy_key(code, mods & !ALT).map(|mut legacy| {
+            if mods & ALT != 0 {
+                legacy.insert(0, 0x1B);
+            }
+            legacy
+        })
+    };
+    Some(legacy.unwrap_or_else(|| canonical_key(code, mods)))
+}
+
+/// Returns the legacy encoding of a key with the shift and ctrl `mods`, if it is unambiguous.
+fn legacy_key(code: u32, mods: u32) -> Option<Vec<u8>> {
+    let c = char::from_u32(code)?;
+    let encoded = match mods {
+        0 => c,
+        SHIFT if c.is_ascii_lowercase() => c.to_ascii_uppercase(),
+        SHIFT if c == '\t' => return Some(b"\x1B[Z".to_vec()),
+        // ctrl-i, ctrl-m and ctrl-[ are the very keys the protocol tells apart from Tab, Enter
+        // and Escape.
+        CTRL if c == 'i' || c == 'm' || c == '[' => return None,
+        CTRL if c.is_ascii_lowercase() || ('\\'..='_').contains(&c) => {
+            char::from(c as u8 & 0x1F)
+        }
+        CTRL if c == ' ' || c == '@' => '\0',
+        _ => return None,
+    };
+    let mut buf = [0; 4];
+    Some(encoded.encode_utf8(&mut buf).as_bytes().to_vec())
+}
+
+/// Returns the canonical form of a key: the code, followed by the modifiers unless there are none.
+fn canonical_key(code: u32, mods: u32) -> Vec<u8> {
+    if mods == 0 {
+        format!("\x1B[{}u", code).into_bytes()
+    } else {
+        format!("\x1B[{};{}u", code, mods + 1).into_bytes()
+    }
+}
+
+fn parse_number(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Like [`translate_key()`], but returns an empty sequence if `seq` isn't a key.
+fn kitty_key_sequence(seq: &[u8]) -> Vec<u8> {
+    translate_key(seq).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::translate_key;
+
+    #[test]
+    fn test_translate_key() {
+        let tests: &[(&[u8], &[u8])] = &[
+            // Keys with a legacy encoding.
+            (b"\x1B[97;5u", b"\x01"),
+            (b"\x1B[97;3u", b"\x1Ba"),
+            (b"\x1B[97;4u", b"\x1BA"),
+            (b"\x1B[97;7u", b"\x1B\x01"),
+            (b"\x1B[32;5u", b"\0"),
+            (b"\x1B[27u", b"\x1B"),
+            (b"\x1B[13;3u", b"\x1B\r"),
+            (b"\x1B[9;2u", b"\x1B[Z"),
+            (b"\x1B[228;3u", "\x1Bä".as_bytes()),
+            // Caps Lock and Num Lock are ignored, and so are the event type and the text.
+            (b"\x1B[97;69u", b"\x01"),
+            (b"\x1B[97:65;3:1;97u", b"\x1Ba"),
+            // Keys without one.
+            (b"\x1B[105;5u", b"\x1B[105;5u"),
+            (b"\x1B[109;5u", b"\x1B[109;5u"),
+            (b"\x1B[91;5u", b"\x1B[91;5u"),
+            (b"\x1B[13;2u", b"\x1B[13;2u"),
+            (b"\x1B[13;5u", b"\x1B[13;5u"),
+            (b"\x1B[97;6u", b"\x1B[97;6u"),
+            (b"\x1B[97;9u", b"\x1B[97;9u"),
+            (b"\x1B[105;133u", b"\x1B[105;5u"),
+        ];
+        for (seq, expected) in tests {
+            assert_eq!(
+                translate_key(seq).as_deref(),
+                Some(*expected),
+                "{:?}",
+                String::from_utf8_lossy(seq)
+            );
+        }
+
+        for seq in [&b"\x1B[A"[..], b"\x1B[u", b"\x1B[;5u", b"\x1B[97;0u", b"\x1B[?1u"] {
+            assert_eq!(translate_key(seq), None, "{:?}", String::from_utf8_lossy(seq));
+        }
+    }
+}
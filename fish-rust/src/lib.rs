@@ -15,13 +15,24 @@ mod common;
 
 mod abbrs;
 mod ast;
+mod ast_cache;
+mod bidi;
 mod builtins;
 mod color;
 mod compat;
+mod crash_handler;
 mod curses;
+pub mod embed;
 mod env;
 mod env_dispatch;
 mod event;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+mod event_loop;
 mod expand;
 mod fallback;
 mod fd_monitor;
@@ -40,9 +51,16 @@ mod flog;
 mod future_feature_flags;
 mod global_safety;
 mod highlight;
+mod history_crypt;
+mod intern;
 mod io;
+#[cfg(target_os = "linux")]
+mod io_uring;
 mod job_group;
+mod json;
+mod kitty_keys;
 mod locale;
+mod mangen;
 mod nix;
 mod null_terminated_array;
 mod operation_context;
@@ -52,12 +70,16 @@ mod parse_tree;
 mod parse_util;
 mod parser_keywords;
 mod path;
+mod plugins;
 mod re;
 mod reader;
 mod redirection;
 mod signal;
 mod smoke;
+mod terminal_probe;
+mod terminfo;
 mod termsize;
+mod text_object;
 mod threads;
 mod timer;
 mod tinyexpr;
@@ -66,6 +88,7 @@ mod topic_monitor;
 mod trace;
 mod util;
 mod wait_handle;
+mod watchdog;
 mod wchar;
 mod wchar_ext;
 mod wchar_ffi;
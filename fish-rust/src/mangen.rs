@@ -0,0 +1,761 @@
+//! Generating completions from man pages, for the fish_update_completions builtin.
+//!
+//! Man pages are written in roff, and different tools lay out the options in different ways. Each
+//! parser below understands one common layout. They are tried in turn until one finds options, and
+//! the last one strips all formatting and takes the lines that start with a dash to be options.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Commands whose man pages aren't worth parsing, because the completions that ship with fish are
+/// better or the options confuse the parsers.
+const IGNORED_COMMANDS: &[&str] = &[
+    "cc", "g++", "gcc", "c++", "cpp", "emacs", "gprof", "wget", "ld", "awk",
+];
+
+/// Prefixes of commands that have a man page for each of their many subcommands, which fish
+/// mostly completes already.
+const IGNORED_PREFIXES: &[&str] = &[
+    "bundle-", "cargo-", "ffmpeg-", "flatpak-", "git-", "npm-", "openssl-", "ostree-", "perf-",
+    "perl", "pip-", "zsh",
+];
+
+/// The longest description to give an option, in characters.
+const MAX_DESCRIPTION_WIDTH: usize = 78;
+
+/// Returns the command documented by the man page at `path`, like `mkfs.xfs` for
+/// `mkfs.xfs.8.gz`, or None if it doesn't look like a man page.
+pub fn command_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let name = match name.rsplit_once('.') {
+        Some((stem, "gz" | "bz2" | "xz" | "lzma" | "zst")) => stem,
+        _ => name,
+    };
+    let (cmd, section) = name.rsplit_once('.')?;
+    let is_section = matches!(section.as_bytes(), [b'1'..=b'9']);
+    (is_section && !cmd.is_empty()).then(|| cmd.to_owned())
+}
+
+/// Reads the man page at `path`, decompressing it if needed. Pages that aren't UTF-8 are taken to
+/// be Latin-1.
+pub fn read_man_page(path: &Path) -> io::Result<String> {
+    let decompressor = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Some("gzip"),
+        Some("bz2") => Some("bzip2"),
+        Some("xz" | "lzma") => Some("xz"),
+        Some("zst") => Some("zstd"),
+        _ => None,
+    };
+    let bytes = match decompressor {
+        None => std::fs::read(path)?,
+        Some(program) => {
+            let output = Command::new(program)
+                .arg("-dc")
+                .arg(path)
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()?;
+            if !output.status.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} could not decompress it", program),
+                ));
+            }
+            output.stdout
+        }
+    };
+    Ok(String::from_utf8(bytes)
+        .unwrap_or_else(|err| err.into_bytes().into_iter().map(char::from).collect()))
+}
+
+/// Returns whether `contents` is a completions file written by [`generate_completions()`], which
+/// may be deleted or replaced.
+pub fn is_autogenerated(contents: &str) -> bool {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .take_while(|line| line.starts_with('#'))
+        .any(|line| line.contains("Autogenerated"))
+}
+
+/// Parses the man page `page` for `cmd`, and returns the contents of its completions file, or None
+/// if no options were found. `source` is where the page came from, which the file mentions.
+pub fn generate_completions(
+    cmd: &str,
+    page: &str,
+    source: &str,
+    deroff_only: bool,
+) -> Option<String> {
+    if IGNORED_COMMANDS.contains(&cmd) || IGNORED_PREFIXES.iter().any(|p| cmd.starts_with(p)) {
+        return None;
+    }
+    // Skip the many links to BUILTIN(1).
+    if page.contains("BUILTIN 1") || page.contains("builtin.1") {
+        return None;
+    }
+
+    let parsers: &[Parser] = if deroff_only {
+        &[parse_deroffed]
+    } else {
+        &[
+            parse_scdoc,
+            parse_docbook,
+            parse_options_section,
+            parse_function_letters,
+            parse_description_section,
+            parse_mdoc,
+            parse_deroffed,
+        ]
+    };
+    for parser in parsers {
+        let mut completions = Completions::new(cmd);
+        parser(page, &mut completions);
+        if completions.lines.is_empty() {
+            continue;
+        }
+        // The word "Autogenerated" tells is_autogenerated() that the file may be replaced.
+        let mut result = format!("# {}\n# Autogenerated from man page {}\n", cmd, source);
+        for line in completions.lines {
+            result.push_str(&line);
+            result.push('\n');
+        }
+        result.push('\n');
+        return Some(result);
+    }
+    None
+}
+
+/// A parser for one layout of man pages, which adds the options it finds.
+type Parser = fn(&str, &mut Completions);
+
+/// The completions found in a man page.
+struct Completions<'a> {
+    cmd: &'a str,
+    /// The `complete` commands.
+    lines: Vec<String>,
+    /// The options that already have a completion, like `-s a` or `-l all`.
+    seen: HashSet<String>,
+}
+
+impl<'a> Completions<'a> {
+    fn new(cmd: &'a str) -> Self {
+        Completions {
+            cmd,
+            lines: vec![],
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Adds a completion for the options named in `options`, like `-a, --all`, with the
+    /// description `description`.
+    fn add(&mut self, options: &str, description: &str) {
+        let mut args = vec![];
+        for word in options.split([' ', ',', '"', '=', '|']) {
+            let word = remove_brackets(word);
+            let option = word.trim_matches(|c: char| " \t\r\n[](){}.,:!".contains(c));
+            if option == "-" || option == "--" || option.contains(['{', '}', '(', ')']) {
+                continue;
+            }
+            let arg = if let Some(long) = option.strip_prefix("--") {
+                format!("-l {}", escape(long))
+            } else if let Some(short) = option.strip_prefix('-') {
+                let switch = if short.chars().count() == 1 {
+                    "-s"
+                } else {
+                    "-o"
+                };
+                format!("{} {}", switch, escape(short))
+            } else {
+                continue;
+            };
+            if self.seen.insert(arg.clone()) {
+                args.push(arg);
+            }
+        }
+        if args.is_empty() {
+            return;
+        }
+
+        let mut line = format!("complete -c {} {}", escape(self.cmd), args.join(" "));
+        let description = shorten_description(description);
+        if !description.is_empty() {
+            line.push_str(" -d ");
+            line.push_str(&escape(&description));
+        }
+        self.lines.push(line);
+    }
+
+    /// Adds a completion for an option whose name and description were taken from a page,
+    /// unless the name has no dash.
+    fn add_parsed(&mut self, name: &str, description: &str) {
+        let name = name.trim();
+        if !name.contains('-') {
+            return;
+        }
+        let name = unquote(name);
+        self.add(name, &description.trim().replace('\n', " "));
+    }
+}
+
+/// Removes everything from the first `[` up to the last `]`, like an optional argument.
+fn remove_brackets(word: &str) -> String {
+    match (word.find('['), word.rfind(']')) {
+        (Some(open), Some(close)) if open < close => {
+            format!("{}{}", &word[..open], &word[close + 1..])
+        }
+        _ => word.to_owned(),
+    }
+}
+
+/// Removes the double quotes, or the backquote and quote, around `name`.
+fn unquote(name: &str) -> &str {
+    for (open, close) in [('"', '"'), ('`', '\'')] {
+        if name.len() >= 2 && name.starts_with(open) && name.ends_with(close) {
+            return &name[1..name.len() - 1];
+        }
+    }
+    name
+}
+
+/// Quotes `s` for fish, unless it has only characters that don't need that.
+fn escape(s: &str) -> String {
+    if s.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_+-|/:=@~".contains(c))
+    {
+        return s.to_owned();
+    }
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Shortens a description to as many whole sentences as fit, or else cuts the first one off.
+fn shorten_description(description: &str) -> String {
+    let description = description.replace("\\'", "'").replace("\\.", ".");
+    let mut sentences = description.split('.').filter(|s| !s.trim().is_empty());
+    let mut result = format!("{}.", sentences.next().unwrap_or(""));
+    for sentence in sentences {
+        let longer = format!("{} {}.", result, sentence.trim_start());
+        if longer.chars().count() > MAX_DESCRIPTION_WIDTH {
+            break;
+        }
+        result = longer;
+    }
+    let result = result.trim_matches('.');
+    if result.chars().count() <= MAX_DESCRIPTION_WIDTH {
+        return result.to_owned();
+    }
+    let mut cut: String = result.chars().take(MAX_DESCRIPTION_WIDTH - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// Removes the most common font changes, macros and escapes from the text of an option.
+fn remove_groff_formatting(data: &str) -> String {
+    let mut data = data.to_owned();
+    for code in ["\\fI", "\\fP", "\\f1", "\\fB", "\\fR", "\\e"] {
+        data = data.replace(code, "");
+    }
+    // Paragraph spacing, like `.PD 0`.
+    while let Some(start) = data.find(".PD ") {
+        let digits = data[start + 4..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if digits == 0 {
+            break;
+        }
+        data.replace_range(start..start + 4 + digits, "");
+    }
+    for (code, replacement) in [
+        (".BI", ""),
+        (".BR", ""),
+        ("0.5i", ""),
+        (".rb", ""),
+        ("\\^", ""),
+        ("{ ", ""),
+        (" }", ""),
+        ("\\ ", ""),
+        ("\\-", "-"),
+        ("\\&", ""),
+        (".B", ""),
+        (".I", ""),
+        ("\x0C", ""),
+        ("\\(cq", "'"),
+    ] {
+        data = data.replace(code, replacement);
+    }
+    data
+}
+
+/// Returns the text after `header` up to the next section, if the page has that section.
+fn section<'a>(page: &'a str, header: &str) -> Option<&'a str> {
+    let rest = &page[page.find(header)? + header.len()..];
+    Some(&rest[..rest.find(".SH").unwrap_or(rest.len())])
+}
+
+/// Returns the position and length of whichever of `needles` comes first in `text`.
+fn find_first(text: &str, needles: &[&str]) -> Option<(usize, usize)> {
+    needles
+        .iter()
+        .filter_map(|needle| text.find(needle).map(|pos| (pos, needle.len())))
+        .min()
+}
+
+/// Returns the text between each of the `starts` macros and the next of the `ends` macros, or the
+/// end of `text`. The end can also be the start of the next block, like the `.TP` between two
+/// options.
+fn blocks<'a>(mut text: &'a str, starts: &[&str], ends: &[&str]) -> Vec<&'a str> {
+    let mut result = vec![];
+    while let Some((start, len)) = find_first(text, starts) {
+        let body = &text[start + len..];
+        let end = find_first(body, ends).map_or(body.len(), |(end, _)| end);
+        result.push(&body[..end]);
+        text = &body[end..];
+    }
+    result
+}
+
+/// Removes the indentation argument of a `.TP` or `.IP` from the start of its block, like the
+/// ` 4` in `.TP 4` or the ` 0.5i` in `.IP 0.5i`.
+fn strip_indent(block: &str) -> &str {
+    let Some(rest) = block.strip_prefix(' ') else {
+        return block;
+    };
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 {
+        return block;
+    }
+    let mut rest = &rest[digits..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        rest = &fraction[digits..];
+    }
+    rest.strip_prefix('i').unwrap_or(rest)
+}
+
+/// Adds the options in blocks whose first line names the option and the rest describes it.
+fn parse_tagged(section: &str, starts: &[&str], ends: &[&str], out: &mut Completions) {
+    for block in blocks(section, starts, ends) {
+        let data = remove_groff_formatting(strip_indent(block));
+        if let Some((name, description)) = data.trim().split_once('\n') {
+            out.add_parsed(name, description);
+        }
+    }
+}
+
+/// Pages generated by scdoc, where each option ends with `.RE`.
+fn parse_scdoc(page: &str, out: &mut Completions) {
+    if !page.contains(".\\\" Generated by scdoc") {
+        return;
+    }
+    let Some(start) = page.find(".SH OPTIONS") else {
+        return;
+    };
+    let rest = &page[start + ".SH OPTIONS".len()..];
+    let Some(end) = rest.find(".SH") else {
+        return;
+    };
+    let mut options: Vec<&str> = rest[..end].split(".RE").collect();
+    // What follows the last option isn't one.
+    options.pop();
+    for option in options {
+        let option = remove_groff_formatting(option);
+        let lines: Vec<&str> = option
+            .split('\n')
+            .filter(|line| !matches!(*line, "" | ".P" | ".RS 4"))
+            .collect();
+        // The name and the description; later paragraphs are left out.
+        if let [name, description, ..] = lines[..] {
+            out.add_parsed(name, description);
+        }
+    }
+}
+
+/// Pages generated by DocBook, where each option is a `.PP` followed by its description indented
+/// with `.RS 4`, under a quoted `.SH "OPTIONS"`.
+fn parse_docbook(page: &str, out: &mut Completions) {
+    let Some(options) = section(page, ".SH \"OPTIONS\"") else {
+        return;
+    };
+    let paragraphs = blocks(options, &[".PP"], &[".RE"]);
+    if paragraphs.is_empty() {
+        // Some of these pages use tagged or indented paragraphs instead.
+        parse_tagged(options, &[".TP"], &[".TP"], out);
+        if out.lines.is_empty() {
+            parse_indented_paragraphs(options, out);
+        }
+        return;
+    }
+    for paragraph in paragraphs {
+        let paragraph = match paragraph.rfind(".PP") {
+            Some(pos) => &paragraph[pos + 3..],
+            None => paragraph,
+        };
+        let data = remove_groff_formatting(paragraph);
+        if let Some((name, description)) = data.split_once(".RS 4") {
+            out.add_parsed(name, description);
+        }
+    }
+}
+
+/// Options in `.IP` paragraphs, as written by pod2man, whose tags may end in an indentation.
+fn parse_indented_paragraphs(section: &str, out: &mut Completions) {
+    // Drop the index entries.
+    let section: String = section
+        .lines()
+        .map(|line| &line[..line.find(".IX").unwrap_or(line.len())])
+        .collect::<Vec<_>>()
+        .join("\n");
+    for block in blocks(&section, &[".IP "], &[".IP"]) {
+        let data = remove_groff_formatting(block);
+        if let Some((name, description)) = data.trim().split_once('\n') {
+            if !description.trim().is_empty() {
+                let name = name.trim().trim_end_matches(|c: char| c.is_ascii_digit());
+                out.add_parsed(name, description);
+            }
+        }
+    }
+}
+
+/// The most common layout: tagged or indented paragraphs under `.SH OPTIONS`.
+fn parse_options_section(page: &str, out: &mut Completions) {
+    if let Some(options) = section(page, ".SH OPTIONS") {
+        parse_tagged(options, &[".TP", ".IP"], &[".TP", ".IP", ".UN", ".SH"], out);
+    }
+}
+
+/// Pages like tar's, which list the operations under `.SH FUNCTION LETTERS`.
+fn parse_function_letters(page: &str, out: &mut Completions) {
+    if let Some(letters) = section(page, ".SH FUNCTION LETTERS") {
+        parse_tagged(letters, &[".TP"], &[".TP"], out);
+    }
+}
+
+/// Pages that describe the options in tagged paragraphs in the description.
+fn parse_description_section(page: &str, out: &mut Completions) {
+    if let Some(description) = section(page, ".SH DESCRIPTION") {
+        parse_tagged(description, &[".TP"], &[".TP"], out);
+    }
+}
+
+/// Removes the mdoc macros from the start of `line`, like the `It Fl` in `.It Fl a`.
+fn trim_mdoc(line: &str) -> String {
+    let mut line = line.strip_prefix('.').unwrap_or(line);
+    loop {
+        let mut chars = line.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(upper), Some(lower), Some(space))
+                if upper.is_ascii_uppercase()
+                    && lower.is_ascii_lowercase()
+                    && space.is_whitespace() =>
+            {
+                line = &line[upper.len_utf8() + lower.len_utf8() + space.len_utf8()..];
+            }
+            _ => break,
+        }
+    }
+    // Lines like `.Ar projectname .` have a space before the punctuation.
+    for punctuation in [" ,", " ."] {
+        if let Some(start) = line.strip_suffix(punctuation) {
+            return format!("{}{}", start, &punctuation[1..]);
+        }
+    }
+    line.to_owned()
+}
+
+/// Pages written with the mdoc macros, as on the BSDs and macOS, where each option is an
+/// `.It Fl` item.
+fn parse_mdoc(page: &str, out: &mut Completions) {
+    let is_option = |line: &&str| line.starts_with(".It Fl");
+    let replace_escapes = |line: &str| {
+        line.replace(".Nm", out.cmd)
+            .replace("\\ ", " ")
+            .replace("\\& ", "")
+            .replace(".Pp", "")
+    };
+
+    let mut items = vec![];
+    let mut lines = page
+        .lines()
+        .skip_while(|line| {
+            !line.starts_with(".Sh DESCRIPTION") && !line.starts_with(".SH DESCRIPTION")
+        })
+        .peekable();
+    loop {
+        while lines.next_if(|line| !is_option(line)).is_some() {}
+        let Some(line) = lines.next() else {
+            break;
+        };
+        // Each `Fl` is a dash, so `.It Fl Fl all` is `--all`.
+        let mut dashes = 0;
+        let mut flags = line.get(4..).unwrap_or("");
+        while let Some(rest) = flags.strip_prefix("Fl ") {
+            dashes += 1;
+            flags = rest;
+        }
+
+        let line = trim_mdoc(&replace_escapes(line));
+        let Some(name) = line.split_whitespace().next() else {
+            continue;
+        };
+        let mut description = vec![];
+        while let Some(line) = lines.next_if(|line| !is_option(line)) {
+            let line = line.trim();
+            if line.starts_with(".\\\"") {
+                continue;
+            }
+            let line = if line.starts_with('.') {
+                trim_mdoc(&replace_escapes(line)).trim().to_owned()
+            } else {
+                line.to_owned()
+            };
+            if !line.is_empty() {
+                description.push(line);
+            }
+        }
+        if name == "-" {
+            continue;
+        }
+        let dashes = if name.chars().count() > 1 { dashes } else { 1 };
+        items.push((
+            format!("{}{}", "-".repeat(dashes), name),
+            description.join(" "),
+        ));
+    }
+    for (option, description) in items {
+        out.add(&option, &description);
+    }
+}
+
+/// Returns the text that a roff escape sequence at the start of `s` (after the backslash) stands
+/// for, and how many bytes it takes up, or None for a comment.
+fn unescape_one(s: &str) -> Option<(&'static str, usize)> {
+    // The name of an escape with an argument, like the `B` in `\fB`, the `cq` in `\(cq` or the
+    // `lq` in `\[lq]`.
+    let argument_len = |rest: &str| -> usize {
+        if let Some(name) = rest.strip_prefix('(') {
+            1 + name.chars().take(2).map(char::len_utf8).sum::<usize>()
+        } else if rest.starts_with('[') {
+            rest.find(']').map_or(rest.len(), |end| end + 1)
+        } else {
+            rest.chars().next().map_or(0, char::len_utf8)
+        }
+    };
+    let named = |name: &str| match name.trim_matches(['(', '[', ']']) {
+        "cq" | "aq" | "rs" => "'",
+        "lq" | "rq" | "dq" => "\"",
+        "hy" | "en" | "em" | "mi" => "-",
+        "bu" => "*",
+        _ => "",
+    };
+
+    let c = s.chars().next()?;
+    let rest = &s[c.len_utf8()..];
+    let escape = match c {
+        '"' | '#' => return None,
+        '-' => ("-", 1),
+        'e' | '\\' => ("\\", 1),
+        ' ' | '~' | '0' => (" ", 1),
+        '\'' => ("'", 1),
+        '`' => ("`", 1),
+        '.' => (".", 1),
+        '&' | '^' | '|' | '%' | ':' | ')' | 'c' | '/' | ',' => ("", 1),
+        '(' | '[' => {
+            let len = argument_len(s);
+            (named(&s[..len]), len)
+        }
+        'f' | '*' | 'n' | 'F' | 'm' | 'M' | 'g' | 'k' | 'V' | 'Y' => {
+            // Fonts, strings and registers, none of which we keep.
+            let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+            ("", s.len() - rest.len() + argument_len(rest))
+        }
+        's' => {
+            // Font sizes, like `\s-1`, `\s+2`, `\s0` or `\s(12`.
+            let sign = usize::from(rest.starts_with(['+', '-']));
+            let digits = rest[sign..].bytes().take_while(u8::is_ascii_digit).count();
+            if digits > 0 {
+                ("", 1 + sign + digits)
+            } else {
+                ("", 1 + sign + argument_len(&rest[sign..]))
+            }
+        }
+        _ => ("", c.len_utf8()),
+    };
+    Some(escape)
+}
+
+/// Replaces the roff escape sequences in `text` with the text they stand for, dropping the ones
+/// that only format it.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('\\') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let Some((replacement, len)) = unescape_one(rest) else {
+            return result;
+        };
+        result.push_str(replacement);
+        rest = &rest[len.min(rest.len())..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Splits the arguments of a macro, keeping quoted ones together.
+fn macro_arguments(args: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut chars = args.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let mut arg = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+                arg.push(c);
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        result.push(arg);
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+    result
+}
+
+/// Strips the roff formatting from `page`, leaving roughly the text that man shows. Paragraph
+/// macros become empty lines.
+fn deroff(page: &str) -> String {
+    let mut result = String::new();
+    for line in page.lines() {
+        let Some(request) = line.strip_prefix(['.', '\'']) else {
+            result.push_str(&unescape(line));
+            result.push('\n');
+            continue;
+        };
+        let request = request.trim_start();
+        let (name, args) = request
+            .split_once(char::is_whitespace)
+            .unwrap_or((request, ""));
+        let text = match name {
+            "SH" | "SS" | "B" | "I" | "SM" | "SB" => macro_arguments(args).join(" "),
+            // The alternating fonts, like `.BR -a ,`, which don't separate their arguments.
+            "BI" | "BR" | "IB" | "IR" | "RB" | "RI" => macro_arguments(args).concat(),
+            "IP" => {
+                result.push('\n');
+                macro_arguments(args).into_iter().next().unwrap_or_default()
+            }
+            "PP" | "P" | "LP" | "TP" | "sp" | "Pp" => {
+                result.push('\n');
+                continue;
+            }
+            _ => continue,
+        };
+        result.push_str(&unescape(&text));
+        result.push('\n');
+    }
+    result
+}
+
+/// Any page: with the formatting stripped, lines that start with a dash are taken to be options,
+/// described by the lines up to the next empty one.
+fn parse_deroffed(page: &str, out: &mut Completions) {
+    let text = deroff(page);
+    let mut lines = text
+        .lines()
+        .skip_while(|line| {
+            !["DESCRIPTION", "OPTIONS", "COMMAND OPTIONS"]
+                .iter()
+                .any(|heading| line.starts_with(heading))
+        })
+        .take_while(|line| !line.starts_with("BUGS"))
+        .peekable();
+    loop {
+        while lines.next_if(|line| !line.starts_with('-')).is_some() {}
+        let Some(options) = lines.next() else {
+            break;
+        };
+        let mut description = String::new();
+        while let Some(line) = lines.next_if(|line| !line.is_empty() && !line.starts_with('-')) {
+            if !description.is_empty() {
+                description.push(' ');
+            }
+            description.push_str(line);
+        }
+        out.add(options, &description);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command_name, generate_completions, is_autogenerated, shorten_description};
+    use std::path::Path;
+
+    #[test]
+    fn test_command_name() {
+        let name = |path: &str| command_name(Path::new(path));
+        assert_eq!(name("/usr/share/man/man1/ls.1.gz").as_deref(), Some("ls"));
+        assert_eq!(name("man8/mkfs.xfs.8").as_deref(), Some("mkfs.xfs"));
+        assert_eq!(name("man1/foo.1.bz2").as_deref(), Some("foo"));
+        assert_eq!(name("man1/README"), None);
+        assert_eq!(name("man1/foo.gz"), None);
+    }
+
+    #[test]
+    fn test_shorten_description() {
+        assert_eq!(shorten_description("List all files."), "List all files");
+        assert_eq!(
+            shorten_description("Short one. Another short one. "),
+            "Short one. Another short one"
+        );
+        let long = "a".repeat(100);
+        let shortened = shorten_description(&long);
+        assert_eq!(shortened.chars().count(), 78);
+        assert!(shortened.ends_with('…'));
+    }
+
+    #[test]
+    fn test_generate_completions() {
+        let page = r#".TH FOO 1
+.SH NAME
+foo \- do things
+.SH OPTIONS
+.TP
+\fB\-a\fR, \fB\-\-all\fR
+Show everything. Even the hidden things.
+.TP
+\fB\-\-color\fR[=\fIWHEN\fR]
+Use colors.
+.TP
+.B \-verbose
+Talk more.
+.SH SEE ALSO
+"#;
+        let completions = generate_completions("foo", page, "foo.1", false).unwrap();
+        assert_eq!(
+            completions,
+            "# foo\n# Autogenerated from man page foo.1\n\
+             complete -c foo -s a -l all -d 'Show everything. Even the hidden things'\n\
+             complete -c foo -l color -d 'Use colors'\n\
+             complete -c foo -o verbose -d 'Talk more'\n\n"
+        );
+        assert!(is_autogenerated(&completions));
+        assert!(!is_autogenerated("# foo\ncomplete -c foo -s a\n"));
+
+        let mdoc = ".Sh DESCRIPTION\n.It Fl a\nShow all.\n.It Fl Fl long\nBe long.\n";
+        let completions = generate_completions("bar", mdoc, "bar.1", false).unwrap();
+        assert!(completions.contains("complete -c bar -s a -d 'Show all'\n"));
+        assert!(completions.contains("complete -c bar -l long -d 'Be long'\n"));
+
+        assert_eq!(generate_completions("gcc", page, "gcc.1", false), None);
+        assert_eq!(
+            generate_completions("git-log", page, "git-log.1", false),
+            None
+        );
+    }
+}
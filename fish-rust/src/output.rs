@@ -1,4 +1,33 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::sync::Mutex;
+
 use bitflags::bitflags;
+use once_cell::sync::Lazy;
+
+use crate::color::Color24;
+use crate::common::{escape_string, wcs2string, write_loop, EscapeStringStyle};
+use crate::curses::{self, Param};
+use crate::env::{EnvStack, Environment};
+use crate::ffi::is_interactive_session;
+use crate::wchar::{wstr, L};
+
+#[cxx::bridge]
+mod output_ffi {
+    extern "Rust" {
+        fn report_cwd_ffi();
+        fn quantize_color_ffi(r: u8, g: u8, b: u8, palette_size: u16) -> u8;
+        fn term_underline_style_ffi(style: u8) -> Vec<u8>;
+        fn term_supports_underline_color() -> bool;
+        fn term_underline_color_ffi(rgb: u32) -> Vec<u8>;
+        fn term_strikethrough_ffi(enable: bool) -> Vec<u8>;
+        fn term_has_entry() -> bool;
+        fn term_get_string_ffi(name: &str, value: &mut Vec<u8>) -> bool;
+        fn term_get_number_ffi(name: &str) -> i32;
+        fn term_get_flag_ffi(name: &str) -> bool;
+        fn term_expand_ffi(cap: &[u8], params: &[i32]) -> Vec<u8>;
+    }
+}
 
 bitflags! {
     pub struct ColorSupport: u8 {
@@ -17,3 +46,286 @@ pub fn output_set_color_support(value: ColorSupport) {
         output_set_color_support(value.bits() as i32);
     }
 }
+
+/// A palette of indexed colors that 24-bit colors are reduced to for terminals without them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Palette {
+    /// The eight basic colors, for terminals whose `max_colors` is 8.
+    Ansi8,
+    /// The basic colors and their bright variants.
+    Ansi16,
+    /// The 6x6x6 color cube and the gray ramp of the xterm 256-color palette. The first 16
+    /// colors are left out, because they vary with the terminal's theme.
+    Xterm256,
+}
+
+impl Palette {
+    /// Returns the palette that fits a terminal supporting `max_colors` colors.
+    pub fn for_max_colors(max_colors: u16) -> Self {
+        match max_colors {
+            0..=8 => Palette::Ansi8,
+            9..=255 => Palette::Ansi16,
+            _ => Palette::Xterm256,
+        }
+    }
+
+    /// Returns the index of the first color and the colors, in the terminals' default RGB values.
+    fn colors(self) -> (u8, &'static [Color24]) {
+        match self {
+            Palette::Ansi8 => (0, &ANSI_COLORS[..8]),
+            Palette::Ansi16 => (0, &ANSI_COLORS[..]),
+            Palette::Xterm256 => (16, &XTERM_256_COLORS[..]),
+        }
+    }
+}
+
+const fn rgb(bits: u32) -> Color24 {
+    Color24 {
+        r: (bits >> 16) as u8,
+        g: (bits >> 8) as u8,
+        b: bits as u8,
+    }
+}
+
+const ANSI_COLORS: [Color24; 16] = [
+    rgb(0x000000), // Black
+    rgb(0x800000), // Red
+    rgb(0x008000), // Green
+    rgb(0x808000), // Yellow
+    rgb(0x000080), // Blue
+    rgb(0x800080), // Magenta
+    rgb(0x008080), // Cyan
+    rgb(0xc0c0c0), // White
+    rgb(0x808080), // Bright Black
+    rgb(0xff0000), // Bright Red
+    rgb(0x00ff00), // Bright Green
+    rgb(0xffff00), // Bright Yellow
+    rgb(0x0000ff), // Bright Blue
+    rgb(0xff00ff), // Bright Magenta
+    rgb(0x00ffff), // Bright Cyan
+    rgb(0xffffff), // Bright White
+];
+
+/// Colors 16 through 255 of the xterm palette.
+static XTERM_256_COLORS: Lazy<Vec<Color24>> = Lazy::new(|| {
+    const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+    let mut colors = Vec::with_capacity(240);
+    for r in CUBE_LEVELS {
+        for g in CUBE_LEVELS {
+            for b in CUBE_LEVELS {
+                colors.push(Color24 { r, g, b });
+            }
+        }
+    }
+    for i in 0..24 {
+        let level = 8 + 10 * i;
+        colors.push(Color24 {
+            r: level,
+            g: level,
+            b: level,
+        });
+    }
+    colors
+});
+
+/// How different two colors look, using the "redmean" weighting of the RGB components. This is
+/// much closer to what we perceive than plain RGB distance, which e.g. maps dark blues to gray,
+/// while being cheaper than converting to a perceptual color space.
+fn color_distance(a: Color24, b: Color24) -> u32 {
+    let rmean = (u32::from(a.r) + u32::from(b.r)) / 2;
+    let dr = u32::from(a.r.abs_diff(b.r));
+    let dg = u32::from(a.g.abs_diff(b.g));
+    let db = u32::from(a.b.abs_diff(b.b));
+    (((512 + rmean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - rmean) * db * db) >> 8)
+}
+
+/// Remembers the colors we quantized. Themes use a handful of colors, which are looked up again
+/// on every repaint.
+static QUANTIZED: Lazy<Mutex<HashMap<(Color24, Palette), u8>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the index of the color in `palette` that looks the most like `color`.
+pub fn quantize_color(color: Color24, palette: Palette) -> u8 {
+    let mut cache = QUANTIZED.lock().expect("Mutex poisoned!");
+    *cache.entry((color, palette)).or_insert_with(|| {
+        let (first, colors) = palette.colors();
+        let (idx, _) = colors
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &c)| color_distance(color, c))
+            .expect("palettes are not empty");
+        first + u8::try_from(idx).unwrap()
+    })
+}
+
+fn quantize_color_ffi(r: u8, g: u8, b: u8, palette_size: u16) -> u8 {
+    quantize_color(Color24 { r, g, b }, Palette::for_max_colors(palette_size))
+}
+
+/// Returns the sequence to switch to the underline `style`, as numbered by the `Smulx` capability
+/// (e.g. 3 for curly), or nothing if the terminal doesn't support styled underlines.
+fn term_underline_style_ffi(style: u8) -> Vec<u8> {
+    curses::term()
+        .and_then(|term| {
+            term.get_extended_str_with_params("Smulx", &[Param::Number(i32::from(style))])
+        })
+        .map(|seq| seq.into_bytes())
+        .unwrap_or_default()
+}
+
+/// Whether the terminal can color underlines separately from the text, per the `Setulc`
+/// capability.
+fn term_supports_underline_color() -> bool {
+    curses::term().map_or(false, |term| term.get_extended_str("Setulc").is_some())
+}
+
+/// Returns the sequence to set the color of underlines to the 24-bit color `rgb`, or nothing if the
+/// terminal can't color underlines.
+fn term_underline_color_ffi(rgb: u32) -> Vec<u8> {
+    curses::term()
+        .and_then(|term| term.get_extended_str_with_params("Setulc", &[Param::Number(rgb as i32)]))
+        .map(|seq| seq.into_bytes())
+        .unwrap_or_default()
+}
+
+/// Returns the sequence to start or end struck through text, per the `smxx` and `rmxx`
+/// capabilities, or nothing if the terminal has none.
+fn term_strikethrough_ffi(enable: bool) -> Vec<u8> {
+    let name = if enable { "smxx" } else { "rmxx" };
+    curses::term()
+        .and_then(|term| term.get_extended_str(name))
+        .map(|seq| seq.into_bytes())
+        .unwrap_or_default()
+}
+
+/// Whether we have a terminfo entry for the terminal. Without one, the C++ side doesn't use any
+/// capabilities.
+fn term_has_entry() -> bool {
+    curses::term().is_some()
+}
+
+/// Sets `value` to the string capability `name` of the terminal, by its terminfo name like `sgr0`.
+/// Returns false if the terminal doesn't have it.
+fn term_get_string_ffi(name: &str, value: &mut Vec<u8>) -> bool {
+    let Some(cap) = curses::term().and_then(|term| term.get_str(name)) else {
+        return false;
+    };
+    *value = cap.into_bytes();
+    true
+}
+
+/// Returns the number capability `name` of the terminal, or -1 if it doesn't have it.
+fn term_get_number_ffi(name: &str) -> i32 {
+    curses::term()
+        .and_then(|term| term.get_number(name))
+        .unwrap_or(-1)
+}
+
+/// Returns whether the terminal has the flag capability `name`.
+fn term_get_flag_ffi(name: &str) -> bool {
+    curses::term().map_or(false, |term| term.get_flag(name))
+}
+
+/// Instantiates the parameterized capability `cap` with the numbers `params`, for the C++ side.
+/// Returns an empty sequence if `cap` is malformed or there is no terminal.
+fn term_expand_ffi(cap: &[u8], params: &[i32]) -> Vec<u8> {
+    let Ok(cap) = CString::new(cap) else {
+        return Vec::new();
+    };
+    let params: Vec<Param> = params.iter().map(|&n| Param::Number(n)).collect();
+    curses::term()
+        .and_then(|term| term.expand(&cap, &params))
+        .map(CString::into_bytes)
+        .unwrap_or_default()
+}
+
+/// The escape sequence a terminal understands for being told the current directory, so it can
+/// open new tabs and windows there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CwdReporting {
+    None,
+    /// OSC 7 with a `file://` URL, as understood by most terminals.
+    Osc7,
+    /// ConEmu's OSC 9;9 with the plain path, also understood by Windows Terminal.
+    ConEmu,
+}
+
+/// Returns the sequence telling the terminal that the current directory is `cwd` on the host
+/// `hostname`, or nothing if the terminal doesn't take one.
+pub fn cwd_report_sequence(style: CwdReporting, hostname: &wstr, cwd: &wstr) -> Vec<u8> {
+    let mut seq = Vec::new();
+    match style {
+        CwdReporting::None => (),
+        CwdReporting::Osc7 => {
+            seq.extend_from_slice(b"\x1B]7;file://");
+            seq.extend(wcs2string(&escape_string(hostname, EscapeStringStyle::Url)));
+            seq.extend(wcs2string(&escape_string(cwd, EscapeStringStyle::Url)));
+            seq.push(b'\x07');
+        }
+        CwdReporting::ConEmu => {
+            seq.extend_from_slice(b"\x1B]9;9;\"");
+            seq.extend(wcs2string(cwd));
+            seq.extend_from_slice(b"\"\x07");
+        }
+    }
+    seq
+}
+
+/// Tells the terminal about the current directory in `$PWD`, if it wants to know. This is done
+/// when `cd` changes it, and once at startup for the directory we inherited.
+pub fn report_cwd(vars: &EnvStack) {
+    let style = crate::env_dispatch::term_cwd_reporting();
+    // Emacs' terminals show the sequence instead of understanding it.
+    if style == CwdReporting::None || vars.get(L!("INSIDE_EMACS")).is_some() {
+        return;
+    }
+    if !is_interactive_session() || unsafe { libc::isatty(libc::STDOUT_FILENO) } == 0 {
+        return;
+    }
+    let get = |name: &wstr| vars.get(name).map(|v| v.as_string()).unwrap_or_default();
+    let seq = cwd_report_sequence(style, &get(L!("hostname")), &get(L!("PWD")));
+    let _ = write_loop(&libc::STDOUT_FILENO, &seq);
+}
+
+fn report_cwd_ffi() {
+    report_cwd(EnvStack::principal());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cwd_report_sequence, quantize_color, rgb, CwdReporting, Palette};
+    use crate::wchar::L;
+
+    #[test]
+    fn test_quantize_color() {
+        let tests = [
+            (0xff0000, 196, 9, 1),
+            (0x000000, 16, 0, 0),
+            (0xffffff, 231, 15, 7),
+            (0x808080, 244, 8, 7),
+            (0x5f87af, 67, 8, 6),
+            (0x8b0000, 88, 1, 1),
+            (0xffa500, 214, 11, 3),
+        ];
+        for (color, xterm, ansi16, ansi8) in tests {
+            let color = rgb(color);
+            assert_eq!(quantize_color(color, Palette::Xterm256), xterm);
+            assert_eq!(quantize_color(color, Palette::Ansi16), ansi16);
+            assert_eq!(quantize_color(color, Palette::Ansi8), ansi8);
+        }
+    }
+
+    #[test]
+    fn test_cwd_report_sequence() {
+        let report = |style| cwd_report_sequence(style, L!("host"), L!("/tmp/a dir/ü"));
+        assert_eq!(
+            report(CwdReporting::Osc7),
+            b"\x1B]7;file://host/tmp/a%20dir/%C3%BC\x07"
+        );
+        assert_eq!(
+            report(CwdReporting::ConEmu),
+            "\x1B]9;9;\"/tmp/a dir/ü\"\x07".as_bytes()
+        );
+        assert!(report(CwdReporting::None).is_empty());
+    }
+}
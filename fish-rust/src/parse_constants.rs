@@ -160,6 +160,7 @@ mod parse_constants_ffi {
         type ParseError;
         fn code(self: &ParseError) -> ParseErrorCode;
         fn source_start(self: &ParseError) -> usize;
+        fn source_length(self: &ParseError) -> usize;
         fn text(self: &ParseError) -> UniquePtr<CxxWString>;
 
         #[cxx_name = "describe"]
@@ -550,6 +551,9 @@ impl ParseError {
     fn source_start(&self) -> usize {
         self.source_start
     }
+    fn source_length(&self) -> usize {
+        self.source_length
+    }
     fn text(&self) -> UniquePtr<CxxWString> {
         self.text.to_ffi()
     }
@@ -52,6 +52,19 @@ pub fn path_get_data() -> Option<WString> {
     }
 }
 
+/// Returns the user cache directory for fish. If the directory or one of its parents doesn't exist,
+/// they are first created.
+///
+/// Files that fish can always recreate, like cached parse trees, are stored in this directory.
+pub fn path_get_cache() -> Option<WString> {
+    let dir = get_cache_directory();
+    if dir.success() {
+        Some(dir.path.to_owned())
+    } else {
+        None
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum DirRemoteness {
     /// directory status is unknown
@@ -720,6 +733,13 @@ fn get_data_directory() -> &'static BaseDirectory {
     &*DIR
 }
 
+#[widestrs]
+fn get_cache_directory() -> &'static BaseDirectory {
+    static DIR: Lazy<BaseDirectory> =
+        Lazy::new(|| make_base_directory("XDG_CACHE_HOME"L, "/.cache/fish"L));
+    &*DIR
+}
+
 #[widestrs]
 fn get_config_directory() -> &'static BaseDirectory {
     static DIR: Lazy<BaseDirectory> =
@@ -0,0 +1,809 @@
+//! Plugins, which are loaded with `plugin load`.
+//!
+//! A WebAssembly plugin is a WASI module which can provide builtins, completions and prompt
+//! segments. Loading it also grants it access to variables and directories. A plugin can't see
+//! anything else: it gets no environment, no arguments and no files beyond the granted
+//! directories, and what it writes to its standard output and error is captured and passed on to
+//! the builtin's streams.
+//!
+//! A native plugin is a shared library which can provide builtins and event handlers through the
+//! C interface in fish_plugin.h. It is not sandboxed. See the native module.
+//!
+//! The interface between fish and a plugin is described in the documentation for `plugin`.
+//! Strings are passed as UTF-8, lists of strings as each string followed by a NUL byte.
+//!
+//! WebAssembly plugins need fish to be built with the wasm-plugins feature. Without it, only
+//! native plugins can be loaded.
+
+use std::io::Read;
+use std::sync::Mutex;
+
+use cxx::{CxxWString, UniquePtr};
+
+use crate::common::wcs2osstring;
+use crate::ffi::{parser_t, wcstring_list_ffi_t};
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::{AsWstr, WCharToFFI};
+use crate::wutil::wgettext_fmt;
+
+mod native;
+
+#[cxx::bridge]
+mod plugins_ffi {
+    extern "C++" {
+        include!("wutil.h");
+        type wcstring_list_ffi_t = super::wcstring_list_ffi_t;
+    }
+    extern "Rust" {
+        #[cxx_name = "plugin_builtin_exists"]
+        fn plugin_builtin_exists_ffi(name: &CxxWString) -> bool;
+        #[cxx_name = "plugin_builtin_names"]
+        fn plugin_builtin_names_ffi() -> UniquePtr<wcstring_list_ffi_t>;
+    }
+}
+
+/// What a plugin may access, as granted by `plugin load`.
+#[derive(Default, Clone)]
+pub struct Capabilities {
+    /// Variables the plugin may read.
+    pub read_vars: Vec<WString>,
+    /// Variables the plugin may read and set.
+    pub write_vars: Vec<WString>,
+    /// Directories the plugin may access, with the same path inside the plugin.
+    pub dirs: Vec<WString>,
+}
+
+impl Capabilities {
+    fn can_read(&self, name: &wstr) -> bool {
+        self.read_vars.iter().any(|v| v == name) || self.can_write(name)
+    }
+
+    fn can_write(&self, name: &wstr) -> bool {
+        self.write_vars.iter().any(|v| v == name)
+    }
+}
+
+/// The builtins, completions, prompt segments and event handlers a plugin provides.
+#[derive(Default, Clone)]
+pub struct Registrations {
+    pub builtins: Vec<WString>,
+    pub completions: Vec<WString>,
+    pub segments: Vec<WString>,
+    pub events: Vec<EventHandler>,
+}
+
+/// The kinds of events a plugin can handle.
+#[derive(Clone, Copy)]
+pub enum EventKind {
+    Generic,
+    Variable,
+    Signal,
+}
+
+impl EventKind {
+    /// \return the option of `function` which handles this kind of event.
+    pub fn function_option(self) -> &'static wstr {
+        match self {
+            EventKind::Generic => L!("--on-event"),
+            EventKind::Variable => L!("--on-variable"),
+            EventKind::Signal => L!("--on-signal"),
+        }
+    }
+}
+
+/// An event handler of a plugin, which is run by a fish function.
+#[derive(Clone)]
+pub struct EventHandler {
+    /// The name of the function which calls the handler.
+    pub function: WString,
+    pub kind: EventKind,
+    /// The name of the event, variable or signal.
+    pub name: WString,
+}
+
+/// A description of a loaded plugin, for `plugin list`.
+pub struct PluginInfo {
+    pub name: WString,
+    pub path: WString,
+    pub registrations: Registrations,
+}
+
+/// The result of calling into a plugin.
+pub struct CallResult {
+    /// The status the plugin returned.
+    pub status: i32,
+    /// What it wrote to its standard output and error.
+    pub out: WString,
+    pub err: WString,
+    /// The variables it set, to be set by the caller.
+    pub sets: Vec<(WString, Vec<WString>)>,
+}
+
+/// A loaded plugin.
+enum Plugin {
+    Wasm(wasm::Plugin),
+    Native(native::Plugin),
+}
+
+impl Plugin {
+    fn name(&self) -> &wstr {
+        match self {
+            Plugin::Wasm(p) => &p.name,
+            Plugin::Native(p) => &p.name,
+        }
+    }
+
+    fn path(&self) -> &wstr {
+        match self {
+            Plugin::Wasm(p) => &p.path,
+            Plugin::Native(p) => &p.path,
+        }
+    }
+
+    fn registrations(&self) -> &Registrations {
+        match self {
+            Plugin::Wasm(p) => p.registrations(),
+            Plugin::Native(p) => p.registrations(),
+        }
+    }
+
+    fn call(
+        &mut self,
+        parser: &mut parser_t,
+        entry: Entry,
+        name: &wstr,
+        inputs: &[&wstr],
+    ) -> Result<CallResult, WString> {
+        match self {
+            Plugin::Wasm(p) => p.call(parser, entry, name, inputs),
+            Plugin::Native(p) => p.call(parser, entry, name, inputs),
+        }
+    }
+}
+
+/// The loaded plugins.
+static PLUGINS: Mutex<Vec<Plugin>> = Mutex::new(Vec::new());
+
+/// Load the plugin at \p path, granting it \p caps. \return its name and what it registered.
+/// Capabilities only apply to WebAssembly plugins, native plugins can access everything.
+pub fn load(
+    parser: &mut parser_t,
+    path: &wstr,
+    caps: Capabilities,
+) -> Result<(WString, Registrations, CallResult), WString> {
+    let name = plugin_name(path);
+    if PLUGINS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|p| p.name() == name.as_utfstr())
+    {
+        return Err(wgettext_fmt!("plugin '%ls' is already loaded", name));
+    }
+    let (plugin, result) = if is_wasm(path)? {
+        let (plugin, result) = wasm::Plugin::load(parser, name.clone(), path, caps)?;
+        (Plugin::Wasm(plugin), result)
+    } else {
+        let (plugin, result) = native::Plugin::load(parser, name.clone(), path)?;
+        (Plugin::Native(plugin), result)
+    };
+
+    // A plugin can't replace builtins, including those of other plugins.
+    let registrations = plugin.registrations().clone();
+    for builtin in &registrations.builtins {
+        if crate::ffi::builtin_exists(&builtin.to_ffi()) {
+            return Err(wgettext_fmt!(
+                "plugin '%ls' tried to register '%ls', which is already a builtin",
+                name,
+                builtin
+            ));
+        }
+    }
+    PLUGINS.lock().unwrap().push(plugin);
+    Ok((name, registrations, result))
+}
+
+/// Unload the plugin named \p name. \return what it had registered, or None if there is none.
+pub fn unload(name: &wstr) -> Option<Registrations> {
+    let mut plugins = PLUGINS.lock().unwrap();
+    let idx = plugins.iter().position(|p| p.name() == name)?;
+    Some(plugins.remove(idx).registrations().clone())
+}
+
+/// \return the loaded plugins, in the order they were loaded.
+pub fn list() -> Vec<PluginInfo> {
+    PLUGINS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|p| PluginInfo {
+            name: p.name().to_owned(),
+            path: p.path().to_owned(),
+            registrations: p.registrations().clone(),
+        })
+        .collect()
+}
+
+/// Which kind of thing to call in a plugin.
+#[derive(Clone, Copy)]
+pub enum Entry {
+    Builtin,
+    Completion,
+    Segment,
+    /// An event handler, named by the function which calls it.
+    Event,
+}
+
+/// Call the plugin which registered \p name as \p entry, passing it \p inputs.
+/// \return None if no plugin registered it.
+pub fn call(
+    parser: &mut parser_t,
+    entry: Entry,
+    name: &wstr,
+    inputs: &[&wstr],
+) -> Option<Result<CallResult, WString>> {
+    let mut plugins = PLUGINS.lock().unwrap();
+    let plugin = plugins.iter_mut().find(|p| {
+        let regs = p.registrations();
+        let names = match entry {
+            Entry::Builtin => &regs.builtins,
+            Entry::Completion => &regs.completions,
+            Entry::Segment => &regs.segments,
+            Entry::Event => return regs.events.iter().any(|e| &e.function == name),
+        };
+        names.iter().any(|n| n == name)
+    })?;
+    Some(plugin.call(parser, entry, name, inputs))
+}
+
+/// \return whether a plugin registered the builtin \p name.
+pub fn plugin_builtin_exists(name: &wstr) -> bool {
+    PLUGINS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|p| p.registrations().builtins.iter().any(|b| b == name))
+}
+
+fn plugin_builtin_exists_ffi(name: &CxxWString) -> bool {
+    plugin_builtin_exists(name.as_wstr())
+}
+
+fn plugin_builtin_names_ffi() -> UniquePtr<wcstring_list_ffi_t> {
+    let names: Vec<WString> = PLUGINS
+        .lock()
+        .unwrap()
+        .iter()
+        .flat_map(|p| p.registrations().builtins.clone())
+        .collect();
+    names.to_ffi()
+}
+
+/// The name of a plugin is its file name without the extension, and without the lib prefix of
+/// shared libraries, so /path/libfoo.so is called foo.
+fn plugin_name(path: &wstr) -> WString {
+    let base = crate::wutil::wbasename(path.to_owned());
+    let chars = base.as_char_slice();
+    let name = if let Some(stem) = chars.strip_suffix(L!(".wasm").as_char_slice()) {
+        stem
+    } else if let Some(stem) = chars
+        .strip_suffix(L!(".so").as_char_slice())
+        .or_else(|| chars.strip_suffix(L!(".dylib").as_char_slice()))
+    {
+        stem.strip_prefix(L!("lib").as_char_slice()).unwrap_or(stem)
+    } else {
+        chars
+    };
+    if name.is_empty() {
+        return base;
+    }
+    WString::from_chars(name)
+}
+
+/// \return whether the file at \p path is a WebAssembly module rather than a shared library.
+fn is_wasm(path: &wstr) -> Result<bool, WString> {
+    let mut magic = [0; 4];
+    std::fs::File::open(wcs2osstring(path))
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map_err(|e| wgettext_fmt!("%ls: %s", path, e.to_string()))?;
+    Ok(&magic == b"\0asm")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_wasm, plugin_name, Capabilities};
+    use crate::wchar::{WString, L};
+
+    #[test]
+    fn test_plugin_name() {
+        assert_eq!(plugin_name(L!("/path/to/prompt.wasm")), L!("prompt"));
+        assert_eq!(plugin_name(L!("/path/to/libfoo.so")), L!("foo"));
+        assert_eq!(plugin_name(L!("libfoo.dylib")), L!("foo"));
+        // Only shared libraries lose their lib prefix.
+        assert_eq!(plugin_name(L!("libfoo.wasm")), L!("libfoo"));
+        assert_eq!(plugin_name(L!("foo.so")), L!("foo"));
+        assert_eq!(plugin_name(L!("/path/to/plugin")), L!("plugin"));
+        // A name can't be empty.
+        assert_eq!(plugin_name(L!("/path/to/.wasm")), L!(".wasm"));
+        assert_eq!(plugin_name(L!("lib.so")), L!("lib.so"));
+    }
+
+    #[test]
+    fn test_is_wasm() {
+        let dir = std::env::temp_dir().join(format!("fish_plugin_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| WString::from(dir.join(name).to_str().unwrap());
+
+        // Extensions don't matter, only the contents.
+        std::fs::write(dir.join("module.so"), b"\0asm\x01\0\0\0").unwrap();
+        assert_eq!(is_wasm(&path("module.so")), Ok(true));
+        std::fs::write(dir.join("lib.wasm"), b"\x7fELF\x02\x01\x01").unwrap();
+        assert_eq!(is_wasm(&path("lib.wasm")), Ok(false));
+        // Files too short to have a magic number can't be loaded at all.
+        std::fs::write(dir.join("short.wasm"), b"\0as").unwrap();
+        assert!(is_wasm(&path("short.wasm")).is_err());
+        assert!(is_wasm(&path("missing.wasm")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let caps = Capabilities {
+            read_vars: vec![WString::from("PWD")],
+            write_vars: vec![WString::from("prompt_status")],
+            dirs: vec![],
+        };
+        assert!(caps.can_read(L!("PWD")));
+        assert!(!caps.can_write(L!("PWD")));
+        // Writable variables are also readable.
+        assert!(caps.can_read(L!("prompt_status")));
+        assert!(caps.can_write(L!("prompt_status")));
+        assert!(!caps.can_read(L!("HOME")));
+        assert!(!Capabilities::default().can_read(L!("PWD")));
+    }
+}
+
+#[cfg(feature = "wasm-plugins")]
+crate::ffi_tests::add_test!("test_wasm_plugin", || {
+    use crate::env::EnvMode;
+
+    let parser: &mut parser_t = unsafe { &mut *parser_t::principal_parser_ffi() };
+    parser.set_var(L!("plugin_name"), &[L!("x"), L!("y z")], EnvMode::GLOBAL);
+    parser.set_var(L!("plugin_secret"), &[L!("hidden")], EnvMode::GLOBAL);
+
+    // wasmtime also loads modules in the text format, so the fixture needn't be compiled.
+    let path = std::env::temp_dir().join(format!("fish_test_plugin_{}.wat", std::process::id()));
+    std::fs::write(&path, include_str!("plugins/test_plugin.wat")).unwrap();
+    let caps = Capabilities {
+        read_vars: vec![L!("plugin_name").to_owned()],
+        write_vars: vec![L!("plugin_greeted").to_owned()],
+        dirs: vec![],
+    };
+    let path_str = WString::from(path.to_str().unwrap());
+    let (mut plugin, init) =
+        wasm::Plugin::load(parser, L!("test").to_owned(), &path_str, caps).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(init.status, 0);
+    assert_eq!(init.out, L!("loaded\n"));
+
+    let regs = plugin.registrations();
+    assert_eq!(regs.builtins, [L!("greet")]);
+    assert_eq!(regs.segments, [L!("prompt")]);
+    assert_eq!(regs.completions, [L!("mycmd")]);
+
+    // The builtin gets its arguments as a list, and returns its length.
+    let result = plugin
+        .call(parser, Entry::Builtin, L!("greet"), &[L!("a"), L!("b c")])
+        .unwrap();
+    assert_eq!(result.status, 6);
+    assert_eq!(result.out, L!("greet"));
+    assert_eq!(result.err, L!(""));
+    assert_eq!(result.sets.len(), 1);
+    assert_eq!(result.sets[0].0, L!("plugin_greeted"));
+    assert_eq!(result.sets[0].1, [L!("x"), L!("y z")]);
+
+    let result = plugin
+        .call(parser, Entry::Segment, L!("prompt"), &[])
+        .unwrap();
+    assert_eq!((result.status, result.out), (0, L!("prompt").to_owned()));
+    let result = plugin
+        .call(parser, Entry::Completion, L!("mycmd"), &[L!("mycmd --fo")])
+        .unwrap();
+    assert_eq!(
+        (result.status, result.out),
+        (0, L!("mycmd --fo").to_owned())
+    );
+
+    // Variables are read when a call starts.
+    parser.set_var(L!("plugin_name"), &[], EnvMode::GLOBAL);
+    let result = plugin
+        .call(parser, Entry::Builtin, L!("greet"), &[])
+        .unwrap();
+    assert_eq!(result.status, 0);
+    assert_eq!(result.sets[0].1, Vec::<WString>::new());
+});
+
+#[cfg(feature = "wasm-plugins")]
+mod wasm {
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    use once_cell::sync::Lazy;
+    use wasi_common::pipe::WritePipe;
+    use wasmtime::{Caller, Engine, Instance, Linker, Module, Store, Val};
+    use wasmtime_wasi::sync::{ambient_authority, Dir, WasiCtxBuilder};
+    use wasmtime_wasi::WasiCtx;
+
+    use super::{CallResult, Capabilities, Entry, Registrations};
+    use crate::common::{str2wcstring, wcs2osstring, wcs2string};
+    use crate::env::EnvMode;
+    use crate::ffi::parser_t;
+    use crate::wchar::{wstr, WString};
+    use crate::wchar_ffi::{WCharFromFFI, WCharToFFI};
+    use crate::wutil::wgettext_fmt;
+
+    static ENGINE: Lazy<Engine> = Lazy::new(Engine::default);
+
+    /// The host side of a plugin instance.
+    struct State {
+        wasi: WasiCtx,
+        caps: Capabilities,
+        registrations: Registrations,
+        /// Set while fish_plugin_init runs, which is the only time a plugin may register things.
+        initializing: bool,
+        /// The readable variables, captured when a call starts.
+        vars: HashMap<WString, Vec<WString>>,
+        /// The variables set by the current call.
+        sets: Vec<(WString, Vec<WString>)>,
+    }
+
+    pub struct Plugin {
+        pub name: WString,
+        pub path: WString,
+        store: Store<State>,
+        instance: Instance,
+        stdout: Arc<RwLock<Vec<u8>>>,
+        stderr: Arc<RwLock<Vec<u8>>>,
+    }
+
+    fn error(name: &wstr, err: impl std::fmt::Display) -> WString {
+        wgettext_fmt!("plugin '%ls': %s", name, err.to_string())
+    }
+
+    impl Plugin {
+        pub fn load(
+            parser: &mut parser_t,
+            name: WString,
+            path: &wstr,
+            caps: Capabilities,
+        ) -> Result<(Plugin, CallResult), WString> {
+            let module =
+                Module::from_file(&ENGINE, wcs2osstring(path)).map_err(|e| error(&name, e))?;
+
+            let stdout = Arc::new(RwLock::new(Vec::new()));
+            let stderr = Arc::new(RwLock::new(Vec::new()));
+            let mut builder = WasiCtxBuilder::new()
+                .stdout(Box::new(WritePipe::from_shared(stdout.clone())))
+                .stderr(Box::new(WritePipe::from_shared(stderr.clone())));
+            for dir in &caps.dirs {
+                let path = wcs2osstring(dir);
+                let opened = Dir::open_ambient_dir(&path, ambient_authority())
+                    .map_err(|e| error(&name, e))?;
+                builder = builder
+                    .preopened_dir(opened, &path)
+                    .map_err(|e| error(&name, e))?;
+            }
+            let state = State {
+                wasi: builder.build(),
+                caps,
+                registrations: Registrations::default(),
+                initializing: true,
+                vars: HashMap::new(),
+                sets: Vec::new(),
+            };
+
+            let mut store = Store::new(&ENGINE, state);
+            let linker = make_linker().map_err(|e| error(&name, e))?;
+            let instance = linker
+                .instantiate(&mut store, &module)
+                .map_err(|e| error(&name, e))?;
+            let mut plugin = Plugin {
+                name,
+                path: path.to_owned(),
+                store,
+                instance,
+                stdout,
+                stderr,
+            };
+
+            // Reactor modules need to be initialized before anything else is called.
+            if let Ok(initialize) =
+                instance.get_typed_func::<(), ()>(&mut plugin.store, "_initialize")
+            {
+                initialize
+                    .call(&mut plugin.store, ())
+                    .map_err(|e| error(&plugin.name, e))?;
+            }
+            let result = plugin.call_export(parser, "fish_plugin_init", &[])?;
+            plugin.store.data_mut().initializing = false;
+            if result.status != 0 {
+                let mut msg = wgettext_fmt!(
+                    "plugin '%ls': initialization failed with status %d",
+                    plugin.name,
+                    result.status
+                );
+                if !result.err.is_empty() {
+                    msg.push('\n');
+                    msg.push_utfstr(&result.err);
+                    if msg.ends_with('\n') {
+                        msg.pop();
+                    }
+                }
+                return Err(msg);
+            }
+            Ok((plugin, result))
+        }
+
+        pub fn registrations(&self) -> &Registrations {
+            &self.store.data().registrations
+        }
+
+        pub fn call(
+            &mut self,
+            parser: &mut parser_t,
+            entry: Entry,
+            name: &wstr,
+            inputs: &[&wstr],
+        ) -> Result<CallResult, WString> {
+            let export = match entry {
+                Entry::Builtin => "fish_builtin",
+                Entry::Completion => "fish_complete",
+                Entry::Segment => "fish_prompt_segment",
+                Entry::Event => unreachable!("WebAssembly plugins can't register event handlers"),
+            };
+            let mut args = vec![wcs2string(name)];
+            if let Entry::Builtin = entry {
+                // The arguments of a builtin are passed as one list.
+                let mut list = Vec::new();
+                for input in inputs {
+                    list.extend_from_slice(&wcs2string(input));
+                    list.push(b'\0');
+                }
+                args.push(list);
+            } else {
+                args.extend(inputs.iter().map(|s| wcs2string(s)));
+            }
+            let args: Vec<&[u8]> = args.iter().map(|a| a.as_slice()).collect();
+            self.call_export(parser, export, &args)
+        }
+
+        /// Call \p export, passing each of \p inputs as a pointer and length.
+        fn call_export(
+            &mut self,
+            parser: &mut parser_t,
+            export: &str,
+            inputs: &[&[u8]],
+        ) -> Result<CallResult, WString> {
+            // Capture the variables the plugin may read. It sees them as they were when the call
+            // started, plus anything it sets itself.
+            let mut vars = HashMap::new();
+            let caps = &self.store.data().caps;
+            for name in caps.read_vars.iter().chain(caps.write_vars.iter()) {
+                let var = parser
+                    .get_var_stack()
+                    .get_or_null(&name.to_ffi(), EnvMode::DEFAULT.bits());
+                if let Some(var) = var.as_ref() {
+                    let values: Vec<WString> = var.as_list_ffi().as_ref().unwrap().from_ffi();
+                    vars.insert(name.clone(), values);
+                }
+            }
+            let state = self.store.data_mut();
+            state.vars = vars;
+            state.sets.clear();
+
+            let status = self.invoke(export, inputs);
+            let out = std::mem::take(&mut *self.stdout.write().unwrap());
+            let err = std::mem::take(&mut *self.stderr.write().unwrap());
+            let sets = std::mem::take(&mut self.store.data_mut().sets);
+            Ok(CallResult {
+                status: status.map_err(|e| error(&self.name, e))?,
+                out: str2wcstring(&out),
+                err: str2wcstring(&err),
+                sets,
+            })
+        }
+
+        fn invoke(&mut self, export: &str, inputs: &[&[u8]]) -> wasmtime::Result<i32> {
+            let memory = self
+                .instance
+                .get_memory(&mut self.store, "memory")
+                .ok_or_else(|| wasmtime::Error::msg("no memory export"))?;
+            let mut params = Vec::new();
+            if !inputs.is_empty() {
+                // The buffers belong to the plugin once they are passed, it must free them.
+                let alloc = self
+                    .instance
+                    .get_typed_func::<i32, i32>(&mut self.store, "fish_alloc")?;
+                for input in inputs {
+                    let len = i32::try_from(input.len())?;
+                    let ptr = alloc.call(&mut self.store, len)?;
+                    memory.write(&mut self.store, ptr as u32 as usize, input)?;
+                    params.push(Val::I32(ptr));
+                    params.push(Val::I32(len));
+                }
+            }
+            let func = self
+                .instance
+                .get_func(&mut self.store, export)
+                .ok_or_else(|| wasmtime::Error::msg(format!("no {} export", export)))?;
+            let mut results = [Val::I32(0)];
+            func.call(&mut self.store, &params, &mut results)?;
+            results[0]
+                .i32()
+                .ok_or_else(|| wasmtime::Error::msg(format!("{} must return an i32", export)))
+        }
+    }
+
+    /// \return the bytes at \p ptr and \p len in the plugin's memory.
+    fn read_bytes(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> Option<Vec<u8>> {
+        let memory = caller.get_export("memory")?.into_memory()?;
+        let start = ptr as u32 as usize;
+        let end = start.checked_add(len as u32 as usize)?;
+        memory.data(&caller).get(start..end).map(|b| b.to_vec())
+    }
+
+    fn read_string(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> Option<WString> {
+        read_bytes(caller, ptr, len).map(|b| str2wcstring(&b))
+    }
+
+    /// Register \p name in the list picked by \p field. \return 0 on success, -1 if we are not
+    /// initializing or the name is invalid.
+    fn register(
+        mut caller: Caller<'_, State>,
+        ptr: i32,
+        len: i32,
+        field: fn(&mut Registrations) -> &mut Vec<WString>,
+    ) -> i32 {
+        let Some(name) = read_string(&mut caller, ptr, len) else {
+            return -1;
+        };
+        let state = caller.data_mut();
+        if !state.initializing || name.is_empty() {
+            return -1;
+        }
+        let names = field(&mut state.registrations);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        0
+    }
+
+    fn make_linker() -> wasmtime::Result<Linker<State>> {
+        let mut linker = Linker::new(&ENGINE);
+        wasmtime_wasi::add_to_linker(&mut linker, |s: &mut State| &mut s.wasi)?;
+        linker.func_wrap(
+            "fish",
+            "register_builtin",
+            |caller: Caller<'_, State>, ptr: i32, len: i32| {
+                register(caller, ptr, len, |r| &mut r.builtins)
+            },
+        )?;
+        linker.func_wrap(
+            "fish",
+            "register_completion",
+            |caller: Caller<'_, State>, ptr: i32, len: i32| {
+                register(caller, ptr, len, |r| &mut r.completions)
+            },
+        )?;
+        linker.func_wrap(
+            "fish",
+            "register_prompt_segment",
+            |caller: Caller<'_, State>, ptr: i32, len: i32| {
+                register(caller, ptr, len, |r| &mut r.segments)
+            },
+        )?;
+        // Copy the values of a variable to the buffer, as a list. Returns the length of the list,
+        // which may be larger than the buffer, or -1 if the variable is unset or not readable.
+        linker.func_wrap(
+            "fish",
+            "get_var",
+            |mut caller: Caller<'_, State>, ptr: i32, len: i32, buf: i32, buf_len: i32| -> i32 {
+                let Some(name) = read_string(&mut caller, ptr, len) else {
+                    return -1;
+                };
+                let state = caller.data();
+                if !state.caps.can_read(&name) {
+                    return -1;
+                }
+                let Some(values) = state.vars.get(&name) else {
+                    return -1;
+                };
+                let mut list = Vec::new();
+                for value in values {
+                    list.extend_from_slice(&wcs2string(value));
+                    list.push(b'\0');
+                }
+                let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) else {
+                    return -1;
+                };
+                let count = list.len().min(buf_len.max(0) as usize);
+                if memory
+                    .write(&mut caller, buf as u32 as usize, &list[..count])
+                    .is_err()
+                {
+                    return -1;
+                }
+                i32::try_from(list.len()).unwrap_or(-1)
+            },
+        )?;
+        // Set a variable to a list. Returns 0 on success or -1 if it is not writable.
+        linker.func_wrap(
+            "fish",
+            "set_var",
+            |mut caller: Caller<'_, State>, ptr: i32, len: i32, vals: i32, vals_len: i32| -> i32 {
+                let Some(name) = read_string(&mut caller, ptr, len) else {
+                    return -1;
+                };
+                let Some(list) = read_bytes(&mut caller, vals, vals_len) else {
+                    return -1;
+                };
+                let state = caller.data_mut();
+                if !state.caps.can_write(&name) {
+                    return -1;
+                }
+                let list = list.strip_suffix(b"\0").unwrap_or(&list);
+                let values: Vec<WString> = if list.is_empty() && vals_len == 0 {
+                    vec![]
+                } else {
+                    list.split(|&b| b == b'\0').map(str2wcstring).collect()
+                };
+                state.vars.insert(name.clone(), values.clone());
+                state.sets.push((name, values));
+                0
+            },
+        )?;
+        Ok(linker)
+    }
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+mod wasm {
+    use super::{CallResult, Capabilities, Entry, Registrations};
+    use crate::ffi::parser_t;
+    use crate::wchar::{wstr, WString};
+    use crate::wutil::wgettext;
+
+    /// Without WebAssembly support, no WebAssembly plugin can be loaded, so this is never
+    /// constructed.
+    pub struct Plugin {
+        pub name: WString,
+        pub path: WString,
+        registrations: Registrations,
+    }
+
+    impl Plugin {
+        pub fn load(
+            _parser: &mut parser_t,
+            _name: WString,
+            _path: &wstr,
+            _caps: Capabilities,
+        ) -> Result<(Plugin, CallResult), WString> {
+            Err(wgettext!("fish was built without WebAssembly plugin support").to_owned())
+        }
+
+        pub fn registrations(&self) -> &Registrations {
+            &self.registrations
+        }
+
+        pub fn call(
+            &mut self,
+            _parser: &mut parser_t,
+            _entry: Entry,
+            _name: &wstr,
+            _inputs: &[&wstr],
+        ) -> Result<CallResult, WString> {
+            unreachable!("WebAssembly plugins can't be loaded")
+        }
+    }
+}
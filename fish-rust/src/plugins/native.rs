@@ -0,0 +1,377 @@
+//! Native plugins, which are shared libraries using the C interface in fish_plugin.h.
+//!
+//! Unlike WebAssembly plugins, native plugins are not sandboxed: they can read and set any
+//! variable, and do anything else a library loaded into fish can do.
+
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use libc::{c_char, c_int, c_void};
+
+use super::{CallResult, Entry, EventHandler, EventKind, Registrations};
+use crate::common::{str2wcstring, wcs2string, wcs2zstring};
+use crate::env::EnvMode;
+use crate::ffi::parser_t;
+use crate::wchar::{wstr, WString};
+use crate::wchar_ffi::{WCharFromFFI, WCharToFFI};
+use crate::wutil::{sprintf, wgettext_fmt};
+
+/// Must match FISH_PLUGIN_ABI_VERSION in fish_plugin.h.
+const ABI_VERSION: u32 = 1;
+
+type BuiltinFn =
+    unsafe extern "C" fn(*mut CallState, *mut c_void, usize, *const *const c_char) -> c_int;
+type EventFn = unsafe extern "C" fn(*mut CallState, *mut c_void, usize, *const *const c_char);
+
+/// struct fish_plugin_api.
+#[repr(C)]
+struct Api {
+    abi_version: u32,
+    register_builtin:
+        unsafe extern "C" fn(*mut HostState, *const c_char, BuiltinFn, *mut c_void) -> c_int,
+    register_event_handler:
+        unsafe extern "C" fn(*mut HostState, c_int, *const c_char, EventFn, *mut c_void) -> c_int,
+    get_var: unsafe extern "C" fn(*mut CallState, *const c_char, *mut c_char, usize) -> isize,
+    set_var: unsafe extern "C" fn(*mut CallState, *const c_char, *const c_char, usize) -> c_int,
+    write_out: unsafe extern "C" fn(*mut CallState, *const c_char, usize),
+    write_err: unsafe extern "C" fn(*mut CallState, *const c_char, usize),
+}
+
+static API: Api = Api {
+    abi_version: ABI_VERSION,
+    register_builtin,
+    register_event_handler,
+    get_var,
+    set_var,
+    write_out,
+    write_err,
+};
+
+/// Event handlers are run by fish functions with these names, followed by a number.
+static NEXT_HANDLER: AtomicU32 = AtomicU32::new(0);
+
+/// fish_plugin_host: what a plugin registered, with the functions to call.
+struct HostState {
+    registrations: Registrations,
+    builtins: Vec<(WString, BuiltinFn, *mut c_void)>,
+    /// Keyed by the name of the function which runs the handler.
+    events: Vec<(WString, EventFn, *mut c_void)>,
+    initializing: bool,
+}
+
+/// fish_call: the state of one call into the plugin.
+struct CallState {
+    /// The parser of the builtin which made the call, which outlives the call.
+    parser: *const parser_t,
+    out: Vec<u8>,
+    err: Vec<u8>,
+    sets: Vec<(WString, Vec<WString>)>,
+}
+
+pub struct Plugin {
+    pub name: WString,
+    pub path: WString,
+    handle: *mut c_void,
+    state: Box<HostState>,
+}
+
+// The handle and the data pointers of a plugin are only used on the main thread.
+unsafe impl Send for Plugin {}
+
+/// \return the message for the last dlopen or dlsym failure.
+fn dlerror() -> String {
+    let msg = unsafe { libc::dlerror() };
+    if msg.is_null() {
+        return "unknown error".to_owned();
+    }
+    unsafe { CStr::from_ptr(msg) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// \return the symbol \p name of \p handle, or None if it doesn't exist.
+fn symbol(handle: *mut c_void, name: &str) -> Option<*mut c_void> {
+    let name = CString::new(name).unwrap();
+    let sym = unsafe { libc::dlsym(handle, name.as_ptr()) };
+    (!sym.is_null()).then_some(sym)
+}
+
+impl Plugin {
+    pub fn load(
+        parser: &mut parser_t,
+        name: WString,
+        path: &wstr,
+    ) -> Result<(Plugin, CallResult), WString> {
+        let error = |msg: String| wgettext_fmt!("plugin '%ls': %s", name, msg);
+        let handle = unsafe {
+            libc::dlopen(
+                wcs2zstring(path).as_ptr(),
+                libc::RTLD_NOW | libc::RTLD_LOCAL,
+            )
+        };
+        if handle.is_null() {
+            return Err(error(dlerror()));
+        }
+        // From here on, dropping the plugin closes the library.
+        let mut plugin = Plugin {
+            name: name.clone(),
+            path: path.to_owned(),
+            handle,
+            state: Box::new(HostState {
+                registrations: Registrations::default(),
+                builtins: Vec::new(),
+                events: Vec::new(),
+                initializing: true,
+            }),
+        };
+
+        let Some(version) = symbol(handle, "fish_plugin_abi_version") else {
+            return Err(error(dlerror()));
+        };
+        let version: unsafe extern "C" fn() -> u32 = unsafe { std::mem::transmute(version) };
+        let version = unsafe { version() };
+        if version != ABI_VERSION {
+            return Err(wgettext_fmt!(
+                "plugin '%ls': built for plugin interface version %u, but fish uses version %u",
+                name,
+                version,
+                ABI_VERSION
+            ));
+        }
+        let Some(init) = symbol(handle, "fish_plugin_init") else {
+            return Err(error(dlerror()));
+        };
+        let init: unsafe extern "C" fn(*const Api, *mut HostState, *mut CallState) -> c_int =
+            unsafe { std::mem::transmute(init) };
+
+        let host: *mut HostState = &mut *plugin.state;
+        let result = with_call(parser, |call| unsafe { init(&API, host, call) });
+        plugin.state.initializing = false;
+        if result.status != 0 {
+            let mut msg = wgettext_fmt!(
+                "plugin '%ls': initialization failed with status %d",
+                name,
+                result.status
+            );
+            if !result.err.is_empty() {
+                msg.push('\n');
+                msg.push_utfstr(&result.err);
+                if msg.ends_with('\n') {
+                    msg.pop();
+                }
+            }
+            return Err(msg);
+        }
+        Ok((plugin, result))
+    }
+
+    pub fn registrations(&self) -> &Registrations {
+        &self.state.registrations
+    }
+
+    pub fn call(
+        &mut self,
+        parser: &mut parser_t,
+        entry: Entry,
+        name: &wstr,
+        inputs: &[&wstr],
+    ) -> Result<CallResult, WString> {
+        // The arguments, as a NULL-terminated array with the name first.
+        let strings: Vec<CString> = std::iter::once(name)
+            .chain(inputs.iter().copied())
+            .map(wcs2zstring)
+            .collect();
+        let mut argv: Vec<*const c_char> = strings.iter().map(|s| s.as_ptr()).collect();
+        let argc = argv.len();
+        argv.push(std::ptr::null());
+
+        match entry {
+            Entry::Builtin => {
+                let &(_, func, data) = self.state.builtins.iter().find(|b| &b.0 == name).unwrap();
+                Ok(with_call(parser, |call| unsafe {
+                    func(call, data, argc, argv.as_ptr())
+                }))
+            }
+            Entry::Event => {
+                let &(_, func, data) = self.state.events.iter().find(|e| &e.0 == name).unwrap();
+                // The handler is identified by its function, but gets the name of its event.
+                let registrations = &self.state.registrations;
+                let event = registrations
+                    .events
+                    .iter()
+                    .find(|e| &e.function == name)
+                    .unwrap();
+                let event_name = wcs2zstring(&event.name);
+                argv[0] = event_name.as_ptr();
+                Ok(with_call(parser, |call| {
+                    unsafe { func(call, data, argc, argv.as_ptr()) };
+                    0
+                }))
+            }
+            Entry::Completion | Entry::Segment => {
+                unreachable!("native plugins can't register completions or prompt segments")
+            }
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        if let Some(unload) = symbol(self.handle, "fish_plugin_unload") {
+            let unload: unsafe extern "C" fn() = unsafe { std::mem::transmute(unload) };
+            unsafe { unload() };
+        }
+        unsafe { libc::dlclose(self.handle) };
+    }
+}
+
+/// Run \p f with a new call state. \return what it returned, with what it wrote and set.
+fn with_call(parser: &parser_t, f: impl FnOnce(*mut CallState) -> c_int) -> CallResult {
+    let mut call = CallState {
+        parser: parser as *const parser_t,
+        out: Vec::new(),
+        err: Vec::new(),
+        sets: Vec::new(),
+    };
+    let status = f(&mut call);
+    CallResult {
+        status,
+        out: str2wcstring(&call.out),
+        err: str2wcstring(&call.err),
+        sets: call.sets,
+    }
+}
+
+/// \return the string at \p s, or None if it is NULL or empty.
+unsafe fn read_name(s: *const c_char) -> Option<WString> {
+    if s.is_null() {
+        return None;
+    }
+    let bytes = CStr::from_ptr(s).to_bytes();
+    (!bytes.is_empty()).then(|| str2wcstring(bytes))
+}
+
+/// \return the \p len bytes at \p s.
+unsafe fn read_bytes<'a>(s: *const c_char, len: usize) -> &'a [u8] {
+    if s.is_null() || len == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(s.cast(), len)
+}
+
+unsafe extern "C" fn register_builtin(
+    host: *mut HostState,
+    name: *const c_char,
+    func: BuiltinFn,
+    data: *mut c_void,
+) -> c_int {
+    let host = &mut *host;
+    let Some(name) = read_name(name) else {
+        return -1;
+    };
+    if !host.initializing {
+        return -1;
+    }
+    if !host.registrations.builtins.contains(&name) {
+        host.registrations.builtins.push(name.clone());
+        host.builtins.push((name, func, data));
+    }
+    0
+}
+
+unsafe extern "C" fn register_event_handler(
+    host: *mut HostState,
+    kind: c_int,
+    name: *const c_char,
+    func: EventFn,
+    data: *mut c_void,
+) -> c_int {
+    let host = &mut *host;
+    let kind = match kind {
+        0 => EventKind::Generic,
+        1 => EventKind::Variable,
+        2 => EventKind::Signal,
+        _ => return -1,
+    };
+    let Some(name) = read_name(name) else {
+        return -1;
+    };
+    if !host.initializing {
+        return -1;
+    }
+    let function = sprintf!(
+        "__fish_plugin_event_%u",
+        NEXT_HANDLER.fetch_add(1, Ordering::Relaxed)
+    );
+    host.registrations.events.push(EventHandler {
+        function: function.clone(),
+        kind,
+        name,
+    });
+    host.events.push((function, func, data));
+    0
+}
+
+unsafe extern "C" fn get_var(
+    call: *mut CallState,
+    name: *const c_char,
+    buf: *mut c_char,
+    len: usize,
+) -> isize {
+    let call = &mut *call;
+    let Some(name) = read_name(name) else {
+        return -1;
+    };
+    // The plugin sees what it set itself, even though that isn't set yet.
+    let values = match call.sets.iter().rev().find(|(n, _)| *n == name) {
+        Some((_, values)) => values.clone(),
+        None => {
+            let var = (*call.parser)
+                .get_var_stack()
+                .get_or_null(&name.to_ffi(), EnvMode::DEFAULT.bits());
+            let Some(var) = var.as_ref() else {
+                return -1;
+            };
+            var.as_list_ffi().as_ref().unwrap().from_ffi()
+        }
+    };
+    let mut list = Vec::new();
+    for value in &values {
+        list.extend_from_slice(&wcs2string(value));
+        list.push(b'\0');
+    }
+    if !buf.is_null() {
+        let count = list.len().min(len);
+        std::ptr::copy_nonoverlapping(list.as_ptr(), buf.cast(), count);
+    }
+    isize::try_from(list.len()).unwrap_or(-1)
+}
+
+unsafe extern "C" fn set_var(
+    call: *mut CallState,
+    name: *const c_char,
+    values: *const c_char,
+    len: usize,
+) -> c_int {
+    let call = &mut *call;
+    let Some(name) = read_name(name) else {
+        return -1;
+    };
+    let list = read_bytes(values, len);
+    let list = list.strip_suffix(b"\0").unwrap_or(list);
+    let values: Vec<WString> = if len == 0 {
+        vec![]
+    } else {
+        list.split(|&b| b == b'\0').map(str2wcstring).collect()
+    };
+    call.sets.push((name, values));
+    0
+}
+
+unsafe extern "C" fn write_out(call: *mut CallState, s: *const c_char, len: usize) {
+    (*call).out.extend_from_slice(read_bytes(s, len));
+}
+
+unsafe extern "C" fn write_err(call: *mut CallState, s: *const c_char, len: usize) {
+    (*call).err.extend_from_slice(read_bytes(s, len));
+}
@@ -0,0 +1,80 @@
+;; A WebAssembly plugin for testing, which provides the builtin `greet`, the prompt segment
+;; `prompt` and completions for `mycmd`.
+;;
+;; The builtin copies $plugin_name to $plugin_greeted, checks that it can't set $plugin_name or read
+;; $plugin_secret, and prints its name. It returns the length of its argument list, or a status
+;; above 100 if a check failed.
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "fish" "register_builtin" (func $register_builtin (param i32 i32) (result i32)))
+  (import "fish" "register_completion" (func $register_completion (param i32 i32) (result i32)))
+  (import "fish" "register_prompt_segment" (func $register_segment (param i32 i32) (result i32)))
+  (import "fish" "get_var" (func $get_var (param i32 i32 i32 i32) (result i32)))
+  (import "fish" "set_var" (func $set_var (param i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 0) "greet")
+  (data (i32.const 8) "prompt")
+  (data (i32.const 16) "mycmd")
+  (data (i32.const 24) "plugin_name")
+  (data (i32.const 40) "plugin_greeted")
+  (data (i32.const 56) "plugin_secret")
+  (data (i32.const 72) "loaded\n")
+
+  ;; Memory from here on is handed out by fish_alloc, and never freed.
+  (global $next (mut i32) (i32.const 1024))
+
+  (func (export "fish_alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+
+  ;; Write the $len bytes at $ptr to $fd, using the iovec at 128.
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 128) (local.get $ptr))
+    (i32.store (i32.const 132) (local.get $len))
+    (drop (call $fd_write (local.get $fd) (i32.const 128) (i32.const 1) (i32.const 136))))
+
+  (func (export "fish_plugin_init") (result i32)
+    (call $write (i32.const 1) (i32.const 72) (i32.const 7))
+    (i32.or
+      (i32.or
+        (call $register_builtin (i32.const 0) (i32.const 5))
+        (call $register_segment (i32.const 8) (i32.const 6)))
+      (call $register_completion (i32.const 16) (i32.const 5))))
+
+  (func (export "fish_builtin")
+    (param $name i32) (param $name_len i32) (param $args i32) (param $args_len i32) (result i32)
+    (local $len i32)
+    ;; Nothing can be registered after fish_plugin_init.
+    (if (i32.ne (call $register_builtin (i32.const 0) (i32.const 5)) (i32.const -1))
+      (then (return (i32.const 101))))
+    (local.set $len (call $get_var (i32.const 24) (i32.const 11) (i32.const 256) (i32.const 128)))
+    (if (i32.lt_s (local.get $len) (i32.const 0))
+      (then (return (i32.const 102))))
+    (if (call $set_var (i32.const 40) (i32.const 14) (i32.const 256) (local.get $len))
+      (then (return (i32.const 103))))
+    (if (i32.ne (call $set_var (i32.const 24) (i32.const 11) (i32.const 256) (local.get $len))
+                (i32.const -1))
+      (then (return (i32.const 104))))
+    (if (i32.ne (call $get_var (i32.const 56) (i32.const 13) (i32.const 512) (i32.const 128))
+                (i32.const -1))
+      (then (return (i32.const 105))))
+    ;; The plugin sees what it set itself.
+    (if (i32.ne (call $get_var (i32.const 40) (i32.const 14) (i32.const 512) (i32.const 128))
+                (local.get $len))
+      (then (return (i32.const 106))))
+    (call $write (i32.const 1) (local.get $name) (local.get $name_len))
+    (local.get $args_len))
+
+  (func (export "fish_prompt_segment") (param $name i32) (param $name_len i32) (result i32)
+    (call $write (i32.const 1) (local.get $name) (local.get $name_len))
+    (i32.const 0))
+
+  ;; Print the commandline as the only completion.
+  (func (export "fish_complete")
+    (param $cmd i32) (param $cmd_len i32) (param $line i32) (param $line_len i32) (result i32)
+    (call $write (i32.const 1) (local.get $line) (local.get $line_len))
+    (i32.const 0)))
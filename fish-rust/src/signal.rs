@@ -4,11 +4,12 @@ use crate::common::{exit_without_destructors, restore_term_foreground_process_gr
 use crate::event::{enqueue_signal, is_signal_observed};
 use crate::termsize::termsize_handle_winch;
 use crate::topic_monitor::{generation_t, invalid_generations, topic_monitor_principal, topic_t};
-use crate::wchar::{wstr, WExt, L};
+use crate::wchar::{wstr, WExt, WString, L};
 use crate::wchar_ffi::{AsWstr, WCharToFFI};
 use crate::wutil::{fish_wcstoi, wgettext, wgettext_str, wperror};
 use cxx::{CxxWString, UniquePtr};
 use errno::{errno, set_errno};
+use once_cell::sync::Lazy;
 use std::sync::atomic::{AtomicI32, Ordering};
 use widestring_suffix::widestrs;
 
@@ -137,6 +138,7 @@ extern "C" fn fish_signal_handler(
         libc::SIGWINCH => {
             // Respond to a winch signal by telling the termsize container.
             termsize_handle_winch();
+            topic_monitor_principal().post(topic_t::sigwinch);
         }
         libc::SIGHUP => {
             // Exit unless the signal was trapped.
@@ -197,6 +199,11 @@ pub fn signal_reset_handlers() {
             libc::sigaction(data.signal.code(), &act, std::ptr::null_mut());
         };
     }
+    if let Some((min, max)) = realtime_signals() {
+        for sig in min..=max {
+            unsafe { libc::sigaction(sig, &act, std::ptr::null_mut()) };
+        }
+    }
 }
 
 // Wrapper around sigaction.
@@ -347,6 +354,15 @@ pub fn get_signals_with_handlers(set: &mut libc::sigset_t) {
             unsafe { libc::sigaddset(set, data.signal.code()) };
         }
     }
+    if let Some((min, max)) = realtime_signals() {
+        for sig in min..=max {
+            let mut act: libc::sigaction = unsafe { std::mem::zeroed() };
+            unsafe { libc::sigaction(sig, std::ptr::null(), &mut act) };
+            if act.sa_sigaction != libc::SIG_DFL {
+                unsafe { libc::sigaddset(set, sig) };
+            }
+        }
+    }
 }
 
 /// Ensure we did not inherit any blocked signals. See issue #3964.
@@ -495,6 +511,67 @@ fn match_signal_name(canonical: &wstr, mut name: &wstr) -> bool {
     equals_ascii_icase(canonical.slice_from(3), name)
 }
 
+/// \return the lowest and highest realtime signal, if the system has them.
+fn realtime_signals() -> Option<(i32, i32)> {
+    #[cfg(target_os = "linux")]
+    {
+        Some((libc::SIGRTMIN(), libc::SIGRTMAX()))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Names of the realtime signals, from SIGRTMIN on. Like in kill -l, the lower half is named
+/// relative to SIGRTMIN and the upper half relative to SIGRTMAX.
+static REALTIME_NAMES: Lazy<Vec<WString>> = Lazy::new(|| {
+    let Some((min, max)) = realtime_signals() else {
+        return Vec::new();
+    };
+    let half = (max - min) / 2;
+    (min..=max)
+        .map(|sig| {
+            let name = if sig == min {
+                "SIGRTMIN".to_owned()
+            } else if sig == max {
+                "SIGRTMAX".to_owned()
+            } else if sig - min <= half {
+                format!("SIGRTMIN+{}", sig - min)
+            } else {
+                format!("SIGRTMAX-{}", max - sig)
+            };
+            WString::from_str(&name)
+        })
+        .collect()
+});
+
+/// Parse a realtime signal name like `SIGRTMIN+3`, `RTMAX-1` or `rtmin`. The `SIG` prefix should
+/// already be stripped.
+fn parse_realtime_signal(name: &wstr) -> Option<Signal> {
+    let (min, max) = realtime_signals()?;
+    if name.char_count() < 5 {
+        return None;
+    }
+    let (base, rest) = (name.slice_to(5), name.slice_from(5));
+    let sig = if equals_ascii_icase(base, L!("rtmin")) {
+        match rest.as_char_slice().first() {
+            None => min,
+            Some('+') => min.checked_add(fish_wcstoi(rest.slice_from(1)).ok()?)?,
+            Some(_) => return None,
+        }
+    } else if equals_ascii_icase(base, L!("rtmax")) {
+        match rest.as_char_slice().first() {
+            None => max,
+            Some('-') => max.checked_sub(fish_wcstoi(rest.slice_from(1)).ok()?)?,
+            Some(_) => return None,
+        }
+    } else {
+        return None;
+    };
+    (min..=max).contains(&sig).then(|| Signal::new(sig))
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
 /// A wrapper around the system signal code.
 pub struct Signal(NonZeroI32);
@@ -519,10 +596,13 @@ impl Signal {
     /// Get string representation of a signal.
     /// Previously sig2wcs().
     pub fn name(&self) -> &'static wstr {
-        match self.get_lookup_entry() {
-            Some(entry) => entry.name,
-            None => wgettext!("Unknown"),
+        if let Some(entry) = self.get_lookup_entry() {
+            return entry.name;
+        }
+        if let Some(name) = self.realtime_offset().map(|i| &REALTIME_NAMES[i]) {
+            return name;
         }
+        wgettext!("Unknown")
     }
 
     /// Returns a description of the specified signal.
@@ -530,16 +610,26 @@ impl Signal {
     pub fn desc(&self) -> &'static wstr {
         match self.get_lookup_entry() {
             Some(entry) => wgettext_str(entry.desc),
+            None if self.realtime_offset().is_some() => wgettext!("Realtime signal"),
             None => wgettext!("Unknown"),
         }
     }
 
+    /// \return how far above SIGRTMIN we are, if we are a realtime signal.
+    fn realtime_offset(&self) -> Option<usize> {
+        let (min, max) = realtime_signals()?;
+        (min..=max)
+            .contains(&self.code())
+            .then(|| (self.code() - min) as usize)
+    }
+
     pub fn code(&self) -> i32 {
         self.0.into()
     }
     /// Parses a string into the equivalent [`Signal`] sharing the same name.
     /// Accepts both `SIGABC` and `ABC` to match against `Signal::SIGABC`. If the signal name is not
     /// recognized, `None` is returned.
+    /// Realtime signals are accepted as `SIGRTMIN+N` or `SIGRTMAX-N`, where supported.
     /// This also accepts integer codes via fish_wcstoi().
     /// Previously sig2wcs().
     pub fn parse(name: &wstr) -> Option<Signal> {
@@ -549,6 +639,16 @@ impl Signal {
             }
         }
 
+        let unprefixed =
+            if name.char_count() >= 3 && equals_ascii_icase(name.slice_to(3), L!("sig")) {
+                name.slice_from(3)
+            } else {
+                name
+            };
+        if let Some(sig) = parse_realtime_signal(unprefixed) {
+            return Some(sig);
+        }
+
         if let Ok(num) = fish_wcstoi(name) {
             if num > 0 {
                 return Some(Signal::new(num));
@@ -610,6 +710,29 @@ add_test!("test_signal_parse", || {
     assert_eq!(Signal::parse(L!("-1")), None);
 });
 
+#[cfg(target_os = "linux")]
+add_test!("test_signal_realtime", || {
+    let (min, max) = (libc::SIGRTMIN(), libc::SIGRTMAX());
+    assert_eq!(Signal::parse(L!("SIGRTMIN")), Some(Signal::new(min)));
+    assert_eq!(Signal::parse(L!("rtmin+2")), Some(Signal::new(min + 2)));
+    assert_eq!(Signal::parse(L!("SIGRTMAX-1")), Some(Signal::new(max - 1)));
+    assert_eq!(Signal::parse(L!("RTMAX")), Some(Signal::new(max)));
+    assert_eq!(Signal::parse(L!("SIGRTMIN+1000")), None);
+    assert_eq!(Signal::parse(L!("SIGRTMIN-1")), None);
+    assert_eq!(Signal::parse(L!("SIGRTMINX")), None);
+
+    assert_eq!(Signal::new(min).name(), "SIGRTMIN");
+    assert_eq!(Signal::new(min + 1).name(), "SIGRTMIN+1");
+    assert_eq!(Signal::new(max - 1).name(), "SIGRTMAX-1");
+    assert_eq!(Signal::new(max).name(), "SIGRTMAX");
+    for sig in min..=max {
+        assert_eq!(
+            Signal::parse(Signal::new(sig).name()),
+            Some(Signal::new(sig))
+        );
+    }
+});
+
 #[test]
 #[cfg(any(target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
 /// Verify bsd feature is detected on the known BSDs, which gives us greater confidence it'll work
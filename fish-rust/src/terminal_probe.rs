@@ -0,0 +1,334 @@
+//! Asking the terminal which features it supports, rather than guessing from $TERM and friends.
+//!
+//! At startup, an interactive fish sends a few queries and waits a short while for the replies:
+//! XTGETTCAP for the `RGB` and `Tc` capabilities (true color), DECRQM for mode 2026 (synchronized
+//! output), the kitty keyboard protocol's flags query, and Secondary and Primary Device Attributes
+//! (DA2 and DA1). Every terminal answers DA1, so it is sent last and its reply marks the end of the
+//! replies. Terminals ignore the queries they don't know.
+//!
+//! Anything else read in the meantime is typeahead, which is given back to the reader.
+
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+
+use crate::env::{EnvStack, Environment};
+use crate::flog::FLOG;
+use crate::wchar::L;
+
+#[cxx::bridge]
+mod terminal_probe_ffi {
+    extern "Rust" {
+        fn terminal_probe_ffi() -> Vec<u8>;
+        fn term_supports_synchronized_output() -> bool;
+        fn term_supports_kitty_keyboard() -> bool;
+    }
+}
+
+/// How long we wait for the terminal to answer. Local terminals take a few milliseconds; this
+/// leaves room for slow connections without delaying the first prompt much if nothing answers.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// The queries, in the order they are sent. DA1 must come last.
+const QUERIES: &[u8] = concat!(
+    // XTGETTCAP for "RGB" and "Tc", hex-encoded.
+    "\x1BP+q524742\x1B\\",
+    "\x1BP+q5463\x1B\\",
+    // DECRQM for synchronized output.
+    "\x1B[?2026$p",
+    // The kitty keyboard protocol's progressive enhancement flags.
+    "\x1B[?u",
+    // DA2, then DA1.
+    "\x1B[>c",
+    "\x1B[c",
+)
+.as_bytes();
+
+/// What the terminal told us about itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TerminalFeatures {
+    /// The attributes from the DA1 reply, e.g. 4 for sixel graphics. The first one is the
+    /// conformance level.
+    pub primary_attributes: Vec<u32>,
+    /// The terminal type and version from the DA2 reply.
+    pub secondary_attributes: Option<(u32, u32)>,
+    /// Whether the terminal reports the `RGB` or `Tc` capability, meaning 24-bit colors.
+    pub truecolor: bool,
+    /// Whether the terminal knows mode 2026, so updates can be drawn at once.
+    pub synchronized_output: bool,
+    /// Whether the terminal speaks the kitty keyboard protocol.
+    pub kitty_keyboard: bool,
+}
+
+/// The features found by [`probe()`], set once at startup.
+static FEATURES: OnceCell<TerminalFeatures> = OnceCell::new();
+
+/// Returns the features of the terminal, or `None` if it hasn't been probed (yet).
+pub fn terminal_features() -> Option<&'static TerminalFeatures> {
+    FEATURES.get()
+}
+
+/// Probes the terminal on `in_fd`/`out_fd` and records the results for [`terminal_features()`].
+/// Returns the typeahead read while waiting for the replies. Does nothing if we probed before.
+pub fn probe(in_fd: RawFd, out_fd: RawFd) -> Vec<u8> {
+    if FEATURES.get().is_some() {
+        return Vec::new();
+    }
+    let (features, typeahead) = query_terminal(in_fd, out_fd);
+    FLOG!(
+        term_support,
+        "Terminal features:",
+        format!("{:?}", features)
+    );
+    let _ = FEATURES.set(features);
+    typeahead
+}
+
+/// Sends the queries and reads replies until the DA1 reply or the timeout.
+fn query_terminal(in_fd: RawFd, out_fd: RawFd) -> (TerminalFeatures, Vec<u8>) {
+    let mut received = Vec::new();
+    unsafe {
+        if libc::isatty(in_fd) == 0 || libc::isatty(out_fd) == 0 {
+            return Default::default();
+        }
+
+        // Read the replies byte by byte, without echoing them. TCSANOW keeps any typeahead.
+        let mut saved: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(in_fd, &mut saved) != 0 {
+            return Default::default();
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if libc::tcsetattr(in_fd, libc::TCSANOW, &raw) != 0 {
+            return Default::default();
+        }
+
+        let written = libc::write(out_fd, QUERIES.as_ptr().cast(), QUERIES.len());
+        if written == QUERIES.len() as isize {
+            let deadline = Instant::now() + PROBE_TIMEOUT;
+            let mut buf = [0u8; 256];
+            while !parse_replies(&received).2 {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    FLOG!(term_support, "Terminal did not answer the queries in time");
+                    break;
+                }
+                let mut pfd = libc::pollfd {
+                    fd: in_fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let ret = libc::poll(&mut pfd, 1, remaining.as_millis() as libc::c_int + 1);
+                if ret <= 0 {
+                    // Interrupted, or timed out and we check the deadline again.
+                    continue;
+                }
+                let n = libc::read(in_fd, buf.as_mut_ptr().cast(), buf.len());
+                if n <= 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n as usize]);
+            }
+        }
+
+        libc::tcsetattr(in_fd, libc::TCSANOW, &saved);
+    }
+
+    let (features, typeahead, _) = parse_replies(&received);
+    (features, typeahead)
+}
+
+/// Picks the replies to our queries out of `data`. Returns the features, the remaining bytes and
+/// whether the DA1 reply, which ends the replies, was seen.
+fn parse_replies(data: &[u8]) -> (TerminalFeatures, Vec<u8>, bool) {
+    let mut features = TerminalFeatures::default();
+    let mut rest = Vec::new();
+    let mut done = false;
+    let mut i = 0;
+    while i < data.len() {
+        let Some((len, reply)) = parse_reply(&data[i..]) else {
+            rest.push(data[i]);
+            i += 1;
+            continue;
+        };
+        i += len;
+        match reply {
+            Reply::Capability(name) => {
+                if name == b"RGB" || name == b"Tc" {
+                    features.truecolor = true;
+                }
+            }
+            Reply::Mode(mode, value) => {
+                // 1 and 2 mean set and reset, 3 means permanently set.
+                if mode == 2026 && (1..=3).contains(&value) {
+                    features.synchronized_output = true;
+                }
+            }
+            Reply::KittyKeyboard => features.kitty_keyboard = true,
+            Reply::SecondaryAttributes(params) => {
+                features.secondary_attributes = Some((
+                    params.first().copied().unwrap_or(0),
+                    params.get(1).copied().unwrap_or(0),
+                ));
+            }
+            Reply::PrimaryAttributes(params) => {
+                features.primary_attributes = params;
+                done = true;
+            }
+            Reply::Other => (),
+        }
+    }
+    (features, rest, done)
+}
+
+enum Reply {
+    /// XTGETTCAP found the named capability.
+    Capability(Vec<u8>),
+    /// The DECRQM reply for a mode and its value.
+    Mode(u32, u32),
+    KittyKeyboard,
+    SecondaryAttributes(Vec<u32>),
+    PrimaryAttributes(Vec<u32>),
+    /// A reply to one of our queries that tells us nothing, like XTGETTCAP not finding a
+    /// capability.
+    Other,
+}
+
+/// Parses a single reply at the start of `data`. Returns its length and what it says, or `None`
+/// if `data` doesn't start with a (complete) reply.
+fn parse_reply(data: &[u8]) -> Option<(usize, Reply)> {
+    if data.first() != Some(&0x1B) {
+        return None;
+    }
+    match data.get(1)? {
+        b'P' => {
+            // DCS, terminated by ST.
+            let end = data.windows(2).position(|w| w == b"\x1B\\")?;
+            let body = &data[2..end];
+            let reply = if let Some(caps) = body.strip_prefix(b"1+r") {
+                // Only the name matters; the value of RGB is the number of bits per color.
+                let name = caps.split(|&c| c == b'=').next()?;
+                Reply::Capability(decode_hex(name)?)
+            } else if body.starts_with(b"0+r") {
+                Reply::Other
+            } else {
+                return None;
+            };
+            Some((end + 2, reply))
+        }
+        b'[' => {
+            // CSI: a private marker, parameters, intermediate bytes and a final byte.
+            let end = data[2..].iter().position(|c| (0x40..=0x7E).contains(c))? + 2;
+            if end < 3 {
+                return None;
+            }
+            let marker = data[2];
+            let body = &data[3..end];
+            let (params, intermediate) = match body.iter().position(|&c| c == b'$') {
+                Some(pos) => (&body[..pos], &body[pos..]),
+                None => (body, &b""[..]),
+            };
+            let params = parse_params(params)?;
+            let reply = match (marker, intermediate, data[end]) {
+                (b'?', b"", b'c') => Reply::PrimaryAttributes(params),
+                (b'>', b"", b'c') => Reply::SecondaryAttributes(params),
+                (b'?', b"", b'u') => Reply::KittyKeyboard,
+                (b'?', b"$", b'y') if params.len() == 2 => Reply::Mode(params[0], params[1]),
+                _ => return None,
+            };
+            Some((end + 1, reply))
+        }
+        _ => None,
+    }
+}
+
+/// Parses semicolon-separated numbers. Empty parameters are zero.
+fn parse_params(params: &[u8]) -> Option<Vec<u32>> {
+    if params.is_empty() {
+        return Some(Vec::new());
+    }
+    params
+        .split(|&c| c == b';')
+        .map(|p| {
+            if p.is_empty() {
+                Some(0)
+            } else {
+                std::str::from_utf8(p).ok()?.parse().ok()
+            }
+        })
+        .collect()
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Probes the terminal on stdin and stdout, updates the color support with what we learned and
+/// returns the typeahead.
+fn terminal_probe_ffi() -> Vec<u8> {
+    let vars = EnvStack::principal();
+    if vars
+        .get(L!("TERM"))
+        .map_or(false, |term| term.as_string() == "dumb")
+    {
+        return Vec::new();
+    }
+    let typeahead = probe(libc::STDIN_FILENO, libc::STDOUT_FILENO);
+    crate::env_dispatch::update_fish_color_support(vars);
+    typeahead
+}
+
+pub fn term_supports_synchronized_output() -> bool {
+    crate::curses::term().map_or(false, |term| term.supports_synchronized_output())
+}
+
+pub fn term_supports_kitty_keyboard() -> bool {
+    terminal_features().map_or(false, |f| f.kitty_keyboard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_replies, TerminalFeatures};
+
+    #[test]
+    fn test_parse_replies() {
+        // What kitty answers, with some typeahead mixed in.
+        let (features, rest, done) = parse_replies(
+            b"ls\x1BP1+r524742=382F382F38\x1B\\\x1BP0+r5463\x1B\\\x1B[?2026;2$y\x1B[?0u-l\
+              \x1B[>1;4000;29c\x1B[?62;c\x1B[A",
+        );
+        assert!(done);
+        assert_eq!(rest, b"ls-l\x1B[A");
+        assert_eq!(
+            features,
+            TerminalFeatures {
+                primary_attributes: vec![62, 0],
+                secondary_attributes: Some((1, 4000)),
+                truecolor: true,
+                synchronized_output: true,
+                kitty_keyboard: true,
+            }
+        );
+
+        // A terminal that only knows DA1.
+        let (features, rest, done) = parse_replies(b"\x1B[?1;2c");
+        assert!(done);
+        assert!(rest.is_empty());
+        assert_eq!(features.primary_attributes, [1, 2]);
+        assert!(!features.truecolor && !features.synchronized_output && !features.kitty_keyboard);
+
+        // Mode 2026 unknown (0), and a reply that isn't complete yet.
+        let (features, rest, done) = parse_replies(b"\x1B[?2026;0$y\x1B[>0;1");
+        assert!(!done);
+        assert!(!features.synchronized_output);
+        assert_eq!(rest, b"\x1B[>0;1");
+    }
+}
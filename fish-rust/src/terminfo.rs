@@ -0,0 +1,826 @@
+//! A reader for compiled terminfo entries, as found in `/usr/share/terminfo`, so we can look up
+//! terminal capabilities without going through curses and its global `cur_term`.
+//!
+//! Both the legacy format (16-bit numbers) and the ncurses 6.1 extended-number format (32-bit
+//! numbers) are supported, including the extended section with user-defined capabilities like
+//! `Smulx`. See term(5) for a description of the format.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+
+use crate::flog::FLOG;
+
+/// The magic number of the legacy format.
+const MAGIC_LEGACY: i16 = 0o432;
+/// The magic number of the extended-number format.
+const MAGIC_32BIT: i16 = 0o1036;
+
+/// The directories searched after `$TERMINFO`, `~/.terminfo` and `$TERMINFO_DIRS`, in order.
+const DEFAULT_DIRS: [&str; 4] = [
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+];
+
+/// The capabilities of a single terminal, as read from its compiled terminfo entry. Capabilities
+/// are keyed by their terminfo name (e.g. `sitm` rather than the termcap `ZH`). Absent and
+/// cancelled capabilities aren't stored.
+#[derive(Debug, Default)]
+pub struct TermInfo {
+    /// The names of the terminal, the first one being the primary name.
+    pub names: Vec<String>,
+    flags: HashMap<String, bool>,
+    numbers: HashMap<String, i32>,
+    strings: HashMap<String, CString>,
+}
+
+impl TermInfo {
+    /// Finds the entry for `name` in the terminfo directories and parses it. Returns `None` if
+    /// there is no such entry or it can't be parsed.
+    ///
+    /// The directories come from the `TERMINFO` and `TERMINFO_DIRS` environment variables, so
+    /// these need to be exported before this is called, same as with curses.
+    pub fn load(name: &CStr) -> Option<TermInfo> {
+        let name = OsStr::from_bytes(name.to_bytes());
+        let first = *name.as_bytes().first()?;
+        // An entry can't be outside of its subdirectory.
+        if name.as_bytes().contains(&b'/') {
+            return None;
+        }
+
+        for dir in search_dirs() {
+            // Entries live in a subdirectory named after their first character, or its hex value
+            // on case-insensitive filesystems.
+            for subdir in [(first as char).to_string(), format!("{:x}", first)] {
+                let path = dir.join(subdir).join(name);
+                let Ok(data) = std::fs::read(&path) else {
+                    continue;
+                };
+                let result = TermInfo::parse(&data);
+                if result.is_none() {
+                    FLOG!(
+                        term_support,
+                        "Could not parse terminfo entry",
+                        path.display()
+                    );
+                }
+                return result;
+            }
+        }
+        None
+    }
+
+    /// Parses a compiled terminfo entry. Returns `None` if it is malformed.
+    pub fn parse(data: &[u8]) -> Option<TermInfo> {
+        let mut reader = Reader { data, pos: 0 };
+        let magic = reader.i16()?;
+        let number_size = match magic {
+            MAGIC_LEGACY => 2,
+            MAGIC_32BIT => 4,
+            _ => return None,
+        };
+        let names_size = reader.count()?;
+        let flag_count = reader.count()?;
+        let number_count = reader.count()?;
+        let string_count = reader.count()?;
+        let table_size = reader.count()?;
+        if flag_count > BOOL_NAMES.len()
+            || number_count > NUMBER_NAMES.len()
+            || string_count > STRING_NAMES.len()
+        {
+            return None;
+        }
+
+        let mut result = TermInfo::default();
+        let names = reader.bytes(names_size)?;
+        let names = names.split(|&c| c == b'\0').next()?;
+        result.names = String::from_utf8_lossy(names)
+            .split('|')
+            .map(str::to_owned)
+            .collect();
+
+        let flags = reader.bytes(flag_count)?.to_vec();
+        reader.align();
+        let numbers = reader.numbers(number_count, number_size)?;
+        let offsets = reader.offsets(string_count)?;
+        let table = reader.bytes(table_size)?;
+        for (name, value) in BOOL_NAMES.iter().zip(flags) {
+            if value == 1 {
+                result.flags.insert(name.to_string(), true);
+            }
+        }
+        for (name, value) in NUMBER_NAMES.iter().zip(numbers) {
+            if let Some(value) = value {
+                result.numbers.insert(name.to_string(), value);
+            }
+        }
+        for (name, offset) in STRING_NAMES.iter().zip(offsets) {
+            if let Some(value) = offset.and_then(|o| string_at(table, o)) {
+                result.strings.insert(name.to_string(), value.to_owned());
+            }
+        }
+
+        // The extended section, if any, follows the string table after aligning.
+        reader.align();
+        if reader.pos < data.len() {
+            result.parse_extended(&mut reader, number_size)?;
+        }
+        Some(result)
+    }
+
+    fn parse_extended(&mut self, reader: &mut Reader, number_size: usize) -> Option<()> {
+        let flag_count = reader.count()?;
+        let number_count = reader.count()?;
+        let string_count = reader.count()?;
+        let _offset_count = reader.count()?;
+        let table_size = reader.count()?;
+
+        let flags = reader.bytes(flag_count)?.to_vec();
+        reader.align();
+        let numbers = reader.numbers(number_count, number_size)?;
+        let offsets = reader.offsets(string_count)?;
+        let name_offsets = reader.offsets(flag_count + number_count + string_count)?;
+        let table = reader.bytes(table_size)?;
+
+        // The names follow the string values in the table, and their offsets are relative to the
+        // end of the last value.
+        let names_start = offsets
+            .iter()
+            .flatten()
+            .filter_map(|&o| string_at(table, o).map(|s| o + s.to_bytes_with_nul().len()))
+            .max()
+            .unwrap_or(0);
+        let names = table.get(names_start..)?;
+        let mut names = name_offsets.into_iter().map(|o| {
+            o.and_then(|o| string_at(names, o))
+                .map(|s| s.to_string_lossy().into_owned())
+        });
+
+        for value in flags {
+            let name = names.next()??;
+            if value == 1 {
+                self.flags.insert(name, true);
+            }
+        }
+        for value in numbers {
+            let name = names.next()??;
+            if let Some(value) = value {
+                self.numbers.insert(name, value);
+            }
+        }
+        for offset in offsets {
+            let name = names.next()??;
+            if let Some(value) = offset.and_then(|o| string_at(table, o)) {
+                self.strings.insert(name, value.to_owned());
+            }
+        }
+        Some(())
+    }
+
+    /// Returns whether the flag capability `name` is set.
+    pub fn get_flag(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+
+    /// Returns the value of the number capability `name`, if present.
+    pub fn get_number(&self, name: &str) -> Option<i32> {
+        self.numbers.get(name).copied()
+    }
+
+    /// Returns the value of the string capability `name`, if present.
+    pub fn get_string(&self, name: &str) -> Option<&CStr> {
+        self.strings.get(name).map(CString::as_c_str)
+    }
+
+    /// Applies `o`, replacing any value the capability had.
+    pub fn apply(&mut self, o: &Override) {
+        let name = o.name();
+        self.flags.remove(name);
+        self.numbers.remove(name);
+        self.strings.remove(name);
+        match o {
+            Override::Flag(name) => {
+                self.flags.insert(name.clone(), true);
+            }
+            Override::Number(name, n) => {
+                self.numbers.insert(name.clone(), *n);
+            }
+            Override::String(name, s) => {
+                self.strings.insert(name.clone(), s.clone());
+            }
+            Override::Cancel(_) => (),
+        }
+    }
+}
+
+/// Returns the directories to look for entries in, in the same order as ncurses.
+fn search_dirs() -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        result.push(PathBuf::from(dir));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        result.push(PathBuf::from(home).join(".terminfo"));
+    }
+    let defaults = || DEFAULT_DIRS.iter().map(PathBuf::from);
+    match std::env::var_os("TERMINFO_DIRS") {
+        Some(dirs) => {
+            // An empty element stands for the default directories.
+            for dir in dirs.into_vec().split(|&c| c == b':') {
+                if dir.is_empty() {
+                    result.extend(defaults());
+                } else {
+                    result.push(PathBuf::from(OsString::from_vec(dir.to_vec())));
+                }
+            }
+        }
+        None => result.extend(defaults()),
+    }
+    result
+}
+
+/// Returns the nul-terminated string starting at `offset` in `table`.
+fn string_at(table: &[u8], offset: usize) -> Option<&CStr> {
+    let rest = table.get(offset..)?;
+    let len = rest.iter().position(|&c| c == b'\0')?;
+    CStr::from_bytes_with_nul(&rest[..=len]).ok()
+}
+
+/// A cursor over the little-endian data of a compiled entry.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let result = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(result)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        let bytes = self.bytes(2)?;
+        Some(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        let bytes = self.bytes(4)?;
+        Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a count or size from a header, which mustn't be negative.
+    fn count(&mut self) -> Option<usize> {
+        usize::try_from(self.i16()?).ok()
+    }
+
+    /// Reads `count` numbers of `size` bytes each. Negative values mean absent or cancelled.
+    fn numbers(&mut self, count: usize, size: usize) -> Option<Vec<Option<i32>>> {
+        (0..count)
+            .map(|_| {
+                let n = if size == 4 {
+                    self.i32()?
+                } else {
+                    i32::from(self.i16()?)
+                };
+                Some((n >= 0).then_some(n))
+            })
+            .collect()
+    }
+
+    /// Reads `count` offsets into a string table. Negative values mean absent or cancelled.
+    fn offsets(&mut self, count: usize) -> Option<Vec<Option<usize>>> {
+        (0..count)
+            .map(|_| Some(usize::try_from(self.i16()?).ok()))
+            .collect()
+    }
+
+    /// Sections start on even offsets.
+    fn align(&mut self) {
+        self.pos += self.pos % 2;
+    }
+}
+
+/// A change to a single capability, written in terminfo source syntax: `name=value` sets a string
+/// capability, `name#number` a number and `name` a flag, while `name@` removes the capability.
+/// Users set these in `$fish_terminfo_overrides` to fix broken entries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Override {
+    Flag(String),
+    Number(String, i32),
+    String(String, CString),
+    Cancel(String),
+}
+
+impl Override {
+    /// Parses an override like `sitm=\E[3m`. Returns `None` if it is malformed.
+    pub fn parse(s: &str) -> Option<Override> {
+        let name_len = s.find(['=', '#', '@']).unwrap_or(s.len());
+        let (name, rest) = s.split_at(name_len);
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',') {
+            return None;
+        }
+        let name = name.to_owned();
+        let result = match rest.as_bytes().first() {
+            None => Override::Flag(name),
+            Some(b'@') if rest.len() == 1 => Override::Cancel(name),
+            Some(b'#') => Override::Number(name, parse_number(&rest[1..])?),
+            Some(b'=') => Override::String(name, CString::new(unescape(&rest[1..])?).ok()?),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// The name of the capability this changes.
+    pub fn name(&self) -> &str {
+        match self {
+            Override::Flag(name)
+            | Override::Number(name, _)
+            | Override::String(name, _)
+            | Override::Cancel(name) => name,
+        }
+    }
+}
+
+/// Parses a number in terminfo source, which may be decimal, octal with a leading 0 or hex with a
+/// leading 0x.
+fn parse_number(s: &str) -> Option<i32> {
+    let n = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i32::from_str_radix(hex, 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        i32::from_str_radix(&s[1..], 8)
+    } else {
+        s.parse()
+    };
+    n.ok().filter(|&n| n >= 0)
+}
+
+/// Resolves the escapes of a string capability in terminfo source, like `\E` for escape and `^G`
+/// for control characters.
+fn unescape(s: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut chars = s.bytes().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            b'\\' => {
+                let c = chars.next()?;
+                result.push(match c {
+                    b'E' | b'e' => 0x1B,
+                    b'n' | b'l' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => 0x08,
+                    b'f' => 0x0C,
+                    b's' => b' ',
+                    b'0'..=b'7' => {
+                        let mut n = u32::from(c - b'0');
+                        for _ in 0..2 {
+                            match chars.peek() {
+                                Some(&d @ b'0'..=b'7') => {
+                                    n = n * 8 + u32::from(d - b'0');
+                                    chars.next();
+                                }
+                                _ => break,
+                            }
+                        }
+                        // A nul can't be in a capability, so curses encodes it as \200.
+                        match n {
+                            0 => 0x80,
+                            n => u8::try_from(n).ok()?,
+                        }
+                    }
+                    _ => c,
+                });
+            }
+            b'^' => {
+                let c = chars.next()?;
+                result.push(if c == b'?' { 0x7F } else { c & 0x1F });
+            }
+            _ => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+/// A parameter for [`expand()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Param {
+    Number(i32),
+    String(Vec<u8>),
+}
+
+impl Param {
+    /// Strings are treated as zero in numeric context, like curses does.
+    fn number(&self) -> i32 {
+        match self {
+            Param::Number(n) => *n,
+            Param::String(_) => 0,
+        }
+    }
+}
+
+/// Instantiates the parameterized capability string `cap` with `params`, implementing the
+/// language described in terminfo(5) like curses' `tparm()`: pushing parameters and constants,
+/// arithmetic, `%i`, `%?`/`%t`/`%e`/`%;` conditionals and printf-style output. Missing parameters
+/// are zero. Returns `None` if `cap` is malformed.
+///
+/// Padding (`$<..>`) is passed through as-is, and static variables (`%PA`) don't outlive the
+/// call.
+pub fn expand(cap: &[u8], params: &[Param]) -> Option<Vec<u8>> {
+    let mut params: Vec<Param> = params.to_vec();
+    params.resize(9, Param::Number(0));
+    let mut stack: Vec<Param> = Vec::new();
+    let mut vars: [i32; 52] = [0; 52];
+    let mut result = Vec::new();
+
+    let pop = |stack: &mut Vec<Param>| stack.pop().unwrap_or(Param::Number(0));
+    let var_index = |c: u8| match c {
+        b'a'..=b'z' => Some(usize::from(c - b'a')),
+        b'A'..=b'Z' => Some(usize::from(c - b'A') + 26),
+        _ => None,
+    };
+
+    let mut i = 0;
+    while i < cap.len() {
+        let c = cap[i];
+        i += 1;
+        if c != b'%' {
+            result.push(c);
+            continue;
+        }
+        let op = *cap.get(i)?;
+        i += 1;
+        match op {
+            b'%' => result.push(b'%'),
+            b'c' => result.push(pop(&mut stack).number() as u8),
+            b'p' => {
+                let n = cap.get(i)?.checked_sub(b'1')?;
+                i += 1;
+                stack.push(params.get(usize::from(n))?.clone());
+            }
+            b'P' => {
+                let idx = var_index(*cap.get(i)?)?;
+                i += 1;
+                vars[idx] = pop(&mut stack).number();
+            }
+            b'g' => {
+                let idx = var_index(*cap.get(i)?)?;
+                i += 1;
+                stack.push(Param::Number(vars[idx]));
+            }
+            b'\'' => {
+                let c = *cap.get(i)?;
+                if cap.get(i + 1) != Some(&b'\'') {
+                    return None;
+                }
+                i += 2;
+                stack.push(Param::Number(i32::from(c)));
+            }
+            b'{' => {
+                let len = cap[i..].iter().position(|&c| c == b'}')?;
+                let n = std::str::from_utf8(&cap[i..i + len]).ok()?.parse().ok()?;
+                i += len + 1;
+                stack.push(Param::Number(n));
+            }
+            b'l' => {
+                let len = match pop(&mut stack) {
+                    Param::String(s) => s.len() as i32,
+                    Param::Number(_) => 0,
+                };
+                stack.push(Param::Number(len));
+            }
+            b'+' | b'-' | b'*' | b'/' | b'm' | b'&' | b'|' | b'^' | b'=' | b'>' | b'<' | b'A'
+            | b'O' => {
+                let b = pop(&mut stack).number();
+                let a = pop(&mut stack).number();
+                let n = match op {
+                    b'+' => a.wrapping_add(b),
+                    b'-' => a.wrapping_sub(b),
+                    b'*' => a.wrapping_mul(b),
+                    b'/' => a.checked_div(b).unwrap_or(0),
+                    b'm' => a.checked_rem(b).unwrap_or(0),
+                    b'&' => a & b,
+                    b'|' => a | b,
+                    b'^' => a ^ b,
+                    b'=' => i32::from(a == b),
+                    b'>' => i32::from(a > b),
+                    b'<' => i32::from(a < b),
+                    b'A' => i32::from(a != 0 && b != 0),
+                    b'O' => i32::from(a != 0 || b != 0),
+                    _ => unreachable!(),
+                };
+                stack.push(Param::Number(n));
+            }
+            b'!' => {
+                let a = pop(&mut stack).number();
+                stack.push(Param::Number(i32::from(a == 0)));
+            }
+            b'~' => {
+                let a = pop(&mut stack).number();
+                stack.push(Param::Number(!a));
+            }
+            b'i' => {
+                for param in &mut params[..2] {
+                    if let Param::Number(n) = param {
+                        *n += 1;
+                    }
+                }
+            }
+            b'?' | b';' => (),
+            b't' => {
+                if pop(&mut stack).number() == 0 {
+                    // Skip to the else part or the end of the conditional.
+                    i = skip_conditional(cap, i, true)?;
+                }
+            }
+            // We only get here at the end of a then-part that was taken.
+            b'e' => i = skip_conditional(cap, i, false)?,
+            _ => {
+                // A printf-style conversion, with an optional ':' to allow flags like '-' that
+                // would otherwise be operators.
+                let start = i - 1;
+                let spec_len = cap[start..].iter().position(|c| b"doxXs".contains(c))?;
+                let spec = &cap[start..start + spec_len];
+                if !spec.iter().all(|c| b":-+# .0123456789".contains(c)) {
+                    return None;
+                }
+                let spec = spec.strip_prefix(b":").unwrap_or(spec);
+                let conv = cap[start + spec_len];
+                i = start + spec_len + 1;
+                let param = pop(&mut stack);
+                format_param(&mut result, spec, conv, &param)?;
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Returns the offset just past the `%e` (if `to_else` is set) or `%;` that matches the
+/// conditional we're in, starting at `i`.
+fn skip_conditional(cap: &[u8], mut i: usize, to_else: bool) -> Option<usize> {
+    let mut depth = 0;
+    while i < cap.len() {
+        if cap[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        let op = *cap.get(i + 1)?;
+        i += 2;
+        match op {
+            b'?' => depth += 1,
+            b';' if depth == 0 => return Some(i),
+            b';' => depth -= 1,
+            b'e' if depth == 0 && to_else => return Some(i),
+            _ => (),
+        }
+    }
+    Some(i)
+}
+
+/// Appends `param` formatted per the printf-style `spec` (flags, width and precision) and
+/// conversion `conv` to `out`.
+fn format_param(out: &mut Vec<u8>, spec: &[u8], conv: u8, param: &Param) -> Option<()> {
+    let flags_len = spec
+        .iter()
+        .position(|c| !b"-+# ".contains(c))
+        .unwrap_or(spec.len());
+    let (flags, rest) = spec.split_at(flags_len);
+    let rest = std::str::from_utf8(rest).ok()?;
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision.parse::<usize>().ok()?)),
+        None => (rest, None),
+    };
+    let width = if width.is_empty() {
+        0
+    } else {
+        width.parse::<usize>().ok()?
+    };
+    let left = flags.contains(&b'-');
+    let zero_pad = !left && precision.is_none() && width > 0 && rest.starts_with('0');
+
+    let mut body = match conv {
+        b's' => {
+            let mut s = match param {
+                Param::String(s) => s.clone(),
+                Param::Number(n) => n.to_string().into_bytes(),
+            };
+            if let Some(precision) = precision {
+                s.truncate(precision);
+            }
+            s
+        }
+        _ => {
+            let n = param.number();
+            let mut digits = match conv {
+                b'd' => n.unsigned_abs().to_string(),
+                b'o' => format!("{:o}", n),
+                b'x' => format!("{:x}", n),
+                _ => format!("{:X}", n),
+            };
+            if let Some(precision) = precision {
+                while digits.len() < precision {
+                    digits.insert(0, '0');
+                }
+            }
+            let mut prefix = String::new();
+            if conv == b'd' {
+                if n < 0 {
+                    prefix.push('-');
+                } else if flags.contains(&b'+') {
+                    prefix.push('+');
+                } else if flags.contains(&b' ') {
+                    prefix.push(' ');
+                }
+            } else if flags.contains(&b'#') && n != 0 {
+                prefix.push_str(match conv {
+                    b'o' => "0",
+                    b'x' => "0x",
+                    _ => "0X",
+                });
+            }
+            if zero_pad {
+                while prefix.len() + digits.len() < width {
+                    digits.insert(0, '0');
+                }
+            }
+            (prefix + &digits).into_bytes()
+        }
+    };
+
+    let padding = width.saturating_sub(body.len());
+    if left {
+        body.resize(body.len() + padding, b' ');
+        out.extend(body);
+    } else {
+        out.resize(out.len() + padding, b' ');
+        out.extend(body);
+    }
+    Some(())
+}
+
+/// The names of the standard capabilities, in the order they appear in compiled entries.
+const BOOL_NAMES: [&str; 44] = [
+    "bw", "am", "xsb", "xhp", "xenl", "eo", "gn", "hc", "km", "hs", "in", "da", "db", "mir",
+    "msgr", "os", "eslok", "xt", "hz", "ul", "xon", "nxon", "mc5i", "chts", "nrrmc", "npc",
+    "ndscr", "ccc", "bce", "hls", "xhpa", "crxm", "daisy", "xvpa", "sam", "cpix", "lpix", "OTbs",
+    "OTns", "OTnc", "OTMT", "OTNL", "OTpt", "OTxr",
+];
+
+const NUMBER_NAMES: [&str; 39] = [
+    "cols", "it", "lines", "lm", "xmc", "pb", "vt", "wsl", "nlab", "lh", "lw", "ma", "wnum",
+    "colors", "pairs", "ncv", "bufsz", "spinv", "spinh", "maddr", "mjump", "mcs", "mls", "npins",
+    "orc", "orl", "orhi", "orvi", "cps", "widcs", "btns", "bitwin", "bitype", "OTug", "OTdC",
+    "OTdN", "OTdB", "OTdT", "OTkn",
+];
+
+const STRING_NAMES: [&str; 414] = [
+    "cbt", "bel", "cr", "csr", "tbc", "clear", "el", "ed", "hpa", "cmdch", "cup", "cud1", "home",
+    "civis", "cub1", "mrcup", "cnorm", "cuf1", "ll", "cuu1", "cvvis", "dch1", "dl1", "dsl", "hd",
+    "smacs", "blink", "bold", "smcup", "smdc", "dim", "smir", "invis", "prot", "rev", "smso",
+    "smul", "ech", "rmacs", "sgr0", "rmcup", "rmdc", "rmir", "rmso", "rmul", "flash", "ff", "fsl",
+    "is1", "is2", "is3", "if", "ich1", "il1", "ip", "kbs", "ktbc", "kclr", "kctab", "kdch1",
+    "kdl1", "kcud1", "krmir", "kel", "ked", "kf0", "kf1", "kf10", "kf2", "kf3", "kf4", "kf5",
+    "kf6", "kf7", "kf8", "kf9", "khome", "kich1", "kil1", "kcub1", "kll", "knp", "kpp", "kcuf1",
+    "kind", "kri", "khts", "kcuu1", "rmkx", "smkx", "lf0", "lf1", "lf10", "lf2", "lf3", "lf4",
+    "lf5", "lf6", "lf7", "lf8", "lf9", "rmm", "smm", "nel", "pad", "dch", "dl", "cud", "ich",
+    "indn", "il", "cub", "cuf", "rin", "cuu", "pfkey", "pfloc", "pfx", "mc0", "mc4", "mc5", "rep",
+    "rs1", "rs2", "rs3", "rf", "rc", "vpa", "sc", "ind", "ri", "sgr", "hts", "wind", "ht", "tsl",
+    "uc", "hu", "iprog", "ka1", "ka3", "kb2", "kc1", "kc3", "mc5p", "rmp", "acsc", "pln", "kcbt",
+    "smxon", "rmxon", "smam", "rmam", "xonc", "xoffc", "enacs", "smln", "rmln", "kbeg", "kcan",
+    "kclo", "kcmd", "kcpy", "kcrt", "kend", "kent", "kext", "kfnd", "khlp", "kmrk", "kmsg", "kmov",
+    "knxt", "kopn", "kopt", "kprv", "kprt", "krdo", "kref", "krfr", "krpl", "krst", "kres", "ksav",
+    "kspd", "kund", "kBEG", "kCAN", "kCMD", "kCPY", "kCRT", "kDC", "kDL", "kslt", "kEND", "kEOL",
+    "kEXT", "kFND", "kHLP", "kHOM", "kIC", "kLFT", "kMSG", "kMOV", "kNXT", "kOPT", "kPRV", "kPRT",
+    "kRDO", "kRPL", "kRIT", "kRES", "kSAV", "kSPD", "kUND", "rfi", "kf11", "kf12", "kf13", "kf14",
+    "kf15", "kf16", "kf17", "kf18", "kf19", "kf20", "kf21", "kf22", "kf23", "kf24", "kf25", "kf26",
+    "kf27", "kf28", "kf29", "kf30", "kf31", "kf32", "kf33", "kf34", "kf35", "kf36", "kf37", "kf38",
+    "kf39", "kf40", "kf41", "kf42", "kf43", "kf44", "kf45", "kf46", "kf47", "kf48", "kf49", "kf50",
+    "kf51", "kf52", "kf53", "kf54", "kf55", "kf56", "kf57", "kf58", "kf59", "kf60", "kf61", "kf62",
+    "kf63", "el1", "mgc", "smgl", "smgr", "fln", "sclk", "dclk", "rmclk", "cwin", "wingo", "hup",
+    "dial", "qdial", "tone", "pulse", "hook", "pause", "wait", "u0", "u1", "u2", "u3", "u4", "u5",
+    "u6", "u7", "u8", "u9", "op", "oc", "initc", "initp", "scp", "setf", "setb", "cpi", "lpi",
+    "chr", "cvr", "defc", "swidm", "sdrfq", "sitm", "slm", "smicm", "snlq", "snrmq", "sshm",
+    "ssubm", "ssupm", "sum", "rwidm", "ritm", "rlm", "rmicm", "rshm", "rsubm", "rsupm", "rum",
+    "mhpa", "mcud1", "mcub1", "mcuf1", "mvpa", "mcuu1", "porder", "mcud", "mcub", "mcuf", "mcuu",
+    "scs", "smgb", "smgbp", "smglp", "smgrp", "smgt", "smgtp", "sbim", "scsd", "rbim", "rcsd",
+    "subcs", "supcs", "docr", "zerom", "csnm", "kmous", "minfo", "reqmp", "getm", "setaf", "setab",
+    "pfxl", "devt", "csin", "s0ds", "s1ds", "s2ds", "s3ds", "smglr", "smgtb", "birep", "binel",
+    "bicr", "colornm", "defbi", "endbi", "setcolor", "slines", "dispc", "smpch", "rmpch", "smsc",
+    "rmsc", "pctrm", "scesc", "scesa", "ehhlm", "elhlm", "elohlm", "erhlm", "ethlm", "evhlm",
+    "sgr1", "slength", "OTi2", "OTrs", "OTnl", "OTbc", "OTko", "OTma", "OTG2", "OTG3", "OTG1",
+    "OTG4", "OTGR", "OTGL", "OTGU", "OTGD", "OTGH", "OTGV", "OTGC", "meml", "memu", "box1",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, Override, Param, TermInfo};
+
+    #[test]
+    fn test_parse_terminfo() {
+        let info = TermInfo::parse(include_bytes!("../terminfo/xterm-256color")).unwrap();
+        assert_eq!(info.names[0], "xterm-256color");
+        assert!(info.get_flag("xenl"));
+        assert!(!info.get_flag("hz"));
+        assert_eq!(info.get_number("colors"), Some(256));
+        assert_eq!(info.get_number("wsl"), None);
+        assert_eq!(info.get_string("sitm").unwrap().to_bytes(), b"\x1B[3m");
+        assert_eq!(
+            info.get_string("cup").unwrap().to_bytes(),
+            b"\x1B[%i%p1%d;%p2%dH"
+        );
+        assert!(info.get_string("ritm").is_some());
+        assert!(info.get_string("Smulx").is_none());
+
+        // Extended capabilities.
+        assert!(info.get_flag("XT"));
+        assert_eq!(
+            info.get_string("Ms").unwrap().to_bytes(),
+            b"\x1B]52;%p1%s;%p2%s\x07"
+        );
+        let info = TermInfo::parse(include_bytes!("../terminfo/tmux")).unwrap();
+        assert_eq!(
+            info.get_string("Smulx").unwrap().to_bytes(),
+            b"\x1B[4:%p1%dm"
+        );
+
+        assert!(TermInfo::parse(b"").is_none());
+        assert!(TermInfo::parse(&include_bytes!("../terminfo/dumb")[..20]).is_none());
+    }
+
+    #[test]
+    fn test_expand() {
+        use Param::Number as N;
+        let expand = |cap: &str, params: &[Param]| {
+            expand(cap.as_bytes(), params).map(|s| String::from_utf8(s).unwrap())
+        };
+        let cup = "\x1B[%i%p1%d;%p2%dH";
+        assert_eq!(expand(cup, &[N(2), N(4)]).unwrap(), "\x1B[3;5H");
+        let setaf = "\x1B[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m";
+        assert_eq!(expand(setaf, &[N(1)]).unwrap(), "\x1B[31m");
+        assert_eq!(expand(setaf, &[N(9)]).unwrap(), "\x1B[91m");
+        assert_eq!(expand(setaf, &[N(200)]).unwrap(), "\x1B[38;5;200m");
+
+        assert_eq!(expand("%p1%02d", &[N(5)]).unwrap(), "05");
+        assert_eq!(expand("%p1%:-3d|", &[N(5)]).unwrap(), "5  |");
+        assert_eq!(expand("%p1%x %p1%#X", &[N(255)]).unwrap(), "ff 0XFF");
+        assert_eq!(expand("%p1%d", &[N(-12)]).unwrap(), "-12");
+        assert_eq!(expand("%'A'%c%{10}%p1%*%d", &[N(3)]).unwrap(), "A30");
+        assert_eq!(expand("%p1%PA%gA%gA%+%d", &[N(4)]).unwrap(), "8");
+        let s = Param::String(b"abc".to_vec());
+        assert_eq!(expand("%p1%s=%p1%l%d", &[s]).unwrap(), "abc=3");
+        // Missing parameters are zero.
+        assert_eq!(expand("%p3%d", &[]).unwrap(), "0");
+        assert_eq!(expand("100%%", &[]).unwrap(), "100%");
+
+        assert_eq!(expand("%p", &[]), None);
+        assert_eq!(expand("%{12", &[]), None);
+        assert_eq!(expand("%z", &[]), None);
+    }
+
+    #[test]
+    fn test_override() {
+        let parse = |s: &str| Override::parse(s);
+        let string = |name: &str, value: &[u8]| {
+            Some(Override::String(
+                name.to_owned(),
+                std::ffi::CString::new(value).unwrap(),
+            ))
+        };
+        assert_eq!(parse("sitm=\\E[3m"), string("sitm", b"\x1B[3m"));
+        assert_eq!(
+            parse("Smulx=\\E[4:%p1%dm"),
+            string("Smulx", b"\x1B[4:%p1%dm")
+        );
+        assert_eq!(parse("bel=^G\\072\\0"), string("bel", b"\x07:\x80"));
+        assert_eq!(parse("dim="), string("dim", b""));
+        assert_eq!(
+            parse("colors#256"),
+            Some(Override::Number("colors".into(), 256))
+        );
+        assert_eq!(
+            parse("colors#0x100"),
+            Some(Override::Number("colors".into(), 256))
+        );
+        assert_eq!(parse("it#010"), Some(Override::Number("it".into(), 8)));
+        assert_eq!(parse("xenl"), Some(Override::Flag("xenl".into())));
+        assert_eq!(parse("xenl@"), Some(Override::Cancel("xenl".into())));
+
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("=\\E[3m"), None);
+        assert_eq!(parse("colors#lots"), None);
+        assert_eq!(parse("colors#-1"), None);
+        assert_eq!(parse("xenl@@"), None);
+        assert_eq!(parse("sitm=\\"), None);
+
+        let mut info = TermInfo::parse(include_bytes!("../terminfo/xterm-256color")).unwrap();
+        info.apply(&parse("colors#16").unwrap());
+        info.apply(&parse("xenl@").unwrap());
+        info.apply(&parse("Smulx=\\E[4:%p1%dm").unwrap());
+        assert_eq!(info.get_number("colors"), Some(16));
+        assert!(!info.get_flag("xenl"));
+        assert!(info.get_string("Smulx").is_some());
+    }
+}
@@ -188,3 +188,148 @@ add_test!("fd_monitor_items", || {
     assert_eq!(item_pokee.total_calls.load(Ordering::Relaxed), 1);
     assert_eq!(item_pokee.pokes.load(Ordering::Relaxed), 1);
 });
+
+#[cfg(target_os = "linux")]
+add_test!("fd_monitor_io_uring", || {
+    use crate::fd_readable_set::FdReadableSet;
+    use crate::io_uring::IoUringReadableSet;
+
+    // io_uring may be unavailable, for example if the kernel is too old or it is disabled.
+    let Some(mut set) = IoUringReadableSet::new() else {
+        return;
+    };
+
+    let mut pipes = make_autoclose_pipes().expect("fds exhausted!");
+    let fd = pipes.read.as_raw_fd();
+    set.add(fd);
+    assert_eq!(set.check_readable(1000), 0);
+    assert!(!set.test(fd));
+
+    pipes.write.write_all(b"x").unwrap();
+    set.clear();
+    set.add(fd);
+    assert_eq!(set.check_readable(FdReadableSet::kNoTimeout), 1);
+    assert!(set.test(fd));
+
+    // The data was not consumed, so the re-armed poll fires again.
+    set.clear();
+    set.add(fd);
+    assert_eq!(set.check_readable(1000), 1);
+
+    // A new fd which reuses the number of a closed one gets its own poll.
+    set.forget(fd);
+    drop(pipes);
+    let pipes = make_autoclose_pipes().expect("fds exhausted!");
+    let fd = pipes.read.as_raw_fd();
+    set.clear();
+    set.add(fd);
+    assert_eq!(set.check_readable(1000), 0);
+    assert!(!set.test(fd));
+});
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+add_test!("fd_monitor_event_loop", || {
+    use crate::event_loop::EventLoop;
+    use crate::fd_readable_set::FdReadableSet;
+    use std::time::Instant;
+
+    let mut set = EventLoop::new().expect("Could not create event loop");
+
+    // The timeout is kept, even though no fd becomes readable.
+    let mut pipes = make_autoclose_pipes().expect("fds exhausted!");
+    let fd = pipes.read.as_raw_fd();
+    set.add(fd);
+    let start = Instant::now();
+    assert_eq!(set.check_readable(20_000), 0);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert!(!set.test(fd));
+
+    pipes.write.write_all(b"x").unwrap();
+    set.clear();
+    set.add(fd);
+    assert_eq!(set.check_readable(FdReadableSet::kNoTimeout), 1);
+    assert!(set.test(fd));
+
+    // Fds which are no longer added are not reported.
+    set.clear();
+    assert_eq!(set.check_readable(1000), 0);
+    assert!(!set.test(fd));
+
+    // A new fd which reuses the number of a closed one is registered anew.
+    set.forget(fd);
+    drop(pipes);
+    let pipes = make_autoclose_pipes().expect("fds exhausted!");
+    let fd = pipes.read.as_raw_fd();
+    set.clear();
+    set.add(fd);
+    assert_eq!(set.check_readable(1000), 0);
+    assert!(!set.test(fd));
+});
+
+add_test!("fd_monitor_timers", || {
+    let monitor = FdMonitor::new();
+
+    let make_timer = |timeout| {
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let callback_reasons = Arc::clone(&reasons);
+        let id = monitor.add_timer(
+            timeout,
+            Box::new(move |fd: &mut AutoCloseFd, reason| {
+                assert!(!fd.is_valid());
+                callback_reasons.lock().unwrap().push(reason);
+            }),
+        );
+        (id, reasons)
+    };
+
+    // A timer which expires, and one which is poked before it would.
+    let (_, expired) = make_timer(Duration::from_millis(16));
+    let (poked_id, poked) = make_timer(Duration::from_millis(100_000_000));
+    monitor.poke_item(poked_id);
+
+    for _ in 0..100 {
+        std::thread::sleep(Duration::from_millis(84));
+        if !expired.lock().unwrap().is_empty() && !poked.lock().unwrap().is_empty() {
+            break;
+        }
+    }
+
+    drop(monitor);
+
+    // Timers are only ever invoked once.
+    assert!(*expired.lock().unwrap() == [ItemWakeReason::Timeout]);
+    assert!(*poked.lock().unwrap() == [ItemWakeReason::Poke]);
+});
+
+add_test!("fd_monitor_timer_order", || {
+    let monitor = FdMonitor::new();
+
+    // Timers fire in the order of their deadlines, not in the order they were added.
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    for millis in [60, 20, 40] {
+        let fired = Arc::clone(&fired);
+        monitor.add_timer(
+            Duration::from_millis(millis),
+            Box::new(move |_fd: &mut AutoCloseFd, reason| {
+                assert!(reason == ItemWakeReason::Timeout);
+                fired.lock().unwrap().push(millis);
+            }),
+        );
+    }
+
+    for _ in 0..100 {
+        std::thread::sleep(Duration::from_millis(20));
+        if fired.lock().unwrap().len() == 3 {
+            break;
+        }
+    }
+
+    drop(monitor);
+
+    assert!(*fired.lock().unwrap() == [20, 40, 60]);
+});
@@ -1 +1,2 @@
 mod fd_monitor;
+mod topic_monitor;
@@ -0,0 +1,31 @@
+use crate::ffi_tests::add_test;
+use crate::topic_monitor::{
+    invalid_generation, new_topic_monitor, topic_monitor_t, topic_subscription_t, topic_t,
+};
+
+add_test!("topic_subscription", || {
+    let monitor: &'static topic_monitor_t = Box::leak(new_topic_monitor());
+
+    // Posts from before subscribing are not reported.
+    monitor.post(topic_t::sigwinch);
+    let mut sub = topic_subscription_t::new(monitor, &[topic_t::sigwinch, topic_t::uvar_change]);
+    assert!(!sub.check(false));
+    assert!(sub.is_subscribed(topic_t::uvar_change));
+    assert!(!sub.is_subscribed(topic_t::job_stopcont));
+    assert_eq!(sub.generation(topic_t::job_stopcont), invalid_generation);
+
+    // Topics we did not subscribe to are ignored.
+    monitor.post(topic_t::job_stopcont);
+    assert!(!sub.check(false));
+
+    let gen = sub.generation(topic_t::uvar_change);
+    monitor.post(topic_t::uvar_change);
+    assert!(sub.check(false));
+    assert_eq!(sub.generation(topic_t::uvar_change), gen + 1);
+    assert!(!sub.check(false));
+
+    // Waiting returns once another thread posts.
+    let poster = std::thread::spawn(move || monitor.post(topic_t::sigwinch));
+    assert!(sub.check(true));
+    poster.join().unwrap();
+});
@@ -0,0 +1,427 @@
+//! Vi text objects: the parts of the command line that `iw`, `a(`, `i"` and friends work on.
+//!
+//! Quotes and brackets are found the way the tokenizer sees them, so a bracket that is quoted,
+//! escaped or in a comment doesn't count. Brackets and quotes may span several lines; words end at
+//! the end of the line.
+
+use crate::tokenizer::{
+    is_token_delimiter, TokenType, Tokenizer, TOK_ACCEPT_UNFINISHED, TOK_CONTINUE_AFTER_ERROR,
+    TOK_SHOW_COMMENTS,
+};
+use crate::wchar::wstr;
+use crate::wchar_ffi::WCharFromFFI;
+use cxx::CxxWString;
+use std::ops::Range;
+
+#[cxx::bridge]
+mod text_object_ffi {
+    /// The range of a text object, if one was found.
+    struct text_object_range_t {
+        found: bool,
+        start: usize,
+        end: usize,
+    }
+
+    extern "Rust" {
+        #[cxx_name = "text_object_range"]
+        fn text_object_range_ffi(
+            text: &CxxWString,
+            cursor: usize,
+            object: u32,
+            around: bool,
+        ) -> text_object_range_t;
+    }
+}
+
+use text_object_ffi::text_object_range_t;
+
+/// Returns the range of the text object at `cursor`, or `None` if there is none. `object` is the
+/// character that names it in vi, like `w` in `diw`, and `around` is true for the `a` variant,
+/// which includes the quotes or brackets, or the blanks around a word.
+///
+/// Any other character is taken as a delimiter on both sides, so `di/` deletes the path
+/// component under the cursor.
+pub fn text_object_range(
+    text: &wstr,
+    cursor: usize,
+    object: char,
+    around: bool,
+) -> Option<Range<usize>> {
+    let chars = text.as_char_slice();
+    if chars.is_empty() {
+        return None;
+    }
+    let cursor = cursor.min(chars.len() - 1);
+    match object {
+        'w' => word_range(chars, cursor, false, around),
+        'W' => word_range(chars, cursor, true, around),
+        '"' | '\'' => quote_range(text, cursor, object, around),
+        '(' | ')' | 'b' => bracket_range(text, cursor, '(', ')', around),
+        '[' | ']' => bracket_range(text, cursor, '[', ']', around),
+        '{' | '}' | 'B' => bracket_range(text, cursor, '{', '}', around),
+        '<' | '>' => bracket_range(text, cursor, '<', '>', around),
+        _ => delimiter_range(chars, cursor, object, around),
+    }
+}
+
+fn text_object_range_ffi(
+    text: &CxxWString,
+    cursor: usize,
+    object: u32,
+    around: bool,
+) -> text_object_range_t {
+    let range = char::from_u32(object)
+        .and_then(|object| text_object_range(&text.from_ffi(), cursor, object, around));
+    match range {
+        Some(range) => text_object_range_t {
+            found: true,
+            start: range.start,
+            end: range.end,
+        },
+        None => text_object_range_t {
+            found: false,
+            start: 0,
+            end: 0,
+        },
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Newline,
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char, bigword: bool) -> CharClass {
+    match c {
+        '\n' => CharClass::Newline,
+        ' ' | '\t' => CharClass::Blank,
+        _ if bigword || c.is_alphanumeric() || c == '_' => CharClass::Word,
+        _ => CharClass::Punctuation,
+    }
+}
+
+/// The run of characters of the same class as the one under the cursor. With `around`, the blanks
+/// after a word (or else before it) are included, or the word after blanks.
+fn word_range(chars: &[char], cursor: usize, bigword: bool, around: bool) -> Option<Range<usize>> {
+    let class = |i: usize| char_class(chars[i], bigword);
+    let run_start = |mut i: usize| {
+        let c = class(i);
+        while i > 0 && class(i - 1) == c {
+            i -= 1;
+        }
+        i
+    };
+    let run_end = |mut i: usize| {
+        let c = class(i);
+        while i < chars.len() && class(i) == c {
+            i += 1;
+        }
+        i
+    };
+
+    if class(cursor) == CharClass::Newline {
+        return None;
+    }
+    let start = run_start(cursor);
+    let end = run_end(cursor);
+    if !around {
+        return Some(start..end);
+    }
+    let continues = |i: usize| i < chars.len() && class(i) != CharClass::Newline;
+    if class(cursor) == CharClass::Blank {
+        if continues(end) {
+            return Some(start..run_end(end));
+        }
+    } else if continues(end) && class(end) == CharClass::Blank {
+        return Some(start..run_end(end));
+    } else if start > 0 && class(start - 1) == CharClass::Blank {
+        return Some(run_start(start - 1)..end);
+    }
+    Some(start..end)
+}
+
+/// A quoted string in the command line.
+struct Quoted {
+    /// The position of the opening quote.
+    open: usize,
+    /// The end of the quoted text, which is the position of the closing quote if there is one.
+    inner_end: usize,
+    /// The end including the closing quote.
+    end: usize,
+}
+
+/// Where the quotes are in a command line.
+struct Quoting {
+    /// For each character, whether it is syntax, i.e. not quoted, escaped or in a comment.
+    code: Vec<bool>,
+    quotes: Vec<Quoted>,
+}
+
+fn scan_quoting(text: &wstr) -> Quoting {
+    let chars = text.as_char_slice();
+    let mut result = Quoting {
+        code: vec![true; chars.len()],
+        quotes: vec![],
+    };
+    let flags = TOK_ACCEPT_UNFINISHED | TOK_SHOW_COMMENTS | TOK_CONTINUE_AFTER_ERROR;
+    for tok in Tokenizer::new(text, flags) {
+        let range = tok.offset()..tok.offset() + tok.length();
+        match tok.type_ {
+            TokenType::comment => result.code[range].fill(false),
+            TokenType::string => scan_string(chars, range, &mut result),
+            _ => (),
+        }
+    }
+    result
+}
+
+/// Finds the quotes and escapes in a string token. Command substitutions in double quotes, like
+/// `"$(echo "hi")"`, are syntax again and may have quotes of their own.
+fn scan_string(chars: &[char], range: Range<usize>, result: &mut Quoting) {
+    #[derive(Clone, Copy)]
+    enum Context {
+        /// A quote, with its position.
+        Quote(char, usize),
+        /// A command substitution in double quotes, with the number of parentheses open in it.
+        Subst(usize),
+    }
+    let mut stack: Vec<Context> = vec![];
+    let mut i = range.start;
+    while i < range.end {
+        let c = chars[i];
+        let next = if i + 1 < range.end {
+            Some(chars[i + 1])
+        } else {
+            None
+        };
+        match stack.last().copied() {
+            Some(Context::Quote(quote, open)) => {
+                result.code[i] = false;
+                if c == '\\' && (quote == '"' || matches!(next, Some('\\' | '\''))) {
+                    if next.is_some() {
+                        result.code[i + 1] = false;
+                    }
+                    i += 2;
+                    continue;
+                }
+                if c == quote {
+                    stack.pop();
+                    result.quotes.push(Quoted {
+                        open,
+                        inner_end: i,
+                        end: i + 1,
+                    });
+                } else if quote == '"' && c == '$' && next == Some('(') {
+                    stack.push(Context::Subst(0));
+                    i += 2;
+                    continue;
+                }
+            }
+            context => match c {
+                '\\' => {
+                    result.code[i] = false;
+                    if next.is_some() {
+                        result.code[i + 1] = false;
+                    }
+                    i += 2;
+                    continue;
+                }
+                '\'' | '"' => {
+                    result.code[i] = false;
+                    stack.push(Context::Quote(c, i));
+                }
+                '#' if i > range.start && is_token_delimiter(chars[i - 1], Some(c)) => {
+                    // A comment in a command substitution runs to the end of the line.
+                    let end = chars[i..range.end]
+                        .iter()
+                        .position(|&c| c == '\n')
+                        .map_or(range.end, |len| i + len);
+                    result.code[i..end].fill(false);
+                    i = end;
+                    continue;
+                }
+                '(' => {
+                    if let Some(Context::Subst(depth)) = context {
+                        *stack.last_mut().unwrap() = Context::Subst(depth + 1);
+                    }
+                }
+                ')' => match context {
+                    Some(Context::Subst(0)) => {
+                        stack.pop();
+                    }
+                    Some(Context::Subst(depth)) => {
+                        *stack.last_mut().unwrap() = Context::Subst(depth - 1);
+                    }
+                    _ => (),
+                },
+                _ => (),
+            },
+        }
+        i += 1;
+    }
+    // Quotes that are still open run to the end of the token.
+    for context in stack {
+        if let Context::Quote(_, open) = context {
+            result.quotes.push(Quoted {
+                open,
+                inner_end: range.end,
+                end: range.end,
+            });
+        }
+    }
+}
+
+/// The innermost quoted string around the cursor, or else the next one on the same line.
+fn quote_range(text: &wstr, cursor: usize, quote: char, around: bool) -> Option<Range<usize>> {
+    let chars = text.as_char_slice();
+    let quoting = scan_quoting(text);
+    let candidates = quoting.quotes.iter().filter(|q| chars[q.open] == quote);
+    let found = candidates
+        .clone()
+        .filter(|q| q.open <= cursor && cursor < q.end)
+        .max_by_key(|q| q.open)
+        .or_else(|| {
+            candidates
+                .filter(|q| q.open > cursor && !chars[cursor..q.open].contains(&'\n'))
+                .min_by_key(|q| q.open)
+        })?;
+    Some(if around {
+        found.open..found.end
+    } else {
+        found.open + 1..found.inner_end
+    })
+}
+
+/// The innermost pair of brackets around the cursor.
+fn bracket_range(
+    text: &wstr,
+    cursor: usize,
+    open: char,
+    close: char,
+    around: bool,
+) -> Option<Range<usize>> {
+    let chars = text.as_char_slice();
+    let code = scan_quoting(text).code;
+    let is = |i: usize, c: char| chars[i] == c && code[i];
+
+    // Go back to the first open bracket that isn't closed before the cursor. A close bracket under
+    // the cursor belongs to the pair we are looking for.
+    let mut depth = 0;
+    let mut start = None;
+    for i in (0..=cursor).rev() {
+        if is(i, close) && i != cursor {
+            depth += 1;
+        } else if is(i, open) {
+            if depth == 0 {
+                start = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let start = start?;
+
+    let mut depth = 0;
+    let mut end = None;
+    for i in start + 1..chars.len() {
+        if is(i, open) {
+            depth += 1;
+        } else if is(i, close) {
+            if depth == 0 {
+                end = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let end = end?;
+
+    Some(if around {
+        start..end + 1
+    } else {
+        start + 1..end
+    })
+}
+
+/// The text between the closest `delimiter` at or before the cursor and the next one.
+fn delimiter_range(
+    chars: &[char],
+    cursor: usize,
+    delimiter: char,
+    around: bool,
+) -> Option<Range<usize>> {
+    let start = chars[..=cursor].iter().rposition(|&c| c == delimiter)?;
+    let end = start + 1 + chars[start + 1..].iter().position(|&c| c == delimiter)?;
+    Some(if around {
+        start..end + 1
+    } else {
+        start + 1..end
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::text_object_range;
+    use crate::wchar::WString;
+
+    #[test]
+    fn test_text_object_range() {
+        // The cursor is at the `^`, which is removed from the text.
+        let tests: &[(&str, &str, Option<&str>)] = &[
+            ("echo fo^o bar", "iw", Some("foo")),
+            ("echo fo^o bar", "aw", Some("foo ")),
+            ("echo foo ba^r", "aw", Some(" bar")),
+            ("echo foo^.bar baz", "iw", Some(".")),
+            ("echo foo^.bar baz", "iW", Some("foo.bar")),
+            ("echo^  foo", "iw", Some("  ")),
+            ("echo^  foo", "aw", Some("  foo")),
+            ("echo foo\nb^ar", "aw", Some("bar")),
+            ("echo foo^\nbar", "iw", None),
+            ("echo \"a ^b\" c", "i\"", Some("a b")),
+            ("echo \"a ^b\" c", "a\"", Some("\"a b\"")),
+            ("ec^ho 'a b' c", "i'", Some("a b")),
+            ("ec^ho 'a b'\necho 'c'", "i'", Some("a b")),
+            ("echo a^ 'b'\n'c'", "a'", Some("'b'")),
+            ("echo\n^'c'", "i\"", None),
+            ("echo 'a\\'^b'", "i'", Some("a\\'b")),
+            ("echo \"a $(echo 'b^c') d\"", "i'", Some("bc")),
+            (
+                "echo \"a $(echo 'bc') ^d\"",
+                "i\"",
+                Some("a $(echo 'bc') d"),
+            ),
+            ("echo \"open^", "i\"", Some("open")),
+            ("echo (a (b^ c) d)", "i(", Some("b c")),
+            ("echo (a (b c) ^d)", "a)", Some("(a (b c) d)")),
+            ("echo (a (b c^) d)", "ib", Some("b c")),
+            ("echo ^(a)", "i(", Some("a")),
+            ("echo (a \")\" ^b)", "i(", Some("a \")\" b")),
+            ("echo (a \\) ^b)", "i(", Some("a \\) b")),
+            ("echo a # (b^)", "i(", None),
+            ("echo (a # )\n^b)", "i(", Some("a # )\nb")),
+            ("echo \"$(echo ^a)\"", "i(", Some("echo a")),
+            ("echo {a,^b}", "iB", Some("a,b")),
+            ("echo $a[1^ 2]", "a[", Some("[1 2]")),
+            ("echo (a^", "i(", None),
+            ("echo ^a", "i(", None),
+            ("echo ()^", "i(", Some("")),
+            ("cd /usr/l^ocal/bin", "i/", Some("local")),
+            ("cd /usr/l^ocal/bin", "a/", Some("/local/")),
+        ];
+        for (marked, object, expected) in tests {
+            let cursor = marked.find('^').unwrap();
+            let text = WString::from_str(&marked.replacen('^', "", 1));
+            let mut object_chars = object.chars();
+            let around = object_chars.next() == Some('a');
+            let object_char = object_chars.next().unwrap();
+            let cursor = marked[..cursor].chars().count();
+            let range = text_object_range(&text, cursor, object_char, around);
+            let found: Option<String> =
+                range.map(|range| text.as_char_slice()[range].iter().collect());
+            assert_eq!(found.as_deref(), *expected, "{} {}", object, marked);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! The rusty version of iothreads from the cpp code, to be consumed by native rust code. This isn't
 //! ported directly from the cpp code so we can use rust threads instead of using pthreads.
 
+use crate::fd_monitor::ItemWakeReason;
 use crate::flog::{FloggableDebug, FLOG};
 use once_cell::race::OnceBox;
 use std::num::NonZeroU64;
@@ -10,6 +11,7 @@ use std::thread::{self, ThreadId};
 use std::time::{Duration, Instant};
 
 impl FloggableDebug for ThreadId {}
+impl FloggableDebug for iothread_priority_t {}
 
 // We don't want to use a full-blown Lazy<T> for the cached main thread id, but we can't use
 // AtomicU64 since std::thread::ThreadId::as_u64() is a nightly-only feature (issue #67939,
@@ -29,6 +31,11 @@ static IS_FORKED_PROC: AtomicBool = AtomicBool::new(false);
 /// Maximum number of threads for the IO thread pool.
 const IO_MAX_THREADS: usize = 1024;
 
+/// Maximum number of threads running best-effort work at once, so that slow autosuggestions or
+/// highlighting (e.g. on a hung network filesystem) can't take all the threads and delay work the
+/// user is waiting for.
+const IO_MAX_BEST_EFFORT_THREADS: usize = 4;
+
 /// How long an idle [`ThreadPool`] thread will wait for work (against the condition variable)
 /// before exiting.
 const IO_WAIT_FOR_WORK_DURATION: Duration = Duration::from_millis(500);
@@ -51,6 +58,34 @@ static NOTIFY_SIGNALLER: once_cell::sync::Lazy<&'static crate::fd_monitor::FdEve
 
 #[cxx::bridge]
 mod ffi {
+    /// The priority classes of work on the IO thread pool. Queued user-blocking work always runs
+    /// before best-effort work.
+    #[repr(u8)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum iothread_priority_t {
+        user_blocking, // Work the user is waiting for, like loading history.
+        best_effort,   // Work the user can do without, like autosuggestions and highlighting.
+    }
+
+    /// Statistics about one priority class of the IO thread pool.
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct iothread_stats_t {
+        /// How many work items are waiting to run.
+        pub queued: usize,
+        /// The most work items that were ever waiting to run at once.
+        pub peak_queued: usize,
+        /// How many work items are running.
+        pub running: usize,
+        /// The most work items that may run at once.
+        pub max_running: usize,
+        /// How many work items finished.
+        pub completed: u64,
+        /// How many threads there are in the pool, across all classes.
+        pub threads: usize,
+        /// How many of them are idle, across all classes.
+        pub idle_threads: usize,
+    }
+
     unsafe extern "C++" {
         include!("callback.h");
 
@@ -84,12 +119,15 @@ mod ffi {
         fn iothread_service_main();
         #[cxx_name = "iothread_service_main_with_timeout"]
         fn iothread_service_main_with_timeout_ffi(timeout_usec: u64);
+        #[cxx_name = "iothread_wake_main_after"]
+        fn iothread_wake_main_after_ffi(timeout_usec: u64);
         #[cxx_name = "iothread_drain_all"]
         fn iothread_drain_all_ffi();
         #[cxx_name = "iothread_perform"]
         fn iothread_perform_ffi(callback: &SharedPtr<CppCallback>);
         #[cxx_name = "iothread_perform_cantwait"]
         fn iothread_perform_cant_wait_ffi(callback: &SharedPtr<CppCallback>);
+        fn iothread_stats(priority: iothread_priority_t) -> iothread_stats_t;
     }
 
     extern "Rust" {
@@ -106,10 +144,12 @@ mod ffi {
         ) -> u64;
 
         #[cxx_name = "new_debounce_t"]
-        fn new_debounce_ffi(timeout_ms: u64) -> Box<Debounce>;
+        fn new_debounce_ffi(timeout_ms: u64, priority: iothread_priority_t) -> Box<Debounce>;
     }
 }
 
+pub use ffi::{iothread_priority_t, iothread_stats_t};
+
 unsafe impl Send for ffi::CppCallback {}
 unsafe impl Sync for ffi::CppCallback {}
 
@@ -117,6 +157,10 @@ fn iothread_service_main_with_timeout_ffi(timeout_usec: u64) {
     iothread_service_main_with_timeout(Duration::from_micros(timeout_usec))
 }
 
+fn iothread_wake_main_after_ffi(timeout_usec: u64) {
+    iothread_wake_main_after(Duration::from_micros(timeout_usec));
+}
+
 fn iothread_drain_all_ffi() {
     unsafe { iothread_drain_all() }
 }
@@ -165,7 +209,11 @@ pub fn init() {
     }
 
     IO_THREAD_POOL
-        .set(Box::new(Mutex::new(ThreadPool::new(1, IO_MAX_THREADS))))
+        .set(Box::new(Mutex::new(ThreadPool::new(
+            1,
+            IO_MAX_THREADS,
+            IO_MAX_BEST_EFFORT_THREADS,
+        ))))
         .expect("IO_THREAD_POOL has already been initialized!");
 }
 
@@ -268,6 +316,7 @@ pub fn spawn<F: FnOnce() + Send + 'static>(callback: F) -> bool {
     // automatically if the returned join handle is dropped.
 
     let result = match std::thread::Builder::new().spawn(move || {
+        let _registration = crate::watchdog::register_thread("background");
         (callback)();
     }) {
         Ok(handle) => {
@@ -328,25 +377,84 @@ pub fn asan_before_exit() {
     }
 }
 
+/// The number of [`iothread_priority_t`] classes.
+const PRIORITY_COUNT: usize = 2;
+
+impl iothread_priority_t {
+    /// The index of this class in per-class arrays.
+    fn index(self) -> usize {
+        match self {
+            iothread_priority_t::user_blocking => 0,
+            iothread_priority_t::best_effort => 1,
+            _ => panic!("invalid priority"),
+        }
+    }
+
+    /// All classes, from the highest priority to the lowest.
+    fn all() -> [iothread_priority_t; PRIORITY_COUNT] {
+        [
+            iothread_priority_t::user_blocking,
+            iothread_priority_t::best_effort,
+        ]
+    }
+}
+
 /// Data shared between the thread pool [`ThreadPool`] and worker threads [`WorkerThread`].
 #[derive(Default)]
 struct ThreadPoolProtected {
-    /// The queue of outstanding, unclaimed work requests
-    pub request_queue: std::collections::VecDeque<WorkItem>,
+    /// The queues of outstanding, unclaimed work requests, one per priority class
+    pub request_queues: [std::collections::VecDeque<WorkItem>; PRIORITY_COUNT],
+    /// The number of work requests running, per priority class
+    pub running: [usize; PRIORITY_COUNT],
+    /// The longest each queue has been
+    pub peak_queued: [usize; PRIORITY_COUNT],
+    /// The number of work requests completed, per priority class
+    pub completed: [u64; PRIORITY_COUNT],
     /// The number of threads that exist in the pool
     pub total_threads: usize,
     /// The number of threads waiting for more work (i.e. idle threads)
     pub waiting_threads: usize,
 }
 
+impl ThreadPoolProtected {
+    /// The number of queued work requests which a thread could start now, given the concurrency
+    /// caps of their classes.
+    fn runnable(&self, max_running: &[usize; PRIORITY_COUNT]) -> usize {
+        (0..PRIORITY_COUNT)
+            .map(|i| {
+                let slots = max_running[i].saturating_sub(self.running[i]);
+                self.request_queues[i].len().min(slots)
+            })
+            .sum()
+    }
+
+    /// Claim the next work request a thread may start, highest priority first.
+    fn claim(
+        &mut self,
+        max_running: &[usize; PRIORITY_COUNT],
+    ) -> Option<(WorkItem, iothread_priority_t)> {
+        for priority in iothread_priority_t::all() {
+            let i = priority.index();
+            if self.running[i] < max_running[i] {
+                if let Some(item) = self.request_queues[i].pop_front() {
+                    self.running[i] += 1;
+                    return Some((item, priority));
+                }
+            }
+        }
+        None
+    }
+}
+
 /// Data behind an [`Arc`] to share between the [`ThreadPool`] and [`WorkerThread`] instances.
-#[derive(Default)]
 struct ThreadPoolShared {
     /// The mutex to access shared state between [`ThreadPool`] and [`WorkerThread`] instances. This
     /// is accessed both standalone and via [`cond_var`](Self::cond_var).
     mutex: Mutex<ThreadPoolProtected>,
     /// The condition variable used to wake up waiting threads. This is tied to [`mutex`](Self::mutex).
     cond_var: std::sync::Condvar,
+    /// The most work requests of each priority class which may run at once.
+    max_running: [usize; PRIORITY_COUNT],
 }
 
 pub struct ThreadPool {
@@ -364,32 +472,69 @@ impl std::fmt::Debug for ThreadPool {
         f.debug_struct("ThreadPool")
             .field("min_threads", &self.soft_min_threads)
             .field("max_threads", &self.max_threads)
+            .field("max_running", &self.shared.max_running)
             .finish()
     }
 }
 
 impl ThreadPool {
-    /// Construct a new `ThreadPool` instance with the specified min and max num of threads.
-    pub fn new(soft_min_threads: usize, max_threads: usize) -> Self {
+    /// Construct a new `ThreadPool` instance with the specified min and max num of threads, and the
+    /// max num of threads which may run best-effort work at once.
+    pub fn new(soft_min_threads: usize, max_threads: usize, max_best_effort: usize) -> Self {
         ThreadPool {
-            shared: Default::default(),
+            shared: Arc::new(ThreadPoolShared {
+                mutex: Default::default(),
+                cond_var: Default::default(),
+                max_running: [max_threads, max_best_effort.min(max_threads)],
+            }),
             soft_min_threads,
             max_threads,
         }
     }
 
-    /// Enqueue a new work item onto the thread pool.
+    /// Enqueue a new user-blocking work item onto the thread pool.
     ///
     /// The function `func` will execute on one of the pool's background threads. If `cant_wait` is
     /// set, the thread limit may be disregarded if extant threads are busy.
     ///
     /// Returns the number of threads that were alive when the work item was enqueued.
     pub fn perform<F: FnOnce() + 'static + Send>(&mut self, func: F, cant_wait: bool) -> usize {
+        self.perform_with_priority(func, iothread_priority_t::user_blocking, cant_wait)
+    }
+
+    /// Enqueue a new work item of the given priority class onto the thread pool. Best-effort work
+    /// waits while the class is at its concurrency cap, even if `cant_wait` is set.
+    pub fn perform_with_priority<F: FnOnce() + 'static + Send>(
+        &mut self,
+        func: F,
+        priority: iothread_priority_t,
+        cant_wait: bool,
+    ) -> usize {
         let work_item = Box::new(func);
-        self.perform_inner(work_item, cant_wait)
+        self.perform_inner(work_item, priority, cant_wait)
+    }
+
+    /// Return statistics about the given priority class.
+    pub fn stats(&self, priority: iothread_priority_t) -> iothread_stats_t {
+        let i = priority.index();
+        let data = self.shared.mutex.lock().expect("Mutex poisoned!");
+        iothread_stats_t {
+            queued: data.request_queues[i].len(),
+            peak_queued: data.peak_queued[i],
+            running: data.running[i],
+            max_running: self.shared.max_running[i],
+            completed: data.completed[i],
+            threads: data.total_threads,
+            idle_threads: data.waiting_threads,
+        }
     }
 
-    fn perform_inner(&mut self, f: WorkItem, cant_wait: bool) -> usize {
+    fn perform_inner(
+        &mut self,
+        f: WorkItem,
+        priority: iothread_priority_t,
+        cant_wait: bool,
+    ) -> usize {
         enum ThreadAction {
             None,
             Wake,
@@ -398,16 +543,27 @@ impl ThreadPool {
 
         let local_thread_count;
         let thread_action = {
+            let i = priority.index();
+            let max_running = &self.shared.max_running;
             let mut data = self.shared.mutex.lock().expect("Mutex poisoned!");
             local_thread_count = data.total_threads;
-            data.request_queue.push_back(f);
+            let runnable_before = data.runnable(max_running);
+            data.request_queues[i].push_back(f);
+            data.peak_queued[i] = data.peak_queued[i].max(data.request_queues[i].len());
+            let runnable = data.runnable(max_running);
             FLOG!(
                 iothread,
-                "enqueuing work item (count is ",
-                data.request_queue.len(),
+                "enqueuing",
+                priority,
+                "work item (count is ",
+                data.request_queues[i].len(),
                 ")"
             );
-            if data.waiting_threads >= data.request_queue.len() {
+            if runnable == runnable_before {
+                // The class is at its concurrency cap. A thread running work of this class will
+                // pick the item up when it is done.
+                ThreadAction::None
+            } else if data.waiting_threads >= runnable {
                 // There are enough waiting threads, wake one up.
                 ThreadAction::Wake
             } else if cant_wait || data.total_threads < self.max_threads {
@@ -476,16 +632,20 @@ pub struct WorkerThread {
 impl WorkerThread {
     /// The worker loop entry point for this thread.
     fn run(mut self) {
-        while let Some(work_item) = self.dequeue_work_or_commit_to_exit() {
+        let mut finished = None;
+        while let Some((work_item, priority)) = self.dequeue_work_or_commit_to_exit(finished) {
             FLOG!(
                 iothread,
                 "pthread ",
                 std::thread::current().id(),
-                " got work"
+                " got",
+                priority,
+                "work"
             );
 
             // Perform the work
             work_item();
+            finished = Some(priority);
         }
 
         FLOG!(
@@ -497,13 +657,22 @@ impl WorkerThread {
     }
 
     /// Dequeue a work item (perhaps waiting on the condition variable) or commit to exiting by
-    /// reducing the active thread count.
-    fn dequeue_work_or_commit_to_exit(&mut self) -> Option<WorkItem> {
+    /// reducing the active thread count. `finished` is the priority class of the work item this
+    /// thread just completed, if any.
+    fn dequeue_work_or_commit_to_exit(
+        &mut self,
+        finished: Option<iothread_priority_t>,
+    ) -> Option<(WorkItem, iothread_priority_t)> {
+        let max_running = &self.shared.max_running;
         let mut data = self.shared.mutex.lock().expect("Mutex poisoned!");
+        if let Some(priority) = finished {
+            data.running[priority.index()] -= 1;
+            data.completed[priority.index()] += 1;
+        }
 
-        // If the queue is empty, check to see if we should wait. We should wait if our exiting
-        // would drop us below our soft thread count minimum.
-        if data.request_queue.is_empty()
+        // If nothing can run, check to see if we should wait. We should wait if our exiting would
+        // drop us below our soft thread count minimum.
+        if data.runnable(max_running) == 0
             && data.total_threads == self.soft_min_threads
             && IO_WAIT_FOR_WORK_DURATION > Duration::ZERO
         {
@@ -517,8 +686,8 @@ impl WorkerThread {
             data.waiting_threads -= 1;
         }
 
-        // Now that we've (perhaps) waited, see if there's something on the queue.
-        let result = data.request_queue.pop_front();
+        // Now that we've (perhaps) waited, see if there's something on the queues we may run.
+        let result = data.claim(max_running);
 
         // If we are returning None then ensure we balance the thread count increment from when we
         // were created. This has to be done here in this awkward place because we've already
@@ -541,12 +710,26 @@ fn borrow_io_thread_pool() -> std::sync::MutexGuard<'static, ThreadPool> {
         .expect("Mutex poisoned!")
 }
 
-/// Enqueues work on the IO thread pool singleton.
+/// Enqueues user-blocking work on the IO thread pool singleton.
 pub fn iothread_perform(f: impl FnOnce() + 'static + Send) {
     let mut thread_pool = borrow_io_thread_pool();
     thread_pool.perform(f, false);
 }
 
+/// Enqueues work of the given priority class on the IO thread pool singleton.
+pub fn iothread_perform_with_priority(
+    priority: iothread_priority_t,
+    f: impl FnOnce() + 'static + Send,
+) {
+    let mut thread_pool = borrow_io_thread_pool();
+    thread_pool.perform_with_priority(f, priority, false);
+}
+
+/// Return statistics about the given priority class of the IO thread pool singleton.
+pub fn iothread_stats(priority: iothread_priority_t) -> iothread_stats_t {
+    borrow_io_thread_pool().stats(priority)
+}
+
 /// Enqueues priority work on the IO thread pool singleton, disregarding the thread limit.
 ///
 /// It does its best to spawn a thread if all other threads are occupied. This is primarily for
@@ -582,6 +765,22 @@ pub fn iothread_service_main() {
     }
 }
 
+/// Make [`iothread_port()`] readable once `timeout` has passed, with a timer on the fd monitor, so
+/// the main thread wakes up to poll something. Does nothing if such a wakeup is already pending.
+pub fn iothread_wake_main_after(timeout: Duration) {
+    static PENDING: AtomicBool = AtomicBool::new(false);
+    if PENDING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    crate::io::fd_monitor().add_timer(
+        timeout.max(Duration::from_micros(1)),
+        Box::new(|_fd, _reason| {
+            PENDING.store(false, Ordering::Relaxed);
+            NOTIFY_SIGNALLER.post();
+        }),
+    );
+}
+
 /// Does nasty polling via select() and marked as unsafe because it should only be used for testing.
 pub unsafe fn iothread_drain_all() {
     while borrow_io_thread_pool()
@@ -609,6 +808,8 @@ pub unsafe fn iothread_drain_all() {
 #[derive(Clone)]
 pub struct Debounce {
     timeout: Duration,
+    /// The priority class of the thread pool work.
+    priority: iothread_priority_t,
     /// The data shared between [`Debounce`] instances.
     data: Arc<Mutex<DebounceData>>,
 }
@@ -626,14 +827,23 @@ struct DebounceData {
     start_time: Instant,
 }
 
-fn new_debounce_ffi(timeout_ms: u64) -> Box<Debounce> {
-    Box::new(Debounce::new(Duration::from_millis(timeout_ms)))
+fn new_debounce_ffi(timeout_ms: u64, priority: iothread_priority_t) -> Box<Debounce> {
+    Box::new(Debounce::with_priority(
+        Duration::from_millis(timeout_ms),
+        priority,
+    ))
 }
 
 impl Debounce {
     pub fn new(timeout: Duration) -> Self {
+        Self::with_priority(timeout, iothread_priority_t::user_blocking)
+    }
+
+    /// Construct a `Debounce` whose work runs in the given priority class of the IO thread pool.
+    pub fn with_priority(timeout: Duration, priority: iothread_priority_t) -> Self {
         Self {
             timeout,
+            priority,
             data: Arc::new(Mutex::new(DebounceData {
                 next_req: None,
                 active_token: None,
@@ -728,8 +938,7 @@ impl Debounce {
     }
 
     fn perform_inner(&self, handler: WorkItem) -> NonZeroU64 {
-        let mut spawn = false;
-        let active_token = {
+        let (active_token, spawn) = {
             let mut data = self.data.lock().expect("Mutex poisoned!");
             data.next_req = Some(handler);
             // If we have a timeout and our running thread has exceeded it, abandon that thread.
@@ -740,31 +949,85 @@ impl Debounce {
                 // Abandon this thread by dissociating its token from this [`Debounce`] instance.
                 data.active_token = None;
             }
-            if data.active_token.is_none() {
-                // We need to spawn a new thread. Mark the current time so that a new request won't
-                // immediately abandon us and start a new thread too.
-                spawn = true;
-                data.active_token = Some(data.next_token);
-                data.next_token = data.next_token.checked_add(1).unwrap();
-                data.start_time = Instant::now();
-            }
-            data.active_token.expect("Something should be active now.")
+            Self::claim_thread(&mut data)
         };
 
         // Spawn after unlocking the mutex above.
         if spawn {
-            // We need to clone the Arc to get it to last for the duration of the 'static lifetime.
-            let debounce = self.clone();
-            iothread_perform(move || {
-                while debounce.run_next(active_token) {
-                    // Keep thread alive/busy.
-                }
-            });
+            self.spawn_thread(active_token);
         }
 
         active_token
     }
 
+    /// Returns the token of the active thread, and whether it is a new thread which needs to be
+    /// spawned because none was active.
+    fn claim_thread(data: &mut DebounceData) -> (NonZeroU64, bool) {
+        if let Some(token) = data.active_token {
+            return (token, false);
+        }
+        // Mark the current time so that a new request won't immediately abandon us and start a
+        // new thread too.
+        let token = data.next_token;
+        data.active_token = Some(token);
+        data.next_token = data.next_token.checked_add(1).unwrap();
+        data.start_time = Instant::now();
+        (token, true)
+    }
+
+    /// Spawn the thread with the given token, which runs requests until there are none left. If
+    /// we have a timeout, a timer on the fd monitor checks on the thread when it passes.
+    fn spawn_thread(&self, token: NonZeroU64) {
+        // We need to clone the Arc to get it to last for the duration of the 'static lifetime.
+        let debounce = self.clone();
+        iothread_perform_with_priority(self.priority, move || {
+            while debounce.run_next(token) {
+                // Keep thread alive/busy.
+            }
+        });
+        if !self.timeout.is_zero() {
+            self.arm_timer(token, self.timeout);
+        }
+    }
+
+    fn arm_timer(&self, token: NonZeroU64, timeout: Duration) {
+        let debounce = self.clone();
+        crate::io::fd_monitor().add_timer(
+            timeout,
+            Box::new(move |_fd, reason| {
+                if reason == ItemWakeReason::Timeout {
+                    debounce.check_on_thread(token);
+                }
+            }),
+        );
+    }
+
+    /// Called on the fd monitor's thread when the timer for the thread with the given token
+    /// expires. If that thread is still running a request which exceeded the timeout, and another
+    /// request is waiting, abandon the thread and spawn a new one for the waiting request. Without
+    /// this, the request would wait until the next one is enqueued.
+    fn check_on_thread(&self, token: NonZeroU64) {
+        let new_token = {
+            let mut data = self.data.lock().expect("Mutex poisoned!");
+            if data.active_token != Some(token) {
+                // The thread is done or was abandoned.
+                return;
+            }
+            let elapsed = Instant::now() - data.start_time;
+            if elapsed <= self.timeout || data.next_req.is_none() {
+                // The thread moved on to a newer request, or nothing waits for it yet. Check again
+                // when its request times out.
+                drop(data);
+                let remaining = self.timeout.checked_sub(elapsed).unwrap_or(self.timeout);
+                self.arm_timer(token, remaining.max(Duration::from_millis(1)));
+                return;
+            }
+            data.active_token = None;
+            Self::claim_thread(&mut data).0
+        };
+        self.spawn_thread(new_token);
+    }
+
     /// Static helper to add a [`WorkItem`] to [`MAIN_THREAD_ID`] and signal [`NOTIFY_SIGNALLER`].
     fn enqueue_main_thread_result(f: WorkItem) {
         MAIN_THREAD_QUEUE.lock().expect("Mutex poisoned!").push(f);
@@ -836,3 +1099,62 @@ fn std_thread_inherits_sigmask() {
         assert_eq!(result, 0, "Failed to restore sigmask!");
     }
 }
+
+#[test]
+/// Verify that best-effort work is capped, and doesn't hold up user-blocking work.
+fn thread_pool_priorities() {
+    use std::sync::mpsc;
+    let mut pool = ThreadPool::new(0, 8, 1);
+
+    // Block two best-effort items. Only one may run at once.
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let release_rx = Arc::new(Mutex::new(release_rx));
+    let (done_tx, done_rx) = mpsc::channel::<&'static str>();
+    for _ in 0..2 {
+        let release_rx = Arc::clone(&release_rx);
+        let done_tx = done_tx.clone();
+        pool.perform_with_priority(
+            move || {
+                release_rx.lock().unwrap().recv().unwrap();
+                done_tx.send("best-effort").unwrap();
+            },
+            iothread_priority_t::best_effort,
+            false,
+        );
+    }
+
+    // User-blocking work still runs.
+    let user_tx = done_tx.clone();
+    pool.perform(move || user_tx.send("user-blocking").unwrap(), false);
+    assert_eq!(
+        done_rx.recv_timeout(Duration::from_secs(5)),
+        Ok("user-blocking")
+    );
+
+    // The first best-effort item may not have been picked up yet.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.stats(iothread_priority_t::best_effort).running == 0 {
+        assert!(Instant::now() < deadline);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let stats = pool.stats(iothread_priority_t::best_effort);
+    assert_eq!(stats.running, 1);
+    assert_eq!(stats.max_running, 1);
+    assert_eq!(stats.queued, 1);
+    assert_eq!(stats.peak_queued, 2);
+
+    // Once released, the queued best-effort item runs too.
+    for _ in 0..2 {
+        release_tx.send(()).unwrap();
+        assert_eq!(
+            done_rx.recv_timeout(Duration::from_secs(5)),
+            Ok("best-effort")
+        );
+    }
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.stats(iothread_priority_t::best_effort).completed < 2 {
+        assert!(Instant::now() < deadline);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(pool.stats(iothread_priority_t::user_blocking).completed, 1);
+}
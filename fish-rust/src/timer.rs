@@ -14,6 +14,7 @@
 //! but it's still the best we can do because we don't know how long of a time might elapse between
 //! `TimerSnapshot` instances and need to avoid rollover.
 
+use once_cell::sync::Lazy;
 use std::io::Write;
 use std::time::{Duration, Instant};
 
@@ -23,9 +24,21 @@ mod timer_ffi {
         type PrintElapsedOnDropFfi;
         #[cxx_name = "push_timer"]
         fn push_timer_ffi(enabled: bool) -> Box<PrintElapsedOnDropFfi>;
+        #[cxx_name = "timer_monotonic_usec"]
+        fn monotonic_micros() -> u64;
     }
 }
 
+/// The point [`monotonic_micros()`] counts from.
+static MONOTONIC_EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Returns the microseconds elapsed on a monotonic clock since an arbitrary point which is fixed
+/// for the lifetime of the process. Only the difference between two values is meaningful; unlike
+/// the wall clock, it is not affected by changes to the system time.
+pub fn monotonic_micros() -> u64 {
+    MONOTONIC_EPOCH.elapsed().as_micros() as u64
+}
+
 enum Unit {
     Minutes,
     Seconds,
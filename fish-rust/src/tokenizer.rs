@@ -461,10 +461,19 @@ impl Iterator for Tokenizer {
                     self.token_cursor += redir.consumed;
                     Some(result)
                 } else {
+                    // &--queue also backgrounds, but waits for a free slot first.
+                    let queue = L!("&--queue");
+                    let length = if buff.starts_with(queue)
+                        && !tok_is_string_character(buff.char_at(queue.len()), None)
+                    {
+                        queue.len()
+                    } else {
+                        1
+                    };
                     let mut result = Tok::new(TokenType::background);
                     result.offset = start_pos as u32;
-                    result.length = 1;
-                    self.token_cursor+=1;
+                    result.length = length as u32;
+                    self.token_cursor += length;
                     Some(result)
                 }
             }
@@ -18,13 +18,15 @@ generation value is larger than any value previously queried.
 Tying this all together is the topic_monitor_t. This provides the current topic generations, and
 also provides the ability to perform a blocking wait for any topic to change in a particular topic
 set. This is the real power of topics: you can wait for a sigchld signal OR a thread exit.
+
+To watch some topics without managing generation lists, use a topic_subscription_t.
 */
 
 use crate::fd_readable_set::fd_readable_set_t;
 use crate::fds::{self, AutoClosePipes};
 use crate::ffi::{self as ffi, c_int};
 use crate::flog::{FloggableDebug, FLOG};
-use crate::wchar::{widestrs, wstr, WString};
+use crate::wchar::{widestrs, wstr, WString, L};
 use crate::wchar_ffi::wcharz;
 use nix::errno::Errno;
 use nix::unistd;
@@ -45,6 +47,9 @@ mod topic_monitor_ffi {
         pub sighupint: u64,
         pub sigchld: u64,
         pub internal_exit: u64,
+        pub sigwinch: u64,
+        pub job_stopcont: u64,
+        pub uvar_change: u64,
     }
 
     extern "Rust" {
@@ -62,6 +67,9 @@ mod topic_monitor_ffi {
         sighupint,     // Corresponds to both SIGHUP and SIGINT signals.
         sigchld,       // Corresponds to SIGCHLD signal.
         internal_exit, // Corresponds to an internal process exit.
+        sigwinch,      // Corresponds to SIGWINCH signal, when the terminal is resized.
+        job_stopcont,  // Corresponds to a job stopping or continuing.
+        uvar_change,   // Corresponds to universal variables changing, here or in another fish.
     }
 
     extern "Rust" {
@@ -74,6 +82,13 @@ mod topic_monitor_ffi {
         fn generation_for_topic(self: &topic_monitor_t, topic: topic_t) -> u64;
         fn check(self: &topic_monitor_t, gens: *mut generation_list_t, wait: bool) -> bool;
     }
+
+    extern "Rust" {
+        type topic_subscription_t;
+        fn new_topic_subscription(topics: &[topic_t]) -> Box<topic_subscription_t>;
+        fn check(self: &mut topic_subscription_t, wait: bool) -> bool;
+        fn generation(self: &topic_subscription_t, topic: topic_t) -> u64;
+    }
 }
 
 pub use topic_monitor_ffi::{generation_list_t, topic_t};
@@ -84,8 +99,15 @@ impl FloggableDebug for topic_t {}
 /// A generation value which indicates the topic is not of interest.
 pub const invalid_generation: generation_t = std::u64::MAX;
 
-pub fn all_topics() -> [topic_t; 3] {
-    [topic_t::sighupint, topic_t::sigchld, topic_t::internal_exit]
+pub fn all_topics() -> [topic_t; 6] {
+    [
+        topic_t::sighupint,
+        topic_t::sigchld,
+        topic_t::internal_exit,
+        topic_t::sigwinch,
+        topic_t::job_stopcont,
+        topic_t::uvar_change,
+    ]
 }
 
 #[widestrs]
@@ -115,6 +137,9 @@ impl generation_list_t {
             topic_t::sighupint => &mut self.sighupint,
             topic_t::sigchld => &mut self.sigchld,
             topic_t::internal_exit => &mut self.internal_exit,
+            topic_t::sigwinch => &mut self.sigwinch,
+            topic_t::job_stopcont => &mut self.job_stopcont,
+            topic_t::uvar_change => &mut self.uvar_change,
             _ => panic!("invalid topic"),
         }
     }
@@ -125,13 +150,23 @@ impl generation_list_t {
             topic_t::sighupint => self.sighupint,
             topic_t::sigchld => self.sigchld,
             topic_t::internal_exit => self.internal_exit,
+            topic_t::sigwinch => self.sigwinch,
+            topic_t::job_stopcont => self.job_stopcont,
+            topic_t::uvar_change => self.uvar_change,
             _ => panic!("invalid topic"),
         }
     }
 
     /// \return ourselves as an array.
-    pub fn as_array(&self) -> [generation_t; 3] {
-        [self.sighupint, self.sigchld, self.internal_exit]
+    pub fn as_array(&self) -> [generation_t; 6] {
+        [
+            self.sighupint,
+            self.sigchld,
+            self.internal_exit,
+            self.sigwinch,
+            self.job_stopcont,
+            self.uvar_change,
+        ]
     }
 
     /// Set the value of \p topic to the smaller of our value and the value in \p other.
@@ -163,6 +198,9 @@ impl generation_list_t {
             sighupint: invalid_generation,
             sigchld: invalid_generation,
             internal_exit: invalid_generation,
+            sigwinch: invalid_generation,
+            job_stopcont: invalid_generation,
+            uvar_change: invalid_generation,
         }
     }
 }
@@ -207,6 +245,8 @@ impl binary_semaphore_t {
             let pipes = fds::make_autoclose_pipes();
             assert!(pipes.is_some(), "Failed to make pubsub pipes");
             pipes_ = pipes.unwrap();
+            fds::track_fd(pipes_.read.fd(), L!("topic monitor"));
+            fds::track_fd(pipes_.write.fd(), L!("topic monitor"));
 
             // Whoof. Thread Sanitizer swallows signals and replays them at its leisure, at the point
             // where instrumented code makes certain blocking calls. But tsan cannot interrupt a signal
@@ -622,8 +662,10 @@ impl topic_monitor_t {
                 break;
             }
 
-            // Wait until our gens change.
+            // Wait until our gens change. The main thread is not stuck while it waits.
+            crate::watchdog::watchdog_idle();
             current = self.await_gens(&current);
+            crate::watchdog::watchdog_busy();
         }
         return changed;
     }
@@ -642,3 +684,49 @@ pub fn topic_monitor_principal() -> &'static topic_monitor_t {
         &*s_principal
     }
 }
+
+/// A subscription to some topics of a topic monitor, which remembers the generations it has seen.
+/// Unlike a raw generation_list_t, topics which are not subscribed to are never reported.
+pub struct topic_subscription_t {
+    monitor: &'static topic_monitor_t,
+    gens: generation_list_t,
+}
+
+impl topic_subscription_t {
+    /// Subscribe to \p topics of \p monitor. Only posts after this call are reported.
+    pub fn new(monitor: &'static topic_monitor_t, topics: &[topic_t]) -> Self {
+        let current = monitor.current_generations();
+        let mut gens = generation_list_t::invalids();
+        for &topic in topics {
+            *gens.at_mut(topic) = current.at(topic);
+        }
+        Self { monitor, gens }
+    }
+
+    /// Subscribe to \p topics of the principal topic monitor.
+    pub fn principal(topics: &[topic_t]) -> Self {
+        Self::new(topic_monitor_principal(), topics)
+    }
+
+    /// \return whether any subscribed topic was posted to since the last check, or since we
+    /// subscribed. If \p wait is set, block until one is.
+    pub fn check(&mut self, wait: bool) -> bool {
+        self.monitor.check(&mut self.gens, wait)
+    }
+
+    /// \return the generation of \p topic as of the last check, or invalid_generation if we are
+    /// not subscribed to it.
+    pub fn generation(&self, topic: topic_t) -> generation_t {
+        self.gens.at(topic)
+    }
+
+    /// \return whether we are subscribed to \p topic.
+    pub fn is_subscribed(&self, topic: topic_t) -> bool {
+        self.gens.is_valid(topic)
+    }
+}
+
+/// Subscribe to topics of the principal topic monitor. Exposed for the FFI.
+pub fn new_topic_subscription(topics: &[topic_t]) -> Box<topic_subscription_t> {
+    Box::new(topic_subscription_t::principal(topics))
+}
@@ -0,0 +1,278 @@
+//! A watchdog which notices when the main thread is stuck, to help debug reports of fish freezing.
+//!
+//! The main thread counts as busy unless it is waiting for input, or for jobs or signals. If it
+//! stays busy for longer than [`STALL_THRESHOLD`], the watchdog logs the stacks of fish's threads
+//! to the `stall` FLOG category, once per stall. It only runs if that category is enabled, like
+//! with `fish -d stall -o /tmp/fish.log`.
+//!
+//! Stacks are captured by sending [`WATCHDOG_SIGNAL`] to each thread, whose handler records the
+//! return addresses, which the watchdog then symbolizes. Threads started by [`threads::spawn`]
+//! register themselves for this with [`register_thread`].
+//!
+//! [`threads::spawn`]: crate::threads::spawn
+
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+use crate::flog::{should_flog, FLOG};
+use crate::threads::{assert_is_main_thread, is_main_thread};
+
+#[cxx::bridge]
+mod watchdog_ffi {
+    extern "Rust" {
+        fn watchdog_start();
+        fn watchdog_idle();
+        fn watchdog_busy();
+    }
+}
+
+/// How long the main thread may be busy before we report it.
+const STALL_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// How often the watchdog checks on the main thread.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long to wait for a thread to record its stack.
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// The most frames we record per thread.
+const MAX_FRAMES: usize = 64;
+
+/// The signal used to ask a thread for its stack.
+const WATCHDOG_SIGNAL: libc::c_int = libc::SIGPROF;
+
+/// Whether the watchdog is running.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// When the main thread became busy, in milliseconds since [`EPOCH`] plus one, or 0 if it is idle.
+static BUSY_SINCE: AtomicU64 = AtomicU64::new(0);
+
+/// Whether we have reported the current stall already.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// The time from which [`BUSY_SINCE`] counts.
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// The threads we can capture stacks of, as their pthread ids and names.
+static THREADS: Mutex<Vec<(libc::pthread_t, &'static str)>> = Mutex::new(Vec::new());
+
+/// The return addresses recorded by the signal handler. Only one thread is captured at a time.
+#[allow(clippy::declare_interior_mutable_const)]
+const ATOMIC_USIZE_0: AtomicUsize = AtomicUsize::new(0);
+static CAPTURED_FRAMES: [AtomicUsize; MAX_FRAMES] = [ATOMIC_USIZE_0; MAX_FRAMES];
+
+/// The number of frames in [`CAPTURED_FRAMES`], or -1 while a capture is pending.
+static CAPTURED_COUNT: AtomicI32 = AtomicI32::new(-1);
+
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+extern "C" {
+    fn backtrace(buffer: *mut *mut libc::c_void, size: libc::c_int) -> libc::c_int;
+    fn backtrace_symbols(
+        buffer: *const *mut libc::c_void,
+        size: libc::c_int,
+    ) -> *mut *mut libc::c_char;
+}
+
+fn now_ms() -> u64 {
+    EPOCH.elapsed().as_millis() as u64
+}
+
+/// Start the watchdog, if the `stall` FLOG category is enabled. This must be called from the main
+/// thread.
+pub fn watchdog_start() {
+    assert_is_main_thread();
+    if !should_flog!(stall) || RUNNING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    Lazy::force(&EPOCH);
+    THREADS
+        .lock()
+        .unwrap()
+        .push((unsafe { libc::pthread_self() }, "main"));
+
+    // Capture our own stack once, so the signal handler doesn't need to load anything.
+    capture_frames();
+
+    let mut act: libc::sigaction = unsafe { std::mem::zeroed() };
+    act.sa_sigaction = watchdog_signal_handler as usize;
+    act.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    unsafe {
+        libc::sigemptyset(&mut act.sa_mask);
+        libc::sigaction(WATCHDOG_SIGNAL, &act, std::ptr::null_mut());
+    }
+
+    watchdog_busy();
+    crate::threads::spawn(watchdog_loop);
+}
+
+/// Note that the main thread is about to wait for input, jobs or signals, so it is not stuck.
+/// This does nothing on other threads.
+pub fn watchdog_idle() {
+    if RUNNING.load(Ordering::Relaxed) && is_main_thread() {
+        BUSY_SINCE.store(0, Ordering::Relaxed);
+        REPORTED.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Note that the main thread is done waiting. This does nothing on other threads.
+pub fn watchdog_busy() {
+    if RUNNING.load(Ordering::Relaxed) && is_main_thread() {
+        BUSY_SINCE.store(now_ms() + 1, Ordering::Relaxed);
+    }
+}
+
+/// Register the calling thread, so its stack can be captured. This is called by threads started
+/// with [`crate::threads::spawn`]; the thread is unregistered when the returned guard is dropped.
+pub fn register_thread(name: &'static str) -> ThreadRegistration {
+    // Threads start with all signals blocked, but we need ours.
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, WATCHDOG_SIGNAL);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+    }
+    let this = unsafe { libc::pthread_self() };
+    THREADS.lock().unwrap().push((this, name));
+    ThreadRegistration { thread: this }
+}
+
+/// Unregisters a thread from the watchdog when dropped.
+pub struct ThreadRegistration {
+    thread: libc::pthread_t,
+}
+
+impl Drop for ThreadRegistration {
+    fn drop(&mut self) {
+        let mut threads = THREADS.lock().unwrap();
+        if let Some(idx) = threads
+            .iter()
+            .position(|&(t, _)| unsafe { libc::pthread_equal(t, self.thread) } != 0)
+        {
+            threads.swap_remove(idx);
+        }
+    }
+}
+
+fn watchdog_loop() {
+    loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        let since = BUSY_SINCE.load(Ordering::Relaxed);
+        if since == 0 || REPORTED.load(Ordering::Relaxed) {
+            continue;
+        }
+        let busy = Duration::from_millis(now_ms().saturating_sub(since - 1));
+        if busy < STALL_THRESHOLD {
+            continue;
+        }
+        REPORTED.store(true, Ordering::Relaxed);
+        report_stall(busy);
+    }
+}
+
+/// Log the stacks of all registered threads.
+fn report_stall(busy: Duration) {
+    let mut report = format!(
+        "The main thread has been busy for {} ms. Please include this in bug reports about fish \
+         freezing.",
+        busy.as_millis()
+    );
+    // Hold the lock so threads can't exit while we signal them.
+    let threads = THREADS.lock().unwrap();
+    let this = unsafe { libc::pthread_self() };
+    for &(thread, name) in threads.iter() {
+        if unsafe { libc::pthread_equal(thread, this) } != 0 {
+            continue;
+        }
+        report.push_str(&format!("\nThread {:#x} ({}):", thread as usize, name));
+        match capture_thread(thread) {
+            Some(frames) if !frames.is_empty() => {
+                for (i, frame) in frames.iter().enumerate() {
+                    report.push_str(&format!("\n  {:<3} {}", i, frame));
+                }
+            }
+            Some(_) => report.push_str(" stacks are not available on this system"),
+            None => report.push_str(" no response"),
+        }
+    }
+    drop(threads);
+    FLOG!(stall, report);
+}
+
+/// Ask \p thread for its stack and symbolize it. \return None if it did not respond in time.
+fn capture_thread(thread: libc::pthread_t) -> Option<Vec<String>> {
+    CAPTURED_COUNT.store(-1, Ordering::Release);
+    if unsafe { libc::pthread_kill(thread, WATCHDOG_SIGNAL) } != 0 {
+        return None;
+    }
+    let deadline = Instant::now() + CAPTURE_TIMEOUT;
+    let count = loop {
+        let count = CAPTURED_COUNT.load(Ordering::Acquire);
+        if count >= 0 {
+            break count as usize;
+        }
+        if Instant::now() > deadline {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    let frames: Vec<*mut libc::c_void> = CAPTURED_FRAMES[..count]
+        .iter()
+        .map(|f| f.load(Ordering::Relaxed) as *mut libc::c_void)
+        .collect();
+    // Skip the frames of the signal handler itself.
+    Some(symbolize(&frames).into_iter().skip(2).collect())
+}
+
+extern "C" fn watchdog_signal_handler(
+    _sig: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    let saved_errno = errno::errno();
+    let count = capture_frames();
+    CAPTURED_COUNT.store(count as i32, Ordering::Release);
+    errno::set_errno(saved_errno);
+}
+
+/// Record the return addresses of the calling thread in [`CAPTURED_FRAMES`]. This is called from a
+/// signal handler. \return the number of frames.
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+fn capture_frames() -> usize {
+    let mut frames = [std::ptr::null_mut(); MAX_FRAMES];
+    let count =
+        unsafe { backtrace(frames.as_mut_ptr(), MAX_FRAMES as libc::c_int) }.max(0) as usize;
+    for (slot, frame) in CAPTURED_FRAMES.iter().zip(&frames[..count]) {
+        slot.store(*frame as usize, Ordering::Relaxed);
+    }
+    count
+}
+
+#[cfg(not(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos")))]
+fn capture_frames() -> usize {
+    0
+}
+
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+fn symbolize(frames: &[*mut libc::c_void]) -> Vec<String> {
+    if frames.is_empty() {
+        return vec![];
+    }
+    let symbols = unsafe { backtrace_symbols(frames.as_ptr(), frames.len() as libc::c_int) };
+    if symbols.is_null() {
+        return frames.iter().map(|f| format!("{:p}", *f)).collect();
+    }
+    let result = (0..frames.len())
+        .map(|i| unsafe { std::ffi::CStr::from_ptr(*symbols.add(i)) })
+        .map(|s| s.to_string_lossy().into_owned())
+        .collect();
+    unsafe { libc::free(symbols as *mut libc::c_void) };
+    result
+}
+
+#[cfg(not(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos")))]
+fn symbolize(_frames: &[*mut libc::c_void]) -> Vec<String> {
+    vec![]
+}
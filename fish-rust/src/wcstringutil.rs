@@ -1,6 +1,6 @@
 //! Helper functions for working with wcstring.
 
-use crate::common::{get_ellipsis_char, get_ellipsis_str};
+use crate::common::{get_ellipsis_char, get_ellipsis_str, LOCALE_IS_UTF8};
 use crate::compat::MB_CUR_MAX;
 use crate::expand::INTERNAL_SEPARATOR;
 use crate::fallback::{fish_wcwidth, wcscasecmp};
@@ -251,6 +251,7 @@ pub fn string_fuzzy_match_string(
 pub fn wcs2string_callback(input: &wstr, mut func: impl FnMut(&[u8]) -> bool) -> bool {
     let mut state = zero_mbstate();
     let mut converted = [0_u8; AT_LEAST_MB_LEN_MAX];
+    let utf8 = LOCALE_IS_UTF8.load();
 
     for c in input.chars() {
         // TODO: this doesn't seem sound.
@@ -261,6 +262,10 @@ pub fn wcs2string_callback(input: &wstr, mut func: impl FnMut(&[u8]) -> bool) ->
             if !func(&converted[..1]) {
                 return false;
             }
+        } else if utf8 {
+            if !func(c.encode_utf8(&mut converted).as_bytes()) {
+                return false;
+            }
         } else if MB_CUR_MAX() == 1 {
             // single-byte locale (C/POSIX/ISO-8859)
             // If `c` contains a wide character we emit a question-mark.
@@ -1,31 +1,494 @@
-use crate::ffi;
-use crate::wchar::wstr;
-use crate::wchar_ffi::{wchar_t, wcslen};
-use widestring::U32CString;
+//! Support for wgettext: translating messages with the catalogs in fish's .mo files.
+//!
+//! This reads the catalogs itself instead of going through libintl, which isn't part of every
+//! libc and is awkward to link on macOS and musl. The catalog follows the locale: whenever the
+//! locale variables change, the next lookup uses the catalog for the new language.
+//!
+//! Translated strings are never freed, so they can be handed out as `&'static wstr`, and to C++ as
+//! nul-terminated pointers. Each catalog is loaded at most once.
 
-/// Support for wgettext.
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::common::str2wcstring;
+use crate::flog::FLOGF;
+use crate::wchar::{wstr, WString, L};
+use crate::wchar_ffi::{wchar_t, wcharz_t};
+
+#[cxx::bridge]
+mod gettext_ffi {
+    extern "C++" {
+        include!("wutil.h");
+        type wcharz_t = super::wcharz_t;
+    }
+    extern "Rust" {
+        fn wgettext_ffi(text: wcharz_t) -> wcharz_t;
+    }
+}
+
+/// Where the catalogs are installed, as `LOCALEDIR/<language>/LC_MESSAGES/fish.mo`.
+const LOCALEDIR: &str = env!("LOCALEDIR");
+
+/// The messages of one .mo file.
+struct Catalog {
+    /// The translations by msgid. Messages with plural forms have one translation per form.
+    messages: HashMap<WString, Vec<&'static wstr>>,
+    /// Which plural form to use for a count.
+    plural: PluralExpr,
+}
+
+struct State {
+    /// Every catalog we tried to load, by path, and None if it couldn't be loaded.
+    catalogs: HashMap<PathBuf, Option<&'static Catalog>>,
+    /// The catalogs for the current locale, in order of preference.
+    current: Vec<&'static Catalog>,
+    /// Untranslated strings we have handed out.
+    untranslated: HashMap<WString, &'static wstr>,
+}
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| {
+    let mut state = State {
+        catalogs: HashMap::new(),
+        current: Vec::new(),
+        untranslated: HashMap::new(),
+    };
+    state.current = state.load_languages();
+    Mutex::new(state)
+});
+
+/// Copy `s` into memory that is never freed, with a nul terminator after it.
+fn leak(s: &wstr) -> &'static wstr {
+    let mut owned = s.to_owned();
+    owned.push('\0');
+    let leaked: &'static wstr = Box::leak(owned.into_boxed_utfstr());
+    &leaked[..leaked.len() - 1]
+}
+
+impl State {
+    /// The catalogs for the languages the user asked for.
+    fn load_languages(&mut self) -> Vec<&'static Catalog> {
+        let mut result = vec![];
+        for language in requested_languages() {
+            let found = locale_candidates(&language)
+                .into_iter()
+                .find_map(|candidate| {
+                    let path = Path::new(LOCALEDIR)
+                        .join(candidate)
+                        .join("LC_MESSAGES")
+                        .join("fish.mo");
+                    self.load(path)
+                });
+            if let Some(catalog) = found {
+                result.push(catalog);
+            }
+        }
+        result
+    }
+
+    fn load(&mut self, path: PathBuf) -> Option<&'static Catalog> {
+        *self.catalogs.entry(path).or_insert_with_key(|path| {
+            let data = std::fs::read(path).ok()?;
+            let catalog = parse_mo(&data);
+            if catalog.is_none() {
+                FLOGF!(warning, "Ignoring invalid message catalog", path.display());
+            }
+            catalog.map(|c| &*Box::leak(Box::new(c)))
+        })
+    }
+
+    fn untranslated(&mut self, text: &wstr) -> &'static wstr {
+        if let Some(s) = self.untranslated.get(text) {
+            return s;
+        }
+        let s = leak(text);
+        self.untranslated.insert(text.to_owned(), s);
+        s
+    }
+
+    fn translate(&mut self, text: &wstr) -> &'static wstr {
+        // The empty msgid is the catalog's header.
+        if !text.is_empty() {
+            for catalog in &self.current {
+                if let Some(translation) = catalog.messages.get(text) {
+                    return translation[0];
+                }
+            }
+        }
+        self.untranslated(text)
+    }
+
+    fn translate_plural(&mut self, singular: &wstr, plural: &wstr, n: u64) -> &'static wstr {
+        for catalog in &self.current {
+            if let Some(translations) = catalog.messages.get(singular) {
+                let idx = catalog.plural.eval(n) as usize;
+                return translations[idx.min(translations.len() - 1)];
+            }
+        }
+        self.untranslated(if n == 1 { singular } else { plural })
+    }
+}
+
+/// The languages to translate to, most preferred first, following gettext's rules: none for the C
+/// locale, otherwise those in $LANGUAGE, or else the language of the LC_MESSAGES locale.
+fn requested_languages() -> Vec<String> {
+    let locale = unsafe { libc::setlocale(libc::LC_MESSAGES, std::ptr::null()) };
+    if locale.is_null() {
+        return vec![];
+    }
+    let locale = unsafe { CStr::from_ptr(locale) }
+        .to_string_lossy()
+        .into_owned();
+    if locale == "C" || locale == "POSIX" {
+        return vec![];
+    }
+    let from_language: Vec<String> = std::env::var("LANGUAGE")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_owned())
+        .collect();
+    if from_language.is_empty() {
+        vec![locale]
+    } else {
+        from_language
+    }
+}
+
+/// The directory names to look for a catalog for `locale` under, e.g. for "de_DE.UTF-8@euro":
+/// "de_DE.UTF-8@euro", "de_DE@euro", "de@euro", "de_DE.UTF-8", "de_DE", "de".
+fn locale_candidates(locale: &str) -> Vec<String> {
+    let (name, modifier) = match locale.split_once('@') {
+        Some((name, modifier)) => (name, Some(modifier)),
+        None => (locale, None),
+    };
+    let without_codeset = name.split('.').next().unwrap();
+    let language = without_codeset.split('_').next().unwrap();
+
+    let mut result: Vec<String> = vec![];
+    let mut add = |candidate: String| {
+        if !candidate.is_empty() && !result.contains(&candidate) {
+            result.push(candidate);
+        }
+    };
+    if let Some(modifier) = modifier {
+        for base in [name, without_codeset, language] {
+            add(format!("{}@{}", base, modifier));
+        }
+    }
+    for base in [name, without_codeset, language] {
+        add(base.to_owned());
+    }
+    result
+}
+
+/// Parse the contents of a .mo file.
+/// See https://www.gnu.org/software/gettext/manual/html_node/MO-Files.html
+fn parse_mo(data: &[u8]) -> Option<Catalog> {
+    const MAGIC: u32 = 0x950412de;
+    let word = |offset: usize, big_endian: bool| -> Option<u32> {
+        let bytes: [u8; 4] = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let big_endian = match word(0, false)? {
+        MAGIC => false,
+        _ if word(0, true)? == MAGIC => true,
+        _ => return None,
+    };
+    let word = |offset: usize| word(offset, big_endian).map(|w| w as usize);
+    // The string at index `i` of the table of (length, offset) pairs at `table`.
+    let string = |table: usize, i: usize| -> Option<&[u8]> {
+        let entry = table.checked_add(i.checked_mul(8)?)?;
+        let len = word(entry)?;
+        let offset = word(entry + 4)?;
+        data.get(offset..offset.checked_add(len)?)
+    };
+
+    let count = word(8)?;
+    let originals = word(12)?;
+    let translations = word(16)?;
+
+    let mut messages = HashMap::new();
+    let mut plural = PluralExpr::default();
+    for i in 0..count {
+        let original = string(originals, i)?;
+        let translation = string(translations, i)?;
+        if original.is_empty() {
+            let header = String::from_utf8_lossy(translation);
+            if let Some(expr) = plural_forms_from_header(&header) {
+                plural = expr;
+            }
+            continue;
+        }
+        // For messages with plural forms, the msgid is the singular and the plural, separated by
+        // a nul, and the translations are separated by nuls as well.
+        let msgid = original.split(|&b| b == 0).next().unwrap();
+        let forms: Vec<&'static wstr> = translation
+            .split(|&b| b == 0)
+            .map(|form| leak(&str2wcstring(form)))
+            .collect();
+        messages.insert(str2wcstring(msgid), forms);
+    }
+    Some(Catalog { messages, plural })
+}
+
+/// Get the plural expression out of the "Plural-Forms: nplurals=2; plural=(n != 1);" header line.
+fn plural_forms_from_header(header: &str) -> Option<PluralExpr> {
+    let line = header
+        .lines()
+        .find_map(|line| line.strip_prefix("Plural-Forms:"))?;
+    let expr = line
+        .split(';')
+        .find_map(|part| part.trim().strip_prefix("plural="))?;
+    PluralExpr::parse(expr)
+}
+
+/// The C expression in a catalog's header which picks the plural form for a count `n`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PluralExpr {
+    N,
+    Number(u64),
+    Not(Box<PluralExpr>),
+    Binary(BinaryOp, Box<PluralExpr>, Box<PluralExpr>),
+    Conditional(Box<PluralExpr>, Box<PluralExpr>, Box<PluralExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl Default for PluralExpr {
+    /// Without a header, use the English rule: one form for 1, another for everything else.
+    fn default() -> Self {
+        PluralExpr::Binary(
+            BinaryOp::NotEqual,
+            Box::new(PluralExpr::N),
+            Box::new(PluralExpr::Number(1)),
+        )
+    }
+}
+
+impl PluralExpr {
+    fn parse(text: &str) -> Option<PluralExpr> {
+        let mut parser = PluralParser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let expr = parser.conditional()?;
+        parser.skip_space();
+        (parser.pos == parser.text.len()).then_some(expr)
+    }
+
+    fn eval(&self, n: u64) -> u64 {
+        match self {
+            PluralExpr::N => n,
+            PluralExpr::Number(v) => *v,
+            PluralExpr::Not(e) => (e.eval(n) == 0).into(),
+            PluralExpr::Conditional(cond, a, b) => {
+                if cond.eval(n) != 0 {
+                    a.eval(n)
+                } else {
+                    b.eval(n)
+                }
+            }
+            PluralExpr::Binary(op, a, b) => {
+                let a = a.eval(n);
+                // Short-circuit like C does.
+                match op {
+                    BinaryOp::Or if a != 0 => return 1,
+                    BinaryOp::And if a == 0 => return 0,
+                    _ => (),
+                }
+                let b = b.eval(n);
+                match op {
+                    BinaryOp::Or | BinaryOp::And => (b != 0).into(),
+                    BinaryOp::Equal => (a == b).into(),
+                    BinaryOp::NotEqual => (a != b).into(),
+                    BinaryOp::Less => (a < b).into(),
+                    BinaryOp::LessEqual => (a <= b).into(),
+                    BinaryOp::Greater => (a > b).into(),
+                    BinaryOp::GreaterEqual => (a >= b).into(),
+                    BinaryOp::Add => a.wrapping_add(b),
+                    BinaryOp::Subtract => a.wrapping_sub(b),
+                    BinaryOp::Multiply => a.wrapping_mul(b),
+                    BinaryOp::Divide => a.checked_div(b).unwrap_or(0),
+                    BinaryOp::Remainder => a.checked_rem(b).unwrap_or(0),
+                }
+            }
+        }
+    }
+}
+
+/// A recursive descent parser for plural expressions, with C's precedence.
+struct PluralParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl PluralParser<'_> {
+    fn skip_space(&mut self) {
+        while self
+            .text
+            .get(self.pos)
+            .map_or(false, |c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        if self.text[self.pos..].starts_with(token.as_bytes()) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn conditional(&mut self) -> Option<PluralExpr> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Some(cond);
+        }
+        let a = self.conditional()?;
+        if !self.eat(":") {
+            return None;
+        }
+        let b = self.conditional()?;
+        Some(PluralExpr::Conditional(
+            Box::new(cond),
+            Box::new(a),
+            Box::new(b),
+        ))
+    }
+
+    /// Parse binary operators of precedence `level` or higher.
+    fn binary(&mut self, level: usize) -> Option<PluralExpr> {
+        // From lowest to highest precedence. Longer tokens come first so "<=" isn't read as "<".
+        const LEVELS: &[&[(&str, BinaryOp)]] = &[
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual)],
+            &[
+                ("<=", BinaryOp::LessEqual),
+                (">=", BinaryOp::GreaterEqual),
+                ("<", BinaryOp::Less),
+                (">", BinaryOp::Greater),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+            &[
+                ("*", BinaryOp::Multiply),
+                ("/", BinaryOp::Divide),
+                ("%", BinaryOp::Remainder),
+            ],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for &(token, op) in ops.iter() {
+                if self.eat(token) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = PluralExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Some(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Option<PluralExpr> {
+        if self.eat("!") {
+            return Some(PluralExpr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.conditional()?;
+            return self.eat(")").then_some(expr);
+        }
+        if self.eat("n") {
+            return Some(PluralExpr::N);
+        }
+        let start = self.pos;
+        while self
+            .text
+            .get(self.pos)
+            .map_or(false, |c| c.is_ascii_digit())
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+            .map(PluralExpr::Number)
+    }
+}
+
+/// Switch to the catalogs for the current locale. This is called when the locale changes.
+pub fn update_locale() {
+    let mut state = STATE.lock().unwrap();
+    state.current = state.load_languages();
+}
 
 /// Implementation detail for wgettext!.
-pub fn wgettext_impl_do_not_use_directly(text: &[wchar_t]) -> &'static wstr {
-    assert_eq!(text.last(), Some(&0), "should be nul-terminated");
-    let res: *const wchar_t = ffi::wgettext_ptr(text.as_ptr());
-    let slice = unsafe { std::slice::from_raw_parts(res as *const u32, wcslen(res)) };
-    wstr::from_slice(slice).expect("Invalid UTF-32")
+pub fn wgettext_impl_do_not_use_directly(text: &wstr) -> &'static wstr {
+    // Preserve errno across this since this is often used in printing error messages.
+    let err = errno::errno();
+    let res = STATE.lock().unwrap().translate(text);
+    errno::set_errno(err);
+    res
 }
 
 /// Get a (possibly translated) string from a non-literal.
 pub fn wgettext_str(s: &wstr) -> &'static wstr {
-    let cstr: U32CString = U32CString::from_chars_truncate(s.as_char_slice());
-    wgettext_impl_do_not_use_directly(cstr.as_slice_with_nul())
+    wgettext_impl_do_not_use_directly(s)
+}
+
+/// Get the (possibly translated) form of a message for the count `n`.
+pub fn wngettext_str(singular: &wstr, plural: &wstr, n: u64) -> &'static wstr {
+    let err = errno::errno();
+    let res = STATE.lock().unwrap().translate_plural(singular, plural, n);
+    errno::set_errno(err);
+    res
+}
+
+/// Translate for C++. The result lives forever.
+fn wgettext_ffi(text: wcharz_t) -> wcharz_t {
+    let translated = wgettext_str(&WString::from(&text));
+    // The strings we hand out are followed by a nul.
+    wcharz_t {
+        str_: translated.as_char_slice().as_ptr().cast::<wchar_t>(),
+    }
 }
 
 /// Get a (possibly translated) string from a string literal.
 /// This returns a &'static wstr.
 macro_rules! wgettext {
     ($string:expr) => {
-        crate::wutil::gettext::wgettext_impl_do_not_use_directly(
-            crate::wchar_ffi::u32cstr!($string).as_slice_with_nul(),
-        )
+        crate::wutil::gettext::wgettext_impl_do_not_use_directly(crate::wchar::L!($string))
     };
 }
 pub(crate) use wgettext;
@@ -43,10 +506,112 @@ macro_rules! wgettext_fmt {
 }
 pub(crate) use wgettext_fmt;
 
+/// Get the (possibly translated) singular or plural of a string literal, for the count `n`.
+/// This returns a &'static wstr.
+macro_rules! wngettext {
+    ($singular:expr, $plural:expr, $n:expr) => {
+        crate::wutil::gettext::wngettext_str(
+            crate::wchar::L!($singular),
+            crate::wchar::L!($plural),
+            $n as u64,
+        )
+    };
+}
+pub(crate) use wngettext;
+
 use crate::ffi_tests::add_test;
 add_test!("test_untranslated", || {
     let s: &'static wstr = wgettext!("abc");
     assert_eq!(s, "abc");
     let s2: &'static wstr = wgettext!("static");
     assert_eq!(s2, "static");
+    assert_eq!(wngettext!("%d file", "%d files", 1), "%d file");
+    assert_eq!(wngettext!("%d file", "%d files", 2), "%d files");
+});
+
+add_test!("test_plural_expr", || {
+    let forms = |expr: &str| -> Vec<u64> {
+        let expr = PluralExpr::parse(expr).unwrap();
+        [0, 1, 2, 5, 11, 22, 101]
+            .iter()
+            .map(|&n| expr.eval(n))
+            .collect()
+    };
+    assert_eq!(forms("n != 1"), [1, 0, 1, 1, 1, 1, 1]);
+    assert_eq!(forms("0"), [0; 7]);
+    assert_eq!(forms("n>1"), [0, 0, 1, 1, 1, 1, 1]);
+    // Polish.
+    assert_eq!(
+        forms("(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2)"),
+        [2, 0, 1, 2, 2, 1, 2]
+    );
+    assert_eq!(forms("!(n % 2)"), [1, 0, 1, 0, 0, 1, 0]);
+    assert_eq!(forms("n / 0"), [0; 7]);
+    assert!(PluralExpr::parse("n +").is_none());
+    assert!(PluralExpr::parse("(n").is_none());
+    assert!(PluralExpr::parse("n ? 1").is_none());
+});
+
+add_test!("test_mo_catalog", || {
+    // Build a little-endian .mo file with a header, a plain message and one with plural forms.
+    let entries: [(&[u8], &[u8]); 3] = [
+        (
+            b"",
+            b"Content-Type: text/plain; charset=UTF-8\nPlural-Forms: nplurals=2; plural=n>1;\n",
+        ),
+        (b"%d file\0%d files", b"%d fichier\0%d fichiers"),
+        (b"hello", b"bonjour"),
+    ];
+    let count = entries.len() as u32;
+    let mut data: Vec<u8> = vec![];
+    let mut strings: Vec<u8> = vec![];
+    let strings_start = 28 + 16 * count;
+    let mut tables = [vec![], vec![]];
+    for (original, translation) in entries {
+        for (table, s) in tables.iter_mut().zip([original, translation]) {
+            table.extend((s.len() as u32).to_le_bytes());
+            table.extend((strings_start + strings.len() as u32).to_le_bytes());
+            strings.extend(s);
+            strings.push(0);
+        }
+    }
+    for word in [0x950412de, 0, count, 28, 28 + 8 * count, 0, 0] {
+        data.extend(u32::to_le_bytes(word));
+    }
+    data.extend(&tables[0]);
+    data.extend(&tables[1]);
+    data.extend(&strings);
+
+    let catalog = parse_mo(&data).unwrap();
+    assert_eq!(catalog.messages[L!("hello")], [L!("bonjour")]);
+    assert_eq!(
+        catalog.messages[L!("%d file")],
+        [L!("%d fichier"), L!("%d fichiers")]
+    );
+    assert_eq!(catalog.plural.eval(1), 0);
+    assert_eq!(catalog.plural.eval(0), 0);
+    assert_eq!(catalog.plural.eval(2), 1);
+
+    // Byte-swapped files are read too, and junk isn't.
+    let mut swapped = data.clone();
+    for chunk in swapped[..28 + 16 * count as usize].chunks_mut(4) {
+        chunk.reverse();
+    }
+    assert!(parse_mo(&swapped).is_some());
+    assert!(parse_mo(&data[..20]).is_none());
+    assert!(parse_mo(b"not a catalog at all").is_none());
+
+    assert_eq!(
+        locale_candidates("de_DE.UTF-8@euro"),
+        [
+            "de_DE.UTF-8@euro",
+            "de_DE@euro",
+            "de@euro",
+            "de_DE.UTF-8",
+            "de_DE",
+            "de"
+        ]
+    );
+    assert_eq!(locale_candidates("pt_BR"), ["pt_BR", "pt"]);
+    assert_eq!(locale_candidates("sv"), ["sv"]);
 });
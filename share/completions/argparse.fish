@@ -70,3 +70,5 @@ complete --command argparse --short-option i --long-option ignore-unknown \
     --description 'Ignore unknown options'
 complete --command argparse --short-option s --long-option stop-nonopt \
     --description 'Exit on subcommand'
+complete --command argparse --short-option d --long-option description --no-files --require-parameter \
+    --description 'Describe an option for completions'
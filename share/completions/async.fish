@@ -0,0 +1,3 @@
+complete -c async -n 'test (count (commandline -opc)) -eq 1' -xa '(set -n)' -d Variable
+complete -c async -n 'test (count (commandline -opc)) -ge 2' -xa '(__fish_complete_subcommand --fcs-skip=2)'
+complete -c async -s h -l help -d 'Display help and exit'
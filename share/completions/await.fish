@@ -0,0 +1,2 @@
+complete -c await -xa '(set -n | string replace -rf "^__fish_async_" "")' -d 'Pending async result'
+complete -c await -s h -l help -d 'Display help and exit'
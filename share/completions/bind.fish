@@ -53,7 +53,15 @@ complete -c bind -s K -l key-names -d 'Print names of available keys'
 complete -c bind -s M -l mode -d 'Specify the bind mode that the bind is used in' -xa '(bind -L)'
 complete -c bind -s m -l sets-mode -d 'Change current mode after bind is executed' -xa '(bind -L)'
 complete -c bind -s L -l list-modes -d 'Display a list of defined bind modes'
+complete -c bind -l new-mode -d 'Define a bind mode' -xa '(bind -L)'
+complete -c bind -l inherits -d 'Fall back to the bindings of this mode' -xa '(bind -L)'
+complete -c bind -l record -d 'Start recording a keyboard macro'
+complete -c bind -l stop-recording -d 'Stop recording and name the macro' -xa '(bind --list-macros)'
+complete -c bind -l play -d 'Replay a keyboard macro' -xa '(bind --list-macros)'
+complete -c bind -l list-macros -d 'Display a list of keyboard macros'
 complete -c bind -s s -l silent -d 'Operate silently'
+complete -c bind -l timeout -d 'Wait at most this many ms for each further key' -x
+complete -c bind -l json -d 'Print bindings as JSON'
 complete -c bind -l preset -d 'Operate on preset bindings'
 complete -c bind -l user -d 'Operate on user bindings'
 
@@ -12,9 +12,15 @@ complete -c complete -s r -l require-parameter -d "Require parameter"
 complete -c complete -s x -l exclusive -d "Require parameter and don't use file completion"
 complete -c complete -s a -l arguments -d "Space-separated list of possible arguments" -x
 complete -c complete -s d -l description -d "Description of completion" -x
+complete -c complete -s g -l group -d "Group to list completion under in the pager" -x
 complete -c complete -s e -l erase -d "Remove completion"
 complete -c complete -s h -l help -d "Display help and exit"
 complete -c complete -s C -l do-complete -d "Print completions for a commandline specified as a parameter"
+complete -c complete -l json -d "Print completions as JSON"
+complete -c complete -l no-sort -d "Print completions in the order they were generated in"
+complete -c complete -l cache -d "Seconds to cache computed arguments for" -x
+complete -c complete -l flush-cache -d "Forget cached arguments"
+complete -c complete -l from-argparse -d "Complete the argparse options of a function" -x -a "(functions -n)"
 complete -c complete -s n -l condition -d "Completion only used if command has zero exit status" -x
 complete -c complete -s w -l wraps -d "Inherit completions from specified command" -xa '(__fish_complete_command)'
 complete -c complete -s k -l keep-order -d "Keep order of arguments instead of sorting alphabetically"
@@ -0,0 +1,2 @@
+complete -c coproc -n 'test (count (commandline -opc)) -ge 2' -xa '(__fish_complete_subcommand --fcs-skip=2)'
+complete -c coproc -s h -l help -d 'Display help and exit'
@@ -1,2 +1,7 @@
 complete -c exec -n 'test (count (commandline -opc)) -eq 1' -s h -l help -d 'Display help and exit'
-complete -c exec -xa "(__fish_complete_subcommand)"
+complete -c exec -n 'test (count (commandline -opc)) -eq 1' -s o -l open -d 'Open a file to an fd stored in a variable'
+complete -c exec -n '__fish_seen_argument -s o -l open' -s a -l append -d 'Open the file for appending'
+complete -c exec -n '__fish_seen_argument -s o -l open' -s r -l read -d 'Open the file for reading'
+complete -c exec -n '__fish_seen_argument -s o -l open' -s i -l inherit -d 'Pass the fd to all commands'
+complete -c exec -n 'test (count (commandline -opc)) -eq 1' -s c -l close -d 'Close fds opened with --open'
+complete -c exec -n 'not __fish_seen_argument -s o -l open -s c -l close' -xa "(__fish_complete_subcommand)"
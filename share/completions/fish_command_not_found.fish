@@ -0,0 +1,2 @@
+complete -c fish_command_not_found -s h -l help -d "Display help and exit"
+complete -c fish_command_not_found -n __fish_is_first_arg -f -a "(__fish_complete_command)"
@@ -1,9 +1,16 @@
 complete -c fish_indent -s h -l help -d 'Display help and exit'
 complete -c fish_indent -s v -l version -d 'Display version and exit'
 complete -c fish_indent -s i -l no-indent -d 'Do not indent output, only reformat into one job per line'
+complete -c fish_indent -l indent-width -x -d 'Number of columns per indentation level'
+complete -c fish_indent -l tabs -d 'Indent with tabs'
+complete -c fish_indent -l spaces -d 'Indent with spaces'
+complete -c fish_indent -l max-width -x -d 'Split jobs longer than this many columns'
+complete -c fish_indent -l collapse-begin -d 'Keep one-line begin blocks on one line'
 complete -c fish_indent -l ansi -d 'Colorize the output using ANSI escape sequences'
 complete -c fish_indent -l html -d 'Output in HTML format'
 complete -c fish_indent -s w -l write -d 'Write to file'
+complete -c fish_indent -s c -l check -d 'Only check whether the code is formatted'
+complete -c fish_indent -l diff -d 'Print a diff of the changes instead'
 complete -c fish_indent -s d -l debug -x -d 'Enable debug at specified verbosity level'
 complete -c fish_indent -s o -l debug-output -d "Where to direct debug output to" -rF
 complete -c fish_indent -s D -l debug-stack-frames -x -d 'Specify how many stack frames to display in debug messages'
@@ -1,3 +1,6 @@
 complete -c fish_key_reader -s h -l help -d 'Display help and exit'
 complete -c fish_key_reader -s v -l version -d 'Display version and exit'
 complete -c fish_key_reader -s c -l continuous -d 'Start a continuous session'
+complete -c fish_key_reader -l stream -d 'Report every key sequence until end of input'
+complete -c fish_key_reader -l format -x -a 'text json' -d 'Output format'
+complete -c fish_key_reader -s V -l verbose -d 'Explain the received sequence in more detail'
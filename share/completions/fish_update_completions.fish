@@ -0,0 +1,9 @@
+complete -c fish_update_completions -s h -l help -d "Display help and exit"
+complete -c fish_update_completions -s d -l directory -x -a "(__fish_complete_directories)" -d "Write the completions to this directory"
+complete -c fish_update_completions -s s -l stdout -d "Print the completions instead"
+complete -c fish_update_completions -s m -l manpath -d "Parse every page in the manual path"
+complete -c fish_update_completions -s c -l cleanup-in -x -a "(__fish_complete_directories)" -d "Remove generated completions from this directory"
+complete -c fish_update_completions -s k -l keep -d "Keep completions generated earlier"
+complete -c fish_update_completions -s p -l progress -d "Report the progress"
+complete -c fish_update_completions -s v -l verbose -x -a "0 1 2" -d "Report unparsable pages"
+complete -c fish_update_completions -s z -l deroff-only -d "Only guess options from the text"
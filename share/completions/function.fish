@@ -36,4 +36,5 @@ complete -c function -s e -l on-event -d "Make the function a generic event hand
 complete -c function -s a -l argument-names -d "Specify named arguments" -x
 complete -c function -s S -l no-scope-shadowing -d "Do not shadow variable scope of calling function"
 complete -c function -s w -l wraps -d "Inherit completions from the given command" -xa "(__fish_complete_command)"
+complete -c function -l job-scope -d "What to do with background jobs on return" -xa "wait\t'Wait for them' cancel\t'Send them SIGTERM' disown\t'Disown them'"
 complete -c function -s V -l inherit-variable -d "Snapshot and define local variable" -xa "(__fish_complete_variables)"
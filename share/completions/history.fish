@@ -1,5 +1,5 @@
 # Note that when a completion file is sourced a new block scope is created so `set -l` works.
-set -l __fish_history_all_commands search delete save merge clear clear-session
+set -l __fish_history_all_commands search delete save merge clear clear-session export import
 
 complete -c history -s h -l help -d "Display help and exit"
 
@@ -12,8 +12,20 @@ complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish
     -s e -l exact -d "Match items identical to the string"
 complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
     -s t -l show-time -d "Output with timestamps"
+complete -c history -n '__fish_seen_subcommand_from search; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l show-status -d "Output with exit status and duration"
 complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
     -s C -l case-sensitive -d "Match items in a case-sensitive manner"
+complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l regex -d "Match items with a regular expression"
+complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l before -d "Only items from before this time" -x
+complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l after -d "Only items from this time or later" -x
+complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l cwd -d "Only items run in this directory" -xa "(__fish_complete_directories)"
+complete -c history -n '__fish_seen_subcommand_from search delete; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l status -d "Only items with this exit status" -x
 
 # Note that these options are only valid with the "search" subcommand.
 complete -c history -n '__fish_seen_subcommand_from search; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
@@ -22,6 +34,16 @@ complete -c history -n '__fish_seen_subcommand_from search; or not __fish_seen_s
     -s z -l null -d "Terminate entries with NUL character"
 complete -c history -n '__fish_seen_subcommand_from search; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
     -s R -l reverse -d "Output the oldest results first" -x
+complete -c history -n '__fish_seen_subcommand_from search; or not __fish_seen_subcommand_from $__fish_history_all_commands' \
+    -l json -d "Print matches as JSON"
+
+# Note that this option is only valid with the "merge" subcommand.
+complete -c history -n '__fish_seen_subcommand_from merge' \
+    -l from -d "Merge the history of this session" -xa "(__fish_complete_history_sessions)"
+
+# Note that this option is only valid with the "export" and "import" subcommands.
+complete -c history -n '__fish_seen_subcommand_from export import' \
+    -l format -d "Format of the history" -xa "json bash zsh"
 
 # We don't include a completion for the "save" subcommand because it should not be used
 # interactively.
@@ -35,3 +57,7 @@ complete -f -c history -n "not __fish_seen_subcommand_from $__fish_history_all_c
     -a clear -d "Clears history file"
 complete -f -c history -n "not __fish_seen_subcommand_from $__fish_history_all_commands" \
     -a clear-session -d "Clears all history from the current session"
+complete -f -c history -n "not __fish_seen_subcommand_from $__fish_history_all_commands" \
+    -a export -d "Prints all of history for other shells or tools"
+complete -c history -n "not __fish_seen_subcommand_from $__fish_history_all_commands" \
+    -a import -d "Adds history from a file written by another shell or tool"
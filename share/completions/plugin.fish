@@ -0,0 +1,13 @@
+# Completion for builtin plugin
+set -l subcommands load unload list segment complete
+complete -f -c plugin -s h -l help -d "Display help and exit"
+complete -f -c plugin -n "not __fish_seen_subcommand_from $subcommands" -a load -d 'Load a plugin'
+complete -f -c plugin -n "not __fish_seen_subcommand_from $subcommands" -a unload -d 'Unload plugins'
+complete -f -c plugin -n "not __fish_seen_subcommand_from $subcommands" -a list -d 'List loaded plugins'
+complete -f -c plugin -n "not __fish_seen_subcommand_from $subcommands" -a segment -d 'Print a prompt segment'
+complete -F -c plugin -n "__fish_seen_subcommand_from load"
+complete -x -c plugin -n "__fish_seen_subcommand_from load" -s r -l read-var -d 'Allow reading a variable' -a '(set -n)'
+complete -x -c plugin -n "__fish_seen_subcommand_from load" -s w -l write-var -d 'Allow setting a variable' -a '(set -n)'
+complete -x -c plugin -n "__fish_seen_subcommand_from load" -s d -l dir -d 'Allow access to a directory' -a '(__fish_complete_directories)'
+complete -f -c plugin -n "__fish_seen_subcommand_from unload" -a '(plugin list | string match -rg "^([^\t]+)\t")'
+complete -f -c plugin -n "__fish_seen_subcommand_from segment" -a '(plugin list | string match -rg "^\tprompt-segment\t(.*)")'
@@ -62,6 +62,12 @@ function __fish_complete_special_vars
         fish_escape_delay_ms "How long fish waits to distinguish escape and alt" \
         fish_greeting "The message to display at start (also a function)" \
         fish_history "The session id to store history under" \
+        fish_exit_job_policy "What to do with running jobs on exit" \
+        fish_ionice "I/O scheduling class for external commands" \
+        fish_max_background_jobs "How many background jobs may run at once" \
+        fish_nice "Niceness to add for external commands" \
+        fish_pipe_buffering "Whether commands in pipes flush each line" \
+        fish_timeout "How long external commands may run" \
         fish_trace "Enables execution tracing (if set to non-empty value)" \
         fish_user_paths "A list of dirs to prepend to PATH"
 end
@@ -87,6 +93,7 @@ complete -c set -n "__fish_is_nth_token 1" -s n -l names -d "List the names of t
 complete -c set -n "__fish_is_nth_token 1" -s a -l append -d "Append value to a list"
 complete -c set -n "__fish_is_nth_token 1" -s p -l prepend -d "Prepend value to a list"
 complete -c set -n "__fish_is_nth_token 1" -s S -l show -d "Show variable"
+complete -c set -n "__fish_is_nth_token 1" -l json -d "Print variables as JSON"
 complete -c set -n "__fish_is_nth_token 1" -l path -d "Make variable as a path variable"
 complete -c set -n "__fish_is_nth_token 1" -l unpath -d "Make variable not as a path variable"
 
@@ -1,5 +1,5 @@
 # Note that when a completion file is sourced a new block scope is created so `set -l` works.
-set -l __fish_status_all_commands current-command current-commandline current-filename current-function current-line-number features filename fish-path function is-block is-breakpoint is-command-substitution is-full-job-control is-interactive is-interactive-job-control is-login is-no-job-control job-control line-number print-stack-trace stack-trace test-feature
+set -l __fish_status_all_commands autoload current-command current-commandline current-filename current-function current-line-number fds features filename fish-path function is-block is-breakpoint is-command-substitution is-full-job-control is-interactive is-interactive-job-control is-login is-no-job-control job-control last-job last-pipeline line-number multiplexer print-stack-trace profile stack-trace stats test-feature
 
 # These are the recognized flags.
 complete -c status -s h -l help -d "Display help and exit"
@@ -25,8 +25,19 @@ complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_com
 complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a line-number -d "Print the line number of the currently running script"
 complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a print-stack-trace -d "Print a list of all function calls leading up to running the current command"
 complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a stack-trace -d "Print a list of all function calls leading up to running the current command"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a last-job -d "Print the resources used by the last job"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a last-pipeline -d "Print the status and time of each process in the last pipeline"
 complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a features -d "List all feature flags"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a fds -d "List the file descriptors fish has open"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a stats -d "Print statistics about background work"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a autoload -d "Show which files provide a function and its completions"
+complete -f -c status -n "__fish_seen_subcommand_from autoload" -a "(functions -a)"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a multiplexer -d "Print the terminal multiplexer fish runs in"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a profile -d "Profile the commands fish runs"
+complete -f -c status -n "__fish_seen_subcommand_from profile; and not __fish_seen_subcommand_from start stop" -a "start\t'Start profiling' stop\t'Stop profiling and print the times'"
+complete -c status -n "__fish_seen_subcommand_from profile; and __fish_seen_subcommand_from stop" -l format -x -a "text json folded" -d "Profile format"
 complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a test-feature -d "Test if a feature flag is enabled"
+complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands; or __fish_seen_subcommand_from features fds stats last-job last-pipeline profile" -l json -d "Print as JSON"
 complete -f -c status -n "__fish_seen_subcommand_from test-feature" -a '(status features | sed "s/\s\+\S*\s\+\S*/\t/")'
 complete -f -c status -n "not __fish_seen_subcommand_from $__fish_status_all_commands" -a fish-path -d "Print the path to the current instance of fish"
 
@@ -1,3 +1,4 @@
 complete -c wait -xa '(__fish_complete_job_pids)'
 complete -c wait -s n -l any -d 'Return as soon as the first job completes'
+complete -c wait -s p -l pid -d 'Wait for process IDs, including ones fish did not start'
 complete -c wait -s h -l help -d 'Display help and exit'
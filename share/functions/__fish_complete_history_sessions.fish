@@ -0,0 +1,3 @@
+function __fish_complete_history_sessions --description "Print the names of the history sessions that have a history file"
+    path basename -- $__fish_user_data_dir/*_history | string replace -r '_history$' ''
+end
@@ -79,35 +79,24 @@ end" >$__fish_config_dir/config.fish
     #
     # Don't do this if we're being invoked as part of running unit tests.
     if not set -q FISH_UNIT_TESTS_RUNNING
-        # Check if our manpage completion script exists because some distros split it out.
-        # (#7183)
-        set -l script $__fish_data_dir/tools/create_manpage_completions.py
-        if not test -d $__fish_user_data_dir/generated_completions; and test -e "$script"
-            # Generating completions from man pages needs python (see issue #3588).
-
-            # We cannot simply do `fish_update_completions &` because it is a function.
-            # We cannot do `eval` since it is a function.
-            # We don't want to call `fish -c` since that is unnecessary and sources config.fish again.
-            # Hence we'll call python directly.
-            # c_m_p.py should work with any python version.
-            set -l update_args -B $__fish_data_dir/tools/create_manpage_completions.py --manpath --cleanup-in '~/.config/fish/completions' --cleanup-in '~/.config/fish/generated_completions'
-            if set -l python (__fish_anypython)
-                # Run python directly in the background and swallow all output
-                $python $update_args >/dev/null 2>&1 &
-                # Then disown the job so that it continues to run in case of an early exit (#6269)
-                disown >/dev/null 2>&1
-            end
+        if not test -d $__fish_user_data_dir/generated_completions
+            # We cannot simply do `fish_update_completions &` because builtins don't run in the
+            # background, so run it in a separate fish that doesn't source config.fish again.
+            # Swallow all output.
+            (status fish-path) --no-config -c 'fish_update_completions --manpath --cleanup-in $__fish_config_dir/completions --cleanup-in $__fish_config_dir/generated_completions' >/dev/null 2>&1 &
+            # Then disown the job so that it continues to run in case of an early exit (#6269)
+            disown >/dev/null 2>&1
         end
     end
 
     #
     # Print a greeting.
-    # The default just prints a variable of the same name.
+    # The default just prints a variable of the same name, others run in the background.
     #
     # NOTE: This status check is necessary to not print the greeting when `read`ing in scripts. See #7080.
     if status --is-interactive
         and functions -q fish_greeting
-        fish_greeting
+        __fish_print_greeting
     end
 
     #
@@ -212,17 +201,21 @@ end" >$__fish_config_dir/config.fish
         and __fish_enable_bracketed_paste
     end
 
-    # Similarly, enable TMUX's focus reporting when in tmux.
+    # Similarly, enable focus reporting when in tmux, or when notifications for long commands
+    # are enabled, because they are only sent if the terminal is unfocused.
     # This will be handled by
     # - The keybindings (reading the sequence and triggering an event)
-    # - Any listeners (like the vi-cursor)
-    if set -q TMUX
-        and not set -q FISH_UNIT_TESTS_RUNNING
+    # - Any listeners (like the vi-cursor and the notifications below)
+    if not set -q FISH_UNIT_TESTS_RUNNING
         function __fish_enable_focus --on-event fish_postexec
-            echo -n \e\[\?1004h
+            if set -q TMUX; or set -q fish_notify_duration
+                echo -n \e\[\?1004h
+            end
         end
         function __fish_disable_focus --on-event fish_preexec
-            echo -n \e\[\?1004l
+            if set -q TMUX; or set -q fish_notify_duration
+                echo -n \e\[\?1004l
+            end
         end
         # Note: Don't call this initially because, even though we're in a fish_prompt event,
         # tmux reacts sooo quickly that we'll still get a sequence before we're prepared for it.
@@ -231,6 +224,37 @@ end" >$__fish_config_dir/config.fish
         # __fish_enable_focus
     end
 
+    # Call fish_notify when a command took longer than $fish_notify_duration milliseconds and the
+    # terminal is unfocused when it finishes.
+    # Focus reporting is off while commands run, so we only learn that the user left after the
+    # command is done: either because the terminal reports its focus when reporting is turned back
+    # on, or because the user leaves later. In the meantime, the notification is pending.
+    function __fish_notify_postexec --on-event fish_postexec
+        set -l last_status $status
+        set -e __fish_notify_pending
+        test "$CMD_DURATION" -ge "$fish_notify_duration" 2>/dev/null
+        or return
+        if test "$__fish_focused" = 0
+            fish_notify $argv[1] $CMD_DURATION $last_status
+        else
+            set -g __fish_notify_pending $argv[1] $CMD_DURATION $last_status
+        end
+    end
+
+    function __fish_notify_focus_out --on-event fish_focus_out
+        set -g __fish_focused 0
+        if set -q __fish_notify_pending[1]
+            fish_notify $__fish_notify_pending
+            set -e __fish_notify_pending
+        end
+    end
+
+    function __fish_notify_focus_in --on-event fish_focus_in
+        set -g __fish_focused 1
+        # The user is back and can see that the command finished.
+        set -e __fish_notify_pending
+    end
+
     # Detect whether the terminal reflows on its own
     # If it does we shouldn't do it.
     # Allow $fish_handle_reflow to override it.
@@ -254,31 +278,14 @@ end" >$__fish_config_dir/config.fish
         end
     end
 
-    function __fish_winch_handler --on-signal WINCH -d "Repaint screen when window changes size"
+    # $COLUMNS and $LINES are only updated once the reader is ready to repaint, and only if the size
+    # changed, so a burst of resizes repaints once.
+    function __fish_winch_handler --on-variable COLUMNS --on-variable LINES -d "Repaint screen when window changes size"
         if test "$fish_handle_reflow" = 1 2>/dev/null
             commandline -f repaint >/dev/null 2>/dev/null
         end
     end
 
-    # Notify terminals when $PWD changes (issue #906).
-    # VTE based terminals, Terminal.app, iTerm.app (TODO), foot, and kitty support this.
-    if not set -q FISH_UNIT_TESTS_RUNNING
-        and begin
-            string match -q -- 'foot*' $TERM
-            or string match -q -- 'xterm-kitty*' $TERM
-            or test 0"$VTE_VERSION" -ge 3405
-            or test "$TERM_PROGRAM" = Apple_Terminal && test (string match -r '\d+' 0"$TERM_PROGRAM_VERSION") -ge 309
-            or test "$TERM_PROGRAM" = WezTerm
-        end
-        function __update_cwd_osc --on-variable PWD --description 'Notify capable terminals when $PWD changes'
-            if status --is-command-substitution || set -q INSIDE_EMACS
-                return
-            end
-            printf \e\]7\;file://%s%s\a $hostname (string escape --style=url $PWD)
-        end
-        __update_cwd_osc # Run once because we might have already inherited a PWD from an old tab
-    end
-
     # Bump this whenever some code below needs to run once when upgrading to a new version.
     # The universal variable __fish_initialized is initialized in share/config.fish.
     set __fish_initialized 3400
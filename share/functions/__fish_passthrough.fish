@@ -0,0 +1,17 @@
+function __fish_passthrough -d "Print an escape sequence, like printf, so it reaches the terminal in a multiplexer"
+    set -l seq (printf $argv | string collect)
+    # Multiplexers swallow sequences they don't know, unless they are wrapped in a DCS sequence
+    # that tells them to pass it on. tmux also needs `set -g allow-passthrough on`.
+    switch "$(status multiplexer)"
+        case tmux
+            # Escapes inside need to be doubled.
+            printf '\ePtmux;%s\e\\\\' (string replace -a \e \e\e -- $seq | string collect)
+        case screen
+            # screen limits the length of DCS sequences, so long ones are split.
+            for chunk in (string match -ra '.{1,256}' -- $seq)
+                printf '\eP%s\e\\\\' $chunk
+            end
+        case '*'
+            printf '%s' $seq
+    end
+end
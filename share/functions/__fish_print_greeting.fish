@@ -0,0 +1,44 @@
+function __fish_print_greeting --description "Print the greeting without holding up the first prompt"
+    # The default greeting only prints a variable, so there is nothing to wait for.
+    if test "$(functions --details fish_greeting)" = $__fish_data_dir/functions/fish_greeting.fish
+        fish_greeting
+        return
+    end
+
+    # With $fish_greeting_cache set, the greeting is computed once a day, and reused until then.
+    set -l today (date +%F)
+    set -l cache $__fish_user_data_dir/greeting
+    if set -q fish_greeting_cache
+        and test -r $cache
+        and read -l day <$cache
+        and test "$day" = "$today"
+        tail -n +2 $cache
+        return
+    end
+
+    # Run the greeting in another fish, and print what it printed once it exits.
+    # The prompt is up by then, so it goes above the prompt.
+    set -l tmpdir /tmp
+    set -q TMPDIR
+    and set tmpdir $TMPDIR
+    set -l out (mktemp $tmpdir/.greeting.XXXXXXXXXX)
+    or begin
+        fish_greeting
+        return
+    end
+    set -l greeting (functions fish_greeting | string collect)
+    set -l fish (status fish-path)
+    $fish -c "$greeting"\n"fish_greeting" >$out 2>&1 &
+
+    function __fish_greeting_ready --on-process-exit $last_pid -V out -V cache -V today
+        functions -e __fish_greeting_ready
+        set -l text (string collect <$out)
+        command rm -f $out
+        if set -q fish_greeting_cache
+            mkdir -p (path dirname $cache)
+            printf '%s\n' $today "$text" >$cache
+        end
+        test -n "$text"
+        and commandline --print-above -- $text
+    end
+end
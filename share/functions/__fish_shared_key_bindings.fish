@@ -117,12 +117,10 @@ function __fish_shared_key_bindings -d "Bindings shared between emacs and vi mod
     bind --preset $argv \ee edit_command_buffer
     bind --preset $argv \ev edit_command_buffer
 
-    # Tmux' focus events.
-    # Exclude paste mode because that should get _everything_ literally.
-    for mode in (bind --list-modes | string match -v paste)
-        # We only need the in-focus event currently (to redraw the vi-cursor).
+    # Focus events, which are enabled in tmux and for notifications.
+    for mode in (bind --list-modes)
         bind --preset -M $mode \e\[I 'emit fish_focus_in'
-        bind --preset -M $mode \e\[O false
+        bind --preset -M $mode \e\[O 'emit fish_focus_out'
         bind --preset -M $mode \e\[\?1004h false
     end
 
@@ -131,42 +129,16 @@ function __fish_shared_key_bindings -d "Bindings shared between emacs and vi mod
     # \e\[?2004h
     # then the terminal will "bracket" every paste in
     # \e\[200~ and \e\[201~
-    # Every character in between those two will be part of the paste and should not cause a binding to execute (like \n executing commands).
+    # fish reads everything in between as one paste, so it doesn't cause a binding to execute (like \n executing commands).
+    # It is passed to the fish_paste_filter function, if there is one, and then inserted.
     #
     # We enable it after every command and disable it before (in __fish_config_interactive.fish)
     #
     # Support for this seems to be ubiquitous - emacs enables it unconditionally (!) since 25.1
     # (though it only supports it since then, it seems to be the last term to gain support).
     #
-    # NOTE: This is more of a "security" measure than a proper feature.
-    # The better way to paste remains the `fish_clipboard_paste` function (bound to \cv by default).
-    # We don't disable highlighting here, so it will be redone after every character (which can be slow),
-    # and it doesn't handle "paste-stop" sequences in the paste (which the terminal needs to strip).
-    #
     # See http://thejh.net/misc/website-terminal-copy-paste.
 
-    # Bind the starting sequence in every bind mode, even user-defined ones.
-    # Exclude paste mode or there'll be an additional binding after switching between emacs and vi
-    for mode in (bind --list-modes | string match -v paste)
-        bind --preset -M $mode -m paste \e\[200~ __fish_start_bracketed_paste
-    end
-    # This sequence ends paste-mode and returns to the previous mode we have saved before.
-    bind --preset -M paste \e\[201~ __fish_stop_bracketed_paste
-    # In paste-mode, everything self-inserts except for the sequence to get out of it
-    bind --preset -M paste "" self-insert
-    # Without this, a \r will overwrite the other text, rendering it invisible - which makes the exercise kinda pointless.
-    bind --preset -M paste \r "commandline -i \n"
-
-    # We usually just pass the text through as-is to facilitate pasting code,
-    # but when the current token contains an unbalanced single-quote (`'`),
-    # we escape all single-quotes and backslashes, effectively turning the paste
-    # into one literal token, to facilitate pasting non-code (e.g. markdown or git commitishes)
-    bind --preset -M paste "'" "__fish_commandline_insert_escaped \' \$__fish_paste_quoted"
-    bind --preset -M paste \\ "__fish_commandline_insert_escaped \\\ \$__fish_paste_quoted"
-    # Only insert spaces if we're either quoted or not at the beginning of the commandline
-    # - this strips leading spaces if they would trigger histignore.
-    bind --preset -M paste " " self-insert-notfirst
-
     # Bindings that are shared in text-insertion modes.
     if not set -l index (contains --index -- -M $argv)
         or test $argv[(math $index + 1)] = insert
@@ -200,28 +172,3 @@ function __fish_shared_key_bindings -d "Bindings shared between emacs and vi mod
         bind --preset $argv \e\[13\;2u execute
     end
 end
-
-function __fish_commandline_insert_escaped --description 'Insert the first arg escaped if a second arg is given'
-    if set -q argv[2]
-        commandline -i \\$argv[1]
-    else
-        commandline -i $argv[1]
-    end
-end
-
-function __fish_start_bracketed_paste
-    # Save the last bind mode so we can restore it.
-    set -g __fish_last_bind_mode $fish_bind_mode
-    # If the token is currently single-quoted,
-    # we escape single-quotes (and backslashes).
-    string match -q 'single*' (__fish_tokenizer_state -- (commandline -ct | string collect))
-    and set -g __fish_paste_quoted 1
-    commandline -f begin-undo-group
-end
-
-function __fish_stop_bracketed_paste
-    # Restore the last bind mode.
-    set fish_bind_mode $__fish_last_bind_mode
-    set -e __fish_paste_quoted
-    commandline -f end-undo-group
-end
@@ -0,0 +1,39 @@
+function async --description "Start a command in the background and collect its output for await"
+    argparse -n async --min-args=2 --stop-nonopt h/help -- $argv
+    or return
+
+    if set -q _flag_help
+        __fish_print_help async
+        return 0
+    end
+
+    set -l var $argv[1]
+    if not string match -qr '^[a-zA-Z0-9_]+$' -- $var
+        printf (_ "%s: Invalid variable name '%s'\n") async $var >&2
+        return 2
+    end
+
+    if set -q __fish_async_$var
+        printf (_ "%s: An async command for '%s' is already pending\n") async $var >&2
+        return 1
+    end
+
+    set -l tmpdir /tmp
+    set -q TMPDIR
+    and set tmpdir $TMPDIR
+    set -l file (mktemp $tmpdir/.async.XXXXXXXXXX)
+    or return 1
+
+    # Functions and builtins can't run in the background in this fish, so a separate fish runs
+    # them, along with the definition of the function.
+    if functions -q -- $argv[2]; or builtin -q -- $argv[2]
+        set -l fish_exe (status fish-path)
+        set -l definition (functions --no-details -- $argv[2] 2>/dev/null | string collect)
+        $fish_exe -c "$definition"\n'$argv' -- $argv[2..] >$file &
+    else
+        $argv[2..] >$file &
+    end
+
+    # The handle is the pid and the output file. The first expansion of $var runs await.
+    set -g __fish_async_$var $last_pid $file
+end
@@ -0,0 +1,32 @@
+function await --description "Wait for commands started with async and store their output"
+    argparse -n await --min-args=1 h/help -- $argv
+    or return
+
+    if set -q _flag_help
+        __fish_print_help await
+        return 0
+    end
+
+    set -l ret 0
+    for var in $argv
+        set -l handle __fish_async_$var
+        if not set -q $handle
+            printf (_ "%s: No async command is pending for '%s'\n") await $var >&2
+            set ret 1
+            continue
+        end
+
+        set -l info $$handle
+        set -l pid $info[1]
+        set -l file $info[2]
+        # If the job already finished and was reaped, e.g. by a plain `wait`, the file holds
+        # all of its output and there is nothing to wait for.
+        if test -n "$pid"; and jobs -q $pid 2>/dev/null
+            wait $pid
+        end
+        set -g $var (cat $file)
+        command rm -f $file
+        set -e $handle
+    end
+    return $ret
+end
@@ -1,6 +1,9 @@
 function fish_clipboard_copy
     set -l cmdline
-    if isatty stdin
+    if set -q argv[1]
+        # Copy the arguments, which is how kills to the "+" register get here.
+        set cmdline (string join \n -- $argv | string collect -N)
+    else if isatty stdin
         # Copy the current selection, or the entire commandline if that is empty.
         # Don't use `string collect -N` here - `commandline` adds a newline.
         set cmdline (commandline --current-selection | string collect)
@@ -32,7 +35,12 @@ function fish_clipboard_copy
             return 1
         end
         set -l encoded (printf %s $cmdline | base64 | string join '')
-        printf '\e]52;c;%s\a' "$encoded"
+        if set -q STY
+            # screen doesn't know OSC 52, so pass it through to the terminal.
+            __fish_passthrough '\e]52;c;%s\a' "$encoded"
+        else
+            printf '\e]52;c;%s\a' "$encoded"
+        end
         # tmux requires user configuration to interpret OSC 52 on stdout.
         # Luckily we can still make this work for the common case by bypassing
         # tmux and writing to its underlying terminal.
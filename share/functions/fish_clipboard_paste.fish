@@ -26,6 +26,12 @@ function fish_clipboard_paste
     # Also split on \r, otherwise it looks confusing
     set data (string split \r -- $data | string split \n)
 
+    # Let the user clean up the paste, like for pastes from the terminal.
+    if functions -q fish_paste_filter
+        set data (fish_paste_filter (string join \n -- $data | string collect -N))
+        or return
+    end
+
     # If the current token has an unmatched single-quote,
     # escape all single-quotes (and backslashes) in the paste,
     # in order to turn it into a single literal token.
@@ -232,9 +232,10 @@ function fish_config --description "Launch fish's web based configuration"
                     set -l known_colors fish_color_{normal,command,keyword,quote,redirection,\
                         end,error,param,option,comment,selection,operator,escape,autosuggestion,\
                         cwd,user,host,host_remote,cancel,search_match} \
-                        fish_pager_color_{progress,background,prefix,completion,description,\
-                        selected_background,selected_prefix,selected_completion,selected_description,\
-                        secondary_background,secondary_prefix,secondary_completion,secondary_description}
+                        fish_pager_color_{progress,group,background,prefix,completion,description,\
+                        match,selected_background,selected_prefix,selected_completion,\
+                        selected_description,selected_match,secondary_background,secondary_prefix,\
+                        secondary_completion,secondary_description,secondary_match}
 
                     # If we are choosing a theme or saving from a named theme, load the theme now.
                     # Otherwise, we'll persist the currently loaded/themed variables (in case of `theme save`).
@@ -0,0 +1,31 @@
+function fish_notify -a cmd_line duration last_status -d "Notify that a long command finished"
+    # cmd_line: The command line of the command.
+    # duration: How long it took, in milliseconds.
+    # last_status: Its exit status.
+    set -l title (string shorten -m 40 -- $cmd_line)
+    set -l seconds (math --scale 0 $duration / 1000)
+    set -l body (printf (_ "Finished after %ss") $seconds)
+    if test "$last_status" -ne 0 2>/dev/null
+        set body (printf (_ "Failed with status %s after %ss") $last_status $seconds)
+    end
+    # Control characters would end the escape sequences early.
+    set title (string replace -ra '[[:cntrl:]]' ' ' -- $title)
+
+    if set -q KITTY_WINDOW_ID; or set -q WT_SESSION
+        or string match -q -- iTerm.app "$TERM_PROGRAM"
+        or string match -q -- WezTerm "$TERM_PROGRAM"
+        # OSC 9 only has a body.
+        __fish_passthrough '\e]9;%s\a' "$title: $body"
+    else if not set -q SSH_TTY; and string match -q Darwin -- "$(uname)"
+        # Over ssh, this would notify the remote machine.
+        osascript -e 'on run argv' \
+            -e 'display notification (item 2 of argv) with title (item 1 of argv)' \
+            -e 'end run' $title $body >/dev/null 2>&1
+    else if not set -q SSH_TTY; and command -q notify-send
+        notify-send -- $title $body >/dev/null 2>&1
+    else
+        # OSC 777 separates the title and the body with a semicolon.
+        # Terminals which don't support it ignore it.
+        __fish_passthrough '\e]777;notify;%s;%s\a' (string replace -a ';' , -- $title) $body
+    end
+end
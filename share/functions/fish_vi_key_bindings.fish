@@ -72,6 +72,7 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset -m insert a forward-single-char repaint-mode
     bind -s --preset -m insert A end-of-line repaint-mode
     bind -s --preset -m visual v begin-selection repaint-mode
+    bind -s --preset -m visual \cv begin-selection repaint-mode
 
     #bind -s --preset -m insert o "commandline -a \n" down-line repaint-mode
     #bind -s --preset -m insert O beginning-of-line "commandline -i \n" up-line repaint-mode # doesn't work
@@ -135,10 +136,6 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset d0 backward-kill-line
     bind -s --preset dw kill-word
     bind -s --preset dW kill-bigword
-    bind -s --preset diw forward-single-char forward-single-char backward-word kill-word
-    bind -s --preset diW forward-single-char forward-single-char backward-bigword kill-bigword
-    bind -s --preset daw forward-single-char forward-single-char backward-word kill-word
-    bind -s --preset daW forward-single-char forward-single-char backward-bigword kill-bigword
     bind -s --preset de kill-word
     bind -s --preset dE kill-bigword
     bind -s --preset db backward-kill-word
@@ -151,8 +148,9 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset dT begin-selection backward-jump forward-single-char kill-selection end-selection
     bind -s --preset dh backward-char delete-char
     bind -s --preset dl delete-char
-    bind -s --preset di backward-jump-till and repeat-jump-reverse and begin-selection repeat-jump kill-selection end-selection
-    bind -s --preset da backward-jump and repeat-jump-reverse and begin-selection repeat-jump kill-selection end-selection
+    # Text objects like "iw", "a(" or 'i"' are read by select-inner-object and select-around-object.
+    bind -s --preset di select-inner-object and kill-selection end-selection
+    bind -s --preset da select-around-object and kill-selection end-selection
     bind -s --preset 'd;' begin-selection repeat-jump kill-selection end-selection
     bind -s --preset 'd,' begin-selection repeat-jump-reverse kill-selection end-selection
 
@@ -165,10 +163,6 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset -m insert c0 backward-kill-line repaint-mode
     bind -s --preset -m insert cw kill-word repaint-mode
     bind -s --preset -m insert cW kill-bigword repaint-mode
-    bind -s --preset -m insert ciw forward-single-char forward-single-char backward-word kill-word repaint-mode
-    bind -s --preset -m insert ciW forward-single-char forward-single-char backward-bigword kill-bigword repaint-mode
-    bind -s --preset -m insert caw forward-single-char forward-single-char backward-word kill-word repaint-mode
-    bind -s --preset -m insert caW forward-single-char forward-single-char backward-bigword kill-bigword repaint-mode
     bind -s --preset -m insert ce kill-word repaint-mode
     bind -s --preset -m insert cE kill-bigword repaint-mode
     bind -s --preset -m insert cb backward-kill-word repaint-mode
@@ -181,8 +175,8 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset -m insert cT begin-selection backward-jump forward-single-char kill-selection end-selection repaint-mode
     bind -s --preset -m insert ch backward-char begin-selection kill-selection end-selection repaint-mode
     bind -s --preset -m insert cl begin-selection kill-selection end-selection repaint-mode
-    bind -s --preset -m insert ci backward-jump-till and repeat-jump-reverse and begin-selection repeat-jump kill-selection end-selection repaint-mode
-    bind -s --preset -m insert ca backward-jump and repeat-jump-reverse and begin-selection repeat-jump kill-selection end-selection repaint-mode
+    bind -s --preset -m insert ci select-inner-object and kill-selection end-selection repaint-mode
+    bind -s --preset -m insert ca select-around-object and kill-selection end-selection repaint-mode
 
     bind -s --preset '~' togglecase-char forward-single-char
     bind -s --preset gu downcase-word
@@ -198,10 +192,6 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset y0 backward-kill-line yank
     bind -s --preset yw kill-word yank
     bind -s --preset yW kill-bigword yank
-    bind -s --preset yiw forward-single-char forward-single-char backward-word kill-word yank
-    bind -s --preset yiW forward-single-char forward-single-char backward-bigword kill-bigword yank
-    bind -s --preset yaw forward-single-char forward-single-char backward-word kill-word yank
-    bind -s --preset yaW forward-single-char forward-single-char backward-bigword kill-bigword yank
     bind -s --preset ye kill-word yank
     bind -s --preset yE kill-bigword yank
     bind -s --preset yb backward-kill-word yank
@@ -214,8 +204,8 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset yT begin-selection backward-jump-till kill-selection yank end-selection
     bind -s --preset yh backward-char begin-selection kill-selection yank end-selection
     bind -s --preset yl begin-selection kill-selection yank end-selection
-    bind -s --preset yi backward-jump-till and repeat-jump-reverse and begin-selection repeat-jump kill-selection yank end-selection
-    bind -s --preset ya backward-jump and repeat-jump-reverse and begin-selection repeat-jump kill-selection yank end-selection
+    bind -s --preset yi select-inner-object and kill-selection yank end-selection
+    bind -s --preset ya select-around-object and kill-selection yank end-selection
 
     bind -s --preset f forward-jump
     bind -s --preset F backward-jump
@@ -228,6 +218,9 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     # in vim p means paste *after* current character, so go forward a char before pasting
     # also in vim, P means paste *at* current position (like at '|' with cursor = line),
     # \ so there's no need to go back a char, just paste it without moving
+    # Like in vim, '"a' makes the next delete, change, yank or put use register "a", and '"+' the
+    # system clipboard.
+    bind -s --preset '"' use-register
     bind -s --preset p forward-char yank
     bind -s --preset P yank
     bind -s --preset gp yank-pop
@@ -273,6 +266,8 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
     bind -s --preset -M visual e forward-word
     bind -s --preset -M visual E forward-bigword
     bind -s --preset -M visual o swap-selection-start-stop repaint-mode
+    bind -s --preset -M visual i select-inner-object repaint-mode
+    bind -s --preset -M visual a select-around-object repaint-mode
 
     bind -s --preset -M visual f forward-jump
     bind -s --preset -M visual t forward-jump-till
@@ -288,11 +283,13 @@ function fish_vi_key_bindings --description 'vi-like key bindings for fish'
 
     bind -s --preset -M visual -m insert c kill-selection end-selection repaint-mode
     bind -s --preset -M visual -m insert s kill-selection end-selection repaint-mode
+    bind -s --preset -M visual -m insert I multi-cursor-from-selection repaint-mode
     bind -s --preset -M visual -m default d kill-selection end-selection repaint-mode
     bind -s --preset -M visual -m default x kill-selection end-selection repaint-mode
     bind -s --preset -M visual -m default X kill-whole-line end-selection repaint-mode
     bind -s --preset -M visual -m default y kill-selection yank end-selection repaint-mode
     bind -s --preset -M visual -m default '"*y' "fish_clipboard_copy; commandline -f end-selection repaint-mode"
+    bind -s --preset -M visual '"' use-register
     bind -s --preset -M visual -m default '~' togglecase-selection end-selection repaint-mode
 
     bind -s --preset -M visual -m default \cc end-selection repaint-mode
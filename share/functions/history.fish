@@ -4,6 +4,9 @@
 function __fish_unexpected_hist_args --no-scope-shadowing
     if test -n "$search_mode"
         or set -q show_time[1]
+        or set -q _flag_show_status
+        or set -q _flag_json
+        or set -q filters[1]
         printf (_ "%ls: %ls: subcommand takes no options\n") $cmd $hist_cmd >&2
         return 0
     end
@@ -16,9 +19,10 @@ end
 
 function history --description "display or manipulate interactive command history"
     set -l cmd history
-    set -l options --exclusive 'c,e,p' --exclusive 'S,D,M,V,X'
-    set -a options h/help c/contains e/exact p/prefix
-    set -a options C/case-sensitive R/reverse z/null 't/show-time=?' 'n#max'
+    set -l options --exclusive 'c,e,p,regex' --exclusive 'S,D,M,V,X'
+    set -a options h/help c/contains e/exact p/prefix regex
+    set -a options C/case-sensitive R/reverse z/null 't/show-time=?' 'n#max' json format= show-status
+    set -a options before= after= cwd= status= from=
     # The following options are deprecated and will be removed in the next major release.
     # Note that they do not have usable short flags.
     set -a options S-search D-delete M-merge V-save X-clear
@@ -51,6 +55,11 @@ function history --description "display or manipulate interactive command histor
     and set -l search_mode --contains
     set -q _flag_exact
     and set -l search_mode --exact
+    set -q _flag_regex
+    and set -l search_mode --regex
+
+    # Filters for searching and deleting. Each is left out if it wasn't given.
+    set -l filters --before=$_flag_before --after=$_flag_after --cwd=$_flag_cwd --status=$_flag_status
 
     if set -q _flag_delete
         set hist_cmd delete
@@ -70,7 +79,7 @@ function history --description "display or manipulate interactive command histor
     # command. This allows the flags to appear before or after the subcommand.
     if not set -q hist_cmd[1]
         and set -q argv[1]
-        if contains $argv[1] search delete merge save clear clear-session
+        if contains $argv[1] search delete merge save clear clear-session export import
             set hist_cmd $argv[1]
             set -e argv[1]
         end
@@ -80,12 +89,23 @@ function history --description "display or manipulate interactive command histor
         set hist_cmd search # default to "search" if the user didn't specify a subcommand
     end
 
+    if set -q _flag_format
+        and not contains $hist_cmd export import
+        printf (_ "%ls: invalid option combination\n") $cmd >&2
+        return 1
+    end
+    if set -q _flag_from
+        and test $hist_cmd != merge
+        printf (_ "%ls: invalid option combination\n") $cmd >&2
+        return 1
+    end
+
     switch $hist_cmd
         case search # search the interactive command history
             test -z "$search_mode"
             and set search_mode --contains
 
-            if isatty stdout
+            if isatty stdout; and not set -q _flag_json
                 set -l pager less
                 set -q PAGER
                 and echo $PAGER | read -at pager
@@ -102,15 +122,16 @@ function history --description "display or manipulate interactive command histor
                 not set -qx LV # ask the pager lv not to strip colors
                 and set -x LV -c
 
-                builtin history search $search_mode $show_time $max_count $_flag_case_sensitive $_flag_reverse $_flag_null -- $argv | $pager
+                builtin history search $search_mode $filters $show_time $_flag_show_status $max_count $_flag_case_sensitive $_flag_reverse $_flag_null -- $argv | $pager
             else
-                builtin history search $search_mode $show_time $max_count $_flag_case_sensitive $_flag_reverse $_flag_null -- $argv
+                builtin history search $search_mode $filters $show_time $_flag_show_status $max_count $_flag_case_sensitive $_flag_reverse $_flag_null $_flag_json -- $argv
             end
 
         case delete # interactively delete history
             # TODO: Fix this to deal with history entries that have multiple lines.
             set -l searchterm $argv
             if not set -q argv[1]
+                and not set -q filters[1]
                 read -P"Search term: " searchterm
             end
 
@@ -119,7 +140,7 @@ function history --description "display or manipulate interactive command histor
             end
 
             if test $search_mode = --exact
-                builtin history delete $search_mode $_flag_case_sensitive -- $searchterm
+                builtin history delete $search_mode $filters $_flag_case_sensitive -- $searchterm
                 builtin history save
                 return
             end
@@ -127,7 +148,7 @@ function history --description "display or manipulate interactive command histor
             # TODO: Fix this so that requesting history entries with a timestamp works:
             #   set -l found_items (builtin history search $search_mode $show_time -- $argv)
             set -l found_items
-            set found_items (builtin history search $search_mode $_flag_case_sensitive --null -- $searchterm | string split0)
+            set found_items (builtin history search $search_mode $filters $_flag_case_sensitive --null -- $searchterm | string split0)
             if set -q found_items[1]
                 set -l found_items_count (count $found_items)
                 for i in (seq $found_items_count)
@@ -175,11 +196,17 @@ function history --description "display or manipulate interactive command histor
 
             builtin history save -- $argv
 
+        case export # print the history for other shells or tools
+            builtin history export --format=$_flag_format $search_mode $filters $show_time $_flag_show_status $_flag_null $_flag_json -- $argv
+
+        case import # add history from other shells or tools
+            builtin history import --format=$_flag_format $search_mode $filters $show_time $_flag_show_status $_flag_null $_flag_json -- $argv
+
         case merge # merge the persistent interactive command history with our history
             __fish_unexpected_hist_args $argv
             and return 1
 
-            builtin history merge -- $argv
+            builtin history merge --from=$_flag_from -- $argv
 
         case clear # clear the interactive command history
             __fish_unexpected_hist_args $argv
@@ -1487,6 +1487,7 @@ class FishConfigHTTPRequestHandler(SimpleHTTPServer.SimpleHTTPRequestHandler):
                     "fish_color_operator",
                     "fish_color_escape",
                     "fish_color_autosuggestion",
+                    "fish_color_continuation",
                     "fish_color_cwd",
                     "fish_color_user",
                     "fish_color_host",
@@ -1494,18 +1495,22 @@ class FishConfigHTTPRequestHandler(SimpleHTTPServer.SimpleHTTPRequestHandler):
                     "fish_color_cancel",
                     "fish_color_search_match",
                     "fish_pager_color_progress",
+                    "fish_pager_color_group",
                     "fish_pager_color_background",
                     "fish_pager_color_prefix",
                     "fish_pager_color_completion",
                     "fish_pager_color_description",
+                    "fish_pager_color_match",
                     "fish_pager_color_selected_background",
                     "fish_pager_color_selected_prefix",
                     "fish_pager_color_selected_completion",
                     "fish_pager_color_selected_description",
+                    "fish_pager_color_selected_match",
                     "fish_pager_color_secondary_background",
                     "fish_pager_color_secondary_prefix",
                     "fish_pager_color_secondary_completion",
                     "fish_pager_color_secondary_description",
+                    "fish_pager_color_secondary_match",
                 )
             )
             output=""
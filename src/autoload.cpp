@@ -11,6 +11,7 @@
 
 #include "common.h"
 #include "env.h"
+#include "flog.h"
 #include "io.h"
 #include "lru.h"
 #include "parser.h"
@@ -181,6 +182,29 @@ std::vector<wcstring> autoload_t::get_autoloaded_commands() const {
     return result;
 }
 
+std::vector<wcstring> autoload_t::forget_changed_commands(const environment_t &env) {
+    std::vector<wcstring> paths;
+    if (maybe_t<env_var_t> mvar = env.get(env_var_name_)) {
+        paths = mvar->as_list();
+    }
+    // Start from a fresh cache so edits are seen right away, not after the staleness interval.
+    cache_ = make_unique<autoload_file_cache_t>(paths);
+
+    std::vector<wcstring> result;
+    for (const auto &kv : autoloaded_files_) {
+        if (current_autoloading_.count(kv.first) > 0) continue;
+        auto mfile = cache_->check(kv.first);
+        if (!mfile || mfile->file_id != kv.second) {
+            result.push_back(kv.first);
+        }
+    }
+    for (const wcstring &cmd : result) {
+        autoloaded_files_.erase(cmd);
+    }
+    std::sort(result.begin(), result.end());
+    return result;
+}
+
 maybe_t<wcstring> autoload_t::resolve_command(const wcstring &cmd, const environment_t &env) {
     if (maybe_t<env_var_t> mvar = env.get(env_var_name_)) {
         return resolve_command(cmd, mvar->as_list());
@@ -212,12 +236,43 @@ maybe_t<wcstring> autoload_t::resolve_command(const wcstring &cmd,
         return none();
     }
 
+    // If a file that comes earlier in the path has appeared since we last loaded this command,
+    // say so: a plugin installed later can silently take over a definition that was in use.
+    auto prev = autoloaded_paths_.find(cmd);
+    if (prev != autoloaded_paths_.end() && prev->second != mfile->path &&
+        file_id_for_path(prev->second) != kInvalidFileID) {
+        FLOGF(warning,
+              _(L"'%ls' now shadows '%ls', which '%ls' was loaded from before. "
+                L"See `status autoload %ls`."),
+              mfile->path.c_str(), prev->second.c_str(), cmd.c_str(), cmd.c_str());
+    }
+
     // We're going to (tell our caller to) autoload this command.
     current_autoloading_.insert(cmd);
     autoloaded_files_[cmd] = mfile->file_id;
+    autoloaded_paths_[cmd] = mfile->path;
     return std::move(mfile->path);
 }
 
+autoload_info_t autoload_t::get_info(const wcstring &cmd, const environment_t &env) const {
+    autoload_info_t result;
+    // As in locate_file(), an empty command would find the directories themselves.
+    if (cmd.empty() || cmd[0] == L'\0') return result;
+    if (maybe_t<env_var_t> mvar = env.get(env_var_name_)) {
+        for (const wcstring &dir : mvar->as_list()) {
+            wcstring path = dir + L"/" + cmd + L".fish";
+            if (file_id_for_path(path) != kInvalidFileID) {
+                result.candidates.push_back(std::move(path));
+            }
+        }
+    }
+    auto iter = autoloaded_paths_.find(cmd);
+    if (iter != autoloaded_paths_.end()) {
+        result.loaded_path = iter->second;
+    }
+    return result;
+}
+
 void autoload_t::perform_autoload(const wcstring &path, parser_t &parser) {
     // We do the useful part of what exec_subshell does ourselves
     // - we source the file.
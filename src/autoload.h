@@ -14,11 +14,23 @@
 #include "maybe.h"
 #include "wutil.h"
 
+#include <vector>
+
 class autoload_file_cache_t;
 class environment_t;
 class parser_t;
 struct autoload_tester_t;
 
+/// What an autoloader knows about the files for a command, for `status autoload`.
+struct autoload_info_t {
+    /// Every file that could provide the command, in the order they are searched. The first one
+    /// wins.
+    std::vector<wcstring> candidates;
+
+    /// The file the command was last loaded from, if any.
+    maybe_t<wcstring> loaded_path;
+};
+
 /// autoload_t is a class that knows how to autoload .fish files from a list of directories. This
 /// is used by autoloading functions and completions. It maintains a file cache, which is
 /// responsible for potentially cached accesses of files, and then a list of files that have
@@ -33,6 +45,10 @@ class autoload_t {
     /// A map from command to the files we have autoloaded.
     std::unordered_map<wcstring, file_id_t> autoloaded_files_;
 
+    /// A map from command to the path it was last autoloaded from. Unlike autoloaded_files_, this
+    /// is kept when a changed file is forgotten, so we can tell if a different file took over.
+    std::unordered_map<wcstring, wcstring> autoloaded_paths_;
+
     /// The list of commands that we are currently autoloading.
     std::unordered_set<wcstring> current_autoloading_;
 
@@ -96,11 +112,22 @@ class autoload_t {
     /// commands.
     std::vector<wcstring> get_autoloaded_commands() const;
 
+    /// Look at the files of all autoloaded commands again, bypassing the cache, and forget the
+    /// commands whose file has changed or disappeared since it was loaded, so that
+    /// resolve_command() returns their path again.
+    /// \return the names of the forgotten commands, sorted.
+    std::vector<wcstring> forget_changed_commands(const environment_t &env);
+
+    /// \return every file in our paths that could provide \p cmd, and the one it was loaded from.
+    /// This always hits the disk.
+    autoload_info_t get_info(const wcstring &cmd, const environment_t &env) const;
+
     /// Mark that all autoloaded files have been forgotten.
     /// Future calls to path_to_autoload() will return previously-returned paths.
     void clear() {
         // Note there is no reason to invalidate the cache here.
         autoloaded_files_.clear();
+        autoloaded_paths_.clear();
     }
 };
 
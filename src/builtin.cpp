@@ -34,8 +34,10 @@
 #include "builtins/cd.h"
 #include "builtins/commandline.h"
 #include "builtins/complete.h"
+#include "builtins/coproc.h"
 #include "builtins/disown.h"
 #include "builtins/eval.h"
+#include "builtins/exec.h"
 #include "builtins/fg.h"
 #include "builtins/functions.h"
 #include "builtins/history.h"
@@ -60,6 +62,7 @@
 #include "parse_constants.h"
 #include "parse_util.h"
 #include "parser.h"
+#include "plugins.rs.h"
 #include "proc.h"
 #include "reader.h"
 #include "wgetopt.h"
@@ -366,6 +369,7 @@ static constexpr builtin_data_t builtin_datas[] = {
     {L"complete", &builtin_complete, N_(L"Edit command specific completions")},
     {L"contains", &implemented_in_rust, N_(L"Search for a specified string in a list")},
     {L"continue", &builtin_break_continue, N_(L"Skip over remaining innermost loop")},
+    {L"coproc", &builtin_coproc, N_(L"Start a command connected to the shell by pipes")},
     {L"count", &builtin_count, N_(L"Count the number of arguments")},
     {L"disown", &builtin_disown, N_(L"Remove job from job list")},
     {L"echo", &implemented_in_rust, N_(L"Print arguments")},
@@ -373,10 +377,14 @@ static constexpr builtin_data_t builtin_datas[] = {
     {L"emit", &implemented_in_rust, N_(L"Emit an event")},
     {L"end", &builtin_generic, N_(L"End a block of commands")},
     {L"eval", &builtin_eval, N_(L"Evaluate a string as a statement")},
-    {L"exec", &builtin_generic, N_(L"Run command in current process")},
+    {L"exec", &builtin_exec, N_(L"Run command in current process")},
     {L"exit", &implemented_in_rust, N_(L"Exit the shell")},
     {L"false", &builtin_false, N_(L"Return an unsuccessful result")},
     {L"fg", &builtin_fg, N_(L"Send job to foreground")},
+    {L"fish_command_not_found", &implemented_in_rust,
+     N_(L"Explain a command that was not found")},
+    {L"fish_update_completions", &implemented_in_rust,
+     N_(L"Generate completions from man pages")},
     {L"for", &builtin_generic, N_(L"Perform a set of commands multiple times")},
     {L"function", &builtin_generic, N_(L"Define a new function")},
     {L"functions", &builtin_functions, N_(L"List or remove functions")},
@@ -387,6 +395,7 @@ static constexpr builtin_data_t builtin_datas[] = {
     {L"not", &builtin_generic, N_(L"Negate exit status of job")},
     {L"or", &builtin_generic, N_(L"Execute command if previous command failed")},
     {L"path", &builtin_path, N_(L"Handle paths")},
+    {L"plugin", &implemented_in_rust, N_(L"Load plugins")},
     {L"printf", &implemented_in_rust, N_(L"Prints formatted text")},
     {L"pwd", &implemented_in_rust, N_(L"Print the working directory")},
     {L"random", &implemented_in_rust, N_(L"Generate random number")},
@@ -424,7 +433,9 @@ static const builtin_data_t *builtin_lookup(const wcstring &name) {
 }
 
 /// Is there a builtin command with the given name?
-bool builtin_exists(const wcstring &cmd) { return static_cast<bool>(builtin_lookup(cmd)); }
+bool builtin_exists(const wcstring &cmd) {
+    return builtin_lookup(cmd) || plugin_builtin_exists(cmd);
+}
 
 /// Is the command a keyword we need to special-case the handling of `-h` and `--help`.
 static const wchar_t *const help_builtins[] = {L"for", L"while",  L"function", L"if",
@@ -497,6 +508,9 @@ std::vector<wcstring> builtin_get_names() {
     for (const auto &builtin_data : builtin_datas) {
         result.push_back(builtin_data.name);
     }
+    for (const wcstring &name : plugin_builtin_names()->vals) {
+        result.push_back(name);
+    }
     return result;
 }
 
@@ -509,6 +523,9 @@ void builtin_get_names(completion_list_t *list) {
     for (const auto &builtin_data : builtin_datas) {
         append_completion(list, builtin_data.name);
     }
+    for (const wcstring &name : plugin_builtin_names()->vals) {
+        append_completion(list, name);
+    }
 }
 
 /// Return a one-line description of the specified builtin.
@@ -517,6 +534,8 @@ const wchar_t *builtin_get_desc(const wcstring &name) {
     const builtin_data_t *builtin = builtin_lookup(name);
     if (builtin) {
         result = _(builtin->desc);
+    } else if (plugin_builtin_exists(name)) {
+        result = _(L"Builtin provided by a plugin");
     }
     return result;
 }
@@ -549,6 +568,12 @@ static maybe_t<RustBuiltin> try_get_rust_builtin(const wcstring &cmd) {
     if (cmd == L"exit") {
         return RustBuiltin::Exit;
     }
+    if (cmd == L"fish_command_not_found") {
+        return RustBuiltin::FishCommandNotFound;
+    }
+    if (cmd == L"fish_update_completions") {
+        return RustBuiltin::FishUpdateCompletions;
+    }
     if (cmd == L"math") {
         return RustBuiltin::Math;
     }
@@ -576,6 +601,12 @@ static maybe_t<RustBuiltin> try_get_rust_builtin(const wcstring &cmd) {
     if (cmd == L"return") {
         return RustBuiltin::Return;
     }
+    if (cmd == L"plugin") {
+        return RustBuiltin::Plugin;
+    }
+    if (plugin_builtin_exists(cmd)) {
+        return RustBuiltin::PluginBuiltin;
+    }
     return none();
 }
 
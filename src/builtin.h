@@ -121,7 +121,11 @@ enum class RustBuiltin : int32_t {
     Echo,
     Emit,
     Exit,
+    FishCommandNotFound,
+    FishUpdateCompletions,
     Math,
+    Plugin,
+    PluginBuiltin,
     Printf,
     Pwd,
     Random,
@@ -19,8 +19,11 @@
 #include "../env.h"
 #include "../exec.h"
 #include "../fallback.h"  // IWYU pragma: keep
+#include "../function.h"
 #include "../io.h"
 #include "../maybe.h"
+#include "../null_terminated_array.h"
+#include "../parse_util.h"
 #include "../parser.h"
 #include "../wcstringutil.h"
 #include "../wgetopt.h"  // IWYU pragma: keep
@@ -35,6 +38,7 @@ struct option_spec_t {
     wchar_t short_flag;
     wcstring long_flag;
     wcstring validation_command;
+    wcstring description;
     std::vector<wcstring> vals;
     bool short_flag_valid{true};
     int num_allowed{0};
@@ -53,6 +57,7 @@ struct argparse_cmd_opts_t {
     wchar_t implicit_int_flag = L'\0';
     wcstring name;
     std::vector<wcstring> raw_exclusive_flags;
+    std::vector<wcstring> raw_descriptions;
     std::vector<wcstring> argv;
     std::unordered_map<wchar_t, option_spec_ref_t> options;
     std::unordered_map<wcstring, wchar_t> long_to_short_flag;
@@ -60,12 +65,23 @@ struct argparse_cmd_opts_t {
 };
 }  // namespace
 
-static const wchar_t *const short_options = L"+:hn:six:N:X:";
+static const wchar_t *const short_options = L"+:hn:six:N:X:d:";
 static const struct woption long_options[] = {
     {L"stop-nonopt", no_argument, 's'},    {L"ignore-unknown", no_argument, 'i'},
     {L"name", required_argument, 'n'},     {L"exclusive", required_argument, 'x'},
     {L"help", no_argument, 'h'},           {L"min-args", required_argument, 'N'},
-    {L"max-args", required_argument, 'X'}, {}};
+    {L"max-args", required_argument, 'X'}, {L"description", required_argument, 'd'},
+    {}};
+
+/// Find the short flag of the option named \p flag, which may be its short or its long flag.
+static maybe_t<wchar_t> find_short_flag(const argparse_cmd_opts_t &opts, const wcstring &flag) {
+    if (flag.size() == 1 && opts.options.find(flag[0]) != opts.options.end()) {
+        return flag[0];
+    }
+    auto x = opts.long_to_short_flag.find(flag);
+    if (x != opts.long_to_short_flag.end()) return x->second;
+    return none();
+}
 
 // Check if any pair of mutually exclusive options was seen. Note that since every option must have
 // a short name we only need to check those.
@@ -133,19 +149,13 @@ static int parse_exclusive_args(argparse_cmd_opts_t &opts, io_streams_t &streams
 
         std::vector<wchar_t> exclusive_set;
         for (const auto &flag : xflags) {
-            if (flag.size() == 1 && opts.options.find(flag[0]) != opts.options.end()) {
-                // It's a short flag.
-                exclusive_set.push_back(flag[0]);
+            // Long flags are stored as their short flag equivalent.
+            if (auto short_flag = find_short_flag(opts, flag)) {
+                exclusive_set.push_back(*short_flag);
             } else {
-                auto x = opts.long_to_short_flag.find(flag);
-                if (x != opts.long_to_short_flag.end()) {
-                    // It's a long flag we store as its short flag equivalent.
-                    exclusive_set.push_back(x->second);
-                } else {
-                    streams.err.append_format(_(L"%ls: exclusive flag '%ls' is not valid\n"),
-                                              opts.name.c_str(), flag.c_str());
-                    return STATUS_CMD_ERROR;
-                }
+                streams.err.append_format(_(L"%ls: exclusive flag '%ls' is not valid\n"),
+                                          opts.name.c_str(), flag.c_str());
+                return STATUS_CMD_ERROR;
             }
         }
 
@@ -156,6 +166,23 @@ static int parse_exclusive_args(argparse_cmd_opts_t &opts, io_streams_t &streams
     return STATUS_CMD_OK;
 }
 
+// Like parse_exclusive_args, this attaches the `--description` values to the options they are for.
+static int parse_descriptions(argparse_cmd_opts_t &opts, io_streams_t &streams) {
+    for (const wcstring &raw_desc : opts.raw_descriptions) {
+        size_t eq = raw_desc.find(L'=');
+        maybe_t<wchar_t> short_flag;
+        if (eq != wcstring::npos) short_flag = find_short_flag(opts, raw_desc.substr(0, eq));
+        if (!short_flag) {
+            streams.err.append_format(_(L"%ls: description '%ls' is not valid\n"),
+                                      opts.name.c_str(), raw_desc.c_str());
+            return STATUS_CMD_ERROR;
+        }
+        opts.options.at(*short_flag)->description = raw_desc.substr(eq + 1);
+    }
+
+    return STATUS_CMD_OK;
+}
+
 static bool parse_flag_modifiers(const argparse_cmd_opts_t &opts, const option_spec_ref_t &opt_spec,
                                  const wcstring &option_spec, const wchar_t **opt_spec_str,
                                  io_streams_t &streams) {
@@ -380,6 +407,11 @@ static int parse_cmd_opts(argparse_cmd_opts_t &opts, int *optind,  //!OCLINT(hig
                 opts.raw_exclusive_flags.push_back(w.woptarg);
                 break;
             }
+            case 'd': {
+                // These are parsed once all the options are known, like the exclusive flags.
+                opts.raw_descriptions.push_back(w.woptarg);
+                break;
+            }
             case 'h': {
                 opts.print_help = true;
                 break;
@@ -726,6 +758,9 @@ maybe_t<int> builtin_argparse(parser_t &parser, io_streams_t &streams, const wch
     retval = parse_exclusive_args(opts, streams);
     if (retval != STATUS_CMD_OK) return retval;
 
+    retval = parse_descriptions(opts, streams);
+    if (retval != STATUS_CMD_OK) return retval;
+
     // wgetopt expects the first argument to be the command, and skips it.
     // if optind was 0 we'd already have returned.
     assert(optind > 0 && "Optind is 0?");
@@ -738,3 +773,79 @@ maybe_t<int> builtin_argparse(parser_t &parser, io_streams_t &streams, const wch
     set_argparse_result_vars(parser.vars(), opts);
     return retval;
 }
+
+/// Collect the unescaped arguments of an argparse invocation up to and including the `--`, if they
+/// are all literal. Anything that needs expanding, like a variable, could only be known by running
+/// the function.
+static bool get_literal_spec(const wcstring &src, const ast::decorated_statement_t &stmt,
+                             std::vector<wcstring> *out_args) {
+    for (size_t i = 0; i < stmt.args_or_redirs().count(); i++) {
+        const auto &v = *stmt.args_or_redirs().at(i);
+        if (!v.is_argument()) continue;
+        wcstring arg = *v.argument().source(src);
+        size_t cursor = 0, start, end;
+        if (parse_util_locate_cmdsubst_range(arg, &cursor, nullptr, &start, &end, true) != 0) {
+            return false;
+        }
+        if (!unescape_string_in_place(&arg, UNESCAPE_SPECIAL)) return false;
+        for (wchar_t c : arg) {
+            if (c >= EXPAND_RESERVED_BASE && c <= EXPAND_RESERVED_END) return false;
+        }
+        out_args->push_back(arg);
+        if (arg == L"--") return true;
+    }
+    return false;
+}
+
+std::vector<argparse_option_t> argparse_function_options(const wcstring &name, parser_t &parser) {
+    std::vector<argparse_option_t> result;
+    function_load(name, parser);
+    function_properties_ref_t props = function_get_props(name);
+    if (!props) return result;
+
+    const wcstring &src = props->parsed_source->src();
+    for (auto ast_traversal = new_ast_traversal(*props->func_node->ptr());;) {
+        auto node = ast_traversal->next();
+        if (!node->has_value()) break;
+        const ast::decorated_statement_t *stmt = node->try_as_decorated_statement();
+        if (!stmt) continue;
+        wcstring command = *stmt->command().source(src);
+        if (!unescape_string_in_place(&command, UNESCAPE_DEFAULT) || command != L"argparse") {
+            continue;
+        }
+
+        std::vector<wcstring> args{command};
+        if (!get_literal_spec(src, *stmt, &args)) continue;
+        null_terminated_array_t<wchar_t> argv(args);
+
+        // Parse it like argparse would, but quietly. Errors will be reported when the function
+        // runs.
+        null_output_stream_t null_output;
+        io_streams_t streams(null_output, null_output);
+        argparse_cmd_opts_t opts;
+        opts.name = name;
+        int optind;
+        if (parse_cmd_opts(opts, &optind, static_cast<int>(args.size()), argv.get(), parser,
+                           streams) != STATUS_CMD_OK ||
+            opts.print_help || parse_descriptions(opts, streams) != STATUS_CMD_OK) {
+            continue;
+        }
+
+        for (const auto &kv : opts.options) {
+            const option_spec_t &opt_spec = *kv.second;
+            // Without a short flag, the implicit int flag is only given as a number.
+            if (opt_spec.short_flag == opts.implicit_int_flag && !opt_spec.short_flag_valid) {
+                continue;
+            }
+            argparse_option_t opt;
+            opt.short_flag = opt_spec.short_flag_valid ? opt_spec.short_flag : L'\0';
+            opt.long_flag = opt_spec.long_flag;
+            opt.takes_value = opt_spec.num_allowed != 0;
+            opt.value_optional = opt_spec.num_allowed == -1;
+            opt.description = opt_spec.description;
+            result.push_back(std::move(opt));
+        }
+        break;
+    }
+    return result;
+}
@@ -2,10 +2,30 @@
 #ifndef FISH_BUILTIN_ARGPARSE_H
 #define FISH_BUILTIN_ARGPARSE_H
 
+#include <vector>
+
+#include "../common.h"
 #include "../maybe.h"
 
 class parser_t;
 struct io_streams_t;
 
 maybe_t<int> builtin_argparse(parser_t &parser, io_streams_t &streams, const wchar_t **argv);
+
+/// An option that a function defines with argparse.
+struct argparse_option_t {
+    /// The short flag, or 0 if there is only a long one.
+    wchar_t short_flag;
+    /// The long flag, or empty if there is only a short one.
+    wcstring long_flag;
+    /// Whether the option takes a value, and whether that value may be left out.
+    bool takes_value;
+    bool value_optional;
+    /// What was given with `argparse --description`.
+    wcstring description;
+};
+
+/// Return the options of the first argparse call in the function \p name whose option specs are
+/// all literal, loading the function if needed. This is used by `complete --from-argparse`.
+std::vector<argparse_option_t> argparse_function_options(const wcstring &name, parser_t &parser);
 #endif
@@ -17,12 +17,23 @@
 #include "../highlight.h"
 #include "../input.h"
 #include "../io.h"
+#include "../json.rs.h"
 #include "../maybe.h"
 #include "../parser.h"
+#include "../reader.h"
 #include "../wgetopt.h"
 #include "../wutil.h"  // IWYU pragma: keep
 
-enum { BIND_INSERT, BIND_ERASE, BIND_KEY_NAMES, BIND_FUNCTION_NAMES };
+enum {
+    BIND_INSERT,
+    BIND_ERASE,
+    BIND_KEY_NAMES,
+    BIND_FUNCTION_NAMES,
+    BIND_RECORD,
+    BIND_STOP_RECORDING,
+    BIND_PLAY,
+    BIND_MACRO_NAMES
+};
 struct bind_cmd_opts_t {
     bool all = false;
     bool bind_mode_given = false;
@@ -34,9 +45,14 @@ struct bind_cmd_opts_t {
     bool user = false;
     bool have_preset = false;
     bool preset = false;
+    bool json = false;
     int mode = BIND_INSERT;
+    int timeout_ms = 0;
     const wchar_t *bind_mode = DEFAULT_BIND_MODE;
     const wchar_t *sets_bind_mode = L"";
+    const wchar_t *new_mode = nullptr;
+    const wchar_t *inherits = nullptr;
+    const wchar_t *macro_name = nullptr;
 };
 
 namespace {
@@ -54,17 +70,27 @@ class builtin_bind_t {
     /// lock again.
     acquired_lock<input_mapping_set_t> input_mappings_;
 
+    /// The output for --json.
+    rust::Box<JsonWriter> json_ = new_json_writer();
+
     void list(const wchar_t *bind_mode, bool user, parser_t &parser, io_streams_t &streams);
+    void list_mode_definitions(const wchar_t *bind_mode, parser_t &parser, io_streams_t &streams);
+    bool define_mode(io_streams_t &streams);
+    bool macro(io_streams_t &streams);
+    void print_command(const wcstring &out, parser_t &parser, io_streams_t &streams);
     void key_names(bool all, io_streams_t &streams);
     void function_names(io_streams_t &streams);
     bool add(const wcstring &seq, const wchar_t *const *cmds, size_t cmds_len, const wchar_t *mode,
-             const wchar_t *sets_mode, bool terminfo, bool user, io_streams_t &streams);
+             const wchar_t *sets_mode, bool terminfo, bool user, int timeout_ms,
+             io_streams_t &streams);
     bool erase(const wchar_t *const *seq, bool all, const wchar_t *mode, bool use_terminfo,
                bool user, io_streams_t &streams);
     bool get_terminfo_sequence(const wcstring &seq, wcstring *out_seq, io_streams_t &streams) const;
     bool insert(int optind, int argc, const wchar_t **argv, parser_t &parser,
                 io_streams_t &streams);
     void list_modes(io_streams_t &streams);
+    void print_names(const std::vector<wcstring> &names, io_streams_t &streams);
+    void print_json(io_streams_t &streams);
     bool list_one(const wcstring &seq, const wcstring &bind_mode, bool user, parser_t &parser,
                   io_streams_t &streams);
     bool list_one(const wcstring &seq, const wcstring &bind_mode, bool user, bool preset,
@@ -77,11 +103,49 @@ bool builtin_bind_t::list_one(const wcstring &seq, const wcstring &bind_mode, bo
                               parser_t &parser, io_streams_t &streams) {
     std::vector<wcstring> ecmds;
     wcstring sets_mode, out;
+    int timeout_ms = 0;
 
-    if (!input_mappings_->get(seq, bind_mode, &ecmds, user, &sets_mode)) {
+    if (!input_mappings_->get(seq, bind_mode, &ecmds, user, &sets_mode, &timeout_ms)) {
         return false;
     }
 
+    if (opts->json) {
+        json_->begin_object();
+        json_->key(L"mode");
+        json_->string(bind_mode);
+        json_->key(L"sets_mode");
+        if (!sets_mode.empty() && sets_mode != bind_mode) {
+            json_->string(sets_mode);
+        } else {
+            json_->null();
+        }
+        json_->key(L"timeout");
+        if (timeout_ms > 0) {
+            json_->integer(timeout_ms);
+        } else {
+            json_->null();
+        }
+        json_->key(L"preset");
+        json_->boolean(!user);
+        json_->key(L"key");
+        wcstring tname;
+        if (input_terminfo_get_name(seq, &tname)) {
+            json_->string(tname);
+        } else {
+            json_->null();
+        }
+        json_->key(L"sequence");
+        json_->string(seq);
+        json_->key(L"commands");
+        json_->begin_array();
+        for (const auto &ecmd : ecmds) {
+            json_->string(ecmd);
+        }
+        json_->end_array();
+        json_->end_object();
+        return true;
+    }
+
     out.append(L"bind");
 
     // Append the mode flags if applicable.
@@ -96,6 +160,9 @@ bool builtin_bind_t::list_one(const wcstring &seq, const wcstring &bind_mode, bo
         out.append(L" -m ");
         out.append(escape_string(sets_mode));
     }
+    if (timeout_ms > 0) {
+        out.append(format_string(L" --timeout %d", timeout_ms));
+    }
 
     // Append the name.
     wcstring tname;
@@ -116,7 +183,12 @@ bool builtin_bind_t::list_one(const wcstring &seq, const wcstring &bind_mode, bo
         out.append(escape_string(ecmd));
     }
     out.push_back(L'\n');
+    print_command(out, parser, streams);
+    return true;
+}
 
+/// Print a bind command, highlighted if printing to a terminal.
+void builtin_bind_t::print_command(const wcstring &out, parser_t &parser, io_streams_t &streams) {
     if (!streams.out_is_redirected && isatty(STDOUT_FILENO)) {
         std::vector<highlight_spec_t> colors;
         highlight_shell(out, colors, parser.context());
@@ -124,8 +196,45 @@ bool builtin_bind_t::list_one(const wcstring &seq, const wcstring &bind_mode, bo
     } else {
         streams.out.append(out);
     }
+}
 
-    return true;
+/// List the modes defined with --new-mode, or just \p bind_mode if it is not null.
+void builtin_bind_t::list_mode_definitions(const wchar_t *bind_mode, parser_t &parser,
+                                           io_streams_t &streams) {
+    if (opts->json) return;
+    for (const auto &kv : input_mappings_->defined_modes()) {
+        if (bind_mode && kv.first != bind_mode) continue;
+        wcstring out = L"bind --new-mode " + escape_string(kv.first);
+        if (!kv.second.empty()) {
+            out.append(L" --inherits ");
+            out.append(escape_string(kv.second));
+        }
+        out.push_back(L'\n');
+        print_command(out, parser, streams);
+    }
+}
+
+/// Define or erase the mode given with --new-mode.
+/// Returns true on error, like add() and erase().
+bool builtin_bind_t::define_mode(io_streams_t &streams) {
+    if (opts->mode == BIND_ERASE) {
+        if (!input_mappings_->erase_mode(opts->new_mode)) {
+            if (!opts->silent) {
+                streams.err.append_format(_(L"%ls: No mode named '%ls' was defined\n"), L"bind",
+                                          opts->new_mode);
+            }
+            return true;
+        }
+        return false;
+    }
+
+    const wchar_t *parent = opts->inherits ? opts->inherits : L"";
+    if (!input_mappings_->define_mode(opts->new_mode, parent)) {
+        streams.err.append_format(_(L"%ls: Mode '%ls' cannot inherit from itself\n"), L"bind",
+                                  opts->new_mode);
+        return true;
+    }
+    return false;
 }
 
 // Overload with both kinds of bindings.
@@ -161,20 +270,32 @@ void builtin_bind_t::list(const wchar_t *bind_mode, bool user, parser_t &parser,
 /// \param all if set, all terminfo key binding names will be printed. If not set, only ones that
 /// are defined for this terminal are printed.
 void builtin_bind_t::key_names(bool all, io_streams_t &streams) {
-    const std::vector<wcstring> names = input_terminfo_get_names(!all);
-    for (const wcstring &name : names) {
-        streams.out.append(name);
-        streams.out.push(L'\n');
-    }
+    print_names(input_terminfo_get_names(!all), streams);
 }
 
 /// Print all the special key binding functions to string buffer used for standard output.
 void builtin_bind_t::function_names(io_streams_t &streams) {
-    std::vector<wcstring> names = input_function_get_names();
+    print_names(input_function_get_names(), streams);
+}
 
-    for (const auto &name : names) {
-        auto seq = name.c_str();
-        streams.out.append_format(L"%ls\n", seq);
+/// Print \p names one per line, or as a JSON array for --json.
+void builtin_bind_t::print_names(const std::vector<wcstring> &names, io_streams_t &streams) {
+    for (const wcstring &name : names) {
+        if (opts->json) {
+            json_->string(name);
+        } else {
+            streams.out.append(name);
+            streams.out.push(L'\n');
+        }
+    }
+}
+
+/// Print the JSON output, if --json was given.
+void builtin_bind_t::print_json(io_streams_t &streams) {
+    if (opts->json) {
+        json_->end_array();
+        streams.out.append(*json_->contents());
+        streams.out.push(L'\n');
     }
 }
 
@@ -204,17 +325,17 @@ bool builtin_bind_t::get_terminfo_sequence(const wcstring &seq, wcstring *out_se
 /// Add specified key binding.
 bool builtin_bind_t::add(const wcstring &seq, const wchar_t *const *cmds, size_t cmds_len,
                          const wchar_t *mode, const wchar_t *sets_mode, bool terminfo, bool user,
-                         io_streams_t &streams) {
+                         int timeout_ms, io_streams_t &streams) {
     if (terminfo) {
         wcstring seq2;
         if (get_terminfo_sequence(seq, &seq2, streams)) {
-            input_mappings_->add(seq2, cmds, cmds_len, mode, sets_mode, user);
+            input_mappings_->add(seq2, cmds, cmds_len, mode, sets_mode, user, timeout_ms);
         } else {
             return true;
         }
 
     } else {
-        input_mappings_->add(seq, cmds, cmds_len, mode, sets_mode, user);
+        input_mappings_->add(seq, cmds, cmds_len, mode, sets_mode, user, timeout_ms);
     }
 
     return false;
@@ -258,6 +379,52 @@ bool builtin_bind_t::erase(const wchar_t *const *seq, bool all, const wchar_t *m
     return res;
 }
 
+/// Start or stop recording a keyboard macro, or play one.
+/// Returns true on error, like add() and erase().
+bool builtin_bind_t::macro(io_streams_t &streams) {
+    const wchar_t *cmd = L"bind";
+    switch (opts->mode) {
+        case BIND_RECORD: {
+            if (!input_mappings_->start_recording()) {
+                streams.err.append_format(_(L"%ls: A macro is already being recorded\n"), cmd);
+                return true;
+            }
+            return false;
+        }
+        case BIND_STOP_RECORDING: {
+            if (!input_mappings_->stop_recording(opts->macro_name)) {
+                streams.err.append_format(_(L"%ls: No macro is being recorded\n"), cmd);
+                return true;
+            }
+            return false;
+        }
+        case BIND_PLAY: {
+            // The keys of a macro played while recording would be recorded twice.
+            if (input_mappings_->is_recording()) {
+                streams.err.append_format(_(L"%ls: Cannot play a macro while recording one\n"),
+                                          cmd);
+                return true;
+            }
+            auto keys = input_mappings_->get_macro(opts->macro_name);
+            if (!keys) {
+                if (!opts->silent) {
+                    streams.err.append_format(_(L"%ls: No macro named '%ls'\n"), cmd,
+                                              opts->macro_name);
+                }
+                return true;
+            }
+            // The keys go through the bindings again, after any input that is already queued.
+            for (wchar_t c : *keys) {
+                reader_queue_ch(c);
+            }
+            return false;
+        }
+        default: {
+            DIE("unexpected macro mode");
+        }
+    }
+}
+
 bool builtin_bind_t::insert(int optind, int argc, const wchar_t **argv, parser_t &parser,
                             io_streams_t &streams) {
     const wchar_t *cmd = argv[0];
@@ -284,6 +451,8 @@ bool builtin_bind_t::insert(int optind, int argc, const wchar_t **argv, parser_t
             list(opts->bind_mode_given ? opts->bind_mode : nullptr, false, parser, streams);
         }
         if (opts->user) {
+            list_mode_definitions(opts->bind_mode_given ? opts->bind_mode : nullptr, parser,
+                                  streams);
             list(opts->bind_mode_given ? opts->bind_mode : nullptr, true, parser, streams);
         }
     } else if (arg_count == 1) {
@@ -313,7 +482,8 @@ bool builtin_bind_t::insert(int optind, int argc, const wchar_t **argv, parser_t
     } else {
         // Actually insert!
         if (add(argv[optind], argv + (optind + 1), argc - (optind + 1), opts->bind_mode,
-                opts->sets_bind_mode, opts->use_terminfo, opts->user, streams)) {
+                opts->sets_bind_mode, opts->use_terminfo, opts->user, opts->timeout_ms,
+                streams)) {
             return true;
         }
     }
@@ -337,9 +507,10 @@ void builtin_bind_t::list_modes(io_streams_t &streams) {
     for (const input_mapping_name_t &binding : preset_lst) {
         modes.insert(binding.mode);
     }
-    for (const auto &mode : modes) {
-        streams.out.append_format(L"%ls\n", mode.c_str());
+    for (const auto &kv : input_mappings_->defined_modes()) {
+        modes.insert(kv.first);
     }
+    print_names(std::vector<wcstring>(modes.begin(), modes.end()), streams);
 }
 
 static int parse_cmd_opts(bind_cmd_opts_t &opts, int *optind,  //!OCLINT(high ncss method)
@@ -350,13 +521,21 @@ static int parse_cmd_opts(bind_cmd_opts_t &opts, int *optind,  //!OCLINT(high nc
                                                   {L"erase", no_argument, 'e'},
                                                   {L"function-names", no_argument, 'f'},
                                                   {L"help", no_argument, 'h'},
+                                                  {L"inherits", required_argument, 4},
+                                                  {L"json", no_argument, 1},
                                                   {L"key", no_argument, 'k'},
                                                   {L"key-names", no_argument, 'K'},
+                                                  {L"list-macros", no_argument, 8},
                                                   {L"list-modes", no_argument, 'L'},
                                                   {L"mode", required_argument, 'M'},
+                                                  {L"new-mode", required_argument, 3},
+                                                  {L"play", required_argument, 7},
                                                   {L"preset", no_argument, 'p'},
+                                                  {L"record", no_argument, 5},
                                                   {L"sets-mode", required_argument, 'm'},
                                                   {L"silent", no_argument, 's'},
+                                                  {L"stop-recording", required_argument, 6},
+                                                  {L"timeout", required_argument, 2},
                                                   {L"user", no_argument, 'u'},
                                                   {}};
 
@@ -380,6 +559,55 @@ static int parse_cmd_opts(bind_cmd_opts_t &opts, int *optind,  //!OCLINT(high nc
                 opts.print_help = true;
                 break;
             }
+            case 1: {
+                opts.json = true;
+                break;
+            }
+            case 2: {
+                opts.timeout_ms = fish_wcstoi(w.woptarg);
+                if (errno || opts.timeout_ms <= 0) {
+                    streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd, w.woptarg);
+                    builtin_print_error_trailer(parser, streams.err, cmd);
+                    return STATUS_INVALID_ARGS;
+                }
+                break;
+            }
+            case 3: {
+                if (!valid_var_name(w.woptarg)) {
+                    streams.err.append_format(BUILTIN_ERR_BIND_MODE, cmd, w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                opts.new_mode = w.woptarg;
+                break;
+            }
+            case 4: {
+                if (!valid_var_name(w.woptarg)) {
+                    streams.err.append_format(BUILTIN_ERR_BIND_MODE, cmd, w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                opts.inherits = w.woptarg;
+                break;
+            }
+            case 5: {
+                opts.mode = BIND_RECORD;
+                break;
+            }
+            case 6:
+            case 7: {
+                if (!valid_var_name(w.woptarg)) {
+                    streams.err.append_format(
+                        _(L"%ls: %ls: invalid macro name. See `help identifiers`\n"), cmd,
+                        w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                opts.mode = opt == 6 ? BIND_STOP_RECORDING : BIND_PLAY;
+                opts.macro_name = w.woptarg;
+                break;
+            }
+            case 8: {
+                opts.mode = BIND_MACRO_NAMES;
+                break;
+            }
             case L'k': {
                 opts.use_terminfo = true;
                 break;
@@ -455,8 +683,10 @@ maybe_t<int> builtin_bind_t::builtin_bind(parser_t &parser, io_streams_t &stream
     int retval = parse_cmd_opts(opts, &optind, argc, argv, parser, streams);
     if (retval != STATUS_CMD_OK) return retval;
 
+    json_->begin_array();
     if (opts.list_modes) {
         list_modes(streams);
+        print_json(streams);
         return STATUS_CMD_OK;
     }
     if (opts.print_help) {
@@ -464,6 +694,45 @@ maybe_t<int> builtin_bind_t::builtin_bind(parser_t &parser, io_streams_t &stream
         return STATUS_CMD_OK;
     }
 
+    // JSON output is only for listing, not for adding or erasing bindings.
+    if (opts.json && (opts.mode == BIND_ERASE || (opts.mode == BIND_INSERT && argc - optind > 1))) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    // Defining a mode takes no other arguments, and only --inherits makes sense with it.
+    if ((opts.inherits && !opts.new_mode) ||
+        (opts.new_mode && (opts.json || argc - optind > 0 || opts.all ||
+                           (opts.mode != BIND_INSERT && opts.mode != BIND_ERASE) ||
+                           (opts.mode == BIND_ERASE && opts.inherits)))) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+    if (opts.new_mode) {
+        return define_mode(streams) ? STATUS_CMD_ERROR : STATUS_CMD_OK;
+    }
+
+    // Recording and playing macros takes no other arguments.
+    bool is_macro = opts.mode == BIND_RECORD || opts.mode == BIND_STOP_RECORDING ||
+                    opts.mode == BIND_PLAY;
+    if (is_macro && (opts.json || argc - optind > 0)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+    if (is_macro) {
+        return macro(streams) ? STATUS_CMD_ERROR : STATUS_CMD_OK;
+    }
+
+    // A timeout only applies to bindings being added.
+    if (opts.timeout_ms > 0 && !(opts.mode == BIND_INSERT && argc - optind > 1)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
     // Default to user mode
     if (!opts.have_preset && !opts.have_user) opts.user = true;
     switch (opts.mode) {
@@ -498,16 +767,23 @@ maybe_t<int> builtin_bind_t::builtin_bind(parser_t &parser, io_streams_t &stream
             function_names(streams);
             break;
         }
+        case BIND_MACRO_NAMES: {
+            print_names(input_mappings_->get_macro_names(), streams);
+            break;
+        }
         default: {
             streams.err.append_format(_(L"%ls: Invalid state\n"), cmd);
             return STATUS_CMD_ERROR;
         }
     }
 
+    print_json(streams);
     return STATUS_CMD_OK;
 }
 
 maybe_t<int> builtin_bind(parser_t &parser, io_streams_t &streams, const wchar_t **argv) {
+    // This needs the terminfo key names and preset bindings. Do it before taking the lock.
+    init_input();
     builtin_bind_t bind;
     return bind.builtin_bind(parser, streams, argv);
 }
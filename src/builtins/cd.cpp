@@ -19,6 +19,7 @@
 #include "../fds.h"
 #include "../io.h"
 #include "../maybe.h"
+#include "../output.rs.h"
 #include "../parser.h"
 #include "../path.h"
 #include "../wutil.h"  // IWYU pragma: keep
@@ -107,6 +108,9 @@ maybe_t<int> builtin_cd(parser_t &parser, io_streams_t &streams, const wchar_t *
 
         parser.libdata().cwd_fd = std::make_shared<const autoclose_fd_t>(std::move(dir_fd));
         parser.set_var_and_fire(L"PWD", ENV_EXPORT | ENV_GLOBAL, std::move(norm_dir));
+        // Let the terminal open new tabs here, unless this is a command substitution whose
+        // directory change doesn't outlive it.
+        if (!parser.libdata().is_subshell) report_cwd_ffi();
         return STATUS_CMD_OK;
     }
 
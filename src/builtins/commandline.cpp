@@ -8,6 +8,7 @@
 #include <cwchar>
 #include <string>
 
+#include "../ast.h"
 #include "../builtin.h"
 #include "../common.h"
 #include "../fallback.h"  // IWYU pragma: keep
@@ -21,6 +22,7 @@
 #include "../proc.h"
 #include "../reader.h"
 #include "../tokenizer.h"
+#include "../wcstringutil.h"
 #include "../wgetopt.h"
 #include "../wutil.h"  // IWYU pragma: keep
 
@@ -93,10 +95,12 @@ static void replace_part(const wchar_t *begin, const wchar_t *end, const wchar_t
 /// \param cut_at_cursor whether printing should stop at the surrent cursor position
 /// \param tokenize whether the string should be tokenized, printing one string token on every line
 /// and skipping non-string tokens
+/// \param tokens_raw whether tokens should be printed as written, without unescaping them
 /// \param buffer the original command line buffer
 /// \param cursor_pos the position of the cursor in the command line
 static void write_part(const wchar_t *begin, const wchar_t *end, int cut_at_cursor, int tokenize,
-                       const wchar_t *buffer, size_t cursor_pos, io_streams_t &streams) {
+                       bool tokens_raw, const wchar_t *buffer, size_t cursor_pos,
+                       io_streams_t &streams) {
     size_t pos = cursor_pos - (begin - buffer);
 
     if (tokenize) {
@@ -109,7 +113,7 @@ static void write_part(const wchar_t *begin, const wchar_t *end, int cut_at_curs
 
             if (token->type_ == token_type_t::string) {
                 wcstring tmp = *tok->text_of(*token);
-                unescape_string_in_place(&tmp, UNESCAPE_INCOMPLETE);
+                if (!tokens_raw) unescape_string_in_place(&tmp, UNESCAPE_INCOMPLETE);
                 out.append(tmp);
                 out.push_back(L'\n');
             }
@@ -140,6 +144,8 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
     bool selection_mode = false;
 
     bool tokenize = false;
+    bool tokens_raw = false;
+    bool ast_mode = false;
 
     bool cursor_mode = false;
     bool selection_start_mode = false;
@@ -149,8 +155,13 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
     bool paging_mode = false;
     bool paging_full_mode = false;
     bool is_valid = false;
+    bool print_above = false;
+    const wchar_t *checkpoint_name = nullptr;
+    const wchar_t *revert_name = nullptr;
     const wchar_t *begin = nullptr, *end = nullptr;
     const wchar_t *override_buffer = nullptr;
+    const wchar_t *insert_at = nullptr;
+    const wchar_t *replace_start = nullptr;
 
     const auto &ld = parser.libdata();
 
@@ -176,6 +187,13 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
                                                   {L"paging-mode", no_argument, 'P'},
                                                   {L"paging-full-mode", no_argument, 'F'},
                                                   {L"is-valid", no_argument, 1},
+                                                  {L"print-above", no_argument, 2},
+                                                  {L"checkpoint", required_argument, 3},
+                                                  {L"revert", required_argument, 4},
+                                                  {L"ast", no_argument, 5},
+                                                  {L"tokens-raw", no_argument, 6},
+                                                  {L"insert-at", required_argument, 7},
+                                                  {L"replace-range", required_argument, 8},
                                                   {}};
 
     int opt;
@@ -263,6 +281,35 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
                 is_valid = true;
                 break;
             }
+            case 2: {
+                print_above = true;
+                break;
+            }
+            case 3: {
+                checkpoint_name = w.woptarg;
+                break;
+            }
+            case 4: {
+                revert_name = w.woptarg;
+                break;
+            }
+            case 5: {
+                ast_mode = true;
+                break;
+            }
+            case 6: {
+                tokenize = true;
+                tokens_raw = true;
+                break;
+            }
+            case 7: {
+                insert_at = w.woptarg;
+                break;
+            }
+            case 8: {
+                replace_start = w.woptarg;
+                break;
+            }
             case 'h': {
                 builtin_print_help(parser, streams, cmd);
                 return STATUS_CMD_OK;
@@ -281,6 +328,31 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
         }
     }
 
+    // The syntax tree can be printed for the buffer, job, process or token, optionally cut at the
+    // cursor, but not combined with anything else.
+    if (ast_mode &&
+        (function_mode || print_above || checkpoint_name || revert_name || selection_mode ||
+         tokenize || append_mode || cursor_mode || line_mode || search_mode || paging_mode ||
+         paging_full_mode || selection_start_mode || selection_end_mode || is_valid ||
+         argc != w.woptind)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, argv[0]);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    // Edits at given offsets work on the buffer, job, process or token, but not combined with
+    // anything else.
+    bool offset_mode = insert_at || replace_start;
+    if (offset_mode &&
+        (function_mode || print_above || checkpoint_name || revert_name || selection_mode ||
+         tokenize || cut_at_cursor || append_mode || cursor_mode || line_mode || search_mode ||
+         paging_mode || paging_full_mode || selection_start_mode || selection_end_mode ||
+         is_valid || (insert_at && replace_start))) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, argv[0]);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
     if (function_mode) {
         int i;
 
@@ -327,6 +399,54 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
         return STATUS_CMD_OK;
     }
 
+    if (print_above) {
+        // Check for invalid switch combinations.
+        if (buffer_part || cut_at_cursor || append_mode || tokenize || cursor_mode || line_mode ||
+            search_mode || paging_mode || selection_mode || selection_start_mode ||
+            selection_end_mode || is_valid) {
+            streams.err.append_format(BUILTIN_ERR_COMBO, argv[0]);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+
+        std::vector<wcstring> lines(argv + w.woptind, argv + argc);
+        reader_write_above_prompt(join_strings(lines, L'\n'));
+        return STATUS_CMD_OK;
+    }
+
+    if (checkpoint_name || revert_name) {
+        // Check for invalid switch combinations.
+        if (buffer_part || cut_at_cursor || append_mode || tokenize || cursor_mode || line_mode ||
+            search_mode || paging_mode || selection_mode || selection_start_mode ||
+            selection_end_mode || is_valid || (checkpoint_name && revert_name)) {
+            streams.err.append_format(BUILTIN_ERR_COMBO, argv[0]);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+        if (argc != w.woptind) {
+            streams.err.append_format(BUILTIN_ERR_TOO_MANY_ARGUMENTS, argv[0]);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+
+        if (checkpoint_name) {
+            if (!commandline_set_checkpoint(checkpoint_name, {rstate.text, rstate.cursor_pos})) {
+                streams.err.append_format(_(L"%ls: No command line is being edited\n"), cmd);
+                return STATUS_CMD_ERROR;
+            }
+            return STATUS_CMD_OK;
+        }
+
+        // Going back is an edit like any other, so it can be undone too.
+        auto checkpoint = commandline_get_checkpoint(revert_name);
+        if (!checkpoint) {
+            streams.err.append_format(_(L"%ls: No checkpoint named '%ls'\n"), cmd, revert_name);
+            return STATUS_CMD_ERROR;
+        }
+        commandline_set_buffer(std::move(checkpoint->text), checkpoint->cursor_pos);
+        return STATUS_CMD_OK;
+    }
+
     if (selection_mode) {
         if (rstate.selection) {
             streams.out.append(rstate.text.c_str() + rstate.selection->start,
@@ -336,7 +456,13 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
     }
 
     // Check for invalid switch combinations.
-    if ((selection_start_mode || selection_end_mode) && (argc - w.woptind)) {
+    if (selection_start_mode && selection_end_mode && (argc - w.woptind)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, argv[0]);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    if ((selection_start_mode || selection_end_mode) && (argc - w.woptind > 1)) {
         streams.err.append_format(BUILTIN_ERR_TOO_MANY_ARGUMENTS, argv[0]);
         builtin_print_error_trailer(parser, streams.err, cmd);
         return STATUS_INVALID_ARGS;
@@ -397,6 +523,31 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
         return (state.pager_mode && state.pager_fully_disclosed) ? 0 : 1;
     }
 
+    if ((selection_start_mode || selection_end_mode) && (argc - w.woptind)) {
+        long pos = fish_wcstol(argv[w.woptind]);
+        if (errno) {
+            streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd, argv[w.woptind]);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+        if (!rstate.initialized) {
+            return STATUS_CMD_ERROR;
+        }
+
+        // The other end of the selection stays where it is, or is the cursor if nothing is
+        // selected yet.
+        size_t new_pos = std::max(0L, pos);
+        size_t start = rstate.selection ? rstate.selection->start : rstate.cursor_pos;
+        size_t end = rstate.selection ? rstate.selection->end() : rstate.cursor_pos;
+        if (selection_start_mode) {
+            start = new_pos;
+        } else {
+            end = new_pos;
+        }
+        commandline_set_selection(std::min(start, end), std::max(start, end));
+        return STATUS_CMD_OK;
+    }
+
     if (selection_start_mode) {
         if (!rstate.selection) {
             return STATUS_CMD_ERROR;
@@ -425,7 +576,7 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
     if (override_buffer) {
         current_buffer = override_buffer;
         current_cursor_pos = std::wcslen(current_buffer);
-    } else if (!ld.transient_commandlines.empty() && !cursor_mode) {
+    } else if (!ld.transient_commandlines.empty() && !cursor_mode && !offset_mode) {
         transient = ld.transient_commandlines.back();
         current_buffer = transient.c_str();
         current_cursor_pos = transient.size();
@@ -496,9 +647,70 @@ maybe_t<int> builtin_commandline(parser_t &parser, io_streams_t &streams, const
     }
 
     int arg_count = argc - w.woptind;
-    if (arg_count == 0) {
-        write_part(begin, end, cut_at_cursor, tokenize, current_buffer, current_cursor_pos,
-                   streams);
+    if (offset_mode) {
+        // Offsets are relative to the selected part, like the cursor position.
+        long part_len = end - begin;
+        long cut_start = fish_wcstol(insert_at ? insert_at : replace_start);
+        if (errno) {
+            streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd,
+                                      insert_at ? insert_at : replace_start);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+        long cut_end = cut_start;
+        int first_arg = w.woptind;
+        if (replace_start) {
+            if (arg_count == 0) {
+                streams.err.append_format(BUILTIN_ERR_MISSING, cmd, L"--replace-range");
+                builtin_print_error_trailer(parser, streams.err, cmd);
+                return STATUS_INVALID_ARGS;
+            }
+            cut_end = fish_wcstol(argv[first_arg]);
+            if (errno) {
+                streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd, argv[first_arg]);
+                builtin_print_error_trailer(parser, streams.err, cmd);
+                return STATUS_INVALID_ARGS;
+            }
+            first_arg++;
+        } else if (arg_count == 0) {
+            // Nothing to insert, like --insert without arguments.
+            return STATUS_CMD_ERROR;
+        }
+        cut_start = std::max(0L, std::min(cut_start, part_len));
+        cut_end = std::max(0L, std::min(cut_end, part_len));
+        if (cut_start > cut_end) {
+            streams.err.append_format(_(L"%ls: Range end %ld is before its start %ld\n"), cmd,
+                                      cut_end, cut_start);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+
+        std::vector<wcstring> lines(argv + first_arg, argv + argc);
+        wcstring insert = join_strings(lines, L'\n');
+        size_t offset = begin - current_buffer;
+        size_t from = offset + cut_start, to = offset + cut_end;
+        wcstring out = current_buffer;
+        out.replace(from, to - from, insert);
+
+        // Keep the cursor on the same text. If that text was replaced, put it after the
+        // replacement.
+        size_t out_pos = current_cursor_pos;
+        if (out_pos > from) {
+            out_pos = out_pos >= to ? out_pos - (to - from) + insert.size() : from + insert.size();
+        }
+        commandline_set_buffer(std::move(out), out_pos);
+    } else if (ast_mode) {
+        size_t len = end - begin;
+        if (cut_at_cursor) len = current_cursor_pos - (begin - current_buffer);
+        wcstring src(begin, len);
+        auto ast = ast_parse(src, parse_flag_continue_after_error |
+                                      parse_flag_accept_incomplete_tokens |
+                                      parse_flag_leave_unterminated);
+        streams.out.append(*ast->to_json(src));
+        streams.out.push(L'\n');
+    } else if (arg_count == 0) {
+        write_part(begin, end, cut_at_cursor, tokenize, tokens_raw, current_buffer,
+                   current_cursor_pos, streams);
     } else if (arg_count == 1) {
         replace_part(begin, end, argv[w.woptind], append_mode, current_buffer, current_cursor_pos);
     } else {
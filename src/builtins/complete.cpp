@@ -17,6 +17,7 @@
 #include "../fallback.h"  // IWYU pragma: keep
 #include "../highlight.h"
 #include "../io.h"
+#include "../json.rs.h"
 #include "../maybe.h"
 #include "../parse_constants.h"
 #include "../parse_util.h"
@@ -37,25 +38,26 @@ static void builtin_complete_add2(const wcstring &cmd, bool cmd_is_path, const w
                                   const std::vector<wcstring> &old_opts,
                                   completion_mode_t result_mode,
                                   const std::vector<wcstring> &condition, const wchar_t *comp,
-                                  const wchar_t *desc, complete_flags_t flags) {
+                                  const wchar_t *desc, const wchar_t *group,
+                                  complete_flags_t flags) {
     for (const wchar_t *s = short_opt; *s; s++) {
         complete_add(cmd, cmd_is_path, wcstring{*s}, option_type_short, result_mode, condition,
-                     comp, desc, flags);
+                     comp, desc, flags, group);
     }
 
     for (const wcstring &gnu_opt : gnu_opts) {
         complete_add(cmd, cmd_is_path, gnu_opt, option_type_double_long, result_mode, condition,
-                     comp, desc, flags);
+                     comp, desc, flags, group);
     }
 
     for (const wcstring &old_opt : old_opts) {
         complete_add(cmd, cmd_is_path, old_opt, option_type_single_long, result_mode, condition,
-                     comp, desc, flags);
+                     comp, desc, flags, group);
     }
 
     if (old_opts.empty() && gnu_opts.empty() && short_opt[0] == L'\0') {
         complete_add(cmd, cmd_is_path, wcstring(), option_type_args_only, result_mode, condition,
-                     comp, desc, flags);
+                     comp, desc, flags, group);
     }
 }
 
@@ -66,15 +68,16 @@ static void builtin_complete_add(const std::vector<wcstring> &cmds,
                                  const std::vector<wcstring> &old_opt,
                                  completion_mode_t result_mode,
                                  const std::vector<wcstring> &condition, const wchar_t *comp,
-                                 const wchar_t *desc, complete_flags_t flags) {
+                                 const wchar_t *desc, const wchar_t *group,
+                                 complete_flags_t flags) {
     for (const wcstring &cmd : cmds) {
         builtin_complete_add2(cmd, false /* not path */, short_opt, gnu_opt, old_opt, result_mode,
-                              condition, comp, desc, flags);
+                              condition, comp, desc, group, flags);
     }
 
     for (const wcstring &path : paths) {
         builtin_complete_add2(path, true /* is path */, short_opt, gnu_opt, old_opt, result_mode,
-                              condition, comp, desc, flags);
+                              condition, comp, desc, group, flags);
     }
 }
 
@@ -133,6 +136,11 @@ static void builtin_complete_print(const wcstring &cmd, io_streams_t &streams, p
 /// Values used for long-only options.
 enum {
     opt_escape = 1,
+    opt_json = 2,
+    opt_no_sort = 3,
+    opt_cache = 4,
+    opt_flush_cache = 5,
+    opt_from_argparse = 6,
 };
 /// The complete builtin. Used for specifying programmable tab-completions. Calls the functions in
 // complete.cpp for any heavy lifting.
@@ -143,7 +151,7 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
     int remove = 0;
     wcstring short_opt;
     std::vector<wcstring> gnu_opt, old_opt, subcommand;
-    const wchar_t *comp = L"", *desc = L"";
+    const wchar_t *comp = L"", *desc = L"", *group = L"";
     std::vector<wcstring> condition;
     bool do_complete = false;
     bool have_do_complete_param = false;
@@ -153,8 +161,13 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
     std::vector<wcstring> wrap_targets;
     bool preserve_order = false;
     bool unescape_output = true;
+    bool json = false;
+    bool no_sort = false;
+    int cache_seconds = -1;
+    bool flush_cache = false;
+    const wchar_t *argparse_function = nullptr;
 
-    static const wchar_t *const short_options = L":a:c:p:s:l:o:d:fFrxeuAn:C::w:hk";
+    static const wchar_t *const short_options = L":a:c:p:s:l:o:d:g:fFrxeuAn:C::w:hk";
     static const struct woption long_options[] = {{L"exclusive", no_argument, 'x'},
                                                   {L"no-files", no_argument, 'f'},
                                                   {L"force-files", no_argument, 'F'},
@@ -166,6 +179,7 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
                                                   {L"old-option", required_argument, 'o'},
                                                   {L"subcommand", required_argument, 'S'},
                                                   {L"description", required_argument, 'd'},
+                                                  {L"group", required_argument, 'g'},
                                                   {L"arguments", required_argument, 'a'},
                                                   {L"erase", no_argument, 'e'},
                                                   {L"unauthoritative", no_argument, 'u'},
@@ -176,6 +190,12 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
                                                   {L"help", no_argument, 'h'},
                                                   {L"keep-order", no_argument, 'k'},
                                                   {L"escape", no_argument, opt_escape},
+                                                  {L"json", no_argument, opt_json},
+                                                  {L"no-sort", no_argument, opt_no_sort},
+                                                  {L"cache", required_argument, opt_cache},
+                                                  {L"flush-cache", no_argument, opt_flush_cache},
+                                                  {L"from-argparse", required_argument,
+                                                   opt_from_argparse},
                                                   {}};
 
     bool have_x = false;
@@ -225,6 +245,11 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
                 assert(desc);
                 break;
             }
+            case 'g': {
+                group = w.woptarg;
+                assert(group);
+                break;
+            }
             case 'u': {
                 // This option was removed in commit 1911298 and is now a no-op.
                 break;
@@ -293,6 +318,31 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
                 unescape_output = false;
                 break;
             }
+            case opt_json: {
+                json = true;
+                break;
+            }
+            case opt_no_sort: {
+                no_sort = true;
+                break;
+            }
+            case opt_cache: {
+                cache_seconds = fish_wcstoi(w.woptarg);
+                if (errno || cache_seconds < 0) {
+                    streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd, w.woptarg);
+                    builtin_print_error_trailer(parser, streams.err, cmd);
+                    return STATUS_INVALID_ARGS;
+                }
+                break;
+            }
+            case opt_flush_cache: {
+                flush_cache = true;
+                break;
+            }
+            case opt_from_argparse: {
+                argparse_function = w.woptarg;
+                break;
+            }
             case 'h': {
                 builtin_print_help(parser, streams, cmd);
                 return STATUS_CMD_OK;
@@ -366,6 +416,39 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
         }
     }
 
+    // No arguments that would add or remove anything means we print the matching completions.
+    bool defines_nothing = path.empty() && gnu_opt.empty() && short_opt.empty() &&
+                           old_opt.empty() && !remove && !*comp && !*desc && !*group &&
+                           condition.empty() && wrap_targets.empty() && !result_mode.no_files &&
+                           !result_mode.force_files && !result_mode.requires_param;
+    bool print_definitions =
+        defines_nothing && cache_seconds < 0 && !flush_cache && !argparse_function;
+
+    if (cache_seconds >= 0 && (do_complete || (cmd_to_complete.empty() && path.empty()))) {
+        streams.err.append_format(BUILTIN_ERR_COMBO2, cmd, L"--cache requires a command");
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+    if (argparse_function && (do_complete || (cmd_to_complete.empty() && path.empty()))) {
+        streams.err.append_format(BUILTIN_ERR_COMBO2, cmd, L"--from-argparse requires a command");
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    // JSON output is only for printing completions.
+    if (json && !do_complete && !print_definitions) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+    if (no_sort && !do_complete) {
+        streams.err.append_format(BUILTIN_ERR_COMBO2, cmd, L"--no-sort requires --do-complete");
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+    auto json_writer = new_json_writer();
+    json_writer->begin_array();
+
     if (do_complete) {
         if (!have_do_complete_param) {
             // No argument given, try to use the current commandline.
@@ -397,6 +480,12 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
             completion_list_t comp = complete(
                 do_complete_param, completion_request_options_t::normal(), parser.context());
 
+            // With --no-sort, keep the order the completions were generated in, like --keep-order
+            // does for the arguments of a single completion.
+            if (no_sort) {
+                for (auto &c : comp) c.flags |= COMPLETE_DONT_SORT;
+            }
+
             // Apply the same sort and deduplication treatment as pager completions
             completions_sort_and_prioritize(&comp);
 
@@ -423,6 +512,20 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
                     unescape_string_in_place(&faux_cmdline_with_completion, UNESCAPE_DEFAULT);
                 }
 
+                if (json) {
+                    json_writer->begin_object();
+                    json_writer->key(L"completion");
+                    json_writer->string(faux_cmdline_with_completion);
+                    json_writer->key(L"description");
+                    json_writer->string(next.description);
+                    if (!next.group.empty()) {
+                        json_writer->key(L"group");
+                        json_writer->string(next.group);
+                    }
+                    json_writer->end_object();
+                    continue;
+                }
+
                 // Append any description.
                 if (!next.description.empty()) {
                     faux_cmdline_with_completion.reserve(faux_cmdline_with_completion.size() + 2 +
@@ -436,12 +539,17 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
 
             parser.libdata().builtin_complete_current_commandline = false;
         }
-    } else if (path.empty() && gnu_opt.empty() && short_opt.empty() && old_opt.empty() && !remove &&
-               !*comp && !*desc && condition.empty() && wrap_targets.empty() &&
-               !result_mode.no_files && !result_mode.force_files && !result_mode.requires_param) {
-        // No arguments that would add or remove anything specified, so we print the definitions of
-        // all matching completions.
-        if (cmd_to_complete.empty()) {
+    } else if (print_definitions) {
+        // Print the definitions of all matching completions.
+        if (json) {
+            if (cmd_to_complete.empty()) {
+                complete_print_json(L"", *json_writer);
+            } else {
+                for (auto &cmd : cmd_to_complete) {
+                    complete_print_json(cmd, *json_writer);
+                }
+            }
+        } else if (cmd_to_complete.empty()) {
             builtin_complete_print(L"", streams, parser);
         } else {
             for (auto &cmd : cmd_to_complete) {
@@ -459,9 +567,25 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
 
         if (remove) {
             builtin_complete_remove(cmd_to_complete, path, short_opt.c_str(), gnu_opt, old_opt);
-        } else {
+        } else if (!defines_nothing) {
             builtin_complete_add(cmd_to_complete, path, short_opt.c_str(), gnu_opt, old_opt,
-                                 result_mode, condition, comp, desc, flags);
+                                 result_mode, condition, comp, desc, group, flags);
+        }
+
+        if (flush_cache) {
+            if (cmd_to_complete.empty() && path.empty()) complete_flush_cache();
+            for (const auto &i : cmd_to_complete) complete_flush_cache(i);
+            for (const auto &i : path) complete_flush_cache(i);
+        }
+        if (cache_seconds >= 0) {
+            for (const auto &i : cmd_to_complete) complete_set_cache(i, false, cache_seconds);
+            for (const auto &i : path) complete_set_cache(i, true, cache_seconds);
+        }
+        if (argparse_function && !remove) {
+            for (const auto &i : cmd_to_complete) {
+                complete_set_argparse(i, false, argparse_function);
+            }
+            for (const auto &i : path) complete_set_argparse(i, true, argparse_function);
         }
 
         // Handle wrap targets (probably empty). We only wrap commands, not paths.
@@ -472,5 +596,10 @@ maybe_t<int> builtin_complete(parser_t &parser, io_streams_t &streams, const wch
         }
     }
 
+    if (json) {
+        json_writer->end_array();
+        streams.out.append(*json_writer->contents());
+        streams.out.push(L'\n');
+    }
     return STATUS_CMD_OK;
 }
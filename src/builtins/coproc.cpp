@@ -0,0 +1,85 @@
+// Implementation of the coproc builtin.
+#include "config.h"  // IWYU pragma: keep
+
+#include "coproc.h"
+
+#include <unistd.h>
+
+#include <memory>
+#include <utility>
+
+#include "../builtin.h"
+#include "../common.h"
+#include "../env.h"
+#include "../fallback.h"  // IWYU pragma: keep
+#include "../fds.h"
+#include "../fds.rs.h"
+#include "../io.h"
+#include "../maybe.h"
+#include "../parser.h"
+#include "../wutil.h"  // IWYU pragma: keep
+
+/// The coproc builtin. Starts a command in the background, connected to the shell by a pipe to its
+/// stdin and a pipe from its stdout. The shell's ends of the pipes are stored in a local variable,
+/// and are closed when the enclosing scope ends, like fds opened by `exec --open`.
+maybe_t<int> builtin_coproc(parser_t &parser, io_streams_t &streams, const wchar_t **argv) {
+    const wchar_t *cmd = argv[0];
+    int argc = builtin_count_args(argv);
+    help_only_cmd_opts_t opts;
+
+    int optind;
+    int retval = parse_help_only_cmd_opts(opts, &optind, argc, argv, parser, streams);
+    if (retval != STATUS_CMD_OK) return retval;
+
+    if (opts.print_help) {
+        builtin_print_help(parser, streams, cmd);
+        return STATUS_CMD_OK;
+    }
+
+    if (argc - optind < 2) {
+        streams.err.append_format(BUILTIN_ERR_MIN_ARG_COUNT1, cmd, 2, argc - optind);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    const wchar_t *varname = argv[optind];
+    if (!valid_var_name(varname)) {
+        streams.err.append_format(BUILTIN_ERR_VARNAME, cmd, varname);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    // Our arguments have already been expanded, so escape them to run them as-is.
+    wcstring new_cmd;
+    for (int i = optind + 1; i < argc; i++) {
+        new_cmd += escape_string(argv[i]);
+        new_cmd += L' ';
+    }
+    new_cmd += L'&';
+
+    auto to_child = make_autoclose_pipes();
+    auto from_child = make_autoclose_pipes();
+    if (!to_child || !from_child) {
+        streams.err.append_format(L"%ls: %ls\n", cmd, PIPE_ERROR);
+        return STATUS_CMD_ERROR;
+    }
+
+    io_chain_t ios = *streams.io_chain;
+    ios.push_back(std::make_shared<io_pipe_t>(STDIN_FILENO, true, std::move(to_child->read)));
+    ios.push_back(std::make_shared<io_pipe_t>(STDOUT_FILENO, false, std::move(from_child->write)));
+    auto res = parser.eval_with(new_cmd, ios, nullptr, block_type_t::top);
+    // Drop our references to the child's ends, so the child's exit is seen as EOF.
+    ios.clear();
+    if (res.status.status_value() != STATUS_CMD_OK) {
+        return res.status.status_value();
+    }
+
+    // $NAME[1] writes to the coprocess, $NAME[2] reads from it.
+    std::vector<wcstring> fds{to_string(to_child->write.fd()), to_string(from_child->read.fd())};
+    track_fd(to_child->write.fd(), format_string(L"coproc %ls input", varname));
+    track_fd(from_child->read.fd(), format_string(L"coproc %ls output", varname));
+    parser.own_fd_in_scope(std::move(to_child->write));
+    parser.own_fd_in_scope(std::move(from_child->read));
+    parser.set_var_and_fire(varname, ENV_LOCAL | ENV_USER, std::move(fds));
+    return STATUS_CMD_OK;
+}
@@ -0,0 +1,11 @@
+// Prototypes for executing builtin_coproc function.
+#ifndef FISH_BUILTIN_COPROC_H
+#define FISH_BUILTIN_COPROC_H
+
+#include "../maybe.h"
+
+class parser_t;
+struct io_streams_t;
+
+maybe_t<int> builtin_coproc(parser_t &parser, io_streams_t &streams, const wchar_t **argv);
+#endif
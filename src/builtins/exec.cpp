@@ -0,0 +1,199 @@
+// Implementation of the exec builtin.
+//
+// Note that `exec COMMAND` is handled by the parser as a decoration; this builtin is only reached
+// when exec is followed by an option, like `exec --open`.
+#include "config.h"  // IWYU pragma: keep
+
+#include "exec.h"
+
+#include <fcntl.h>
+#include <unistd.h>
+
+#include <cerrno>
+#include <cstring>
+
+#include "../builtin.h"
+#include "../common.h"
+#include "../env.h"
+#include "../fallback.h"  // IWYU pragma: keep
+#include "../fds.h"
+#include "../fds.rs.h"
+#include "../io.h"
+#include "../maybe.h"
+#include "../parser.h"
+#include "../path.h"
+#include "../wgetopt.h"
+#include "../wutil.h"  // IWYU pragma: keep
+
+namespace {
+struct exec_cmd_opts_t {
+    bool print_help = false;
+    bool open = false;
+    bool close = false;
+    bool append = false;
+    bool read = false;
+    bool inherit = false;
+};
+}  // namespace
+
+static const wchar_t *const short_options = L":achior";
+static const struct woption long_options[] = {
+    {L"append", no_argument, 'a'}, {L"close", no_argument, 'c'}, {L"help", no_argument, 'h'},
+    {L"inherit", no_argument, 'i'}, {L"open", no_argument, 'o'}, {L"read", no_argument, 'r'},
+    {}};
+
+static int parse_cmd_opts(exec_cmd_opts_t &opts, int *optind, int argc, const wchar_t **argv,
+                          parser_t &parser, io_streams_t &streams) {
+    const wchar_t *cmd = argv[0];
+    int opt;
+    wgetopter_t w;
+    while ((opt = w.wgetopt_long(argc, argv, short_options, long_options, nullptr)) != -1) {
+        switch (opt) {
+            case 'a': {
+                opts.append = true;
+                break;
+            }
+            case 'c': {
+                opts.close = true;
+                break;
+            }
+            case 'h': {
+                opts.print_help = true;
+                break;
+            }
+            case 'i': {
+                opts.inherit = true;
+                break;
+            }
+            case 'o': {
+                opts.open = true;
+                break;
+            }
+            case 'r': {
+                opts.read = true;
+                break;
+            }
+            case ':': {
+                builtin_missing_argument(parser, streams, cmd, argv[w.woptind - 1]);
+                return STATUS_INVALID_ARGS;
+            }
+            case '?': {
+                builtin_unknown_option(parser, streams, cmd, argv[w.woptind - 1]);
+                return STATUS_INVALID_ARGS;
+            }
+            default: {
+                DIE("unexpected retval from wgetopt_long");
+            }
+        }
+    }
+
+    *optind = w.woptind;
+    return STATUS_CMD_OK;
+}
+
+/// Open \p path and store the resulting fd in the local variable \p varname.
+static int exec_open(const wchar_t *cmd, const exec_cmd_opts_t &opts, const wcstring &varname,
+                     const wcstring &path, parser_t &parser, io_streams_t &streams) {
+    if (!valid_var_name(varname)) {
+        streams.err.append_format(BUILTIN_ERR_VARNAME, cmd, varname.c_str());
+        return STATUS_INVALID_ARGS;
+    }
+
+    int flags;
+    if (opts.read) {
+        flags = O_RDONLY;
+    } else if (opts.append) {
+        flags = O_WRONLY | O_CREAT | O_APPEND;
+    } else {
+        flags = O_WRONLY | O_CREAT | O_TRUNC;
+    }
+
+    wcstring abspath = path_apply_working_directory(path, parser.vars().get_pwd_slash());
+    autoclose_fd_t fd{wopen_cloexec(abspath, flags, 0666)};
+    if (!fd.valid()) {
+        streams.err.append_format(_(L"%ls: %ls: %s\n"), cmd, path.c_str(), std::strerror(errno));
+        return STATUS_CMD_ERROR;
+    }
+
+    // Move the fd out of the range that may be used for explicit redirections like 3>&1, so the
+    // two cannot collide.
+    if (fd.fd() < k_first_high_fd) {
+        autoclose_fd_t high{fcntl(fd.fd(), F_DUPFD_CLOEXEC, k_first_high_fd)};
+        if (!high.valid()) {
+            streams.err.append_format(_(L"%ls: %ls: %s\n"), cmd, path.c_str(),
+                                      std::strerror(errno));
+            return STATUS_CMD_ERROR;
+        }
+        fd = std::move(high);
+    }
+
+    // Commands only get the fd through a redirection, unless it is passed to all of them on purpose.
+    int fdnum = fd.fd();
+    wcstring purpose = format_string(L"exec --open %ls", varname.c_str());
+    if (opts.inherit) {
+        pass_fd(fdnum, purpose);
+    } else {
+        track_fd(fdnum, purpose);
+    }
+    parser.own_fd_in_scope(std::move(fd));
+    parser.set_var_and_fire(varname, ENV_LOCAL | ENV_USER, to_string(fdnum));
+    return STATUS_CMD_OK;
+}
+
+/// The exec builtin, for the options that do not replace the shell.
+maybe_t<int> builtin_exec(parser_t &parser, io_streams_t &streams, const wchar_t **argv) {
+    const wchar_t *cmd = argv[0];
+    int argc = builtin_count_args(argv);
+    exec_cmd_opts_t opts;
+
+    int optind;
+    int retval = parse_cmd_opts(opts, &optind, argc, argv, parser, streams);
+    if (retval != STATUS_CMD_OK) return retval;
+
+    if (opts.print_help) {
+        builtin_print_help(parser, streams, cmd);
+        return STATUS_CMD_OK;
+    }
+
+    if (opts.open && opts.close) {
+        streams.err.append_format(BUILTIN_ERR_COMBO2_EXCLUSIVE, cmd, L"--open", L"--close");
+        return STATUS_INVALID_ARGS;
+    }
+    if (!opts.open && (opts.append || opts.read || opts.inherit)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO2, cmd,
+                                  _(L"--append, --read and --inherit require --open"));
+        return STATUS_INVALID_ARGS;
+    }
+    if (opts.append && opts.read) {
+        streams.err.append_format(BUILTIN_ERR_COMBO2_EXCLUSIVE, cmd, L"--append", L"--read");
+        return STATUS_INVALID_ARGS;
+    }
+
+    if (opts.open) {
+        if (argc - optind != 2) {
+            streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, L"--open", 2, argc - optind);
+            return STATUS_INVALID_ARGS;
+        }
+        return exec_open(cmd, opts, argv[optind], argv[optind + 1], parser, streams);
+    }
+
+    if (opts.close) {
+        retval = STATUS_CMD_OK;
+        for (int i = optind; i < argc; i++) {
+            int fd = fish_wcstoi(argv[i]);
+            if (errno || fd < 0) {
+                streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd, argv[i]);
+                retval = STATUS_INVALID_ARGS;
+            } else if (!parser.close_owned_fd(fd)) {
+                streams.err.append_format(_(L"%ls: %d: fd was not opened with exec --open\n"),
+                                          cmd, fd);
+                retval = STATUS_CMD_ERROR;
+            }
+        }
+        return retval;
+    }
+
+    // Any other options are only valid with a command, which the parser handles.
+    builtin_print_help(parser, streams, cmd);
+    return STATUS_INVALID_ARGS;
+}
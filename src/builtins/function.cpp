@@ -36,6 +36,7 @@ namespace {
 struct function_cmd_opts_t {
     bool print_help = false;
     bool shadow_scope = true;
+    function_job_scope_t job_scope = function_job_scope_t::none;
     wcstring description;
     std::vector<event_description_t> events;
     std::vector<wcstring> named_arguments;
@@ -58,6 +59,7 @@ static const struct woption long_options[] = {{L"description", required_argument
                                               {L"argument-names", required_argument, 'a'},
                                               {L"no-scope-shadowing", no_argument, 'S'},
                                               {L"inherit-variable", required_argument, 'V'},
+                                              {L"job-scope", required_argument, 2},
                                               {}};
 
 /// \return the internal_job_id for a pid, or 0 if none.
@@ -169,6 +171,16 @@ static int parse_cmd_opts(function_cmd_opts_t &opts, int *optind,  //!OCLINT(hig
                 opts.shadow_scope = false;
                 break;
             }
+            case 2: {
+                auto scope = function_job_scope_from_string(w.woptarg);
+                if (!scope) {
+                    streams.err.append_format(_(L"%ls: %ls: invalid job scope\n"), cmd,
+                                              w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                opts.job_scope = *scope;
+                break;
+            }
             case 'w': {
                 opts.wrap_targets.push_back(w.woptarg);
                 break;
@@ -279,6 +291,7 @@ int builtin_function(parser_t &parser, io_streams_t &streams, const std::vector<
     // We have what we need to actually define the function.
     auto props = std::make_shared<function_properties_t>();
     props->shadow_scope = opts.shadow_scope;
+    props->job_scope = opts.job_scope;
     props->named_arguments = std::move(opts.named_arguments);
     props->parsed_source = source.clone();
     props->func_node = &func_node;
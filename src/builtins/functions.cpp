@@ -18,6 +18,7 @@
 #include "../function.h"
 #include "../highlight.h"
 #include "../io.h"
+#include "../json.rs.h"
 #include "../maybe.h"
 #include "../parser.h"
 #include "../parser_keywords.h"
@@ -36,6 +37,7 @@ struct functions_cmd_opts_t {
     bool no_metadata = false;
     bool verbose = false;
     bool handlers = false;
+    bool json = false;
     const wchar_t *handlers_type = nullptr;
     const wchar_t *description = nullptr;
 };
@@ -52,6 +54,7 @@ static const struct woption long_options[] = {{L"erase", no_argument, 'e'},
                                               {L"verbose", no_argument, 'v'},
                                               {L"handlers", no_argument, 'H'},
                                               {L"handlers-type", required_argument, 't'},
+                                              {L"json", no_argument, 2},
                                               {}};
 
 static int parse_cmd_opts(functions_cmd_opts_t &opts, int *optind,  //!OCLINT(high ncss method)
@@ -77,6 +80,10 @@ static int parse_cmd_opts(functions_cmd_opts_t &opts, int *optind,  //!OCLINT(hi
                 opts.no_metadata = true;
                 break;
             }
+            case 2: {
+                opts.json = true;
+                break;
+            }
             case 'd': {
                 opts.description = w.woptarg;
                 break;
@@ -208,6 +215,46 @@ static int report_function_metadata(const wcstring &funcname, bool verbose, io_s
     return STATUS_CMD_OK;
 }
 
+/// Write a JSON object describing the function \p funcname with properties \p props.
+static void function_json(const wcstring &funcname, const function_properties_t &props,
+                          JsonWriter &w) {
+    w.begin_object();
+    w.key(L"name");
+    w.string(funcname);
+    w.key(L"description");
+    w.string(props.description);
+    // The file is null if the function was defined interactively, and "-" if via `source`.
+    w.key(L"path");
+    if (props.definition_file) {
+        w.string(*props.definition_file);
+    } else {
+        w.null();
+    }
+    w.key(L"line");
+    w.integer(props.definition_lineno());
+    w.key(L"autoloaded");
+    w.boolean(props.is_autoload);
+    w.key(L"shadow_scope");
+    w.boolean(props.shadow_scope);
+    w.key(L"arguments");
+    w.begin_array();
+    for (const wcstring &arg : props.named_arguments) {
+        w.string(arg);
+    }
+    w.end_array();
+    w.key(L"copied_from");
+    if (!props.is_copy) {
+        w.null();
+    } else if (props.copy_definition_file) {
+        w.string(*props.copy_definition_file);
+    } else {
+        w.string(L"stdin");
+    }
+    w.key(L"definition");
+    w.string(props.annotated_definition(funcname));
+    w.end_object();
+}
+
 /// \return whether a type filter is valid.
 static bool type_filter_valid(const wcstring &filter) {
     if (filter.empty()) return true;
@@ -240,6 +287,13 @@ maybe_t<int> builtin_functions(parser_t &parser, io_streams_t &streams, const wc
         return STATUS_INVALID_ARGS;
     }
 
+    // JSON output is only for listing and showing functions.
+    if (opts.json && (describe || opts.erase || opts.query || opts.copy || opts.handlers)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
     if (opts.report_metadata && opts.no_metadata) {
         streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
         builtin_print_error_trailer(parser, streams.err, cmd);
@@ -269,7 +323,8 @@ maybe_t<int> builtin_functions(parser_t &parser, io_streams_t &streams, const wc
         return STATUS_CMD_OK;
     }
 
-    if (opts.report_metadata) {
+    // With --json, the details are part of each function's object.
+    if (opts.report_metadata && !opts.json) {
         if (argc - optind != 1) {
             streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, argv[optind - 1], 1,
                                       argc - optind);
@@ -301,7 +356,16 @@ maybe_t<int> builtin_functions(parser_t &parser, io_streams_t &streams, const wc
         std::vector<wcstring> names = function_get_names(opts.show_hidden);
         std::sort(names.begin(), names.end());
         bool is_screen = !streams.out_is_redirected && isatty(STDOUT_FILENO);
-        if (is_screen) {
+        if (opts.json) {
+            auto w = new_json_writer();
+            w->begin_array();
+            for (const auto &name : names) {
+                w->string(name);
+            }
+            w->end_array();
+            streams.out.append(*w->contents());
+            streams.out.push(L'\n');
+        } else if (is_screen) {
             wcstring buff;
             for (const auto &name : names) {
                 buff.append(name);
@@ -364,11 +428,15 @@ maybe_t<int> builtin_functions(parser_t &parser, io_streams_t &streams, const wc
     }
 
     int res = STATUS_CMD_OK;
+    auto w = new_json_writer();
+    w->begin_array();
     for (int i = optind; i < argc; i++) {
         wcstring funcname = argv[i];
         auto func = function_get_props_autoload(argv[i], parser);
         if (!func) {
             res++;
+        } else if (opts.json) {
+            function_json(funcname, *func, *w);
         } else {
             if (!opts.query) {
                 if (i != optind) streams.out.append(L"\n");
@@ -388,5 +456,10 @@ maybe_t<int> builtin_functions(parser_t &parser, io_streams_t &streams, const wc
         }
     }
 
+    if (opts.json) {
+        w->end_array();
+        streams.out.append(*w->contents());
+        streams.out.push(L'\n');
+    }
     return res;
 }
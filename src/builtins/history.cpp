@@ -3,9 +3,14 @@
 
 #include "history.h"
 
+#include <fcntl.h>
+#include <unistd.h>
+
 #include <cerrno>
 #include <cstddef>
 #include <cstdint>
+#include <cstdio>
+#include <ctime>
 #include <memory>
 #include <string>
 
@@ -14,10 +19,13 @@
 #include "../enum_map.h"
 #include "../env.h"
 #include "../fallback.h"  // IWYU pragma: keep
+#include "../fds.h"
 #include "../history.h"
 #include "../io.h"
 #include "../maybe.h"
 #include "../parser.h"
+#include "../path.h"
+#include "../re.h"
 #include "../reader.h"
 #include "../wgetopt.h"
 #include "../wutil.h"  // IWYU pragma: keep
@@ -29,27 +37,92 @@ enum hist_cmd_t {
     HIST_MERGE,
     HIST_SAVE,
     HIST_UNDEF,
-    HIST_CLEAR_SESSION
+    HIST_CLEAR_SESSION,
+    HIST_EXPORT,
+    HIST_IMPORT
 };
 
 // Must be sorted by string, not enum or random.
 static const enum_map<hist_cmd_t> hist_enum_map[] = {
     {HIST_CLEAR, L"clear"},   {HIST_CLEAR_SESSION, L"clear-session"},
-    {HIST_DELETE, L"delete"}, {HIST_MERGE, L"merge"},
+    {HIST_DELETE, L"delete"}, {HIST_EXPORT, L"export"},
+    {HIST_IMPORT, L"import"}, {HIST_MERGE, L"merge"},
     {HIST_SAVE, L"save"},     {HIST_SEARCH, L"search"},
     {HIST_UNDEF, nullptr},
 };
 
+/// \return the history format with the given name, for `--format`.
+static maybe_t<history_format_t> parse_history_format(const wcstring &name) {
+    if (name == L"json") return history_format_t::json;
+    if (name == L"bash") return history_format_t::bash;
+    if (name == L"zsh") return history_format_t::zsh;
+    return none();
+}
+
+/// \return the time for `--before` and `--after`: seconds since the epoch after an "@", a date
+/// like "2023-04-01" with an optional time like "13:30" or "13:30:15" in local time, or a duration
+/// like "2d" for that long ago, in (s)econds, (m)inutes, (h)ours, (d)ays or (w)eeks.
+static maybe_t<time_t> parse_timespec(const wcstring &spec) {
+    if (spec.empty()) return none();
+    if (spec.front() == L'@') {
+        long long seconds = fish_wcstoll(spec.c_str() + 1);
+        if (errno) return none();
+        return static_cast<time_t>(seconds);
+    }
+
+    const wchar_t *end;
+    long long amount = fish_wcstoll(spec.c_str(), &end);
+    if (errno == -1 && amount >= 0 && end[0] && !end[1]) {
+        const wchar_t *units = L"smhdw";
+        const long long unit_seconds[] = {1, 60, 60 * 60, 24 * 60 * 60, 7 * 24 * 60 * 60};
+        if (const wchar_t *unit = std::wcschr(units, end[0])) {
+            return time(nullptr) - static_cast<time_t>(amount * unit_seconds[unit - units]);
+        }
+    }
+
+    std::string narrow = wcs2string(spec);
+    const char *cursor = narrow.c_str();
+    int year, month, day, hour = 0, minute = 0, second = 0, used = 0;
+    if (std::sscanf(cursor, "%d-%d-%d%n", &year, &month, &day, &used) != 3) return none();
+    cursor += used;
+    if (*cursor) {
+        if (std::sscanf(cursor, " %d:%d%n", &hour, &minute, &used) != 2) return none();
+        cursor += used;
+        if (*cursor == ':') {
+            if (std::sscanf(cursor, ":%d%n", &second, &used) != 1) return none();
+            cursor += used;
+        }
+    }
+    if (*cursor) return none();
+
+    struct tm tm {};
+    tm.tm_year = year - 1900;
+    tm.tm_mon = month - 1;
+    tm.tm_mday = day;
+    tm.tm_hour = hour;
+    tm.tm_min = minute;
+    tm.tm_sec = second;
+    tm.tm_isdst = -1;
+    time_t result = mktime(&tm);
+    if (result == -1) return none();
+    return result;
+}
+
 struct history_cmd_opts_t {
     hist_cmd_t hist_cmd = HIST_UNDEF;
     history_search_type_t search_type = static_cast<history_search_type_t>(-1);
     const wchar_t *show_time_format = nullptr;
+    bool show_status = false;
     size_t max_items = SIZE_MAX;
     bool print_help = false;
     bool history_search_type_defined = false;
     bool case_sensitive = false;
     bool null_terminate = false;
     bool reverse = false;
+    bool json = false;
+    const wchar_t *format = nullptr;
+    const wchar_t *merge_from = nullptr;
+    history_filter_t filter;
 };
 
 /// Note: Do not add new flags that represent subcommands. We're encouraging people to switch to
@@ -71,6 +144,15 @@ static const struct woption long_options[] = {{L"prefix", no_argument, 'p'},
                                               {L"clear", no_argument, 4},
                                               {L"merge", no_argument, 5},
                                               {L"reverse", no_argument, 'R'},
+                                              {L"json", no_argument, 6},
+                                              {L"format", required_argument, 7},
+                                              {L"show-status", no_argument, 8},
+                                              {L"regex", no_argument, 9},
+                                              {L"before", required_argument, 10},
+                                              {L"after", required_argument, 11},
+                                              {L"cwd", required_argument, 12},
+                                              {L"status", required_argument, 13},
+                                              {L"from", required_argument, 14},
                                               {}};
 
 /// Remember the history subcommand and disallow selecting more than one history subcommand.
@@ -90,7 +172,8 @@ static bool set_hist_cmd(const wchar_t *cmd, hist_cmd_t *hist_cmd, hist_cmd_t su
 static bool check_for_unexpected_hist_args(const history_cmd_opts_t &opts, const wchar_t *cmd,
                                            const std::vector<wcstring> &args,
                                            io_streams_t &streams) {
-    if (opts.history_search_type_defined || opts.show_time_format || opts.null_terminate) {
+    if (opts.history_search_type_defined || opts.show_time_format || opts.show_status ||
+        opts.null_terminate || opts.json || !opts.filter.empty()) {
         const wchar_t *subcmd_str = enum_to_str(opts.hist_cmd, hist_enum_map);
         streams.err.append_format(_(L"%ls: %ls: subcommand takes no options\n"), cmd, subcmd_str);
         return true;
@@ -140,6 +223,55 @@ static int parse_cmd_opts(history_cmd_opts_t &opts, int *optind,  //!OCLINT(high
                 }
                 break;
             }
+            case 6: {
+                opts.json = true;
+                break;
+            }
+            case 7: {
+                opts.format = w.woptarg;
+                break;
+            }
+            case 8: {
+                opts.show_status = true;
+                break;
+            }
+            case 9: {
+                opts.search_type = history_search_type_t::regex;
+                opts.history_search_type_defined = true;
+                break;
+            }
+            case 10:
+            case 11: {
+                maybe_t<time_t> when = parse_timespec(w.woptarg);
+                if (!when.has_value()) {
+                    streams.err.append_format(_(L"%ls: invalid time '%ls'\n"), cmd, w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                if (opt == 10) {
+                    opts.filter.before = when;
+                } else {
+                    opts.filter.after = when;
+                }
+                break;
+            }
+            case 12: {
+                wcstring pwd = parser.vars().get_pwd_slash();
+                opts.filter.cwd = normalize_path(path_apply_working_directory(w.woptarg, pwd));
+                break;
+            }
+            case 13: {
+                int code = fish_wcstoi(w.woptarg);
+                if (errno || code < 0) {
+                    streams.err.append_format(BUILTIN_ERR_NOT_NUMBER, cmd, w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                opts.filter.exit_status = code;
+                break;
+            }
+            case 14: {
+                opts.merge_from = w.woptarg;
+                break;
+            }
             case 'C': {
                 opts.case_sensitive = true;
                 break;
@@ -253,34 +385,71 @@ maybe_t<int> builtin_history(parser_t &parser, io_streams_t &streams, const wcha
         if (opts.hist_cmd == HIST_DELETE) opts.search_type = history_search_type_t::exact;
     }
 
+    // JSON output is only for searching, and has its own format.
+    if (opts.json && (opts.hist_cmd == HIST_DELETE || opts.show_time_format || opts.show_status ||
+                      opts.null_terminate)) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    // Formats are only for exporting and importing.
+    history_format_t format = history_format_t::json;
+    if (opts.format) {
+        if (opts.hist_cmd != HIST_EXPORT && opts.hist_cmd != HIST_IMPORT) {
+            streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+            builtin_print_error_trailer(parser, streams.err, cmd);
+            return STATUS_INVALID_ARGS;
+        }
+        maybe_t<history_format_t> parsed = parse_history_format(opts.format);
+        if (!parsed) {
+            streams.err.append_format(_(L"%ls: invalid history format '%ls'\n"), cmd,
+                                      opts.format);
+            return STATUS_INVALID_ARGS;
+        }
+        format = *parsed;
+    }
+
+    // Only merging takes another session.
+    if (opts.merge_from && opts.hist_cmd != HIST_MERGE) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
+    // Report invalid regexes, instead of finding nothing.
+    if (opts.search_type == history_search_type_t::regex &&
+        (opts.hist_cmd == HIST_SEARCH || opts.hist_cmd == HIST_DELETE)) {
+        for (const wcstring &pattern : args) {
+            re::re_error_t error{};
+            if (!re::regex_t::try_compile(pattern, re::flags_t{}, &error)) {
+                streams.err.append_format(_(L"%ls: Regular expression compile error: %ls\n"), cmd,
+                                          error.message().c_str());
+                streams.err.append_format(L"%ls: %ls\n", cmd, pattern.c_str());
+                streams.err.append_format(L"%ls: %*ls\n", cmd, static_cast<int>(error.offset),
+                                          L"^");
+                return STATUS_INVALID_ARGS;
+            }
+        }
+    }
+
     int status = STATUS_CMD_OK;
     switch (opts.hist_cmd) {
         case HIST_SEARCH: {
-            if (!history->search(opts.search_type, args, opts.show_time_format, opts.max_items,
-                                 opts.case_sensitive, opts.null_terminate, opts.reverse,
+            if (!history->search(opts.search_type, args, opts.filter, opts.show_time_format,
+                                 opts.show_status, opts.max_items, opts.case_sensitive,
+                                 opts.null_terminate, opts.reverse, opts.json,
                                  parser.cancel_checker(), streams)) {
                 status = STATUS_CMD_ERROR;
             }
             break;
         }
         case HIST_DELETE: {
-            // TODO: Move this code to the history module and support the other search types
-            // including case-insensitive matches. At this time we expect the non-exact deletions to
-            // be handled only by the history function's interactive delete feature.
-            if (opts.search_type != history_search_type_t::exact) {
-                streams.err.append_format(_(L"builtin history delete only supports --exact\n"));
-                status = STATUS_INVALID_ARGS;
-                break;
-            }
-            if (!opts.case_sensitive) {
-                streams.err.append_format(
-                    _(L"builtin history delete --exact requires --case-sensitive\n"));
-                status = STATUS_INVALID_ARGS;
-                break;
-            }
-            for (const wcstring &delete_string : args) {
-                history->remove(delete_string);
-            }
+            // Without search strings, the filters select what to delete. Without either, we delete
+            // nothing rather than everything.
+            if (args.empty() && opts.filter.empty()) break;
+            history->remove_matching(opts.search_type, args, opts.filter, opts.case_sensitive,
+                                     parser.cancel_checker());
             break;
         }
         case HIST_CLEAR: {
@@ -312,6 +481,27 @@ maybe_t<int> builtin_history(parser_t &parser, io_streams_t &streams, const wcha
                 status = STATUS_INVALID_ARGS;
                 break;
             }
+            if (opts.merge_from) {
+                if (!valid_var_name(opts.merge_from)) {
+                    streams.err.append_format(_(L"%ls: invalid history session name '%ls'\n"),
+                                              cmd, opts.merge_from);
+                    status = STATUS_INVALID_ARGS;
+                    break;
+                }
+                std::shared_ptr<history_t> other = history_t::with_name(opts.merge_from);
+                if (other == history) {
+                    streams.err.append_format(
+                        _(L"%ls: can't merge history session '%ls' into itself\n"), cmd,
+                        opts.merge_from);
+                    status = STATUS_INVALID_ARGS;
+                    break;
+                }
+                if (!history->merge_from(*other).has_value()) {
+                    streams.err.append_format(_(L"%ls: could not write the history file\n"), cmd);
+                    status = STATUS_CMD_ERROR;
+                }
+                break;
+            }
             history->incorporate_external_changes();
             break;
         }
@@ -323,6 +513,73 @@ maybe_t<int> builtin_history(parser_t &parser, io_streams_t &streams, const wcha
             history->save();
             break;
         }
+        case HIST_EXPORT: {
+            if (check_for_unexpected_hist_args(opts, cmd, args, streams)) {
+                status = STATUS_INVALID_ARGS;
+                break;
+            }
+            history->export_items(format, parser.cancel_checker(), streams);
+            break;
+        }
+        case HIST_IMPORT: {
+            if (opts.history_search_type_defined || opts.show_time_format || opts.show_status ||
+                opts.null_terminate || opts.json || !opts.filter.empty()) {
+                streams.err.append_format(_(L"%ls: %ls: subcommand takes no options\n"), cmd,
+                                          enum_to_str(opts.hist_cmd, hist_enum_map));
+                status = STATUS_INVALID_ARGS;
+                break;
+            }
+            if (args.size() > 1) {
+                streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, L"import", 1, args.size());
+                status = STATUS_INVALID_ARGS;
+                break;
+            }
+            if (in_private_mode(parser.vars())) {
+                streams.err.append_format(_(L"%ls: can't import history in private mode\n"), cmd);
+                status = STATUS_INVALID_ARGS;
+                break;
+            }
+
+            // Read the file, or stdin if there is none.
+            autoclose_fd_t opened_fd;
+            int fd = streams.stdin_fd;
+            if (!args.empty()) {
+                opened_fd = autoclose_fd_t(wopen_cloexec(args.front(), O_RDONLY));
+                if (!opened_fd.valid()) {
+                    streams.err.append_format(_(L"%ls: could not open '%ls'\n"), cmd,
+                                              args.front().c_str());
+                    builtin_wperror(cmd, streams);
+                    status = STATUS_CMD_ERROR;
+                    break;
+                }
+                fd = opened_fd.fd();
+            } else if (fd < 0 || isatty(fd)) {
+                // Don't read from the terminal.
+                streams.err.append_format(_(L"%ls: import: expected a file or redirected stdin\n"),
+                                          cmd);
+                status = STATUS_INVALID_ARGS;
+                break;
+            }
+
+            std::string contents;
+            char buff[4096];
+            ssize_t amt;
+            while ((amt = read_loop(fd, buff, sizeof buff)) > 0) {
+                contents.append(buff, amt);
+            }
+            if (amt < 0) {
+                builtin_wperror(cmd, streams);
+                status = STATUS_CMD_ERROR;
+                break;
+            }
+
+            wcstring err;
+            if (!history->import_items(format, contents, &err).has_value()) {
+                streams.err.append_format(L"%ls: import: %ls\n", cmd, err.c_str());
+                status = STATUS_CMD_ERROR;
+            }
+            break;
+        }
         case HIST_UNDEF: {
             DIE("Unexpected HIST_UNDEF seen");
         }
@@ -2,6 +2,7 @@
 #include "config.h"  // IWYU pragma: keep
 
 #include <cerrno>
+#include <chrono>
 #include <deque>
 #include <memory>
 
@@ -9,6 +10,7 @@
 #include "../common.h"
 #include "../fallback.h"  // IWYU pragma: keep
 #include "../io.h"
+#include "../json.rs.h"
 #include "../maybe.h"
 #include "../parser.h"
 #include "../proc.h"
@@ -22,6 +24,7 @@ enum {
     JOBS_PRINT_COMMAND,  // print command name of each process in job
     JOBS_PRINT_GROUP,    // print group id of job
     JOBS_PRINT_NOTHING,  // print nothing (exit status only)
+    JOBS_PRINT_JSON,     // print a JSON object per job, including resource usage
 };
 
 /// Calculates the cpu usage (as a fraction of 1) of the specified job.
@@ -39,6 +42,39 @@ static double cpu_use(const job_t *j) {
     return u;
 }
 
+/// Write a JSON object describing process \p p, including the resources it used so far.
+static void process_json(const process_t &p, JsonWriter &w) {
+    proc_usage_t usage = p.usage;
+    if (!p.completed && p.launch_time) {
+        auto elapsed = std::chrono::steady_clock::now() - *p.launch_time;
+        usage.wall_usec = std::chrono::duration_cast<std::chrono::microseconds>(elapsed).count();
+    }
+    w.begin_object();
+    w.key(L"pid");
+    w.integer(p.pid);
+    w.key(L"command");
+    w.string(p.argv0() ? p.argv0() : L"");
+    w.key(L"completed");
+    w.boolean(p.completed);
+    if (p.completed && !p.status.is_empty()) {
+        w.key(L"status");
+        w.integer(p.status.status_value());
+    }
+    w.key(L"wall_ms");
+    w.decimal(usage.wall_usec / 1000.0, 3);
+    w.key(L"user_ms");
+    w.decimal(usage.user_usec / 1000.0, 3);
+    w.key(L"sys_ms");
+    w.decimal(usage.sys_usec / 1000.0, 3);
+    w.key(L"max_rss_kb");
+    w.integer(usage.max_rss_kb);
+    w.key(L"in_blocks");
+    w.integer(usage.in_blocks);
+    w.key(L"out_blocks");
+    w.integer(usage.out_blocks);
+    w.end_object();
+}
+
 /// Print information about the specified job.
 static void builtin_jobs_print(const job_t *j, int mode, int header, io_streams_t &streams) {
     int pgid = INVALID_PID;
@@ -77,6 +113,29 @@ static void builtin_jobs_print(const job_t *j, int mode, int header, io_streams_
             streams.out.append(out);
             break;
         }
+        case JOBS_PRINT_JSON: {
+            // One object per line, so consumers can process jobs as they come.
+            auto w = new_json_writer();
+            w->begin_object();
+            w->key(L"id");
+            w->integer(j->job_id());
+            w->key(L"pgid");
+            w->integer(pgid);
+            w->key(L"state");
+            w->string(j->is_stopped() ? L"stopped" : L"running");
+            w->key(L"command");
+            w->string(j->command());
+            w->key(L"processes");
+            w->begin_array();
+            for (const process_ptr_t &p : j->processes) {
+                process_json(*p, *w);
+            }
+            w->end_array();
+            w->end_object();
+            streams.out.append(*w->contents());
+            streams.out.push(L'\n');
+            break;
+        }
         case JOBS_PRINT_GROUP: {
             if (header) {
                 // Print table header before first job.
@@ -127,9 +186,10 @@ maybe_t<int> builtin_jobs(parser_t &parser, io_streams_t &streams, const wchar_t
     static const wchar_t *const short_options = L":cghlpq";
     static const struct woption long_options[] = {
         {L"command", no_argument, 'c'}, {L"group", no_argument, 'g'},
-        {L"help", no_argument, 'h'},    {L"last", no_argument, 'l'},
-        {L"pid", no_argument, 'p'},     {L"quiet", no_argument, 'q'},
-        {L"query", no_argument, 'q'},   {}};
+        {L"help", no_argument, 'h'},    {L"json", no_argument, 1},
+        {L"last", no_argument, 'l'},    {L"pid", no_argument, 'p'},
+        {L"quiet", no_argument, 'q'},   {L"query", no_argument, 'q'},
+        {}};
 
     int opt;
     wgetopter_t w;
@@ -155,6 +215,10 @@ maybe_t<int> builtin_jobs(parser_t &parser, io_streams_t &streams, const wchar_t
                 print_last = true;
                 break;
             }
+            case 1: {
+                mode = JOBS_PRINT_JSON;
+                break;
+            }
             case 'h': {
                 builtin_print_help(parser, streams, cmd);
                 return STATUS_CMD_OK;
@@ -232,7 +296,7 @@ maybe_t<int> builtin_jobs(parser_t &parser, io_streams_t &streams, const wchar_t
 
     if (!found) {
         // Do not babble if not interactive.
-        if (!streams.out_is_redirected && mode != JOBS_PRINT_NOTHING) {
+        if (!streams.out_is_redirected && mode != JOBS_PRINT_NOTHING && mode != JOBS_PRINT_JSON) {
             streams.out.append_format(_(L"%ls: There are no jobs\n"), argv[0]);
         }
         return STATUS_CMD_ERROR;
@@ -21,6 +21,7 @@
 #include "../fallback.h"  // IWYU pragma: keep
 #include "../history.h"
 #include "../io.h"
+#include "../json.rs.h"
 #include "../maybe.h"
 #include "../parser.h"
 #include "../wgetopt.h"
@@ -44,12 +45,14 @@ struct set_cmd_opts_t {
     bool append = false;
     bool prepend = false;
     bool preserve_failure_exit_status = true;
+    bool json = false;
 };
 
 /// Values used for long-only options.
 enum {
     opt_path = 1,
     opt_unpath = 2,
+    opt_json = 3,
 };
 
 // Variables used for parsing the argument list. This command is atypical in using the "+"
@@ -71,6 +74,7 @@ static const struct woption long_options[] = {{L"export", no_argument, 'x'},
                                               {L"prepend", no_argument, 'p'},
                                               {L"path", no_argument, opt_path},
                                               {L"unpath", no_argument, opt_unpath},
+                                              {L"json", no_argument, opt_json},
                                               {L"help", no_argument, 'h'},
                                               {}};
 
@@ -143,6 +147,10 @@ static int parse_cmd_opts(set_cmd_opts_t &opts, int *optind,  //!OCLINT(high ncs
                 opts.unpathvar = true;
                 break;
             }
+            case opt_json: {
+                opts.json = true;
+                break;
+            }
             case 'U': {
                 opts.universal = true;
                 break;
@@ -243,6 +251,13 @@ static int validate_cmd_opts(const wchar_t *cmd, const set_cmd_opts_t &opts, int
         return STATUS_INVALID_ARGS;
     }
 
+    // JSON output is only for listing and showing variables.
+    if (opts.json && (opts.query || opts.erase || (argc > 0 && !opts.list && !opts.show))) {
+        streams.err.append_format(BUILTIN_ERR_COMBO, cmd);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
     if (argc == 0 && opts.erase) {
         streams.err.append_format(BUILTIN_ERR_MISSING, cmd, argv[-1]);
         builtin_print_error_trailer(parser, streams.err, cmd);
@@ -428,6 +443,76 @@ static env_mode_flags_t compute_scope(const set_cmd_opts_t &opts) {
     return scope;
 }
 
+/// \return the name of \p scope, which is one of ENV_LOCAL, ENV_GLOBAL and ENV_UNIVERSAL.
+static const wchar_t *show_scope_name(int scope) {
+    switch (scope) {
+        case ENV_LOCAL:
+            return L"local";
+        case ENV_GLOBAL:
+            return L"global";
+        case ENV_UNIVERSAL:
+            return L"universal";
+        default:
+            DIE("invalid scope");
+    }
+}
+
+/// Write the properties and values of \p var to the JSON object being written.
+static void var_json(const env_var_t &var, JsonWriter &w) {
+    w.key(L"exported");
+    w.boolean(var.exports());
+    w.key(L"path");
+    w.boolean(var.is_pathvar());
+    w.key(L"values");
+    w.begin_array();
+    for (const wcstring &val : var.as_list()) {
+        w.string(val);
+    }
+    w.end_array();
+}
+
+/// List the variables \p names as JSON: an array of their names if --names was given, otherwise
+/// an array of objects with the scope and values of each.
+static void builtin_set_list_json(const std::vector<wcstring> &names, const set_cmd_opts_t &opts,
+                                  const environment_t &vars, io_streams_t &streams) {
+    env_mode_flags_t mode = compute_scope(opts);
+    auto w = new_json_writer();
+    w->begin_array();
+    for (const auto &key : names) {
+        if (opts.list) {
+            w->string(key);
+            continue;
+        }
+        auto var = vars.get(key, mode);
+        if (!var) continue;
+        w->begin_object();
+        w->key(L"name");
+        w->string(key);
+        // Report the innermost of the requested scopes which has the variable.
+        w->key(L"scope");
+        int requested = mode & (ENV_LOCAL | ENV_FUNCTION | ENV_GLOBAL | ENV_UNIVERSAL);
+        if (requested & ENV_FUNCTION) requested |= ENV_LOCAL;
+        const wchar_t *scope_name = nullptr;
+        for (int scope : {ENV_LOCAL, ENV_GLOBAL, ENV_UNIVERSAL}) {
+            if (requested && !(requested & scope)) continue;
+            if (vars.get(key, scope)) {
+                scope_name = show_scope_name(scope);
+                break;
+            }
+        }
+        if (scope_name) {
+            w->string(scope_name);
+        } else {
+            w->null();
+        }
+        var_json(*var, *w);
+        w->end_object();
+    }
+    w->end_array();
+    streams.out.append(*w->contents());
+    streams.out.push(L'\n');
+}
+
 /// Print the names of all environment variables in the scope. It will include the values unless the
 /// `set --names` flag was used.
 static int builtin_set_list(const wchar_t *cmd, set_cmd_opts_t &opts, int argc,
@@ -441,6 +526,11 @@ static int builtin_set_list(const wchar_t *cmd, set_cmd_opts_t &opts, int argc,
     std::vector<wcstring> names = parser.vars().get_names(compute_scope(opts));
     sort(names.begin(), names.end());
 
+    if (opts.json) {
+        builtin_set_list_json(names, opts, parser.vars(), streams);
+        return STATUS_CMD_OK;
+    }
+
     for (const auto &key : names) {
         wcstring out;
         out.append(key);
@@ -514,25 +604,7 @@ static int builtin_set_query(const wchar_t *cmd, set_cmd_opts_t &opts, int argc,
 
 static void show_scope(const wchar_t *var_name, int scope, io_streams_t &streams,
                        const environment_t &vars) {
-    const wchar_t *scope_name;
-    switch (scope) {
-        case ENV_LOCAL: {
-            scope_name = L"local";
-            break;
-        }
-        case ENV_GLOBAL: {
-            scope_name = L"global";
-            break;
-        }
-        case ENV_UNIVERSAL: {
-            scope_name = L"universal";
-            break;
-        }
-        default: {
-            DIE("invalid scope");
-        }
-    }
-
+    const wchar_t *scope_name = show_scope_name(scope);
     const auto var = vars.get(var_name, scope);
     if (!var) {
         return;
@@ -565,17 +637,52 @@ static void show_scope(const wchar_t *var_name, int scope, io_streams_t &streams
     }
 }
 
+/// Write a JSON object describing \p var_name in each scope which has it.
+static void show_json(const wcstring &var_name, const environment_t &vars,
+                      const std::map<wcstring, wcstring> &inheriteds, JsonWriter &w) {
+    w.begin_object();
+    w.key(L"name");
+    w.string(var_name);
+    w.key(L"read_only");
+    w.boolean(env_var_t::flags_for(var_name.c_str()) & env_var_t::flag_read_only);
+    w.key(L"scopes");
+    w.begin_array();
+    for (int scope : {ENV_LOCAL, ENV_GLOBAL, ENV_UNIVERSAL}) {
+        if (auto var = vars.get(var_name, scope)) {
+            w.begin_object();
+            w.key(L"scope");
+            w.string(show_scope_name(scope));
+            var_json(*var, w);
+            w.end_object();
+        }
+    }
+    w.end_array();
+    w.key(L"inherited");
+    auto inherited = inheriteds.find(var_name);
+    if (inherited != inheriteds.end()) {
+        w.string(inherited->second);
+    } else {
+        w.null();
+    }
+    w.end_object();
+}
+
 /// Show mode. Show information about the named variable(s).
 static int builtin_set_show(const wchar_t *cmd, const set_cmd_opts_t &opts, int argc,
                             const wchar_t **argv, parser_t &parser, io_streams_t &streams) {
-    UNUSED(opts);
     const auto &vars = parser.vars();
     auto inheriteds = env_get_inherited();
+    auto w = new_json_writer();
+    w->begin_array();
     if (argc == 0) {  // show all vars
         std::vector<wcstring> names = vars.get_names(ENV_USER);
         sort(names.begin(), names.end());
         for (const auto &name : names) {
             if (name == L"history") continue;
+            if (opts.json) {
+                show_json(name, vars, inheriteds, *w);
+                continue;
+            }
             show_scope(name.c_str(), ENV_LOCAL, streams, vars);
             show_scope(name.c_str(), ENV_GLOBAL, streams, vars);
             show_scope(name.c_str(), ENV_UNIVERSAL, streams, vars);
@@ -606,6 +713,11 @@ static int builtin_set_show(const wchar_t *cmd, const set_cmd_opts_t &opts, int
                 return STATUS_CMD_ERROR;
             }
 
+            if (opts.json) {
+                show_json(arg, vars, inheriteds, *w);
+                continue;
+            }
+
             show_scope(arg, ENV_LOCAL, streams, vars);
             show_scope(arg, ENV_GLOBAL, streams, vars);
             show_scope(arg, ENV_UNIVERSAL, streams, vars);
@@ -619,6 +731,11 @@ static int builtin_set_show(const wchar_t *cmd, const set_cmd_opts_t &opts, int
         }
     }
 
+    if (opts.json) {
+        w->end_array();
+        streams.out.append(*w->contents());
+        streams.out.push(L'\n');
+    }
     return STATUS_CMD_OK;
 }
 
@@ -7,19 +7,6 @@
 
 #include <cstdlib>
 
-#if HAVE_CURSES_H
-#include <curses.h>  // IWYU pragma: keep
-#elif HAVE_NCURSES_H
-#include <ncurses.h>
-#elif HAVE_NCURSES_CURSES_H
-#include <ncurses/curses.h>
-#endif
-#if HAVE_TERM_H
-#include <term.h>
-#elif HAVE_NCURSES_TERM_H
-#include <ncurses/term.h>
-#endif
-
 #include <string>
 #include <vector>
 
@@ -27,6 +14,7 @@
 #include "../color.h"
 #include "../common.h"
 #include "../env.h"
+#include "../env_dispatch.rs.h"
 #include "../fallback.h"  // IWYU pragma: keep
 #include "../io.h"
 #include "../maybe.h"
@@ -36,15 +24,23 @@
 
 class parser_t;
 
-static void print_modifiers(outputter_t &outp, bool bold, bool underline, bool italics, bool dim,
-                            bool reverse, rgb_color_t bg) {
+static void print_modifiers(outputter_t &outp, bool bold, underline_style_t underline,
+                            bool italics, bool dim, bool reverse, bool strikethrough, bool blink,
+                            rgb_color_t bg) {
+    const maybe_t<std::string> enter_bold_mode = term_get_string("bold");
+    const maybe_t<std::string> enter_italics_mode = term_get_string("sitm");
+    const maybe_t<std::string> enter_dim_mode = term_get_string("dim");
+    const maybe_t<std::string> enter_reverse_mode = term_get_string("rev");
+    const maybe_t<std::string> enter_standout_mode = term_get_string("smso");
+    const maybe_t<std::string> enter_blink_mode = term_get_string("blink");
+    const maybe_t<std::string> exit_attribute_mode = term_get_string("sgr0");
+
     if (bold && enter_bold_mode) {
-        // These casts are needed to work with different curses implementations.
-        writembs_nofail(outp, fish_tparm(const_cast<char *>(enter_bold_mode)));
+        writembs_nofail(outp, term_expand(*enter_bold_mode));
     }
 
-    if (underline && enter_underline_mode) {
-        writembs_nofail(outp, enter_underline_mode);
+    if (underline != underline_style_t::none) {
+        outp.write_underline_style(underline);
     }
 
     if (italics && enter_italics_mode) {
@@ -60,18 +56,29 @@ static void print_modifiers(outputter_t &outp, bool bold, bool underline, bool i
     } else if (reverse && enter_standout_mode) {
         writembs_nofail(outp, enter_standout_mode);
     }
-    if (!bg.is_none() && bg.is_normal()) {
-        writembs_nofail(outp, fish_tparm(const_cast<char *>(exit_attribute_mode)));
+
+    if (strikethrough) {
+        outp.write_strikethrough(true);
+    }
+
+    if (blink && enter_blink_mode) {
+        writembs_nofail(outp, enter_blink_mode);
+    }
+
+    if (!bg.is_none() && bg.is_normal() && exit_attribute_mode) {
+        writembs_nofail(outp, term_expand(*exit_attribute_mode));
     }
 }
 
 static void print_colors(io_streams_t &streams, std::vector<wcstring> args, bool bold,
-                         bool underline, bool italics, bool dim, bool reverse, rgb_color_t bg) {
+                         underline_style_t underline, bool italics, bool dim, bool reverse,
+                         bool strikethrough, bool blink, rgb_color_t bg) {
     outputter_t outp;
+    const maybe_t<std::string> exit_attribute_mode = term_get_string("sgr0");
     if (args.empty()) args = rgb_color_t::named_color_names();
     for (const auto &color_name : args) {
         if (!streams.out_is_redirected && isatty(STDOUT_FILENO)) {
-            print_modifiers(outp, bold, underline, italics, dim, reverse, bg);
+            print_modifiers(outp, bold, underline, italics, dim, reverse, strikethrough, blink, bg);
             rgb_color_t color = rgb_color_t(color_name);
             outp.set_color(color, rgb_color_t::none());
             if (!bg.is_none()) {
@@ -79,10 +86,10 @@ static void print_colors(io_streams_t &streams, std::vector<wcstring> args, bool
             }
         }
         outp.writestr(color_name);
-        if (!bg.is_none()) {
+        if (!bg.is_none() && exit_attribute_mode) {
             // If we have a background, stop it after the color
             // or it goes to the end of the line and looks ugly.
-            writembs_nofail(outp, fish_tparm(const_cast<char *>(exit_attribute_mode)));
+            writembs_nofail(outp, term_expand(*exit_attribute_mode));
         }
         outp.writech(L'\n');
     }  // conveniently, 'normal' is always the last color so we don't need to reset here
@@ -90,21 +97,24 @@ static void print_colors(io_streams_t &streams, std::vector<wcstring> args, bool
     streams.out.append(str2wcstring(outp.contents()));
 }
 
-static const wchar_t *const short_options = L":b:hoidrcu";
+static const wchar_t *const short_options = L":b:hoidrscu";
 static const struct woption long_options[] = {{L"background", required_argument, 'b'},
                                               {L"help", no_argument, 'h'},
                                               {L"bold", no_argument, 'o'},
-                                              {L"underline", no_argument, 'u'},
+                                              {L"underline", optional_argument, 'u'},
+                                              {L"underline-color", required_argument, 1},
                                               {L"italics", no_argument, 'i'},
                                               {L"dim", no_argument, 'd'},
                                               {L"reverse", no_argument, 'r'},
+                                              {L"strikethrough", no_argument, 's'},
+                                              {L"blink", no_argument, 2},
                                               {L"print-colors", no_argument, 'c'},
                                               {}};
 
 /// set_color builtin.
 maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wchar_t **argv) {
-    // By the time this is called we should have initialized the curses subsystem.
-    assert(CURSES_INITIALIZED);
+    // Non-interactive shells set up curses when it is first needed.
+    env_ensure_curses_initialized();
 
     // Variables used for parsing the argument list.
     int argc = builtin_count_args(argv);
@@ -116,8 +126,10 @@ maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wc
     }
 
     const wchar_t *bgcolor = nullptr;
-    bool bold = false, underline = false, italics = false, dim = false, reverse = false,
-         print = false;
+    const wchar_t *underline_color_name = nullptr;
+    underline_style_t underline = underline_style_t::none;
+    bool bold = false, italics = false, dim = false, reverse = false, strikethrough = false,
+         blink = false, print = false;
 
     // Parse options to obtain the requested operation and the modifiers.
     int opt;
@@ -148,8 +160,28 @@ maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wc
                 reverse = true;
                 break;
             }
+            case 's': {
+                strikethrough = true;
+                break;
+            }
+            case 2: {
+                blink = true;
+                break;
+            }
             case 'u': {
-                underline = true;
+                if (!w.woptarg) {
+                    underline = underline_style_t::single;
+                } else if (auto style = underline_style_from_name(w.woptarg)) {
+                    underline = *style;
+                } else {
+                    streams.err.append_format(_(L"%ls: Unknown underline style '%ls'\n"), argv[0],
+                                              w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                break;
+            }
+            case 1: {
+                underline_color_name = w.woptarg;
                 break;
             }
             case 'c': {
@@ -177,6 +209,13 @@ maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wc
         return STATUS_INVALID_ARGS;
     }
 
+    rgb_color_t underline_color = rgb_color_t(underline_color_name ? underline_color_name : L"");
+    if (underline_color_name && underline_color.is_none()) {
+        streams.err.append_format(_(L"%ls: Unknown color '%ls'\n"), argv[0],
+                                  underline_color_name);
+        return STATUS_INVALID_ARGS;
+    }
+
     if (print) {
         // Hack: Explicitly setting a background of "normal" crashes
         // for --print-colors. Because it's not interesting in terms of display,
@@ -185,7 +224,8 @@ maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wc
             bg = rgb_color_t(L"");
         }
         std::vector<wcstring> args(argv + w.woptind, argv + argc);
-        print_colors(streams, args, bold, underline, italics, dim, reverse, bg);
+        print_colors(streams, args, bold, underline, italics, dim, reverse, strikethrough, blink,
+                     bg);
         return STATUS_CMD_OK;
     }
 
@@ -207,20 +247,21 @@ maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wc
 
     // Test if we have at least basic support for setting fonts, colors and related bits - otherwise
     // just give up...
-    if (cur_term == nullptr || !exit_attribute_mode) {
+    const maybe_t<std::string> exit_attribute_mode = term_get_string("sgr0");
+    if (!exit_attribute_mode) {
         return STATUS_CMD_ERROR;
     }
     outputter_t outp;
 
-    print_modifiers(outp, bold, underline, italics, dim, reverse, bg);
+    print_modifiers(outp, bold, underline, italics, dim, reverse, strikethrough, blink, bg);
 
     if (bgcolor != nullptr && bg.is_normal()) {
-        writembs_nofail(outp, fish_tparm(const_cast<char *>(exit_attribute_mode)));
+        writembs_nofail(outp, term_expand(*exit_attribute_mode));
     }
 
     if (!fg.is_none()) {
         if (fg.is_normal() || fg.is_reset()) {
-            writembs_nofail(outp, fish_tparm(const_cast<char *>(exit_attribute_mode)));
+            writembs_nofail(outp, term_expand(*exit_attribute_mode));
         } else {
             if (!outp.write_color(fg, true /* is_fg */)) {
                 // We need to do *something* or the lack of any output messes up
@@ -235,6 +276,10 @@ maybe_t<int> builtin_set_color(parser_t &parser, io_streams_t &streams, const wc
         outp.write_color(bg, false /* not is_fg */);
     }
 
+    if (underline_color_name != nullptr && !underline_color.is_reset()) {
+        outp.write_underline_color(underline_color);
+    }
+
     // Output the collected string.
     streams.out.append(str2wcstring(outp.contents()));
 
@@ -3,6 +3,7 @@
 
 #include "status.h"
 
+#include <fcntl.h>
 #include <unistd.h>
 
 #include <algorithm>
@@ -12,15 +13,23 @@
 #include <limits>
 #include <memory>
 #include <string>
+#include <utility>
 
 #include "../builtin.h"
+#include "../autoload.h"
 #include "../common.h"
+#include "../complete.h"
 #include "../enum_map.h"
 #include "../fallback.h"  // IWYU pragma: keep
+#include "../fds.rs.h"
+#include "../function.h"
 #include "../io.h"
+#include "../json.rs.h"
 #include "../maybe.h"
 #include "../parser.h"
+#include "../path.h"
 #include "../proc.h"
+#include "../threads.rs.h"
 #include "../wgetopt.h"
 #include "../wutil.h"  // IWYU pragma: keep
 #include "future_feature_flags.h"
@@ -29,6 +38,7 @@ enum status_cmd_t {
     STATUS_CURRENT_CMD = 1,
     STATUS_BASENAME,
     STATUS_DIRNAME,
+    STATUS_FDS,
     STATUS_FEATURES,
     STATUS_FILENAME,
     STATUS_FISH_PATH,
@@ -46,11 +56,18 @@ enum status_cmd_t {
     STATUS_STACK_TRACE,
     STATUS_TEST_FEATURE,
     STATUS_CURRENT_COMMANDLINE,
+    STATUS_LAST_PIPELINE,
+    STATUS_LAST_JOB,
+    STATUS_STATS,
+    STATUS_MULTIPLEXER,
+    STATUS_PROFILE,
+    STATUS_AUTOLOAD,
     STATUS_UNDEF
 };
 
 // Must be sorted by string, not enum or random.
 const enum_map<status_cmd_t> status_enum_map[] = {
+    {STATUS_AUTOLOAD, L"autoload"},
     {STATUS_BASENAME, L"basename"},
     {STATUS_BASENAME, L"current-basename"},
     {STATUS_CURRENT_CMD, L"current-command"},
@@ -60,6 +77,7 @@ const enum_map<status_cmd_t> status_enum_map[] = {
     {STATUS_FUNCTION, L"current-function"},
     {STATUS_LINE_NUMBER, L"current-line-number"},
     {STATUS_DIRNAME, L"dirname"},
+    {STATUS_FDS, L"fds"},
     {STATUS_FEATURES, L"features"},
     {STATUS_FILENAME, L"filename"},
     {STATUS_FISH_PATH, L"fish-path"},
@@ -73,9 +91,14 @@ const enum_map<status_cmd_t> status_enum_map[] = {
     {STATUS_IS_LOGIN, L"is-login"},
     {STATUS_IS_NO_JOB_CTRL, L"is-no-job-control"},
     {STATUS_SET_JOB_CONTROL, L"job-control"},
+    {STATUS_LAST_JOB, L"last-job"},
+    {STATUS_LAST_PIPELINE, L"last-pipeline"},
     {STATUS_LINE_NUMBER, L"line-number"},
+    {STATUS_MULTIPLEXER, L"multiplexer"},
     {STATUS_STACK_TRACE, L"print-stack-trace"},
+    {STATUS_PROFILE, L"profile"},
     {STATUS_STACK_TRACE, L"stack-trace"},
+    {STATUS_STATS, L"stats"},
     {STATUS_TEST_FEATURE, L"test-feature"},
     {STATUS_UNDEF, nullptr}};
 #define status_enum_map_len (sizeof status_enum_map / sizeof *status_enum_map)
@@ -89,6 +112,9 @@ const enum_map<status_cmd_t> status_enum_map[] = {
         break;                                                                              \
     }
 
+/// The long-only options which aren't subcommands. These come after the subcommands.
+enum { STATUS_OPT_JSON = STATUS_UNDEF + 1, STATUS_OPT_FORMAT };
+
 /// Values that may be returned from the test-feature option to status.
 enum { TEST_FEATURE_ON, TEST_FEATURE_OFF, TEST_FEATURE_NOT_RECOGNIZED };
 
@@ -111,6 +137,9 @@ struct status_cmd_opts_t {
     maybe_t<job_control_t> new_job_control_mode{};
     status_cmd_t status_cmd{STATUS_UNDEF};
     bool print_help{false};
+    bool json{false};
+    /// The format for `status profile stop`.
+    maybe_t<profile_format_t> profile_format{};
 };
 }  // namespace
 
@@ -125,6 +154,7 @@ static const struct woption long_options[] = {
     {L"current-line-number", no_argument, 'n'},
     {L"filename", no_argument, 'f'},
     {L"fish-path", no_argument, STATUS_FISH_PATH},
+    {L"format", required_argument, STATUS_OPT_FORMAT},
     {L"is-block", no_argument, 'b'},
     {L"is-command-substitution", no_argument, 'c'},
     {L"is-full-job-control", no_argument, STATUS_IS_FULL_JOB_CTRL},
@@ -133,6 +163,7 @@ static const struct woption long_options[] = {
     {L"is-login", no_argument, 'l'},
     {L"is-no-job-control", no_argument, STATUS_IS_NO_JOB_CTRL},
     {L"job-control", required_argument, 'j'},
+    {L"json", no_argument, STATUS_OPT_JSON},
     {L"level", required_argument, 'L'},
     {L"line", no_argument, 'n'},
     {L"line-number", no_argument, 'n'},
@@ -153,7 +184,111 @@ static bool set_status_cmd(const wchar_t *cmd, status_cmd_opts_t &opts, status_c
     return true;
 }
 
+static const wchar_t *job_control_mode_name(job_control_t mode) {
+    switch (mode) {
+        case job_control_t::all:
+            return L"full";
+        case job_control_t::interactive:
+            return L"interactive";
+        case job_control_t::none:
+            return L"none";
+    }
+    DIE("unexpected job control mode");
+}
+
+/// Print whether this is a login shell and the job control mode, as a JSON object.
+static void print_summary_json(io_streams_t &streams) {
+    auto w = new_json_writer();
+    w->begin_object();
+    w->key(L"login");
+    w->boolean(get_login());
+    w->key(L"job_control");
+    w->string(job_control_mode_name(get_job_control_mode()));
+    w->end_object();
+    streams.out.append(*w->contents());
+    streams.out.push(L'\n');
+}
+
+/// Print the features and their values, as a JSON array.
+static void print_features_json(io_streams_t &streams) {
+    auto w = new_json_writer();
+    w->begin_array();
+    for (const auto &md : feature_metadata()) {
+        w->begin_object();
+        w->key(L"name");
+        w->string(*md.name);
+        w->key(L"enabled");
+        w->boolean(feature_test(md.flag));
+        w->key(L"groups");
+        w->string(*md.groups);
+        w->key(L"description");
+        w->string(*md.description);
+        w->end_object();
+    }
+    w->end_array();
+    streams.out.append(*w->contents());
+    streams.out.push(L'\n');
+}
+
 /// Print the features and their values.
+/// Print the files in \p info, from the autoloader for \p var. If \p overridden, the command is
+/// defined some other way, so none of them is used. \return whether there were any files.
+static bool print_autoload_files(const autoload_info_t &info, const wchar_t *var, bool overridden,
+                                 io_streams_t &streams) {
+    if (info.candidates.empty()) {
+        streams.out.append_format(_(L"  no file in $%ls\n"), var);
+    }
+    for (size_t i = 0; i < info.candidates.size(); i++) {
+        const wchar_t *path = info.candidates[i].c_str();
+        if (i > 0) {
+            streams.out.append_format(_(L"  %ls (shadowed by the first file)\n"), path);
+        } else if (overridden) {
+            streams.out.append_format(_(L"  %ls (first in $%ls, but unused)\n"), path, var);
+        } else {
+            streams.out.append_format(_(L"  %ls (wins, it is first in $%ls)\n"), path, var);
+        }
+    }
+    // The autoloader only looks at the files again every so often, or when told to.
+    const maybe_t<wcstring> &loaded = info.loaded_path;
+    if (!overridden && loaded && (info.candidates.empty() || *loaded != info.candidates[0])) {
+        streams.out.append_format(_(L"  still loaded from %ls until it is loaded again\n"),
+                                  loaded->c_str());
+    }
+    return !info.candidates.empty();
+}
+
+/// Print every file that could provide the function \p name and its completions, and which one
+/// wins. \return false if there is no function and no file at all.
+static bool print_autoload(const wcstring &name, io_streams_t &streams) {
+    bool found = false;
+
+    streams.out.append_format(_(L"function %ls:\n"), name.c_str());
+    bool overridden = false;
+    if (auto props = function_get_props(name)) {
+        found = true;
+        if (!props->is_autoload) {
+            overridden = true;
+            wcstring file = function_get_definition_file(*props);
+            if (file.empty()) {
+                streams.out.append(_(L"  defined interactively, which takes precedence\n"));
+            } else {
+                streams.out.append_format(_(L"  defined in %ls, which takes precedence\n"),
+                                          file.c_str());
+            }
+        }
+    } else if (function_is_autoload_tombstoned(name)) {
+        overridden = true;
+        streams.out.append(_(L"  erased with `functions --erase`, so it won't be autoloaded\n"));
+    }
+    found |= print_autoload_files(function_get_autoload_info(name), L"fish_function_path",
+                                  overridden, streams);
+
+    streams.out.append_format(_(L"completions for %ls:\n"), name.c_str());
+    found |= print_autoload_files(complete_get_autoload_info(name), L"fish_complete_path", false,
+                                  streams);
+    return found;
+}
+
 static void print_features(io_streams_t &streams) {
     auto max_len = std::numeric_limits<int>::min();
     for (const auto &md : feature_metadata())
@@ -165,6 +300,109 @@ static void print_features(io_streams_t &streams) {
     }
 }
 
+/// How many fds to look at for `status fds`.
+static constexpr int k_max_listed_fd = 65536;
+
+/// Print the fds fish has open, whether child processes inherit them, and what they are for, if
+/// known.
+static void print_fds(io_streams_t &streams, bool json) {
+    static const wchar_t *const stdio_names[] = {L"stdin", L"stdout", L"stderr"};
+    auto w = new_json_writer();
+    w->begin_array();
+    long max_fd = std::min<long>(sysconf(_SC_OPEN_MAX), k_max_listed_fd);
+    for (int fd = 0; fd < max_fd; fd++) {
+        int flags = fcntl(fd, F_GETFD);
+        if (flags < 0) continue;
+        wcstring purpose = *tracked_fd_purpose(fd);
+        if (purpose.empty() && fd <= STDERR_FILENO) purpose = stdio_names[fd];
+        if (json) {
+            w->begin_object();
+            w->key(L"fd");
+            w->integer(fd);
+            w->key(L"inherited");
+            w->boolean(!(flags & FD_CLOEXEC));
+            w->key(L"purpose");
+            w->string(purpose);
+            w->end_object();
+            continue;
+        }
+        streams.out.append_format(L"%d\t%ls\t%ls\n", fd,
+                                  (flags & FD_CLOEXEC) ? L"cloexec" : L"inherited",
+                                  purpose.c_str());
+    }
+    if (json) {
+        w->end_array();
+        streams.out.append(*w->contents());
+        streams.out.push(L'\n');
+    }
+}
+
+static const std::pair<iothread_priority_t, const wchar_t *> iothread_classes[] = {
+    {iothread_priority_t::user_blocking, L"user-blocking"},
+    {iothread_priority_t::best_effort, L"best-effort"},
+};
+
+/// Print statistics about fish's background work, as a JSON object.
+static void print_stats_json(io_streams_t &streams) {
+    iothread_stats_t pool = iothread_stats(iothread_priority_t::user_blocking);
+    auto w = new_json_writer();
+    w->begin_object();
+    w->key(L"iothread-threads");
+    w->integer(static_cast<int64_t>(pool.threads));
+    w->key(L"iothread-idle");
+    w->integer(static_cast<int64_t>(pool.idle_threads));
+    for (const auto &cls : iothread_classes) {
+        iothread_stats_t stats = iothread_stats(cls.first);
+        w->key(cls.second);
+        w->begin_object();
+        w->key(L"queued");
+        w->integer(static_cast<int64_t>(stats.queued));
+        w->key(L"peak-queued");
+        w->integer(static_cast<int64_t>(stats.peak_queued));
+        w->key(L"running");
+        w->integer(static_cast<int64_t>(stats.running));
+        w->key(L"max-running");
+        w->integer(static_cast<int64_t>(stats.max_running));
+        w->key(L"completed");
+        w->integer(static_cast<int64_t>(stats.completed));
+        w->end_object();
+    }
+    w->end_object();
+    streams.out.append(*w->contents());
+    streams.out.push(L'\n');
+}
+
+/// Print statistics about fish's background work.
+static void print_stats(io_streams_t &streams) {
+    iothread_stats_t pool = iothread_stats(iothread_priority_t::user_blocking);
+    streams.out.append_format(L"iothread-threads\t%lu\n", static_cast<unsigned long>(pool.threads));
+    streams.out.append_format(L"iothread-idle\t%lu\n",
+                              static_cast<unsigned long>(pool.idle_threads));
+    for (const auto &cls : iothread_classes) {
+        iothread_stats_t stats = iothread_stats(cls.first);
+        streams.out.append_format(L"%ls-queued\t%lu\n", cls.second,
+                                  static_cast<unsigned long>(stats.queued));
+        streams.out.append_format(L"%ls-peak-queued\t%lu\n", cls.second,
+                                  static_cast<unsigned long>(stats.peak_queued));
+        streams.out.append_format(L"%ls-running\t%lu\n", cls.second,
+                                  static_cast<unsigned long>(stats.running));
+        streams.out.append_format(L"%ls-max-running\t%lu\n", cls.second,
+                                  static_cast<unsigned long>(stats.max_running));
+        streams.out.append_format(L"%ls-completed\t%llu\n", cls.second,
+                                  static_cast<unsigned long long>(stats.completed));
+    }
+}
+
+/// Write the resource usage of \p usage as members of the current JSON object.
+static void usage_json(const proc_usage_t &usage, JsonWriter &w) {
+    w.key(L"wall");
+    w.decimal(usage.wall_usec / 1000.0, 3);
+    w.key(L"user");
+    w.decimal(usage.user_usec / 1000.0, 3);
+    w.key(L"sys");
+    w.decimal(usage.sys_usec / 1000.0, 3);
+}
+
 static int parse_cmd_opts(status_cmd_opts_t &opts, int *optind,  //!OCLINT(high ncss method)
                           int argc, const wchar_t **argv, parser_t &parser, io_streams_t &streams) {
     const wchar_t *cmd = argv[0];
@@ -265,6 +503,19 @@ static int parse_cmd_opts(status_cmd_opts_t &opts, int *optind,  //!OCLINT(high
                 opts.print_help = true;
                 break;
             }
+            case STATUS_OPT_JSON: {
+                opts.json = true;
+                break;
+            }
+            case STATUS_OPT_FORMAT: {
+                opts.profile_format = profile_format_from_string(w.woptarg);
+                if (!opts.profile_format) {
+                    streams.err.append_format(_(L"%ls: Invalid profile format '%ls'\n"), cmd,
+                                              w.woptarg);
+                    return STATUS_INVALID_ARGS;
+                }
+                break;
+            }
             case ':': {
                 builtin_missing_argument(parser, streams, cmd, argv[w.woptind - 1]);
                 return STATUS_INVALID_ARGS;
@@ -316,9 +567,40 @@ maybe_t<int> builtin_status(parser_t &parser, io_streams_t &streams, const wchar
     // Every argument that we haven't consumed already is an argument for a subcommand.
     const std::vector<wcstring> args(argv + optind, argv + argc);
 
+    if (opts.json) {
+        switch (opts.status_cmd) {
+            case STATUS_UNDEF:
+            case STATUS_FDS:
+            case STATUS_FEATURES:
+            case STATUS_STATS:
+            case STATUS_LAST_PIPELINE:
+            case STATUS_LAST_JOB:
+            case STATUS_PROFILE:
+                break;
+            default: {
+                streams.err.append_format(_(L"%ls: %ls: --json is not supported\n"), cmd,
+                                          enum_to_str(opts.status_cmd, status_enum_map));
+                builtin_print_error_trailer(parser, streams.err, cmd);
+                return STATUS_INVALID_ARGS;
+            }
+        }
+    }
+
+    if (opts.profile_format && opts.status_cmd != STATUS_PROFILE) {
+        const wchar_t *subcmd_str = enum_to_str(opts.status_cmd, status_enum_map);
+        if (!subcmd_str) subcmd_str = L"default";
+        streams.err.append_format(_(L"%ls: %ls: --format is not supported\n"), cmd, subcmd_str);
+        builtin_print_error_trailer(parser, streams.err, cmd);
+        return STATUS_INVALID_ARGS;
+    }
+
     switch (opts.status_cmd) {
         case STATUS_UNDEF: {
             CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd)
+            if (opts.json) {
+                print_summary_json(streams);
+                break;
+            }
             if (get_login()) {
                 streams.out.append_format(_(L"This is a login shell\n"));
             } else {
@@ -355,10 +637,102 @@ maybe_t<int> builtin_status(parser_t &parser, io_streams_t &streams, const wchar
             set_job_control_mode(*opts.new_job_control_mode);
             break;
         }
+        case STATUS_FDS: {
+            CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd)
+            print_fds(streams, opts.json);
+            break;
+        }
         case STATUS_FEATURES: {
+            if (opts.json) {
+                print_features_json(streams);
+                break;
+            }
             print_features(streams);
             break;
         }
+        case STATUS_STATS: {
+            CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd)
+            if (opts.json) {
+                print_stats_json(streams);
+                break;
+            }
+            print_stats(streams);
+            break;
+        }
+        case STATUS_MULTIPLEXER: {
+            CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd)
+            // Multiplexers don't pass on sequences they don't know, so scripts need to know.
+            const auto &vars = parser.vars();
+            const wchar_t *multiplexer = nullptr;
+            if (vars.get(L"TMUX")) {
+                multiplexer = L"tmux";
+            } else if (vars.get(L"STY")) {
+                multiplexer = L"screen";
+            } else if (vars.get(L"ZELLIJ")) {
+                multiplexer = L"zellij";
+            }
+            if (!multiplexer) {
+                retval = STATUS_CMD_ERROR;
+                break;
+            }
+            streams.out.append_format(L"%ls\n", multiplexer);
+            break;
+        }
+        case STATUS_PROFILE: {
+            const wchar_t *subcmd_str = enum_to_str(opts.status_cmd, status_enum_map);
+            if (args.empty()) {
+                streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, subcmd_str, 1, 0);
+                return STATUS_INVALID_ARGS;
+            }
+            if (args[0] == L"start") {
+                if (args.size() != 1) {
+                    streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, subcmd_str, 1,
+                                              args.size());
+                    return STATUS_INVALID_ARGS;
+                }
+                if (!parser.start_profile_session()) {
+                    streams.err.append_format(_(L"%ls: %ls: Already profiling\n"), cmd,
+                                              subcmd_str);
+                    retval = STATUS_CMD_ERROR;
+                }
+                break;
+            }
+            if (args[0] != L"stop") {
+                streams.err.append_format(_(L"%ls: %ls: Invalid action '%ls'\n"), cmd, subcmd_str,
+                                          args[0].c_str());
+                return STATUS_INVALID_ARGS;
+            }
+            if (args.size() > 2) {
+                streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, subcmd_str, 2,
+                                          args.size());
+                return STATUS_INVALID_ARGS;
+            }
+            profile_format_t format = opts.profile_format.value_or(
+                opts.json ? profile_format_t::json : profile_format_t::text);
+            maybe_t<wcstring> profile = parser.stop_profile_session(format);
+            if (!profile) {
+                streams.err.append_format(_(L"%ls: %ls: Not profiling\n"), cmd, subcmd_str);
+                retval = STATUS_CMD_ERROR;
+            } else if (args.size() == 2) {
+                wcstring path =
+                    path_apply_working_directory(args[1], parser.vars().get_pwd_slash());
+                write_profile(*profile, wcs2zstring(path).c_str());
+            } else {
+                streams.out.append(*profile);
+            }
+            break;
+        }
+        case STATUS_AUTOLOAD: {
+            if (args.empty()) {
+                const wchar_t *subcmd_str = enum_to_str(opts.status_cmd, status_enum_map);
+                streams.err.append_format(BUILTIN_ERR_ARG_COUNT2, cmd, subcmd_str, 1, 0);
+                return STATUS_INVALID_ARGS;
+            }
+            for (const wcstring &name : args) {
+                if (!print_autoload(name, streams)) retval = STATUS_CMD_ERROR;
+            }
+            break;
+        }
         case STATUS_TEST_FEATURE: {
             if (args.size() != 1) {
                 const wchar_t *subcmd_str = enum_to_str(opts.status_cmd, status_enum_map);
@@ -468,6 +842,63 @@ maybe_t<int> builtin_status(parser_t &parser, io_streams_t &streams, const wchar
             streams.out.push(L'\n');
             break;
         }
+        case STATUS_LAST_PIPELINE: {
+            CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd)
+            // One line per process: its status, then wall, user and system time in milliseconds.
+            statuses_t last = parser.get_last_statuses();
+            auto w = new_json_writer();
+            w->begin_array();
+            for (size_t i = 0; i < last.pipestatus.size(); i++) {
+                proc_usage_t usage{};
+                if (i < last.pipestatus_usage.size()) usage = last.pipestatus_usage.at(i);
+                if (opts.json) {
+                    w->begin_object();
+                    w->key(L"status");
+                    w->integer(last.pipestatus.at(i));
+                    usage_json(usage, *w);
+                    w->end_object();
+                    continue;
+                }
+                streams.out.append_format(L"%d\t%.3f\t%.3f\t%.3f\n", last.pipestatus.at(i),
+                                          usage.wall_usec / 1000.0, usage.user_usec / 1000.0,
+                                          usage.sys_usec / 1000.0);
+            }
+            if (opts.json) {
+                w->end_array();
+                streams.out.append(*w->contents());
+                streams.out.push(L'\n');
+            }
+            break;
+        }
+        case STATUS_LAST_JOB: {
+            CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd)
+            proc_usage_t total = parser.get_last_statuses().total_usage();
+            if (opts.json) {
+                auto w = new_json_writer();
+                w->begin_object();
+                usage_json(total, *w);
+                w->key(L"max-rss");
+                w->integer(total.max_rss_kb);
+                w->key(L"in-blocks");
+                w->integer(total.in_blocks);
+                w->key(L"out-blocks");
+                w->integer(total.out_blocks);
+                w->end_object();
+                streams.out.append(*w->contents());
+                streams.out.push(L'\n');
+                break;
+            }
+            streams.out.append_format(L"wall\t%.3f\n", total.wall_usec / 1000.0);
+            streams.out.append_format(L"user\t%.3f\n", total.user_usec / 1000.0);
+            streams.out.append_format(L"sys\t%.3f\n", total.sys_usec / 1000.0);
+            streams.out.append_format(L"max-rss\t%lld\n",
+                                      static_cast<long long>(total.max_rss_kb));
+            streams.out.append_format(L"in-blocks\t%lld\n",
+                                      static_cast<long long>(total.in_blocks));
+            streams.out.append_format(L"out-blocks\t%lld\n",
+                                      static_cast<long long>(total.out_blocks));
+            break;
+        }
         case STATUS_FISH_PATH: {
             CHECK_FOR_UNEXPECTED_STATUS_ARGS(opts.status_cmd);
             auto path = str2wcstring(get_executable_path("fish"));
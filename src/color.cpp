@@ -11,6 +11,7 @@
 
 #include "common.h"
 #include "fallback.h"  // IWYU pragma: keep
+#include "output.rs.h"
 
 /// Compare wide strings with simple ASCII canonicalization.
 /// \return -1, 0, or 1 if s1 is less than, equal to, or greater than s2, respectively.
@@ -55,29 +56,6 @@ bool rgb_color_t::try_parse_special(const wcstring &special) {
     return this->type != type_none;
 }
 
-static unsigned long squared_difference(long p1, long p2) {
-    auto diff = static_cast<unsigned long>(labs(p1 - p2));
-    return diff * diff;
-}
-
-static uint8_t convert_color(const uint8_t rgb[3], const uint32_t *colors, size_t color_count) {
-    long r = rgb[0], g = rgb[1], b = rgb[2];
-    auto best_distance = static_cast<unsigned long>(-1);
-    auto best_index = static_cast<uint8_t>(-1);
-    for (size_t idx = 0; idx < color_count; idx++) {
-        uint32_t color = colors[idx];
-        long test_r = (color >> 16) & 0xFF, test_g = (color >> 8) & 0xFF,
-             test_b = (color >> 0) & 0xFF;
-        unsigned long distance = squared_difference(r, test_r) + squared_difference(g, test_g) +
-                                 squared_difference(b, test_b);
-        if (distance <= best_distance) {
-            best_index = idx;
-            best_distance = distance;
-        }
-    }
-    return best_index;
-}
-
 bool rgb_color_t::try_parse_rgb(const wcstring &name) {
     std::memset(&data, 0, sizeof data);
     // We support the following style of rgb formats (case insensitive):
@@ -190,7 +168,9 @@ static const wchar_t *name_for_color_idx(uint8_t idx) {
     return L"unknown";
 }
 
-rgb_color_t::rgb_color_t(uint8_t t, uint8_t i) : type(t), flags(), data() { data.name_idx = i; }
+rgb_color_t::rgb_color_t(uint8_t t, uint8_t i) : type(t), underline_(), data(), flags() {
+    data.name_idx = i;
+}
 
 rgb_color_t rgb_color_t::normal() { return rgb_color_t(type_normal); }
 
@@ -202,63 +182,10 @@ rgb_color_t rgb_color_t::white() { return rgb_color_t(type_named, 7); }
 
 rgb_color_t rgb_color_t::black() { return rgb_color_t(type_named, 0); }
 
-static uint8_t term16_color_for_rgb(const uint8_t rgb[3]) {
-    const uint32_t kColors[] = {
-        0x000000,  // Black
-        0x800000,  // Red
-        0x008000,  // Green
-        0x808000,  // Yellow
-        0x000080,  // Blue
-        0x800080,  // Magenta
-        0x008080,  // Cyan
-        0xc0c0c0,  // White
-        0x808080,  // Bright Black
-        0xFF0000,  // Bright Red
-        0x00FF00,  // Bright Green
-        0xFFFF00,  // Bright Yellow
-        0x0000FF,  // Bright Blue
-        0xFF00FF,  // Bright Magenta
-        0x00FFFF,  // Bright Cyan
-        0xFFFFFF   // Bright White
-    };
-    return convert_color(rgb, kColors, sizeof kColors / sizeof *kColors);
-}
-
-static uint8_t term256_color_for_rgb(const uint8_t rgb[3]) {
-    const uint32_t kColors[240] = {
-        0x000000, 0x00005f, 0x000087, 0x0000af, 0x0000d7, 0x0000ff, 0x005f00, 0x005f5f, 0x005f87,
-        0x005faf, 0x005fd7, 0x005fff, 0x008700, 0x00875f, 0x008787, 0x0087af, 0x0087d7, 0x0087ff,
-        0x00af00, 0x00af5f, 0x00af87, 0x00afaf, 0x00afd7, 0x00afff, 0x00d700, 0x00d75f, 0x00d787,
-        0x00d7af, 0x00d7d7, 0x00d7ff, 0x00ff00, 0x00ff5f, 0x00ff87, 0x00ffaf, 0x00ffd7, 0x00ffff,
-        0x5f0000, 0x5f005f, 0x5f0087, 0x5f00af, 0x5f00d7, 0x5f00ff, 0x5f5f00, 0x5f5f5f, 0x5f5f87,
-        0x5f5faf, 0x5f5fd7, 0x5f5fff, 0x5f8700, 0x5f875f, 0x5f8787, 0x5f87af, 0x5f87d7, 0x5f87ff,
-        0x5faf00, 0x5faf5f, 0x5faf87, 0x5fafaf, 0x5fafd7, 0x5fafff, 0x5fd700, 0x5fd75f, 0x5fd787,
-        0x5fd7af, 0x5fd7d7, 0x5fd7ff, 0x5fff00, 0x5fff5f, 0x5fff87, 0x5fffaf, 0x5fffd7, 0x5fffff,
-        0x870000, 0x87005f, 0x870087, 0x8700af, 0x8700d7, 0x8700ff, 0x875f00, 0x875f5f, 0x875f87,
-        0x875faf, 0x875fd7, 0x875fff, 0x878700, 0x87875f, 0x878787, 0x8787af, 0x8787d7, 0x8787ff,
-        0x87af00, 0x87af5f, 0x87af87, 0x87afaf, 0x87afd7, 0x87afff, 0x87d700, 0x87d75f, 0x87d787,
-        0x87d7af, 0x87d7d7, 0x87d7ff, 0x87ff00, 0x87ff5f, 0x87ff87, 0x87ffaf, 0x87ffd7, 0x87ffff,
-        0xaf0000, 0xaf005f, 0xaf0087, 0xaf00af, 0xaf00d7, 0xaf00ff, 0xaf5f00, 0xaf5f5f, 0xaf5f87,
-        0xaf5faf, 0xaf5fd7, 0xaf5fff, 0xaf8700, 0xaf875f, 0xaf8787, 0xaf87af, 0xaf87d7, 0xaf87ff,
-        0xafaf00, 0xafaf5f, 0xafaf87, 0xafafaf, 0xafafd7, 0xafafff, 0xafd700, 0xafd75f, 0xafd787,
-        0xafd7af, 0xafd7d7, 0xafd7ff, 0xafff00, 0xafff5f, 0xafff87, 0xafffaf, 0xafffd7, 0xafffff,
-        0xd70000, 0xd7005f, 0xd70087, 0xd700af, 0xd700d7, 0xd700ff, 0xd75f00, 0xd75f5f, 0xd75f87,
-        0xd75faf, 0xd75fd7, 0xd75fff, 0xd78700, 0xd7875f, 0xd78787, 0xd787af, 0xd787d7, 0xd787ff,
-        0xd7af00, 0xd7af5f, 0xd7af87, 0xd7afaf, 0xd7afd7, 0xd7afff, 0xd7d700, 0xd7d75f, 0xd7d787,
-        0xd7d7af, 0xd7d7d7, 0xd7d7ff, 0xd7ff00, 0xd7ff5f, 0xd7ff87, 0xd7ffaf, 0xd7ffd7, 0xd7ffff,
-        0xff0000, 0xff005f, 0xff0087, 0xff00af, 0xff00d7, 0xff00ff, 0xff5f00, 0xff5f5f, 0xff5f87,
-        0xff5faf, 0xff5fd7, 0xff5fff, 0xff8700, 0xff875f, 0xff8787, 0xff87af, 0xff87d7, 0xff87ff,
-        0xffaf00, 0xffaf5f, 0xffaf87, 0xffafaf, 0xffafd7, 0xffafff, 0xffd700, 0xffd75f, 0xffd787,
-        0xffd7af, 0xffd7d7, 0xffd7ff, 0xffff00, 0xffff5f, 0xffff87, 0xffffaf, 0xffffd7, 0xffffff,
-        0x080808, 0x121212, 0x1c1c1c, 0x262626, 0x303030, 0x3a3a3a, 0x444444, 0x4e4e4e, 0x585858,
-        0x626262, 0x6c6c6c, 0x767676, 0x808080, 0x8a8a8a, 0x949494, 0x9e9e9e, 0xa8a8a8, 0xb2b2b2,
-        0xbcbcbc, 0xc6c6c6, 0xd0d0d0, 0xdadada, 0xe4e4e4, 0xeeeeee};
-    return 16 + convert_color(rgb, kColors, sizeof kColors / sizeof *kColors);
-}
-
 uint8_t rgb_color_t::to_term256_index() const {
     assert(type == type_rgb);
-    return term256_color_for_rgb(data.color.rgb);
+    const uint8_t *rgb = data.color.rgb;
+    return quantize_color_ffi(rgb[0], rgb[1], rgb[2], 256);
 }
 
 color24_t rgb_color_t::to_color24() const {
@@ -267,10 +194,12 @@ color24_t rgb_color_t::to_color24() const {
 }
 
 uint8_t rgb_color_t::to_name_index() const {
-    // TODO: This should look for the nearest color.
     assert(type == type_named || type == type_rgb);
     if (type == type_named) return data.name_idx;
-    if (type == type_rgb) return term16_color_for_rgb(data.color.rgb);
+    if (type == type_rgb) {
+        const uint8_t *rgb = data.color.rgb;
+        return quantize_color_ffi(rgb[0], rgb[1], rgb[2], 16);
+    }
     return static_cast<uint8_t>(-1);  // this is an error
 }
 
@@ -285,9 +214,9 @@ void rgb_color_t::parse(const wcstring &str) {
     }
 }
 
-rgb_color_t::rgb_color_t(const wcstring &str) : type(), flags() { this->parse(str); }
+rgb_color_t::rgb_color_t(const wcstring &str) : type(), underline_(), flags() { this->parse(str); }
 
-rgb_color_t::rgb_color_t(const std::string &str) : type(), flags() {
+rgb_color_t::rgb_color_t(const std::string &str) : type(), underline_(), flags() {
     this->parse(str2wcstring(str));
 }
 
@@ -13,28 +13,36 @@ struct color24_t {
     uint8_t rgb[3];
 };
 
-/// A type that represents a color. We work hard to keep it at a size of 4 bytes and verify with
+/// The style of an underline. Terminals that don't know styled underlines show all but none as
+/// a single underline. The values are those of the Smulx terminfo capability.
+enum class underline_style_t : uint8_t { none, single, double_, curly, dotted, dashed };
+
+/// A type that represents a color. We work hard to keep it at a size of 5 bytes and verify with
 /// static_assert
 class rgb_color_t {
     // Types
     enum { type_none, type_named, type_rgb, type_normal, type_reset };
     uint8_t type : 3;
 
-    // Flags
-    enum {
-        flag_bold = 1 << 0,
-        flag_underline = 1 << 1,
-        flag_italics = 1 << 2,
-        flag_dim = 1 << 3,
-        flag_reverse = 1 << 4
-    };
-    uint8_t flags : 5;
+    // The underline_style_t, which shares the byte with the type.
+    uint8_t underline_ : 3;
 
     union {
         uint8_t name_idx;  // 0-10
         color24_t color;
     } data;
 
+    // Flags
+    enum {
+        flag_bold = 1 << 0,
+        flag_italics = 1 << 1,
+        flag_dim = 1 << 2,
+        flag_reverse = 1 << 3,
+        flag_strikethrough = 1 << 4,
+        flag_blink = 1 << 5
+    };
+    uint8_t flags;
+
     /// Try parsing a special color name like "normal".
     bool try_parse_special(const wcstring &special);
 
@@ -52,7 +60,7 @@ class rgb_color_t {
 
    public:
     /// Default constructor of type none.
-    explicit rgb_color_t() : type(type_none), flags(), data() {}
+    explicit rgb_color_t() : type(type_none), underline_(), data(), flags() {}
 
     /// Parse a color from a string.
     explicit rgb_color_t(const wcstring &str);
@@ -114,15 +122,20 @@ class rgb_color_t {
             flags &= ~flag_bold;
     }
 
-    /// Returns whether the color is underlined.
-    bool is_underline() const { return static_cast<bool>(flags & flag_underline); }
+    /// Returns whether the color is underlined, in any style.
+    bool is_underline() const { return underline_style() != underline_style_t::none; }
 
-    /// Set whether the color is underlined.
+    /// Set whether the color is underlined, with a single line.
     void set_underline(bool x) {
-        if (x)
-            flags |= flag_underline;
-        else
-            flags &= ~flag_underline;
+        set_underline_style(x ? underline_style_t::single : underline_style_t::none);
+    }
+
+    /// Returns the style of the underline.
+    underline_style_t underline_style() const { return static_cast<underline_style_t>(underline_); }
+
+    /// Set the style of the underline.
+    void set_underline_style(underline_style_t style) {
+        underline_ = static_cast<uint8_t>(style);
     }
 
     /// Returns whether the color is italics.
@@ -158,6 +171,28 @@ class rgb_color_t {
             flags &= ~flag_reverse;
     }
 
+    /// Returns whether the color is struck through.
+    bool is_strikethrough() const { return static_cast<bool>(flags & flag_strikethrough); }
+
+    /// Set whether the color is struck through.
+    void set_strikethrough(bool x) {
+        if (x)
+            flags |= flag_strikethrough;
+        else
+            flags &= ~flag_strikethrough;
+    }
+
+    /// Returns whether the color is blinking.
+    bool is_blink() const { return static_cast<bool>(flags & flag_blink); }
+
+    /// Set whether the color is blinking.
+    void set_blink(bool x) {
+        if (x)
+            flags |= flag_blink;
+        else
+            flags &= ~flag_blink;
+    }
+
     /// Compare two colors for equality.
     bool operator==(const rgb_color_t &other) const {
         return type == other.type && !std::memcmp(&data, &other.data, sizeof data);
@@ -170,6 +205,6 @@ class rgb_color_t {
     static std::vector<wcstring> named_color_names(void);
 };
 
-static_assert(sizeof(rgb_color_t) <= 4, "rgb_color_t is too big");
+static_assert(sizeof(rgb_color_t) <= 5, "rgb_color_t is too big");
 
 #endif
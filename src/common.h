@@ -602,6 +602,8 @@ enum {
     STATUS_READ_TOO_MUCH = 122,
     /// The status code when an expansion fails, for example, "$foo["
     STATUS_EXPAND_ERROR = 121,
+    /// The status code when a job was killed because it exceeded $fish_timeout.
+    STATUS_TIMED_OUT = 120,
 };
 
 /* Normally casting an expression to void discards its value, but GCC
@@ -20,6 +20,7 @@
 #include <mutex>
 #include <set>
 #include <string>
+#include <tuple>
 #include <unordered_map>
 #include <unordered_set>
 #include <utility>
@@ -27,6 +28,7 @@
 #include "abbrs.h"
 #include "autoload.h"
 #include "builtin.h"
+#include "builtins/argparse.h"
 #include "common.h"
 #include "enum_set.h"
 #include "env.h"
@@ -37,6 +39,7 @@
 #include "function.h"
 #include "global_safety.h"
 #include "history.h"
+#include "json.rs.h"
 #include "maybe.h"
 #include "operation_context.h"
 #include "parse_constants.h"
@@ -66,15 +69,10 @@
 #define ABBR_DESC _(L"Abbreviation: %ls")
 
 /// The special cased translation macro for completions. The empty string needs to be special cased,
-/// since it can occur, and should not be translated. (Gettext returns the version information as
-/// the response).
-#ifdef HAVE_GETTEXT
+/// since it can occur, and should not be translated. (The catalog's header is stored under it).
 static const wcstring &C_(const wcstring &s) {
     return s.empty() ? g_empty_string : wgettext(s.c_str());
 }
-#else
-static const wcstring &C_(const wcstring &s) { return s; }
-#endif
 
 /// Struct describing a completion rule for options to a command.
 ///
@@ -96,6 +94,8 @@ struct complete_entry_opt_t {
     wcstring comp;
     /// Description of the completion.
     wcstring desc;
+    /// The group to list the completions under in the pager, if any.
+    wcstring group;
     // Conditions under which to use the option, expanded and evaluated at completion time.
     std::vector<wcstring> conditions;
     /// Type of the option: args_only, short, single_long, or double_long.
@@ -138,6 +138,12 @@ class completion_entry_t {
     /// Getters for option list.
     const option_list_t &get_options() const { return options; }
 
+    /// How many seconds to cache the arguments the options compute, or 0 for not at all.
+    unsigned cache_seconds{0};
+
+    /// The function whose argparse spec provides more options, if any.
+    wcstring argparse_function;
+
     /// Adds an option.
     void add_option(complete_entry_opt_t &&opt) { options.push_back(std::move(opt)); }
 
@@ -162,6 +168,17 @@ using completion_key_t = std::pair<wcstring, bool>;
 using completion_entry_map_t = std::map<completion_key_t, completion_entry_t>;
 static owning_lock<completion_entry_map_t> s_completion_map;
 
+/// Arguments computed for completions of commands that have a cache time, keyed by the command,
+/// the arguments string, the command line and the working directory.
+namespace {
+struct cached_args_t {
+    completion_list_t args;
+    timepoint_t expiry;
+};
+}  // namespace
+using args_cache_key_t = std::tuple<wcstring, wcstring, wcstring, wcstring>;
+static owning_lock<std::map<args_cache_key_t, cached_args_t>> s_args_cache;
+
 /// Completion "wrapper" support. The map goes from wrapping-command to wrapped-command-list.
 using wrapper_map_t = std::unordered_map<wcstring, std::vector<wcstring>>;
 static owning_lock<wrapper_map_t> wrapper_map;
@@ -303,10 +320,30 @@ void completions_sort_and_prioritize(completion_list_t *comps, completion_reques
     // Deduplicate both sorted and unsorted results.
     unique_completions_retaining_order(comps);
 
+    // Remember the order the groups came up in, to list them in.
+    std::unordered_map<wcstring, size_t> group_order;
+    for (const auto &comp : *comps) {
+        group_order.emplace(comp.group, group_order.size());
+    }
+
     // Sort, provided COMPLETE_DONT_SORT isn't set.
     // Here we do not pass suppress_exact, so that exact matches appear first.
     stable_sort(comps->begin(), comps->end(), natural_compare_completions);
 
+    // Subsequence matches go best first. These are only left if there were no better matches, so
+    // prefix matches always come before them.
+    stable_sort(comps->begin(), comps->end(), [](const completion_t &a, const completion_t &b) {
+        return a.match.subseq_score > b.match.subseq_score;
+    });
+
+    // Keep the completions of each group together, sorted within it as above.
+    if (group_order.size() > 1 && !flags.autosuggestion) {
+        stable_sort(comps->begin(), comps->end(),
+                    [&](const completion_t &a, const completion_t &b) {
+                        return group_order.at(a.group) < group_order.at(b.group);
+                    });
+    }
+
     // Lastly, if this is for an autosuggestion, prefer to avoid completions that duplicate
     // arguments, and penalize files that end in tilde - they're frequently autosave files from e.g.
     // emacs. Also prefer samecase to smartcase.
@@ -321,6 +358,12 @@ void completions_sort_and_prioritize(completion_list_t *comps, completion_reques
     }
 }
 
+/// \return whether $fish_completion_fuzzy turns on case-insensitive, scored subsequence matches.
+static bool fuzzy_subsequence_enabled(const environment_t &vars) {
+    auto var = vars.get(L"fish_completion_fuzzy");
+    return var && bool_from_string(var->as_string());
+}
+
 namespace {
 /// Class representing an attempt to compute completions.
 class completer_t {
@@ -330,12 +373,23 @@ class completer_t {
     /// Flags associated with the completion request.
     const completion_request_options_t flags;
 
+    /// Whether $fish_completion_fuzzy asks for scored subsequence matches.
+    const bool fuzzy_subsequence;
+
     /// The output completions.
     completion_receiver_t completions;
 
     /// Commands which we would have tried to load, if we had a parser.
     std::vector<wcstring> needs_load;
 
+    /// The command line that custom completions are computed for.
+    wcstring custom_cmdline;
+
+    /// The command whose options are being tested, and how many seconds the arguments they compute
+    /// may be cached, if at all.
+    wcstring cache_cmd;
+    unsigned cache_seconds{0};
+
     /// Table of completions conditions that have already been tested and the corresponding test
     /// results.
     using condition_cache_t = std::unordered_map<wcstring, bool>;
@@ -356,7 +410,9 @@ class completer_t {
     void complete_abbr(const wcstring &cmd);
 
     void complete_from_args(const wcstring &str, const wcstring &args, const wcstring &desc,
-                            complete_flags_t flags);
+                            complete_flags_t flags, const wcstring &group = wcstring());
+
+    completion_list_t expand_args_cached(const wcstring &args, expand_flags_t eflags);
 
     void complete_cmd_desc(const wcstring &str);
 
@@ -373,6 +429,7 @@ class completer_t {
         expand_flags_t result{};
         if (flags.autosuggestion) result |= expand_flag::skip_cmdsubst;
         if (flags.fuzzy_match) result |= expand_flag::fuzzy_match;
+        if (flags.fuzzy_match && fuzzy_subsequence) result |= expand_flag::fuzzy_subsequence;
         if (flags.descriptions) result |= expand_flag::gen_descriptions;
         return result;
     }
@@ -426,7 +483,10 @@ class completer_t {
 
    public:
     completer_t(const operation_context_t &ctx, completion_request_options_t f)
-        : ctx(ctx), flags(f), completions(ctx.expansion_limit) {}
+        : ctx(ctx),
+          flags(f),
+          fuzzy_subsequence(fuzzy_subsequence_enabled(ctx.vars)),
+          completions(ctx.expansion_limit) {}
 
     void perform_for_commandline(wcstring cmdline);
 
@@ -712,9 +772,12 @@ void completer_t::complete_abbr(const wcstring &cmd) {
 ///    Description of the completion
 /// @param  flags
 ///    The flags
+/// @param  group
+///    The group to list the completions under in the pager, if any
 ///
 void completer_t::complete_from_args(const wcstring &str, const wcstring &args,
-                                     const wcstring &desc, complete_flags_t flags) {
+                                     const wcstring &desc, complete_flags_t flags,
+                                     const wcstring &group) {
     const bool is_autosuggest = this->flags.autosuggestion;
 
     bool saved_interactive = false;
@@ -730,7 +793,9 @@ void completer_t::complete_from_args(const wcstring &str, const wcstring &args,
         eflags |= expand_flag::skip_cmdsubst;
     }
 
-    completion_list_t possible_comp = parser_t::expand_argument_list(args, eflags, ctx);
+    completion_list_t possible_comp = cache_seconds && !is_autosuggest
+                                          ? expand_args_cached(args, eflags)
+                                          : parser_t::expand_argument_list(args, eflags, ctx);
 
     if (ctx.parser) {
         ctx.parser->libdata().is_interactive = saved_interactive;
@@ -738,8 +803,39 @@ void completer_t::complete_from_args(const wcstring &str, const wcstring &args,
     }
 
     // Allow leading dots - see #3707.
+    size_t first = this->completions.size();
     this->complete_strings(escape_string(str), const_desc(desc), possible_comp, flags,
                            expand_flag::allow_nonliteral_leading_dot);
+    for (size_t i = first; i < this->completions.size(); i++) {
+        this->completions.at(i).group = group;
+    }
+}
+
+/// Expand \p args like complete_from_args does, reusing what was computed for the same command line
+/// in the same directory within the last cache_seconds.
+completion_list_t completer_t::expand_args_cached(const wcstring &args, expand_flags_t eflags) {
+    args_cache_key_t key{cache_cmd, args, custom_cmdline, ctx.vars.get_pwd_slash()};
+    {
+        auto cache = s_args_cache.acquire();
+        auto iter = cache->find(key);
+        if (iter != cache->end()) {
+            if (timef() < iter->second.expiry) return iter->second.args;
+            cache->erase(iter);
+        }
+    }
+
+    completion_list_t result = parser_t::expand_argument_list(args, eflags, ctx);
+    // An interrupted expansion may be missing arguments.
+    if (ctx.check_cancel()) return result;
+
+    auto cache = s_args_cache.acquire();
+    timepoint_t now = timef();
+    // Drop whatever has expired, so the cache doesn't grow without bound.
+    for (auto iter = cache->begin(); iter != cache->end();) {
+        iter = now < iter->second.expiry ? std::next(iter) : cache->erase(iter);
+    }
+    (*cache)[std::move(key)] = cached_args_t{result, now + cache_seconds};
+    return result;
 }
 
 static size_t leading_dash_count(const wchar_t *str) {
@@ -855,8 +951,11 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
         needs_load.push_back(cmd);
     }
 
-    // Make a list of lists of all options that we care about.
+    // Make a list of lists of all options that we care about, along with the command they are for
+    // and how long the arguments they compute may be cached.
     std::vector<option_list_t> all_options;
+    std::vector<std::pair<wcstring, unsigned>> all_caching;
+    std::vector<wcstring> all_argparse_functions;
     {
         auto completion_map = s_completion_map.acquire();
         for (const auto &kv : *completion_map) {
@@ -868,13 +967,42 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
                 // We have to copy them in reverse order to preserve legacy behavior (#9221).
                 const auto &options = kv.second.get_options();
                 all_options.emplace_back(options.rbegin(), options.rend());
+                all_caching.emplace_back(key.first, kv.second.cache_seconds);
+                all_argparse_functions.push_back(kv.second.argparse_function);
+            }
+        }
+    }
+
+    // Add the options from argparse specs. Loading the function runs a script, so this also has to
+    // happen outside the lock.
+    if (ctx.parser) {
+        for (size_t i = 0; i < all_options.size(); i++) {
+            const wcstring &function = all_argparse_functions.at(i);
+            if (function.empty()) continue;
+            for (argparse_option_t &opt : argparse_function_options(function, *ctx.parser)) {
+                complete_entry_opt_t entry{};
+                entry.desc = std::move(opt.description);
+                entry.result_mode.requires_param = opt.takes_value && !opt.value_optional;
+                if (opt.short_flag) {
+                    entry.option = wcstring(1, opt.short_flag);
+                    entry.type = option_type_short;
+                    all_options.at(i).push_back(entry);
+                }
+                if (!opt.long_flag.empty()) {
+                    entry.option = std::move(opt.long_flag);
+                    entry.type = option_type_double_long;
+                    all_options.at(i).push_back(std::move(entry));
+                }
             }
         }
     }
 
     // Now release the lock and test each option that we captured above. We have to do this outside
     // the lock because callouts (like the condition) may add or remove completions. See issue 2.
-    for (const auto &options : all_options) {
+    for (size_t i = 0; i < all_options.size(); i++) {
+        const option_list_t &options = all_options.at(i);
+        scoped_push<wcstring> saved_cache_cmd(&cache_cmd, all_caching.at(i).first);
+        scoped_push<unsigned> saved_cache_seconds(&cache_seconds, all_caching.at(i).second);
         size_t short_opt_pos = short_option_pos(str, options);
         // We want last_option_requires_param to default to false but distinguish between when
         // a previous completion has set it to false and when it has its default value.
@@ -909,7 +1037,7 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
                             if (o.result_mode.requires_param) use_common = false;
                             if (o.result_mode.no_files) use_files = false;
                             if (o.result_mode.force_files) has_force = true;
-                            complete_from_args(arg, o.comp, o.localized_desc(), o.flags);
+                            complete_from_args(arg, o.comp, o.localized_desc(), o.flags, o.group);
                         }
                     }
                 }
@@ -927,7 +1055,7 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
                         if (o.result_mode.requires_param) use_common = false;
                         if (o.result_mode.no_files) use_files = false;
                         if (o.result_mode.force_files) has_force = true;
-                        complete_from_args(str, o.comp, o.localized_desc(), o.flags);
+                        complete_from_args(str, o.comp, o.localized_desc(), o.flags, o.group);
                     }
                 }
 
@@ -956,7 +1084,7 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
                             if (o.result_mode.requires_param) use_common = false;
                             if (o.result_mode.no_files) use_files = false;
                             if (o.result_mode.force_files) has_force = true;
-                            complete_from_args(str, o.comp, o.localized_desc(), o.flags);
+                            complete_from_args(str, o.comp, o.localized_desc(), o.flags, o.group);
                         }
                     }
                 }
@@ -976,10 +1104,16 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
         for (const complete_entry_opt_t &o : options) {
             // If this entry is for the base command, check if any of the arguments match.
             if (!this->conditions_test(o.conditions)) continue;
+            auto add_option = [&](wcstring &&option, complete_flags_t flags) {
+                completion_t comp(std::move(option), o.localized_desc(),
+                                  string_fuzzy_match_t::exact_match(), flags);
+                comp.group = o.group;
+                return this->completions.add(std::move(comp));
+            };
             if (o.option.empty()) {
                 use_files = use_files && (!(o.result_mode.no_files));
                 has_force = has_force || o.result_mode.force_files;
-                complete_from_args(str, o.comp, o.localized_desc(), o.flags);
+                complete_from_args(str, o.comp, o.localized_desc(), o.flags, o.group);
             }
 
             if (!use_switches || str.empty()) {
@@ -1003,9 +1137,8 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
                     if (str.find(optchar) != wcstring::npos) continue;
                 }
                 // It's a match.
-                wcstring desc = o.localized_desc();
                 // Append a short-style option
-                if (!this->completions.add(wcstring{o.option}, std::move(desc), 0)) {
+                if (!add_option(wcstring{o.option}, 0)) {
                     return false;
                 }
             }
@@ -1050,14 +1183,13 @@ bool completer_t::complete_param_for_command(const wcstring &cmd_orig, const wcs
                 // functions.
                 wcstring completion = format_string(L"%ls=", whole_opt.c_str() + offset);
                 // Append a long-style option with a mandatory trailing equal sign
-                if (!this->completions.add(std::move(completion), C_(o.desc),
-                                           flags | COMPLETE_NO_SPACE)) {
+                if (!add_option(std::move(completion), flags | COMPLETE_NO_SPACE)) {
                     return false;
                 }
             }
 
             // Append a long-style option
-            if (!this->completions.add(whole_opt.substr(offset), C_(o.desc), flags)) {
+            if (!add_option(whole_opt.substr(offset), flags)) {
                 return false;
             }
         }
@@ -1145,7 +1277,7 @@ bool completer_t::complete_variable(const wcstring &str, size_t start_offset) {
     for (const wcstring &env_name : ctx.vars.get_names(0)) {
         bool anchor_start = !this->flags.fuzzy_match;
         maybe_t<string_fuzzy_match_t> match =
-            string_fuzzy_match_string(var, env_name, anchor_start);
+            string_fuzzy_match_string(var, env_name, anchor_start, fuzzy_subsequence);
         if (!match) continue;
 
         wcstring comp;
@@ -1365,6 +1497,8 @@ void completer_t::complete_custom(const wcstring &cmd, const wcstring &cmdline,
         remove_transient.emplace([=] { ctx.parser->libdata().transient_commandlines.pop_back(); });
     }
 
+    scoped_push<wcstring> saved_cmdline(&custom_cmdline, cmdline);
+
     // Maybe apply variable assignments.
     cleanup_t restore_vars{apply_var_assignments(*ad->var_assignments)};
     if (ctx.check_cancel()) return;
@@ -1374,6 +1508,13 @@ void completer_t::complete_custom(const wcstring &cmd, const wcstring &cmdline,
                                      !ad->had_ddash, &ad->do_file);
 }
 
+/// Whether \p tok is a keyword that only introduces the command after it, like `and` or `begin`.
+static bool is_command_prefix_keyword(const wcstring &tok) {
+    static const wcstring keywords[] = {L"and",   L"or",    L"not",  L"!",   L"begin",
+                                        L"if",    L"while", L"else", L"time"};
+    return contains(keywords, tok);
+}
+
 static bool expand_command_token(const operation_context_t &ctx, wcstring &cmd_tok) {
     // TODO: we give up if the first token expands to more than one argument. We could handle
     // that case by propagating arguments.
@@ -1530,6 +1671,12 @@ void completer_t::perform_for_commandline(wcstring cmdline) {
         if (ctx.parser) --ctx.parser->libdata().complete_recursion_level;
     }};
 
+    // Only the innermost command substitution around the cursor matters. The reader passes just
+    // that, but `complete -C` passes the whole command line.
+    const wchar_t *cmdsub_begin, *cmdsub_end;
+    parse_util_cmdsubst_extent(cmdline.c_str(), cmdline.size(), &cmdsub_begin, &cmdsub_end);
+    cmdline.erase(0, cmdsub_begin - cmdline.c_str());
+
     const size_t cursor_pos = cmdline.size();
     const bool is_autosuggest = flags.autosuggestion;
 
@@ -1546,15 +1693,25 @@ void completer_t::perform_for_commandline(wcstring cmdline) {
     parse_util_process_extent(cmdline.c_str(), position_in_statement, nullptr, nullptr, &tokens);
     size_t actual_token_count = tokens.size();
 
-    // Hack: fix autosuggestion by removing prefixing "and"s #6249.
-    if (is_autosuggest) {
-        tokens.erase(
-            std::remove_if(tokens.begin(), tokens.end(),
-                           [&cmdline](const tok_t &token) {
-                               return parser_keywords_is_subcommand(*token.get_source(cmdline));
-                           }),
-            tokens.end());
+    // Complete the command after keywords like `and` or `begin` as if they weren't there, so it
+    // gets the same completions anywhere in a chain or block. That is unless the cursor is on the
+    // keyword, or an option is given to it. Autosuggestions also skip decorations like `command`
+    // (#6249), since their completions need a parser.
+    size_t prefix_keywords = 0;
+    for (; prefix_keywords < tokens.size(); prefix_keywords++) {
+        const tok_t &tok = tokens.at(prefix_keywords);
+        if (tok.location_in_or_at_end_of_source_range(cursor_pos)) break;
+        wcstring tok_src = *tok.get_source(cmdline);
+        if (is_autosuggest ? !parser_keywords_is_subcommand(tok_src)
+                           : !is_command_prefix_keyword(tok_src)) {
+            break;
+        }
+        if (prefix_keywords + 1 < tokens.size() &&
+            string_prefixes_string(L"-", *tokens.at(prefix_keywords + 1).get_source(cmdline))) {
+            break;
+        }
     }
+    tokens.erase(tokens.begin(), tokens.begin() + prefix_keywords);
 
     // Consume variable assignments in tokens strictly before the cursor.
     // This is a list of (escaped) strings of the form VAR=VAL.
@@ -1718,7 +1875,7 @@ void append_completion(completion_list_t *completions, wcstring comp, wcstring d
 void complete_add(const wcstring &cmd, bool cmd_is_path, const wcstring &option,
                   complete_option_type_t option_type, completion_mode_t result_mode,
                   std::vector<wcstring> condition, const wchar_t *comp, const wchar_t *desc,
-                  complete_flags_t flags) {
+                  complete_flags_t flags, const wchar_t *group) {
     // option should be empty iff the option type is arguments only.
     assert(option.empty() == (option_type == option_type_args_only));
 
@@ -1735,6 +1892,7 @@ void complete_add(const wcstring &cmd, bool cmd_is_path, const wcstring &option,
     if (comp) opt.comp = comp;
     opt.conditions = std::move(condition);
     if (desc) opt.desc = desc;
+    if (group) opt.group = group;
     opt.flags = flags;
 
     c.add_option(std::move(opt));
@@ -1757,6 +1915,27 @@ void complete_remove_all(const wcstring &cmd, bool cmd_is_path) {
     completion_map->erase(std::make_pair(cmd, cmd_is_path));
 }
 
+void complete_set_cache(const wcstring &cmd, bool cmd_is_path, unsigned seconds) {
+    auto completion_map = s_completion_map.acquire();
+    (*completion_map)[std::make_pair(cmd, cmd_is_path)].cache_seconds = seconds;
+}
+
+void complete_set_argparse(const wcstring &cmd, bool cmd_is_path, const wcstring &function) {
+    auto completion_map = s_completion_map.acquire();
+    (*completion_map)[std::make_pair(cmd, cmd_is_path)].argparse_function = function;
+}
+
+void complete_flush_cache(const wcstring &cmd) {
+    auto cache = s_args_cache.acquire();
+    if (cmd.empty()) {
+        cache->clear();
+        return;
+    }
+    for (auto iter = cache->begin(); iter != cache->end();) {
+        iter = std::get<0>(iter->first) == cmd ? cache->erase(iter) : std::next(iter);
+    }
+}
+
 completion_list_t complete(const wcstring &cmd_with_subcmds, completion_request_options_t flags,
                            const operation_context_t &ctx, std::vector<wcstring> *out_needs_loads) {
     // Determine the innermost subcommand.
@@ -1828,6 +2007,7 @@ static wcstring completion2string(const completion_key_t &key, const complete_en
     }
 
     append_switch(out, L'd', C_(o.desc));
+    append_switch(out, L'g', o.group);
     append_switch(out, L'a', o.comp);
     for (const auto &c : o.conditions) {
         append_switch(out, L'n', c);
@@ -1881,6 +2061,27 @@ wcstring complete_print(const wcstring &cmd) {
         for (auto o = options.rbegin(); o != options.rend(); ++o) {
             out.append(completion2string(key, *o));
         }
+        if (entry.cache_seconds) {
+            out.append(L"complete");
+            if (key.second) {
+                append_switch(out, L'p', key.first);
+            } else {
+                out.append(L" ");
+                out.append(escape_string(key.first));
+            }
+            append_format(out, L" --cache %u\n", entry.cache_seconds);
+        }
+        if (!entry.argparse_function.empty()) {
+            out.append(L"complete");
+            if (key.second) {
+                append_switch(out, L'p', key.first);
+            } else {
+                out.append(L" ");
+                out.append(escape_string(key.first));
+            }
+            append_switch(out, L"from-argparse", entry.argparse_function);
+            out.append(L"\n");
+        }
     }
 
     // Append wraps.
@@ -1898,6 +2099,109 @@ wcstring complete_print(const wcstring &cmd) {
     return out;
 }
 
+void complete_print_json(const wcstring &cmd, JsonWriter &w) {
+    auto completions = s_completion_map.acquire();
+    using comp_ref_t = std::reference_wrapper<const completion_entry_map_t::value_type>;
+    std::vector<comp_ref_t> completion_refs(completions->begin(), completions->end());
+    std::sort(completion_refs.begin(), completion_refs.end(), [](comp_ref_t a, comp_ref_t b) {
+        return a.get().second.order < b.get().second.order;
+    });
+
+    for (const comp_ref_t &cr : completion_refs) {
+        const completion_key_t &key = cr.get().first;
+        const completion_entry_t &entry = cr.get().second;
+        if (!cmd.empty() && key.first != cmd) continue;
+        const option_list_t &options = entry.get_options();
+        // Use the same order as complete_print.
+        for (auto o = options.rbegin(); o != options.rend(); ++o) {
+            w.begin_object();
+            w.key(L"command");
+            w.string(key.first);
+            w.key(L"path");
+            w.boolean(key.second);
+            w.key(L"option_type");
+            switch (o->type) {
+                case option_type_args_only:
+                    w.null();
+                    break;
+                case option_type_short:
+                    w.string(L"short");
+                    break;
+                case option_type_single_long:
+                    w.string(L"old");
+                    break;
+                case option_type_double_long:
+                    w.string(L"long");
+                    break;
+            }
+            w.key(L"option");
+            if (o->type == option_type_args_only) {
+                w.null();
+            } else {
+                w.string(o->option);
+            }
+            w.key(L"description");
+            w.string(o->localized_desc());
+            w.key(L"group");
+            w.string(o->group);
+            w.key(L"arguments");
+            w.string(o->comp);
+            w.key(L"conditions");
+            w.begin_array();
+            for (const wcstring &condition : o->conditions) {
+                w.string(condition);
+            }
+            w.end_array();
+            w.key(L"no_files");
+            w.boolean(o->result_mode.no_files);
+            w.key(L"force_files");
+            w.boolean(o->result_mode.force_files);
+            w.key(L"requires_param");
+            w.boolean(o->result_mode.requires_param);
+            w.key(L"keep_order");
+            w.boolean(o->flags & COMPLETE_DONT_SORT);
+            w.end_object();
+        }
+        // The cache time has its own kind of object.
+        if (entry.cache_seconds) {
+            w.begin_object();
+            w.key(L"command");
+            w.string(key.first);
+            w.key(L"path");
+            w.boolean(key.second);
+            w.key(L"cache");
+            w.integer(entry.cache_seconds);
+            w.end_object();
+        }
+        // And so does the function whose argparse spec is used.
+        if (!entry.argparse_function.empty()) {
+            w.begin_object();
+            w.key(L"command");
+            w.string(key.first);
+            w.key(L"path");
+            w.boolean(key.second);
+            w.key(L"from_argparse");
+            w.string(entry.argparse_function);
+            w.end_object();
+        }
+    }
+
+    // Wraps have their own kind of object, too.
+    auto locked_wrappers = wrapper_map.acquire();
+    for (const auto &entry : *locked_wrappers) {
+        const wcstring &src = entry.first;
+        if (!cmd.empty() && src != cmd) continue;
+        for (const wcstring &target : entry.second) {
+            w.begin_object();
+            w.key(L"command");
+            w.string(src);
+            w.key(L"wraps");
+            w.string(target);
+            w.end_object();
+        }
+    }
+}
+
 void complete_invalidate_path() {
     // TODO: here we unload all completions for commands that are loaded by the autoloader. We also
     // unload any completions that the user may specified on the command line. We should in
@@ -1908,6 +2212,18 @@ void complete_invalidate_path() {
     }
 }
 
+autoload_info_t complete_get_autoload_info(const wcstring &cmd) {
+    return completion_autoloader.acquire()->get_info(cmd, env_stack_t::globals());
+}
+
+void complete_reload_changed() {
+    std::vector<wcstring> cmds =
+        completion_autoloader.acquire()->forget_changed_commands(env_stack_t::globals());
+    for (const wcstring &cmd : cmds) {
+        complete_remove_all(cmd, false /* not a path */);
+    }
+}
+
 /// Add a new target that wraps a command. Example: __fish_XYZ (function) wraps XYZ (target).
 bool complete_add_wrapper(const wcstring &command, const wcstring &new_target) {
     if (command.empty() || new_target.empty()) {
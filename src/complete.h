@@ -30,6 +30,7 @@ struct completion_mode_t {
 #define PROG_COMPLETE_SEP L'\t'
 
 class parser_t;
+struct autoload_info_t;
 
 enum {
     /// Do not insert space afterwards if this is the only completion. (The default is to try insert
@@ -77,6 +78,8 @@ class completion_t {
     string_fuzzy_match_t match;
     /// Flags determining the completion behavior.
     complete_flags_t flags;
+    /// The group to list this completion under in the pager, like "Branches", or empty for none.
+    wcstring group;
 
     // Construction.
     explicit completion_t(wcstring comp, wcstring desc = wcstring(),
@@ -240,10 +243,11 @@ void completions_sort_and_prioritize(completion_list_t *comps,
 /// \param condition a command to be run to check it this completion should be used. If \c condition
 /// is empty, the completion is always used.
 /// \param flags A set of completion flags
+/// \param group The group to list the completions under in the pager, if any.
 void complete_add(const wcstring &cmd, bool cmd_is_path, const wcstring &option,
                   complete_option_type_t option_type, completion_mode_t result_mode,
                   std::vector<wcstring> condition, const wchar_t *comp, const wchar_t *desc,
-                  complete_flags_t flags);
+                  complete_flags_t flags, const wchar_t *group = nullptr);
 
 /// Remove a previously defined completion.
 void complete_remove(const wcstring &cmd, bool cmd_is_path, const wcstring &option,
@@ -252,6 +256,18 @@ void complete_remove(const wcstring &cmd, bool cmd_is_path, const wcstring &opti
 /// Removes all completions for a given command.
 void complete_remove_all(const wcstring &cmd, bool cmd_is_path);
 
+/// Cache the arguments that the completions for a command compute, like with command
+/// substitutions, for \p seconds, or stop caching them if it is 0. Cached arguments are reused for
+/// the same command line in the same directory.
+void complete_set_cache(const wcstring &cmd, bool cmd_is_path, unsigned seconds);
+
+/// Also complete the options that the function \p function defines with argparse for a command,
+/// or stop doing that if it is empty.
+void complete_set_argparse(const wcstring &cmd, bool cmd_is_path, const wcstring &function);
+
+/// Forget the cached arguments of the completions for \p cmd, or for all commands if it is empty.
+void complete_flush_cache(const wcstring &cmd = wcstring());
+
 /// Load command-specific completions for the specified command.
 /// \return true if something new was loaded, false if not.
 bool complete_load(const wcstring &cmd, parser_t &parser);
@@ -268,6 +284,11 @@ completion_list_t complete(const wcstring &cmd, completion_request_options_t fla
 /// Return a list of all current completions.
 wcstring complete_print(const wcstring &cmd = L"");
 
+/// Write the current completions for \p cmd (or all commands if empty) to \p w, as an array of
+/// JSON objects.
+struct JsonWriter;
+void complete_print_json(const wcstring &cmd, JsonWriter &w);
+
 /// Create a new completion entry.
 ///
 /// \param completions The array of completions to append to
@@ -288,4 +309,12 @@ std::vector<wcstring> complete_get_wrap_targets(const wcstring &command);
 // Observes that fish_complete_path has changed.
 void complete_invalidate_path();
 
+/// \return the files in fish_complete_path that could provide completions for \p cmd, and the one
+/// they were loaded from. This does not autoload.
+autoload_info_t complete_get_autoload_info(const wcstring &cmd);
+
+/// Unload the autoloaded completions of commands whose completion file has changed or gone away.
+/// They are loaded again the next time the command is completed.
+void complete_reload_changed();
+
 #endif
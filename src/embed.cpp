@@ -0,0 +1,101 @@
+// Support for evaluating fish script inside another program.
+#include "config.h"  // IWYU pragma: keep
+
+#include "embed.h"
+
+#include <locale.h>
+
+#include <memory>
+#include <utility>
+
+#include "common.h"
+#include "env.h"
+#include "ffi_init.rs.h"
+#include "future_feature_flags.h"
+#include "io.h"
+#include "parse_constants.h"
+#include "parse_tree.h"
+#include "parser.h"
+#include "path.h"
+#include "proc.h"
+#include "reader.h"
+#include "signals.h"
+#include "wutil.h"  // IWYU pragma: keep
+
+/// Source config.fish in \p dir, if it is readable.
+static void embed_source_config(parser_t &parser, const wcstring &dir) {
+    const wcstring path = dir + L"/config.fish";
+    if (waccess(path, R_OK) != 0) return;
+    parser.libdata().within_fish_init = true;
+    parser.eval(L"builtin source " + escape_string(path), io_chain_t());
+    parser.libdata().within_fish_init = false;
+}
+
+parser_t &embed_init(bool read_config) {
+    static bool s_initialized = false;
+    if (s_initialized) return parser_t::principal_parser();
+    s_initialized = true;
+
+    if (!program_name) program_name = L"fish";
+    rust_init();
+    signal_unblock_all();
+    setlocale(LC_ALL, "");
+
+    // There is no executable to find our data relative to, so use the compiled in paths.
+    config_paths_t paths;
+    paths.data = L"" DATADIR "/fish";
+    paths.sysconf = L"" SYSCONFDIR "/fish";
+    paths.doc = L"" DOCDIR;
+    paths.bin = L"" BINDIR;
+    env_init(&paths, /* do uvars */ read_config, /* default paths */ !read_config);
+
+    if (auto features_var = env_stack_t::globals().get(L"fish_features")) {
+        for (const wcstring &s : features_var->as_list()) {
+            mutable_fish_features()->set_from_string(s.c_str());
+        }
+    }
+    proc_init();
+    misc_init();
+    reader_init();
+
+    parser_t &parser = parser_t::principal_parser();
+    parser.set_syncs_uvars(read_config);
+    if (read_config) {
+        embed_source_config(parser, paths.data);
+        embed_source_config(parser, paths.sysconf);
+        wcstring config_dir;
+        if (path_get_config(config_dir)) embed_source_config(parser, config_dir);
+    }
+    parser.set_last_statuses(statuses_t::just(STATUS_CMD_OK));
+    return parser;
+}
+
+int embed_eval(parser_t &parser, const wcstring &cmd, wcstring &out, wcstring &err) {
+    out.clear();
+    err.clear();
+
+    // Syntax errors are reported like any other error output, rather than on our stderr.
+    auto errors = new_parse_error_list();
+    auto ps = parse_source(wcstring{cmd}, parse_flag_none, &*errors);
+    if (!ps->has_value()) {
+        parser.get_backtrace(cmd, *errors, err);
+        err.push_back(L'\n');
+        parser.set_last_statuses(statuses_t::just(STATUS_ILLEGAL_CMD));
+        return STATUS_ILLEGAL_CMD;
+    }
+
+    // Buffer creation may fail, e.g. if we have too many open files to make a pipe.
+    auto out_fill = io_bufferfill_t::create();
+    auto err_fill = io_bufferfill_t::create(0, STDERR_FILENO);
+    if (!out_fill || !err_fill) {
+        if (out_fill) io_bufferfill_t::finish(std::move(out_fill));
+        if (err_fill) io_bufferfill_t::finish(std::move(err_fill));
+        return STATUS_CMD_ERROR;
+    }
+
+    eval_res_t res = parser.eval_parsed_source(*ps, io_chain_t{out_fill, err_fill}, {},
+                                               block_type_t::top);
+    out = str2wcstring(io_bufferfill_t::finish(std::move(out_fill)).newline_serialized());
+    err = str2wcstring(io_bufferfill_t::finish(std::move(err_fill)).newline_serialized());
+    return res.status.status_value();
+}
@@ -0,0 +1,22 @@
+// Support for evaluating fish script inside another program, without spawning a fish process.
+//
+// This is the C++ half of the embedding API; programs written in Rust use the `embed` module of
+// fish-rust, which wraps it.
+#ifndef FISH_EMBED_H
+#define FISH_EMBED_H
+
+#include "common.h"
+
+class parser_t;
+
+/// Initialize the shell the way main() does for a non-interactive fish, and return the parser to
+/// evaluate with. If \p read_config is set, uvars and the config.fish files are read as well.
+/// Only the first call has an effect; later calls just return the parser.
+parser_t &embed_init(bool read_config);
+
+/// Evaluate \p cmd with \p parser, storing what it writes to stdout in \p out and what it writes
+/// to stderr, including any syntax errors, in \p err.
+/// \return the exit status.
+int embed_eval(parser_t &parser, const wcstring &cmd, wcstring &out, wcstring &err);
+
+#endif
@@ -302,6 +302,7 @@ void env_init(const struct config_paths_t *paths, bool do_uvars, bool default_pa
     // in a function like `fish_prompt` or `fish_right_prompt` it is defined at the time the first
     // prompt is written.
     vars.set_one(L"CMD_DURATION", ENV_UNEXPORT, L"0");
+    vars.set_one(L"cmd_duration_us", ENV_UNEXPORT, L"0");
 
     // Set up the version variable.
     wcstring version = str2wcstring(get_fish_version());
@@ -366,7 +367,8 @@ void env_init(const struct config_paths_t *paths, bool do_uvars, bool default_pa
     // Allow changes to variables to produce events.
     env_dispatch_init_ffi(/* vars */);
 
-    init_input();
+    // Non-interactive shells set up key bindings when they first read from the terminal.
+    if (is_interactive_session()) init_input();
 
     // Complain about invalid config paths.
     // HACK: Assume the defaults are correct (in practice this is only --no-config anyway).
@@ -392,13 +394,41 @@ statuses_t env_stack_t::get_last_statuses() const {
     res.kill_signal = statuses_ffi->get_kill_signal();
     auto &pipestatus = statuses_ffi->get_pipestatus();
     res.pipestatus.assign(pipestatus.begin(), pipestatus.end());
+    // Usage is flattened into six integers per process, in declaration order.
+    auto usage = statuses_ffi->get_pipestatus_usage();
+    for (size_t i = 0; i + 6 <= usage.size(); i += 6) {
+        res.pipestatus_usage.push_back(proc_usage_t{usage[i], usage[i + 1], usage[i + 2],
+                                                    usage[i + 3], usage[i + 4], usage[i + 5]});
+    }
     return res;
 }
 
+std::vector<int64_t> flatten_proc_usage(const proc_usage_t &u) {
+    return {u.wall_usec, u.user_usec, u.sys_usec, u.max_rss_kb, u.in_blocks, u.out_blocks};
+}
+
+proc_usage_t statuses_t::total_usage() const {
+    proc_usage_t total{};
+    for (const auto &u : pipestatus_usage) {
+        total.wall_usec = std::max(total.wall_usec, u.wall_usec);
+        total.user_usec += u.user_usec;
+        total.sys_usec += u.sys_usec;
+        total.max_rss_kb = std::max(total.max_rss_kb, u.max_rss_kb);
+        total.in_blocks += u.in_blocks;
+        total.out_blocks += u.out_blocks;
+    }
+    return total;
+}
+
 int env_stack_t::get_last_status() const { return get_last_statuses().status; }
 
 void env_stack_t::set_last_statuses(statuses_t s) {
-    return impl_->set_last_statuses(s.status, s.kill_signal, s.pipestatus);
+    std::vector<int64_t> usage;
+    for (const auto &u : s.pipestatus_usage) {
+        auto flat = flatten_proc_usage(u);
+        usage.insert(usage.end(), flat.begin(), flat.end());
+    }
+    return impl_->set_last_statuses(s.status, s.kill_signal, s.pipestatus, usage);
 }
 
 /// Update the PWD variable directory from the result of getcwd().
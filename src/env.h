@@ -92,6 +92,27 @@ struct config_paths_t {
     wcstring bin;      // e.g., /usr/local/bin
 };
 
+/// Resources used by a single process, collected when it is reaped.
+struct proc_usage_t {
+    /// Elapsed wall time between launch and exit, in microseconds.
+    int64_t wall_usec{0};
+
+    /// User and system CPU time, in microseconds. This and the remaining fields are only known
+    /// for external processes.
+    int64_t user_usec{0};
+    int64_t sys_usec{0};
+
+    /// Maximum resident set size, in kilobytes.
+    int64_t max_rss_kb{0};
+
+    /// Number of block input and output operations.
+    int64_t in_blocks{0};
+    int64_t out_blocks{0};
+};
+
+/// Flatten \p usage into the list of six integers used to pass it to Rust, in declaration order.
+std::vector<int64_t> flatten_proc_usage(const proc_usage_t &usage);
+
 /// A collection of status and pipestatus.
 struct statuses_t {
     /// Status of the last job to exit.
@@ -104,13 +125,21 @@ struct statuses_t {
     /// Pipestatus value.
     std::vector<int> pipestatus{};
 
+    /// Resources used by each process of the pipeline, parallel to pipestatus.
+    std::vector<proc_usage_t> pipestatus_usage{};
+
     /// Return a statuses for a single process status.
     static statuses_t just(int s) {
         statuses_t result{};
         result.status = s;
         result.pipestatus.push_back(s);
+        result.pipestatus_usage.push_back(proc_usage_t{});
         return result;
     }
+
+    /// \return the combined usage of all processes in the pipeline. The processes run
+    /// concurrently, so this has the longest wall time and peak RSS, and the sum of the rest.
+    proc_usage_t total_usage() const;
 };
 
 /// Initialize environment variable data.
@@ -339,7 +368,8 @@ void unsetenv_lock(const char *name);
 const std::map<wcstring, wcstring> &env_get_inherited();
 
 /// Populate the values in the "$history" variable.
-/// fish_history_val is the value of the "$fish_history" variable, or "fish" if not set.
+/// fish_history_val is the value of the "$fish_history_session" variable, or if not set, of the
+/// "$fish_history" variable, or "fish" if neither is set.
 wcstring_list_ffi_t get_history_variable_text_ffi(const wcstring &fish_history_val);
 
 #endif
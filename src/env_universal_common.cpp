@@ -38,6 +38,7 @@
 #include "env_universal_common.h"
 #include "fallback.h"  // IWYU pragma: keep
 #include "fd_readable_set.rs.h"
+#include "fds.rs.h"
 #include "flog.h"
 #include "path.h"
 #include "utf8.h"
@@ -1152,6 +1153,8 @@ static autoclose_fd_t make_fifo(const wchar_t *test_path, const wchar_t *suffix)
         const char *error = std::strerror(errno);
         const wchar_t *errmsg = _(L"Unable to open a pipe for universal variables using '%ls': %s");
         FLOGF(error, errmsg, vars_path.c_str(), error);
+    } else {
+        track_fd(res.fd(), L"universal variable notifier");
     }
     return res;
 }
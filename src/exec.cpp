@@ -33,13 +33,16 @@
 #include "common.h"
 #include "env.h"
 #include "env_dispatch.rs.h"
+#include "event.h"
 #include "exec.h"
 #include "fallback.h"  // IWYU pragma: keep
+#include "fd_monitor.rs.h"
 #include "fds.h"
 #include "ffi.h"
 #include "flog.h"
 #include "function.h"
 #include "global_safety.h"
+#include "history.h"
 #include "io.h"
 #include "iothread.h"
 #include "job_group.rs.h"
@@ -51,6 +54,7 @@
 #include "proc.h"
 #include "reader.h"
 #include "redirection.h"
+#include "signals.h"
 #include "timer.rs.h"
 #include "trace.rs.h"
 #include "wcstringutil.h"
@@ -212,20 +216,12 @@ bool is_thompson_shell_script(const char *path) {
 // To avoid the race between the caller calling tcsetpgrp() and the client checking the
 // foreground process group, we don't use posix_spawn if we're going to foreground the process. (If
 // we use fork(), we can call tcsetpgrp after the fork, before the exec, and avoid the race).
-static bool can_use_posix_spawn_for_job(const std::shared_ptr<job_t> &job,
-                                        const dup2_list_t &dup2s) {
+static bool can_use_posix_spawn_for_job(const std::shared_ptr<job_t> &job) {
     // Is it globally disabled?
     if (!use_posix_spawn()) return false;
 
-    // Hack - do not use posix_spawn if there are self-fd redirections.
-    // For example if you were to write:
-    //   cmd 6< /dev/null
-    // it is possible that the open() of /dev/null would result in fd 6. Here even if we attempted
-    // to add a dup2 action, it would be ignored and the CLO_EXEC bit would remain. So don't use
-    // posix_spawn in this case; instead we'll call fork() and clear the CLO_EXEC bit manually.
-    for (const auto &action : dup2s.get_actions()) {
-        if (action.src == action.target) return false;
-    }
+    // Note self-fd redirections like `cmd 6< /dev/null` (where the open() may itself return fd 6)
+    // are handled by posix_spawner_t, so they need not force a fork.
     if (job->group->wants_terminal()) {
         // This job will be foregrounded, so we will call tcsetpgrp(), therefore do not use
         // posix_spawn.
@@ -234,13 +230,25 @@ static bool can_use_posix_spawn_for_job(const std::shared_ptr<job_t> &job,
     return true;
 }
 
-static void internal_exec(env_stack_t &vars, job_t *j, const io_chain_t &block_io) {
+/// Called when exec is about to replace fish with \p p, once its redirections are known to work.
+/// Fire the fish_preexec_replace event, then save what would otherwise be saved when fish exits.
+/// The terminal modes are restored when launching, and the universal variable notifier's fds are
+/// close-on-exec.
+static void prepare_for_exec_replace(parser_t &parser, process_t *p) {
+    event_fire_generic(parser, L"fish_preexec_replace", p->argv());
+    parser.sync_uvars_and_fire();
+    history_save_all();
+}
+
+static void internal_exec(parser_t &parser, job_t *j, const io_chain_t &block_io) {
     // Do a regular launch -  but without forking first...
+    env_stack_t &vars = parser.vars();
     process_t *p = j->processes.front().get();
     io_chain_t all_ios = block_io;
     if (!all_ios.append_from_specs(p->redirection_specs(), vars.get_pwd_slash())) {
         return;
     }
+    prepare_for_exec_replace(parser, p);
 
     // child_setup_process makes sure signals are properly set up.
     dup2_list_t redirs = dup2_list_resolve_chain_shim(all_ios);
@@ -366,6 +374,7 @@ static void run_internal_process_or_short_circuit(parser_t &parser, const std::s
                                                   std::string &&errdata, const io_chain_t &ios) {
     if (outdata.empty() && errdata.empty()) {
         p->completed = true;
+        p->record_usage(nullptr);
         if (p->is_last_in_job) {
             FLOGF(exec_job_status, L"Set status of job %d (%ls) to %d using short circuit",
                   j->job_id(), j->preview().c_str(), p->status);
@@ -398,9 +407,10 @@ bool blocked_signals_for_job(const job_t &job, sigset_t *sigmask) {
 }
 
 /// Call fork() as part of executing a process \p p in a job \j. Execute \p child_action in the
-/// context of the child.
+/// context of the child, in the directory \p cwd_fd unless it is -1.
 static launch_result_t fork_child_for_process(const std::shared_ptr<job_t> &job, process_t *p,
-                                              const dup2_list_t &dup2s, const char *fork_type,
+                                              const dup2_list_t &dup2s, int cwd_fd,
+                                              const char *fork_type,
                                               const std::function<void()> &child_action) {
     // Claim the tty from fish, if the job wants it and we are the pgroup leader.
     pid_t claim_tty_from =
@@ -428,7 +438,8 @@ static launch_result_t fork_child_for_process(const std::shared_ptr<job_t> &job,
     }
 
     if (!is_parent) {
-        // Child process.
+        // Child process. Change the directory first, before a dup2 can replace the fd.
+        if (cwd_fd >= 0) ignore_result(fchdir(cwd_fd));
         child_setup_process(claim_tty_from, *job, true, dup2s);
         child_action();
         DIE("Child process returned control to fork_child lambda!");
@@ -506,6 +517,60 @@ static void handle_builtin_output(parser_t &parser, const std::shared_ptr<job_t>
                                           io_chain);
 }
 
+#ifdef __linux__
+/// Parse an I/O scheduling class like "idle", "best-effort" or "realtime", optionally followed by a
+/// level from 0 (highest) to 7 like "best-effort:7", into the value for ioprio_set.
+/// \return none() if it is not valid.
+static maybe_t<int> parse_ioprio(const wcstring &str) {
+    // These are IOPRIO_CLASS_RT, IOPRIO_CLASS_BE and IOPRIO_CLASS_IDLE.
+    static const std::pair<const wchar_t *, int> classes[] = {
+        {L"realtime", 1}, {L"best-effort", 2}, {L"idle", 3}};
+    wcstring name = str;
+    int level = 4;
+    auto colon = str.find(L':');
+    if (colon != wcstring::npos) {
+        name = str.substr(0, colon);
+        level = fish_wcstoi(str.c_str() + colon + 1);
+        if (errno || level < 0 || level > 7) return none();
+    }
+    for (const auto &cls : classes) {
+        if (name == cls.first) {
+            // The idle class has no levels.
+            return (cls.second << 13) | (cls.second == 3 ? 0 : level);
+        }
+    }
+    return none();
+}
+#endif
+
+/// \return the scheduling settings from $fish_nice and $fish_ionice, warning about invalid values.
+static launch_priority_t get_launch_priority(const parser_t &parser) {
+    launch_priority_t result{};
+    auto nice_var = parser.vars().get(L"fish_nice");
+    if (nice_var && !nice_var->empty()) {
+        int increment = fish_wcstoi(nice_var->as_string().c_str());
+        if (errno) {
+            FLOGF(warning, _(L"Invalid value for fish_nice: '%ls'"),
+                  nice_var->as_string().c_str());
+        } else {
+            result.nice_increment = increment;
+        }
+    }
+    auto ionice_var = parser.vars().get(L"fish_ionice");
+    if (ionice_var && !ionice_var->empty()) {
+#ifdef __linux__
+        result.ioprio = parse_ioprio(ionice_var->as_string());
+        if (!result.ioprio) {
+            FLOGF(warning, _(L"Invalid value for fish_ionice: '%ls'"),
+                  ionice_var->as_string().c_str());
+        }
+#else
+        FLOGF(warning, _(L"fish_ionice is not supported on this system"));
+#endif
+    }
+    return result;
+}
+
 /// Executes an external command.
 /// An error return here indicates that the process failed to launch, and the rest of
 /// the pipeline should be cancelled.
@@ -530,13 +595,18 @@ static launch_result_t exec_external_command(parser_t &parser, const std::shared
     std::string actual_cmd_str = wcs2zstring(p->actual_cmd);
     const char *actual_cmd = actual_cmd_str.c_str();
     filename_ref_t file = parser.libdata().current_filename;
+    launch_priority_t priority = get_launch_priority(parser);
+    // Start the command in the parser's directory. That is normally fish's own directory too, but
+    // a program which embeds fish can change that behind fish's back.
+    int cwd_fd = parser.libdata().cwd_fd ? parser.libdata().cwd_fd->fd() : -1;
 
 #if FISH_USE_POSIX_SPAWN
     // Prefer to use posix_spawn, since it's faster on some systems like OS X.
-    if (can_use_posix_spawn_for_job(j, dup2s)) {
+    // It cannot change the priority of the child, so fork if that is wanted.
+    if (can_use_posix_spawn_for_job(j) && priority.empty()) {
         ++s_fork_count;  // spawn counts as a fork+exec
 
-        posix_spawner_t spawner(j.get(), dup2s);
+        posix_spawner_t spawner(j.get(), dup2s, cwd_fd);
         maybe_t<pid_t> pid = spawner.spawn(actual_cmd, const_cast<char *const *>(argv),
                                            const_cast<char *const *>(envv));
         if (int err = spawner.get_error()) {
@@ -567,8 +637,10 @@ static launch_result_t exec_external_command(parser_t &parser, const std::shared
     } else
 #endif
     {
-        return fork_child_for_process(j, p, dup2s, "external command",
-                                      [&] { safe_launch_process(p, actual_cmd, argv, envv); });
+        return fork_child_for_process(j, p, dup2s, cwd_fd, "external command", [&] {
+            if (!priority.empty()) child_apply_priority(priority);
+            safe_launch_process(p, actual_cmd, argv, envv);
+        });
     }
 }
 
@@ -617,6 +689,52 @@ static void function_restore_environment(parser_t &parser, const block_t *block)
     parser.libdata().returning = false;
 }
 
+/// \return the internal ids of all jobs in the parser.
+static std::vector<internal_job_id_t> get_job_ids(const parser_t &parser) {
+    std::vector<internal_job_id_t> result;
+    for (const auto &j : parser.jobs()) {
+        result.push_back(j->internal_job_id);
+    }
+    return result;
+}
+
+// Given that a function with a job scope has returned, apply the scope to the background jobs it
+// started, which are those not in \p prior_ids.
+static void finish_function_jobs(parser_t &parser, function_job_scope_t scope,
+                                 const std::vector<internal_job_id_t> &prior_ids) {
+    auto started_here = [&](const shared_ptr<job_t> &j) {
+        return j->is_constructed() && !j->is_completed() && !j->flags().disown_requested &&
+               !contains(prior_ids, j->internal_job_id);
+    };
+    switch (scope) {
+        case function_job_scope_t::none:
+            break;
+        case function_job_scope_t::wait: {
+            while (!signal_check_cancel() &&
+                   std::any_of(parser.jobs().begin(), parser.jobs().end(), started_here)) {
+                proc_wait_any(parser);
+            }
+            break;
+        }
+        case function_job_scope_t::cancel: {
+            for (const auto &j : parser.jobs()) {
+                if (!started_here(j)) continue;
+                if (j->is_stopped()) j->signal(SIGCONT);
+                j->signal(SIGTERM);
+            }
+            break;
+        }
+        case function_job_scope_t::disown: {
+            for (const auto &j : parser.jobs()) {
+                if (!started_here(j)) continue;
+                j->mut_flags().disown_requested = true;
+                add_disowned_job(j.get());
+            }
+            break;
+        }
+    }
+}
+
 // The "performer" function of a block or function process.
 // This accepts a place to execute as \p parser and then executes the result, returning a status.
 // This is factored out in this funny way in preparation for concurrent execution.
@@ -655,9 +773,20 @@ static proc_performer_t get_performer_for_process(process_t *p, job_t *job,
         return [=](parser_t &parser) {
             // Pull out the job list from the function.
             const ast::job_list_t &body = props->func_node->jobs();
+            std::vector<internal_job_id_t> prior_ids;
+            if (props->job_scope != function_job_scope_t::none) prior_ids = get_job_ids(parser);
+            profile_item_t *profile_item = parser.create_profile_item();
+            const auto start_time = profile_item ? profile_item_t::now() : 0;
             const block_t *fb = function_prepare_environment(parser, argv, *props);
             auto res = parser.eval_node(*props->parsed_source, body, io_chain, job_group);
             function_restore_environment(parser, fb);
+            finish_function_jobs(parser, props->job_scope, prior_ids);
+            if (profile_item) {
+                profile_item->duration = profile_item_t::now() - start_time;
+                profile_item->level = parser.eval_level;
+                profile_item->type = profile_item_t::type_t::function;
+                profile_item->cmd = argv.front();
+            }
 
             // If the function did not execute anything, treat it as success.
             if (res.was_empty) {
@@ -895,6 +1024,7 @@ static launch_result_t exec_process_in_job(parser_t &parser, process_t *p,
 
     // Execute the process.
     p->check_generations_before_launch();
+    p->mark_launched();
     switch (p->type) {
         case process_type_t::function:
         case process_type_t::block_node: {
@@ -969,13 +1099,21 @@ static void abort_pipeline_from(const shared_ptr<job_t> &job, const process_t *f
     assert(found && "Process not present in job");
 }
 
+/// \return whether the exec job \p j replaces us with another fish, which can adopt our jobs.
+static bool exec_replaces_with_fish(const job_t *j) {
+    return wbasename(j->processes.front()->actual_cmd) == L"fish";
+}
+
 // Given that we are about to execute an exec() call, check if the parser is interactive and there
 // are extant background jobs. If so, warn the user and do not exec().
 // \return true if we should allow exec, false to disallow it.
-static bool allow_exec_with_background_jobs(parser_t &parser) {
+static bool allow_exec_with_background_jobs(parser_t &parser, const job_t *j) {
     // If we're not interactive, we cannot warn.
     if (!parser.is_interactive()) return true;
 
+    // A new fish takes over our background jobs, so there is nothing to warn about.
+    if (exec_replaces_with_fish(j)) return true;
+
     // Construct the list of running background jobs.
     job_list_t bgs = jobs_requiring_warning_on_exit(parser);
     if (bgs.empty()) return true;
@@ -993,6 +1131,197 @@ static bool allow_exec_with_background_jobs(parser_t &parser) {
     }
 }
 
+/// \return the value of the variable \p name as it applies to the job \p j, or none() if unset.
+/// Assignments like `var=val cmd` are only in scope while each process is launched, so they are
+/// checked before the parser's variables.
+static maybe_t<wcstring> get_job_variable(const parser_t &parser, const job_t *j,
+                                          const wcstring &name) {
+    for (const auto &p : j->processes) {
+        for (const auto &assignment : p->variable_assignments) {
+            if (assignment.variable_name == name) return join_strings(assignment.values, L' ');
+        }
+    }
+    auto var = parser.vars().get(name);
+    if (!var) return none();
+    return var->as_string();
+}
+
+/// How long a job which exceeded $fish_timeout has to exit after SIGTERM, before it gets SIGKILL.
+static constexpr auto k_timeout_kill_grace = std::chrono::seconds(2);
+
+/// Parse a duration like "10", "1.5s", "500ms", "2m" or "1h" from $fish_timeout.
+/// \return none() if it is not a valid positive duration.
+static maybe_t<std::chrono::milliseconds> parse_timeout(const wcstring &str) {
+    wchar_t *end = nullptr;
+    errno = 0;
+    double value = fish_wcstod(str, &end);
+    if (errno || end == str.c_str() || !(value > 0)) return none();
+    wcstring unit = end;
+    double scale;
+    if (unit.empty() || unit == L"s") {
+        scale = 1000;
+    } else if (unit == L"ms") {
+        scale = 1;
+    } else if (unit == L"m") {
+        scale = 60 * 1000;
+    } else if (unit == L"h") {
+        scale = 60 * 60 * 1000;
+    } else {
+        return none();
+    }
+    return std::chrono::milliseconds(static_cast<long long>(value * scale));
+}
+
+/// The state of the fd monitor timer enforcing $fish_timeout for a job.
+struct job_timeout_timer_t {
+    std::shared_ptr<job_timeout_t> timeout;
+    /// Set once SIGTERM was sent, so the next expiry sends SIGKILL.
+    bool terminated{false};
+};
+
+extern "C" {
+/// Called on the fd monitor's thread when a $fish_timeout timer expires.
+static void job_timeout_callback(autoclose_fd_t2 &, item_wake_reason_t reason,
+                                 job_timeout_timer_t *timer) {
+    if (reason == item_wake_reason_t::Timeout && !timer->timeout->finished) {
+        std::lock_guard<std::mutex> guard(timer->timeout->lock);
+        int sig = timer->terminated ? SIGKILL : SIGTERM;
+        for (pid_t pid : timer->timeout->pids) kill(pid, sig);
+        if (!timer->terminated) {
+            timer->timeout->expired = true;
+            timer->terminated = true;
+            auto grace = std::chrono::microseconds(k_timeout_kill_grace);
+            timer->timeout->timer_id = fd_monitor().add_timer(
+                grace.count(), (uint8_t *)job_timeout_callback, (uint8_t *)timer);
+            return;
+        }
+    }
+    delete timer;
+}
+}
+
+/// If $fish_timeout is set, start a timer which signals the external processes of \p j if they
+/// are still running when it expires: first SIGTERM, then SIGKILL after a grace period.
+static void arm_job_timeout(const parser_t &parser, const shared_ptr<job_t> &j) {
+    auto var = get_job_variable(parser, j.get(), L"fish_timeout");
+    if (!var || var->empty()) return;
+    auto duration = parse_timeout(*var);
+    if (!duration) {
+        FLOGF(warning, _(L"Invalid value for fish_timeout: '%ls'"), var->c_str());
+        return;
+    }
+
+    // Signal the process group if the job has its own, otherwise each process.
+    std::vector<pid_t> pids;
+    maybe_t<pid_t> pgid = j->get_pgid();
+    if (pgid.has_value() && *pgid != getpgrp()) {
+        pids.push_back(-*pgid);
+    } else {
+        for (const auto &p : j->processes) {
+            if (p->pid > 0) pids.push_back(p->pid);
+        }
+    }
+    if (pids.empty()) return;
+
+    auto timeout = std::make_shared<job_timeout_t>();
+    timeout->pids = std::move(pids);
+    j->timeout = timeout;
+    // The timer owns this and deletes it when done, perhaps before add_timer returns.
+    auto timer = new job_timeout_timer_t();
+    timer->timeout = timeout;
+    auto usecs = std::chrono::duration_cast<std::chrono::microseconds>(*duration);
+    timeout->timer_id =
+        fd_monitor().add_timer(usecs.count(), (uint8_t *)job_timeout_callback, (uint8_t *)timer);
+}
+
+/// \return the limit on running background jobs for the job \p j. That is $fish_max_background_jobs
+/// if set, else the number of processors for jobs launched with &--queue, else 0 for none.
+static long get_max_background_jobs(const parser_t &parser, const job_t *j) {
+    auto var = get_job_variable(parser, j, L"fish_max_background_jobs");
+    if (var && !var->empty()) {
+        long limit = fish_wcstol(var->c_str());
+        if (!errno && limit > 0) return limit;
+    }
+    if (j->wants_queue()) return std::max(1L, sysconf(_SC_NPROCESSORS_ONLN));
+    return 0;
+}
+
+/// If the background job \p j would exceed the limit on running background jobs, wait until enough
+/// other background jobs have finished. Stopped jobs do not count against the limit, since they
+/// would never free their slot.
+static void wait_for_background_slot(parser_t &parser, const shared_ptr<job_t> &j) {
+    if (j->is_foreground()) return;
+    long limit = get_max_background_jobs(parser, j.get());
+    if (limit <= 0) return;
+
+    auto running_in_background = [&](const shared_ptr<job_t> &other) {
+        return other != j && other->is_constructed() && !other->is_completed() &&
+               !other->is_stopped() && !other->is_foreground() &&
+               !other->flags().disown_requested;
+    };
+    while (!signal_check_cancel() &&
+           std::count_if(parser.jobs().begin(), parser.jobs().end(), running_in_background) >=
+               limit) {
+        proc_wait_any(parser);
+    }
+}
+
+/// \return whether $fish_pipe_buffering asks for the pipes of the job \p j to be line-buffered.
+static bool wants_line_buffered_pipes(const parser_t &parser, const job_t *j) {
+    if (j->processes.size() < 2) return false;
+    auto var = get_job_variable(parser, j, L"fish_pipe_buffering");
+    if (!var || var->empty() || *var == L"full") return false;
+    if (*var == L"line") return true;
+    FLOGF(warning, _(L"Invalid value for fish_pipe_buffering: '%ls'"), var->c_str());
+    return false;
+}
+
+/// Make the pipe from an external process to the next process in its job, when it should be
+/// line-buffered. The process writes to a pseudoterminal, so its C library flushes each line, and
+/// a thread relays the output into an ordinary pipe for the next process.
+/// \return the pipes, or none() on failure, in which case the caller should fall back to a pipe.
+static maybe_t<autoclose_pipes_t> make_line_buffered_pipes() {
+    auto pty = make_autoclose_pty();
+    if (!pty) return none();
+    auto pipes = make_autoclose_pipes();
+    if (!pipes) return none();
+
+    // The relay thread owns the pty master and the write end of the pipe.
+    int master = pty->read.acquire();
+    int relay_write = pipes->write.acquire();
+    bool started = make_detached_pthread([=] {
+        char buff[4096];
+        for (;;) {
+            ssize_t amt = read(master, buff, sizeof buff);
+            if (amt < 0 && errno == EINTR) continue;
+            // Once all writers have closed the slave, this fails with EIO on Linux and returns 0
+            // elsewhere. If the reader has exited, closing the master makes further writes fail.
+            if (amt <= 0 || write_loop(relay_write, buff, amt) < 0) break;
+        }
+        close(master);
+        close(relay_write);
+    });
+    if (!started) {
+        close(master);
+        close(relay_write);
+        return none();
+    }
+    return autoclose_pipes_t(std::move(pipes->read), std::move(pty->write));
+}
+
+/// Fire the fish_job_started event for \p j, which has launched its processes.
+static void fire_job_started_event(parser_t &parser, const job_t *j) {
+    // The jobs of the handlers themselves don't fire it again.
+    if (parser.libdata().is_event) return;
+    std::vector<int32_t> pids;
+    for (const auto &p : j->processes) {
+        if (p->pid > 0) pids.push_back(p->pid);
+    }
+    // Jobs of only functions and builtins are not started in a way that's interesting to watch.
+    if (pids.empty()) return;
+    event_fire(parser, *new_event_job_started(j->command(), pids));
+}
+
 bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &block_io) {
     assert(j && "null job_t passed to exec_job!");
 
@@ -1004,16 +1333,19 @@ bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &bl
     // Handle an exec call.
     if (j->processes.front()->type == process_type_t::exec) {
         // If we are interactive, perhaps disallow exec if there are background jobs.
-        if (!allow_exec_with_background_jobs(parser)) {
+        if (!allow_exec_with_background_jobs(parser, j.get())) {
             for (const auto &p : j->processes) {
                 p->mark_aborted_before_launch();
             }
             return false;
         }
 
-        internal_exec(parser.vars(), j.get(), block_io);
+        bool hand_off_jobs = exec_replaces_with_fish(j.get());
+        if (hand_off_jobs) hand_off_jobs_for_exec(parser);
+        internal_exec(parser, j.get(), block_io);
         // internal_exec only returns if it failed to set up redirections.
         // In case of an successful exec, this code is not reached.
+        if (hand_off_jobs) revoke_jobs_hand_off(parser);
         int status = j->flags().negate ? 0 : 1;
         parser.set_last_statuses(statuses_t::just(status));
 
@@ -1023,6 +1355,7 @@ bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &bl
         }
         return false;
     }
+    wait_for_background_slot(parser, j);
     auto timer = push_timer(j->wants_timing() && !no_exec());
 
     // Get the deferred process, if any. We will have to remember its pipes.
@@ -1046,6 +1379,7 @@ bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &bl
     // Lastly, a process may experience a pipeline-aborting error, which prevents launching
     // further processes in the pipeline.
     autoclose_fd_t pipe_next_read;
+    bool line_buffered_pipes = wants_line_buffered_pipes(parser, j.get());
     bool aborted_pipeline = false;
     size_t procs_launched = 0;
     for (const auto &procptr : j->processes) {
@@ -1057,7 +1391,11 @@ bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &bl
         autoclose_pipes_t proc_pipes;
         proc_pipes.read = std::move(pipe_next_read);
         if (!p->is_last_in_job) {
-            auto pipes = make_autoclose_pipes();
+            maybe_t<autoclose_pipes_t> pipes{};
+            if (line_buffered_pipes && p->type == process_type_t::external) {
+                pipes = make_line_buffered_pipes();
+            }
+            if (!pipes) pipes = make_autoclose_pipes();
             if (!pipes) {
                 FLOGF(warning, PIPE_ERROR);
                 wperror(L"pipe");
@@ -1119,6 +1457,7 @@ bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &bl
     FLOGF(exec_job_exec, L"Executed job %d from command '%ls'", j->job_id(), j->command_wcstr());
 
     j->mark_constructed();
+    arm_job_timeout(parser, j);
 
     // If exec_error then a backgrounded job would have been terminated before it was ever assigned
     // a pgroup, so error out before setting last_pid.
@@ -1130,6 +1469,7 @@ bool exec_job(parser_t &parser, const shared_ptr<job_t> &j, const io_chain_t &bl
             parser.vars().set_empty(L"last_pid", ENV_GLOBAL);
         }
     }
+    fire_job_started_event(parser, j.get());
 
     if (!j->is_initially_background()) {
         j->continue_job(parser);
@@ -1237,8 +1577,16 @@ static int exec_subshell_internal(const wcstring &cmd, parser_t &parser,
 int exec_subshell_for_expand(const wcstring &cmd, parser_t &parser,
                              const job_group_ref_t &job_group, std::vector<wcstring> &outputs) {
     parser.assert_can_execute();
+    profile_item_t *profile_item = parser.create_profile_item();
+    const auto start_time = profile_item ? profile_item_t::now() : 0;
     bool break_expand = false;
     int ret = exec_subshell_internal(cmd, parser, job_group, &outputs, &break_expand, true, true);
+    if (profile_item) {
+        profile_item->duration = profile_item_t::now() - start_time;
+        profile_item->level = parser.eval_level;
+        profile_item->type = profile_item_t::type_t::command_substitution;
+        profile_item->cmd = cmd;
+    }
     // Only return an error code if we should break expansion.
     return break_expand ? ret : STATUS_CMD_OK;
 }
@@ -968,6 +968,29 @@ expand_result_t expander_t::stage_cmdsubst(wcstring input, completion_receiver_t
 }
 
 // We pass by value to match other stages. NOLINTNEXTLINE(performance-unnecessary-value-param)
+/// Run `await` for the variables in \p instr whose value a command started with `async` is still
+/// computing, so that expanding such a variable waits for its command the first time. `async`
+/// records a pending command for NAME in the variable __fish_async_NAME. This needs the parser, so
+/// background expansions, like for highlighting, leave the variable alone.
+static void await_async_variables(const wcstring &instr, const operation_context_t &ctx) {
+    // await itself expands variables, which must not wait again.
+    static bool s_awaiting = false;
+    if (!ctx.parser || s_awaiting) return;
+    for (size_t i = 0; i < instr.size(); i++) {
+        if (instr.at(i) != VARIABLE_EXPAND && instr.at(i) != VARIABLE_EXPAND_SINGLE) continue;
+        size_t end = i + 1;
+        while (end < instr.size() && valid_var_name_char(instr.at(end))) end++;
+        wcstring name = instr.substr(i + 1, end - i - 1);
+        if (name.empty() || !ctx.vars.get(L"__fish_async_" + name)) continue;
+
+        // Waiting is not a command of its own, so it leaves $status alone.
+        scoped_push<bool> awaiting(&s_awaiting, true);
+        statuses_t saved_statuses = ctx.parser->get_last_statuses();
+        ctx.parser->eval(L"await " + escape_string(name), io_chain_t{});
+        ctx.parser->set_last_statuses(std::move(saved_statuses));
+    }
+}
+
 expand_result_t expander_t::stage_variables(wcstring input, completion_receiver_t *out) {
     // We accept incomplete strings here, since complete uses expand_string to expand incomplete
     // strings from the commandline.
@@ -986,6 +1009,7 @@ expand_result_t expander_t::stage_variables(wcstring input, completion_receiver_
         }
         return expand_result_t::ok;
     } else {
+        await_async_variables(next, ctx);
         size_t size = next.size();
         return expand_variables(std::move(next), out, size, ctx.vars, errors);
     }
@@ -58,6 +58,9 @@ enum class expand_flag {
     /// Do expansions specifically to support external command completions. This means using PATH as
     /// a list of potential working directories.
     special_for_command,
+    /// Also allow case-insensitive subsequence matches, and rank subsequence matches by how close
+    /// they are. Only applicable if fuzzy_match is set.
+    fuzzy_subsequence,
 
     COUNT,
 };
@@ -4,8 +4,6 @@
 // Many of these functions are more or less broken and incomplete.
 #include "config.h"
 
-// IWYU likes to recommend adding term.h when we want ncurses.h.
-// IWYU pragma: no_include "term.h"
 #include <errno.h>   // IWYU pragma: keep
 #include <fcntl.h>   // IWYU pragma: keep
 #include <limits.h>  // IWYU pragma: keep
@@ -14,35 +12,11 @@
 
 #include <cstdlib>
 #include <cwchar>
-#if HAVE_GETTEXT
-#include <libintl.h>
-#endif
-#if defined(TPARM_SOLARIS_KLUDGE)
-#if HAVE_CURSES_H
-#include <curses.h>  // IWYU pragma: keep
-#elif HAVE_NCURSES_H
-#include <ncurses.h>  // IWYU pragma: keep
-#elif HAVE_NCURSES_CURSES_H
-#include <ncurses/curses.h>  // IWYU pragma: keep
-#endif
-#if HAVE_TERM_H
-#include <term.h>  // IWYU pragma: keep
-#elif HAVE_NCURSES_TERM_H
-#include <ncurses/term.h>
-#endif
-#endif
 #include <signal.h>  // IWYU pragma: keep
 
 #include "common.h"    // IWYU pragma: keep
 #include "fallback.h"  // IWYU pragma: keep
 
-#if defined(TPARM_SOLARIS_KLUDGE)
-char *tparm_solaris_kludge(char *str, long p1, long p2, long p3, long p4, long p5, long p6, long p7,
-                           long p8, long p9) {
-    return tparm(str, p1, p2, p3, p4, p5, p6, p7, p8, p9);
-}
-#endif
-
 int fish_mkstemp_cloexec(char *name_template) {
 #if HAVE_MKOSTEMP
     // null check because mkostemp may be a weak symbol
@@ -101,27 +75,6 @@ int wcsncasecmp(const wchar_t *a, const wchar_t *b, size_t n) {
 #endif
 #endif
 
-#if HAVE_GETTEXT
-char *fish_gettext(const char *msgid) { return gettext(msgid); }
-
-char *fish_bindtextdomain(const char *domainname, const char *dirname) {
-    return bindtextdomain(domainname, dirname);
-}
-
-char *fish_textdomain(const char *domainname) { return textdomain(domainname); }
-#else
-char *fish_gettext(const char *msgid) { return (char *)msgid; }
-char *fish_bindtextdomain(const char *domainname, const char *dirname) {
-    UNUSED(domainname);
-    UNUSED(dirname);
-    return nullptr;
-}
-char *fish_textdomain(const char *domainname) {
-    UNUSED(domainname);
-    return nullptr;
-}
-#endif
-
 #ifndef HAVE_KILLPG
 int killpg(int pgr, int sig) {
     assert(pgr > 1);
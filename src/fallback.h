@@ -44,14 +44,6 @@ int fish_mkstemp_cloexec(char *);
 #define WCHAR_MAX INT_MAX
 #endif
 
-/// Both ncurses and NetBSD curses expect an int (*func)(int) as the last parameter for tputs.
-/// Apparently OpenIndiana's curses still uses int (*func)(char) here.
-#if TPUTS_USES_INT_ARG
-using tputs_arg_t = int;
-#else
-using tputs_arg_t = char;
-#endif
-
 #ifndef HAVE_WINSIZE
 /// Structure used to get the size of a terminal window.
 struct winsize {
@@ -63,15 +55,6 @@ struct winsize {
 
 #endif
 
-#if defined(TPARM_SOLARIS_KLUDGE)
-/// Solaris tparm has a set fixed of parameters in its curses implementation, work around this here.
-#define fish_tparm tparm_solaris_kludge
-char *tparm_solaris_kludge(char *str, long p1 = 0, long p2 = 0, long p3 = 0, long p4 = 0,
-                           long p5 = 0, long p6 = 0, long p7 = 0, long p8 = 0, long p9 = 0);
-#else
-#define fish_tparm tparm
-#endif
-
 /// These functions are missing from Solaris 10, and only accessible from
 /// Solaris 11 in the std:: namespace.
 #ifndef HAVE_WCSCASECMP
@@ -98,18 +81,6 @@ int wcsncasecmp(const wchar_t *s1, const wchar_t *s2, size_t n);
 #endif
 #endif
 
-// autoconf may fail to detect gettext (645), so don't define a function call gettext or we'll get
-// build errors.
-
-/// Cover for gettext().
-char *fish_gettext(const char *msgid);
-
-/// Cover for bindtextdomain().
-char *fish_bindtextdomain(const char *domainname, const char *dirname);
-
-/// Cover for textdomain().
-char *fish_textdomain(const char *domainname);
-
 #ifndef HAVE_KILLPG
 /// Send specified signal to specified process group.
 int killpg(int pgr, int sig);
@@ -6,10 +6,13 @@
 
 #include <errno.h>
 #include <fcntl.h>
+#include <stdlib.h>
+#include <termios.h>
 #include <unistd.h>
 
 #include <algorithm>
 
+#include "fds.rs.h"
 #include "flog.h"
 #include "wutil.h"
 
@@ -182,9 +185,45 @@ maybe_t<autoclose_pipes_t> make_autoclose_pipes() {
     write_end = heightenize_fd(std::move(write_end), already_cloexec);
     if (!write_end.valid()) return none();
 
+    track_fd(read_end.fd(), L"pipe");
+    track_fd(write_end.fd(), L"pipe");
     return autoclose_pipes_t(std::move(read_end), std::move(write_end));
 }
 
+maybe_t<autoclose_pipes_t> make_autoclose_pty() {
+    autoclose_fd_t master{posix_openpt(O_RDWR | O_NOCTTY)};
+    if (!master.valid() || grantpt(master.fd()) < 0 || unlockpt(master.fd()) < 0) {
+        wperror(L"posix_openpt");
+        return none();
+    }
+    const char *slave_name = ptsname(master.fd());
+    if (!slave_name) {
+        wperror(L"ptsname");
+        return none();
+    }
+    autoclose_fd_t slave{open_cloexec(slave_name, O_RDWR | O_NOCTTY)};
+    if (!slave.valid()) {
+        wperror(L"open");
+        return none();
+    }
+
+    // Don't translate newlines, echo or generate signals; the output should arrive as written.
+    struct termios modes;
+    if (tcgetattr(slave.fd(), &modes) == 0) {
+        modes.c_oflag &= ~OPOST;
+        modes.c_lflag &= ~(ECHO | ICANON | ISIG | IEXTEN);
+        tcsetattr(slave.fd(), TCSANOW, &modes);
+    }
+
+    master = heightenize_fd(std::move(master), false);
+    if (!master.valid()) return none();
+    slave = heightenize_fd(std::move(slave), true);
+    if (!slave.valid()) return none();
+    track_fd(master.fd(), L"pty");
+    track_fd(slave.fd(), L"pty");
+    return autoclose_pipes_t(std::move(master), std::move(slave));
+}
+
 pipes_ffi_t make_pipes_ffi() {
     pipes_ffi_t res = {-1, -1};
     if (auto pipes = make_autoclose_pipes()) {
@@ -231,6 +270,7 @@ int open_cloexec(const char *path, int flags, mode_t mode) {
         fd = -1;
     }
 #endif
+    if (fd >= 0) track_fd(fd, str2wcstring(path));
     return fd;
 }
 
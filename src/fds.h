@@ -80,10 +80,17 @@ struct autoclose_pipes_t {
 };
 
 /// Call pipe(), populating autoclose fds.
-/// The pipes are marked CLO_EXEC and are placed in the high fd range.
+/// The pipes are marked CLO_EXEC, are placed in the high fd range and are tracked for `status fds`.
 /// \return pipes on success, none() on error.
 maybe_t<autoclose_pipes_t> make_autoclose_pipes();
 
+/// Open a pseudoterminal, for a writer which should believe it is writing to a terminal.
+/// The read end is the master and the write end is the slave. The slave is put in raw mode, so
+/// output passes through unchanged. Both are marked CLO_EXEC, are placed in the high fd range and
+/// are tracked for `status fds`.
+/// \return the pair on success, none() on error.
+maybe_t<autoclose_pipes_t> make_autoclose_pty();
+
 /// Create pipes.
 /// Upon failure both values will be negative.
 struct pipes_ffi_t {
@@ -141,7 +148,7 @@ std::shared_ptr<fd_event_signaller_t> ffi_new_fd_event_signaller_t();
 int set_cloexec(int fd, bool should_set = true);
 
 /// Wide character version of open() that also sets the close-on-exec flag (atomically when
-/// possible).
+/// possible). The fd is tracked for `status fds`, with the path as its purpose.
 int wopen_cloexec(const wcstring &pathname, int flags, mode_t mode = 0);
 
 /// Narrow versions of wopen_cloexec.
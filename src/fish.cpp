@@ -39,6 +39,7 @@ Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
 
 #include "ast.h"
 #include "common.h"
+#include "crash_handler.rs.h"
 #include "cxxgen.h"
 #include "env.h"
 #include "event.h"
@@ -54,6 +55,7 @@ Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
 #include "global_safety.h"
 #include "history.h"
 #include "io.h"
+#include "lsp.h"
 #include "maybe.h"
 #include "parse_constants.h"
 #include "parse_tree.h"
@@ -77,6 +79,8 @@ class fish_cmd_opts_t {
     // File path for profiling output, or empty for none.
     std::string profile_output;
     std::string profile_startup_output;
+    // The format of the profiling output.
+    profile_format_t profile_format{profile_format_t::text};
     // Commands to be executed in place of interactive shell.
     std::vector<std::string> batch_cmds;
     // Commands to execute after the shell's config has been read.
@@ -93,6 +97,10 @@ class fish_cmd_opts_t {
     bool is_interactive_session{false};
     /// Whether to enable private mode.
     bool enable_private_mode{false};
+    /// The history session to use instead of $fish_history, if not empty.
+    wcstring history_session;
+    /// Whether to run a language server instead of a shell.
+    bool lsp{false};
 };
 
 /// \return a timeval converted to milliseconds.
@@ -293,17 +301,20 @@ static int fish_parse_opt(int argc, char **argv, fish_cmd_opts_t *opts) {
         {"command", required_argument, nullptr, 'c'},
         {"init-command", required_argument, nullptr, 'C'},
         {"features", required_argument, nullptr, 'f'},
+        {"history-session", required_argument, nullptr, 6},
         {"debug", required_argument, nullptr, 'd'},
         {"debug-output", required_argument, nullptr, 'o'},
         {"debug-stack-frames", required_argument, nullptr, 'D'},
         {"interactive", no_argument, nullptr, 'i'},
         {"login", no_argument, nullptr, 'l'},
+        {"lsp", no_argument, nullptr, 4},
         {"no-config", no_argument, nullptr, 'N'},
         {"no-execute", no_argument, nullptr, 'n'},
         {"print-rusage-self", no_argument, nullptr, 1},
         {"print-debug-categories", no_argument, nullptr, 2},
         {"profile", required_argument, nullptr, 'p'},
         {"profile-startup", required_argument, nullptr, 3},
+        {"profile-format", required_argument, nullptr, 5},
         {"private", no_argument, nullptr, 'P'},
         {"help", no_argument, nullptr, 'h'},
         {"version", no_argument, nullptr, 'v'},
@@ -350,6 +361,10 @@ static int fish_parse_opt(int argc, char **argv, fish_cmd_opts_t *opts) {
                 opts->is_login = true;
                 break;
             }
+            case 4: {
+                opts->lsp = true;
+                break;
+            }
             case 'N': {
                 opts->no_config = true;
                 // --no-config implies private mode, we won't be saving history
@@ -391,6 +406,25 @@ static int fish_parse_opt(int argc, char **argv, fish_cmd_opts_t *opts) {
                 g_profiling_active = true;
                 break;
             }
+            case 5: {
+                auto format = profile_format_from_string(str2wcstring(optarg));
+                if (!format) {
+                    std::fwprintf(stderr, _(L"%ls: Invalid profile format '%s'\n"), program_name,
+                                  optarg);
+                    exit(1);
+                }
+                opts->profile_format = *format;
+                break;
+            }
+            case 6: {
+                opts->history_session = str2wcstring(optarg);
+                if (!valid_var_name(opts->history_session)) {
+                    std::fwprintf(stderr, _(L"%ls: Invalid history session name '%s'\n"),
+                                  program_name, optarg);
+                    exit(1);
+                }
+                break;
+            }
             case 'P': {
                 opts->enable_private_mode = true;
                 break;
@@ -417,7 +451,7 @@ static int fish_parse_opt(int argc, char **argv, fish_cmd_opts_t *opts) {
     // We are an interactive session if we have not been given an explicit
     // command or file to execute and stdin is a tty. Note that the -i or
     // --interactive options also force interactive mode.
-    if (opts->batch_cmds.empty() && optind == argc && isatty(STDIN_FILENO)) {
+    if (!opts->lsp && opts->batch_cmds.empty() && optind == argc && isatty(STDIN_FILENO)) {
         set_interactive_session(true);
     }
 
@@ -487,6 +521,23 @@ int main(int argc, char **argv) {
         save_term_foreground_process_group();
     }
 
+    // The language server talks over stdin and stdout. Keep those for it, so nothing else can
+    // read its requests or mix output into its responses; that goes to stderr instead.
+    int lsp_in = -1, lsp_out = -1;
+    if (opts.lsp) {
+        lsp_in = dup(STDIN_FILENO);
+        lsp_out = dup(STDOUT_FILENO);
+        if (lsp_in < 0 || lsp_out < 0) {
+            perror("dup");
+            exit(1);
+        }
+        set_cloexec(lsp_in);
+        set_cloexec(lsp_out);
+        autoclose_fd_t null_fd(open_cloexec("/dev/null", O_RDONLY));
+        if (null_fd.valid()) dup2(null_fd.fd(), STDIN_FILENO);
+        dup2(STDERR_FILENO, STDOUT_FILENO);
+    }
+
     struct config_paths_t paths;
     // If we're not executing, there's no need to find the config.
     if (!opts.no_exec) {
@@ -494,6 +545,11 @@ int main(int argc, char **argv) {
         env_init(&paths, /* do uvars */ !opts.no_config, /* default paths */ opts.no_config);
     }
 
+    // Write a report if we crash. This goes in the data directory, if we have one.
+    wcstring data_dir;
+    if (opts.no_exec || !path_get_data(data_dir)) data_dir.clear();
+    crash_handler_install(data_dir, str2wcstring(get_fish_version()));
+
     // Set features early in case other initialization depends on them.
     // Start with the ones set in the environment, then those set on the command line (so the
     // command line takes precedence).
@@ -507,9 +563,17 @@ int main(int argc, char **argv) {
     misc_init();
     reader_init();
 
+    // Private mode keeps the history off the disk, whatever the session.
+    if (!opts.history_session.empty() && !opts.enable_private_mode) {
+        env_stack_t::globals().set_one(L"fish_history_session", ENV_GLOBAL, opts.history_session);
+    }
+
     parser_t &parser = parser_t::principal_parser();
     parser.set_syncs_uvars(!opts.no_config);
 
+    // If we replaced a fish via exec, take over its background jobs.
+    adopt_inherited_jobs(parser);
+
     if (!opts.no_exec && !opts.no_config) {
         read_init(parser, paths);
     }
@@ -531,7 +595,7 @@ int main(int argc, char **argv) {
     // If we're profiling startup to a separate file, write it now.
     if (!opts.profile_startup_output.empty() &&
         opts.profile_startup_output != opts.profile_output) {
-        parser.emit_profiling(opts.profile_startup_output.c_str());
+        parser.emit_profiling(opts.profile_startup_output.c_str(), opts.profile_format);
 
         // If we are profiling both, ensure the startup data only
         // ends up in the startup file.
@@ -548,7 +612,11 @@ int main(int argc, char **argv) {
     // Clear signals in case we were interrupted (#9024).
     signal_clear_cancel();
 
-    if (!opts.batch_cmds.empty()) {
+    if (opts.lsp) {
+        int status = lsp_serve(parser, lsp_in, lsp_out);
+        parser.set_last_statuses(statuses_t::just(status));
+        res = 0;
+    } else if (!opts.batch_cmds.empty()) {
         // Run the commands specified as arguments, if any.
         if (get_login()) {
             // Do something nasty to support OpenSUSE assuming we're bash. This may modify cmds.
@@ -605,7 +673,7 @@ int main(int argc, char **argv) {
     restore_term_foreground_process_group_for_exit();
 
     if (!opts.profile_output.empty()) {
-        parser.emit_profiling(opts.profile_output.c_str());
+        parser.emit_profiling(opts.profile_output.c_str(), opts.profile_format);
     }
 
     history_save_all();
@@ -23,14 +23,17 @@ Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
 #include <stddef.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <sys/stat.h>
 
 #include <algorithm>
+#include <atomic>
 #include <cstdint>
 #include <cstring>
 #include <cwchar>
 #include <cwctype>
 #include <memory>
 #include <string>
+#include <thread>
 #include <type_traits>
 #include <utility>
 #include <vector>
@@ -102,18 +105,22 @@ static const char *highlight_role_to_string(highlight_role_t role) {
         TEST_ROLE(autosuggestion)
         TEST_ROLE(selection)
         TEST_ROLE(pager_progress)
+        TEST_ROLE(pager_group)
         TEST_ROLE(pager_background)
         TEST_ROLE(pager_prefix)
         TEST_ROLE(pager_completion)
         TEST_ROLE(pager_description)
+        TEST_ROLE(pager_match)
         TEST_ROLE(pager_secondary_background)
         TEST_ROLE(pager_secondary_prefix)
         TEST_ROLE(pager_secondary_completion)
         TEST_ROLE(pager_secondary_description)
+        TEST_ROLE(pager_secondary_match)
         TEST_ROLE(pager_selected_background)
         TEST_ROLE(pager_selected_prefix)
         TEST_ROLE(pager_selected_completion)
         TEST_ROLE(pager_selected_description)
+        TEST_ROLE(pager_selected_match)
         default:
             DIE("UNKNOWN ROLE");
     }
@@ -165,7 +172,7 @@ static std::string make_pygments_csv(const wcstring &src) {
 }
 
 // Entry point for prettification.
-static wcstring prettify(const wcstring &src, bool do_indent) {
+static wcstring prettify(const wcstring &src, const pretty_printer_options_t &options) {
     if (dump_parse_tree) {
         auto ast = ast_parse(src, parse_flag_leave_unterminated | parse_flag_include_comments |
                                       parse_flag_show_extra_semis);
@@ -173,7 +180,7 @@ static wcstring prettify(const wcstring &src, bool do_indent) {
         std::fwprintf(stderr, L"%ls\n", ast_dump.c_str());
     }
 
-    pretty_printer_t printer{src, do_indent};
+    pretty_printer_t printer{src, options};
     wcstring output = printer.prettify();
     return output;
 }
@@ -286,6 +293,310 @@ static std::string html_colorize(const wcstring &text,
 
 static std::string no_colorize(const wcstring &text) { return wcs2zstring(text); }
 
+enum class diff_op_t { equal, remove, insert };
+
+/// Split \p text into lines, keeping their newlines.
+static std::vector<wcstring> split_lines(const wcstring &text) {
+    std::vector<wcstring> lines;
+    size_t start = 0;
+    while (start < text.size()) {
+        size_t end = std::min(text.find(L'\n', start), text.size() - 1) + 1;
+        lines.push_back(text.substr(start, end - start));
+        start = end;
+    }
+    return lines;
+}
+
+/// Compute the shortest edit script turning the lines \p a into the lines \p b, with Myers' diff
+/// algorithm.
+static std::vector<diff_op_t> diff_lines(const std::vector<wcstring> &a,
+                                         const std::vector<wcstring> &b) {
+    const long n = a.size(), m = b.size();
+    // v[offset + k] is the furthest x reached on the diagonal k = x - y. We keep the part of it
+    // that matters before each round, to find our way back.
+    const long offset = n + m + 1;
+    std::vector<long> v(2 * offset + 1, 0);
+    std::vector<std::vector<long>> trace;
+    for (long d = 0; d <= n + m; d++) {
+        trace.emplace_back(v.begin() + offset - d - 1, v.begin() + offset + d + 2);
+        bool done = false;
+        for (long k = -d; k <= d && !done; k += 2) {
+            long x = (k == -d || (k != d && v[offset + k - 1] < v[offset + k + 1]))
+                         ? v[offset + k + 1]
+                         : v[offset + k - 1] + 1;
+            long y = x - k;
+            while (x < n && y < m && a[x] == b[y]) x++, y++;
+            v[offset + k] = x;
+            done = x >= n && y >= m;
+        }
+        if (done) break;
+    }
+
+    std::vector<diff_op_t> ops;
+    long x = n, y = m;
+    for (long d = trace.size() - 1; d >= 0; d--) {
+        const std::vector<long> &prev = trace[d];
+        auto furthest = [&](long k) { return prev[k + d + 1]; };
+        long k = x - y;
+        long prev_k =
+            (k == -d || (k != d && furthest(k - 1) < furthest(k + 1))) ? k + 1 : k - 1;
+        long prev_x = furthest(prev_k);
+        long prev_y = prev_x - prev_k;
+        for (; x > prev_x && y > prev_y; x--, y--) ops.push_back(diff_op_t::equal);
+        if (d > 0) ops.push_back(x == prev_x ? diff_op_t::insert : diff_op_t::remove);
+        x = prev_x;
+        y = prev_y;
+    }
+    std::reverse(ops.begin(), ops.end());
+    return ops;
+}
+
+/// \return a unified diff from \p old_text to \p new_text, labeled \p old_label and \p new_label.
+static wcstring unified_diff(const wcstring &old_text, const wcstring &new_text,
+                             const wcstring &old_label, const wcstring &new_label) {
+    const size_t context = 3;
+    std::vector<wcstring> a = split_lines(old_text), b = split_lines(new_text);
+    std::vector<diff_op_t> ops = diff_lines(a, b);
+    // For each step, the number of lines of a and b before it.
+    std::vector<size_t> a_pos, b_pos;
+    size_t ai = 0, bi = 0;
+    for (diff_op_t op : ops) {
+        a_pos.push_back(ai);
+        b_pos.push_back(bi);
+        if (op != diff_op_t::insert) ai++;
+        if (op != diff_op_t::remove) bi++;
+    }
+    a_pos.push_back(ai);
+    b_pos.push_back(bi);
+
+    wcstring out = format_string(L"--- %ls\n+++ %ls\n", old_label.c_str(), new_label.c_str());
+    auto append_line = [&](wchar_t prefix, const wcstring &line) {
+        out.push_back(prefix);
+        out.append(line);
+        if (line.back() != L'\n') out.append(L"\n\\ No newline at end of file\n");
+    };
+    // Lines are numbered from 1, except that an empty range names the line before it.
+    auto append_range = [&](wchar_t prefix, size_t start, size_t count) {
+        out.push_back(prefix);
+        out.append(std::to_wstring(count ? start + 1 : start));
+        if (count != 1) out.append(L"," + std::to_wstring(count));
+    };
+
+    size_t i = 0;
+    while (i < ops.size()) {
+        while (i < ops.size() && ops[i] == diff_op_t::equal) i++;
+        if (i == ops.size()) break;
+        size_t start = i > context ? i - context : 0;
+        // Changes separated by only a few unchanged lines go into the same hunk.
+        size_t end = i;
+        while (true) {
+            while (end < ops.size() && ops[end] != diff_op_t::equal) end++;
+            size_t next = end;
+            while (next < ops.size() && ops[next] == diff_op_t::equal) next++;
+            if (next == ops.size() || next - end > 2 * context) break;
+            end = next;
+        }
+        end = std::min(end + context, ops.size());
+
+        out.append(L"@@ ");
+        append_range(L'-', a_pos[start], a_pos[end] - a_pos[start]);
+        out.push_back(L' ');
+        append_range(L'+', b_pos[start], b_pos[end] - b_pos[start]);
+        out.append(L" @@\n");
+        for (size_t j = start; j < end; j++) {
+            switch (ops[j]) {
+                case diff_op_t::equal: {
+                    append_line(L' ', a[a_pos[j]]);
+                    break;
+                }
+                case diff_op_t::remove: {
+                    append_line(L'-', a[a_pos[j]]);
+                    break;
+                }
+                case diff_op_t::insert: {
+                    append_line(L'+', b[b_pos[j]]);
+                    break;
+                }
+            }
+        }
+        i = end;
+    }
+    return out;
+}
+
+/// Add \p path to \p paths, or if it is a directory, the fish scripts in it and its
+/// subdirectories in sorted order, skipping hidden files. \return false and print an error if a
+/// directory can't be read.
+static bool collect_paths(const std::string &path, std::vector<std::string> *paths) {
+    struct stat buf;
+    if (stat(path.c_str(), &buf) != 0 || !S_ISDIR(buf.st_mode)) {
+        // Errors opening files are reported when we read them.
+        paths->push_back(path);
+        return true;
+    }
+
+    dir_iter_t dir(str2wcstring(path));
+    if (!dir.valid()) {
+        std::fwprintf(stderr, _(L"Opening \"%s\" failed: %s\n"), path.c_str(),
+                      std::strerror(dir.error()));
+        return false;
+    }
+    std::vector<wcstring> names;
+    while (const auto *entry = dir.next()) {
+        if (string_prefixes_string(L".", entry->name)) continue;
+        if (entry->is_dir() || string_suffixes_string(L".fish", entry->name)) {
+            names.push_back(entry->name);
+        }
+    }
+    std::sort(names.begin(), names.end());
+    std::string prefix = path.back() == '/' ? path : path + "/";
+    for (const wcstring &name : names) {
+        if (!collect_paths(prefix + wcs2string(name), paths)) return false;
+    }
+    return true;
+}
+
+/// A file, or standard input, to format.
+struct indent_job_t {
+    // The path, or empty for standard input.
+    std::string path;
+    wcstring src;
+    pretty_printer_options_t options;
+    // The formatted source.
+    wcstring output;
+};
+
+/// Prettify all \p jobs, on as many threads as we have processors.
+static void prettify_all(std::vector<indent_job_t> &jobs) {
+    std::atomic<size_t> next{0};
+    auto work = [&] {
+        for (size_t i = next++; i < jobs.size(); i = next++) {
+            jobs[i].output = prettify(jobs[i].src, jobs[i].options);
+        }
+    };
+    size_t thread_count = std::min<size_t>(std::thread::hardware_concurrency(), jobs.size());
+    std::vector<std::thread> threads;
+    for (size_t i = 1; i < thread_count; i++) {
+        threads.emplace_back(work);
+    }
+    work();
+    for (std::thread &thread : threads) {
+        thread.join();
+    }
+}
+
+// Ids of long options without a short one. The style options, from opt_indent_width to
+// opt_collapse_begin, change how the output looks and may also be given in the file itself.
+enum {
+    opt_indent_width = 4,
+    opt_tabs,
+    opt_spaces,
+    opt_max_width,
+    opt_collapse_begin,
+    opt_diff,
+};
+
+static const char *short_opts = "+d:hvwicD:";
+static const struct option long_opts[] = {{"debug", required_argument, nullptr, 'd'},
+                                          {"debug-output", required_argument, nullptr, 'o'},
+                                          {"debug-stack-frames", required_argument, nullptr, 'D'},
+                                          {"dump-parse-tree", no_argument, nullptr, 'P'},
+                                          {"no-indent", no_argument, nullptr, 'i'},
+                                          {"indent-width", required_argument, nullptr,
+                                           opt_indent_width},
+                                          {"tabs", no_argument, nullptr, opt_tabs},
+                                          {"spaces", no_argument, nullptr, opt_spaces},
+                                          {"max-width", required_argument, nullptr, opt_max_width},
+                                          {"collapse-begin", no_argument, nullptr,
+                                           opt_collapse_begin},
+                                          {"help", no_argument, nullptr, 'h'},
+                                          {"version", no_argument, nullptr, 'v'},
+                                          {"write", no_argument, nullptr, 'w'},
+                                          {"html", no_argument, nullptr, 1},
+                                          {"ansi", no_argument, nullptr, 2},
+                                          {"pygments", no_argument, nullptr, 3},
+                                          {"check", no_argument, nullptr, 'c'},
+                                          {"diff", no_argument, nullptr, opt_diff},
+                                          {}};
+
+static bool is_style_option(int opt) {
+    return opt == 'i' || (opt >= opt_indent_width && opt <= opt_collapse_begin);
+}
+
+/// Apply the style option \p opt with the argument \p arg to \p options.
+/// \return false and print an error if the argument is invalid.
+static bool apply_style_option(int opt, const wcstring &arg, pretty_printer_options_t *options) {
+    switch (opt) {
+        case 'i': {
+            options->indent = false;
+            return true;
+        }
+        case opt_indent_width:
+        case opt_max_width: {
+            int width = fish_wcstoi(arg.c_str());
+            if (errno || width < (opt == opt_indent_width ? 1 : 0)) {
+                std::fwprintf(stderr, _(L"%ls: Invalid width '%ls'\n"), program_name,
+                              arg.c_str());
+                return false;
+            }
+            if (opt == opt_indent_width) {
+                options->indent_width = width;
+            } else {
+                options->max_line_width = width;
+            }
+            return true;
+        }
+        case opt_tabs:
+        case opt_spaces: {
+            options->use_tabs = opt == opt_tabs;
+            return true;
+        }
+        case opt_collapse_begin: {
+            options->collapse_begin = true;
+            return true;
+        }
+        default: {
+            DIE("unexpected style option");
+        }
+    }
+}
+
+/// Apply the style options given in a "# fish_indent: OPTIONS" line in the comments at the start of
+/// \p src to \p options. OPTIONS are long options as on the command line, like "--tabs" or
+/// "--max-width=100". \return false and print an error if one is invalid.
+static bool read_style_config(const wcstring &src, const char *path,
+                              pretty_printer_options_t *options) {
+    const wcstring marker = L"# fish_indent:";
+    size_t pos = 0;
+    while (pos < src.size() && src.at(pos) == L'#') {
+        size_t eol = std::min(src.find(L'\n', pos), src.size());
+        wcstring line = src.substr(pos, eol - pos);
+        pos = eol + 1;
+        if (!string_prefixes_string(marker, line)) continue;
+
+        for (const wcstring &word : split_string(line.substr(marker.size()), L' ')) {
+            if (word.empty()) continue;
+            size_t eq = word.find(L'=');
+            wcstring name = word.substr(0, eq);
+            wcstring arg = eq == wcstring::npos ? wcstring{} : word.substr(eq + 1);
+            const struct option *opt = nullptr;
+            if (string_prefixes_string(L"--", name)) {
+                for (const struct option *o = long_opts; o->name; o++) {
+                    if (str2wcstring(o->name) == name.substr(2)) opt = o;
+                }
+            }
+            if (!opt || !is_style_option(opt->val) ||
+                (opt->has_arg == required_argument) != (eq != wcstring::npos)) {
+                std::fwprintf(stderr, _(L"%ls: %s: Invalid style option '%ls'\n"), program_name,
+                              path, word.c_str());
+                return false;
+            }
+            if (!apply_style_option(opt->val, arg, options)) return false;
+        }
+    }
+    return true;
+}
+
 int main(int argc, char *argv[]) {
     program_name = L"fish_indent";
     rust_init();
@@ -311,30 +622,23 @@ int main(int argc, char *argv[]) {
         output_type_ansi,
         output_type_pygments_csv,
         output_type_check,
+        output_type_diff,
         output_type_html
     } output_type = output_type_plain_text;
-    const char *output_location = "";
-    bool do_indent = true;
+    // Style options from the command line. These override any given in the files.
+    std::vector<std::pair<int, wcstring>> style_args;
     // File path for debug output.
     std::string debug_output;
 
-    const char *short_opts = "+d:hvwicD:";
-    const struct option long_opts[] = {{"debug", required_argument, nullptr, 'd'},
-                                       {"debug-output", required_argument, nullptr, 'o'},
-                                       {"debug-stack-frames", required_argument, nullptr, 'D'},
-                                       {"dump-parse-tree", no_argument, nullptr, 'P'},
-                                       {"no-indent", no_argument, nullptr, 'i'},
-                                       {"help", no_argument, nullptr, 'h'},
-                                       {"version", no_argument, nullptr, 'v'},
-                                       {"write", no_argument, nullptr, 'w'},
-                                       {"html", no_argument, nullptr, 1},
-                                       {"ansi", no_argument, nullptr, 2},
-                                       {"pygments", no_argument, nullptr, 3},
-                                       {"check", no_argument, nullptr, 'c'},
-                                       {}};
-
     int opt;
     while ((opt = getopt_long(argc, argv, short_opts, long_opts, nullptr)) != -1) {
+        if (is_style_option(opt)) {
+            wcstring arg = optarg ? str2wcstring(optarg) : wcstring{};
+            pretty_printer_options_t scratch;
+            if (!apply_style_option(opt, arg, &scratch)) exit(1);
+            style_args.emplace_back(opt, std::move(arg));
+            continue;
+        }
         switch (opt) {
             case 'P': {
                 dump_parse_tree = true;
@@ -352,10 +656,6 @@ int main(int argc, char *argv[]) {
                 output_type = output_type_file;
                 break;
             }
-            case 'i': {
-                do_indent = false;
-                break;
-            }
             case 1: {
                 output_type = output_type_html;
                 break;
@@ -372,6 +672,10 @@ int main(int argc, char *argv[]) {
                 output_type = output_type_check;
                 break;
             }
+            case opt_diff: {
+                output_type = output_type_diff;
+                break;
+            }
             case 'd': {
                 activate_flog_categories_by_pattern(str2wcstring(optarg));
                 for (auto cat : get_flog_categories()) {
@@ -416,40 +720,60 @@ int main(int argc, char *argv[]) {
 
     int retval = 0;
 
-    wcstring src;
-    for (int i = 0; i < argc || (argc == 0 && i == 0); i++) {
-        if (argc == 0 && i == 0) {
-            if (output_type == output_type_file) {
-                std::fwprintf(
-                    stderr, _(L"Expected file path to read/write for -w:\n\n $ %ls -w foo.fish\n"),
-                    program_name);
-                exit(1);
-            }
-            src = read_file(stdin);
-        } else {
-            FILE *fh = fopen(argv[i], "r");
-            if (fh) {
-                src = read_file(fh);
-                fclose(fh);
-                output_location = argv[i];
-            } else {
-                std::fwprintf(stderr, _(L"Opening \"%s\" failed: %s\n"), argv[i],
-                              std::strerror(errno));
-                exit(1);
-            }
+    // Read everything up front, so we can format in parallel.
+    std::vector<indent_job_t> jobs;
+    if (argc == 0) {
+        if (output_type == output_type_file) {
+            std::fwprintf(stderr,
+                          _(L"Expected file path to read/write for -w:\n\n $ %ls -w foo.fish\n"),
+                          program_name);
+            exit(1);
+        }
+        jobs.emplace_back();
+        jobs.back().src = read_file(stdin);
+    }
+    std::vector<std::string> paths;
+    for (int i = 0; i < argc; i++) {
+        if (!collect_paths(argv[i], &paths)) exit(1);
+    }
+    for (const std::string &path : paths) {
+        FILE *fh = fopen(path.c_str(), "r");
+        if (!fh) {
+            std::fwprintf(stderr, _(L"Opening \"%s\" failed: %s\n"), path.c_str(),
+                          std::strerror(errno));
+            exit(1);
         }
+        jobs.emplace_back();
+        jobs.back().path = path;
+        jobs.back().src = read_file(fh);
+        fclose(fh);
+    }
+
+    bool honor_style_config = output_type == output_type_file ||
+                              output_type == output_type_check || output_type == output_type_diff;
+    for (indent_job_t &job : jobs) {
+        if (honor_style_config && !job.path.empty()) {
+            if (!read_style_config(job.src, job.path.c_str(), &job.options)) exit(1);
+        }
+        for (const auto &arg : style_args) {
+            apply_style_option(arg.first, arg.second, &job.options);
+        }
+    }
+    if (output_type != output_type_pygments_csv) prettify_all(jobs);
 
+    for (const indent_job_t &job : jobs) {
+        const wcstring &src = job.src;
         if (output_type == output_type_pygments_csv) {
             std::string output = make_pygments_csv(src);
             fputs(output.c_str(), stdout);
             continue;
         }
 
-        const wcstring output_wtext = prettify(src, do_indent);
+        const wcstring &output_wtext = job.output;
 
         // Maybe colorize.
         std::vector<highlight_spec_t> colors;
-        if (output_type != output_type_plain_text) {
+        if (output_type == output_type_ansi || output_type == output_type_html) {
             highlight_shell(output_wtext, colors, operation_context_t::globals());
         }
 
@@ -460,12 +784,12 @@ int main(int argc, char *argv[]) {
                 break;
             }
             case output_type_file: {
-                FILE *fh = fopen(output_location, "w");
+                FILE *fh = fopen(job.path.c_str(), "w");
                 if (fh) {
                     std::fputws(output_wtext.c_str(), fh);
                     fclose(fh);
                 } else {
-                    std::fwprintf(stderr, _(L"Opening \"%s\" failed: %s\n"), output_location,
+                    std::fwprintf(stderr, _(L"Opening \"%s\" failed: %s\n"), job.path.c_str(),
                                   std::strerror(errno));
                     exit(1);
                 }
@@ -484,13 +808,26 @@ int main(int argc, char *argv[]) {
             }
             case output_type_check: {
                 if (output_wtext != src) {
-                    if (argc) {
-                        std::fwprintf(stderr, _(L"%s\n"), argv[i]);
+                    if (!job.path.empty()) {
+                        std::fwprintf(stderr, _(L"%s\n"), job.path.c_str());
                     }
                     retval++;
                 }
                 break;
             }
+            case output_type_diff: {
+                if (output_wtext != src) {
+                    // Label the files like git does, so the diff can be applied as a patch.
+                    wcstring path = str2wcstring(job.path);
+                    wcstring diff =
+                        job.path.empty()
+                            ? unified_diff(src, output_wtext, L"-", L"-")
+                            : unified_diff(src, output_wtext, L"a/" + path, L"b/" + path);
+                    std::fputws(diff.c_str(), stdout);
+                    retval++;
+                }
+                break;
+            }
         }
 
         std::fputws(str2wcstring(colored_output).c_str(), stdout);
@@ -4,6 +4,7 @@
 #include "common.h"
 #include "env.h"
 #include "expand.h"
+#include "fallback.h"  // IWYU pragma: keep
 #include "flog.h"
 #include "global_safety.h"
 #include "maybe.h"
@@ -12,29 +13,28 @@
 #include "parse_util.h"
 #include "tokenizer.h"
 #include "wcstringutil.h"
+#include "wutil.h"  // IWYU pragma: keep
 #if INCLUDE_RUST_HEADERS
 #include "fish_indent.rs.h"
 #endif
 
 using namespace ast;
 
-// The number of spaces per indent isn't supposed to be configurable.
-// See discussion at https://github.com/fish-shell/fish-shell/pull/6790
-#define SPACES_PER_INDENT 4
-
 /// \return whether a character at a given index is escaped.
 /// A character is escaped if it has an odd number of backslashes.
 static bool char_is_escaped(const wcstring &text, size_t idx) {
     return count_preceding_backslashes(text, idx) % 2 == 1;
 }
 
-pretty_printer_t::pretty_printer_t(const wcstring &src, bool do_indent)
+pretty_printer_t::pretty_printer_t(const wcstring &src, const pretty_printer_options_t &options)
     : source(src),
-      indents(do_indent ? parse_util_compute_indents(source) : std::vector<int>(src.size(), 0)),
+      indents(options.indent ? parse_util_compute_indents(source)
+                             : std::vector<int>(src.size(), 0)),
       ast(ast_parse(src, parse_flags())),
       visitor(new_pretty_printer(*this)),
-      do_indent(do_indent),
+      options(options),
       gaps(compute_gaps()),
+      collapsed_begin_locations(compute_collapsed_begin_locations()),
       preferred_semi_locations(compute_preferred_semi_locations()) {
     assert(indents.size() == source.size() && "indents and source should be same length");
 }
@@ -157,12 +157,93 @@ std::vector<source_range_t> pretty_printer_t::compute_gaps() const {
     return gaps;
 }
 
-void pretty_printer_t::visit_begin_header() {
+void pretty_printer_t::visit_begin_header(const void *node_) {
+    const auto &node = *static_cast<const begin_header_t *>(node_);
+    if (std::binary_search(collapsed_begin_locations.begin(), collapsed_begin_locations.end(),
+                           node.kw_begin().range().start)) {
+        return;
+    }
     if (!at_line_start()) {
         emit_newline();
     }
 }
 
+maybe_t<size_t> pretty_printer_t::one_line_width(uint32_t start, uint32_t end) const {
+    for (source_range_t comment : ast->extras()->comments()) {
+        if (comment.start < end && comment.end() > start) return none();
+    }
+    wcstring text;
+    for (wchar_t c : source.substr(start, end - start)) {
+        if (c == L'\n') return none();
+        if (c == L' ' || c == L'\t') {
+            if (!text.empty() && text.back() == L' ') continue;
+            c = L' ';
+        }
+        text.push_back(c);
+    }
+    return static_cast<size_t>(std::max(fish_wcswidth(trim(text)), 0));
+}
+
+size_t pretty_printer_t::current_column() const {
+    size_t line_start = output.rfind(L'\n');
+    line_start = line_start == wcstring::npos ? 0 : line_start + 1;
+    size_t width = 0;
+    for (size_t i = line_start; i < output.size(); i++) {
+        width += output[i] == L'\t' ? options.indent_width : std::max(fish_wcwidth(output[i]), 0);
+    }
+    return width;
+}
+
+bool pretty_printer_t::should_split_before(const maybe_newlines_t &node) {
+    if (options.max_line_width == 0) return false;
+
+    // Our parent is a job continuation, after a pipe, or a job conjunction continuation, after a
+    // && or ||. We have already emitted that.
+    auto cont = node.ptr()->parent();
+    const auto *pipe_cont = cont->try_as_job_continuation();
+    uint32_t rest_start = pipe_cont ? pipe_cont->pipe().range().end()
+                                    : cont->try_as_job_conjunction_continuation()
+                                          ->conjunction()
+                                          .range()
+                                          .end();
+
+    // Decide for the whole job conjunction at once, so we either split after all its && and ||,
+    // or after none.
+    auto conj = cont->parent();
+    while (conj->typ() != type_t::job_conjunction) conj = conj->parent();
+    const auto &job = *conj->try_as_job_conjunction();
+    source_range_t conj_range = conj->source_range();
+    uint32_t conj_end = job.has_semi_nl() ? job.semi_nl().range().start : conj_range.end();
+    auto decide = [&](std::map<uint32_t, bool> &decisions, uint32_t start, uint32_t end,
+                      size_t trailer) {
+        auto where = decisions.find(start);
+        if (where != decisions.end()) return where->second;
+        // Leave jobs alone which span several lines already.
+        bool split = false;
+        if (one_line_width(start, end).has_value()) {
+            // The space after the pipe or conjunction, and the rest of the job.
+            size_t rest = *one_line_width(rest_start, end);
+            split = current_column() + 1 + rest + trailer > options.max_line_width;
+        }
+        decisions[start] = split;
+        return split;
+    };
+    if (!decide(split_conjunctions, conj_range.start, conj_end, 0)) return false;
+
+    bool split = true;
+    if (pipe_cont) {
+        // Once the conjunction is split, each pipeline gets its own line; split those that are
+        // still too long after their pipes.
+        auto pipeline = cont->parent()->parent();
+        source_range_t pipeline_range = pipeline->source_range();
+        // Unless this is the last pipeline, its line ends in " &&" or " ||".
+        size_t trailer = pipeline_range.end() < conj_end ? 3 : 0;
+        split = decide(split_pipelines, pipeline_range.start, pipeline_range.end(), trailer);
+    }
+    if (split) continuation_end = std::max(continuation_end, conj_end);
+    return split;
+}
+
 void pretty_printer_t::visit_maybe_newlines(const void *node_) {
     const auto &node = *static_cast<const maybe_newlines_t *>(node_);
     // Our newlines may have comments embedded in them, example:
@@ -170,7 +251,10 @@ void pretty_printer_t::visit_maybe_newlines(const void *node_) {
     //    # something
     //    cmd2
     // Treat it as gap text.
-    if (node.range().length > 0) {
+    if (node.range().length == 0) {
+        // There is no newline in the source, but the job may be too long.
+        if (should_split_before(node)) emit_newline();
+    } else {
         auto flags = gap_text_flags_before_node(*node.ptr());
         current_indent = indents.at(node.range().start);
         bool added_newline = emit_gap_text_before(node.range(), flags);
@@ -242,6 +326,7 @@ void pretty_printer_t::emit_node_text(const void *node_) {
 void pretty_printer_t::emit_text(source_range_t r, gap_flags_t flags) {
     emit_gap_text_before(r, flags);
     current_indent = indents.at(r.start);
+    if (r.start < continuation_end) current_indent++;
     if (r.length > 0) {
         emit_space_or_indent(flags);
         output.append(clean_text(substr(r)));
@@ -396,12 +481,47 @@ bool pretty_printer_t::emit_gap_text(source_range_t range, gap_flags_t flags) {
 
 void pretty_printer_t::emit_space_or_indent(gap_flags_t flags) {
     if (at_line_start()) {
-        output.append(SPACES_PER_INDENT * current_indent, L' ');
+        if (options.use_tabs) {
+            output.append(current_indent, L'\t');
+        } else {
+            output.append(options.indent_width * current_indent, L' ');
+        }
     } else if (!(flags & skip_space) && !has_preceding_space()) {
         output.append(1, L' ');
     }
 }
 
+std::vector<uint32_t> pretty_printer_t::compute_collapsed_begin_locations() const {
+    std::vector<uint32_t> result;
+    if (!options.collapse_begin) return result;
+    for (auto ast_traversal = new_ast_traversal(*ast->top());;) {
+        auto node = ast_traversal->next();
+        if (!node->has_value()) break;
+        const auto *header = node->try_as_begin_header();
+        if (!header) continue;
+        auto block = node->parent();
+        source_range_t range = block->source_range();
+        if (!one_line_width(range.start, range.end()).has_value()) continue;
+
+        // Nested blocks would need to be collapsed too, leave those alone.
+        bool has_nested_block = false;
+        for (auto block_traversal = new_ast_traversal(*block);;) {
+            auto inner = block_traversal->next();
+            if (!inner->has_value()) break;
+            if (inner->pointer_eq(*block)) continue;
+            auto typ = inner->typ();
+            if (typ == type_t::block_statement || typ == type_t::if_statement ||
+                typ == type_t::switch_statement) {
+                has_nested_block = true;
+                break;
+            }
+        }
+        if (!has_nested_block) result.push_back(header->kw_begin().range().start);
+    }
+    std::sort(result.begin(), result.end());
+    return result;
+}
+
 std::vector<uint32_t> pretty_printer_t::compute_preferred_semi_locations() const {
     std::vector<uint32_t> result;
     auto mark_semi_from_input = [&](const semi_nl_t &n) {
@@ -410,6 +530,24 @@ std::vector<uint32_t> pretty_printer_t::compute_preferred_semi_locations() const
         }
     };
 
+    // Collapsed begin blocks keep their semis.
+    for (auto ast_traversal = new_ast_traversal(*ast->top());;) {
+        auto node = ast_traversal->next();
+        if (!node->has_value()) break;
+        const auto *header = node->try_as_begin_header();
+        if (!header || !std::binary_search(collapsed_begin_locations.begin(),
+                                           collapsed_begin_locations.end(),
+                                           header->kw_begin().range().start)) {
+            continue;
+        }
+        if (header->has_semi_nl()) mark_semi_from_input(header->semi_nl());
+        const job_list_t &jobs = node->parent()->try_as_block_statement()->jobs();
+        for (size_t i = 0; i < jobs.count(); i++) {
+            const job_conjunction_t &job = *jobs.at(i);
+            if (job.has_semi_nl()) mark_semi_from_input(job.semi_nl());
+        }
+    }
+
     // andor_job_lists get semis if the input uses semis.
     for (auto ast_traversal = new_ast_traversal(*ast->top());;) {
         auto node = ast_traversal->next();
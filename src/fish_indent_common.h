@@ -1,17 +1,40 @@
 #ifndef FISH_INDENT_STAGING_H
 #define FISH_INDENT_STAGING_H
 
+#include <map>
+
 #include "ast.h"
 #include "common.h"
 #include "cxx.h"
 
 struct PrettyPrinter;
+
+/// Style settings for the pretty printer.
+struct pretty_printer_options_t {
+    // Whether to indent, or just insert spaces.
+    bool indent{true};
+
+    // The number of columns per indentation level.
+    int indent_width{4};
+
+    // Whether to indent with tabs instead of spaces.
+    bool use_tabs{false};
+
+    // If nonzero, jobs which would get longer than this are split after their pipes and
+    // conjunctions.
+    size_t max_line_width{0};
+
+    // Whether to keep `begin` blocks which are written on one line on one line, like
+    // `begin; foo; end`, instead of putting every job on its own line.
+    bool collapse_begin{false};
+};
+
 struct pretty_printer_t {
     // Note: this got somewhat more complicated after introducing the new AST, because that AST no
     // longer encodes detailed lexical information (e.g. every newline). This feels more complex
     // than necessary and would probably benefit from a more layered approach where we identify
     // certain runs, weight line breaks, have a cost model, etc.
-    pretty_printer_t(const wcstring &src, bool do_indent);
+    pretty_printer_t(const wcstring &src, const pretty_printer_options_t &options);
 
     // Original source.
     const wcstring &source;
@@ -31,8 +54,8 @@ struct pretty_printer_t {
     // The indent of the source range which we are currently emitting.
     int current_indent{0};
 
-    // Whether to indent, or just insert spaces.
-    const bool do_indent;
+    // Our style settings.
+    const pretty_printer_options_t options;
 
     // Whether the next gap text should hide the first newline.
     bool gap_text_mask_newline{false};
@@ -42,10 +65,22 @@ struct pretty_printer_t {
     // present in the ast.
     const std::vector<source_range_t> gaps;
 
+    // The sorted set of source offsets of `begin` keywords whose blocks are kept on one line.
+    const std::vector<uint32_t> collapsed_begin_locations;
+
     // The sorted set of source offsets of nl_semi_t which should be set as semis, not newlines.
     // This is computed ahead of time for convenience.
     const std::vector<uint32_t> preferred_semi_locations;
 
+    // Source offsets of job conjunctions and pipelines which we decided to split because they are
+    // too long, mapped to whether we split them.
+    std::map<uint32_t, bool> split_conjunctions;
+    std::map<uint32_t, bool> split_pipelines;
+
+    // Text before this source offset is on a continuation line of a job we split, and gets one more
+    // level of indentation.
+    uint32_t continuation_end{0};
+
     // Flags we support.
     using gap_flags_t = uint32_t;
     enum {
@@ -91,6 +126,22 @@ struct pretty_printer_t {
     // Return sorted list of semi-preferring semi_nl nodes.
     std::vector<uint32_t> compute_preferred_semi_locations() const;
 
+    // Return sorted list of `begin` keywords which we keep on one line with their block.
+    std::vector<uint32_t> compute_collapsed_begin_locations() const;
+
+    // \return the width the source text in [start, end) would have on one line, or none if it has
+    // newlines or comments, which we don't want to move around.
+    maybe_t<size_t> one_line_width(uint32_t start, uint32_t end) const;
+
+    // \return the width of the current line of the output.
+    size_t current_column() const;
+
+#if INCLUDE_RUST_HEADERS
+    // \return whether to break the line after the pipe or conjunction before \p node, because the
+    // job would be too long otherwise.
+    bool should_split_before(const ast::maybe_newlines_t &node);
+#endif
+
     // Emit a space or indent as necessary, depending on the previous output.
     void emit_space_or_indent(gap_flags_t flags = default_flags);
 
@@ -148,7 +199,7 @@ struct pretty_printer_t {
 
     void visit_maybe_newlines(const void *node_);
 
-    void visit_begin_header();
+    void visit_begin_header(const void *node_);
 
     // The flags we use to parse.
     static parse_tree_flags_t parse_flags() {
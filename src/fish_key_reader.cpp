@@ -16,6 +16,7 @@
 
 #include <cstring>
 #include <cwchar>
+#include <cwctype>
 #include <string>
 #include <vector>
 
@@ -28,6 +29,7 @@
 #include "fish_version.h"
 #include "input.h"
 #include "input_common.h"
+#include "json.rs.h"
 #include "maybe.h"
 #include "parser.h"
 #include "print_help.h"
@@ -38,6 +40,35 @@
 
 struct config_paths_t determine_config_directory_paths(const char *argv0);
 
+enum class output_format_t { text, json };
+
+struct key_reader_options_t {
+    /// Whether to keep reading after the first key sequence, until the user asks us to exit.
+    bool continuous_mode{false};
+    /// Whether to keep reading after the first key sequence, until the end of input.
+    bool stream_mode{false};
+    bool verbose{false};
+    output_format_t format{output_format_t::text};
+};
+
+/// A key sequence as it was received, for the JSON output.
+struct key_event_t {
+    /// The characters of the sequence.
+    wcstring chars;
+    /// For each character, the milliseconds since the previous character, or -1 if there was none.
+    std::vector<double> delays_ms;
+    /// When the first character was received.
+    double timestamp{0.0};
+    /// The terminfo name of the sequence, if any.
+    maybe_t<wcstring> terminfo_name;
+
+    void clear() {
+        chars.clear();
+        delays_ms.clear();
+        terminfo_name.reset();
+    }
+};
+
 static const wchar_t *ctrl_symbolic_names[] = {
     nullptr, nullptr, nullptr, nullptr, nullptr,  nullptr, nullptr, nullptr,
     L"\\b",  L"\\t",  L"\\n",  nullptr, nullptr,  L"\\r",  nullptr, nullptr,
@@ -197,14 +228,214 @@ static void output_info_about_char(wchar_t wc) {
     std::fwprintf(stderr, L"hex: %4X  char: %ls\n", wc, char_to_symbol(wc, false));
 }
 
-static bool output_matching_key_name(wchar_t wc) {
+static bool output_matching_key_name(wchar_t wc, key_event_t *event, output_format_t format) {
     if (maybe_t<wcstring> name = sequence_name(wc)) {
-        std::fwprintf(stdout, L"bind -k %ls 'do something'\n", name->c_str());
+        if (format == output_format_t::text) {
+            std::fwprintf(stdout, L"bind -k %ls 'do something'\n", name->c_str());
+        }
+        event->terminfo_name = name.acquire();
         return true;
     }
     return false;
 }
 
+/// Modifier bits as used in the parameters of CSI sequences, minus one.
+enum { key_mod_shift = 1, key_mod_alt = 2, key_mod_ctrl = 4, key_mod_super = 8 };
+
+/// Return the name of a single key character, adding any implied modifiers to \p mods.
+static wcstring single_key_name(wchar_t wc, int *mods) {
+    switch (wc) {
+        case L'\t':
+            return L"tab";
+        case L'\r':
+            return L"enter";
+        case L'\x1B':
+            return L"escape";
+        case L' ':
+            return L"space";
+        case L'\x7F':
+            return L"backspace";
+        default:
+            break;
+    }
+    if (wc < L' ') {
+        *mods |= key_mod_ctrl;
+        return wcstring(1, static_cast<wchar_t>(std::towlower(wc + 0x40)));
+    }
+    return wcstring(1, wc);
+}
+
+/// Return the name of the key for the final character of a CSI or SS3 sequence, and its first
+/// parameter, or none if it is not one we know.
+static maybe_t<wcstring> csi_key_name(wchar_t final, long param) {
+    switch (final) {
+        case L'A':
+            return wcstring(L"up");
+        case L'B':
+            return wcstring(L"down");
+        case L'C':
+            return wcstring(L"right");
+        case L'D':
+            return wcstring(L"left");
+        case L'H':
+            return wcstring(L"home");
+        case L'F':
+            return wcstring(L"end");
+        case L'P':
+        case L'Q':
+        case L'R':
+        case L'S':
+            return format_string(L"f%d", final - L'P' + 1);
+        case L'~':
+            break;
+        default:
+            return none();
+    }
+    switch (param) {
+        case 1:
+        case 7:
+            return wcstring(L"home");
+        case 2:
+            return wcstring(L"insert");
+        case 3:
+            return wcstring(L"delete");
+        case 4:
+        case 8:
+            return wcstring(L"end");
+        case 5:
+            return wcstring(L"pageup");
+        case 6:
+            return wcstring(L"pagedown");
+        default:
+            break;
+    }
+    // F5 through F12 skip some numbers.
+    static const long fkey_params[] = {15, 17, 18, 19, 20, 21, 23, 24};
+    for (size_t i = 0; i < sizeof fkey_params / sizeof *fkey_params; i++) {
+        if (fkey_params[i] == param) return format_string(L"f%lu", i + 5);
+    }
+    return none();
+}
+
+/// Decode the key and modifiers a sequence most likely stands for. This understands single
+/// characters, escape-prefixed ones for alt, and the common CSI and SS3 sequences including their
+/// modifier parameter and the "CSI u" encoding. Return none if we don't know the sequence.
+static maybe_t<wcstring> decode_key(const wcstring &seq, int *mods) {
+    *mods = 0;
+    if (seq.size() == 1) return single_key_name(seq[0], mods);
+    if (seq[0] != L'\x1B') return none();
+    if (seq.size() == 2) {
+        *mods |= key_mod_alt;
+        return single_key_name(seq[1], mods);
+    }
+
+    bool ss3 = seq[1] == L'O';
+    if (seq[1] != L'[' && !ss3) return none();
+    std::vector<long> params;
+    long param = -1;
+    size_t i = 2;
+    for (; i + 1 < seq.size(); i++) {
+        wchar_t c = seq[i];
+        if (c >= L'0' && c <= L'9') {
+            param = std::max(param, 0L) * 10 + (c - L'0');
+        } else if (c == L';' && !ss3) {
+            params.push_back(param);
+            param = -1;
+        } else {
+            return none();
+        }
+    }
+    params.push_back(param);
+    wchar_t final = seq[i];
+    if (params.size() > 1 && params[1] > 1) *mods |= static_cast<int>((params[1] - 1) & 0xF);
+
+    if (final == L'Z' && !ss3) {
+        *mods |= key_mod_shift;
+        return wcstring(L"tab");
+    }
+    if (final == L'u' && !ss3 && params[0] > 0) {
+        return single_key_name(static_cast<wchar_t>(params[0]), mods);
+    }
+    return csi_key_name(final, params[0]);
+}
+
+static void output_json_event(key_event_t *event) {
+    if (event->chars.empty()) return;
+    auto w = new_json_writer();
+    w->begin_object();
+
+    w->key(L"sequence");
+    wcstring symbols;
+    for (wchar_t wc : event->chars) {
+        symbols.append(char_to_symbol(wc, true));
+    }
+    w->string(symbols);
+
+    w->key(L"bytes");
+    w->begin_array();
+    for (char byte : wcs2string(event->chars)) {
+        w->integer(static_cast<unsigned char>(byte));
+    }
+    w->end_array();
+
+    int mods = 0;
+    maybe_t<wcstring> key = decode_key(event->chars, &mods);
+    w->key(L"key");
+    if (key) {
+        w->string(*key);
+    } else {
+        w->null();
+    }
+    w->key(L"modifiers");
+    w->begin_array();
+    const std::pair<int, const wchar_t *> mod_names[] = {{key_mod_ctrl, L"ctrl"},
+                                                         {key_mod_alt, L"alt"},
+                                                         {key_mod_shift, L"shift"},
+                                                         {key_mod_super, L"super"}};
+    for (const auto &mod : mod_names) {
+        if (mods & mod.first) w->string(mod.second);
+    }
+    w->end_array();
+
+    w->key(L"terminfo");
+    if (event->terminfo_name) {
+        w->string(*event->terminfo_name);
+    } else {
+        w->null();
+    }
+
+    w->key(L"time");
+    w->decimal(event->timestamp, 6);
+    w->key(L"delays_ms");
+    w->begin_array();
+    for (double delay : event->delays_ms) {
+        if (delay < 0) {
+            w->null();
+        } else {
+            w->decimal(delay, 3);
+        }
+    }
+    w->end_array();
+
+    w->end_object();
+    std::fwprintf(stdout, L"%ls\n", w->contents()->c_str());
+    // Tools read this as we go, and stdout may be a pipe.
+    std::fflush(stdout);
+    event->clear();
+}
+
+/// Output the key sequence received so far, if any.
+static void output_key_sequence(std::vector<wchar_t> &bind_chars, key_event_t *event,
+                                output_format_t format) {
+    if (format == output_format_t::json) {
+        output_json_event(event);
+        bind_chars.clear();
+    } else {
+        output_bind_command(bind_chars);
+        event->clear();
+    }
+}
+
 static double output_elapsed_time(double prev_tstamp, bool first_char_seen, bool verbose) {
     // How much time has passed since the previous char was received in microseconds.
     double now = timef();
@@ -223,11 +454,13 @@ static double output_elapsed_time(double prev_tstamp, bool first_char_seen, bool
 }
 
 /// Process the characters we receive as the user presses keys.
-static void process_input(bool continuous_mode, bool verbose) {
+static void process_input(const key_reader_options_t &opts) {
+    bool keep_reading = opts.continuous_mode || opts.stream_mode;
     bool first_char_seen = false;
     double prev_tstamp = 0.0;
     input_event_queue_t queue;
     std::vector<wchar_t> bind_chars;
+    key_event_t event;
 
     std::fwprintf(stderr, L"Press a key:\n");
     while (!check_exit_loop_maybe_warning(nullptr)) {
@@ -237,30 +470,38 @@ static void process_input(bool continuous_mode, bool verbose) {
         } else {
             evt = queue.readch_timed();
         }
+        if (evt && evt->is_eof()) {
+            output_key_sequence(bind_chars, &event, opts.format);
+            return;
+        }
         if (!evt || !evt->is_char()) {
-            output_bind_command(bind_chars);
-            if (first_char_seen && !continuous_mode) {
+            output_key_sequence(bind_chars, &event, opts.format);
+            if (first_char_seen && !keep_reading) {
                 return;
             }
             continue;
         }
 
         wchar_t wc = evt->get_char();
-        prev_tstamp = output_elapsed_time(prev_tstamp, first_char_seen, verbose);
+        double tstamp = output_elapsed_time(prev_tstamp, first_char_seen, opts.verbose);
+        if (event.chars.empty()) event.timestamp = tstamp;
+        event.chars.push_back(wc);
+        event.delays_ms.push_back(first_char_seen ? 1000 * (tstamp - prev_tstamp) : -1);
+        prev_tstamp = tstamp;
         // Hack for #3189. Do not suggest \c@ as the binding for nul, because a string containing
         // nul cannot be passed to builtin_bind since it uses C strings. We'll output the name of
         // this key (nul) elsewhere.
         if (wc) {
             add_char_to_bind_command(wc, bind_chars);
         }
-        if (verbose) {
+        if (opts.verbose) {
             output_info_about_char(wc);
         }
-        if (output_matching_key_name(wc)) {
-            output_bind_command(bind_chars);
+        if (output_matching_key_name(wc, &event, opts.format)) {
+            output_key_sequence(bind_chars, &event, opts.format);
         }
 
-        if (continuous_mode && should_exit(wc)) {
+        if (opts.continuous_mode && !opts.stream_mode && should_exit(wc)) {
             std::fwprintf(stderr, L"\nExiting at your request.\n");
             break;
         }
@@ -270,7 +511,7 @@ static void process_input(bool continuous_mode, bool verbose) {
 }
 
 /// Setup our environment (e.g., tty modes), process key strokes, then reset the environment.
-[[noreturn]] static void setup_and_process_keys(bool continuous_mode, bool verbose) {
+[[noreturn]] static void setup_and_process_keys(const key_reader_options_t &opts) {
     set_interactive_session(true);
     rust_init();
     env_init();
@@ -282,7 +523,7 @@ static void process_input(bool continuous_mode, bool verbose) {
     // in fish-proper this is done once a command is run.
     tcsetattr(STDIN_FILENO, TCSANOW, &shell_modes);
 
-    if (continuous_mode) {
+    if (opts.continuous_mode && !opts.stream_mode) {
         std::fwprintf(stderr, L"\n");
         std::fwprintf(stderr,
                       L"To terminate this program type \"exit\" or \"quit\" in this window,\n");
@@ -291,14 +532,16 @@ static void process_input(bool continuous_mode, bool verbose) {
         std::fwprintf(stderr, L"\n");
     }
 
-    process_input(continuous_mode, verbose);
+    process_input(opts);
     restore_term_mode();
     _exit(0);
 }
 
-static bool parse_flags(int argc, char **argv, bool *continuous_mode, bool *verbose) {
+static bool parse_flags(int argc, char **argv, key_reader_options_t *opts) {
     const char *short_opts = "+chvV";
     const struct option long_opts[] = {{"continuous", no_argument, nullptr, 'c'},
+                                       {"stream", no_argument, nullptr, 's'},
+                                       {"format", required_argument, nullptr, 'f'},
                                        {"help", no_argument, nullptr, 'h'},
                                        {"version", no_argument, nullptr, 'v'},
                                        {"verbose", no_argument, nullptr, 'V'},
@@ -308,7 +551,23 @@ static bool parse_flags(int argc, char **argv, bool *continuous_mode, bool *verb
     while (!error && (opt = getopt_long(argc, argv, short_opts, long_opts, nullptr)) != -1) {
         switch (opt) {
             case 'c': {
-                *continuous_mode = true;
+                opts->continuous_mode = true;
+                break;
+            }
+            case 's': {
+                opts->stream_mode = true;
+                break;
+            }
+            case 'f': {
+                if (!std::strcmp(optarg, "text")) {
+                    opts->format = output_format_t::text;
+                } else if (!std::strcmp(optarg, "json")) {
+                    opts->format = output_format_t::json;
+                } else {
+                    std::fwprintf(stderr, L"Invalid format '%s'. Expected 'text' or 'json'\n",
+                                  optarg);
+                    error = true;
+                }
                 break;
             }
             case 'h': {
@@ -320,7 +579,7 @@ static bool parse_flags(int argc, char **argv, bool *continuous_mode, bool *verb
                 exit(0);
             }
             case 'V': {
-                *verbose = true;
+                opts->verbose = true;
                 break;
             }
             default: {
@@ -344,17 +603,16 @@ static bool parse_flags(int argc, char **argv, bool *continuous_mode, bool *verb
 
 int main(int argc, char **argv) {
     program_name = L"fish_key_reader";
-    bool continuous_mode = false;
-    bool verbose = false;
+    key_reader_options_t opts;
 
-    if (!parse_flags(argc, argv, &continuous_mode, &verbose)) return 1;
+    if (!parse_flags(argc, argv, &opts)) return 1;
 
     if (!isatty(STDIN_FILENO)) {
         std::fwprintf(stderr, L"Stdin must be attached to a tty.\n");
         return 1;
     }
 
-    setup_and_process_keys(continuous_mode, verbose);
+    setup_and_process_keys(opts);
     exit_without_destructors(0);
     return EXIT_FAILURE;  // above should exit
 }
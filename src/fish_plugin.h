@@ -0,0 +1,89 @@
+// The C interface for native fish plugins.
+//
+// A native plugin is a shared library loaded with `plugin load /path/to/libfoo.so`. It must
+// export:
+//
+//     uint32_t fish_plugin_abi_version(void);
+//         Return FISH_PLUGIN_ABI_VERSION. fish refuses to load plugins built against another
+//         version of this header.
+//
+//     int fish_plugin_init(const struct fish_plugin_api *api, fish_plugin_host *host,
+//                          fish_call *call);
+//         Register builtins and event handlers through `api` with `host`. Return 0 on success;
+//         any other status makes the load fail. `call` may be used to read and set variables and
+//         to write output, like in a builtin.
+//
+// and may export:
+//
+//     void fish_plugin_unload(void);
+//         Called before the library is closed by `plugin unload`.
+//
+// Native plugins run inside fish without any sandboxing. Everything passed to a plugin is only
+// valid until the function it was passed to returns, except for `api`, which stays valid as long
+// as the plugin is loaded.
+#ifndef FISH_PLUGIN_H
+#define FISH_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define FISH_PLUGIN_ABI_VERSION 1
+
+/// The plugin being initialized, which things are registered with.
+typedef struct fish_plugin_host fish_plugin_host;
+
+/// A call into the plugin, which variables and output go through.
+typedef struct fish_call fish_call;
+
+/// A builtin. argv[0] is the name of the builtin, and argv[argc] is NULL. Returns the status.
+typedef int (*fish_builtin_fn)(fish_call *call, void *data, size_t argc, const char *const *argv);
+
+/// An event handler. argv[0] is the name the handler was registered for, followed by the arguments
+/// a fish function handling the event would get in $argv.
+typedef void (*fish_event_fn)(fish_call *call, void *data, size_t argc, const char *const *argv);
+
+/// The kinds of events a handler can be registered for, like the options of `function`.
+enum fish_event_kind {
+    FISH_EVENT_GENERIC = 0,  // --on-event, for events emitted with `emit`
+    FISH_EVENT_VARIABLE = 1, // --on-variable
+    FISH_EVENT_SIGNAL = 2,   // --on-signal
+};
+
+/// The functions fish provides to plugins. Strings are UTF-8 and NUL-terminated unless they come
+/// with a length. Lists of strings are passed as each string followed by a NUL byte.
+struct fish_plugin_api {
+    uint32_t abi_version;
+
+    /// Register a builtin, passing `data` to it when called. Returns 0 on success, or -1 if the
+    /// name is empty or the plugin is not being initialized.
+    int (*register_builtin)(fish_plugin_host *host, const char *name, fish_builtin_fn fn,
+                            void *data);
+
+    /// Register a handler for the event of `kind` called `name`. Returns 0 on success, or -1 if
+    /// the kind is unknown, the name is empty or the plugin is not being initialized.
+    int (*register_event_handler)(fish_plugin_host *host, int kind, const char *name,
+                                  fish_event_fn fn, void *data);
+
+    /// Copy the values of a variable to `buf`, as a list. Returns the length of the list, which
+    /// may be larger than `len`, or -1 if the variable is unset.
+    ptrdiff_t (*get_var)(fish_call *call, const char *name, char *buf, size_t len);
+
+    /// Set a variable to the list of `len` bytes at `values`, like `set` without a scope. The
+    /// variable is set when the call returns, but get_var sees the new value immediately. Returns
+    /// 0 on success, or -1 if the name is empty.
+    int (*set_var)(fish_call *call, const char *name, const char *values, size_t len);
+
+    /// Write to the standard output or error of the builtin.
+    void (*write_out)(fish_call *call, const char *s, size_t len);
+    void (*write_err)(fish_call *call, const char *s, size_t len);
+};
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,70 @@
+// fish_test_plugin is a native plugin with no fish dependencies beyond fish_plugin.h, which the
+// tests load to check that plugins can provide builtins and event handlers, and read and set
+// variables.
+
+#include <algorithm>
+#include <cstring>
+#include <string>
+
+#include "fish_plugin.h"
+
+static const struct fish_plugin_api *fish;
+
+static void write_out(fish_call *call, const std::string &s) {
+    fish->write_out(call, s.data(), s.size());
+}
+
+/// plugin_greet NAME ...: greet each NAME with $greeting, and set $plugin_greeted to the names.
+static int greet(fish_call *call, void *data, size_t argc, const char *const *argv) {
+    (void)data;
+    if (argc < 2) {
+        const char *msg = "plugin_greet: expected a name\n";
+        fish->write_err(call, msg, std::strlen(msg));
+        return 2;
+    }
+    // Only the first value of $greeting is used.
+    char buf[64];
+    ptrdiff_t len = fish->get_var(call, "greeting", buf, sizeof buf);
+    std::string greeting = len < 0 ? "Hello" : std::string(buf, std::min<size_t>(len, sizeof buf));
+    greeting = greeting.substr(0, greeting.find('\0'));
+
+    std::string names;
+    for (size_t i = 1; i < argc; i++) {
+        write_out(call, greeting + ", " + argv[i] + "!\n");
+        names.append(argv[i]);
+        names.push_back('\0');
+    }
+    return fish->set_var(call, "plugin_greeted", names.data(), names.size()) == 0 ? 0 : 1;
+}
+
+/// Print the event name and arguments a handler got.
+static void print_event(fish_call *call, void *data, size_t argc, const char *const *argv) {
+    std::string line = static_cast<const char *>(data);
+    for (size_t i = 0; i < argc; i++) {
+        line.push_back(' ');
+        line.append(argv[i]);
+    }
+    write_out(call, line + "\n");
+}
+
+extern "C" {
+
+uint32_t fish_plugin_abi_version(void) { return FISH_PLUGIN_ABI_VERSION; }
+
+int fish_plugin_init(const struct fish_plugin_api *api, fish_plugin_host *host, fish_call *call) {
+    fish = api;
+    if (fish->register_builtin(host, "plugin_greet", greet, nullptr) != 0 ||
+        fish->register_event_handler(host, FISH_EVENT_GENERIC, "plugin_test_event", print_event,
+                                     const_cast<char *>("event:")) != 0 ||
+        fish->register_event_handler(host, FISH_EVENT_VARIABLE, "plugin_watched", print_event,
+                                     const_cast<char *>("variable:")) != 0) {
+        return 1;
+    }
+    // Registering an empty name fails.
+    if (fish->register_builtin(host, "", greet, nullptr) != -1) return 1;
+    write_out(call, "fish_test_plugin loaded\n");
+    return 0;
+}
+
+void fish_plugin_unload(void) { fish = nullptr; }
+}
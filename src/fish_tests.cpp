@@ -669,6 +669,25 @@ static void test_tokenizer() {
         }
     }
 
+    // &--queue is a single background token, but only if followed by a separator.
+    {
+        auto t = new_tokenizer(L"cmd &--queue; cmd &--queued", 0);
+        auto token = t->next();  // cmd
+        token = t->next();
+        do_test(token);
+        do_test(token->type_ == token_type_t::background);
+        do_test(*t->text_of(*token) == L"&--queue");
+        token = t->next();
+        do_test(token && token->type_ == token_type_t::end);
+        token = t->next();  // cmd
+        token = t->next();
+        do_test(token);
+        do_test(token->type_ == token_type_t::background);
+        do_test(token->length == 1);
+        token = t->next();
+        do_test(token && *t->text_of(*token) == L"--queued");
+    }
+
     // Test some errors.
     {
         auto t = new_tokenizer(L"abc\\", 0);
@@ -806,7 +825,7 @@ static void test_debounce() {
     say(L"Testing debounce");
     // Run 8 functions using a condition variable.
     // Only the first and last should run.
-    auto db = new_debounce_t(0);
+    auto db = new_debounce_t(0, iothread_priority_t::user_blocking);
     constexpr size_t count = 8;
     std::array<bool, count> handler_ran = {};
     std::array<bool, count> completion_ran = {};
@@ -856,6 +875,23 @@ static void test_debounce() {
     do_test(total_ran <= 2);
 }
 
+static void test_cancel_token() {
+    say(L"Testing cancel tokens");
+    cancel_token_t token;
+    cancel_token_t copy = token;
+    cancel_checker_t checker = copy.checker();
+    operation_context_t ctx{nullptr, env_stack_t::globals(), token.checker()};
+    do_test(!token.cancelled() && !copy.cancelled() && !checker() && !ctx.check_cancel());
+
+    // Cancelling any copy cancels them all, also from another thread.
+    std::thread([=] { copy.cancel(); }).join();
+    do_test(token.cancelled() && checker() && ctx.check_cancel());
+
+    // A new token is independent.
+    token = cancel_token_t{};
+    do_test(!token.cancelled() && checker());
+}
+
 static void test_debounce_timeout() {
     using namespace std::chrono;
     say(L"Testing debounce timeout");
@@ -864,7 +900,7 @@ static void test_debounce_timeout() {
     // Use a shared_ptr so we don't have to join our threads.
     const long timeout_ms = 500;
     struct data_t {
-        rust::box<debounce_t> db = new_debounce_t(timeout_ms);
+        rust::box<debounce_t> db = new_debounce_t(timeout_ms, iothread_priority_t::user_blocking);
         bool exit_ok = false;
         std::mutex m;
         std::condition_variable cv;
@@ -884,9 +920,10 @@ static void test_debounce_timeout() {
     uint64_t token2 = debounce_perform(*data->db, handler);
     do_test(token1 == token2);
 
-    // Wait 75 msec, then enqueue something else; this should spawn a new thread.
+    // Once the timeout passes, the second request gets a new thread, without waiting for another
+    // request to be enqueued.
     std::this_thread::sleep_for(std::chrono::milliseconds(timeout_ms + timeout_ms / 2));
-    do_test(data->running == 1);
+    do_test(data->running == 2);
     uint64_t token3 = debounce_perform(*data->db, handler);
     do_test(token3 > token2);
 
@@ -1339,6 +1376,50 @@ static void test_utility_functions() {
     test_is_sorted_by_name();
 }
 
+static void test_fuzzy_subsequence_match() {
+    say(L"Testing scored subsequence matching");
+    using contain_type_t = string_fuzzy_match_t::contain_type_t;
+
+    // The shortest stretch is chosen.
+    do_test(subsequence_match_positions(L"gcm", L"git-commit-message.sh", false) ==
+            std::vector<size_t>({0, 4, 6}));
+    do_test(subsequence_match_positions(L"ab", L"a-xab", false) == std::vector<size_t>({3, 4}));
+    do_test(subsequence_match_positions(L"GCM", L"git-commit-message.sh", true) ==
+            std::vector<size_t>({0, 4, 6}));
+    do_test(subsequence_match_positions(L"gcm", L"GitCommit", false).empty());
+    do_test(subsequence_match_positions(L"xyz", L"git-commit-message.sh", true).empty());
+
+    // Only scored matches ignore case.
+    do_test(!string_fuzzy_match_string(L"gcm", L"Git-Commit-Message.sh"));
+    auto match = string_fuzzy_match_string(L"gcm", L"Git-Commit-Message.sh", false, true);
+    do_test(match && match->type == contain_type_t::subseq);
+    do_test(match && match->case_fold == string_fuzzy_match_t::case_fold_t::smartcase);
+
+    // Matching the starts of words scores higher than matching in the middle of them.
+    auto word_starts = string_fuzzy_match_string(L"gcm", L"git-commit-message.sh", false, true);
+    auto scattered = string_fuzzy_match_string(L"gcm", L"agcxxmb", false, true);
+    do_test(word_starts && scattered && word_starts->subseq_score > scattered->subseq_score);
+
+    // Prefix matches are found before subsequence matches.
+    auto prefix = string_fuzzy_match_string(L"gi", L"git-commit-message.sh", false, true);
+    do_test(prefix && prefix->type == contain_type_t::prefix);
+    do_test(prefix && word_starts && prefix->rank() < word_starts->rank());
+}
+
+static void test_fuzzy_match_positions() {
+    say(L"Testing fuzzy match positions");
+    auto positions = [](const wchar_t *needle, const wchar_t *haystack) {
+        auto match = string_fuzzy_match_string(needle, haystack, false, true);
+        return match ? match->positions(needle, haystack) : std::vector<size_t>{};
+    };
+    do_test(positions(L"com", L"commit") == std::vector<size_t>({0, 1, 2}));
+    do_test(positions(L"COM", L"commit") == std::vector<size_t>({0, 1, 2}));
+    do_test(positions(L"mit", L"commit") == std::vector<size_t>({3, 4, 5}));
+    do_test(positions(L"MI", L"commit") == std::vector<size_t>({3, 4}));
+    do_test(positions(L"cmt", L"commit") == std::vector<size_t>({0, 2, 5}));
+    do_test(positions(L"commit", L"commit") == std::vector<size_t>({0, 1, 2, 3, 4, 5}));
+}
+
 // UTF8 tests taken from Alexey Vatchenko's utf8 library. See http://www.bsdua.org/libbsdua.html.
 static void test_utf82wchar(const char *src, size_t slen, const wchar_t *dst, size_t dlen,
                             int flags, size_t res, const char *descr) {
@@ -2033,6 +2114,31 @@ static void test_abbreviations() {
         err(L"command yin incorrectly expanded on line %ld to '%ls'", (long)__LINE__,
             result->c_str());
     }
+
+    // Functions see the command line, with the cursor at the end of the token.
+    parser_t::principal_parser().eval(
+        L"function abbr_context; echo (commandline -opc | string join ,) (commandline -C); end;"
+        L"abbr -a ctx --position anywhere --function abbr_context",
+        io_chain_t());
+    result = expand_abbreviation_in_command(L"sudo ctx foo", const_strlen(L"sudo c"));
+    if (result != L"sudo sudo 8 foo") {
+        err(L"ctx incorrectly expanded on line %ld to '%ls'", (long)__LINE__,
+            result ? result->c_str() : L"nothing");
+    }
+    if (commandline_get_state().text == L"sudo ctx foo") {
+        err(L"Command line state was not restored after expanding an abbreviation");
+    }
+
+    // Abbreviations whose condition fails are skipped in favor of earlier ones.
+    parser_t::principal_parser().eval(
+        L"abbr -a cnd_yes --regex 'cnd' --condition 'test 1 = 1' yes;"
+        L"abbr -a cnd_no --regex 'cnd' --condition 'test 1 = 2' no",
+        io_chain_t());
+    result = expand_abbreviation_in_command(L"cnd");
+    if (result != L"yes") {
+        err(L"cnd incorrectly expanded on line %ld to '%ls'", (long)__LINE__,
+            result ? result->c_str() : L"nothing");
+    }
 }
 
 static void test_pager_navigation() {
@@ -2128,6 +2234,61 @@ static void test_pager_navigation() {
     }
 }
 
+static void test_pager_groups() {
+    say(L"Testing pager groups");
+
+    completion_list_t completions;
+    for (const wchar_t *name : {L"a1", L"a2", L"a3", L"b1", L"b2"}) {
+        append_completion(&completions, name);
+        completions.back().group = name[0] == L'a' ? L"A" : L"B";
+    }
+
+    pager_t pager;
+    pager.set_completions(completions);
+    pager.set_term_size(termsize_default());
+    page_rendering_t render = pager.render();
+
+    // Each group gets a header, and its completions below it.
+    do_test(render.cols == 5);
+    do_test(render.rows == 4);
+    auto line_text = [&](size_t idx) {
+        wcstring text;
+        for (const auto &p : render.screen_data.line(idx).text) text.push_back(p.character);
+        return text;
+    };
+    do_test(render.screen_data.line_count() == 4);
+    do_test(line_text(0) == L"A");
+    do_test(line_text(2) == L"B");
+
+    const struct {
+        selection_motion_t dir;
+        size_t sel;
+    } cmds[] = {
+        {selection_motion_t::next, 0},
+        {selection_motion_t::east, 1},
+        {selection_motion_t::east, 2},
+        // Going east past the end of a row goes to the next one, skipping the header.
+        {selection_motion_t::east, 3},
+        {selection_motion_t::south, 0},
+        {selection_motion_t::west, 4},
+        {selection_motion_t::north, 1},
+        {selection_motion_t::south, 4},
+        // A column that is short in the next group goes to the last completion before it.
+        {selection_motion_t::north, 1},
+        {selection_motion_t::east, 2},
+        {selection_motion_t::south, 4},
+        {selection_motion_t::next, 0},
+    };
+    for (size_t i = 0; i < sizeof cmds / sizeof *cmds; i++) {
+        pager.select_next_completion_in_direction(cmds[i].dir, render);
+        pager.update_rendering(&render);
+        if (cmds[i].sel != render.selected_completion_idx) {
+            err(L"For command %lu, expected selection %lu, but found instead %lu\n", i, cmds[i].sel,
+                render.selected_completion_idx);
+        }
+    }
+}
+
 struct pager_layout_testcase_t {
     int width;
     const wchar_t *expected;
@@ -3194,6 +3355,56 @@ static void test_input() {
     } else if (evt.get_readline() != readline_cmd_t::down_line) {
         err(L"Expected to read char down_line");
     }
+
+    // A chord with a timeout matches when its keys are already available.
+    input_mappings()->add(L"zk", L"up-line", DEFAULT_BIND_MODE, DEFAULT_BIND_MODE, true, 50);
+    input.queue_char(L'z');
+    input.queue_char(L'k');
+    evt = input.read_char();
+    if (!evt.is_readline() || evt.get_readline() != readline_cmd_t::up_line) {
+        err(L"Expected the zk chord to read up_line");
+    }
+
+    // A mode which inherits from another uses the bindings of that mode.
+    do_test(input_mappings()->define_mode(L"test_child", DEFAULT_BIND_MODE));
+    do_test(!input_mappings()->define_mode(DEFAULT_BIND_MODE, L"test_child"));
+    auto &vars = parser_t::principal_parser().vars();
+    vars.set_one(FISH_BIND_MODE_VAR, ENV_GLOBAL, L"test_child");
+    input.queue_char(L'z');
+    input.queue_char(L'k');
+    evt = input.read_char();
+    if (!evt.is_readline() || evt.get_readline() != readline_cmd_t::up_line) {
+        err(L"Expected the zk chord to be inherited from the default mode");
+    }
+    vars.set_one(FISH_BIND_MODE_VAR, ENV_GLOBAL, DEFAULT_BIND_MODE);
+    do_test(input_mappings()->erase_mode(L"test_child"));
+
+    // Recording a macro records the keys of the bindings that ran.
+    input_mappings()->add(L"", L"self-insert");
+    do_test(input_mappings()->start_recording());
+    do_test(!input_mappings()->start_recording());
+    for (wchar_t c : wcstring{L"zkx"}) {
+        input.queue_char(c);
+    }
+    evt = input.read_char();
+    do_test(evt.is_readline() && evt.get_readline() == readline_cmd_t::up_line);
+    evt = input.read_char();
+    do_test(evt.is_char() && evt.get_char() == L'x');
+    do_test(input_mappings()->stop_recording(L"test_macro"));
+    do_test(!input_mappings()->stop_recording(L"test_macro"));
+    do_test(input_mappings()->get_macro(L"test_macro") == wcstring(L"zkx"));
+    input_mappings()->erase(L"");
+
+    // Bracketed paste is read as one event, without running the text through the bindings.
+    for (wchar_t c : wcstring{L"\x1B[200~qqqqqqqaa\recho\x1B[201~"}) {
+        input.queue_char(c);
+    }
+    evt = input.read_char();
+    if (!evt.is_paste()) {
+        err(L"Event is not a paste");
+    } else if (evt.get_paste() != L"qqqqqqqaa\recho") {
+        err(L"Unexpected pasted text '%ls'", evt.get_paste().c_str());
+    }
 }
 
 static void test_undo() {
@@ -3248,6 +3459,29 @@ static void test_undo() {
     do_test(line.text() == L"abcde");
     line.undo();
     do_test(line.text() == L"abc");
+
+    say(L"Testing undoing coalesced deletions and words.");
+    reader_change_undo_coalesce(undo_coalesce_insert | undo_coalesce_words | undo_coalesce_delete);
+    line.clear();
+    for (wchar_t c : wcstring{L"ab cd"}) {
+        line.push_edit(edit_t(line.position(), 0, wcstring{c}), true);
+    }
+    line.set_position(3);
+    line.push_edit(edit_t(3, 1, L""), true);  // delete
+    line.push_edit(edit_t(3, 1, L""), true);  // delete
+    line.push_edit(edit_t(2, 1, L""), true);  // backspace
+    do_test(line.text() == L"ab");
+    do_test(line.position() == 2);
+    line.undo();
+    do_test(line.text() == L"ab cd");
+    do_test(line.position() == 3);
+    line.undo();
+    do_test(line.text().empty());
+    line.redo();
+    line.redo();
+    do_test(line.text() == L"ab");
+    do_test(line.position() == 2);
+    reader_change_undo_coalesce(undo_coalesce_insert);
 }
 
 #define UVARS_PER_THREAD 8
@@ -3596,6 +3830,14 @@ static wcstring random_string() {
     return result;
 }
 
+// Wait until the next second.
+static void time_barrier() {
+    time_t start = time(nullptr);
+    do {
+        usleep(1000);
+    } while (time(nullptr) == start);
+}
+
 void history_tests_t::test_history() {
     history_search_t searcher;
     say(L"Testing history");
@@ -3664,6 +3906,109 @@ void history_tests_t::test_history() {
     searcher = history_search_t(history, L"Alpha");
     test_history_matches(searcher, {}, __LINE__);
 
+    // Test ranked search: better matches and more frequent commands come first.
+    history->clear();
+    for (const wchar_t *s : {L"cmake ..", L"make install", L"make", L"ls", L"make install",
+                             L"make install"}) {
+        history->add(s);
+    }
+    size_t total = 0;
+    std::vector<wcstring> ranked = history->search_ranked(L"make", 0, 10, &total, {});
+    do_test((ranked == std::vector<wcstring>{L"make install", L"make", L"cmake .."}));
+    do_test(total == 3);
+    ranked = history->search_ranked(L"make", 1, 1, &total, {});
+    do_test((ranked == std::vector<wcstring>{L"make"}));
+    ranked = history->search_ranked(L"mki", 0, 10, &total, {});
+    do_test((ranked == std::vector<wcstring>{L"make install"}));
+
+    // Test preferring the items run in a directory.
+    history->clear();
+    int n = 0;
+    for (const wchar_t *cwd : {L"/a", L"/b", L"/a", L"/b"}) {
+        history_item_t item(L"echo " + to_string(++n), time(nullptr));
+        item.set_cwd(cwd);
+        history->add(std::move(item));
+    }
+    searcher = history_search_t(history, L"echo");
+    searcher.prefer_cwd(L"/a");
+    test_history_matches(searcher, {L"echo 3", L"echo 1", L"echo 4", L"echo 2"}, __LINE__);
+    searcher = history_search_t(history, L"echo");
+    searcher.prefer_cwd(L"/c");
+    test_history_matches(searcher, {L"echo 4", L"echo 3", L"echo 2", L"echo 1"}, __LINE__);
+
+    // Test encrypted history: only a history with the key can read it back.
+    do_test(history_t::set_encryption_key(
+        L"000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"));
+    history->clear();
+    history->add(L"echo secret");
+    time_barrier();
+    do_test(history_contains(std::make_shared<history_t>(L"test_history"), L"echo secret"));
+    do_test(history_t::set_encryption_key(L""));
+    do_test(!history_contains(std::make_shared<history_t>(L"test_history"), L"echo secret"));
+    do_test(!history_t::set_encryption_key(L"0123"));
+
+    // Test recording how commands ended. A pending item is only saved once its command finished,
+    // with its exit status and duration.
+    history->clear();
+    history->add(history_item_t(L"false", time(nullptr), 1), true /* pending */);
+    history->finish_pending(1, 250);
+    history->add(L"true");
+    history->save();
+    time_barrier();
+    auto reread = std::make_shared<history_t>(L"test_history");
+    do_test(reread->item_at_index(1).get_exit_status() == -1);
+    do_test(reread->item_at_index(1).get_duration_ms() == -1);
+    do_test(reread->item_at_index(2).get_exit_status() == 1);
+    do_test(reread->item_at_index(2).get_duration_ms() == 250);
+
+    // Test regex searches and filters, and deleting what they find.
+    history->clear();
+    history_item_t failed(L"make test", 100);
+    failed.set_exit_status(2);
+    failed.set_cwd(L"/src");
+    history->add(std::move(failed));
+    history_item_t passed(L"make all", 200);
+    passed.set_exit_status(0);
+    history->add(std::move(passed));
+    searcher = history_search_t(history, L"^MAKE \\w+$", history_search_type_t::regex, nocase);
+    test_history_matches(searcher, {L"make all", L"make test"}, __LINE__);
+    searcher = history_search_t(history, L"^MAKE", history_search_type_t::regex);
+    test_history_matches(searcher, {}, __LINE__);
+    history_filter_t filter;
+    filter.exit_status = 2;
+    searcher = history_search_t(history, L"make");
+    searcher.set_filter(filter);
+    test_history_matches(searcher, {L"make test"}, __LINE__);
+    filter = history_filter_t{};
+    filter.after = 150;
+    searcher = history_search_t(history, L"make");
+    searcher.set_filter(filter);
+    test_history_matches(searcher, {L"make all"}, __LINE__);
+    filter.cwd = L"/src";
+    searcher = history_search_t(history, L"make");
+    searcher.set_filter(filter);
+    test_history_matches(searcher, {}, __LINE__);
+    auto never_cancel = [] { return false; };
+    do_test(history->remove_matching(history_search_type_t::regex, {L"test$"}, {}, true,
+                                     never_cancel) == 1);
+    do_test(!history_contains(history, L"make test"));
+    do_test(history_contains(history, L"make all"));
+
+    // Test which commands $fish_history_ignore keeps off the disk.
+    test_environment_t ignore_vars;
+    do_test(!history_ignore_mode(L"export AWS_SECRET=x", ignore_vars).has_value());
+    ignore_vars.vars[L"fish_history_ignore"] = L"export AWS_*";
+    do_test(history_ignore_mode(L"export AWS_SECRET=x", ignore_vars) ==
+            history_persistence_mode_t::memory);
+    do_test(!history_ignore_mode(L"echo export AWS_SECRET", ignore_vars).has_value());
+    ignore_vars.vars[L"fish_history_ignore"] = L"regex:--password\\b";
+    ignore_vars.vars[L"fish_history_ignore_mode"] = L"drop";
+    do_test(history_ignore_mode(L"mysql --password=hunter2 db", ignore_vars) ==
+            history_persistence_mode_t::ephemeral);
+    do_test(!history_ignore_mode(L"mysql --passwords", ignore_vars).has_value());
+    ignore_vars.vars[L"fish_history_ignore"] = L"regex:(";
+    do_test(!history_ignore_mode(L"(", ignore_vars).has_value());
+
     // Test history escaping and unescaping, yaml, etc.
     history_item_list_t before, after;
     history->clear();
@@ -3711,13 +4056,6 @@ void history_tests_t::test_history() {
     // Clean up after our tests.
     history->clear();
 }
-// Wait until the next second.
-static void time_barrier() {
-    time_t start = time(nullptr);
-    do {
-        usleep(1000);
-    } while (time(nullptr) == start);
-}
 
 static std::vector<wcstring> generate_history_lines(size_t item_count, size_t idx) {
     std::vector<wcstring> result;
@@ -3933,6 +4271,28 @@ void history_tests_t::test_history_merge() {
             do_test(history_contains(reader, more_texts[j]));
         }
     }
+
+    // Syncing picks up what another history wrote since.
+    time_barrier();
+    writer->add(L"Item_sync");
+    time_barrier();
+    do_test(!history_contains(reader, L"Item_sync"));
+    reader->sync_external_changes();
+    do_test(history_contains(reader, L"Item_sync"));
+
+    // The watcher of the reader's file sees the writer append to it. Without inotify or kqueue
+    // there is nothing to wait on.
+    history_watcher_t watcher;
+    watcher.watch(hists[1]);
+    if (watcher.notification_fd() >= 0) {
+        do_test(!watcher.notification_fd_became_readable());
+        time_barrier();
+        writer->add(L"Item_watched");
+        do_test(is_fd_readable(watcher.notification_fd(), 1000 * 1000));
+        do_test(watcher.notification_fd_became_readable());
+        reader->sync_external_changes();
+        do_test(history_contains(reader, L"Item_watched"));
+    }
     everything->clear();
 }
 
@@ -4099,6 +4459,29 @@ void history_tests_t::test_history_formats() {
         test_history->clear();
     }
 
+    // The file is read from the end, items from the future are skipped like when read from the
+    // start.
+    name = L"history_sample_fish_2_0_timestamps";
+    say(L"Testing %ls", name);
+    if (!install_sample_history(name)) {
+        err(L"Couldn't open file tests/%ls", name);
+    } else {
+        const wchar_t *const expected[] = {L"echo newest", L"echo no timestamp", L"echo doubled",
+                                           L"echo with paths", L"echo oldest", nullptr};
+
+        auto test_history = history_t::with_name(name);
+        if (test_history->item_at_index(1).str() != L"echo newest") {
+            err(L"test_history_formats failed for %ls\n", name);
+        }
+        if (!history_equals(test_history, expected)) {
+            err(L"test_history_formats failed for %ls\n", name);
+        }
+        if (test_history->size() != 5) {
+            err(L"history has wrong size: %lu but expected 5", (unsigned long)test_history->size());
+        }
+        test_history->clear();
+    }
+
     say(L"Testing bash import");
     FILE *f = fopen("tests/history_sample_bash", "r");
     if (!f) {
@@ -4126,6 +4509,61 @@ void history_tests_t::test_history_formats() {
         fclose(f);
     }
 
+    say(L"Testing zsh and JSON import");
+    {
+        auto test_history = history_t::with_name(L"import");
+        test_history->clear();
+        wcstring import_err;
+        // The continued command spans lines, and \x83\xbf is a metafied \x9f, as zsh writes it.
+        std::string zsh = ": 1000:0;echo one\n: 2000:5;for i in 1 2\\\ndo echo $i\\\ndone\n";
+        zsh += ": 3000:0;echo \xc3\x83\xbf\n";
+        if (test_history->import_items(history_format_t::zsh, zsh, &import_err) != size_t(3)) {
+            err(L"zsh import failed: %ls", import_err.c_str());
+        }
+        std::string json =
+            "[{\"command\": \"echo \\\"json\\\"\", \"timestamp\": 4000, \"paths\": [\"/tmp\"],"
+            " \"extra\": [1, {\"a\": null}]}, {\"command\": \"echo \\ud83d\\ude00\","
+            " \"timestamp\": 2500}]";
+        if (test_history->import_items(history_format_t::json, json, &import_err) != size_t(2)) {
+            err(L"JSON import failed: %ls", import_err.c_str());
+        }
+        const wchar_t *expected[] = {L"echo \"json\"",
+                                     L"echo \u00df",
+                                     L"echo \U0001F600",
+                                     L"for i in 1 2\ndo echo $i\ndone",
+                                     L"echo one",
+                                     nullptr};
+        if (!history_equals(test_history, expected)) {
+            err(L"test_history_formats failed for zsh and JSON import\n");
+        }
+        if (test_history->import_items(history_format_t::json, "[{]", &import_err).has_value()) {
+            err(L"Invalid JSON was imported");
+        }
+        test_history->clear();
+    }
+
+    say(L"Testing merging another history session");
+    {
+        auto project = history_t::with_name(L"merge_project");
+        auto test_history = history_t::with_name(L"merge_main");
+        project->clear();
+        test_history->clear();
+        project->add(L"make");
+        project->add(L"make test");
+        project->save();
+        test_history->add(L"ls");
+        if (test_history->merge_from(*project) != size_t(2)) {
+            err(L"Merging history session failed");
+        }
+        for (const wchar_t *cmd : {L"ls", L"make", L"make test"}) {
+            if (!history_contains(test_history, cmd)) {
+                err(L"Merged history is missing '%ls'", cmd);
+            }
+        }
+        project->clear();
+        test_history->clear();
+    }
+
     name = L"history_sample_corrupt1";
     say(L"Testing %ls", name);
     if (!install_sample_history(name)) {
@@ -5971,6 +6409,15 @@ static void test_killring() {
 
     do_test(kill_yank_rotate() == L"a");
     do_test((kill_entries() == std::vector<wcstring>{L"a", L"c", L"b", L"d"}));
+
+    // Registers hold one string each, apart from the kill ring.
+    do_test(!kill_get_register(L"x"));
+    kill_set_register(L"x", L"first");
+    kill_set_register(L"x", L"second");
+    do_test(kill_get_register(L"x") && *kill_get_register(L"x") == L"second");
+    do_test((kill_entries() == std::vector<wcstring>{L"a", L"c", L"b", L"d"}));
+    do_test(kill_register_is_clipboard(L"+") && kill_register_is_clipboard(L"*"));
+    do_test(!kill_register_is_clipboard(L"x"));
 }
 
 namespace {
@@ -6202,6 +6649,8 @@ struct test_comparator_t {
 #define TEST_GROUP(x) x
 static const test_t s_tests[]{
     {TEST_GROUP("utility_functions"), test_utility_functions},
+    {TEST_GROUP("fuzzy_match"), test_fuzzy_subsequence_match},
+    {TEST_GROUP("fuzzy_match"), test_fuzzy_match_positions},
     {TEST_GROUP("dir_iter"), test_dir_iter},
     {TEST_GROUP("wwrite_to_fd"), test_wwrite_to_fd},
     {TEST_GROUP("env_vars"), test_env_vars},
@@ -6229,6 +6678,7 @@ static const test_t s_tests[]{
     {TEST_GROUP("pthread"), test_pthread},
     {TEST_GROUP("debounce"), test_debounce},
     {TEST_GROUP("debounce"), test_debounce_timeout},
+    {TEST_GROUP("cancel_token"), test_cancel_token},
     {TEST_GROUP("parser"), test_parser},
     {TEST_GROUP("cancellation"), test_cancellation},
     {TEST_GROUP("utf8"), test_utf8},
@@ -6241,6 +6691,7 @@ static const test_t s_tests[]{
     {TEST_GROUP("dup2s"), test_dup2s},
     {TEST_GROUP("dup2s"), test_dup2s_fd_for_target_fd},
     {TEST_GROUP("pager_navigation"), test_pager_navigation},
+    {TEST_GROUP("pager_navigation"), test_pager_groups},
     {TEST_GROUP("pager_layout"), test_pager_layout},
     {TEST_GROUP("word_motion"), test_word_motion},
     {TEST_GROUP("is_potential_path"), test_is_potential_path},
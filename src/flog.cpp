@@ -13,6 +13,7 @@
 #include <vector>
 
 #include "common.h"
+#include "crash_handler.rs.h"
 #include "global_safety.h"
 #include "parse_util.h"
 #include "wcstringutil.h"
@@ -44,30 +45,46 @@ logger_t::logger_t() : file_(stderr) {}
 
 owning_lock<logger_t> g_logger;
 
-void logger_t::log1(const wchar_t *s) { std::fputws(s, file_); }
+void logger_t::log1(const wchar_t *s) {
+    std::fputws(s, file_);
+    line_.append(s);
+}
 
 void logger_t::log1(const char *s) {
     // Note glibc prohibits mixing narrow and wide I/O, so always use wide-printing functions.
     // See #5900.
     std::fwprintf(file_, L"%s", s);
+    line_.append(str2wcstring(s));
 }
 
-void logger_t::log1(wchar_t c) { std::fputwc(c, file_); }
+void logger_t::log1(wchar_t c) {
+    std::fputwc(c, file_);
+    line_.push_back(c);
+}
 
-void logger_t::log1(char c) { std::fwprintf(file_, L"%c", c); }
+void logger_t::log1(char c) {
+    std::fwprintf(file_, L"%c", c);
+    line_.push_back(static_cast<unsigned char>(c));
+}
 
-void logger_t::log1(int64_t v) { std::fwprintf(file_, L"%lld", v); }
+void logger_t::log1(int64_t v) { log1(to_string(static_cast<long long>(v))); }
 
-void logger_t::log1(uint64_t v) { std::fwprintf(file_, L"%llu", v); }
+void logger_t::log1(uint64_t v) { log1(to_string(static_cast<unsigned long long>(v))); }
+
+void logger_t::finish_line() {
+    crash_handler_note_flog(line_);
+    line_.clear();
+}
 
 void logger_t::log_fmt(const category_t &cat, const wchar_t *fmt, ...) {
     va_list va;
     va_start(va, fmt);
     log1(cat.name);
     log1(L": ");
-    std::vfwprintf(file_, fmt, va);
+    log1(vformat_string(fmt, va));
     log1(L'\n');
     va_end(va);
+    finish_line();
 }
 
 void logger_t::log_fmt(const category_t &cat, const char *fmt, ...) {
@@ -108,6 +108,7 @@ class category_list_t {
     category_t path{L"path", L"Searching/using paths"};
 
     category_t screen{L"screen", L"Screen repaints"};
+    category_t stall{L"stall", L"Reports of the main thread being stuck"};
 
     category_t abbrs{L"abbrs", L"Abbreviation expansion"};
 };
@@ -117,6 +118,12 @@ class category_list_t {
 class logger_t {
     FILE *file_;
 
+    // The line being logged, kept for crash reports.
+    wcstring line_;
+
+    // Hand the finished line to the crash handler.
+    void finish_line();
+
     void log1(const wchar_t *);
     void log1(const char *);
     void log1(wchar_t);
@@ -160,13 +167,17 @@ class logger_t {
         log1(": ");
         log_args_impl(args...);
         log1('\n');
+        finish_line();
     }
 
     void log_fmt(const category_t &cat, const wchar_t *fmt, ...);
     void log_fmt(const category_t &cat, const char *fmt, ...);
 
     // Log outside of the usual flog usage.
-    void log_extra(const wchar_t *s) { log1(s); }
+    void log_extra(const wchar_t *s) {
+        log1(s);
+        line_.clear();
+    }
 
     // Variant of flogf which is async safe. This is intended to be used after fork().
     static void flogf_async_safe(const char *category, const char *fmt,
@@ -180,6 +180,28 @@ int function_get_copy_definition_lineno(const function_properties_t &props) {
     return props.copy_definition_lineno;
 }
 
+const wchar_t *function_job_scope_to_string(function_job_scope_t scope) {
+    switch (scope) {
+        case function_job_scope_t::none:
+            return nullptr;
+        case function_job_scope_t::wait:
+            return L"wait";
+        case function_job_scope_t::cancel:
+            return L"cancel";
+        case function_job_scope_t::disown:
+            return L"disown";
+    }
+    DIE("unknown job scope");
+}
+
+maybe_t<function_job_scope_t> function_job_scope_from_string(const wcstring &str) {
+    for (auto scope : {function_job_scope_t::wait, function_job_scope_t::cancel,
+                       function_job_scope_t::disown}) {
+        if (str == function_job_scope_to_string(scope)) return scope;
+    }
+    return none();
+}
+
 wcstring function_get_annotated_definition(const function_properties_t &props,
                                            const wcstring &name) {
     return props.annotated_definition(name);
@@ -207,6 +229,14 @@ bool function_exists_no_autoload(const wcstring &cmd) {
     return funcset->get_props(cmd) || funcset->autoloader.can_autoload(cmd);
 }
 
+autoload_info_t function_get_autoload_info(const wcstring &name) {
+    return function_set.acquire()->autoloader.get_info(name, env_stack_t::globals());
+}
+
+bool function_is_autoload_tombstoned(const wcstring &name) {
+    return function_set.acquire()->autoload_tombstones.count(name) > 0;
+}
+
 bool function_set_t::remove(const wcstring &name) {
     size_t amt = funcs.erase(name);
     if (amt > 0) {
@@ -308,6 +338,25 @@ void function_invalidate_path() {
     funcset->autoloader.clear();
 }
 
+std::vector<wcstring> function_reload_changed(parser_t &parser) {
+    std::vector<wcstring> reloadees;
+    {
+        auto funcset = function_set.acquire();
+        for (const wcstring &name :
+             funcset->autoloader.forget_changed_commands(env_stack_t::globals())) {
+            auto props = funcset->get_props(name);
+            if (props && !props->is_autoload) continue;
+            funcset->remove(name);
+            reloadees.push_back(name);
+        }
+    }
+    // As in function_load(), we can't autoload while holding the lock.
+    for (const wcstring &name : reloadees) {
+        function_load(name, parser);
+    }
+    return reloadees;
+}
+
 function_properties_t::function_properties_t() : parsed_source(empty_parsed_source_ref()) {}
 
 function_properties_t::function_properties_t(const function_properties_t &other)
@@ -322,6 +371,7 @@ function_properties_t &function_properties_t::operator=(const function_propertie
     description = other.description;
     inherit_vars = other.inherit_vars;
     shadow_scope = other.shadow_scope;
+    job_scope = other.job_scope;
     is_autoload = other.is_autoload;
     definition_file = other.definition_file;
     return *this;
@@ -357,6 +407,11 @@ wcstring function_properties_t::annotated_definition(const wcstring &name) const
         out.append(L" --no-scope-shadowing");
     }
 
+    if (const wchar_t *scope = function_job_scope_to_string(this->job_scope)) {
+        out.append(L" --job-scope ");
+        out.append(scope);
+    }
+
     for (const auto &d : handlers) {
         switch (d.typ) {
             case event_type_t::signal: {
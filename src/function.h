@@ -10,9 +10,19 @@
 
 #include "ast.h"
 #include "common.h"
+#include "maybe.h"
 #include "parse_tree.h"
 
 class parser_t;
+struct autoload_info_t;
+
+/// What to do with background jobs a function started which are still running when it returns.
+enum class function_job_scope_t {
+    none,    // leave them running
+    wait,    // wait for them to finish
+    cancel,  // send them SIGTERM
+    disown,  // remove them from the job list
+};
 
 /// A function's constant properties. These do not change once initialized.
 struct function_properties_t {
@@ -41,6 +51,9 @@ struct function_properties_t {
     /// Set to true if invoking this function shadows the variables of the underlying function.
     bool shadow_scope{true};
 
+    /// What to do with the function's background jobs when it returns.
+    function_job_scope_t job_scope{function_job_scope_t::none};
+
     /// Whether the function was autoloaded.
     bool is_autoload{false};
 
@@ -108,6 +121,14 @@ bool function_exists(const wcstring &cmd, parser_t &parser);
 /// directory.
 bool function_exists_no_autoload(const wcstring &cmd);
 
+/// \return the files in fish_function_path that could provide the function \p name, and the one it
+/// was loaded from. This does not autoload.
+autoload_info_t function_get_autoload_info(const wcstring &name);
+
+/// \return true if \p name was erased with `functions --erase`, which keeps it from being
+/// autoloaded again.
+bool function_is_autoload_tombstoned(const wcstring &name);
+
 /// Returns all function names.
 ///
 /// \param get_hidden whether to include hidden functions, i.e. ones starting with an underscore.
@@ -120,4 +141,15 @@ bool function_copy(const wcstring &name, const wcstring &new_name, parser_t &par
 /// Observes that fish_function_path has changed.
 void function_invalidate_path();
 
+/// Unload autoloaded functions whose file has changed or gone away, and load them again from
+/// their current file. Functions that have since been redefined by hand are left alone.
+/// \return the names of the functions that were unloaded.
+std::vector<wcstring> function_reload_changed(parser_t &parser);
+
+/// \return the name of a job scope as used by `function --job-scope`, or nullptr for none.
+const wchar_t *function_job_scope_to_string(function_job_scope_t scope);
+
+/// Parse the name of a job scope. \return none() if it is not valid.
+maybe_t<function_job_scope_t> function_job_scope_from_string(const wcstring &str);
+
 #endif
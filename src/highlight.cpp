@@ -74,8 +74,12 @@ static const wchar_t *get_highlight_var_name(highlight_role_t role) {
             return L"fish_color_autosuggestion";
         case highlight_role_t::selection:
             return L"fish_color_selection";
+        case highlight_role_t::continuation:
+            return L"fish_color_continuation";
         case highlight_role_t::pager_progress:
             return L"fish_pager_color_progress";
+        case highlight_role_t::pager_group:
+            return L"fish_pager_color_group";
         case highlight_role_t::pager_background:
             return L"fish_pager_color_background";
         case highlight_role_t::pager_prefix:
@@ -84,6 +88,8 @@ static const wchar_t *get_highlight_var_name(highlight_role_t role) {
             return L"fish_pager_color_completion";
         case highlight_role_t::pager_description:
             return L"fish_pager_color_description";
+        case highlight_role_t::pager_match:
+            return L"fish_pager_color_match";
         case highlight_role_t::pager_secondary_background:
             return L"fish_pager_color_secondary_background";
         case highlight_role_t::pager_secondary_prefix:
@@ -92,6 +98,8 @@ static const wchar_t *get_highlight_var_name(highlight_role_t role) {
             return L"fish_pager_color_secondary_completion";
         case highlight_role_t::pager_secondary_description:
             return L"fish_pager_color_secondary_description";
+        case highlight_role_t::pager_secondary_match:
+            return L"fish_pager_color_secondary_match";
         case highlight_role_t::pager_selected_background:
             return L"fish_pager_color_selected_background";
         case highlight_role_t::pager_selected_prefix:
@@ -100,6 +108,8 @@ static const wchar_t *get_highlight_var_name(highlight_role_t role) {
             return L"fish_pager_color_selected_completion";
         case highlight_role_t::pager_selected_description:
             return L"fish_pager_color_selected_description";
+        case highlight_role_t::pager_selected_match:
+            return L"fish_pager_color_selected_match";
     }
     DIE("invalid highlight role");
 }
@@ -131,6 +141,10 @@ static highlight_role_t get_fallback(highlight_role_t role) {
             return highlight_role_t::command;
         case highlight_role_t::option:
             return highlight_role_t::param;
+        case highlight_role_t::continuation:
+            return highlight_role_t::autosuggestion;
+        case highlight_role_t::pager_group:
+            return highlight_role_t::pager_description;
         case highlight_role_t::pager_secondary_background:
             return highlight_role_t::pager_background;
         case highlight_role_t::pager_secondary_prefix:
@@ -142,6 +156,11 @@ static highlight_role_t get_fallback(highlight_role_t role) {
         case highlight_role_t::pager_secondary_description:
         case highlight_role_t::pager_selected_description:
             return highlight_role_t::pager_description;
+        case highlight_role_t::pager_match:
+            return highlight_role_t::pager_prefix;
+        case highlight_role_t::pager_secondary_match:
+        case highlight_role_t::pager_selected_match:
+            return highlight_role_t::pager_match;
         case highlight_role_t::pager_selected_background:
             return highlight_role_t::search_match;
     }
@@ -349,6 +368,21 @@ rgb_color_t highlight_color_resolver_t::resolve_spec_uncached(const highlight_sp
     if (!var) var = vars.get(get_highlight_var_name(highlight_role_t::normal));
     if (var) result = parse_color(*var, is_background);
 
+    // Most roles are drawn on the normal background, or on a pager background that is unset. Let
+    // them carry their own, like `set fish_color_command blue --background=white`.
+    if (is_background && highlight.foreground != highlight.background &&
+        (highlight.background == highlight_role_t::normal || result.is_normal())) {
+        auto fg_var = vars.get_unless_empty(get_highlight_var_name(highlight.foreground));
+        if (!fg_var) {
+            fg_var =
+                vars.get_unless_empty(get_highlight_var_name(get_fallback(highlight.foreground)));
+        }
+        if (fg_var) {
+            rgb_color_t own = parse_color(*fg_var, true);
+            if (!own.is_normal()) result = own;
+        }
+    }
+
     // Handle modifiers.
     if (!is_background && highlight.valid_path) {
         auto var2 = vars.get(L"fish_color_valid_path");
@@ -360,22 +394,27 @@ rgb_color_t highlight_color_resolver_t::resolve_spec_uncached(const highlight_sp
                 // Valid path has an actual color, use it and merge the modifiers.
                 auto rescol = result2;
                 rescol.set_bold(result.is_bold() || result2.is_bold());
-                rescol.set_underline(result.is_underline() || result2.is_underline());
+                rescol.set_underline_style(result2.is_underline() ? result2.underline_style()
+                                                                  : result.underline_style());
                 rescol.set_italics(result.is_italics() || result2.is_italics());
                 rescol.set_dim(result.is_dim() || result2.is_dim());
                 rescol.set_reverse(result.is_reverse() || result2.is_reverse());
+                rescol.set_strikethrough(result.is_strikethrough() || result2.is_strikethrough());
+                rescol.set_blink(result.is_blink() || result2.is_blink());
                 result = rescol;
             } else {
                 if (result2.is_bold()) result.set_bold(true);
-                if (result2.is_underline()) result.set_underline(true);
+                if (result2.is_underline()) result.set_underline_style(result2.underline_style());
                 if (result2.is_italics()) result.set_italics(true);
                 if (result2.is_dim()) result.set_dim(true);
                 if (result2.is_reverse()) result.set_reverse(true);
+                if (result2.is_strikethrough()) result.set_strikethrough(true);
+                if (result2.is_blink()) result.set_blink(true);
             }
         }
     }
 
-    if (!is_background && highlight.force_underline) {
+    if (!is_background && highlight.force_underline && !result.is_underline()) {
         result.set_underline(true);
     }
 
@@ -396,6 +435,21 @@ rgb_color_t highlight_color_resolver_t::resolve_spec(const highlight_spec_t &hig
     return iter->second;
 }
 
+rgb_color_t highlight_color_resolver_t::resolve_underline_color(const highlight_spec_t &highlight,
+                                                                const environment_t &vars) {
+    auto p = underline_cache_.emplace(highlight, rgb_color_t{});
+    auto iter = p.first;
+    if (p.second) {
+        // Like the foreground, but valid paths aren't colored differently.
+        highlight_role_t role = highlight.foreground;
+        auto var = vars.get_unless_empty(get_highlight_var_name(role));
+        if (!var) var = vars.get_unless_empty(get_highlight_var_name(get_fallback(role)));
+        if (!var) var = vars.get(get_highlight_var_name(highlight_role_t::normal));
+        iter->second = var ? parse_underline_color(*var) : rgb_color_t::none();
+    }
+    return iter->second;
+}
+
 static bool command_is_valid(const wcstring &cmd, statement_decoration_t decoration,
                              const wcstring &working_directory, const environment_t &vars);
 
@@ -1302,7 +1356,8 @@ std::string colorize(const wcstring &text, const std::vector<highlight_spec_t> &
     for (size_t i = 0; i < text.size(); i++) {
         highlight_spec_t color = colors.at(i);
         if (color != last_color) {
-            outp.set_color(rv.resolve_spec(color, false, vars), rgb_color_t::normal());
+            outp.set_color(rv.resolve_spec(color, false, vars), rgb_color_t::normal(),
+                           rv.resolve_underline_color(color, vars));
             last_color = color;
         }
         outp.writech(text.at(i));
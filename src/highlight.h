@@ -38,22 +38,27 @@ enum class highlight_role_t : uint8_t {
     redirection,           // redirection
     autosuggestion,        // autosuggestion
     selection,
+    continuation,          // continuation indicator
 
     // Pager support.
     // NOTE: pager.cpp relies on these being in this order.
     pager_progress,
+    pager_group,
     pager_background,
     pager_prefix,
     pager_completion,
     pager_description,
+    pager_match,
     pager_secondary_background,
     pager_secondary_prefix,
     pager_secondary_completion,
     pager_secondary_description,
+    pager_secondary_match,
     pager_selected_background,
     pager_selected_prefix,
     pager_selected_completion,
     pager_selected_description,
+    pager_selected_match,
 };
 
 /// Simply value type describing how a character should be highlighted..
@@ -127,9 +132,15 @@ struct highlight_color_resolver_t {
     rgb_color_t resolve_spec(const highlight_spec_t &highlight, bool is_background,
                              const environment_t &vars);
 
+    /// \return the color of underlines for a given highlight spec, or none to use the foreground
+    /// color.
+    rgb_color_t resolve_underline_color(const highlight_spec_t &highlight,
+                                        const environment_t &vars);
+
    private:
     std::unordered_map<highlight_spec_t, rgb_color_t> fg_cache_;
     std::unordered_map<highlight_spec_t, rgb_color_t> bg_cache_;
+    std::unordered_map<highlight_spec_t, rgb_color_t> underline_cache_;
     rgb_color_t resolve_spec_uncached(const highlight_spec_t &highlight, bool is_background,
                                       const environment_t &vars) const;
 };
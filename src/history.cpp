@@ -10,14 +10,23 @@
 #include <sys/file.h>  // IWYU pragma: keep
 #include <sys/stat.h>
 #include <unistd.h>
+#ifdef HAVE_INOTIFY_INIT1
+#include <sys/inotify.h>
+#endif
+#ifdef HAVE_KQUEUE
+#include <sys/event.h>
+#include <sys/types.h>
+#endif
 
 #include <algorithm>
 #include <chrono>
+#include <cmath>
 #include <cwchar>
 #include <functional>
 #include <iterator>
 #include <map>
 #include <random>
+#include <unordered_map>
 #include <unordered_set>
 
 #include "ast.h"
@@ -29,14 +38,17 @@
 #include "flog.h"
 #include "global_safety.h"
 #include "history.h"
+#include "history_crypt.rs.h"
 #include "history_file.h"
 #include "io.h"
 #include "iothread.h"
+#include "json.rs.h"
 #include "lru.h"
 #include "operation_context.h"
 #include "parse_constants.h"
 #include "parse_util.h"
 #include "path.h"
+#include "re.h"
 #include "wcstringutil.h"
 #include "wildcard.h"  // IWYU pragma: keep
 #include "wutil.h"     // IWYU pragma: keep
@@ -154,6 +166,15 @@ bool history_item_t::merge(const history_item_t &item) {
     if (this->identifier < item.identifier) {
         this->identifier = item.identifier;
     }
+    if (!item.cwd.empty()) {
+        this->cwd = item.cwd;
+    }
+    if (item.exit_status >= 0) {
+        this->exit_status = item.exit_status;
+    }
+    if (item.duration_ms >= 0) {
+        this->duration_ms = item.duration_ms;
+    }
     return true;
 }
 
@@ -198,6 +219,14 @@ bool history_item_t::matches_search(const wcstring &term, enum history_search_ty
         case history_search_type_t::contains_subsequence: {
             return subsequence_in_string(term, content_to_match);
         }
+        case history_search_type_t::regex: {
+            // history_search_t compiles the regex just once, this is for everyone else. The term
+            // isn't lowercased here, since that would change the meaning of e.g. "\\D".
+            re::flags_t flags;
+            flags.icase = !case_sensitive;
+            auto regex = re::regex_t::try_compile(term, flags);
+            return regex && regex->match(contents).has_value();
+        }
         case history_search_type_t::match_everything: {
             return true;
         }
@@ -205,6 +234,14 @@ bool history_item_t::matches_search(const wcstring &term, enum history_search_ty
     DIE("unexpected history_search_type_t value");
 }
 
+bool history_filter_t::matches(const history_item_t &item) const {
+    if (before.has_value() && item.timestamp() >= *before) return false;
+    if (after.has_value() && item.timestamp() < *after) return false;
+    if (!cwd.empty() && item.get_cwd() != cwd) return false;
+    if (exit_status.has_value() && item.get_exit_status() != *exit_status) return false;
+    return true;
+}
+
 struct history_impl_t {
     // Add a new history item to the end. If pending is set, the item will not be returned by
     // item_at_index until a call to resolve_pending(). Pending items are tracked with an offset
@@ -230,6 +267,10 @@ struct history_impl_t {
     // item_at_index.
     bool has_pending_item{false};
 
+    // The identifier of the last pending item, if its command hasn't finished yet. We don't save
+    // automatically until it has, so its exit status and duration are written with it.
+    history_identifier_t unfinished_identifier{0};
+
     // Whether we should disable saving to the file for a time.
     uint32_t disable_automatic_save_counter{0};
 
@@ -259,8 +300,34 @@ struct history_impl_t {
     // Whether we've loaded old items.
     bool loaded_old{false};
 
-    // List of old items, as offsets into out mmap data.
-    std::deque<size_t> old_item_offsets{};
+    // List of old items, as offsets into out mmap data, the most recent first.
+    // This is filled lazily by index_old_items(), so only the part of the file in use is read.
+    std::vector<size_t> old_item_offsets{};
+
+    // Where index_old_items() continues looking for older items, going towards the start.
+    size_t old_item_cursor{0};
+
+    // Whether old_item_offsets has all the old items.
+    bool indexed_all_old{false};
+
+    // How often and how recently a command was run, for ranked searches.
+    struct usage_t {
+        uint32_t count{0};
+        time_t last_used{0};
+    };
+
+    // Usage of the old items, keyed by their text. This is built lazily by index_old_usage().
+    std::unordered_map<wcstring, usage_t> old_usage{};
+
+    // Whether old_usage is built.
+    bool indexed_old_usage{false};
+
+    // Items from `history import`, which the next rewrite adds to the file.
+    std::vector<history_item_t> imported_items{};
+
+    // How often each command was added in this session. Unlike new_items, this is not compacted
+    // when saving, so repeated commands keep their weight.
+    std::unordered_map<wcstring, uint32_t> session_run_counts{};
 
     // If set, we gave up on file locking because it took too long.
     // Note this is shared among all history instances.
@@ -272,12 +339,16 @@ struct history_impl_t {
     /// \return a new item identifier, incrementing our counter.
     history_identifier_t next_identifier() { return ++last_identifier; }
 
-    // Figure out the offsets of our file contents.
-    void populate_from_file_contents();
+    // Figure out the offsets of the old items, until there are at least \p count of them or there
+    // are no more.
+    void index_old_items(size_t count);
 
     // Loads old items if necessary.
     void load_old_if_needed();
 
+    // Builds old_usage if necessary.
+    void index_old_usage();
+
     // Deletes duplicates in new_items.
     void compact_new_items();
 
@@ -321,6 +392,9 @@ struct history_impl_t {
     // Resolves any pending history items, so that they may be returned in history searches.
     void resolve_pending();
 
+    // Records how the command of the last pending item ended, resolves it and saves it.
+    void finish_pending(int exit_status, int64_t duration_ms);
+
     // Enable / disable automatic saving. Main thread only!
     void disable_automatic_saving();
     void enable_automatic_saving();
@@ -337,9 +411,16 @@ struct history_impl_t {
     // Populates from a bash history file.
     void populate_from_bash(FILE *stream);
 
+    // Adds items to the history file, merging them with the ones there.
+    // \return false if the file couldn't be written.
+    bool import_items(std::vector<history_item_t> items);
+
     // Incorporates the history of other shells into this history.
     void incorporate_external_changes();
 
+    // Incorporates the history of other shells, if they changed our file since we last looked.
+    void sync_external_changes();
+
     // Gets all the history into a list. This is intended for the $history environment variable.
     // This may be long!
     void get_history(std::vector<wcstring> &result);
@@ -359,6 +440,11 @@ struct history_impl_t {
     // Return the number of history entries.
     size_t size();
 
+    // Return up to \p count distinct items matching \p term fuzzily, best first, skipping the first
+    // \p offset. The total number of matches is stored in \p out_total.
+    std::vector<wcstring> search_ranked(const wcstring &term, size_t offset, size_t count,
+                                        size_t *out_total, const cancel_checker_t &cancel_check);
+
     // Maybe lock a history file.
     // \return true if successful, false if locking was skipped.
     static bool maybe_lock_file(int fd, int lock_type);
@@ -397,6 +483,8 @@ void history_impl_t::add(history_item_t &&item, bool pending, bool do_save) {
     if (item.contents.empty()) {
         return;
     }
+    session_run_counts[item.contents]++;
+    if (pending) unfinished_identifier = item.identifier;
 
     // Try merging with the last item.
     if (!new_items.empty() && new_items.back().merge(item)) {
@@ -412,8 +500,9 @@ void history_impl_t::add(history_item_t &&item, bool pending, bool do_save) {
 }
 
 void history_impl_t::save_unless_disabled() {
-    // Respect disable_automatic_save_counter.
-    if (disable_automatic_save_counter > 0) {
+    // Respect disable_automatic_save_counter, and wait for the command of a pending item to
+    // finish.
+    if (disable_automatic_save_counter > 0 || unfinished_identifier != 0) {
         return;
     }
 
@@ -453,6 +542,8 @@ void history_impl_t::save_unless_disabled() {
 void history_impl_t::remove(const wcstring &str_to_remove) {
     // Add to our list of deleted items.
     deleted_items.insert(std::pair<wcstring, bool>(str_to_remove, false));
+    session_run_counts.erase(str_to_remove);
+    old_usage.erase(str_to_remove);
 
     size_t idx = new_items.size();
     while (idx--) {
@@ -504,8 +595,8 @@ void history_impl_t::get_history(std::vector<wcstring> &result) {
 
     // Append old items.
     load_old_if_needed();
-    for (auto iter = old_item_offsets.crbegin(); iter != old_item_offsets.crend(); ++iter) {
-        size_t offset = *iter;
+    index_old_items(SIZE_MAX);
+    for (size_t offset : old_item_offsets) {
         const history_item_t item = file_contents->decode_item(offset);
         if (seen.insert(item.str()).second) result.push_back(item.str());
     }
@@ -515,6 +606,7 @@ size_t history_impl_t::size() {
     size_t new_item_count = new_items.size();
     if (this->has_pending_item && new_item_count > 0) new_item_count -= 1;
     load_old_if_needed();
+    index_old_items(SIZE_MAX);
     size_t old_item_count = old_item_offsets.size();
     return new_item_count + old_item_count;
 }
@@ -539,17 +631,111 @@ history_item_t history_impl_t::item_at_index(size_t idx) {
     // Now look in our old items.
     idx -= resolved_new_item_count;
     load_old_if_needed();
-    size_t old_item_count = old_item_offsets.size();
-    if (idx < old_item_count) {
-        // idx == 0 corresponds to last item in old_item_offsets.
-        size_t offset = old_item_offsets.at(old_item_count - idx - 1);
-        return file_contents->decode_item(offset);
+    index_old_items(idx + 1);
+    if (idx < old_item_offsets.size()) {
+        return file_contents->decode_item(old_item_offsets.at(idx));
     }
 
     // Index past the valid range, so return an empty history item.
     return history_item_t{};
 }
 
+void history_impl_t::index_old_usage() {
+    if (indexed_old_usage) return;
+    indexed_old_usage = true;
+
+    load_old_if_needed();
+    index_old_items(SIZE_MAX);
+    for (size_t offset : old_item_offsets) {
+        history_item_t item = file_contents->decode_item(offset);
+        if (item.empty() || deleted_items.count(item.str())) continue;
+        // The file only has duplicates until it is vacuumed, so this undercounts, but it is a
+        // good enough signal.
+        usage_t &usage = old_usage[item.str()];
+        usage.count++;
+        usage.last_used = std::max(usage.last_used, item.timestamp());
+    }
+}
+
+/// \return how well \p match matches, from 0 (not at all) to 1 (exact or prefix match).
+static double fuzzy_match_weight(const string_fuzzy_match_t &match, size_t term_len) {
+    using contain_type_t = string_fuzzy_match_t::contain_type_t;
+    double weight = 0;
+    switch (match.type) {
+        case contain_type_t::exact:
+        case contain_type_t::prefix:
+            weight = 1;
+            break;
+        case contain_type_t::substr:
+            weight = 0.25;
+            break;
+        case contain_type_t::subseq: {
+            // Each matched character scores at most 28, see score_subsequence_match().
+            double quality = match.subseq_score / (28.0 * std::max(term_len, size_t(1)));
+            weight = (1 + std::min(std::max(quality, 0.0), 1.0)) / 32;
+            break;
+        }
+    }
+    if (match.case_fold != string_fuzzy_match_t::case_fold_t::samecase) weight /= 2;
+    return weight;
+}
+
+std::vector<wcstring> history_impl_t::search_ranked(const wcstring &term, size_t offset,
+                                                    size_t count, size_t *out_total,
+                                                    const cancel_checker_t &cancel_check) {
+    // Gather the usage of every distinct item, in this session and before.
+    index_old_usage();
+    std::unordered_map<wcstring, usage_t> usages = old_usage;
+    size_t resolved_new_item_count = new_items.size();
+    if (this->has_pending_item && resolved_new_item_count > 0) resolved_new_item_count -= 1;
+    std::unordered_set<wcstring> seen;
+    for (size_t i = 0; i < resolved_new_item_count; i++) {
+        const history_item_t &item = new_items.at(i);
+        usage_t &usage = usages[item.str()];
+        usage.last_used = std::max(usage.last_used, item.timestamp());
+        // new_items may still have duplicates, but each command's runs are counted once.
+        if (!seen.insert(item.str()).second) continue;
+        auto runs = session_run_counts.find(item.str());
+        usage.count += runs != session_run_counts.end() ? runs->second : 1;
+    }
+
+    struct ranked_t {
+        const wcstring *text;
+        double score;
+        time_t last_used;
+    };
+    std::vector<ranked_t> ranked;
+    time_t now = time(nullptr);
+    for (const auto &kv : usages) {
+        if (cancel_check && cancel_check()) break;
+        const wcstring &text = kv.first;
+        const usage_t &usage = kv.second;
+        double weight = 1;
+        if (!term.empty()) {
+            auto match = string_fuzzy_match_string(term, text, false, true);
+            if (!match) continue;
+            weight = fuzzy_match_weight(*match, term.size());
+        }
+        // Frecency: commands run often count more, but their weight fades over the weeks.
+        double age_days = std::max(now - usage.last_used, time_t(0)) / 86400.0;
+        double frecency = (1 + std::log2(std::max(usage.count, 1u))) / (1 + age_days / 7);
+        ranked.push_back(ranked_t{&text, weight * frecency, usage.last_used});
+    }
+
+    std::sort(ranked.begin(), ranked.end(), [](const ranked_t &a, const ranked_t &b) {
+        if (a.score != b.score) return a.score > b.score;
+        if (a.last_used != b.last_used) return a.last_used > b.last_used;
+        return *a.text < *b.text;
+    });
+
+    if (out_total) *out_total = ranked.size();
+    std::vector<wcstring> result;
+    for (size_t i = offset; i < ranked.size() && result.size() < count; i++) {
+        result.push_back(*ranked.at(i).text);
+    }
+    return result;
+}
+
 std::unordered_map<long, wcstring> history_impl_t::items_at_indexes(const std::vector<long> &idxs) {
     std::unordered_map<long, wcstring> result;
     for (long idx : idxs) {
@@ -581,19 +767,39 @@ time_t history_impl_t::timestamp_now() const {
     return when;
 }
 
-void history_impl_t::populate_from_file_contents() {
-    old_item_offsets.clear();
-    if (file_contents) {
+void history_impl_t::index_old_items(size_t count) {
+    if (indexed_all_old || old_item_offsets.size() >= count) return;
+    if (!file_contents) {
+        indexed_all_old = true;
+        return;
+    }
+
+    if (file_contents->type() != history_type_fish_2_0) {
+        // Old formats are read from the start, all at once.
         size_t cursor = 0;
         maybe_t<size_t> offset;
         while ((offset = file_contents->offset_of_next_item(&cursor, boundary_timestamp))
                    .has_value()) {
-            // Remember this item.
+            old_item_offsets.push_back(*offset);
+        }
+        std::reverse(old_item_offsets.begin(), old_item_offsets.end());
+        indexed_all_old = true;
+    } else {
+        while (old_item_offsets.size() < count) {
+            auto offset = file_contents->offset_of_prev_item(&old_item_cursor, boundary_timestamp);
+            if (!offset) {
+                indexed_all_old = true;
+                break;
+            }
+            // Items encrypted with a key we don't have are left alone.
+            if (!file_contents->is_readable(*offset)) continue;
             old_item_offsets.push_back(*offset);
         }
     }
 
-    FLOGF(history, "Loaded %lu old items", old_item_offsets.size());
+    if (indexed_all_old) {
+        FLOGF(history, "Loaded %lu old items", old_item_offsets.size());
+    }
 }
 
 void history_impl_t::load_old_if_needed() {
@@ -618,8 +824,10 @@ void history_impl_t::load_old_if_needed() {
             this->history_file_id = file_contents ? file_id_for_fd(fd) : kInvalidFileID;
             if (locked) unlock_file(fd);
 
-            time_profiler_t profiler("populate_from_file_contents");  //!OCLINT(side-effect)
-            this->populate_from_file_contents();
+            // The items are only found when they are needed, starting with the most recent.
+            old_item_offsets.clear();
+            old_item_cursor = file_contents ? file_contents->length() : 0;
+            indexed_all_old = false;
         }
     }
 }
@@ -640,31 +848,55 @@ bool history_search_t::go_to_next_match(history_search_direction_t direction) {
 
     if (current_index_ == invalid_index) return false;
 
+    // With a preferred directory, we make two passes: one for the items run there and one for the
+    // others.
+    bool tiered = !preferred_cwd_.empty() && direction == history_search_direction_t::backward;
     size_t index = current_index_;
-    while ((index += increment) != invalid_index) {
-        history_item_t item = history_->item_at_index(index);
+    for (;;) {
+        while ((index += increment) != invalid_index) {
+            history_item_t item = history_->item_at_index(index);
 
-        // We're done if it's empty or we cancelled.
-        if (item.empty()) {
-            return false;
-        }
+            // We're done if it's empty or we cancelled.
+            if (item.empty()) {
+                break;
+            }
 
-        // Look for an item that matches and (if deduping) that we haven't seen before.
-        if (!item.matches_search(canon_term_, search_type_, !ignores_case())) {
-            continue;
-        }
+            // Look for an item that matches and (if deduping) that we haven't seen before.
+            bool matches = regex_ ? regex_->match(item.str()).has_value()
+                                  : item.matches_search(canon_term_, search_type_, !ignores_case());
+            if (!matches || !filter_.matches(item)) {
+                continue;
+            }
 
-        // Skip if deduplicating.
-        if (dedup() && !deduper_.insert(item.str()).second) {
-            continue;
+            // Skip items that belong to the other pass.
+            if (tiered && (item.get_cwd() == preferred_cwd_) != in_cwd_tier_) {
+                continue;
+            }
+
+            // Skip if deduplicating.
+            if (dedup() && !deduper_.insert(item.str()).second) {
+                continue;
+            }
+
+            // This is our new item.
+            current_item_ = std::move(item);
+            current_index_ = index;
+            return true;
         }
 
-        // This is our new item.
-        current_item_ = std::move(item);
-        current_index_ = index;
-        return true;
+        if (!tiered || !in_cwd_tier_) return false;
+        // Now find the items run elsewhere.
+        in_cwd_tier_ = false;
+        index = starting_index_;
+    }
+}
+
+void history_search_t::compile_regex() {
+    re::flags_t flags;
+    flags.icase = ignores_case();
+    if (auto regex = re::regex_t::try_compile(orig_term_, flags)) {
+        regex_ = std::make_shared<const re::regex_t>(regex.acquire());
     }
-    return false;
 }
 
 const history_item_t &history_search_t::current_item() const {
@@ -681,6 +913,10 @@ void history_impl_t::clear_file_state() {
     file_contents.reset();
     loaded_old = false;
     old_item_offsets.clear();
+    old_item_cursor = 0;
+    indexed_all_old = false;
+    old_usage.clear();
+    indexed_old_usage = false;
 }
 
 void history_impl_t::compact_new_items() {
@@ -726,12 +962,20 @@ bool history_impl_t::rewrite_to_temporary_file(int existing_fd, int dst_fd) cons
     // Make an LRU cache to save only the last N elements.
     history_lru_cache_t lru(HISTORY_SAVE_MAX);
 
+    // Items encrypted with a key we don't have, which we keep as they are.
+    std::string unreadable;
+
     // Read in existing items (which may have changed out from underneath us, so don't trust our
     // old file contents).
     if (auto local_file = history_file_contents_t::create(existing_fd)) {
         size_t cursor = 0;
         maybe_t<size_t> offset;
         while ((offset = local_file->offset_of_next_item(&cursor, 0)).has_value()) {
+            if (!local_file->is_readable(*offset)) {
+                unreadable.append(local_file->raw_item(*offset));
+                continue;
+            }
+
             // Try decoding an old item.
             history_item_t old_item = local_file->decode_item(*offset);
 
@@ -754,6 +998,11 @@ bool history_impl_t::rewrite_to_temporary_file(int existing_fd, int dst_fd) cons
         }
     }
 
+    // Insert any imported items. These come after the ones in the file, but sorting sorts them in.
+    for (const history_item_t &item : imported_items) {
+        lru.add_item(item);
+    }
+
     // Insert any unwritten new items
     for (auto iter = new_items.cbegin() + this->first_unwritten_new_item_index;
          iter != new_items.cend(); ++iter) {
@@ -769,10 +1018,10 @@ bool history_impl_t::rewrite_to_temporary_file(int existing_fd, int dst_fd) cons
         return item1.timestamp() < item2.timestamp();
     });
 
-    // Write them out.
+    // Write them out, after the ones we can't read.
     int err = 0;
-    std::string buffer;
-    buffer.reserve(HISTORY_OUTPUT_BUFFER_SIZE + 128);
+    std::string buffer = std::move(unreadable);
+    buffer.reserve(buffer.size() + HISTORY_OUTPUT_BUFFER_SIZE + 128);
     for (const auto key_item : lru) {
         append_history_item_to_buffer(key_item.second, &buffer);
         err = flush_to_fd(&buffer, dst_fd, HISTORY_OUTPUT_BUFFER_SIZE);
@@ -981,8 +1230,8 @@ bool history_impl_t::save_internal_via_appending() {
         // for much anyways).
 
         // So far so good. Write all items at or after first_unwritten_new_item_index. Note that we
-        // write even a pending item - pending items are ignored by history within the command
-        // itself, but should still be written to the file.
+        // write even a pending item if asked to save explicitly - pending items are ignored by
+        // history within the command itself, but should still be written to the file.
         // TODO: consider filling the buffer ahead of time, so we can just lock, splat, and unlock?
         int err = 0;
         // Use a small buffer size for appending, we usually only have 1 item
@@ -1057,7 +1306,7 @@ void history_impl_t::save(bool vacuum) {
 // Returns nothing. The only possible failure involves formatting the timestamp. If that happens we
 // simply omit the timestamp from the output.
 static void format_history_record(const history_item_t &item, const wchar_t *show_time_format,
-                                  bool null_terminate, wcstring *result) {
+                                  bool show_status, bool null_terminate, wcstring *result) {
     result->clear();
     if (show_time_format) {
         const time_t seconds = item.timestamp();
@@ -1071,6 +1320,17 @@ static void format_history_record(const history_item_t &item, const wchar_t *sho
             }
         }
     }
+    if (show_status) {
+        if (item.get_exit_status() >= 0) {
+            append_format(*result, L"# status %d", item.get_exit_status());
+        } else {
+            result->append(L"# status unknown");
+        }
+        if (item.get_duration_ms() >= 0) {
+            append_format(*result, L", %.3f seconds", item.get_duration_ms() / 1000.0);
+        }
+        result->push_back(L'\n');
+    }
 
     result->append(item.str());
     result->push_back(null_terminate ? L'\0' : L'\n');
@@ -1090,6 +1350,7 @@ void history_impl_t::enable_automatic_saving() {
 void history_impl_t::clear() {
     new_items.clear();
     deleted_items.clear();
+    session_run_counts.clear();
     first_unwritten_new_item_index = 0;
     old_item_offsets.clear();
     if (maybe_t<wcstring> filename = history_filename(name)) {
@@ -1104,6 +1365,7 @@ void history_impl_t::clear_session() {
     }
 
     new_items.clear();
+    session_run_counts.clear();
     first_unwritten_new_item_index = 0;
 }
 
@@ -1116,6 +1378,7 @@ bool history_impl_t::is_empty() {
     bool empty = false;
     if (loaded_old) {
         // If we've loaded old items, see if we have any offsets.
+        index_old_items(1);
         empty = old_item_offsets.empty();
     } else {
         // If we have not loaded old items, don't actually load them (which may be expensive); just
@@ -1210,6 +1473,300 @@ static bool should_import_bash_history_line(const wcstring &line) {
     return errors->empty();
 }
 
+namespace {
+/// A reader for the JSON that `history export --format=json` prints: an array of objects, whose
+/// values are strings, numbers or arrays of strings. Anything else is skipped.
+class history_json_reader_t {
+   public:
+    explicit history_json_reader_t(const wcstring &text) : text_(text) {}
+
+    /// Read all items. \return false on a syntax error, with a message in \p out_err.
+    bool read(std::vector<history_item_t> *items, wcstring *out_err) {
+        if (!expect(L'[')) return fail(out_err);
+        if (!try_consume(L']')) {
+            do {
+                history_item_t item;
+                if (!read_item(&item)) return fail(out_err);
+                if (!item.empty()) items->push_back(std::move(item));
+            } while (try_consume(L','));
+            if (!expect(L']')) return fail(out_err);
+        }
+        skip_space();
+        if (pos_ != text_.size()) return fail(out_err);
+        return true;
+    }
+
+   private:
+    const wcstring &text_;
+    size_t pos_{0};
+
+    bool fail(wcstring *out_err) const {
+        *out_err = format_string(_(L"invalid JSON at offset %lu"),
+                                 static_cast<unsigned long>(std::min(pos_, text_.size())));
+        return false;
+    }
+
+    void skip_space() {
+        while (pos_ < text_.size() && iswspace(text_[pos_])) pos_++;
+    }
+
+    bool try_consume(wchar_t c) {
+        skip_space();
+        if (pos_ < text_.size() && text_[pos_] == c) {
+            pos_++;
+            return true;
+        }
+        return false;
+    }
+
+    bool expect(wchar_t c) { return try_consume(c); }
+
+    bool read_item(history_item_t *item) {
+        if (!expect(L'{')) return false;
+        wcstring command, cwd;
+        long long when = 0, status = -1, duration = -1;
+        path_list_t paths;
+        if (!try_consume(L'}')) {
+            do {
+                wcstring key;
+                if (!read_string(&key) || !expect(L':')) return false;
+                bool ok;
+                if (key == L"command") {
+                    ok = read_string(&command);
+                } else if (key == L"timestamp") {
+                    ok = read_number(&when);
+                } else if (key == L"cwd") {
+                    ok = read_string(&cwd);
+                } else if (key == L"paths") {
+                    ok = read_strings(&paths);
+                } else if (key == L"status") {
+                    ok = read_number_or_null(&status);
+                } else if (key == L"duration") {
+                    ok = read_number_or_null(&duration);
+                } else {
+                    ok = skip_value();
+                }
+                if (!ok) return false;
+            } while (try_consume(L','));
+            if (!expect(L'}')) return false;
+        }
+        *item = history_item_t(std::move(command), static_cast<time_t>(when));
+        item->set_required_paths(std::move(paths));
+        item->set_cwd(std::move(cwd));
+        item->set_exit_status(static_cast<int>(status));
+        item->set_duration_ms(duration);
+        return true;
+    }
+
+    bool read_string(wcstring *out) {
+        if (!expect(L'"')) return false;
+        out->clear();
+        while (pos_ < text_.size()) {
+            wchar_t c = text_[pos_++];
+            if (c == L'"') return true;
+            if (c != L'\\') {
+                out->push_back(c);
+                continue;
+            }
+            if (pos_ >= text_.size()) return false;
+            c = text_[pos_++];
+            switch (c) {
+                case L'"':
+                case L'\\':
+                case L'/':
+                    out->push_back(c);
+                    break;
+                case L'b':
+                    out->push_back(L'\b');
+                    break;
+                case L'f':
+                    out->push_back(L'\f');
+                    break;
+                case L'n':
+                    out->push_back(L'\n');
+                    break;
+                case L'r':
+                    out->push_back(L'\r');
+                    break;
+                case L't':
+                    out->push_back(L'\t');
+                    break;
+                case L'u': {
+                    long code;
+                    if (!read_hex4(&code)) return false;
+                    // Combine a surrogate pair.
+                    if (code >= 0xD800 && code < 0xDC00 && pos_ + 1 < text_.size() &&
+                        text_[pos_] == L'\\' && text_[pos_ + 1] == L'u') {
+                        size_t save = pos_;
+                        pos_ += 2;
+                        long low;
+                        if (read_hex4(&low) && low >= 0xDC00 && low < 0xE000) {
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        } else {
+                            pos_ = save;
+                        }
+                    }
+                    out->push_back(static_cast<wchar_t>(code));
+                    break;
+                }
+                default:
+                    return false;
+            }
+        }
+        return false;
+    }
+
+    bool read_hex4(long *out) {
+        if (pos_ + 4 > text_.size()) return false;
+        *out = 0;
+        for (size_t i = 0; i < 4; i++) {
+            long digit = convert_digit(text_[pos_++], 16);
+            if (digit < 0) return false;
+            *out = *out * 16 + digit;
+        }
+        return true;
+    }
+
+    bool read_number(long long *out) {
+        skip_space();
+        const wchar_t *start = text_.c_str() + pos_;
+        const wchar_t *end = start;
+        *out = fish_wcstoll(start, &end);
+        if (end == start) return false;
+        // Skip a fraction or exponent, which timestamps don't have.
+        while (*end && std::wcschr(L"0123456789.eE+-", *end)) end++;
+        pos_ += end - start;
+        return true;
+    }
+
+    /// Read a number, or null for an unknown one, which leaves \p out alone.
+    bool read_number_or_null(long long *out) {
+        skip_space();
+        if (text_.compare(pos_, 4, L"null") == 0) {
+            pos_ += 4;
+            return true;
+        }
+        return read_number(out);
+    }
+
+    bool read_strings(std::vector<wcstring> *out) {
+        if (!expect(L'[')) return false;
+        if (try_consume(L']')) return true;
+        do {
+            wcstring str;
+            if (!read_string(&str)) return false;
+            out->push_back(std::move(str));
+        } while (try_consume(L','));
+        return expect(L']');
+    }
+
+    bool skip_value() {
+        skip_space();
+        if (pos_ >= text_.size()) return false;
+        wchar_t c = text_[pos_];
+        if (c == L'"') {
+            wcstring ignored;
+            return read_string(&ignored);
+        } else if (c == L'[' || c == L'{') {
+            wchar_t close = c == L'[' ? L']' : L'}';
+            pos_++;
+            if (try_consume(close)) return true;
+            do {
+                if (c == L'{') {
+                    wcstring ignored;
+                    if (!read_string(&ignored) || !expect(L':')) return false;
+                }
+                if (!skip_value()) return false;
+            } while (try_consume(L','));
+            return expect(close);
+        } else if (c == L'-' || iswdigit(c)) {
+            long long ignored;
+            return read_number(&ignored);
+        }
+        for (const wchar_t *literal : {L"true", L"false", L"null"}) {
+            if (text_.compare(pos_, std::wcslen(literal), literal) == 0) {
+                pos_ += std::wcslen(literal);
+                return true;
+            }
+        }
+        return false;
+    }
+};
+}  // namespace
+
+/// Parse bash history: one command per line, after a "#TIMESTAMP" line if bash was told to record
+/// the time. Lines fish can't handle are skipped.
+static void parse_history_bash(const wcstring &text, std::vector<history_item_t> *items) {
+    time_t when = 0;
+    for (const wcstring &line : split_string(text, L'\n')) {
+        if (line.size() > 1 && line[0] == L'#') {
+            const wchar_t *end;
+            long long timestamp = fish_wcstoll(line.c_str() + 1, &end);
+            if (!errno) when = static_cast<time_t>(timestamp);
+            continue;
+        }
+        wcstring command = trim(line);
+        if (should_import_bash_history_line(command)) {
+            items->push_back(history_item_t(std::move(command), when));
+        }
+        when = 0;
+    }
+}
+
+/// zsh escapes some bytes in its history file with this one.
+static constexpr char zsh_meta = '\x83';
+
+/// \return whether zsh escapes a byte in its history file.
+static bool zsh_is_meta(char c) {
+    auto byte = static_cast<unsigned char>(c);
+    return byte == 0 || (byte >= 0x83 && byte <= 0xA2);
+}
+
+/// Parse zsh history. In extended history, lines look like ": START:DURATION;COMMAND", with the
+/// duration in seconds. Commands that span lines end each line but the last with a backslash. Lines
+/// fish can't handle are skipped.
+static void parse_history_zsh(const std::string &contents, std::vector<history_item_t> *items) {
+    std::string unescaped;
+    unescaped.reserve(contents.size());
+    for (size_t i = 0; i < contents.size(); i++) {
+        if (contents[i] == zsh_meta && i + 1 < contents.size()) {
+            unescaped.push_back(static_cast<char>(contents[++i] ^ 32));
+        } else {
+            unescaped.push_back(contents[i]);
+        }
+    }
+
+    std::vector<wcstring> lines = split_string(str2wcstring(unescaped), L'\n');
+    for (size_t i = 0; i < lines.size(); i++) {
+        wcstring command = std::move(lines[i]);
+        while (string_suffixes_string(L"\\", command) && i + 1 < lines.size()) {
+            command.back() = L'\n';
+            command.append(lines[++i]);
+        }
+
+        time_t when = 0;
+        long long duration = -1;
+        if (string_prefixes_string(L": ", command)) {
+            const wchar_t *end;
+            long long timestamp = fish_wcstoll(command.c_str() + 2, &end);
+            size_t semicolon = command.find(L';');
+            if (*end == L':' && semicolon != wcstring::npos) {
+                when = static_cast<time_t>(timestamp);
+                const wchar_t *duration_end;
+                long long seconds = fish_wcstoll(end + 1, &duration_end);
+                if (*duration_end == L';' && seconds >= 0) duration = seconds * 1000;
+                command.erase(0, semicolon + 1);
+            }
+        }
+        command = trim(command);
+        if (should_import_bash_history_line(command)) {
+            history_item_t item(std::move(command), when);
+            item.set_duration_ms(duration);
+            items->push_back(std::move(item));
+        }
+    }
+}
+
 /// Import a bash command history file. Bash's history format is very simple: just lines with #s for
 /// comments. Ignore a few commands that are bash-specific. It makes no attempt to handle multiline
 /// commands. We can't actually parse bash syntax and the bash history file does not unambiguously
@@ -1247,12 +1804,28 @@ void history_impl_t::populate_from_bash(FILE *stream) {
     this->save_unless_disabled();
 }
 
+bool history_impl_t::import_items(std::vector<history_item_t> items) {
+    // We only see the items in the file up to our boundary timestamp, so newer items, and the ones
+    // whose time is unknown, are made that old.
+    for (history_item_t &item : items) {
+        if (item.creation_timestamp == 0 || item.creation_timestamp > boundary_timestamp) {
+            item.creation_timestamp = boundary_timestamp;
+        }
+    }
+
+    // Rewriting the file merges the items with the ones already in it and sorts them all by time.
+    imported_items = std::move(items);
+    bool ok = save_internal_via_rewrite();
+    imported_items.clear();
+    return ok;
+}
+
 void history_impl_t::incorporate_external_changes() {
     // To incorporate new items, we simply update our timestamp to now, so that items from previous
     // instances get added. We then clear the file state so that we remap the file. Note that this
-    // is somewhat expensive because we will be going back over old items. An optimization would be
-    // to preserve old_item_offsets so that they don't have to be recomputed. (However, then items
-    // *deleted* in other instances would not show up here).
+    // is somewhat expensive because we will be going back over the old items we use again. An
+    // optimization would be to preserve old_item_offsets so that they don't have to be recomputed.
+    // (However, then items *deleted* in other instances would not show up here).
     time_t new_timestamp = time(nullptr);
 
     // If for some reason the clock went backwards, we don't want to start dropping items; therefore
@@ -1272,6 +1845,25 @@ void history_impl_t::incorporate_external_changes() {
     }
 }
 
+void history_impl_t::sync_external_changes() {
+    // Merging clears our new items, so we only do it if the file has all of them. A pending item
+    // is still running, and may be left out because of file detection.
+    if (has_pending_item) return;
+    for (const auto &item : new_items) {
+        if (!item.should_write_to_disk()) return;
+    }
+
+    // Our own saves update history_file_id, so a different file means another shell wrote to it.
+    maybe_t<wcstring> filename = history_filename(name);
+    if (!filename) return;
+    file_id_t file_id = file_id_for_path(*filename);
+    if (file_id == kInvalidFileID || file_id == this->history_file_id) return;
+
+    incorporate_external_changes();
+    // Map the file again now, which remembers its id.
+    load_old_if_needed();
+}
+
 /// Return the prefix for the files to be used for command and read history.
 wcstring history_session_id(std::unique_ptr<env_var_t> fish_history) {
     wcstring result = DFLT_FISH_HISTORY_SESSION_ID;
@@ -1295,7 +1887,8 @@ wcstring history_session_id(std::unique_ptr<env_var_t> fish_history) {
 }
 
 wcstring history_session_id(const environment_t &vars) {
-    auto fish_history = vars.get(L"fish_history");
+    auto fish_history = vars.get(L"fish_history_session");
+    if (!fish_history) fish_history = vars.get(L"fish_history");
     auto var =
         fish_history ? std::make_unique<env_var_t>(*fish_history) : std::unique_ptr<env_var_t>{};
     return history_session_id(std::move(var));
@@ -1358,6 +1951,22 @@ struct history_t::impl_wrapper_t {
 /// Very simple, just mark that we have no more pending items.
 void history_impl_t::resolve_pending() { this->has_pending_item = false; }
 
+void history_impl_t::finish_pending(int exit_status, int64_t duration_ms) {
+    if (unfinished_identifier != 0) {
+        // The item is likely to be at the end of new_items.
+        for (auto iter = new_items.rbegin(); iter != new_items.rend(); ++iter) {
+            if (iter->identifier == unfinished_identifier) {
+                iter->exit_status = exit_status;
+                iter->duration_ms = duration_ms;
+                break;
+            }
+        }
+        unfinished_identifier = 0;
+    }
+    resolve_pending();
+    save_unless_disabled();
+}
+
 bool history_t::chaos_mode = false;
 
 /* OpenBSD's mmap is not synchronized with other file operations. In particular it appears we may
@@ -1379,6 +1988,8 @@ acquired_lock<const history_impl_t> history_t::impl() const { return wrap_->impl
 
 bool history_t::is_default() const { return impl()->is_default(); }
 
+maybe_t<wcstring> history_t::filename() const { return history_filename(impl()->name); }
+
 bool history_t::is_empty() { return impl()->is_empty(); }
 
 void history_t::add(history_item_t &&item, bool pending) { impl()->add(std::move(item), pending); }
@@ -1442,6 +2053,9 @@ void history_t::add_pending_with_file_detection(const std::shared_ptr<history_t>
     time_t when = imp->timestamp_now();
     history_identifier_t identifier = imp->next_identifier();
     history_item_t item{str, when, identifier, persist_mode};
+    if (auto pwd = vars->get(L"PWD")) {
+        item.set_cwd(pwd->as_string());
+    }
 
     if (wants_file_detection) {
         imp->disable_automatic_saving();
@@ -1469,16 +2083,22 @@ void history_t::add_pending_with_file_detection(const std::shared_ptr<history_t>
 }
 void history_t::resolve_pending() { impl()->resolve_pending(); }
 
+void history_t::finish_pending(int exit_status, int64_t duration_ms) {
+    impl()->finish_pending(exit_status, duration_ms);
+}
+
 void history_t::save() { impl()->save(); }
 
 /// Perform a search of \p hist for \p search_string. Invoke a function \p func for each match. If
 /// \p func returns true, continue the search; else stop it.
 static void do_1_history_search(history_t *hist, history_search_type_t search_type,
-                                const wcstring &search_string, bool case_sensitive,
+                                const wcstring &search_string, const history_filter_t &filter,
+                                bool case_sensitive,
                                 const std::function<bool(const history_item_t &item)> &func,
                                 const cancel_checker_t &cancel_check) {
     history_search_t searcher = history_search_t(hist, search_string, search_type,
                                                  case_sensitive ? 0 : history_search_ignore_case);
+    searcher.set_filter(filter);
     while (!cancel_check() && searcher.go_to_next_match(history_search_direction_t::backward)) {
         if (!func(searcher.current_item())) {
             break;
@@ -1486,12 +2106,51 @@ static void do_1_history_search(history_t *hist, history_search_type_t search_ty
     }
 }
 
+/// Write history items as a JSON array.
+static void write_history_json(const std::vector<history_item_t> &items, io_streams_t &streams) {
+    auto w = new_json_writer();
+    w->begin_array();
+    for (const history_item_t &item : items) {
+        w->begin_object();
+        w->key(L"command");
+        w->string(item.str());
+        w->key(L"timestamp");
+        w->integer(item.timestamp());
+        w->key(L"paths");
+        w->begin_array();
+        for (const wcstring &path : item.get_required_paths()) {
+            w->string(path);
+        }
+        w->end_array();
+        w->key(L"cwd");
+        w->string(item.get_cwd());
+        w->key(L"status");
+        if (item.get_exit_status() >= 0) {
+            w->integer(item.get_exit_status());
+        } else {
+            w->null();
+        }
+        w->key(L"duration");
+        if (item.get_duration_ms() >= 0) {
+            w->integer(item.get_duration_ms());
+        } else {
+            w->null();
+        }
+        w->end_object();
+    }
+    w->end_array();
+    streams.out.append(*w->contents());
+    streams.out.push(L'\n');
+}
+
 // Searches history.
 bool history_t::search(history_search_type_t search_type, const std::vector<wcstring> &search_args,
-                       const wchar_t *show_time_format, size_t max_items, bool case_sensitive,
-                       bool null_terminate, bool reverse, const cancel_checker_t &cancel_check,
-                       io_streams_t &streams) {
+                       const history_filter_t &filter, const wchar_t *show_time_format,
+                       bool show_status, size_t max_items, bool case_sensitive,
+                       bool null_terminate, bool reverse, bool json,
+                       const cancel_checker_t &cancel_check, io_streams_t &streams) {
     std::vector<wcstring> collected;
+    std::vector<history_item_t> json_items;
     wcstring formatted_record;
     size_t remaining = max_items;
     bool output_error = false;
@@ -1501,7 +2160,12 @@ bool history_t::search(history_search_type_t search_type, const std::vector<wcst
     std::function<bool(const history_item_t &item)> func = [&](const history_item_t &item) -> bool {
         if (remaining == 0) return false;
         remaining -= 1;
-        format_history_record(item, show_time_format, null_terminate, &formatted_record);
+        if (json) {
+            json_items.push_back(item);
+            return true;
+        }
+        format_history_record(item, show_time_format, show_status, null_terminate,
+                              &formatted_record);
         if (reverse) {
             // We need to collect this for later.
             collected.push_back(std::move(formatted_record));
@@ -1518,19 +2182,25 @@ bool history_t::search(history_search_type_t search_type, const std::vector<wcst
 
     if (search_args.empty()) {
         // The user had no search terms; just append everything.
-        do_1_history_search(this, history_search_type_t::match_everything, {}, false, func,
-                            cancel_check);
+        do_1_history_search(this, history_search_type_t::match_everything, {}, filter, false,
+                            func, cancel_check);
     } else {
         for (const wcstring &search_string : search_args) {
             if (search_string.empty()) {
                 streams.err.append_format(L"Searching for the empty string isn't allowed");
                 return false;
             }
-            do_1_history_search(this, search_type, search_string, case_sensitive, func,
+            do_1_history_search(this, search_type, search_string, filter, case_sensitive, func,
                                 cancel_check);
         }
     }
 
+    if (json) {
+        if (reverse) std::reverse(json_items.begin(), json_items.end());
+        write_history_json(json_items, streams);
+        return true;
+    }
+
     // Output any items we collected (which only happens in reverse).
     for (auto iter = collected.rbegin(); !output_error && iter != collected.rend(); ++iter) {
         if (!streams.out.append(*iter)) {
@@ -1545,6 +2215,115 @@ bool history_t::search(history_search_type_t search_type, const std::vector<wcst
     return true;
 }
 
+size_t history_t::remove_matching(history_search_type_t search_type,
+                                  const std::vector<wcstring> &search_args,
+                                  const history_filter_t &filter, bool case_sensitive,
+                                  const cancel_checker_t &cancel_check) {
+    // Collect the matches first, since removing items while searching would shift their indexes.
+    std::unordered_set<wcstring> matches;
+    auto func = [&](const history_item_t &item) {
+        matches.insert(item.str());
+        return true;
+    };
+    if (search_args.empty()) {
+        do_1_history_search(this, history_search_type_t::match_everything, {}, filter, false,
+                            func, cancel_check);
+    }
+    for (const wcstring &search_string : search_args) {
+        do_1_history_search(this, search_type, search_string, filter, case_sensitive, func,
+                            cancel_check);
+    }
+    if (cancel_check()) return 0;
+
+    for (const wcstring &text : matches) {
+        remove(text);
+    }
+    return matches.size();
+}
+
+void history_t::export_items(history_format_t format, const cancel_checker_t &cancel_check,
+                             io_streams_t &streams) {
+    std::vector<history_item_t> items;
+    do_1_history_search(
+        this, history_search_type_t::match_everything, {}, {}, false,
+        [&](const history_item_t &item) {
+            items.push_back(item);
+            return true;
+        },
+        cancel_check);
+    // The search goes from newest to oldest, other shells' files from oldest to newest.
+    std::reverse(items.begin(), items.end());
+
+    if (format == history_format_t::json) {
+        write_history_json(items, streams);
+        return;
+    }
+
+    for (const history_item_t &item : items) {
+        if (cancel_check()) return;
+        std::string line;
+        if (format == history_format_t::bash) {
+            // bash keeps a command to a line, so a command with newlines becomes several.
+            line = "#" + std::to_string(item.timestamp()) + "\n" + wcs2string(item.str()) + "\n";
+        } else {
+            int64_t seconds = std::max(item.get_duration_ms(), int64_t(0)) / 1000;
+            line = ": " + std::to_string(item.timestamp()) + ":" + std::to_string(seconds) + ";";
+            for (char c : wcs2string(item.str())) {
+                if (c == '\n') {
+                    line.append("\\\n");
+                } else if (zsh_is_meta(c)) {
+                    line.push_back(zsh_meta);
+                    line.push_back(static_cast<char>(c ^ 32));
+                } else {
+                    line.push_back(c);
+                }
+            }
+            line.push_back('\n');
+        }
+        if (!streams.out.append(str2wcstring(line))) return;
+    }
+}
+
+maybe_t<size_t> history_t::import_items(history_format_t format, const std::string &contents,
+                                        wcstring *out_err) {
+    std::vector<history_item_t> items;
+    switch (format) {
+        case history_format_t::json: {
+            if (!history_json_reader_t(str2wcstring(contents)).read(&items, out_err)) {
+                return none();
+            }
+            break;
+        }
+        case history_format_t::bash: {
+            parse_history_bash(str2wcstring(contents), &items);
+            break;
+        }
+        case history_format_t::zsh: {
+            parse_history_zsh(contents, &items);
+            break;
+        }
+    }
+
+    size_t count = items.size();
+    if (count > 0 && !impl()->import_items(std::move(items))) {
+        *out_err = _(L"could not write the history file");
+        return none();
+    }
+    return count;
+}
+
+maybe_t<size_t> history_t::merge_from(history_t &other) {
+    // Read the items oldest first, as they are in the file.
+    std::vector<history_item_t> items;
+    size_t count = other.size();
+    items.reserve(count);
+    for (size_t idx = count; idx > 0; idx--) {
+        items.push_back(other.item_at_index(idx));
+    }
+    if (count > 0 && !impl()->import_items(std::move(items))) return none();
+    return count;
+}
+
 void history_t::clear() { impl()->clear(); }
 
 void history_t::clear_session() { impl()->clear_session(); }
@@ -1555,6 +2334,8 @@ void history_t::populate_from_bash(FILE *f) { impl()->populate_from_bash(f); }
 
 void history_t::incorporate_external_changes() { impl()->incorporate_external_changes(); }
 
+void history_t::sync_external_changes() { impl()->sync_external_changes(); }
+
 void history_t::get_history(std::vector<wcstring> &result) { impl()->get_history(result); }
 
 std::unordered_map<long, wcstring> history_t::items_at_indexes(const std::vector<long> &idxs) {
@@ -1565,6 +2346,12 @@ history_item_t history_t::item_at_index(size_t idx) { return impl()->item_at_ind
 
 size_t history_t::size() { return impl()->size(); }
 
+std::vector<wcstring> history_t::search_ranked(const wcstring &term, size_t offset, size_t count,
+                                               size_t *out_total,
+                                               const cancel_checker_t &cancel_check) {
+    return impl()->search_ranked(term, offset, count, out_total, cancel_check);
+}
+
 /// The set of all histories.
 static owning_lock<std::map<wcstring, std::shared_ptr<history_t>>> s_histories;
 
@@ -1575,6 +2362,21 @@ void history_save_all() {
     }
 }
 
+bool history_t::set_encryption_key(const wcstring &key) {
+    std::string narrow = wcs2string(key);
+    const auto *bytes = reinterpret_cast<const uint8_t *>(narrow.data());
+    bool ok = history_crypt_set_key(rust::Slice<const uint8_t>(bytes, narrow.size()));
+    auto histories = s_histories.acquire();
+    for (auto &p : *histories) {
+        auto imp = p.second->impl();
+        // Which items we can read may have changed.
+        imp->clear_file_state();
+        // Rewrite the file, so the items already in it are encrypted too.
+        if (ok && !key.empty()) imp->save_internal_via_rewrite();
+    }
+    return ok;
+}
+
 std::shared_ptr<history_t> history_t::with_name(const wcstring &name) {
     auto hs = s_histories.acquire();
     std::shared_ptr<history_t> &hist = (*hs)[name];
@@ -1584,11 +2386,123 @@ std::shared_ptr<history_t> history_t::with_name(const wcstring &name) {
     return hist;
 }
 
+history_watcher_t::history_watcher_t() = default;
+history_watcher_t::~history_watcher_t() = default;
+
+history_watcher_t &history_watcher_t::default_watcher() {
+    static history_watcher_t *const s_watcher = new history_watcher_t();
+    return *s_watcher;
+}
+
+void history_watcher_t::watch(const std::shared_ptr<history_t> &history) {
+    if (history == history_) return;
+    history_ = history;
+    maybe_t<wcstring> path = history ? history->filename() : none();
+    path_ = path ? path.acquire() : wcstring{};
+    rewatch();
+}
+
+void history_watcher_t::rewatch() {
+    queue_.close();
+    watched_.close();
+    if (path_.empty()) return;
+#if defined(HAVE_INOTIFY_INIT1)
+    queue_.reset(inotify_init1(IN_NONBLOCK | IN_CLOEXEC));
+    if (!queue_.valid()) {
+        FLOGF(history, "Could not watch history file: %s", std::strerror(errno));
+        return;
+    }
+    // Watch the directory, since vacuuming replaces the file with another one, and the file may not
+    // exist yet. notification_fd_became_readable() skips the other files there.
+    std::string dir = wcs2zstring(wdirname(path_));
+    if (inotify_add_watch(queue_.fd(), dir.c_str(), IN_MODIFY | IN_MOVED_TO) < 0) {
+        FLOGF(history, "Could not watch history file: %s", std::strerror(errno));
+        queue_.close();
+    }
+#elif defined(HAVE_KQUEUE)
+    queue_.reset(kqueue());
+    if (!queue_.valid() || set_cloexec(queue_.fd()) != 0) {
+        FLOGF(history, "Could not watch history file: %s", std::strerror(errno));
+        queue_.close();
+        return;
+    }
+    // Watch the file, or its directory until the file exists. Vacuuming replaces the file with
+    // another one, which deletes the one we watch, so then we watch the new one.
+    watched_.reset(wopen_cloexec(path_, O_RDONLY));
+    watching_directory_ = !watched_.valid();
+    if (watching_directory_) watched_.reset(wopen_cloexec(wdirname(path_), O_RDONLY));
+    struct kevent change;
+    EV_SET(&change, watched_.fd(), EVFILT_VNODE, EV_ADD | EV_CLEAR,
+           NOTE_WRITE | NOTE_EXTEND | NOTE_DELETE | NOTE_RENAME, 0, nullptr);
+    if (!watched_.valid() || kevent(queue_.fd(), &change, 1, nullptr, 0, nullptr) < 0) {
+        FLOGF(history, "Could not watch history file: %s", std::strerror(errno));
+        queue_.close();
+        watched_.close();
+    }
+#endif
+}
+
+bool history_watcher_t::notification_fd_became_readable() {
+    bool changed = false;
+#if defined(HAVE_INOTIFY_INIT1)
+    const wcstring name = wbasename(path_);
+    alignas(struct inotify_event) char buf[4096];
+    ssize_t amt;
+    while ((amt = read(queue_.fd(), buf, sizeof buf)) > 0) {
+        for (ssize_t offset = 0; offset < amt;) {
+            const auto *event = reinterpret_cast<const struct inotify_event *>(buf + offset);
+            if (event->len > 0 && str2wcstring(event->name) == name) changed = true;
+            offset += sizeof(struct inotify_event) + event->len;
+        }
+    }
+#elif defined(HAVE_KQUEUE)
+    bool replaced = false;
+    struct kevent events[8];
+    const struct timespec no_wait = {0, 0};
+    int count;
+    while ((count = kevent(queue_.fd(), nullptr, 0, events, 8, &no_wait)) > 0) {
+        for (int i = 0; i < count; i++) {
+            changed = true;
+            if (events[i].fflags & (NOTE_DELETE | NOTE_RENAME)) replaced = true;
+        }
+    }
+    if (changed && (replaced || watching_directory_)) rewatch();
+#endif
+    return changed;
+}
+
 void start_private_mode(env_stack_t &vars) {
     vars.set_one(L"fish_history", ENV_GLOBAL, L"");
+    vars.set_one(L"fish_history_session", ENV_GLOBAL, L"");
     vars.set_one(L"fish_private_mode", ENV_GLOBAL, L"1");
 }
 
 bool in_private_mode(const environment_t &vars) {
     return vars.get_unless_empty(L"fish_private_mode").has_value();
 }
+
+/// \return whether \p text matches one of \p patterns. A pattern is a glob that must match the
+/// whole command, or with a "regex:" prefix, a regular expression that may match any part of it.
+/// A regex that does not compile matches nothing.
+static bool history_ignore_matches(const wcstring &text, const std::vector<wcstring> &patterns) {
+    const wcstring regex_prefix = L"regex:";
+    for (const wcstring &pattern : patterns) {
+        if (string_prefixes_string(regex_prefix, pattern)) {
+            auto re = re::regex_t::try_compile(pattern.substr(regex_prefix.size()));
+            if (re && re->match(text).has_value()) return true;
+        } else if (!pattern.empty() &&
+                   wildcard_match(text, parse_util_unescape_wildcards(pattern))) {
+            return true;
+        }
+    }
+    return false;
+}
+
+maybe_t<history_persistence_mode_t> history_ignore_mode(const wcstring &text,
+                                                        const environment_t &vars) {
+    auto ignore = vars.get_unless_empty(L"fish_history_ignore");
+    if (!ignore || !history_ignore_matches(text, ignore->as_list())) return none();
+    auto mode = vars.get(L"fish_history_ignore_mode");
+    if (mode && mode->as_string() == L"drop") return history_persistence_mode_t::ephemeral;
+    return history_persistence_mode_t::memory;
+}
@@ -17,6 +17,7 @@
 #include <vector>
 
 #include "common.h"
+#include "fds.h"
 #include "maybe.h"
 #include "wutil.h"  // IWYU pragma: keep
 
@@ -24,6 +25,9 @@ struct io_streams_t;
 class env_stack_t;
 class environment_t;
 class operation_context_t;
+namespace re {
+class regex_t;
+}
 
 /**
 Fish supports multiple shells writing to history at once. Here is its strategy:
@@ -59,12 +63,24 @@ enum class history_search_type_t {
     prefix_glob,
     /// Search for commands containing the given string as a subsequence
     contains_subsequence,
+    /// Search for commands matching the given regular expression.
+    regex,
     /// Matches everything.
     match_everything,
 };
 
 using history_identifier_t = uint64_t;
 
+/// Formats that history can be exported to and imported from.
+enum class history_format_t {
+    /// A JSON array of objects, like `history search --json` prints.
+    json,
+    /// Lines of commands, each after a `#TIMESTAMP` line like bash writes with HISTTIMEFORMAT.
+    bash,
+    /// zsh's extended history, with lines like `: TIMESTAMP:DURATION;COMMAND`.
+    zsh,
+};
+
 /// Ways that a history item may be written to disk (or omitted).
 enum class history_persistence_mode_t : uint8_t {
     disk,       // the history item is written to disk normally
@@ -101,6 +117,19 @@ class history_item_t {
     const path_list_t &get_required_paths() const { return required_paths; }
     void set_required_paths(path_list_t paths) { required_paths = std::move(paths); }
 
+    /// Get and set the directory the command was run in, or an empty string if unknown.
+    /// This is used to prefer commands run in the current directory.
+    const wcstring &get_cwd() const { return cwd; }
+    void set_cwd(wcstring dir) { cwd = std::move(dir); }
+
+    /// Get and set the exit status of the command, or -1 if unknown.
+    int get_exit_status() const { return exit_status; }
+    void set_exit_status(int status) { exit_status = status; }
+
+    /// Get and set how long the command ran in milliseconds, or -1 if unknown.
+    int64_t get_duration_ms() const { return duration_ms; }
+    void set_duration_ms(int64_t ms) { duration_ms = ms; }
+
    private:
     /// Attempts to merge two compatible history items together.
     bool merge(const history_item_t &item);
@@ -114,6 +143,15 @@ class history_item_t {
     /// Paths that we require to be valid for this item to be autosuggested.
     path_list_t required_paths;
 
+    /// The working directory the command was run in.
+    wcstring cwd;
+
+    /// The exit status of the command, or -1 if unknown.
+    int exit_status{-1};
+
+    /// How long the command ran in milliseconds, or -1 if unknown.
+    int64_t duration_ms{-1};
+
     /// Sometimes unique identifier used for hinting.
     history_identifier_t identifier;
 
@@ -128,6 +166,30 @@ class history_item_t {
 
 using history_item_list_t = std::deque<history_item_t>;
 
+/// Restricts which items a history search finds, besides its search term. Unset parts don't
+/// restrict anything.
+struct history_filter_t {
+    /// Only items from before this time.
+    maybe_t<time_t> before{};
+
+    /// Only items from this time or later.
+    maybe_t<time_t> after{};
+
+    /// Only items run in this directory.
+    wcstring cwd{};
+
+    /// Only items whose command exited with this status.
+    maybe_t<int> exit_status{};
+
+    /// \return whether nothing is restricted.
+    bool empty() const {
+        return !before.has_value() && !after.has_value() && cwd.empty() && !exit_status.has_value();
+    }
+
+    /// \return whether an item passes the filter.
+    bool matches(const history_item_t &item) const;
+};
+
 struct history_impl_t;
 
 enum class history_search_direction_t { forward, backward };
@@ -161,9 +223,17 @@ class history_t : noncopyable_t, nonmovable_t {
     /// Returns history with the given name, creating it if necessary.
     static std::shared_ptr<history_t> with_name(const wcstring &name);
 
+    /// Sets the key that history items are encrypted with, given as 64 hex digits, and rewrites
+    /// the history files with it. An empty key turns encryption off.
+    /// \return false if the key is invalid, in which case encryption is off.
+    static bool set_encryption_key(const wcstring &key);
+
     /// Returns whether this is using the default name.
     bool is_default() const;
 
+    /// \return the path of the history file, or none() if this history is not saved.
+    maybe_t<wcstring> filename() const;
+
     /// Determines whether the history is empty. Unfortunately this cannot be const, since it may
     /// require populating the history.
     bool is_empty();
@@ -185,15 +255,44 @@ class history_t : noncopyable_t, nonmovable_t {
     /// Resolves any pending history items, so that they may be returned in history searches.
     void resolve_pending();
 
+    /// Records the exit status and duration of the command of the last pending item, resolves it
+    /// and saves it. Pending items aren't saved automatically until then, so these are written
+    /// along with them.
+    void finish_pending(int exit_status, int64_t duration_ms);
+
     /// Saves history.
     void save();
 
-    /// Searches history.
+    /// Searches history for items that pass \p filter. If \p show_status is set, each match is
+    /// preceded by its exit status and duration. If \p json is set, the matches are printed as a
+    /// JSON array.
     bool search(history_search_type_t search_type, const std::vector<wcstring> &search_args,
-                const wchar_t *show_time_format, size_t max_items, bool case_sensitive,
-                bool null_terminate, bool reverse, const cancel_checker_t &cancel_check,
+                const history_filter_t &filter, const wchar_t *show_time_format,
+                bool show_status, size_t max_items, bool case_sensitive, bool null_terminate,
+                bool reverse, bool json, const cancel_checker_t &cancel_check,
                 io_streams_t &streams);
 
+    /// Removes the items that a search like the one above finds, along with all other items with
+    /// the same text. \return the number of distinct commands removed.
+    size_t remove_matching(history_search_type_t search_type,
+                           const std::vector<wcstring> &search_args, const history_filter_t &filter,
+                           bool case_sensitive, const cancel_checker_t &cancel_check);
+
+    /// Prints all items, the oldest first, in the given \p format.
+    void export_items(history_format_t format, const cancel_checker_t &cancel_check,
+                      io_streams_t &streams);
+
+    /// Reads items in the given \p format from \p contents and adds them to the history file, where
+    /// they are merged with the items already there. \return the number of items read, or none()
+    /// with an error message in \p out_err if \p contents is not in that format.
+    maybe_t<size_t> import_items(history_format_t format, const std::string &contents,
+                                 wcstring *out_err);
+
+    /// Adds the items of the history session \p other to the history file, where they are merged
+    /// with the items already there. \return the number of items read from \p other, or none() if
+    /// the history file could not be written.
+    maybe_t<size_t> merge_from(history_t &other);
+
     /// Irreversibly clears history.
     void clear();
 
@@ -209,6 +308,11 @@ class history_t : noncopyable_t, nonmovable_t {
     /// Incorporates the history of other shells into this history.
     void incorporate_external_changes();
 
+    /// Like incorporate_external_changes(), but only if another shell wrote to the history file
+    /// since we last looked at it, and only if that doesn't lose any of our items. This is cheap if
+    /// nothing changed.
+    void sync_external_changes();
+
     /// Gets all the history into a list. This is intended for the $history environment variable.
     /// This may be long!
     void get_history(std::vector<wcstring> &result);
@@ -224,6 +328,53 @@ class history_t : noncopyable_t, nonmovable_t {
 
     /// Return the number of history entries.
     size_t size();
+
+    /// Return up to \p count distinct history items that match \p term fuzzily, skipping the first
+    /// \p offset. Items are ranked by how well they match and by their frecency, i.e. how often and
+    /// how recently they were run, so the best candidate comes first. The total number of matching
+    /// items is stored in \p out_total, if given.
+    std::vector<wcstring> search_ranked(const wcstring &term, size_t offset, size_t count,
+                                        size_t *out_total, const cancel_checker_t &cancel_check);
+};
+
+/// Watches the file of a history for changes by other shells, which append commands to it and
+/// occasionally replace it with a vacuumed copy. This uses inotify on Linux and kqueue on BSD and
+/// macOS. Elsewhere there is no fd to wait on, and changes are only noticed by
+/// history_t::sync_external_changes() itself.
+class history_watcher_t : noncopyable_t, nonmovable_t {
+   public:
+    history_watcher_t();
+    ~history_watcher_t();
+
+    /// The watcher for the history of the reader, which it waits on along with its input.
+    static history_watcher_t &default_watcher();
+
+    /// Watch the file of \p history, or nothing if it is null. This is cheap if the file is
+    /// watched already.
+    void watch(const std::shared_ptr<history_t> &history);
+
+    /// \return the watched history, or null.
+    const std::shared_ptr<history_t> &history() const { return history_; }
+
+    /// \return the fd that becomes readable when the file changes, or -1 if there is none.
+    int notification_fd() const { return queue_.fd(); }
+
+    /// Called when notification_fd() became readable. \return whether the file changed.
+    bool notification_fd_became_readable();
+
+   private:
+    /// Start watching path_, dropping what we watched before.
+    void rewatch();
+
+    std::shared_ptr<history_t> history_{};
+    wcstring path_{};
+
+    /// The inotify or kqueue fd.
+    autoclose_fd_t queue_{};
+
+    /// With kqueue, the fd of the file or, if it does not exist yet, its directory.
+    autoclose_fd_t watched_{};
+    bool watching_directory_{false};
 };
 
 /// Flags for history searching.
@@ -265,6 +416,24 @@ class history_search_t {
     /// If deduping, the items we've seen.
     std::unordered_set<wcstring> deduper_;
 
+    /// If set, backward searches first find the items run in this directory, and then the others.
+    wcstring preferred_cwd_;
+
+    /// Whether we are still looking for items run in preferred_cwd_.
+    bool in_cwd_tier_{false};
+
+    /// Index we started at, where the search for the other items starts again.
+    size_t starting_index_{0};
+
+    /// Restricts which items we find.
+    history_filter_t filter_{};
+
+    /// The compiled search term, for regex searches. Null if it is not a valid regex.
+    std::shared_ptr<const re::regex_t> regex_{};
+
+    /// Compile the search term for a regex search.
+    void compile_regex();
+
     /// return whether we deduplicate items.
     bool dedup() const { return !(flags_ & history_search_no_dedup); }
 
@@ -287,6 +456,16 @@ class history_search_t {
     /// return whether we are case insensitive.
     bool ignores_case() const { return flags_ & history_search_ignore_case; }
 
+    /// Prefer items run in the directory \p cwd: a backward search first finds those, and then the
+    /// others.
+    void prefer_cwd(wcstring cwd) {
+        preferred_cwd_ = std::move(cwd);
+        in_cwd_tier_ = !preferred_cwd_.empty();
+    }
+
+    /// Only find the items that pass \p filter.
+    void set_filter(history_filter_t filter) { filter_ = std::move(filter); }
+
     /// Construct from a history pointer; the caller is responsible for ensuring the history stays
     /// alive.
     history_search_t(history_t *hist, const wcstring &str,
@@ -297,8 +476,11 @@ class history_search_t {
           canon_term_(str),
           search_type_(type),
           flags_(flags),
-          current_index_(starting_index) {
-        if (ignores_case()) {
+          current_index_(starting_index),
+          starting_index_(starting_index) {
+        if (type == history_search_type_t::regex) {
+            compile_regex();
+        } else if (ignores_case()) {
             std::transform(canon_term_.begin(), canon_term_.end(), canon_term_.begin(), towlower);
         }
     }
@@ -317,11 +499,14 @@ class history_search_t {
 void history_save_all();
 
 #if INCLUDE_RUST_HEADERS
-/** Return the prefix for the files to be used for command and read history. */
+/**
+    Return the prefix for the files to be used for command and read history. This is the session
+    named by $fish_history_session, or if that is not set, by $fish_history.
+*/
 wcstring history_session_id(const environment_t &vars);
 #endif
 
-/** FFI version of above **/
+/** FFI version of above, given $fish_history_session if set, else $fish_history. **/
 class env_var_t;
 wcstring history_session_id(std::unique_ptr<env_var_t> fish_history);
 
@@ -348,4 +533,13 @@ void start_private_mode(env_stack_t &vars);
 /** Queries private mode status. */
 bool in_private_mode(const environment_t &vars);
 
+/**
+    Checks \p text against the patterns in $fish_history_ignore.
+    \return none() if no pattern matches. Otherwise, return how the command should be kept:
+    in memory for the session, or only until the next command if $fish_history_ignore_mode is
+    "drop".
+*/
+maybe_t<history_persistence_mode_t> history_ignore_mode(const wcstring &text,
+                                                        const environment_t &vars);
+
 #endif
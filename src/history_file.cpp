@@ -16,6 +16,7 @@
 
 #include "common.h"
 #include "history.h"
+#include "history_crypt.rs.h"
 #include "path.h"
 #include "wutil.h"
 
@@ -25,6 +26,8 @@ static history_item_t decode_item_fish_1_x(const char *begin, size_t length);
 
 static maybe_t<size_t> offset_of_next_item_fish_2_0(const history_file_contents_t &contents,
                                                     size_t *inout_cursor, time_t cutoff_timestamp);
+static maybe_t<size_t> offset_of_prev_item_fish_2_0(const history_file_contents_t &contents,
+                                                    size_t *inout_cursor, time_t cutoff_timestamp);
 static maybe_t<size_t> offset_of_next_item_fish_1_x(const char *begin, size_t mmap_length,
                                                     size_t *inout_cursor);
 
@@ -199,6 +202,34 @@ history_item_t history_file_contents_t::decode_item(size_t offset) const {
     return history_item_t{};
 }
 
+bool history_file_contents_t::is_readable(size_t offset) const {
+    if (this->type() != history_type_fish_2_0) return true;
+    // Only look at the "- cmd:" line: everything in an item is encrypted with the same key.
+    const char *start = address_at(offset);
+    const char *end = static_cast<const char *>(std::memchr(start, '\n', length() - offset));
+    if (!end) end = this->end();
+    constexpr const char cmd[] = "- cmd: ";
+    constexpr size_t cmd_len = const_strlen(cmd);
+    if (static_cast<size_t>(end - start) < cmd_len || std::memcmp(start, cmd, cmd_len) != 0) {
+        return true;
+    }
+    return history_crypt_can_read(rust::Slice<const uint8_t>(
+        reinterpret_cast<const uint8_t *>(start + cmd_len), end - start - cmd_len));
+}
+
+std::string history_file_contents_t::raw_item(size_t offset) const {
+    // The item is its first line and the interior lines after it, which start with a space.
+    const char *start = address_at(offset);
+    const char *end = this->end();
+    const char *cursor = start;
+    do {
+        cursor = static_cast<const char *>(std::memchr(cursor, '\n', end - cursor));
+        if (!cursor) return std::string(start, end);
+        cursor++;
+    } while (cursor < end && *cursor == ' ');
+    return std::string(start, cursor);
+}
+
 maybe_t<size_t> history_file_contents_t::offset_of_next_item(size_t *cursor, time_t cutoff) const {
     switch (this->type()) {
         case history_type_fish_2_0:
@@ -209,6 +240,11 @@ maybe_t<size_t> history_file_contents_t::offset_of_next_item(size_t *cursor, tim
     return none();
 }
 
+maybe_t<size_t> history_file_contents_t::offset_of_prev_item(size_t *cursor, time_t cutoff) const {
+    assert(this->type() == history_type_fish_2_0 && "Only fish 2.0 files can be read backwards");
+    return offset_of_prev_item_fish_2_0(*this, cursor, cutoff);
+}
+
 /// Read one line, stripping off any newline, and updating cursor. Note that our input string is NOT
 /// null terminated; it's just a memory mapped file.
 static size_t read_line(const char *base, size_t cursor, size_t len, std::string &result) {
@@ -252,10 +288,34 @@ static bool extract_prefix_and_unescape_yaml(std::string *key, std::string *valu
     return where != std::string::npos;
 }
 
+/// \return the bytes of a string, to pass them to Rust.
+static rust::Slice<const uint8_t> as_bytes(const std::string &str) {
+    return rust::Slice<const uint8_t>(reinterpret_cast<const uint8_t *>(str.data()), str.size());
+}
+
+/// Decrypt a value of an item, which may not be encrypted at all. See history_crypt.rs.
+/// \return false if it can't be decrypted.
+static bool decrypt_value(const std::string &value, wcstring *out) {
+    rust::Vec<uint8_t> plain;
+    if (!history_crypt_decrypt(as_bytes(value), plain)) return false;
+    *out = str2wcstring(reinterpret_cast<const char *>(plain.data()), plain.size());
+    return true;
+}
+
+/// Encrypt a value of an item, if encryption is on. See history_crypt.rs.
+static std::string encrypt_value(const wcstring &value) {
+    std::string narrow = wcs2string(value);
+    rust::Vec<uint8_t> result = history_crypt_encrypt(as_bytes(narrow));
+    return std::string(reinterpret_cast<const char *>(result.data()), result.size());
+}
+
 /// Decode an item via the fish 2.0 format.
 static history_item_t decode_item_fish_2_0(const char *base, size_t len) {
     wcstring cmd;
     time_t when = 0;
+    wcstring cwd;
+    int status = -1;
+    long long duration = -1;
     path_list_t paths;
 
     size_t indent = 0, cursor = 0;
@@ -269,7 +329,9 @@ static history_item_t decode_item_fish_2_0(const char *base, size_t len) {
     }
 
     cursor += advance;
-    cmd = str2wcstring(value);
+    if (!decrypt_value(value, &cmd)) {
+        goto done;  //!OCLINT(goto is the cleanest way to handle bad input)
+    }
 
     // Read the remaining lines.
     for (;;) {
@@ -291,6 +353,12 @@ static history_item_t decode_item_fish_2_0(const char *base, size_t len) {
             char *end = nullptr;
             long tmp = strtol(value.c_str(), &end, 0);
             when = tmp;
+        } else if (key == "cwd") {
+            if (!decrypt_value(value, &cwd)) cwd.clear();
+        } else if (key == "status") {
+            status = static_cast<int>(strtol(value.c_str(), nullptr, 10));
+        } else if (key == "duration") {
+            duration = strtoll(value.c_str(), nullptr, 10);
         } else if (key == "paths") {
             // Read lines starting with " - " until we can't read any more.
             for (;;) {
@@ -305,13 +373,17 @@ static history_item_t decode_item_fish_2_0(const char *base, size_t len) {
                 // Skip the leading dash-space and then store this path it.
                 line.erase(0, 2);
                 unescape_yaml_fish_2_0(&line);
-                paths.push_back(str2wcstring(line));
+                wcstring path;
+                if (decrypt_value(line, &path)) paths.push_back(std::move(path));
             }
         }
     }
 
 done:
     history_item_t result(cmd, when);
+    result.set_cwd(std::move(cwd));
+    result.set_exit_status(status);
+    result.set_duration_ms(duration);
     result.set_required_paths(std::move(paths));
     return result;
 }
@@ -367,6 +439,38 @@ static const char *next_line(const char *start, const char *end) {
     return nextline;
 }
 
+/// Given a line which is not in the interior of an item, return where the item it starts begins,
+/// or nullptr if it doesn't start one. \p a_newline points at the newline ending the line.
+static const char *item_start_in_line(const char *line_start, const char *a_newline) {
+    // Skip very short lines to make one of the checks below easier.
+    if (a_newline - line_start < 3) return nullptr;
+
+    // Try to be a little YAML compatible. Skip lines with leading %, ---, or ...
+    if (!std::memcmp(line_start, "%", 1) || !std::memcmp(line_start, "---", 3) ||
+        !std::memcmp(line_start, "...", 3))
+        return nullptr;
+
+    // Hackish: fish 1.x rewriting a fish 2.0 history file can produce lines with lots of
+    // leading "- cmd: - cmd: - cmd:". Trim all but one leading "- cmd:".
+    constexpr const char double_cmd[] = "- cmd: - cmd: ";
+    constexpr const size_t double_cmd_len = const_strlen(double_cmd);
+    while (static_cast<size_t>(a_newline - line_start) > double_cmd_len &&
+           !std::memcmp(line_start, double_cmd, double_cmd_len)) {
+        // Skip over just one of the - cmd. In the end there will be just one left.
+        line_start += const_strlen("- cmd: ");
+    }
+
+    // Hackish: fish 1.x rewriting a fish 2.0 history file can produce commands like "when:
+    // 123456". Ignore those.
+    constexpr const char cmd_when[] = "- cmd:    when:";
+    constexpr const size_t cmd_when_len = const_strlen(cmd_when);
+    if (static_cast<size_t>(a_newline - line_start) >= cmd_when_len &&
+        !std::memcmp(line_start, cmd_when, cmd_when_len)) {
+        return nullptr;
+    }
+    return line_start;
+}
+
 /// Support for iteratively locating the offsets of history items.
 /// Pass the file contents and a pointer to a cursor size_t, initially 0.
 /// If custoff_timestamp is nonzero, skip items created at or after that timestamp.
@@ -390,32 +494,8 @@ static maybe_t<size_t> offset_of_next_item_fish_2_0(const history_file_contents_
         // Skip lines with a leading space, since these are in the interior of one of our items.
         if (line_start[0] == ' ') continue;
 
-        // Skip very short lines to make one of the checks below easier.
-        if (a_newline - line_start < 3) continue;
-
-        // Try to be a little YAML compatible. Skip lines with leading %, ---, or ...
-        if (!std::memcmp(line_start, "%", 1) || !std::memcmp(line_start, "---", 3) ||
-            !std::memcmp(line_start, "...", 3))
-            continue;
-
-        // Hackish: fish 1.x rewriting a fish 2.0 history file can produce lines with lots of
-        // leading "- cmd: - cmd: - cmd:". Trim all but one leading "- cmd:".
-        constexpr const char double_cmd[] = "- cmd: - cmd: ";
-        constexpr const size_t double_cmd_len = const_strlen(double_cmd);
-        while (static_cast<size_t>(a_newline - line_start) > double_cmd_len &&
-               !std::memcmp(line_start, double_cmd, double_cmd_len)) {
-            // Skip over just one of the - cmd. In the end there will be just one left.
-            line_start += const_strlen("- cmd: ");
-        }
-
-        // Hackish: fish 1.x rewriting a fish 2.0 history file can produce commands like "when:
-        // 123456". Ignore those.
-        constexpr const char cmd_when[] = "- cmd:    when:";
-        constexpr const size_t cmd_when_len = const_strlen(cmd_when);
-        if (static_cast<size_t>(a_newline - line_start) >= cmd_when_len &&
-            !std::memcmp(line_start, cmd_when, cmd_when_len)) {
-            continue;
-        }
+        line_start = item_start_in_line(line_start, a_newline);
+        if (!line_start) continue;
 
         // At this point, we know line_start is at the beginning of an item. But maybe we want to
         // skip this item because of timestamps. A 0 cutoff means we don't care; if we do care, then
@@ -459,6 +539,51 @@ static maybe_t<size_t> offset_of_next_item_fish_2_0(const history_file_contents_
     return none();
 }
 
+/// Like offset_of_next_item_fish_2_0, but finds the items from the end of the file to the start, so
+/// the most recent items can be found without reading the rest of the file.
+/// The cursor is the offset just past the lines not yet looked at, initially the length.
+static maybe_t<size_t> offset_of_prev_item_fish_2_0(const history_file_contents_t &contents,
+                                                    size_t *inout_cursor, time_t cutoff_timestamp) {
+    const char *const begin = contents.begin();
+    size_t cursor = *inout_cursor;
+    // Lines must end in a newline, ignore anything after the last one.
+    while (cursor > 0 && begin[cursor - 1] != '\n') cursor--;
+
+    // Interior lines come before the line starting their item. This is the timestamp of the first
+    // of them that has one, like offset_of_next_item_fish_2_0 uses.
+    bool has_timestamp = false;
+    time_t timestamp = 0;
+    while (cursor > 0) {
+        const char *a_newline = begin + cursor - 1;
+        const char *line_start = a_newline;
+        while (line_start > begin && line_start[-1] != '\n') line_start--;
+        cursor = line_start - begin;
+
+        if (line_start[0] == ' ') {
+            time_t when = 0;
+            if (cutoff_timestamp != 0 && parse_timestamp(line_start, &when)) {
+                has_timestamp = true;
+                timestamp = when;
+            }
+            continue;
+        }
+
+        // The interior lines seen so far belong to this line, whether it starts an item or not.
+        bool item_has_timestamp = has_timestamp;
+        has_timestamp = false;
+        line_start = item_start_in_line(line_start, a_newline);
+        if (!line_start) continue;
+
+        // Skip this item if the timestamp is past our cutoff.
+        if (item_has_timestamp && timestamp > cutoff_timestamp) continue;
+
+        *inout_cursor = cursor;
+        return line_start - begin;
+    }
+    *inout_cursor = 0;
+    return none();
+}
+
 void append_history_item_to_buffer(const history_item_t &item, std::string *buffer) {
     assert(item.should_write_to_disk() && "Item should not be persisted");
     auto append = [=](const char *a, const char *b = nullptr, const char *c = nullptr) {
@@ -467,16 +592,28 @@ void append_history_item_to_buffer(const history_item_t &item, std::string *buff
         if (c) buffer->append(c);
     };
 
-    std::string cmd = wcs2string(item.str());
+    std::string cmd = encrypt_value(item.str());
     escape_yaml_fish_2_0(&cmd);
     append("- cmd: ", cmd.c_str(), "\n");
     append("  when: ", std::to_string(item.timestamp()).c_str(), "\n");
+    if (!item.get_cwd().empty()) {
+        std::string cwd = encrypt_value(item.get_cwd());
+        escape_yaml_fish_2_0(&cwd);
+        append("  cwd: ", cwd.c_str(), "\n");
+    }
+    // Older versions of fish skip these keys, and items they wrote don't have them.
+    if (item.get_exit_status() >= 0) {
+        append("  status: ", std::to_string(item.get_exit_status()).c_str(), "\n");
+    }
+    if (item.get_duration_ms() >= 0) {
+        append("  duration: ", std::to_string(item.get_duration_ms()).c_str(), "\n");
+    }
     const path_list_t &paths = item.get_required_paths();
     if (!paths.empty()) {
         append("  paths:\n");
 
         for (const auto &wpath : paths) {
-            std::string path = wcs2string(wpath);
+            std::string path = encrypt_value(wpath);
             escape_yaml_fish_2_0(&path);
             append("    - ", path.c_str(), "\n");
         }
@@ -26,12 +26,23 @@ class history_file_contents_t : noncopyable_t, nonmovable_t {
     /// Decode an item at a given offset.
     history_item_t decode_item(size_t offset) const;
 
+    /// \return whether the item at a given offset can be decoded, which is not the case if it was
+    /// encrypted with a key we don't have.
+    bool is_readable(size_t offset) const;
+
+    /// \return the text of the item at a given offset, as it is in the file.
+    std::string raw_item(size_t offset) const;
+
     /// Support for iterating item offsets.
     /// The cursor should initially be 0.
     /// If cutoff is nonzero, skip items whose timestamp is newer than cutoff.
     /// \return the offset of the next item, or none() on end.
     maybe_t<size_t> offset_of_next_item(size_t *cursor, time_t cutoff) const;
 
+    /// Like offset_of_next_item(), but from the end to the start, returning the newest item first.
+    /// The cursor should initially be length(). Only fish 2.0 files can be read like this.
+    maybe_t<size_t> offset_of_prev_item(size_t *cursor, time_t cutoff) const;
+
     /// Get the file type.
     history_file_type_t type() const { return type_; }
 
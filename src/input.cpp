@@ -3,12 +3,6 @@
 
 #include <errno.h>
 
-#if HAVE_TERM_H
-#include <curses.h>  // IWYU pragma: keep
-#include <term.h>
-#elif HAVE_NCURSES_TERM_H
-#include <ncurses/term.h>
-#endif
 #include <termios.h>
 
 #include <algorithm>
@@ -19,17 +13,24 @@
 
 #include "common.h"
 #include "env.h"
+#include "env_dispatch.rs.h"
 #include "event.h"
 #include "fallback.h"  // IWYU pragma: keep
 #include "flog.h"
 #include "global_safety.h"
+#include "history.h"
 #include "input.h"
 #include "input_common.h"
+#include "kitty_keys.rs.h"
+#include "output.h"
+#include "output.rs.h"
 #include "parser.h"
 #include "proc.h"
 #include "reader.h"
 #include "signals.h"  // IWYU pragma: keep
+#include "terminal_probe.rs.h"
 #include "threads.rs.h"
+#include "wcstringutil.h"
 #include "wutil.h"  // IWYU pragma: keep
 
 /// A name for our own key mapping for nul.
@@ -47,6 +48,9 @@ struct input_mapping_t {
     wcstring mode;
     /// New mode that should be switched to after command evaluation.
     wcstring sets_mode;
+    /// If nonzero, how long to wait for each key after the first, in milliseconds. If the next key
+    /// does not arrive in time, the mapping does not match. Zero means to wait indefinitely.
+    int timeout_ms{0};
 
     input_mapping_t(wcstring s, std::vector<wcstring> c, wcstring m, wcstring sm)
         : seq(std::move(s)), commands(std::move(c)), mode(std::move(m)), sets_mode(std::move(sm)) {
@@ -71,6 +75,9 @@ struct terminfo_mapping_t {
     }
 
     terminfo_mapping_t(const wchar_t *name, std::string s) : name(name), seq(std::move(s)) {}
+
+    terminfo_mapping_t(const wchar_t *name, maybe_t<std::string> s)
+        : name(name), seq(std::move(s)) {}
 };
 
 static constexpr size_t input_function_count = R_END_INPUT_FUNCTIONS;
@@ -143,6 +150,7 @@ static constexpr const input_function_metadata_t input_function_metadata[] = {
     {L"kill-selection", readline_cmd_t::kill_selection},
     {L"kill-whole-line", readline_cmd_t::kill_whole_line},
     {L"kill-word", readline_cmd_t::kill_word},
+    {L"multi-cursor-from-selection", readline_cmd_t::multi_cursor_from_selection},
     {L"nextd-or-forward-word", readline_cmd_t::nextd_or_forward_word},
     {L"or", readline_cmd_t::func_or},
     {L"pager-toggle-search", readline_cmd_t::pager_toggle_search},
@@ -152,6 +160,8 @@ static constexpr const input_function_metadata_t input_function_metadata[] = {
     {L"repaint-mode", readline_cmd_t::repaint_mode},
     {L"repeat-jump", readline_cmd_t::repeat_jump},
     {L"repeat-jump-reverse", readline_cmd_t::reverse_repeat_jump},
+    {L"select-around-object", readline_cmd_t::select_around_object},
+    {L"select-inner-object", readline_cmd_t::select_inner_object},
     {L"self-insert", readline_cmd_t::self_insert},
     {L"self-insert-notfirst", readline_cmd_t::self_insert_notfirst},
     {L"suppress-autosuggestion", readline_cmd_t::suppress_autosuggestion},
@@ -163,6 +173,7 @@ static constexpr const input_function_metadata_t input_function_metadata[] = {
     {L"undo", readline_cmd_t::undo},
     {L"up-line", readline_cmd_t::up_line},
     {L"upcase-word", readline_cmd_t::upcase_word},
+    {L"use-register", readline_cmd_t::use_register},
     {L"yank", readline_cmd_t::yank},
     {L"yank-pop", readline_cmd_t::yank_pop},
 };
@@ -219,6 +230,9 @@ static int input_function_arity(readline_cmd_t function) {
         case readline_cmd_t::backward_jump:
         case readline_cmd_t::forward_jump_till:
         case readline_cmd_t::backward_jump_till:
+        case readline_cmd_t::select_inner_object:
+        case readline_cmd_t::select_around_object:
+        case readline_cmd_t::use_register:
             return 1;
         default:
             return 0;
@@ -244,7 +258,7 @@ static void input_mapping_insert_sorted(mapping_list_t &ml, input_mapping_t new_
 /// Adds an input mapping.
 void input_mapping_set_t::add(wcstring sequence, const wchar_t *const *commands,
                               size_t commands_len, const wchar_t *mode, const wchar_t *sets_mode,
-                              bool user) {
+                              bool user, int timeout_ms) {
     assert(commands && mode && sets_mode && "Null parameter");
 
     // Clear cached mappings.
@@ -259,6 +273,7 @@ void input_mapping_set_t::add(wcstring sequence, const wchar_t *const *commands,
         if (m.seq == sequence && m.mode == mode) {
             m.commands = commands_vector;
             m.sets_mode = sets_mode;
+            m.timeout_ms = timeout_ms;
             return;
         }
     }
@@ -266,12 +281,13 @@ void input_mapping_set_t::add(wcstring sequence, const wchar_t *const *commands,
     // Add a new mapping, using the next order.
     input_mapping_t new_mapping =
         input_mapping_t(std::move(sequence), commands_vector, mode, sets_mode);
+    new_mapping.timeout_ms = timeout_ms;
     input_mapping_insert_sorted(ml, std::move(new_mapping));
 }
 
 void input_mapping_set_t::add(wcstring sequence, const wchar_t *command, const wchar_t *mode,
-                              const wchar_t *sets_mode, bool user) {
-    input_mapping_set_t::add(std::move(sequence), &command, 1, mode, sets_mode, user);
+                              const wchar_t *sets_mode, bool user, int timeout_ms) {
+    input_mapping_set_t::add(std::move(sequence), &command, 1, mode, sets_mode, user, timeout_ms);
 }
 
 /// Set up arrays used by readch to detect escape sequences for special keys and perform related
@@ -352,6 +368,12 @@ void inputter_t::uvar_change_notified() /* override */ {
     this->parser_->sync_uvars_and_fire(true /* always */);
 }
 
+void inputter_t::history_change_notified() /* override */ {
+    if (const auto &history = history_watcher_t::default_watcher().history()) {
+        history->sync_external_changes();
+    }
+}
+
 void inputter_t::function_push_arg(wchar_t arg) { input_function_args_.push_back(arg); }
 
 wchar_t inputter_t::function_pop_arg() {
@@ -373,6 +395,7 @@ void inputter_t::function_push_args(readline_cmd_t code) {
             auto evt = this->readch();
             if (evt.is_char()) {
                 arg = evt.get_char();
+                macro_keys_.push_back(arg);
                 break;
             }
             skipped.push_back(evt);
@@ -387,7 +410,8 @@ void inputter_t::function_push_args(readline_cmd_t code) {
 
 /// Perform the action of the specified binding. allow_commands controls whether fish commands
 /// should be executed, or should be deferred until later.
-void inputter_t::mapping_execute(const input_mapping_t &m,
+/// \return false if the binding was deferred, in which case its keys are back on the queue.
+bool inputter_t::mapping_execute(const input_mapping_t &m,
                                  const command_handler_t &command_handler) {
     // has_functions: there are functions that need to be put on the input queue
     // has_commands: there are shell commands that need to be evaluated
@@ -408,14 +432,14 @@ void inputter_t::mapping_execute(const input_mapping_t &m,
     // !has_functions && !has_commands: only set bind mode
     if (!has_commands && !has_functions) {
         if (!m.sets_mode.empty()) input_set_bind_mode(*parser_, m.sets_mode);
-        return;
+        return true;
     }
 
     if (has_commands && !command_handler) {
         // We don't want to run commands yet. Put the characters back and return check_exit.
         this->insert_front(m.seq.cbegin(), m.seq.cend());
         this->push_front(char_event_type_t::check_exit);
-        return;  // skip the input_set_bind_mode
+        return false;  // skip the input_set_bind_mode
     } else if (has_functions && !has_commands) {
         // Functions are added at the head of the input queue.
         for (auto it = m.commands.rbegin(), end = m.commands.rend(); it != end; ++it) {
@@ -438,11 +462,19 @@ void inputter_t::mapping_execute(const input_mapping_t &m,
 
     // Empty bind mode indicates to not reset the mode (#2871)
     if (!m.sets_mode.empty()) input_set_bind_mode(*parser_, m.sets_mode);
+    return true;
 }
 
 void inputter_t::queue_char(const char_event_t &ch) {
     if (ch.is_readline()) {
-        function_push_args(ch.get_readline());
+        readline_cmd_t cmd = ch.get_readline();
+        // A repaint right after another one does nothing new, so coalesce bursts of them, like
+        // from resizing the terminal.
+        if ((cmd == readline_cmd_t::repaint || cmd == readline_cmd_t::force_repaint) &&
+            this->queue_ends_with(cmd)) {
+            return;
+        }
+        function_push_args(cmd);
     }
     this->push_back(ch);
 }
@@ -465,7 +497,9 @@ class event_queue_peeker_t {
 
     /// Check if the next event is the given character. This advances the index on success only.
     /// If \p timed is set, then return false if this (or any other) character had a timeout.
-    bool next_is_char(wchar_t c, bool timed = false) {
+    /// If \p timeout_ms is nonzero, wait at most that long for a new event; unlike \p timed, this
+    /// timing out does not affect other checks.
+    bool next_is_char(wchar_t c, bool timed = false, int timeout_ms = 0) {
         assert(idx_ <= peeked_.size() && "Index must not be larger than dequeued event count");
         // See if we had a timeout already.
         if (timed && had_timeout_) {
@@ -475,13 +509,21 @@ class event_queue_peeker_t {
         // Use either readch or readch_timed, per our param.
         if (idx_ == peeked_.size()) {
             char_event_t newevt{L'\0'};
-            if (!timed) {
-                newevt = event_queue_.readch();
-            } else if (auto mevt = event_queue_.readch_timed()) {
-                newevt = mevt.acquire();
+            if (timed) {
+                if (auto mevt = event_queue_.readch_timed()) {
+                    newevt = mevt.acquire();
+                } else {
+                    had_timeout_ = true;
+                    return false;
+                }
+            } else if (timeout_ms > 0) {
+                if (auto mevt = event_queue_.readch_timed(timeout_ms)) {
+                    newevt = mevt.acquire();
+                } else {
+                    return false;
+                }
             } else {
-                had_timeout_ = true;
-                return false;
+                newevt = event_queue_.readch();
             }
             peeked_.push_back(newevt);
         }
@@ -586,24 +628,84 @@ static bool have_mouse_tracking_csi(event_queue_peeker_t *peeker) {
     return true;
 }
 
+/// Try reading a key reported with the kitty keyboard protocol, using the given \p peeker.
+/// Events are left on the peeker and the caller must restart or consume it.
+/// \return true if matched, in which case \p out_seq is set to the sequence to bind to.
+static bool have_kitty_key_csi(event_queue_peeker_t *peeker, wcstring *out_seq) {
+    if (!term_supports_kitty_keyboard()) return false;
+    if (!peeker->next_is_char(L'\x1b') || !peeker->next_is_char(L'[', true /* timed */)) {
+        return false;
+    }
+
+    // The key code and modifiers, with their optional sub-fields and the text, are digits,
+    // colons and semicolons, followed by a 'u'.
+    std::string seq = "\x1b[";
+    while (true) {
+        auto next = peeker->next().maybe_char();
+        if (!next || peeker->len() > 32) return false;
+        if (*next == L'u') {
+            seq.push_back('u');
+            break;
+        }
+        if (!iswdigit(*next) && *next != L';' && *next != L':') return false;
+        seq.push_back(static_cast<char>(*next));
+    }
+
+    rust::Vec<uint8_t> translated = kitty_key_sequence(
+        rust::Slice<const uint8_t>(reinterpret_cast<const uint8_t *>(seq.data()), seq.size()));
+    if (translated.empty()) return false;
+    *out_seq = str2wcstring(reinterpret_cast<const char *>(translated.data()), translated.size());
+    return true;
+}
+
+/// Try reading text pasted with bracketed paste, using the given \p peeker. The terminal wraps the
+/// text in \e[200~ and \e[201~, and everything in between is collected into \p out_text.
+/// Events are left on the peeker and the caller must restart or consume it.
+/// \return true if matched, false if not.
+static bool have_bracketed_paste(event_queue_peeker_t *peeker, wcstring *out_text) {
+    if (!peeker->next_is_char(L'\x1b') || !peeker->next_is_char(L'[', true /* timed */) ||
+        !peeker->next_is_char(L'2') || !peeker->next_is_char(L'0') ||
+        !peeker->next_is_char(L'0') || !peeker->next_is_char(L'~')) {
+        return false;
+    }
+
+    const wcstring end = L"\x1B[201~";
+    wcstring text;
+    while (!string_suffixes_string(end, text)) {
+        auto evt = peeker->next();
+        // Signals and other interruptions don't end the paste, only the end of input does.
+        if (evt.is_eof()) break;
+        if (auto c = evt.maybe_char()) text.push_back(*c);
+    }
+    if (string_suffixes_string(end, text)) text.resize(text.size() - end.size());
+    *out_text = std::move(text);
+    return true;
+}
+
 /// \return true if a given \p peeker matches a given sequence of char events given by \p str.
-static bool try_peek_sequence(event_queue_peeker_t *peeker, const wcstring &str) {
+/// If \p timeout_ms is nonzero, each char after the first must arrive within that many
+/// milliseconds.
+static bool try_peek_sequence(event_queue_peeker_t *peeker, const wcstring &str,
+                              int timeout_ms = 0) {
     assert(!str.empty() && "Empty string passed to try_peek_sequence");
     wchar_t prev = L'\0';
+    bool first = true;
     for (wchar_t c : str) {
         // If we just read an escape, we need to add a timeout for the next char,
         // to distinguish between the actual escape key and an "alt"-modifier.
         bool timed = prev == L'\x1B';
-        if (!peeker->next_is_char(c, timed)) {
+        if (!peeker->next_is_char(c, timed, first ? 0 : timeout_ms)) {
             return false;
         }
         prev = c;
+        first = false;
     }
     return true;
 }
 
 /// \return the first mapping that matches, walking first over the user's mapping list, then the
-/// preset list.
+/// preset list. If the bind mode inherits from other modes, their mappings are tried afterwards,
+/// nearest first; generic mappings are only used if no mode has a matching sequence.
 /// \return none if nothing matches, or if we may have matched a longer sequence but it was
 /// interrupted by a readline event.
 maybe_t<input_mapping_t> inputter_t::find_mapping(event_queue_peeker_t *peeker) {
@@ -612,30 +714,38 @@ maybe_t<input_mapping_t> inputter_t::find_mapping(event_queue_peeker_t *peeker)
     const wcstring bind_mode = input_get_bind_mode(vars);
     const input_mapping_t *escape = nullptr;
 
-    auto ml = input_mappings()->all_mappings();
-    for (const auto &m : *ml) {
-        if (m.mode != bind_mode) {
-            continue;
-        }
+    std::shared_ptr<const mapping_list_t> ml;
+    std::vector<wcstring> modes;
+    {
+        auto mappings = input_mappings();
+        ml = mappings->all_mappings();
+        modes = mappings->mode_chain(bind_mode);
+    }
+    for (const wcstring &mode : modes) {
+        for (const auto &m : *ml) {
+            if (m.mode != mode) {
+                continue;
+            }
 
-        // Defer generic mappings until the end.
-        if (m.is_generic()) {
-            if (!generic) generic = &m;
-            continue;
-        }
+            // Defer generic mappings until the end.
+            if (m.is_generic()) {
+                if (!generic) generic = &m;
+                continue;
+            }
 
-        if (try_peek_sequence(peeker, m.seq)) {
-            // A binding for just escape should also be deferred
-            // so escape sequences take precedence.
-            if (m.seq == L"\x1B") {
-                if (!escape) {
-                    escape = &m;
+            if (try_peek_sequence(peeker, m.seq, m.timeout_ms)) {
+                // A binding for just escape should also be deferred
+                // so escape sequences take precedence.
+                if (m.seq == L"\x1B") {
+                    if (!escape) {
+                        escape = &m;
+                    }
+                } else {
+                    return m;
                 }
-            } else {
-                return m;
             }
+            peeker->restart();
         }
-        peeker->restart();
     }
 
     if (peeker->char_sequence_interrupted()) {
@@ -680,10 +790,42 @@ void inputter_t::mapping_execute_matching_or_generic(const command_handler_t &co
     }
     peeker.restart();
 
+    // Deliver bracketed paste as a single event, so the text isn't run through the bindings.
+    wcstring pasted;
+    if (have_bracketed_paste(&peeker, &pasted)) {
+        peeker.consume();
+        this->push_front(char_event_t::from_paste(std::move(pasted)));
+        return;
+    }
+    peeker.restart();
+
+    // Translate keys reported with the kitty keyboard protocol to what bindings use, and match
+    // the mappings against that.
+    wcstring key_seq;
+    if (have_kitty_key_csi(&peeker, &key_seq)) {
+        peeker.consume();
+        std::vector<char_event_t> key_events(key_seq.begin(), key_seq.end());
+        this->insert_front(key_events.cbegin(), key_events.cend());
+    } else {
+        peeker.restart();
+    }
+
     // Check for ordinary mappings.
     if (auto mapping = find_mapping(&peeker)) {
+        // Remember the keys, in case we are recording a macro. A generic mapping gets one key.
+        macro_keys_ = mapping->seq;
+        if (mapping->is_generic()) {
+            if (auto c = peeker.next().maybe_char()) macro_keys_.push_back(*c);
+            peeker.restart();
+        }
         peeker.consume();
-        mapping_execute(*mapping, command_handler);
+
+        // The bindings that start and stop recording are not part of the macro.
+        bool was_recording = input_mappings()->is_recording();
+        if (mapping_execute(*mapping, command_handler)) {
+            mapping_count_++;
+            if (was_recording) input_mappings()->record(macro_keys_);
+        }
         return;
     }
     peeker.restart();
@@ -777,6 +919,9 @@ char_event_t inputter_t::read_char(const command_handler_t &command_handler) {
         } else if (evt.is_check_exit()) {
             // Allow the reader to check for exit conditions.
             return evt;
+        } else if (evt.is_paste()) {
+            // The reader inserts the text itself.
+            return evt;
         } else {
             assert(evt.is_char() && "Should be char event");
             this->push_front(evt);
@@ -827,13 +972,14 @@ bool input_mapping_set_t::erase(const wcstring &sequence, const wcstring &mode,
 
 bool input_mapping_set_t::get(const wcstring &sequence, const wcstring &mode,
                               std::vector<wcstring> *out_cmds, bool user,
-                              wcstring *out_sets_mode) const {
+                              wcstring *out_sets_mode, int *out_timeout_ms) const {
     bool result = false;
     const auto &ml = user ? mapping_list_ : preset_mapping_list_;
     for (const input_mapping_t &m : ml) {
         if (sequence == m.seq && mode == m.mode) {
             *out_cmds = m.commands;
             *out_sets_mode = m.sets_mode;
+            if (out_timeout_ms) *out_timeout_ms = m.timeout_ms;
             result = true;
             break;
         }
@@ -841,6 +987,57 @@ bool input_mapping_set_t::get(const wcstring &sequence, const wcstring &mode,
     return result;
 }
 
+bool input_mapping_set_t::define_mode(const wcstring &mode, const wcstring &parent) {
+    if (!parent.empty()) {
+        std::vector<wcstring> chain = mode_chain(parent);
+        if (contains(chain, mode)) return false;
+    }
+    mode_parents_[mode] = parent;
+    return true;
+}
+
+bool input_mapping_set_t::erase_mode(const wcstring &mode) { return mode_parents_.erase(mode) > 0; }
+
+std::vector<wcstring> input_mapping_set_t::mode_chain(const wcstring &mode) const {
+    std::vector<wcstring> result{mode};
+    auto iter = mode_parents_.find(mode);
+    while (iter != mode_parents_.end() && !iter->second.empty()) {
+        // define_mode() prevents cycles, but be careful anyway.
+        if (contains(result, iter->second)) break;
+        result.push_back(iter->second);
+        iter = mode_parents_.find(iter->second);
+    }
+    return result;
+}
+
+bool input_mapping_set_t::start_recording() {
+    if (recording_) return false;
+    recording_ = wcstring{};
+    return true;
+}
+
+bool input_mapping_set_t::stop_recording(const wcstring &name) {
+    if (!recording_) return false;
+    macros_[name] = recording_.acquire();
+    return true;
+}
+
+void input_mapping_set_t::record(const wcstring &keys) {
+    if (recording_) recording_->append(keys);
+}
+
+maybe_t<wcstring> input_mapping_set_t::get_macro(const wcstring &name) const {
+    auto iter = macros_.find(name);
+    if (iter == macros_.end()) return none();
+    return iter->second;
+}
+
+std::vector<wcstring> input_mapping_set_t::get_macro_names() const {
+    std::vector<wcstring> result;
+    for (const auto &kv : macros_) result.push_back(kv.first);
+    return result;
+}
+
 std::shared_ptr<const mapping_list_t> input_mapping_set_t::all_mappings() {
     // Populate the cache if needed.
     if (!all_mappings_cache_) {
@@ -854,51 +1051,69 @@ std::shared_ptr<const mapping_list_t> input_mapping_set_t::all_mappings() {
 
 /// Create a list of terminfo mappings.
 static std::vector<terminfo_mapping_t> create_input_terminfo() {
-    assert(CURSES_INITIALIZED);
-    if (!cur_term) return {};  // setupterm() failed so we can't referency any key definitions
+    env_ensure_curses_initialized();
+    // Without a terminfo entry we can't reference any key definitions.
+    if (!term_has_entry()) return {};
 
-#define TERMINFO_ADD(key) \
-    { (L## #key) + 4, key }
+#define TERMINFO_ADD(key, cap) terminfo_mapping_t(L## #key, term_get_string(cap))
 
     return {
-        TERMINFO_ADD(key_a1), TERMINFO_ADD(key_a3), TERMINFO_ADD(key_b2),
-        TERMINFO_ADD(key_backspace), TERMINFO_ADD(key_beg), TERMINFO_ADD(key_btab),
-        TERMINFO_ADD(key_c1), TERMINFO_ADD(key_c3), TERMINFO_ADD(key_cancel),
-        TERMINFO_ADD(key_catab), TERMINFO_ADD(key_clear), TERMINFO_ADD(key_close),
-        TERMINFO_ADD(key_command), TERMINFO_ADD(key_copy), TERMINFO_ADD(key_create),
-        TERMINFO_ADD(key_ctab), TERMINFO_ADD(key_dc), TERMINFO_ADD(key_dl), TERMINFO_ADD(key_down),
-        TERMINFO_ADD(key_eic), TERMINFO_ADD(key_end), TERMINFO_ADD(key_enter),
-        TERMINFO_ADD(key_eol), TERMINFO_ADD(key_eos), TERMINFO_ADD(key_exit), TERMINFO_ADD(key_f0),
-        TERMINFO_ADD(key_f1), TERMINFO_ADD(key_f2), TERMINFO_ADD(key_f3), TERMINFO_ADD(key_f4),
-        TERMINFO_ADD(key_f5), TERMINFO_ADD(key_f6), TERMINFO_ADD(key_f7), TERMINFO_ADD(key_f8),
-        TERMINFO_ADD(key_f9), TERMINFO_ADD(key_f10), TERMINFO_ADD(key_f11), TERMINFO_ADD(key_f12),
-        TERMINFO_ADD(key_f13), TERMINFO_ADD(key_f14), TERMINFO_ADD(key_f15), TERMINFO_ADD(key_f16),
-        TERMINFO_ADD(key_f17), TERMINFO_ADD(key_f18), TERMINFO_ADD(key_f19), TERMINFO_ADD(key_f20),
-        // Note key_f21 through key_f63 are available but no actual keyboard supports them.
-        TERMINFO_ADD(key_find), TERMINFO_ADD(key_help), TERMINFO_ADD(key_home),
-        TERMINFO_ADD(key_ic), TERMINFO_ADD(key_il), TERMINFO_ADD(key_left), TERMINFO_ADD(key_ll),
-        TERMINFO_ADD(key_mark), TERMINFO_ADD(key_message), TERMINFO_ADD(key_move),
-        TERMINFO_ADD(key_next), TERMINFO_ADD(key_npage), TERMINFO_ADD(key_open),
-        TERMINFO_ADD(key_options), TERMINFO_ADD(key_ppage), TERMINFO_ADD(key_previous),
-        TERMINFO_ADD(key_print), TERMINFO_ADD(key_redo), TERMINFO_ADD(key_reference),
-        TERMINFO_ADD(key_refresh), TERMINFO_ADD(key_replace), TERMINFO_ADD(key_restart),
-        TERMINFO_ADD(key_resume), TERMINFO_ADD(key_right), TERMINFO_ADD(key_save),
-        TERMINFO_ADD(key_sbeg), TERMINFO_ADD(key_scancel), TERMINFO_ADD(key_scommand),
-        TERMINFO_ADD(key_scopy), TERMINFO_ADD(key_screate), TERMINFO_ADD(key_sdc),
-        TERMINFO_ADD(key_sdl), TERMINFO_ADD(key_select), TERMINFO_ADD(key_send),
-        TERMINFO_ADD(key_seol), TERMINFO_ADD(key_sexit), TERMINFO_ADD(key_sf),
-        TERMINFO_ADD(key_sfind), TERMINFO_ADD(key_shelp), TERMINFO_ADD(key_shome),
-        TERMINFO_ADD(key_sic), TERMINFO_ADD(key_sleft), TERMINFO_ADD(key_smessage),
-        TERMINFO_ADD(key_smove), TERMINFO_ADD(key_snext), TERMINFO_ADD(key_soptions),
-        TERMINFO_ADD(key_sprevious), TERMINFO_ADD(key_sprint), TERMINFO_ADD(key_sr),
-        TERMINFO_ADD(key_sredo), TERMINFO_ADD(key_sreplace), TERMINFO_ADD(key_sright),
-        TERMINFO_ADD(key_srsume), TERMINFO_ADD(key_ssave), TERMINFO_ADD(key_ssuspend),
-        TERMINFO_ADD(key_stab), TERMINFO_ADD(key_sundo), TERMINFO_ADD(key_suspend),
-        TERMINFO_ADD(key_undo), TERMINFO_ADD(key_up),
+        TERMINFO_ADD(a1, "ka1"), TERMINFO_ADD(a3, "ka3"), TERMINFO_ADD(b2, "kb2"),
+        TERMINFO_ADD(backspace, "kbs"), TERMINFO_ADD(beg, "kbeg"), TERMINFO_ADD(btab, "kcbt"),
+        TERMINFO_ADD(c1, "kc1"), TERMINFO_ADD(c3, "kc3"), TERMINFO_ADD(cancel, "kcan"),
+        TERMINFO_ADD(catab, "ktbc"), TERMINFO_ADD(clear, "kclr"), TERMINFO_ADD(close, "kclo"),
+        TERMINFO_ADD(command, "kcmd"), TERMINFO_ADD(copy, "kcpy"), TERMINFO_ADD(create, "kcrt"),
+        TERMINFO_ADD(ctab, "kctab"), TERMINFO_ADD(dc, "kdch1"), TERMINFO_ADD(dl, "kdl1"),
+        TERMINFO_ADD(down, "kcud1"), TERMINFO_ADD(eic, "krmir"), TERMINFO_ADD(end, "kend"),
+        TERMINFO_ADD(enter, "kent"), TERMINFO_ADD(eol, "kel"), TERMINFO_ADD(eos, "ked"),
+        TERMINFO_ADD(exit, "kext"), TERMINFO_ADD(f0, "kf0"), TERMINFO_ADD(f1, "kf1"),
+        TERMINFO_ADD(f2, "kf2"), TERMINFO_ADD(f3, "kf3"), TERMINFO_ADD(f4, "kf4"),
+        TERMINFO_ADD(f5, "kf5"), TERMINFO_ADD(f6, "kf6"), TERMINFO_ADD(f7, "kf7"),
+        TERMINFO_ADD(f8, "kf8"), TERMINFO_ADD(f9, "kf9"), TERMINFO_ADD(f10, "kf10"),
+        TERMINFO_ADD(f11, "kf11"), TERMINFO_ADD(f12, "kf12"), TERMINFO_ADD(f13, "kf13"),
+        TERMINFO_ADD(f14, "kf14"), TERMINFO_ADD(f15, "kf15"), TERMINFO_ADD(f16, "kf16"),
+        TERMINFO_ADD(f17, "kf17"), TERMINFO_ADD(f18, "kf18"), TERMINFO_ADD(f19, "kf19"),
+        TERMINFO_ADD(f20, "kf20"),
+        // Note kf21 through kf63 are available but no actual keyboard supports them.
+        TERMINFO_ADD(find, "kfnd"), TERMINFO_ADD(help, "khlp"), TERMINFO_ADD(home, "khome"),
+        TERMINFO_ADD(ic, "kich1"), TERMINFO_ADD(il, "kil1"), TERMINFO_ADD(left, "kcub1"),
+        TERMINFO_ADD(ll, "kll"), TERMINFO_ADD(mark, "kmrk"), TERMINFO_ADD(message, "kmsg"),
+        TERMINFO_ADD(move, "kmov"), TERMINFO_ADD(next, "knxt"), TERMINFO_ADD(npage, "knp"),
+        TERMINFO_ADD(open, "kopn"), TERMINFO_ADD(options, "kopt"), TERMINFO_ADD(ppage, "kpp"),
+        TERMINFO_ADD(previous, "kprv"), TERMINFO_ADD(print, "kprt"), TERMINFO_ADD(redo, "krdo"),
+        TERMINFO_ADD(reference, "kref"), TERMINFO_ADD(refresh, "krfr"),
+        TERMINFO_ADD(replace, "krpl"), TERMINFO_ADD(restart, "krst"), TERMINFO_ADD(resume, "kres"),
+        TERMINFO_ADD(right, "kcuf1"), TERMINFO_ADD(save, "ksav"), TERMINFO_ADD(sbeg, "kBEG"),
+        TERMINFO_ADD(scancel, "kCAN"), TERMINFO_ADD(scommand, "kCMD"), TERMINFO_ADD(scopy, "kCPY"),
+        TERMINFO_ADD(screate, "kCRT"), TERMINFO_ADD(sdc, "kDC"), TERMINFO_ADD(sdl, "kDL"),
+        TERMINFO_ADD(select, "kslt"), TERMINFO_ADD(send, "kEND"), TERMINFO_ADD(seol, "kEOL"),
+        TERMINFO_ADD(sexit, "kEXT"), TERMINFO_ADD(sf, "kind"), TERMINFO_ADD(sfind, "kFND"),
+        TERMINFO_ADD(shelp, "kHLP"), TERMINFO_ADD(shome, "kHOM"), TERMINFO_ADD(sic, "kIC"),
+        TERMINFO_ADD(sleft, "kLFT"), TERMINFO_ADD(smessage, "kMSG"), TERMINFO_ADD(smove, "kMOV"),
+        TERMINFO_ADD(snext, "kNXT"), TERMINFO_ADD(soptions, "kOPT"),
+        TERMINFO_ADD(sprevious, "kPRV"), TERMINFO_ADD(sprint, "kPRT"), TERMINFO_ADD(sr, "kri"),
+        TERMINFO_ADD(sredo, "kRDO"), TERMINFO_ADD(sreplace, "kRPL"), TERMINFO_ADD(sright, "kRIT"),
+        TERMINFO_ADD(srsume, "kRES"), TERMINFO_ADD(ssave, "kSAV"), TERMINFO_ADD(ssuspend, "kSPD"),
+        TERMINFO_ADD(stab, "khts"), TERMINFO_ADD(sundo, "kUND"), TERMINFO_ADD(suspend, "kspd"),
+        TERMINFO_ADD(undo, "kund"), TERMINFO_ADD(up, "kcuu1"),
 
         // We introduce our own name for the string containing only the nul character - see
         // #3189. This can typically be generated via control-space.
-        terminfo_mapping_t(k_nul_mapping_name, std::string{'\0'})};
+        terminfo_mapping_t(k_nul_mapping_name, std::string{'\0'}),
+
+        // Keys that only the kitty keyboard protocol can tell apart from others, in the form
+        // inputter_t translates them to. These are not terminfo capabilities, so they use names
+        // that can't clash with one.
+        terminfo_mapping_t(L"ctrl-i", "\x1b[105;5u"),
+        terminfo_mapping_t(L"ctrl-m", "\x1b[109;5u"),
+        terminfo_mapping_t(L"ctrl-enter", "\x1b[13;5u"),
+        terminfo_mapping_t(L"shift-enter", "\x1b[13;2u"),
+        terminfo_mapping_t(L"ctrl-shift-enter", "\x1b[13;6u"),
+        terminfo_mapping_t(L"ctrl-tab", "\x1b[9;5u"),
+        terminfo_mapping_t(L"ctrl-shift-tab", "\x1b[9;6u"),
+        terminfo_mapping_t(L"ctrl-backspace", "\x1b[127;5u"),
+        terminfo_mapping_t(L"shift-backspace", "\x1b[127;2u"),
+        terminfo_mapping_t(L"shift-escape", "\x1b[27;2u")};
 #undef TERMINFO_ADD
 }
 
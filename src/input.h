@@ -7,6 +7,7 @@
 #include <unistd.h>
 
 #include <functional>
+#include <map>
 #include <memory>
 #include <vector>
 
@@ -57,6 +58,10 @@ class inputter_t final : private input_event_queue_t {
     /// Pop an argument from the function argument stack.
     wchar_t function_pop_arg();
 
+    /// \return how many mappings have been executed, to tell which binding an input function
+    /// belongs to.
+    uint64_t mapping_count() const { return mapping_count_; }
+
    private:
     // Called right before potentially blocking in select().
     void prepare_to_select() override;
@@ -67,9 +72,12 @@ class inputter_t final : private input_event_queue_t {
     // Called when we are notified of a uvar change.
     void uvar_change_notified() override;
 
+    // Called when another shell changed the history file.
+    void history_change_notified() override;
+
     void function_push_arg(wchar_t arg);
     void function_push_args(readline_cmd_t code);
-    void mapping_execute(const input_mapping_t &m, const command_handler_t &command_handler);
+    bool mapping_execute(const input_mapping_t &m, const command_handler_t &command_handler);
     void mapping_execute_matching_or_generic(const command_handler_t &command_handler);
     maybe_t<input_mapping_t> find_mapping(event_queue_peeker_t *peeker);
     char_event_t read_characters_no_readline();
@@ -82,6 +90,12 @@ class inputter_t final : private input_event_queue_t {
 
     // Transient storage to avoid repeated allocations.
     std::vector<char_event_t> event_storage_{};
+
+    // The keys of the mapping being executed, including any arguments of its input functions, to
+    // record if a keyboard macro is being recorded.
+    wcstring macro_keys_{};
+
+    uint64_t mapping_count_{0};
 };
 
 struct input_mapping_name_t {
@@ -100,6 +114,15 @@ class input_mapping_set_t {
     mapping_list_t preset_mapping_list_;
     std::shared_ptr<const mapping_list_t> all_mappings_cache_;
 
+    /// Modes defined with `bind --new-mode`, mapped to the mode they inherit from, or empty.
+    std::map<wcstring, wcstring> mode_parents_;
+
+    /// Keyboard macros, by name.
+    std::map<wcstring, wcstring> macros_;
+
+    /// The keys of the macro being recorded, or none if we are not recording.
+    maybe_t<wcstring> recording_;
+
     input_mapping_set_t();
 
    public:
@@ -113,9 +136,10 @@ class input_mapping_set_t {
                bool user = true);
 
     /// Gets the command bound to the specified key sequence in the specified mode. Returns true if
-    /// it exists, false if not.
+    /// it exists, false if not. If \p out_timeout_ms is given, it is set to the binding's timeout,
+    /// or 0 if it has none.
     bool get(const wcstring &sequence, const wcstring &mode, std::vector<wcstring> *out_cmds,
-             bool user, wcstring *out_sets_mode) const;
+             bool user, wcstring *out_sets_mode, int *out_timeout_ms = nullptr) const;
 
     /// Returns all mapping names and modes.
     std::vector<input_mapping_name_t> get_names(bool user = true) const;
@@ -124,15 +148,50 @@ class input_mapping_set_t {
     ///
     /// \param sequence the sequence to bind
     /// \param command an input function that will be run whenever the key sequence occurs
+    /// \param timeout_ms if nonzero, how long to wait for each key of the sequence after the first
     void add(wcstring sequence, const wchar_t *command, const wchar_t *mode = DEFAULT_BIND_MODE,
-             const wchar_t *sets_mode = DEFAULT_BIND_MODE, bool user = true);
+             const wchar_t *sets_mode = DEFAULT_BIND_MODE, bool user = true, int timeout_ms = 0);
 
     void add(wcstring sequence, const wchar_t *const *commands, size_t commands_len,
              const wchar_t *mode = DEFAULT_BIND_MODE, const wchar_t *sets_mode = DEFAULT_BIND_MODE,
-             bool user = true);
+             bool user = true, int timeout_ms = 0);
 
     /// \return a snapshot of the list of input mappings.
     std::shared_ptr<const mapping_list_t> all_mappings();
+
+    /// Define the bind mode \p mode. If \p parent is not empty, keys without a binding in \p mode
+    /// use the bindings of \p parent instead. Defining a mode again replaces its parent.
+    /// \return false, changing nothing, if \p mode would end up inheriting from itself.
+    bool define_mode(const wcstring &mode, const wcstring &parent);
+
+    /// Forget the definition of the bind mode \p mode. Its bindings are not erased.
+    /// \return false if it was not defined.
+    bool erase_mode(const wcstring &mode);
+
+    /// \return the defined modes, sorted by name, with their parents or an empty string.
+    const std::map<wcstring, wcstring> &defined_modes() const { return mode_parents_; }
+
+    /// \return \p mode followed by the modes it inherits from, nearest first.
+    std::vector<wcstring> mode_chain(const wcstring &mode) const;
+
+    /// Start recording a keyboard macro. \return false if one is already being recorded.
+    bool start_recording();
+
+    /// Stop recording, and save the recorded keys as the macro \p name, replacing any macro with
+    /// that name. \return false if no macro was being recorded.
+    bool stop_recording(const wcstring &name);
+
+    /// \return whether a keyboard macro is being recorded.
+    bool is_recording() const { return recording_.has_value(); }
+
+    /// Append \p keys to the macro being recorded, if any.
+    void record(const wcstring &keys);
+
+    /// \return the keys of the macro \p name, or none if there is no such macro.
+    maybe_t<wcstring> get_macro(const wcstring &name) const;
+
+    /// \return the names of all macros, sorted.
+    std::vector<wcstring> get_macro_names() const;
 };
 
 /// Access the singleton input mapping set.
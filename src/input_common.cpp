@@ -25,8 +25,10 @@
 #include "fd_readable_set.rs.h"
 #include "fds.h"
 #include "flog.h"
+#include "history.h"
 #include "input_common.h"
 #include "iothread.h"
+#include "watchdog.rs.h"
 #include "wutil.h"
 
 /// Time in milliseconds to wait for another byte to be available for reading
@@ -38,9 +40,9 @@ static int wait_on_escape_ms = WAIT_ON_ESCAPE_DEFAULT;
 input_event_queue_t::input_event_queue_t(int in) : in_(in) {}
 
 /// Internal function used by readch to read one byte.
-/// This calls select() on three fds: input (e.g. stdin), the ioport notifier fd (for main thread
-/// requests), and the uvar notifier. This returns either the byte which was read, or one of the
-/// special values below.
+/// This calls select() on four fds: input (e.g. stdin), the ioport notifier fd (for main thread
+/// requests), the uvar notifier and the history watcher. This returns either the byte which was
+/// read, or one of the special values below.
 enum {
     // The in fd has been closed.
     readb_eof = -1,
@@ -53,12 +55,16 @@ enum {
 
     // Our ioport reported a change, so service main thread requests.
     readb_ioport_notified = -4,
+
+    // Our history watcher reported that another shell changed the history file.
+    readb_history_notified = -5,
 };
 using readb_result_t = int;
 
 static readb_result_t readb(int in_fd) {
     assert(in_fd >= 0 && "Invalid in fd");
     universal_notifier_t& notifier = universal_notifier_t::default_notifier();
+    history_watcher_t& watcher = history_watcher_t::default_watcher();
     auto fdset_box = new_fd_readable_set();
     fd_readable_set_t& fdset = *fdset_box;
     for (;;) {
@@ -73,15 +79,21 @@ static readb_result_t readb(int in_fd) {
         int notifier_fd = notifier.notification_fd();
         fdset.add(notifier_fd);
 
-        // Get its suggested delay (possibly none).
+        // Get the history watcher fd (possibly none).
+        int watcher_fd = watcher.notification_fd();
+        fdset.add(watcher_fd);
+
+        // Get its suggested delay (possibly none), after which a timer on the fd monitor makes the
+        // ioport readable, so we poll it below.
         // Note a 0 here means do not poll.
-        uint64_t timeout = kNoTimeout;
         if (uint64_t usecs_delay = notifier.usec_delay_between_polls()) {
-            timeout = usecs_delay;
+            iothread_wake_main_after(usecs_delay);
         }
 
-        // Here's where we call select().
-        int select_res = fdset.check_readable(timeout);
+        // Here's where we call select(). The main thread is not stuck while it waits for input.
+        watchdog_idle();
+        int select_res = fdset.check_readable(kNoTimeout);
+        watchdog_busy();
         if (select_res < 0) {
             if (errno == EINTR || errno == EAGAIN) {
                 // A signal.
@@ -117,6 +129,10 @@ static readb_result_t readb(int in_fd) {
         if (fdset.test(ioport_fd)) {
             return readb_ioport_notified;
         }
+
+        if (fdset.test(watcher_fd) && watcher.notification_fd_became_readable()) {
+            return readb_history_notified;
+        }
     }
 }
 
@@ -201,6 +217,10 @@ char_event_t input_event_queue_t::readch() {
                 iothread_service_main();
                 break;
 
+            case readb_history_notified:
+                this->history_change_notified();
+                break;
+
             default: {
                 assert(rr >= 0 && rr <= UCHAR_MAX &&
                        "Read byte out of bounds - missing error case?");
@@ -236,6 +256,10 @@ char_event_t input_event_queue_t::readch() {
 }
 
 maybe_t<char_event_t> input_event_queue_t::readch_timed() {
+    return readch_timed(wait_on_escape_ms);
+}
+
+maybe_t<char_event_t> input_event_queue_t::readch_timed(int wait_ms) {
     if (auto evt = try_pop()) {
         return evt;
     }
@@ -248,7 +272,7 @@ maybe_t<char_event_t> input_event_queue_t::readch_timed() {
     // pselect expects timeouts in nanoseconds.
     const uint64_t nsec_per_msec = 1000 * 1000;
     const uint64_t nsec_per_sec = nsec_per_msec * 1000;
-    const uint64_t wait_nsec = wait_on_escape_ms * nsec_per_msec;
+    const uint64_t wait_nsec = wait_ms * nsec_per_msec;
     struct timespec timeout;
     timeout.tv_sec = (wait_nsec) / nsec_per_sec;
     timeout.tv_nsec = (wait_nsec) % nsec_per_sec;
@@ -276,6 +300,10 @@ void input_event_queue_t::push_back(const char_event_t& ch) { queue_.push_back(c
 
 void input_event_queue_t::push_front(const char_event_t& ch) { queue_.push_front(ch); }
 
+bool input_event_queue_t::queue_ends_with(readline_cmd_t cmd) const {
+    return !queue_.empty() && queue_.back().is_readline() && queue_.back().get_readline() == cmd;
+}
+
 void input_event_queue_t::promote_interruptions_to_front() {
     // Find the first sequence of non-char events.
     // EOF is considered a char: we don't want to pull EOF in front of real chars.
@@ -288,4 +316,5 @@ void input_event_queue_t::promote_interruptions_to_front() {
 void input_event_queue_t::prepare_to_select() {}
 void input_event_queue_t::select_interrupted() {}
 void input_event_queue_t::uvar_change_notified() {}
+void input_event_queue_t::history_change_notified() {}
 input_event_queue_t::~input_event_queue_t() = default;
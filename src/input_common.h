@@ -91,6 +91,10 @@ enum class readline_cmd_t {
     end_undo_group,
     repeat_jump,
     disable_mouse_tracking,
+    multi_cursor_from_selection,
+    select_inner_object,
+    select_around_object,
+    use_register,
     // NOTE: This one has to be last.
     reverse_repeat_jump
 };
@@ -106,6 +110,9 @@ enum class char_event_type_t : uint8_t {
     /// A readline event.
     readline,
 
+    /// Text was pasted with bracketed paste.
+    paste,
+
     /// end-of-file was reached.
     eof,
 
@@ -142,6 +149,7 @@ class char_event_t {
 
     /// The sequence of characters in the input mapping which generated this event.
     /// Note that the generic self-insert case does not have any characters, so this would be empty.
+    /// For a paste, this is the pasted text.
     wcstring seq{};
 
     bool is_char() const { return type == char_event_type_t::charc; }
@@ -152,6 +160,8 @@ class char_event_t {
 
     bool is_readline() const { return type == char_event_type_t::readline; }
 
+    bool is_paste() const { return type == char_event_type_t::paste; }
+
     wchar_t get_char() const {
         assert(type == char_event_type_t::charc && "Not a char type");
         return v_.c;
@@ -170,6 +180,19 @@ class char_event_t {
         return v_.rl;
     }
 
+    const wcstring &get_paste() const {
+        assert(type == char_event_type_t::paste && "Not a paste type");
+        return seq;
+    }
+
+    /// \return an event for the pasted \p text, which is delivered all at once rather than
+    /// character by character.
+    static char_event_t from_paste(wcstring text) {
+        char_event_t evt{char_event_type_t::paste};
+        evt.seq = std::move(text);
+        return evt;
+    }
+
     /* implicit */ char_event_t(wchar_t c) : type(char_event_type_t::charc) { v_.c = c; }
 
     /* implicit */ char_event_t(readline_cmd_t rl, wcstring seq = {})
@@ -205,6 +228,9 @@ class input_event_queue_t {
     /// \return none on timeout, the event on success.
     maybe_t<char_event_t> readch_timed();
 
+    /// Like readch_timed(), but wait at most \p wait_ms milliseconds.
+    maybe_t<char_event_t> readch_timed(int wait_ms);
+
     /// Enqueue a character or a readline function to the queue of unread characters that
     /// readch will return before actually reading from fd 0.
     void push_back(const char_event_t &ch);
@@ -216,6 +242,9 @@ class input_event_queue_t {
     /// Find the first sequence of non-char events, and promote them to the front.
     void promote_interruptions_to_front();
 
+    /// \return whether the last event in the queue is the readline function \p cmd.
+    bool queue_ends_with(readline_cmd_t cmd) const;
+
     /// Add multiple characters or readline events to the front of the queue of unread characters.
     /// The order of the provided events is not changed, i.e. they are not inserted in reverse
     /// order.
@@ -235,6 +264,10 @@ class input_event_queue_t {
     /// The default does nothing.
     virtual void uvar_change_notified();
 
+    /// Override point for when select() is interrupted by the history watcher, because another
+    /// shell changed the history file. The default does nothing.
+    virtual void history_change_notified();
+
     virtual ~input_event_queue_t();
 
    private:
@@ -31,8 +31,7 @@
 /// Base open mode to pass to calls to open.
 #define OPEN_MASK 0666
 
-/// Provide the fd monitor used for background fillthread operations.
-static fd_monitor_t &fd_monitor() {
+fd_monitor_t &fd_monitor() {
     // Deliberately leaked to avoid shutdown dtors.
     static auto fdm = make_fd_monitor_t();
     return *fdm;
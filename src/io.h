@@ -277,6 +277,10 @@ class io_bufferfill_t final : public io_data_t {
 
 struct callback_args_t;
 struct autoclose_fd_t2;
+struct fd_monitor_t;
+
+/// Provide the fd monitor used for background fillthread operations and timers.
+fd_monitor_t &fd_monitor();
 
 /// An io_buffer_t is a buffer which can populate itself by reading from an fd.
 /// It is not an io_data_t.
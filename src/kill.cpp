@@ -8,6 +8,7 @@
 
 #include <algorithm>
 #include <list>
+#include <map>
 #include <string>
 #include <utility>
 
@@ -17,6 +18,9 @@
 /** Kill ring */
 static owning_lock<std::list<wcstring>> s_kill_list;
 
+/// Named registers, which hold one string each.
+static owning_lock<std::map<wcstring, wcstring>> s_registers;
+
 void kill_add(wcstring str) {
     if (!str.empty()) {
         s_kill_list.acquire()->push_front(std::move(str));
@@ -59,3 +63,16 @@ std::vector<wcstring> kill_entries() {
 }
 
 wcstring_list_ffi_t kill_entries_ffi() { return kill_entries(); }
+
+bool kill_register_is_clipboard(const wcstring &name) { return name == L"+" || name == L"*"; }
+
+void kill_set_register(const wcstring &name, wcstring str) {
+    (*s_registers.acquire())[name] = std::move(str);
+}
+
+maybe_t<wcstring> kill_get_register(const wcstring &name) {
+    auto registers = s_registers.acquire();
+    auto iter = registers->find(name);
+    if (iter == registers->end()) return none();
+    return iter->second;
+}
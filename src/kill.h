@@ -6,6 +6,7 @@
 #define FISH_KILL_H
 
 #include "common.h"
+#include "maybe.h"
 #include "wutil.h"
 
 /// Replace the specified string in the killring.
@@ -26,4 +27,13 @@ std::vector<wcstring> kill_entries();
 /// Rust-friendly kill entries.
 wcstring_list_ffi_t kill_entries_ffi();
 
+/// \return whether the register called \p name is the system clipboard, like "+" in vim.
+bool kill_register_is_clipboard(const wcstring &name);
+
+/// Set the register called \p name, replacing what it held before.
+void kill_set_register(const wcstring &name, wcstring str);
+
+/// \return the contents of the register called \p name, or none if it was never set.
+maybe_t<wcstring> kill_get_register(const wcstring &name);
+
 #endif
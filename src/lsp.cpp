@@ -0,0 +1,742 @@
+// A language server for fish scripts, run by `fish --lsp`.
+//
+// Messages are JSON-RPC, each preceded by a Content-Length header. Documents are synchronized in
+// full, and positions are counted in UTF-16 code units, as the protocol requires by default.
+#include "config.h"  // IWYU pragma: keep
+
+#include "lsp.h"
+
+#include <errno.h>
+#include <strings.h>
+#include <unistd.h>
+
+#include <algorithm>
+#include <cstdlib>
+#include <cstring>
+#include <cwchar>
+#include <cwctype>
+#include <map>
+#include <memory>
+#include <string>
+#include <utility>
+#include <vector>
+
+#include "ast.h"
+#include "common.h"
+#include "complete.h"
+#include "fallback.h"  // IWYU pragma: keep
+#include "fish_indent_common.h"
+#include "fish_version.h"
+#include "function.h"
+#include "json.rs.h"
+#include "maybe.h"
+#include "operation_context.h"
+#include "parse_constants.h"
+#include "parse_util.h"
+#include "parser.h"
+#include "wcstringutil.h"
+#include "wutil.h"  // IWYU pragma: keep
+
+namespace {
+
+// JSON-RPC error codes.
+enum {
+    lsp_parse_error = -32700,
+    lsp_invalid_request = -32600,
+    lsp_method_not_found = -32601,
+};
+
+/// A parsed JSON value.
+struct json_value_t {
+    enum class type_t { null, boolean, number, string, array, object };
+    type_t type{type_t::null};
+    bool boolean{false};
+    double number{0};
+    wcstring string;
+    /// The elements of an array, or the values of an object.
+    std::vector<json_value_t> items;
+    /// The keys of an object, in the same order as its values.
+    std::vector<wcstring> keys;
+
+    /// \return the member \p key of an object, or null if there is none.
+    const json_value_t &get(const wchar_t *key) const;
+};
+
+const json_value_t &json_value_t::get(const wchar_t *key) const {
+    static const json_value_t none{};
+    for (size_t i = 0; i < keys.size(); i++) {
+        if (keys[i] == key) return items[i];
+    }
+    return none;
+}
+
+/// A parser for the messages we receive. It is strict, except that it doesn't limit what numbers
+/// look like.
+class json_reader_t {
+   public:
+    explicit json_reader_t(const wcstring &src) : src_(src) {}
+
+    /// Parse the whole source into \p out. \return false if it is not a single JSON value.
+    bool parse(json_value_t *out) {
+        if (!value(out, 0)) return false;
+        skip_space();
+        return pos_ == src_.size();
+    }
+
+   private:
+    const wcstring &src_;
+    size_t pos_{0};
+
+    // How deeply values may be nested.
+    static constexpr int max_depth = 64;
+
+    void skip_space() {
+        while (pos_ < src_.size() && (src_[pos_] == L' ' || src_[pos_] == L'\t' ||
+                                      src_[pos_] == L'\r' || src_[pos_] == L'\n')) {
+            pos_++;
+        }
+    }
+
+    /// Consume \p c if it is next, after any whitespace.
+    bool consume(wchar_t c) {
+        skip_space();
+        if (pos_ < src_.size() && src_[pos_] == c) {
+            pos_++;
+            return true;
+        }
+        return false;
+    }
+
+    bool literal(const wchar_t *lit) {
+        size_t len = std::wcslen(lit);
+        if (src_.compare(pos_, len, lit) != 0) return false;
+        pos_ += len;
+        return true;
+    }
+
+    bool value(json_value_t *out, int depth) {
+        using type_t = json_value_t::type_t;
+        skip_space();
+        if (pos_ >= src_.size() || depth > max_depth) return false;
+        wchar_t c = src_[pos_];
+        if (c == L'{') {
+            pos_++;
+            out->type = type_t::object;
+            if (consume(L'}')) return true;
+            do {
+                skip_space();
+                wcstring key;
+                if (!string(&key) || !consume(L':')) return false;
+                out->keys.push_back(std::move(key));
+                out->items.emplace_back();
+                if (!value(&out->items.back(), depth + 1)) return false;
+            } while (consume(L','));
+            return consume(L'}');
+        } else if (c == L'[') {
+            pos_++;
+            out->type = type_t::array;
+            if (consume(L']')) return true;
+            do {
+                out->items.emplace_back();
+                if (!value(&out->items.back(), depth + 1)) return false;
+            } while (consume(L','));
+            return consume(L']');
+        } else if (c == L'"') {
+            out->type = type_t::string;
+            return string(&out->string);
+        } else if (literal(L"true") || literal(L"false")) {
+            out->type = type_t::boolean;
+            out->boolean = c == L't';
+            return true;
+        } else if (literal(L"null")) {
+            return true;
+        }
+        size_t start = pos_;
+        while (pos_ < src_.size() && (iswdigit(src_[pos_]) || std::wcschr(L"+-.eE", src_[pos_]))) {
+            pos_++;
+        }
+        wcstring num = src_.substr(start, pos_ - start);
+        wchar_t *end = nullptr;
+        out->type = type_t::number;
+        out->number = fish_wcstod(num, &end);
+        return !num.empty() && *end == L'\0';
+    }
+
+    /// Read four hex digits into \p out.
+    bool hex4(long *out) {
+        if (pos_ + 4 > src_.size()) return false;
+        *out = 0;
+        for (size_t i = 0; i < 4; i++) {
+            wchar_t c = src_[pos_++];
+            if (!iswxdigit(c)) return false;
+            *out = *out * 16 + std::wcstol(wcstring(1, c).c_str(), nullptr, 16);
+        }
+        return true;
+    }
+
+    bool string(wcstring *out) {
+        if (pos_ >= src_.size() || src_[pos_] != L'"') return false;
+        pos_++;
+        while (pos_ < src_.size()) {
+            wchar_t c = src_[pos_++];
+            if (c == L'"') return true;
+            if (c != L'\\') {
+                out->push_back(c);
+                continue;
+            }
+            if (pos_ >= src_.size()) return false;
+            switch (c = src_[pos_++]) {
+                case L'"':
+                case L'\\':
+                case L'/':
+                    out->push_back(c);
+                    break;
+                case L'b':
+                    out->push_back(L'\b');
+                    break;
+                case L'f':
+                    out->push_back(L'\f');
+                    break;
+                case L'n':
+                    out->push_back(L'\n');
+                    break;
+                case L'r':
+                    out->push_back(L'\r');
+                    break;
+                case L't':
+                    out->push_back(L'\t');
+                    break;
+                case L'u': {
+                    long cp;
+                    if (!hex4(&cp)) return false;
+                    // Characters outside the BMP are escaped as surrogate pairs.
+                    if (cp >= 0xD800 && cp < 0xDC00) {
+                        long low;
+                        if (!literal(L"\\u") || !hex4(&low) || low < 0xDC00 || low >= 0xE000) {
+                            return false;
+                        }
+                        cp = 0x10000 + ((cp - 0xD800) << 10) + (low - 0xDC00);
+                    }
+                    out->push_back(static_cast<wchar_t>(cp));
+                    break;
+                }
+                default:
+                    return false;
+            }
+        }
+        return false;
+    }
+};
+
+/// \return how many UTF-16 code units \p c takes.
+size_t utf16_width(wchar_t c) { return static_cast<uint32_t>(c) > 0xFFFF ? 2 : 1; }
+
+struct position_t {
+    size_t line;
+    size_t character;
+};
+
+/// Convert an offset in \p text to a protocol position.
+position_t offset_to_position(const wcstring &text, size_t offset) {
+    position_t pos{0, 0};
+    offset = std::min(offset, text.size());
+    for (size_t i = 0; i < offset; i++) {
+        if (text[i] == L'\n') {
+            pos.line++;
+            pos.character = 0;
+        } else {
+            pos.character += utf16_width(text[i]);
+        }
+    }
+    return pos;
+}
+
+/// Convert the protocol position \p pos in \p text to an offset, clamping it to the line.
+size_t position_to_offset(const wcstring &text, const json_value_t &pos) {
+    double line = pos.get(L"line").number;
+    double character = pos.get(L"character").number;
+    size_t offset = 0;
+    for (double i = 0; i < line && offset < text.size(); offset++) {
+        if (text[offset] == L'\n') i++;
+    }
+    for (double units = 0; offset < text.size() && text[offset] != L'\n'; offset++) {
+        units += utf16_width(text[offset]);
+        if (units > character) break;
+    }
+    return offset;
+}
+
+/// Whether \p c may appear unescaped in the path of a URI.
+bool is_uri_path_char(char c) {
+    return (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || (c >= '0' && c <= '9') ||
+           std::strchr("-._~/", c);
+}
+
+wcstring path_to_uri(const wcstring &path) {
+    std::string uri = "file://";
+    for (char c : wcs2string(path)) {
+        if (c != '\0' && is_uri_path_char(c)) {
+            uri.push_back(c);
+        } else {
+            char buf[4];
+            snprintf(buf, sizeof buf, "%%%02X", static_cast<unsigned char>(c));
+            uri.append(buf);
+        }
+    }
+    return str2wcstring(uri);
+}
+
+void write_position(JsonWriter &w, position_t pos) {
+    w.begin_object();
+    w.key(L"line");
+    w.integer(pos.line);
+    w.key(L"character");
+    w.integer(pos.character);
+    w.end_object();
+}
+
+/// Write the range of \p length characters at \p start in \p text.
+void write_range(JsonWriter &w, const wcstring &text, size_t start, size_t length) {
+    w.begin_object();
+    w.key(L"start");
+    write_position(w, offset_to_position(text, start));
+    w.key(L"end");
+    write_position(w, offset_to_position(text, start + length));
+    w.end_object();
+}
+
+/// Write \p value back out, for echoing request ids.
+void write_value(JsonWriter &w, const json_value_t &value) {
+    using type_t = json_value_t::type_t;
+    switch (value.type) {
+        case type_t::null:
+            w.null();
+            break;
+        case type_t::boolean:
+            w.boolean(value.boolean);
+            break;
+        case type_t::number:
+            w.integer(static_cast<int64_t>(value.number));
+            break;
+        case type_t::string:
+            w.string(value.string);
+            break;
+        case type_t::array:
+            w.begin_array();
+            for (const auto &item : value.items) write_value(w, item);
+            w.end_array();
+            break;
+        case type_t::object:
+            w.begin_object();
+            for (size_t i = 0; i < value.keys.size(); i++) {
+                w.key(value.keys[i]);
+                write_value(w, value.items[i]);
+            }
+            w.end_object();
+            break;
+    }
+}
+
+class lsp_server_t {
+   public:
+    lsp_server_t(parser_t &parser, int in_fd, int out_fd)
+        : parser_(parser), in_fd_(in_fd), out_fd_(out_fd) {}
+
+    int serve();
+
+   private:
+    parser_t &parser_;
+    const int in_fd_;
+    const int out_fd_;
+
+    /// Input which has been read but not handled yet.
+    std::string input_;
+
+    /// The text of open documents, keyed by URI.
+    std::map<wcstring, wcstring> documents_;
+
+    /// Whether the client asked us to shut down, and to exit.
+    bool shutdown_{false};
+    bool exit_{false};
+
+    bool read_message(std::string *body);
+    void send(const JsonWriter &w);
+
+    /// Start a response to the request \p id. The caller writes the result and ends the object.
+    rust::Box<JsonWriter> begin_response(const json_value_t &id);
+    void respond_null(const json_value_t &id);
+    void respond_error(const json_value_t &id, int code, const wcstring &message);
+
+    void handle(const wcstring &method, const json_value_t *id, const json_value_t &params);
+    void initialize(const json_value_t &id);
+    void publish_diagnostics(const wcstring &uri);
+    void completion(const json_value_t &id, const wcstring &text, size_t offset);
+    void definition(const json_value_t &id, const wcstring &uri, size_t offset);
+    void formatting(const json_value_t &id, const wcstring &text);
+};
+
+int lsp_server_t::serve() {
+    std::string body;
+    while (!exit_ && read_message(&body)) {
+        json_value_t message;
+        wcstring src = str2wcstring(body);
+        if (!json_reader_t(src).parse(&message) ||
+            message.type != json_value_t::type_t::object) {
+            respond_error(json_value_t{}, lsp_parse_error, L"Parse error");
+            continue;
+        }
+        const json_value_t &method = message.get(L"method");
+        // Ignore responses, since we never make requests.
+        if (method.type != json_value_t::type_t::string) continue;
+        const json_value_t &id = message.get(L"id");
+        handle(method.string, id.type == json_value_t::type_t::null ? nullptr : &id,
+               message.get(L"params"));
+    }
+    return exit_ && shutdown_ ? 0 : 1;
+}
+
+bool lsp_server_t::read_message(std::string *body) {
+    // Read more input into input_. \return false at the end of the input.
+    auto fill = [this] {
+        char buf[4096];
+        ssize_t amt;
+        do {
+            amt = read(in_fd_, buf, sizeof buf);
+        } while (amt < 0 && errno == EINTR);
+        if (amt <= 0) return false;
+        input_.append(buf, amt);
+        return true;
+    };
+
+    // Read the headers. Only Content-Length matters.
+    maybe_t<size_t> length;
+    for (;;) {
+        size_t eol;
+        while ((eol = input_.find("\r\n")) == std::string::npos) {
+            if (!fill()) return false;
+        }
+        std::string header = input_.substr(0, eol);
+        input_.erase(0, eol + 2);
+        if (header.empty()) {
+            if (length) break;
+            continue;
+        }
+        const char *name = "content-length:";
+        if (strncasecmp(header.c_str(), name, std::strlen(name)) == 0) {
+            length = std::strtoul(header.c_str() + std::strlen(name), nullptr, 10);
+        }
+    }
+    while (input_.size() < *length) {
+        if (!fill()) return false;
+    }
+    body->assign(input_, 0, *length);
+    input_.erase(0, *length);
+    return true;
+}
+
+void lsp_server_t::send(const JsonWriter &w) {
+    std::string body = wcs2string(*w.contents());
+    std::string message = "Content-Length: " + std::to_string(body.size()) + "\r\n\r\n" + body;
+    if (write_loop(out_fd_, message.data(), message.size()) < 0) {
+        wperror(L"write");
+    }
+}
+
+rust::Box<JsonWriter> lsp_server_t::begin_response(const json_value_t &id) {
+    auto w = new_json_writer();
+    w->begin_object();
+    w->key(L"jsonrpc");
+    w->string(L"2.0");
+    w->key(L"id");
+    write_value(*w, id);
+    return w;
+}
+
+void lsp_server_t::respond_null(const json_value_t &id) {
+    auto w = begin_response(id);
+    w->key(L"result");
+    w->null();
+    w->end_object();
+    send(*w);
+}
+
+void lsp_server_t::respond_error(const json_value_t &id, int code, const wcstring &message) {
+    auto w = begin_response(id);
+    w->key(L"error");
+    w->begin_object();
+    w->key(L"code");
+    w->integer(code);
+    w->key(L"message");
+    w->string(message);
+    w->end_object();
+    w->end_object();
+    send(*w);
+}
+
+void lsp_server_t::handle(const wcstring &method, const json_value_t *id,
+                          const json_value_t &params) {
+    if (method == L"exit") {
+        exit_ = true;
+        return;
+    }
+    if (shutdown_) {
+        if (id) respond_error(*id, lsp_invalid_request, L"The server is shutting down");
+        return;
+    }
+
+    const wcstring &uri = params.get(L"textDocument").get(L"uri").string;
+    if (method == L"textDocument/didOpen") {
+        documents_[uri] = params.get(L"textDocument").get(L"text").string;
+        publish_diagnostics(uri);
+    } else if (method == L"textDocument/didChange") {
+        // We ask for full documents, so the last change has all of the text.
+        const json_value_t &changes = params.get(L"contentChanges");
+        if (!changes.items.empty()) {
+            documents_[uri] = changes.items.back().get(L"text").string;
+            publish_diagnostics(uri);
+        }
+    } else if (method == L"textDocument/didClose") {
+        documents_.erase(uri);
+        publish_diagnostics(uri);
+    } else if (!id) {
+        // Other notifications, like "initialized", need no answer.
+    } else if (method == L"initialize") {
+        initialize(*id);
+    } else if (method == L"shutdown") {
+        shutdown_ = true;
+        respond_null(*id);
+    } else if (method == L"textDocument/completion" || method == L"textDocument/definition" ||
+               method == L"textDocument/formatting") {
+        auto doc = documents_.find(uri);
+        if (doc == documents_.end()) {
+            respond_null(*id);
+            return;
+        }
+        const wcstring &text = doc->second;
+        size_t offset = position_to_offset(text, params.get(L"position"));
+        if (method == L"textDocument/completion") {
+            completion(*id, text, offset);
+        } else if (method == L"textDocument/definition") {
+            definition(*id, uri, offset);
+        } else {
+            formatting(*id, text);
+        }
+    } else {
+        respond_error(*id, lsp_method_not_found, format_string(L"Unknown method '%ls'",
+                                                               method.c_str()));
+    }
+}
+
+void lsp_server_t::initialize(const json_value_t &id) {
+    auto w = begin_response(id);
+    w->key(L"result");
+    w->begin_object();
+    w->key(L"capabilities");
+    w->begin_object();
+    w->key(L"textDocumentSync");
+    w->integer(1);  // full
+    w->key(L"completionProvider");
+    w->begin_object();
+    w->key(L"triggerCharacters");
+    w->begin_array();
+    w->string(L"-");
+    w->string(L"$");
+    w->end_array();
+    w->end_object();
+    w->key(L"definitionProvider");
+    w->boolean(true);
+    w->key(L"documentFormattingProvider");
+    w->boolean(true);
+    w->end_object();
+    w->key(L"serverInfo");
+    w->begin_object();
+    w->key(L"name");
+    w->string(L"fish");
+    w->key(L"version");
+    w->string(str2wcstring(get_fish_version()));
+    w->end_object();
+    w->end_object();
+    w->end_object();
+    send(*w);
+}
+
+void lsp_server_t::publish_diagnostics(const wcstring &uri) {
+    auto w = new_json_writer();
+    w->begin_object();
+    w->key(L"jsonrpc");
+    w->string(L"2.0");
+    w->key(L"method");
+    w->string(L"textDocument/publishDiagnostics");
+    w->key(L"params");
+    w->begin_object();
+    w->key(L"uri");
+    w->string(uri);
+    w->key(L"diagnostics");
+    w->begin_array();
+    auto doc = documents_.find(uri);
+    if (doc != documents_.end()) {
+        const wcstring &text = doc->second;
+        auto errors = new_parse_error_list();
+        parse_util_detect_errors(text, &*errors, false /* allow_incomplete */);
+        for (size_t i = 0; i < errors->size(); i++) {
+            const auto *error = errors->at(i);
+            wcstring message = *error->describe_with_prefix(text, L"", false, true);
+            if (message.empty()) message = *error->text();
+            size_t start = std::min(error->source_start(), text.size());
+            size_t length = std::min(error->source_length(), text.size() - start);
+            w->begin_object();
+            w->key(L"range");
+            write_range(*w, text, start, length);
+            w->key(L"severity");
+            w->integer(1);  // error
+            w->key(L"source");
+            w->string(L"fish");
+            w->key(L"message");
+            w->string(message);
+            w->end_object();
+        }
+    }
+    w->end_array();
+    w->end_object();
+    w->end_object();
+    send(*w);
+}
+
+void lsp_server_t::completion(const json_value_t &id, const wcstring &text, size_t offset) {
+    // Complete like the commandline would with the cursor at the offset.
+    const wchar_t *buff = text.c_str();
+    const wchar_t *cmdsub_begin, *cmdsub_end;
+    parse_util_cmdsubst_extent(buff, offset, &cmdsub_begin, &cmdsub_end);
+    const wchar_t *token_begin, *token_end;
+    parse_util_token_extent(buff, offset, &token_begin, &token_end, nullptr, nullptr);
+    size_t token_start = std::min(static_cast<size_t>(token_begin - buff), offset);
+    wcstring typed = text.substr(token_start, offset - token_start);
+
+    wcstring cmd(cmdsub_begin, buff + offset);
+    completion_list_t comps = complete(cmd, completion_request_options_t::normal(),
+                                       parser_.context());
+    completions_sort_and_prioritize(&comps);
+
+    auto w = begin_response(id);
+    w->key(L"result");
+    w->begin_array();
+    for (const completion_t &comp : comps) {
+        wcstring insert = comp.completion;
+        if (!(comp.flags & COMPLETE_DONT_ESCAPE)) {
+            escape_flags_t flags = ESCAPE_NO_QUOTED;
+            if (comp.flags & COMPLETE_DONT_ESCAPE_TILDES) flags |= ESCAPE_NO_TILDE;
+            insert = escape_string(insert, flags);
+        }
+        wcstring label = comp.completion;
+        if (!comp.replaces_token()) {
+            insert = typed + insert;
+            label = typed + label;
+        }
+        w->begin_object();
+        w->key(L"label");
+        w->string(label);
+        if (!comp.description.empty()) {
+            w->key(L"detail");
+            w->string(comp.description);
+        }
+        w->key(L"textEdit");
+        w->begin_object();
+        w->key(L"range");
+        write_range(*w, text, token_start, offset - token_start);
+        w->key(L"newText");
+        w->string(insert);
+        w->end_object();
+        w->end_object();
+    }
+    w->end_array();
+    w->end_object();
+    send(*w);
+}
+
+void lsp_server_t::definition(const json_value_t &id, const wcstring &uri, size_t offset) {
+    const wcstring &text = documents_[uri];
+    const wchar_t *token_begin, *token_end;
+    parse_util_token_extent(text.c_str(), offset, &token_begin, &token_end, nullptr, nullptr);
+    wcstring name(token_begin, token_end);
+    if (name.empty() || !unescape_string_in_place(&name, UNESCAPE_DEFAULT)) {
+        respond_null(id);
+        return;
+    }
+
+    // Write a location as the response.
+    auto respond = [&](const wcstring &loc_uri, const wcstring &loc_text, size_t start,
+                       size_t length) {
+        auto w = begin_response(id);
+        w->key(L"result");
+        w->begin_object();
+        w->key(L"uri");
+        w->string(loc_uri);
+        w->key(L"range");
+        write_range(*w, loc_text, start, length);
+        w->end_object();
+        w->end_object();
+        send(*w);
+    };
+
+    // Prefer functions defined in this document, then in other open documents.
+    std::vector<const std::pair<const wcstring, wcstring> *> docs;
+    docs.push_back(&*documents_.find(uri));
+    for (const auto &doc : documents_) {
+        if (doc.first != uri) docs.push_back(&doc);
+    }
+    for (const auto *doc : docs) {
+        const wcstring &doc_text = doc->second;
+        auto ast = ast_parse(doc_text, parse_flag_leave_unterminated);
+        for (auto ast_traversal = new_ast_traversal(*ast->top());;) {
+            auto node = ast_traversal->next();
+            if (!node->has_value()) break;
+            const auto *header = node->try_as_function_header();
+            if (!header) continue;
+            const ast::argument_t &arg = header->first_arg();
+            wcstring function = *arg.source(doc_text);
+            if (unescape_string_in_place(&function, UNESCAPE_DEFAULT) && function == name) {
+                source_range_t range = arg.source_range();
+                respond(doc->first, doc_text, range.start, range.length);
+                return;
+            }
+        }
+    }
+
+    // Otherwise go to the file the function was loaded from.
+    if (function_properties_ref_t props = function_get_props_autoload(name, parser_)) {
+        wcstring file = function_get_definition_file(*props);
+        if (string_prefixes_string(L"/", file)) {
+            int lineno = std::max(function_get_definition_lineno(*props), 1);
+            wcstring lines(lineno - 1, L'\n');
+            respond(path_to_uri(file), lines, lines.size(), 0);
+            return;
+        }
+    }
+    respond_null(id);
+}
+
+void lsp_server_t::formatting(const json_value_t &id, const wcstring &text) {
+    pretty_printer_t printer{text, pretty_printer_options_t{}};
+    wcstring output = printer.prettify();
+
+    auto w = begin_response(id);
+    w->key(L"result");
+    w->begin_array();
+    if (output != text) {
+        w->begin_object();
+        w->key(L"range");
+        write_range(*w, text, 0, text.size());
+        w->key(L"newText");
+        w->string(output);
+        w->end_object();
+    }
+    w->end_array();
+    w->end_object();
+    send(*w);
+}
+
+}  // namespace
+
+int lsp_serve(parser_t &parser, int in_fd, int out_fd) {
+    return lsp_server_t(parser, in_fd, out_fd).serve();
+}
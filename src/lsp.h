@@ -0,0 +1,15 @@
+// A language server for fish scripts, run by `fish --lsp`.
+//
+// This speaks the Language Server Protocol over a pair of file descriptors, and answers with the
+// same machinery the interactive shell uses: diagnostics come from the parser, completions from the
+// completion engine, and formatting from fish_indent's pretty printer.
+#ifndef FISH_LSP_H
+#define FISH_LSP_H
+
+class parser_t;
+
+/// Serve requests read from \p in_fd, writing responses to \p out_fd, until the client exits.
+/// \return the exit status: 0 if the client asked for a shutdown before exiting, 1 otherwise.
+int lsp_serve(parser_t &parser, int in_fd, int out_fd);
+
+#endif
@@ -1,6 +1,7 @@
 #ifndef FISH_OPERATION_CONTEXT_H
 #define FISH_OPERATION_CONTEXT_H
 
+#include <atomic>
 #include <cstddef>
 #include <functional>
 #include <memory>
@@ -14,6 +15,30 @@ struct job_group_t;
 /// A common helper which always returns false.
 bool no_cancel();
 
+/// A cooperative cancellation token for background work. Copies share their state, so whoever
+/// starts the work keeps one copy and hands another to the work, and cancels it once the result
+/// would be thrown away. The work polls it, typically through the cancel checker of an
+/// operation_context_t, and gives up early.
+class cancel_token_t {
+   public:
+    cancel_token_t() : cancelled_(std::make_shared<std::atomic<bool>>(false)) {}
+
+    /// Request cancellation. This may be called from any thread.
+    void cancel() const { cancelled_->store(true, std::memory_order_relaxed); }
+
+    /// \return whether cancellation was requested. This may be called from any thread.
+    bool cancelled() const { return cancelled_->load(std::memory_order_relaxed); }
+
+    /// \return a cancel checker which polls this token.
+    cancel_checker_t checker() const {
+        auto cancelled = cancelled_;
+        return [cancelled] { return cancelled->load(std::memory_order_relaxed); };
+    }
+
+   private:
+    std::shared_ptr<std::atomic<bool>> cancelled_;
+};
+
 /// Default limits for expansion.
 enum expansion_limit_t : size_t {
     /// The default maximum number of items from expansion.
@@ -4,31 +4,21 @@
 #include <stdio.h>
 #include <unistd.h>
 
-#if HAVE_CURSES_H
-#include <curses.h>  // IWYU pragma: keep
-#elif HAVE_NCURSES_H
-#include <ncurses.h>
-#elif HAVE_NCURSES_CURSES_H
-#include <ncurses/curses.h>
-#endif
-#if HAVE_TERM_H
-#include <term.h>
-#elif HAVE_NCURSES_TERM_H
-#include <ncurses/term.h>
-#endif
-
+#include <cctype>
 #include <cwchar>
-#include <mutex>
+#include <initializer_list>
 #include <string>
 #include <vector>
 
 #include "color.h"
 #include "common.h"
 #include "env.h"
+#include "env_dispatch.rs.h"
 #include "fallback.h"  // IWYU pragma: keep
 #include "flog.h"
 #include "maybe.h"
 #include "output.h"
+#include "output.rs.h"
 #include "threads.rs.h"
 #include "wcstringutil.h"
 #include "wutil.h"  // IWYU pragma: keep
@@ -36,9 +26,9 @@
 /// Whether term256 and term24bit are supported.
 static color_support_t color_support = 0;
 
-/// Returns true if we think fish_tparm can handle outputting a color index
+/// Returns true if we think the terminal can handle outputting a color index
 static bool term_supports_color_natively(unsigned int c) {
-    return static_cast<unsigned>(max_colors) >= c + 1;
+    return term_get_number_ffi("colors") >= static_cast<int>(c + 1);
 }
 
 extern "C" {
@@ -47,16 +37,23 @@ extern "C" {
 }
 
 unsigned char index_for_color(rgb_color_t c) {
-    if (c.is_named() || !(output_get_color_support() & color_support_term256)) {
-        return c.to_name_index();
+    if (c.is_named()) return c.to_name_index();
+    // Reduce RGB colors to the nearest color the terminal has.
+    color24_t rgb = c.to_color24();
+    uint16_t palette_size = 16;
+    if (output_get_color_support() & color_support_term256) {
+        palette_size = 256;
+    } else if (term_get_number_ffi("colors") == 8) {
+        palette_size = 8;
     }
-    return c.to_term256_index();
+    return quantize_color_ffi(rgb.rgb[0], rgb.rgb[1], rgb.rgb[2], palette_size);
 }
 
-static bool write_color_escape(outputter_t &outp, const char *todo, unsigned char idx, bool is_fg) {
+static bool write_color_escape(outputter_t &outp, const std::string &todo, unsigned char idx,
+                               bool is_fg) {
     if (term_supports_color_natively(idx)) {
-        // Use fish_tparm to emit color escape.
-        writembs(outp, fish_tparm(const_cast<char *>(todo), idx));
+        // Use term_expand to emit color escape.
+        writembs(outp, term_expand(todo, {idx}));
         return true;
     }
 
@@ -69,7 +66,7 @@ static bool write_color_escape(outputter_t &outp, const char *todo, unsigned cha
         // TODO: enter bold mode in builtin_set_color in the same circumstance- doing that combined
         // with what we do here, will make the brights actually work for virtual consoles/ancient
         // emulators.
-        if (max_colors == 8 && idx > 8) idx -= 8;
+        if (term_get_number_ffi("colors") == 8 && idx > 8) idx -= 8;
         snprintf(buff, sizeof buff, "\x1B[%dm", ((idx > 7) ? 82 : 30) + idx + !is_fg * 10);
     } else {
         snprintf(buff, sizeof buff, "\x1B[%d;5;%dm", is_fg ? 38 : 48, idx);
@@ -80,25 +77,68 @@ static bool write_color_escape(outputter_t &outp, const char *todo, unsigned cha
 }
 
 static bool write_foreground_color(outputter_t &outp, unsigned char idx) {
-    if (!cur_term) return false;
-    if (set_a_foreground && set_a_foreground[0]) {
-        return write_color_escape(outp, set_a_foreground, idx, true);
-    } else if (set_foreground && set_foreground[0]) {
-        return write_color_escape(outp, set_foreground, idx, true);
+    maybe_t<std::string> set_a_foreground = term_get_string("setaf");
+    maybe_t<std::string> set_foreground = term_get_string("setf");
+    if (set_a_foreground && !set_a_foreground->empty()) {
+        return write_color_escape(outp, *set_a_foreground, idx, true);
+    } else if (set_foreground && !set_foreground->empty()) {
+        return write_color_escape(outp, *set_foreground, idx, true);
     }
     return false;
 }
 
 static bool write_background_color(outputter_t &outp, unsigned char idx) {
-    if (!cur_term) return false;
-    if (set_a_background && set_a_background[0]) {
-        return write_color_escape(outp, set_a_background, idx, false);
-    } else if (set_background && set_background[0]) {
-        return write_color_escape(outp, set_background, idx, false);
+    maybe_t<std::string> set_a_background = term_get_string("setab");
+    maybe_t<std::string> set_background = term_get_string("setb");
+    if (set_a_background && !set_a_background->empty()) {
+        return write_color_escape(outp, *set_a_background, idx, false);
+    } else if (set_background && !set_background->empty()) {
+        return write_color_escape(outp, *set_background, idx, false);
     }
     return false;
 }
 
+void outputter_t::write_underline_style(underline_style_t style) {
+    if (style == underline_style_t::none) {
+        writembs_nofail(*this, term_get_string("rmul"));
+        return;
+    }
+    if (style != underline_style_t::single) {
+        rust::Vec<uint8_t> seq = term_underline_style_ffi(static_cast<uint8_t>(style));
+        if (!seq.empty()) {
+            writestr(reinterpret_cast<const char *>(seq.data()), seq.size());
+            return;
+        }
+    }
+    // A single underline, which is also what we show for styles the terminal doesn't know.
+    writembs_nofail(*this, term_get_string("smul"));
+}
+
+void outputter_t::write_underline_color(rgb_color_t color) {
+    if (!term_supports_underline_color()) return;
+    if (color.is_special()) {
+        writestr("\x1B[59m");
+    } else if (color.is_rgb() && (output_get_color_support() & color_support_term24bit)) {
+        color24_t rgb = color.to_color24();
+        uint32_t packed = (rgb.rgb[0] << 16) | (rgb.rgb[1] << 8) | rgb.rgb[2];
+        rust::Vec<uint8_t> seq = term_underline_color_ffi(packed);
+        writestr(reinterpret_cast<const char *>(seq.data()), seq.size());
+    } else {
+        // There is no capability for indexed underline colors, but every terminal that can color
+        // underlines understands this.
+        char buff[16];
+        snprintf(buff, sizeof buff, "\x1B[58:5:%dm", index_for_color(color));
+        writestr(buff);
+    }
+}
+
+bool outputter_t::write_strikethrough(bool enable) {
+    rust::Vec<uint8_t> seq = term_strikethrough_ffi(enable);
+    if (seq.empty()) return false;
+    writestr(reinterpret_cast<const char *>(seq.data()), seq.size());
+    return true;
+}
+
 void outputter_t::flush_to(int fd) {
     if (fd >= 0 && !contents_.empty()) {
         write_loop(fd, contents_.data(), contents_.size());
@@ -108,7 +148,8 @@ void outputter_t::flush_to(int fd) {
 
 // Exported for builtin_set_color's usage only.
 bool outputter_t::write_color(rgb_color_t color, bool is_fg) {
-    if (!cur_term) return false;
+    env_ensure_curses_initialized();
+    if (!term_has_entry()) return false;
     bool supports_term24bit =
         static_cast<bool>(output_get_color_support() & color_support_term24bit);
     if (!supports_term24bit || !color.is_rgb()) {
@@ -117,7 +158,7 @@ bool outputter_t::write_color(rgb_color_t color, bool is_fg) {
         return (is_fg ? write_foreground_color : write_background_color)(*this, idx);
     }
 
-    // 24 bit! No fish_tparm here, just ANSI escape sequences.
+    // 24 bit! No term_expand here, just ANSI escape sequences.
     // Foreground: ^[38;2;<r>;<g>;<b>m
     // Background: ^[48;2;<r>;<g>;<b>m
     color24_t rgb = color.to_color24();
@@ -147,18 +188,30 @@ bool outputter_t::write_color(rgb_color_t color, bool is_fg) {
 ///
 /// \param fg Foreground color.
 /// \param bg Background color.
-void outputter_t::set_color(rgb_color_t fg, rgb_color_t bg) {
+/// \param underline_color Underline color, or none for the foreground color.
+void outputter_t::set_color(rgb_color_t fg, rgb_color_t bg, rgb_color_t underline_color) {
     // Test if we have at least basic support for setting fonts, colors and related bits - otherwise
     // just give up...
-    if (!cur_term || !exit_attribute_mode) return;
+    env_ensure_curses_initialized();
+    const maybe_t<std::string> exit_attribute_mode = term_get_string("sgr0");
+    if (!exit_attribute_mode) return;
+    const maybe_t<std::string> enter_bold_mode = term_get_string("bold");
+    const maybe_t<std::string> enter_italics_mode = term_get_string("sitm");
+    const maybe_t<std::string> exit_italics_mode = term_get_string("ritm");
+    const maybe_t<std::string> enter_dim_mode = term_get_string("dim");
+    const maybe_t<std::string> enter_reverse_mode = term_get_string("rev");
+    const maybe_t<std::string> enter_standout_mode = term_get_string("smso");
+    const maybe_t<std::string> enter_blink_mode = term_get_string("blink");
 
     const rgb_color_t normal = rgb_color_t::normal();
     bool bg_set = false, last_bg_set = false;
     bool is_bold = fg.is_bold() || bg.is_bold();
-    bool is_underline = fg.is_underline() || bg.is_underline();
+    underline_style_t underline = fg.is_underline() ? fg.underline_style() : bg.underline_style();
     bool is_italics = fg.is_italics() || bg.is_italics();
     bool is_dim = fg.is_dim() || bg.is_dim();
     bool is_reverse = fg.is_reverse() || bg.is_reverse();
+    bool is_strikethrough = fg.is_strikethrough() || bg.is_strikethrough();
+    bool is_blink = fg.is_blink() || bg.is_blink();
 
     if (fg.is_reset() || bg.is_reset()) {
         fg = bg = normal;
@@ -170,8 +223,9 @@ void outputter_t::set_color(rgb_color_t fg, rgb_color_t bg) {
         return;
     }
 
-    if ((was_bold && !is_bold) || (was_dim && !is_dim) || (was_reverse && !is_reverse)) {
-        // Only way to exit bold/dim/reverse mode is a reset of all attributes.
+    if ((was_bold && !is_bold) || (was_dim && !is_dim) || (was_reverse && !is_reverse) ||
+        (was_blink && !is_blink)) {
+        // Only way to exit bold/dim/reverse/blink mode is a reset of all attributes.
         writembs(*this, exit_attribute_mode);
         last_color = normal;
         last_color2 = normal;
@@ -192,7 +246,7 @@ void outputter_t::set_color(rgb_color_t fg, rgb_color_t bg) {
             fg = (bg == rgb_color_t::white()) ? rgb_color_t::black() : rgb_color_t::white();
     }
 
-    if (enter_bold_mode && enter_bold_mode[0] != '\0') {
+    if (enter_bold_mode && !enter_bold_mode->empty()) {
         if (bg_set && !last_bg_set) {
             // Background color changed and is set, so we enter bold mode to make reading easier.
             // This means bold mode is _always_ on when the background color is set.
@@ -240,75 +294,82 @@ void outputter_t::set_color(rgb_color_t fg, rgb_color_t bg) {
         }
     }
 
-    // Lastly, we set bold, underline, italics, dim, and reverse modes correctly.
-    if (is_bold && !was_bold && enter_bold_mode && enter_bold_mode[0] != '\0' && !bg_set) {
-        // The unconst cast is for NetBSD's benefit. DO NOT REMOVE!
-        writembs_nofail(*this, fish_tparm(const_cast<char *>(enter_bold_mode)));
+    // Lastly, we set bold, underline, italics, dim, reverse, strikethrough and blink modes
+    // correctly.
+    if (is_bold && !was_bold && enter_bold_mode && !enter_bold_mode->empty() && !bg_set) {
+        writembs_nofail(*this, term_expand(*enter_bold_mode));
         was_bold = is_bold;
     }
 
-    if (was_underline && !is_underline) {
-        writembs_nofail(*this, exit_underline_mode);
+    if (underline != was_underline) {
+        // Going back to a single underline from another style needs the style reset first.
+        if (underline == underline_style_t::single && was_underline != underline_style_t::none) {
+            writembs_nofail(*this, term_get_string("rmul"));
+        }
+        write_underline_style(underline);
+        was_underline = underline;
     }
 
-    if (!was_underline && is_underline) {
-        writembs_nofail(*this, enter_underline_mode);
+    if (underline_color != last_underline_color) {
+        write_underline_color(underline_color);
+        last_underline_color = underline_color;
     }
-    was_underline = is_underline;
 
-    if (was_italics && !is_italics && enter_italics_mode && enter_italics_mode[0] != '\0') {
+    if (was_italics && !is_italics && enter_italics_mode && !enter_italics_mode->empty()) {
         writembs_nofail(*this, exit_italics_mode);
         was_italics = is_italics;
     }
 
-    if (!was_italics && is_italics && enter_italics_mode && enter_italics_mode[0] != '\0') {
+    if (!was_italics && is_italics && enter_italics_mode && !enter_italics_mode->empty()) {
         writembs_nofail(*this, enter_italics_mode);
         was_italics = is_italics;
     }
 
-    if (is_dim && !was_dim && enter_dim_mode && enter_dim_mode[0] != '\0') {
+    if (is_dim && !was_dim && enter_dim_mode && !enter_dim_mode->empty()) {
         writembs_nofail(*this, enter_dim_mode);
         was_dim = is_dim;
     }
 
     if (is_reverse && !was_reverse) {
         // Some terms do not have a reverse mode set, so standout mode is a fallback.
-        if (enter_reverse_mode && enter_reverse_mode[0] != '\0') {
+        if (enter_reverse_mode && !enter_reverse_mode->empty()) {
             writembs_nofail(*this, enter_reverse_mode);
             was_reverse = is_reverse;
-        } else if (enter_standout_mode && enter_standout_mode[0] != '\0') {
+        } else if (enter_standout_mode && !enter_standout_mode->empty()) {
             writembs_nofail(*this, enter_standout_mode);
             was_reverse = is_reverse;
         }
     }
-}
 
-// tputs accepts a function pointer that receives an int only.
-// Use the following lock to redirect it to the proper outputter.
-// Note we can't use owning_lock because the tputs_writer must access it and owning_lock is not
-// recursive.
-static std::mutex s_tputs_receiver_lock;
-static outputter_t *s_tputs_receiver{nullptr};
-
-static int tputs_writer(tputs_arg_t b) {
-    ASSERT_IS_LOCKED(s_tputs_receiver_lock);
-    assert(s_tputs_receiver && "null s_tputs_receiver");
-    char c = static_cast<char>(b);
-    s_tputs_receiver->writestr(&c, 1);
-    return 0;
+    if (is_strikethrough != was_strikethrough && write_strikethrough(is_strikethrough)) {
+        was_strikethrough = is_strikethrough;
+    }
+
+    if (is_blink && !was_blink && enter_blink_mode && !enter_blink_mode->empty()) {
+        writembs_nofail(*this, enter_blink_mode);
+        was_blink = is_blink;
+    }
 }
 
-int outputter_t::term_puts(const char *str, int affcnt) {
-    // Acquire the lock, use a scoped_push to substitute in our receiver, then call tputs. The
-    // scoped_push will restore it.
-    scoped_lock locker{s_tputs_receiver_lock};
-    scoped_push<outputter_t *> push(&s_tputs_receiver, this);
-    s_tputs_receiver->begin_buffering();
-    // On some systems, tputs takes a char*, on others a const char*.
-    // Like fish_tparm, we just cast it to unconst, that should work everywhere.
-    int res = tputs(const_cast<char *>(str), affcnt, tputs_writer);
-    s_tputs_receiver->end_buffering();
-    return res;
+void outputter_t::term_puts(const char *str) {
+    // We don't pad, so drop the delays, which look like $<5> or $<2.5*/>. Anything else that starts
+    // with $< is written as is.
+    std::string out;
+    for (const char *cursor = str; *cursor;) {
+        if (cursor[0] == '$' && cursor[1] == '<') {
+            const char *end = cursor + 2;
+            while (std::isdigit(static_cast<unsigned char>(*end)) || *end == '.' || *end == '*' ||
+                   *end == '/') {
+                end++;
+            }
+            if (*end == '>') {
+                cursor = end + 1;
+                continue;
+            }
+        }
+        out.push_back(*cursor++);
+    }
+    writestr(out.data(), out.size());
 }
 
 void outputter_t::writestr(const wchar_t *str, size_t len) {
@@ -357,10 +418,12 @@ rgb_color_t best_color(const std::vector<rgb_color_t> &candidates, color_support
 ///       In particular, the argument parsing still isn't fully capable.
 rgb_color_t parse_color(const env_var_t &var, bool is_background) {
     bool is_bold = false;
-    bool is_underline = false;
+    underline_style_t underline = underline_style_t::none;
     bool is_italics = false;
     bool is_dim = false;
     bool is_reverse = false;
+    bool is_strikethrough = false;
+    bool is_blink = false;
 
     std::vector<rgb_color_t> candidates;
 
@@ -395,13 +458,22 @@ rgb_color_t parse_color(const env_var_t &var, bool is_background) {
             if (next == L"--bold" || next == L"-o")
                 is_bold = true;
             else if (next == L"--underline" || next == L"-u")
-                is_underline = true;
+                underline = underline_style_t::single;
+            else if (string_prefixes_string(L"--underline=", next))
+                underline = underline_style_from_name(wcstring(next, 12))
+                                .value_or(underline_style_t::single);
+            else if (string_prefixes_string(L"--underline-color=", next))
+                continue;  // See parse_underline_color().
             else if (next == L"--italics" || next == L"-i")
                 is_italics = true;
             else if (next == L"--dim" || next == L"-d")
                 is_dim = true;
             else if (next == L"--reverse" || next == L"-r")
                 is_reverse = true;
+            else if (next == L"--strikethrough" || next == L"-s")
+                is_strikethrough = true;
+            else if (next == L"--blink")
+                is_blink = true;
             else
                 color_name = next;
         }
@@ -418,18 +490,68 @@ rgb_color_t parse_color(const env_var_t &var, bool is_background) {
     if (result.is_none()) result = rgb_color_t::normal();
 
     result.set_bold(is_bold);
-    result.set_underline(is_underline);
+    result.set_underline_style(underline);
     result.set_italics(is_italics);
     result.set_dim(is_dim);
     result.set_reverse(is_reverse);
+    result.set_strikethrough(is_strikethrough);
+    result.set_blink(is_blink);
     return result;
 }
 
+rgb_color_t parse_underline_color(const env_var_t &var) {
+    const wchar_t *prefix = L"--underline-color=";
+    size_t prefix_len = wcslen(prefix);
+
+    std::vector<rgb_color_t> candidates;
+    for (const wcstring &next : var.as_list()) {
+        if (string_prefixes_string(prefix, next)) {
+            rgb_color_t color = rgb_color_t(wcstring(next, prefix_len));
+            if (!color.is_none()) candidates.push_back(color);
+        }
+    }
+    return best_color(candidates, output_get_color_support());
+}
+
+maybe_t<underline_style_t> underline_style_from_name(const wcstring &name) {
+    static const struct {
+        const wchar_t *name;
+        underline_style_t style;
+    } styles[] = {
+        {L"single", underline_style_t::single}, {L"double", underline_style_t::double_},
+        {L"curly", underline_style_t::curly},   {L"dotted", underline_style_t::dotted},
+        {L"dashed", underline_style_t::dashed},
+    };
+    for (const auto &s : styles) {
+        if (name == s.name) return s.style;
+    }
+    return none();
+}
+
 /// Write specified multibyte string.
+void writembs_check(outputter_t &outp, const maybe_t<std::string> &mbs, const char *mbs_name,
+                    bool critical, const char *file, long line) {
+    writembs_check(outp, mbs ? mbs->c_str() : nullptr, mbs_name, critical, file, line);
+}
+
+std::string term_expand(const std::string &cap, std::initializer_list<int> params) {
+    rust::Slice<const uint8_t> cap_bytes{reinterpret_cast<const uint8_t *>(cap.data()),
+                                         cap.size()};
+    rust::Vec<uint8_t> result =
+        term_expand_ffi(cap_bytes, rust::Slice<const int32_t>{params.begin(), params.size()});
+    return std::string(reinterpret_cast<const char *>(result.data()), result.size());
+}
+
+maybe_t<std::string> term_get_string(const char *name) {
+    rust::Vec<uint8_t> value;
+    if (!term_get_string_ffi(name, value)) return none();
+    return std::string(reinterpret_cast<const char *>(value.data()), value.size());
+}
+
 void writembs_check(outputter_t &outp, const char *mbs, const char *mbs_name, bool critical,
                     const char *file, long line) {
     if (mbs != nullptr) {
-        outp.term_puts(mbs, 1);
+        outp.term_puts(mbs);
     } else if (critical) {
         auto term = env_stack_t::globals().get(L"TERM");
         const wchar_t *fmt =
@@ -8,12 +8,14 @@
 #include <cstdint>
 #include <cstring>
 #include <cwchar>
+#include <initializer_list>
 #include <string>
 #include <vector>
 
 #include "color.h"
 #include "common.h"
 #include "fallback.h"  // IWYU pragma: keep
+#include "maybe.h"
 
 class env_var_t;
 
@@ -29,18 +31,24 @@ class outputter_t {
 
     rgb_color_t last_color = rgb_color_t::normal();
     rgb_color_t last_color2 = rgb_color_t::normal();
+    rgb_color_t last_underline_color = rgb_color_t::none();
     bool was_bold = false;
-    bool was_underline = false;
+    underline_style_t was_underline = underline_style_t::none;
     bool was_italics = false;
     bool was_dim = false;
     bool was_reverse = false;
+    bool was_strikethrough = false;
+    bool was_blink = false;
 
     void reset_modes() {
         was_bold = false;
-        was_underline = false;
+        was_underline = underline_style_t::none;
         was_italics = false;
         was_dim = false;
         was_reverse = false;
+        was_strikethrough = false;
+        was_blink = false;
+        last_underline_color = rgb_color_t::none();
     }
 
     /// Construct an outputter which outputs to a given fd.
@@ -58,8 +66,22 @@ class outputter_t {
     /// Unconditionally write the color string to the output.
     bool write_color(rgb_color_t color, bool is_fg);
 
-    /// Set the foreground and background color.
-    void set_color(rgb_color_t fg, rgb_color_t bg);
+    /// Write the string to switch to the underline \p style, or to turn underlines off if it is
+    /// none. Styles other than a single line need the Smulx capability.
+    void write_underline_style(underline_style_t style);
+
+    /// Write the string to set the color of underlines, or to use the text color if \p color is
+    /// special. This needs the Setulc capability, without which nothing is written.
+    void write_underline_color(rgb_color_t color);
+
+    /// Write the string to start or end struck through text. This needs the smxx and rmxx
+    /// capabilities; returns false if the terminal doesn't have them.
+    bool write_strikethrough(bool enable);
+
+    /// Set the foreground and background color, and the color of underlines if the terminal can
+    /// color them separately.
+    void set_color(rgb_color_t fg, rgb_color_t bg,
+                   rgb_color_t underline_color = rgb_color_t::none());
 
     /// Write a wide character to the receiver.
     void writech(wchar_t ch) { writestr(&ch, 1); }
@@ -70,8 +92,8 @@ class outputter_t {
     /// Write a wide character string to the receiver.
     void writestr(const wcstring &str) { writestr(str.data(), str.size()); }
 
-    /// Write the given terminfo string to the receiver, like tputs().
-    int term_puts(const char *str, int affcnt);
+    /// Write the given terminfo string to the receiver, like tputs() but without padding.
+    void term_puts(const char *str);
 
     /// Write a wide string of the given length.
     void writestr(const wchar_t *str, size_t len);
@@ -119,11 +141,28 @@ class outputter_t {
 
 void writembs_check(outputter_t &outp, const char *mbs, const char *mbs_name, bool critical,
                     const char *file, long line);
+void writembs_check(outputter_t &outp, const maybe_t<std::string> &mbs, const char *mbs_name,
+                    bool critical, const char *file, long line);
 #define writembs(outp, mbs) writembs_check((outp), (mbs), #mbs, true, __FILE__, __LINE__)
 #define writembs_nofail(outp, mbs) writembs_check((outp), (mbs), #mbs, false, __FILE__, __LINE__)
 
+/// Return the string capability \p name of the terminal, by its terminfo name like "sgr0", or none
+/// if the terminal doesn't have it or we have no terminfo entry for the terminal.
+maybe_t<std::string> term_get_string(const char *name);
+
+/// Instantiate the parameterized terminfo string \p cap with the numbers \p params, like tparm().
+/// Returns an empty string if \p cap is malformed or we have no terminfo entry.
+std::string term_expand(const std::string &cap, std::initializer_list<int> params = {});
+
 rgb_color_t parse_color(const env_var_t &var, bool is_background);
 
+/// Return the color given with --underline-color in \p var, or none if there is none.
+rgb_color_t parse_underline_color(const env_var_t &var);
+
+/// Return the underline style with the given name, like "curly", or none() if there is no such
+/// style.
+maybe_t<underline_style_t> underline_style_from_name(const wcstring &name);
+
 /// Sets what colors are supported.
 enum { color_support_term256 = 1 << 0, color_support_term24bit = 1 << 1 };
 using color_support_t = unsigned int;
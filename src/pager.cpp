@@ -120,6 +120,25 @@ static size_t print_max(const wcstring &str, highlight_spec_t color, size_t max,
         str, [=](size_t) -> highlight_spec_t { return color; }, max, has_more, line);
 }
 
+/// \return the positions of the characters in \p shown, a completion as the pager shows it, that
+/// matched \p needle in the way described by \p match.
+static std::vector<size_t> get_match_positions(const string_fuzzy_match_t &match,
+                                               const wcstring &needle, const wcstring &shown) {
+    // Paths are matched one component at a time, so only the last one is matched fuzzily.
+    size_t dir_len = 0;
+    size_t slash = needle.rfind(L'/');
+    if (slash != wcstring::npos && string_prefixes_string(needle.substr(0, slash + 1), shown)) {
+        dir_len = slash + 1;
+    }
+
+    std::vector<size_t> result;
+    for (size_t i = 0; i < dir_len; i++) result.push_back(i);
+    for (size_t pos : match.positions(needle.substr(dir_len), shown.substr(dir_len))) {
+        result.push_back(dir_len + pos);
+    }
+    return result;
+}
+
 /// Print the specified item using at the specified amount of space.
 line_t pager_t::completion_print_item(const wcstring &prefix, const comp_t *c, size_t row,
                                       size_t column, size_t width, bool secondary, bool selected,
@@ -171,6 +190,7 @@ line_t pager_t::completion_print_item(const wcstring &prefix, const comp_t *c, s
                                      : highlight_role_t::pager_completion),
         bg_role};
     highlight_spec_t comp_col = {modify_role(highlight_role_t::pager_completion), bg_role};
+    highlight_spec_t match_col = {modify_role(highlight_role_t::pager_match), bg_role};
     highlight_spec_t desc_col = {modify_role(highlight_role_t::pager_description), bg_role};
 
     // Print the completion part
@@ -183,13 +203,28 @@ line_t pager_t::completion_print_item(const wcstring &prefix, const comp_t *c, s
                 print_max(PAGER_SPACER_STRING, bg, comp_remaining, true /* has_more */, &line_data);
         }
 
-        comp_remaining -= print_max(prefix, prefix_col, comp_remaining, !comp.empty(), &line_data);
+        // Show which characters matched the token. A shortened prefix can't be lined up with it.
+        std::vector<size_t> matched;
+        if (prefix.empty() || prefix.front() != get_ellipsis_char()) {
+            matched = get_match_positions(c->representative.match, match_needle, prefix + comp);
+        }
+
+        comp_remaining -= print_max(
+            prefix,
+            [&](size_t i) -> highlight_spec_t {
+                return contains(matched, i) ? match_col : prefix_col;
+            },
+            comp_remaining, !comp.empty(), &line_data);
         comp_remaining -= print_max(
             comp,
             [&](size_t i) -> highlight_spec_t {
+                if (contains(matched, prefix.size() + i)) return match_col;
                 if (c->colors.empty()) return comp_col;  // Not a shell command.
                 if (selected) return comp_col;  // Rendered in reverse video, so avoid highlighting.
-                return i < c->colors.size() ? c->colors[i] : c->colors.back();
+                // Keep the row's background, which the command's own colors don't know about.
+                highlight_spec_t col = i < c->colors.size() ? c->colors[i] : c->colors.back();
+                col.background = bg_role;
+                return col;
             },
             comp_remaining, i + 1 < c->comp.size(), &line_data);
     }
@@ -235,15 +270,28 @@ void pager_t::completion_print(size_t cols, const size_t *width_by_column, size_
     rendering->row_start = row_start;
     rendering->row_end = row_stop;
 
-    size_t rows = divide_round_up(lst.size(), cols);
+    size_t rows = groups.empty() ? divide_round_up(lst.size(), cols) : group_row_count(cols);
 
     size_t effective_selected_idx = this->visual_selected_completion_index(rows, cols);
 
     for (size_t row = row_start; row < row_stop; row++) {
+        if (const wcstring *header = groups.empty() ? nullptr : group_header_at(row, cols)) {
+            // The header goes across all columns.
+            highlight_spec_t header_col = {highlight_role_t::pager_group,
+                                           highlight_role_t::pager_background};
+            print_max(*header, header_col, available_term_width, false,
+                      &rendering->screen_data.create_line(row - row_start));
+            continue;
+        }
         for (size_t col = 0; col < cols; col++) {
-            if (lst.size() <= col * rows + row) continue;
-
-            size_t idx = col * rows + row;
+            size_t idx;
+            if (groups.empty()) {
+                if (lst.size() <= col * rows + row) continue;
+                idx = col * rows + row;
+            } else {
+                idx = group_completion_at(row, col, cols);
+                if (idx == PAGER_SELECTION_NONE) continue;
+            }
             const comp_t *el = &lst.at(idx);
             bool is_selected = (idx == effective_selected_idx);
 
@@ -309,8 +357,10 @@ static void join_completions(comp_info_list_t *comps) {
             // We're the first with this description.
             desc_table[desc] = i + 1;
         } else {
-            // There's a prior completion with this description. Append the new ones to it.
+            // There's a prior completion with this description. Append the new ones to it, unless
+            // it is listed in another group.
             comp_t *prior_comp = &comps->at(prev_idx_plus_one - 1);
+            if (prior_comp->representative.group != new_comp.representative.group) continue;
             prior_comp->comp.insert(prior_comp->comp.end(), new_comp.comp.begin(),
                                     new_comp.comp.end());
 
@@ -411,6 +461,66 @@ void pager_t::refilter_completions() {
             this->completion_infos.push_back(info);
         }
     }
+
+    // The completions of a group come one after the other, see completions_sort_and_prioritize().
+    this->groups.clear();
+    bool have_named_group = false;
+    for (size_t i = 0; i < this->completion_infos.size(); i++) {
+        const wcstring &name = this->completion_infos.at(i).representative.group;
+        if (this->groups.empty() || this->groups.back().name != name) {
+            this->groups.push_back(group_t{name, i, 0});
+            have_named_group = have_named_group || !name.empty();
+        }
+        this->groups.back().count++;
+    }
+    if (!have_named_group) this->groups.clear();
+}
+
+size_t pager_t::group_row_count(size_t cols) const {
+    size_t result = 0;
+    for (const auto &group : groups) {
+        result += group.header_rows() + divide_round_up(group.count, cols);
+    }
+    return result;
+}
+
+size_t pager_t::group_completion_at(size_t row, size_t col, size_t cols) const {
+    for (const auto &group : groups) {
+        size_t rows = divide_round_up(group.count, cols);
+        if (row < group.header_rows() + rows) {
+            if (row < group.header_rows() || col >= cols) return PAGER_SELECTION_NONE;
+            size_t idx = col * rows + row - group.header_rows();
+            return idx < group.count ? group.start + idx : PAGER_SELECTION_NONE;
+        }
+        row -= group.header_rows() + rows;
+    }
+    return PAGER_SELECTION_NONE;
+}
+
+const wcstring *pager_t::group_header_at(size_t row, size_t cols) const {
+    for (const auto &group : groups) {
+        size_t rows = divide_round_up(group.count, cols);
+        if (row < group.header_rows() + rows) {
+            return row < group.header_rows() ? &group.name : nullptr;
+        }
+        row -= group.header_rows() + rows;
+    }
+    return nullptr;
+}
+
+void pager_t::group_cell_of(size_t idx, size_t cols, size_t *row, size_t *col) const {
+    size_t first_row = 0;
+    for (const auto &group : groups) {
+        size_t rows = divide_round_up(group.count, cols);
+        first_row += group.header_rows();
+        if (idx < group.start + group.count) {
+            *row = first_row + (idx - group.start) % rows;
+            *col = (idx - group.start) / rows;
+            return;
+        }
+        first_row += rows;
+    }
+    DIE("completion is in no group");
 }
 
 void pager_t::set_completions(const completion_list_t &raw_completions) {
@@ -434,6 +544,10 @@ void pager_t::set_prefix(const wcstring &pref, bool highlight) {
     highlight_prefix = highlight;
 }
 
+void pager_t::set_match_needle(const wcstring &needle) { match_needle = needle; }
+
+void pager_t::set_preview(std::vector<wcstring> lines) { preview_lines = std::move(lines); }
+
 void pager_t::set_term_size(termsize_t ts) {
     available_term_width = ts.width > 0 ? ts.width : 0;
     available_term_height = ts.height > 0 ? ts.height : 0;
@@ -458,6 +572,10 @@ bool pager_t::completion_try_print(size_t cols, const wcstring &prefix, const co
     size_t term_height =
         this->available_term_height - 1 -
         (search_field_shown ? 1 : 0);  // we always subtract 1 to make room for a comment row
+
+    // The preview of the selected completion gets at most half of what's left.
+    size_t preview_rows = std::min(preview_lines.size(), term_height / 2);
+    term_height -= preview_rows;
     if (!this->fully_disclosed) {
         // We disclose between half and the entirety of the terminal height,
         // but at least 4 rows.
@@ -469,7 +587,8 @@ bool pager_t::completion_try_print(size_t cols, const wcstring &prefix, const co
                      std::max(term_height / 2, static_cast<size_t>(PAGER_UNDISCLOSED_MAX_ROWS)));
     }
 
-    size_t row_count = divide_round_up(lst.size(), cols);
+    size_t row_count =
+        groups.empty() ? divide_round_up(lst.size(), cols) : group_row_count(cols);
 
     // We have more to disclose if we are not fully disclosed and there's more rows than we have in
     // our term height.
@@ -487,13 +606,15 @@ bool pager_t::completion_try_print(size_t cols, const wcstring &prefix, const co
     }
 
     // Calculate how wide the list would be.
-    for (size_t col = 0; col < cols; col++) {
-        for (size_t row = 0; row < row_count; row++) {
-            const size_t comp_idx = col * row_count + row;
-            if (comp_idx >= lst.size()) continue;
-            const comp_t &c = lst.at(comp_idx);
-            width_by_column[col] = std::max(width_by_column[col], c.preferred_width());
+    for (size_t comp_idx = 0; comp_idx < lst.size(); comp_idx++) {
+        size_t row, col;
+        if (groups.empty()) {
+            col = comp_idx / row_count;
+        } else {
+            group_cell_of(comp_idx, cols, &row, &col);
         }
+        const comp_t &c = lst.at(comp_idx);
+        width_by_column[col] = std::max(width_by_column[col], c.preferred_width());
     }
 
     bool print;
@@ -557,6 +678,13 @@ bool pager_t::completion_try_print(size_t cols, const wcstring &prefix, const co
         progress_text += extra_progress_text;
     }
 
+    for (size_t i = 0; i < preview_rows; i++) {
+        line_t &line = rendering->screen_data.add_line();
+        highlight_spec_t spec = {highlight_role_t::pager_description,
+                                 highlight_role_t::pager_background};
+        print_max(preview_lines.at(i), spec, term_width, false, &line);
+    }
+
     if (!progress_text.empty()) {
         line_t &line = rendering->screen_data.add_line();
         highlight_spec_t spec = {highlight_role_t::pager_progress,
@@ -597,6 +725,7 @@ page_rendering_t pager_t::render() const {
     rendering.term_height = this->available_term_height;
     rendering.search_field_shown = this->search_field_shown;
     rendering.search_field_line = this->search_field_line;
+    rendering.preview_lines = this->preview_lines;
 
     for (size_t cols = PAGER_MAX_COLS; cols > 0; cols--) {
         // Initially empty rendering.
@@ -618,7 +747,7 @@ page_rendering_t pager_t::render() const {
         }
 
         rendering.cols = cols;
-        rendering.rows = min_rows_required_for_cols;
+        rendering.rows = groups.empty() ? min_rows_required_for_cols : group_row_count(cols);
         rendering.selected_completion_idx =
             this->visual_selected_completion_index(rendering.rows, rendering.cols);
 
@@ -642,6 +771,7 @@ bool pager_t::rendering_needs_update(const page_rendering_t &rendering) const {
            rendering.search_field_shown != this->search_field_shown ||                      //
            rendering.search_field_line.text() != this->search_field_line.text() ||          //
            rendering.search_field_line.position() != this->search_field_line.position() ||  //
+           rendering.preview_lines != this->preview_lines ||                                //
            (rendering.remaining_to_disclose > 0 && this->fully_disclosed);
 }
 
@@ -711,6 +841,8 @@ bool pager_t::select_next_completion_in_direction(selection_motion_t direction,
         } else {
             DIE("unknown non-cardinal direction");
         }
+    } else if (!groups.empty()) {
+        new_selected_completion_idx = group_selection_in_direction(direction, rendering);
     } else {
         // Cardinal directions. We have a completion index; we wish to compute its row and column.
         size_t current_row = this->get_selected_row(rendering);
@@ -815,7 +947,7 @@ bool pager_t::select_next_completion_in_direction(selection_motion_t direction,
     }
 
     // Ensure our suggested row start is not past the selected row.
-    size_t row_containing_selection = this->get_selected_row(rendering.rows);
+    size_t row_containing_selection = this->get_selected_row(rendering.rows, rendering.cols);
     if (suggested_row_start > row_containing_selection) {
         suggested_row_start = row_containing_selection;
     }
@@ -837,6 +969,58 @@ bool pager_t::select_next_completion_in_direction(selection_motion_t direction,
     return true;
 }
 
+size_t pager_t::group_selection_in_direction(selection_motion_t direction,
+                                             const page_rendering_t &rendering) const {
+    size_t cols = rendering.cols;
+    size_t rows = group_row_count(cols);
+    size_t page_height = std::max(rendering.term_height - 1, static_cast<size_t>(1));
+    size_t current = visual_selected_completion_index(rendering.rows, cols);
+    if (current == PAGER_SELECTION_NONE) return selected_completion_idx;
+    size_t row, col;
+    group_cell_of(current, cols, &row, &col);
+
+    // The completion at this column of a row, or the last one before it if that column is short.
+    auto at_or_before = [&](size_t r, size_t c) {
+        for (size_t i = c + 1; i > 0; i--) {
+            size_t idx = group_completion_at(r, i - 1, cols);
+            if (idx != PAGER_SELECTION_NONE) return idx;
+        }
+        return PAGER_SELECTION_NONE;
+    };
+    // The next row with completions in it, above or below this one, wrapping around.
+    auto next_row = [&](size_t r, bool down) {
+        do {
+            r = down ? (r + 1) % rows : (r + rows - 1) % rows;
+        } while (group_completion_at(r, 0, cols) == PAGER_SELECTION_NONE);
+        return r;
+    };
+    // Headers are followed by completions, so skip them going down.
+    auto skip_header = [&](size_t r) {
+        return group_completion_at(r, 0, cols) == PAGER_SELECTION_NONE ? r + 1 : r;
+    };
+
+    switch (direction) {
+        case selection_motion_t::north:
+            return at_or_before(next_row(row, false), col);
+        case selection_motion_t::south:
+            return at_or_before(next_row(row, true), col);
+        case selection_motion_t::east: {
+            size_t idx = group_completion_at(row, col + 1, cols);
+            if (idx != PAGER_SELECTION_NONE) return idx;
+            return group_completion_at(next_row(row, true), 0, cols);
+        }
+        case selection_motion_t::west:
+            if (col > 0) return group_completion_at(row, col - 1, cols);
+            return at_or_before(next_row(row, false), cols - 1);
+        case selection_motion_t::page_north:
+            return at_or_before(skip_header(row > page_height ? row - page_height : 0), col);
+        case selection_motion_t::page_south:
+            return at_or_before(skip_header(std::min(row + page_height, rows - 1)), col);
+        default:
+            DIE("unknown cardinal direction");
+    }
+}
+
 size_t pager_t::visual_selected_completion_index(size_t rows, size_t cols) const {
     // No completions -> no selection.
     if (completion_infos.empty()) {
@@ -850,6 +1034,10 @@ size_t pager_t::visual_selected_completion_index(size_t rows, size_t cols) const
     if (rows == 0 || cols == 0) {
         return PAGER_SELECTION_NONE;
     }
+    if (result != PAGER_SELECTION_NONE && !groups.empty()) {
+        // Groups have no column memory, so just stay within the list.
+        return std::min(result, completion_infos.size() - 1);
+    }
     if (result != PAGER_SELECTION_NONE) {
         // If the selected completion is beyond the last selection, go left by columns until it's
         // within it. This is how we implement "column memory".
@@ -886,25 +1074,35 @@ const completion_t *pager_t::selected_completion(const page_rendering_t &renderi
 /// and our column is 1 (divide by N).
 size_t pager_t::get_selected_row(const page_rendering_t &rendering) const {
     if (rendering.rows == 0) return PAGER_SELECTION_NONE;
-
-    return rendering.selected_completion_idx == PAGER_SELECTION_NONE
-               ? PAGER_SELECTION_NONE
-               : rendering.selected_completion_idx % rendering.rows;
+    if (rendering.selected_completion_idx == PAGER_SELECTION_NONE) return PAGER_SELECTION_NONE;
+    if (!groups.empty()) {
+        size_t row, col;
+        group_cell_of(rendering.selected_completion_idx, rendering.cols, &row, &col);
+        return row;
+    }
+    return rendering.selected_completion_idx % rendering.rows;
 }
 
-size_t pager_t::get_selected_row(size_t rows) const {
+size_t pager_t::get_selected_row(size_t rows, size_t cols) const {
     if (rows == 0) return PAGER_SELECTION_NONE;
-
-    return selected_completion_idx == PAGER_SELECTION_NONE ? PAGER_SELECTION_NONE
-                                                           : selected_completion_idx % rows;
+    if (selected_completion_idx == PAGER_SELECTION_NONE) return PAGER_SELECTION_NONE;
+    if (!groups.empty()) {
+        size_t row, col;
+        group_cell_of(visual_selected_completion_index(rows, cols), cols, &row, &col);
+        return row;
+    }
+    return selected_completion_idx % rows;
 }
 
 size_t pager_t::get_selected_column(const page_rendering_t &rendering) const {
     if (rendering.rows == 0) return PAGER_SELECTION_NONE;
-
-    return rendering.selected_completion_idx == PAGER_SELECTION_NONE
-               ? PAGER_SELECTION_NONE
-               : rendering.selected_completion_idx / rendering.rows;
+    if (rendering.selected_completion_idx == PAGER_SELECTION_NONE) return PAGER_SELECTION_NONE;
+    if (!groups.empty()) {
+        size_t row, col;
+        group_cell_of(rendering.selected_completion_idx, rendering.cols, &row, &col);
+        return col;
+    }
+    return rendering.selected_completion_idx / rendering.rows;
 }
 
 void pager_t::clear() {
@@ -912,6 +1110,9 @@ void pager_t::clear() {
     completion_infos.clear();
     prefix.clear();
     highlight_prefix = false;
+    match_needle.clear();
+    preview_lines.clear();
+    groups.clear();
     selected_completion_idx = PAGER_SELECTION_NONE;
     fully_disclosed = false;
     search_field_shown = false;
@@ -34,6 +34,8 @@ class page_rendering_t {
     bool search_field_shown{false};
     editable_line_t search_field_line{};
 
+    std::vector<wcstring> preview_lines{};
+
     // Returns a rendering with invalid data, useful to indicate "no rendering".
     page_rendering_t();
 };
@@ -119,12 +121,28 @@ class pager_t {
     // The unfiltered list. Note there's a lot of duplication here.
     comp_info_list_t unfiltered_completion_infos;
 
+    /// A run of filtered completions in the same group, listed below a header with its name.
+    struct group_t {
+        wcstring name;
+        size_t start;
+        size_t count;
+
+        size_t header_rows() const { return name.empty() ? 0 : 1; }
+    };
+
+    // The groups of the filtered completions, or empty if none of them has a group.
+    std::vector<group_t> groups;
+
     // This tracks if the completion list has been changed since we last rendered. If yes,
     // then we definitely need to re-render.
     bool have_unrendered_completions = false;
 
     wcstring prefix;
     bool highlight_prefix = false;
+    // The token that subsequence matches were made from, to highlight the matched characters.
+    wcstring match_needle;
+    // The preview of the selected completion, shown below the completions.
+    std::vector<wcstring> preview_lines;
 
     bool completion_try_print(size_t cols, const wcstring &prefix, const comp_info_list_t &lst,
                               page_rendering_t *rendering, size_t suggested_start_row) const;
@@ -134,6 +152,16 @@ class pager_t {
 
     bool completion_info_passes_filter(const comp_t &info) const;
 
+    // When there are groups, each one is laid out in columns of its own, below its header.
+    // These give the number of rows with that many columns, what is at a row and column, and where
+    // a completion is.
+    size_t group_row_count(size_t cols) const;
+    size_t group_completion_at(size_t row, size_t col, size_t cols) const;
+    const wcstring *group_header_at(size_t row, size_t cols) const;
+    void group_cell_of(size_t idx, size_t cols, size_t *row, size_t *col) const;
+    size_t group_selection_in_direction(selection_motion_t direction,
+                                        const page_rendering_t &rendering) const;
+
     void completion_print(size_t cols, const size_t *width_by_column, size_t row_start,
                           size_t row_stop, const wcstring &prefix, const comp_info_list_t &lst,
                           page_rendering_t *rendering) const;
@@ -154,6 +182,12 @@ class pager_t {
     // Sets the prefix.
     void set_prefix(const wcstring &pref, bool highlight = true);
 
+    // Sets the token that the completions were matched against.
+    void set_match_needle(const wcstring &needle);
+
+    // Sets the lines that preview the selected completion.
+    void set_preview(std::vector<wcstring> lines);
+
     // Sets the terminal size.
     void set_term_size(termsize_t ts);
 
@@ -168,8 +202,8 @@ class pager_t {
     // Indicates the row and column for the given rendering. Returns -1 if no selection.
     size_t get_selected_row(const page_rendering_t &rendering) const;
     size_t get_selected_column(const page_rendering_t &rendering) const;
-    // Indicates the row assuming we render this many rows. Returns -1 if no selection.
-    size_t get_selected_row(size_t rows) const;
+    // Indicates the row assuming we render this many rows and columns. Returns -1 if no selection.
+    size_t get_selected_row(size_t rows, size_t cols) const;
 
     // Produces a rendering of the completions, at the given term size.
     page_rendering_t render() const;
@@ -406,7 +406,9 @@ end_execution_reason_t parse_execution_context_t::run_function_statement(
     parser->libdata().status_count++;
     parser->set_last_statuses(statuses_t::just(err_code));
 
-    const wcstring &errtext = errs.contents();
+    // The error is shown above the offending line, which brings its own newline.
+    wcstring errtext = errs.contents();
+    if (!errtext.empty() && errtext.back() == L'\n') errtext.pop_back();
     if (!errtext.empty()) {
         return this->report_error(err_code, *header.ptr(), L"%ls", errtext.c_str());
     }
@@ -1383,6 +1385,7 @@ end_execution_reason_t parse_execution_context_t::run_1_job(const ast::job_pipel
 
     job_t::properties_t props{};
     props.initial_background = job_node.has_bg();
+    props.queue_background = job_node.has_bg() && get_source(*job_node.bg().ptr()) == L"&--queue";
     props.skip_notification =
         ld.is_subshell || parser->is_block() || ld.is_event || !parser->is_interactive();
     props.from_event_handler = ld.is_event;
@@ -26,6 +26,7 @@
 #include "flog.h"
 #include "function.h"
 #include "job_group.rs.h"
+#include "json.rs.h"
 #include "parse_constants.h"
 #include "parse_execution.h"
 #include "proc.h"
@@ -86,6 +87,33 @@ int parser_t::set_var_and_fire(const wcstring &key, env_mode_flags_t mode, wcstr
     return set_var_and_fire(key, mode, std::move(vals));
 }
 
+void parser_t::own_fd_in_scope(autoclose_fd_t fd) {
+    auto owned = std::make_shared<autoclose_fd_t>(std::move(fd));
+    for (block_t &b : block_list) {
+        if (b.wants_pop_env) {
+            b.owned_fds.push_back(std::move(owned));
+            return;
+        }
+    }
+    libdata().owned_fds.push_back(std::move(owned));
+}
+
+bool parser_t::close_owned_fd(int fd) {
+    auto try_close = [=](std::vector<std::shared_ptr<autoclose_fd_t>> &fds) {
+        auto iter = std::find_if(fds.begin(), fds.end(),
+                                 [=](const std::shared_ptr<autoclose_fd_t> &f) {
+                                     return f->fd() == fd;
+                                 });
+        if (iter == fds.end()) return false;
+        fds.erase(iter);
+        return true;
+    };
+    for (block_t &b : block_list) {
+        if (try_close(b.owned_fds)) return true;
+    }
+    return try_close(libdata().owned_fds);
+}
+
 void parser_t::sync_uvars_and_fire(bool always) {
     if (this->syncs_uvars_) {
         auto evts = this->vars().universal_sync(always);
@@ -125,20 +153,23 @@ block_t *parser_t::block_at_index(size_t idx) {
     return idx < block_list.size() ? &block_list[idx] : nullptr;
 }
 
-/// Print profiling information to the specified stream.
-static void print_profile(const std::deque<profile_item_t> &items, FILE *out) {
-    for (size_t idx = 0; idx < items.size(); idx++) {
+/// \return the profile items from \p start on as a table, with the time spent in each command
+/// itself and including nested commands.
+static wcstring profile_text(const std::deque<profile_item_t> &items, size_t start) {
+    using type_t = profile_item_t::type_t;
+    wcstring out = _(L"Time\tSum\tCommand\n");
+    for (size_t idx = start; idx < items.size(); idx++) {
         const profile_item_t &item = items.at(idx);
-        if (item.skipped || item.cmd.empty()) continue;
+        if (item.skipped || item.cmd.empty() || item.type != type_t::job) continue;
 
         long long total_time = item.duration;
 
         // Compute the self time as the total time, minus the total time consumed by subsequent
-        // items exactly one eval level deeper.
+        // items exactly one eval level deeper.
         long long self_time = item.duration;
         for (size_t i = idx + 1; i < items.size(); i++) {
             const profile_item_t &nested_item = items.at(i);
-            if (nested_item.skipped) continue;
+            if (nested_item.skipped || nested_item.type != type_t::job) continue;
 
             // If the eval level is not larger, then we have exhausted nested items.
             if (nested_item.level <= item.level) break;
@@ -147,44 +178,167 @@ static void print_profile(const std::deque<profile_item_t> &items, FILE *out) {
             if (nested_item.level == item.level + 1) self_time -= nested_item.duration;
         }
 
-        if (std::fwprintf(out, L"%lld\t%lld\t", self_time, total_time) < 0) {
-            wperror(L"fwprintf");
-            return;
-        }
+        append_format(out, L"%lld\t%lld\t", self_time, total_time);
+        out.append(item.level, L'-');
+        append_format(out, L"> %ls\n", item.cmd.c_str());
+    }
+    return out;
+}
 
-        for (size_t i = 0; i < item.level; i++) {
-            if (std::fwprintf(out, L"-") < 0) {
-                wperror(L"fwprintf");
-                return;
-            }
-        }
+namespace {
+/// A profile item with the items which ran inside of it.
+struct profile_node_t {
+    const profile_item_t *item;
+    std::vector<size_t> children;
+    long long self_time;
+};
+}  // namespace
 
-        if (std::fwprintf(out, L"> %ls\n", item.cmd.c_str()) < 0) {
-            wperror(L"fwprintf");
-            return;
+/// Arrange the profile items from \p start on into trees, returning the nodes. The indexes of the
+/// roots are put in \p roots.
+static std::vector<profile_node_t> profile_tree(const std::deque<profile_item_t> &items,
+                                                size_t start, std::vector<size_t> *roots) {
+    // Items come in the order they started, and an item is nested in the last one before it
+    // which is shallower. Functions and command substitutions are halfway between the level of
+    // the job which ran them and the level of what they ran.
+    auto depth = [](const profile_item_t &item) {
+        return item.level * 2 + (item.type != profile_item_t::type_t::job);
+    };
+    std::vector<profile_node_t> nodes;
+    std::vector<size_t> stack;
+    for (size_t idx = start; idx < items.size(); idx++) {
+        const profile_item_t &item = items.at(idx);
+        if (item.skipped || item.cmd.empty()) continue;
+        while (!stack.empty() && depth(*nodes.at(stack.back()).item) >= depth(item)) {
+            stack.pop_back();
         }
-    }
+        size_t node = nodes.size();
+        nodes.push_back(profile_node_t{&item, {}, item.duration});
+        if (stack.empty()) {
+            roots->push_back(node);
+        } else {
+            profile_node_t &parent = nodes.at(stack.back());
+            parent.children.push_back(node);
+            parent.self_time -= item.duration;
+        }
+        stack.push_back(node);
+    }
+    return nodes;
+}
+
+static void profile_json(const std::vector<profile_node_t> &nodes,
+                         const std::vector<size_t> &indexes, JsonWriter &w) {
+    w.begin_array();
+    for (size_t idx : indexes) {
+        const profile_node_t &node = nodes.at(idx);
+        w.begin_object();
+        w.key(L"type");
+        switch (node.item->type) {
+            case profile_item_t::type_t::job:
+                w.string(L"job");
+                break;
+            case profile_item_t::type_t::function:
+                w.string(L"function");
+                break;
+            case profile_item_t::type_t::command_substitution:
+                w.string(L"command-substitution");
+                break;
+        }
+        w.key(L"command");
+        w.string(node.item->cmd);
+        w.key(L"self_time");
+        w.integer(std::max(node.self_time, 0LL));
+        w.key(L"total_time");
+        w.integer(node.item->duration);
+        w.key(L"children");
+        profile_json(nodes, node.children, w);
+        w.end_object();
+    }
+    w.end_array();
+}
+
+/// Append a line for each of \p indexes and what ran inside them to \p out, with the names of
+/// the enclosing items in \p stack.
+static void profile_folded(const std::vector<profile_node_t> &nodes,
+                           const std::vector<size_t> &indexes, wcstring *stack, wcstring *out) {
+    for (size_t idx : indexes) {
+        const profile_node_t &node = nodes.at(idx);
+        size_t stack_len = stack->size();
+        if (!stack->empty()) stack->push_back(L';');
+        wcstring frame = node.item->cmd;
+        if (node.item->type == profile_item_t::type_t::command_substitution) {
+            frame = L"(" + frame + L")";
+        }
+        // Semicolons separate frames, and lines separate stacks.
+        std::replace(frame.begin(), frame.end(), L';', L',');
+        std::replace(frame.begin(), frame.end(), L'\n', L' ');
+        stack->append(frame);
+        append_format(*out, L"%ls %lld\n", stack->c_str(), std::max(node.self_time, 0LL));
+        profile_folded(nodes, node.children, stack, out);
+        stack->resize(stack_len);
+    }
+}
+
+maybe_t<profile_format_t> profile_format_from_string(const wcstring &name) {
+    if (name == L"text") return profile_format_t::text;
+    if (name == L"json") return profile_format_t::json;
+    if (name == L"folded") return profile_format_t::folded;
+    return none();
 }
 
-void parser_t::clear_profiling() { profile_items.clear(); }
-
-void parser_t::emit_profiling(const char *path) const {
+void write_profile(const wcstring &profile, const char *path) {
     // Save profiling information. OK to not use CLO_EXEC here because this is called while fish is
-    // exiting (and hence will not fork).
+    // exiting (and hence will not fork), or by a builtin, which doesn't fork either.
     FILE *f = fopen(path, "w");
     if (!f) {
         FLOGF(warning, _(L"Could not write profiling information to file '%s'"), path);
-    } else {
-        if (std::fwprintf(f, _(L"Time\tSum\tCommand\n"), profile_items.size()) < 0) {
-            wperror(L"fwprintf");
-        } else {
-            print_profile(profile_items, f);
-        }
+        return;
+    }
+    if (std::fwprintf(f, L"%ls", profile.c_str()) < 0) {
+        wperror(L"fwprintf");
+    }
+    if (fclose(f)) {
+        wperror(L"fclose");
+    }
+}
 
-        if (fclose(f)) {
-            wperror(L"fclose");
-        }
+void parser_t::clear_profiling() { profile_items.clear(); }
+
+wcstring parser_t::format_profiling(profile_format_t format, size_t start) const {
+    start = std::min(start, profile_items.size());
+    if (format == profile_format_t::text) return profile_text(profile_items, start);
+
+    std::vector<size_t> roots;
+    std::vector<profile_node_t> nodes = profile_tree(profile_items, start, &roots);
+    if (format == profile_format_t::json) {
+        auto w = new_json_writer();
+        profile_json(nodes, roots, *w);
+        return *w->contents() + L"\n";
     }
+    wcstring stack, out;
+    profile_folded(nodes, roots, &stack, &out);
+    return out;
+}
+
+void parser_t::emit_profiling(const char *path, profile_format_t format) const {
+    write_profile(format_profiling(format), path);
+}
+
+bool parser_t::start_profile_session() {
+    if (profile_session_start) return false;
+    profile_session_start = profile_items.size();
+    profile_session_was_active = g_profiling_active;
+    g_profiling_active = true;
+    return true;
+}
+
+maybe_t<wcstring> parser_t::stop_profile_session(profile_format_t format) {
+    if (!profile_session_start) return none();
+    // The items stay around, because the jobs which are still running refer to them.
+    wcstring profile = format_profiling(format, *profile_session_start);
+    profile_session_start.reset();
+    g_profiling_active = profile_session_was_active;
+    return profile;
 }
 
 completion_list_t parser_t::expand_argument_list(const wcstring &arg_list_src,
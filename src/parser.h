@@ -83,6 +83,10 @@ class block_t {
     /// Line number where this block was created.
     int src_lineno{0};
 
+    /// Fds opened by `exec --open` in this block's variable scope. These are closed when the block
+    /// is popped.
+    std::vector<std::shared_ptr<autoclose_fd_t>> owned_fds{};
+
    private:
     /// Type of block.
     const block_type_t block_type;
@@ -120,6 +124,11 @@ class block_t {
 struct profile_item_t {
     using microseconds_t = long long;
 
+    /// What was timed. Function calls and command substitutions have the level of the job which
+    /// ran them, and what they ran is one level deeper.
+    enum class type_t { job, function, command_substitution };
+    type_t type{type_t::job};
+
     /// Time spent executing the command, including nested blocks.
     microseconds_t duration{};
 
@@ -130,13 +139,29 @@ struct profile_item_t {
     /// If the execution of this command was skipped.
     bool skipped{};
 
-    /// The command string.
+    /// The command string, or the name of the function.
     wcstring cmd{};
 
     /// \return the current time as a microsecond timestamp since the epoch.
     static microseconds_t now() { return get_time(); }
 };
 
+/// The formats profiling information can be written in.
+enum class profile_format_t {
+    /// A table of the time for each command, indented by level.
+    text,
+    /// A tree of JSON objects.
+    json,
+    /// One line per stack with its time, for flame graph tools.
+    folded,
+};
+
+/// \return the profile format called \p name, or none if there is none.
+maybe_t<profile_format_t> profile_format_from_string(const wcstring &name);
+
+/// Write \p profile to the file at \p path, warning if that fails.
+void write_profile(const wcstring &profile, const char *path);
+
 class parse_execution_context_t;
 
 /// Plain-Old-Data components of `struct library_data_t` that can be shared over FFI
@@ -216,6 +241,9 @@ struct library_data_t : public library_data_pod_t {
     /// This is never null and never invalid.
     std::shared_ptr<const autoclose_fd_t> cwd_fd{};
 
+    /// Fds opened by `exec --open` outside of any block with its own variable scope.
+    std::vector<std::shared_ptr<autoclose_fd_t>> owned_fds{};
+
     /// Status variables set by the main thread as jobs are parsed and read by various consumers.
     struct {
         /// Used to get the head of the current job (not the current command, at least for now)
@@ -295,6 +323,12 @@ class parser_t : public std::enable_shared_from_this<parser_t> {
     /// to profile_items). deque does not move items on reallocation.
     std::deque<profile_item_t> profile_items;
 
+    /// The index of the first profile item of the running `status profile` session, if any.
+    maybe_t<size_t> profile_session_start;
+
+    /// Whether profiling was active before the `status profile` session started.
+    bool profile_session_was_active{false};
+
     /// Adds a job to the beginning of the job list.
     void job_add(std::shared_ptr<job_t> job);
 
@@ -422,6 +456,16 @@ class parser_t : public std::enable_shared_from_this<parser_t> {
     int set_var_and_fire(const wcstring &key, env_mode_flags_t mode, wcstring val);
     int set_var_and_fire(const wcstring &key, env_mode_flags_t mode, std::vector<wcstring> vals);
 
+    /// Take ownership of \p fd in the innermost variable scope, which is where a local variable
+    /// would be set. It is closed when that scope's block is popped. Blocks which do not push a
+    /// scope (like the top block of an interactive command) defer to the parser, so those fds stay
+    /// open until closed explicitly.
+    void own_fd_in_scope(autoclose_fd_t fd);
+
+    /// Close the fd \p fd if it is owned by any scope.
+    /// \return true if it was found.
+    bool close_owned_fd(int fd);
+
     /// Update any universal variables and send event handlers.
     /// If \p always is set, then do it even if we have no pending changes (that is, look for
     /// changes from other fish instances); otherwise only sync if this instance has changed uvars.
@@ -459,8 +503,18 @@ class parser_t : public std::enable_shared_from_this<parser_t> {
     /// Remove the profiling items.
     void clear_profiling();
 
+    /// \return the profiling data from the item at \p start on, in \p format.
+    wcstring format_profiling(profile_format_t format, size_t start = 0) const;
+
     /// Output profiling data to the given filename.
-    void emit_profiling(const char *path) const;
+    void emit_profiling(const char *path, profile_format_t format = profile_format_t::text) const;
+
+    /// Start profiling for `status profile start`. \return false if that is already running.
+    bool start_profile_session();
+
+    /// Stop profiling for `status profile stop`, restoring whether profiling is active.
+    /// \return the profiling data since the session started, or none if none was running.
+    maybe_t<wcstring> stop_profile_session(profile_format_t format);
 
     void get_backtrace(const wcstring &src, const parse_error_list_t &errors,
                        wcstring &output) const;
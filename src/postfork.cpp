@@ -5,9 +5,14 @@
 #include <fcntl.h>
 #include <paths.h>
 #include <signal.h>
+#include <sys/resource.h>
+#ifdef __linux__
+#include <sys/syscall.h>
+#endif
 #include <time.h>
 #include <unistd.h>
 
+#include <algorithm>
 #include <cstring>
 #ifdef HAVE_SPAWN_H
 #include <spawn.h>
@@ -186,6 +191,25 @@ int child_setup_process(pid_t claim_tty_from, const job_t &job, bool is_forked,
     return 0;
 }
 
+void child_apply_priority(const launch_priority_t &priority) {
+    // Note we are called in a forked child.
+    if (priority.nice_increment) {
+        errno = 0;
+        int current = getpriority(PRIO_PROCESS, 0);
+        if (errno == 0 && setpriority(PRIO_PROCESS, 0, current + *priority.nice_increment) < 0) {
+            FLOGF_SAFE(warning, "Could not change the niceness of the process");
+        }
+    }
+    if (priority.ioprio) {
+#if defined(__linux__) && defined(SYS_ioprio_set)
+        // 1 is IOPRIO_WHO_PROCESS; the kernel headers defining it are not always installed.
+        if (syscall(SYS_ioprio_set, 1, 0, *priority.ioprio) < 0) {
+            FLOGF_SAFE(warning, "Could not change the I/O priority of the process");
+        }
+#endif
+    }
+}
+
 /// This function is a wrapper around fork. If the fork calls fails with EAGAIN, it is retried
 /// FORK_LAPS times, with a very slight delay between each lap. If fork fails even then, the process
 /// will exit with an error message.
@@ -264,7 +288,7 @@ posix_spawner_t::~posix_spawner_t() {
     }
 }
 
-posix_spawner_t::posix_spawner_t(const job_t *j, const dup2_list_t &dup2s) {
+posix_spawner_t::posix_spawner_t(const job_t *j, const dup2_list_t &dup2s, int cwd_fd) {
     // Initialize our fields. This may fail.
     {
         posix_spawnattr_t attr;
@@ -323,9 +347,35 @@ posix_spawner_t::posix_spawner_t(const job_t *j, const dup2_list_t &dup2s) {
         if (check_fail(posix_spawnattr_setsigmask(attr(), &sigmask))) return;
     }
 
+#ifdef HAVE_POSIX_SPAWN_FILE_ACTIONS_ADDFCHDIR_NP
+    // Change the directory first, before a dup2 can replace the fd.
+    if (cwd_fd >= 0) {
+        if (check_fail(posix_spawn_file_actions_addfchdir_np(actions(), cwd_fd))) return;
+    }
+#else
+    UNUSED(cwd_fd);
+#endif
+
+    // Self-fd redirections (src == target) arise when e.g. `cmd 6< /dev/null` opens fd 6 itself.
+    // A dup2 onto the same fd is a no-op which would leave the CLO_EXEC bit set, so route these
+    // through a scratch fd above every fd the actions touch, which the child never sees.
+    int max_fd = 2;
+    for (const auto &act : dup2s.get_actions()) {
+        max_fd = std::max({max_fd, act.src, act.target});
+    }
+
     // Apply our dup2s.
     for (const auto &act : dup2s.get_actions()) {
-        if (act.target < 0) {
+        if (act.target >= 0 && act.src == act.target) {
+            autoclose_fd_t scratch{fcntl(act.src, F_DUPFD_CLOEXEC, max_fd + 1)};
+            if (!scratch.valid()) {
+                check_fail(errno);
+                return;
+            }
+            if (check_fail(posix_spawn_file_actions_adddup2(actions(), scratch.fd(), act.target)))
+                return;
+            scratch_fds_.push_back(std::move(scratch));
+        } else if (act.target < 0) {
             if (check_fail(posix_spawn_file_actions_addclose(actions(), act.src))) return;
         } else {
             if (check_fail(posix_spawn_file_actions_adddup2(actions(), act.src, act.target)))
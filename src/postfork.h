@@ -12,7 +12,10 @@
 #define FISH_USE_POSIX_SPAWN HAVE_SPAWN_H
 #endif
 
+#include <vector>
+
 #include "common.h"
+#include "fds.h"
 #include "maybe.h"
 
 struct Dup2List;
@@ -42,6 +45,22 @@ void report_setpgid_error(int err, bool is_parent, pid_t desired_pgid, const job
 int child_setup_process(pid_t claim_tty_from, const job_t &job, bool is_forked,
                         const dup2_list_t &dup2s);
 
+/// Scheduling settings for an external command, applied between fork and exec.
+struct launch_priority_t {
+    /// How much to add to the niceness of the process.
+    maybe_t<int> nice_increment{};
+
+    /// The I/O priority as passed to ioprio_set, combining the class and the level within it.
+    /// This is only supported on Linux.
+    maybe_t<int> ioprio{};
+
+    bool empty() const { return !nice_increment && !ioprio; }
+};
+
+/// Apply \p priority to the current process, which is a forked child. Failures are reported but
+/// not fatal, like with the nice command.
+void child_apply_priority(const launch_priority_t &priority);
+
 /// Call fork(), retrying on failure a few times.
 pid_t execute_fork();
 
@@ -53,9 +72,10 @@ void safe_report_exec_error(int err, const char *actual_cmd, const char *const *
 /// A RAII type which wraps up posix_spawn's data structures.
 class posix_spawner_t : noncopyable_t, nonmovable_t {
    public:
-    /// Attempt to construct from a job and dup2 list.
+    /// Attempt to construct from a job and dup2 list. If \p cwd_fd is not -1, the process starts in
+    /// that directory, where posix_spawn supports it.
     /// The caller must check the error function, as this may fail.
-    posix_spawner_t(const job_t *j, const dup2_list_t &dup2s);
+    posix_spawner_t(const job_t *j, const dup2_list_t &dup2s, int cwd_fd);
 
     /// \return the last error code, or 0 if there is no error.
     int get_error() const { return error_; }
@@ -76,6 +96,10 @@ class posix_spawner_t : noncopyable_t, nonmovable_t {
     int error_{0};
     maybe_t<posix_spawnattr_t> attr_{};
     maybe_t<posix_spawn_file_actions_t> actions_{};
+
+    /// Scratch fds used to route self-fd redirections. These are CLO_EXEC and must stay open
+    /// until the spawn.
+    std::vector<autoclose_fd_t> scratch_fds_{};
 };
 
 #endif
@@ -13,16 +13,11 @@
 #include <atomic>
 #include <cwchar>
 
-#if HAVE_TERM_H
-#include <curses.h>  // IWYU pragma: keep
-#include <term.h>
-#elif HAVE_NCURSES_TERM_H
-#include <ncurses/term.h>
-#endif
 #include <termios.h>
 #ifdef HAVE_SIGINFO_H
 #include <siginfo.h>
 #endif
+#include <sys/resource.h>
 #include <sys/time.h>  // IWYU pragma: keep
 #include <sys/wait.h>
 
@@ -37,15 +32,19 @@
 #include "env.h"
 #include "event.h"
 #include "fallback.h"  // IWYU pragma: keep
+#include "fd_monitor.rs.h"
 #include "fds.h"
+#include "ffi.h"
 #include "flog.h"
 #include "global_safety.h"
 #include "io.h"
 #include "job_group.rs.h"
+#include "output.rs.h"
 #include "parser.h"
 #include "proc.h"
 #include "reader.h"
 #include "signals.h"
+#include "wcstringutil.h"
 #include "wutil.h"  // IWYU pragma: keep
 
 /// The signals that signify crashes to us.
@@ -163,9 +162,13 @@ maybe_t<statuses_t> job_t::get_statuses() const {
         has_status = true;
         st.pipestatus.push_back(status.status_value());
     }
+    for (const auto &p : processes) {
+        st.pipestatus_usage.push_back(p->usage);
+    }
     if (!has_status) {
         return none();
     }
+    if (timeout && timeout->expired) laststatus = STATUS_TIMED_OUT;
     st.status = flags().negate ? !laststatus : laststatus;
     return st;
 }
@@ -218,16 +221,32 @@ void print_exit_warning_for_jobs(const job_list_t &jobs) {
     reader_schedule_prompt_repaint();
 }
 
+void print_jobs_left_on_exit(const job_list_t &jobs, bool terminating) {
+    if (terminating) {
+        fputws(_(L"Terminating jobs:\n"), stdout);
+    } else {
+        fputws(_(L"Leaving jobs running:\n"), stdout);
+    }
+    fputws(_(L"\n   PID  Command\n"), stdout);
+    for (const auto &j : jobs) {
+        fwprintf(stdout, L"%6d  %ls\n", j->processes[0]->pid, j->command_wcstr());
+    }
+    fflush(stdout);
+}
+
 /// Set the status of \p proc to \p status.
 static void handle_child_status(const shared_ptr<job_t> &job, process_t *proc,
-                                proc_status_t status) {
+                                proc_status_t status, const struct rusage *usage = nullptr) {
     proc->status = status;
     if (status.stopped()) {
         proc->stopped = true;
+        topic_monitor_principal().post(topic_t::job_stopcont);
     } else if (status.continued()) {
         proc->stopped = false;
+        topic_monitor_principal().post(topic_t::job_stopcont);
     } else {
         proc->completed = true;
+        proc->record_usage(usage);
     }
 
     // If the child was killed by SIGINT or SIGQUIT, then cancel the entire group if interactive. If
@@ -263,6 +282,30 @@ void process_t::check_generations_before_launch() {
     gens_ = topic_monitor_principal().current_generations();
 }
 
+void process_t::mark_launched() { launch_time = std::chrono::steady_clock::now(); }
+
+void process_t::record_usage(const struct rusage *rusage) {
+    if (launch_time) {
+        auto elapsed = std::chrono::steady_clock::now() - *launch_time;
+        usage.wall_usec = std::chrono::duration_cast<std::chrono::microseconds>(elapsed).count();
+    }
+    if (rusage) {
+        auto usec = [](const struct timeval &tv) {
+            return static_cast<int64_t>(tv.tv_sec) * 1000000 + tv.tv_usec;
+        };
+        usage.user_usec = usec(rusage->ru_utime);
+        usage.sys_usec = usec(rusage->ru_stime);
+#ifdef __APPLE__
+        // macOS reports ru_maxrss in bytes, everybody else in kilobytes.
+        usage.max_rss_kb = rusage->ru_maxrss / 1024;
+#else
+        usage.max_rss_kb = rusage->ru_maxrss;
+#endif
+        usage.in_blocks = rusage->ru_inblock;
+        usage.out_blocks = rusage->ru_oublock;
+    }
+}
+
 void process_t::mark_aborted_before_launch() {
     this->completed = true;
     // The status may have already been set to e.g. STATUS_NOT_EXECUTABLE.
@@ -319,7 +362,13 @@ job_t::job_t(const properties_t &props, wcstring command_str)
       command_str(std::move(command_str)),
       internal_job_id(next_internal_job_id()) {}
 
-job_t::~job_t() = default;
+job_t::~job_t() {
+    if (timeout) {
+        timeout->finished = true;
+        // Poke the timer so it is cleaned up now, rather than when it expires.
+        if (uint64_t timer_id = timeout->timer_id) fd_monitor().poke_item(timer_id);
+    }
+}
 
 bool job_t::wants_job_control() const { return group->wants_job_control(); }
 
@@ -370,6 +419,24 @@ static void reap_disowned_pids() {
                          disowned_pids->end());
 }
 
+/// Remove the reaped \p pid from the pids which the timeout of \p j signals, as it may be reused
+/// now. Once every external process is reaped, the timeout is finished. The caller holds the lock
+/// of the timeout.
+static void forget_reaped_pid(const job_t &j, pid_t pid) {
+    job_timeout_t &timeout = *j.timeout;
+    bool all_reaped =
+        std::all_of(j.processes.begin(), j.processes.end(),
+                    [](const process_ptr_t &p) { return p->pid <= 0 || p->completed; });
+    if (all_reaped) {
+        // This includes a process group id, which may be reused now too.
+        timeout.pids.clear();
+        timeout.finished = true;
+    } else {
+        timeout.pids.erase(std::remove(timeout.pids.begin(), timeout.pids.end(), pid),
+                           timeout.pids.end());
+    }
+}
+
 /// See if any reapable processes have exited, and mark them accordingly.
 /// \param block_ok if no reapable processes have exited, block until one is (or until we receive a
 /// signal).
@@ -420,15 +487,27 @@ static void process_mark_finished_children(parser_t &parser, bool block_ok) {
             if (proc->gens_.sigchld == reapgens.sigchld) continue;
             proc->gens_.sigchld = reapgens.sigchld;
 
-            // Ok, we are reapable. Run waitpid()!
+            // Ok, we are reapable. Run wait4(), which is waitpid() that also reports rusage.
+            // If the job has a timeout, keep its timer from signalling until it forgot the pid.
+            std::unique_lock<std::mutex> timeout_lock;
+            if (j->timeout) timeout_lock = std::unique_lock<std::mutex>(j->timeout->lock);
             int statusv = -1;
-            pid_t pid = waitpid(proc->pid, &statusv, WNOHANG | WUNTRACED | WCONTINUED);
+            struct rusage usage {};
+            pid_t pid = wait4(proc->pid, &statusv, WNOHANG | WUNTRACED | WCONTINUED, &usage);
             assert((pid <= 0 || pid == proc->pid) && "Unexpcted waitpid() return");
             if (pid <= 0) continue;
 
             // The process has stopped or exited! Update its status.
             proc_status_t status = proc_status_t::from_waitpid(statusv);
-            handle_child_status(j, proc.get(), status);
+            handle_child_status(j, proc.get(), status, &usage);
+            if (j->timeout && proc->completed) {
+                forget_reaped_pid(*j, pid);
+                timeout_lock.unlock();
+                if (j->timeout->finished) {
+                    // Poke the timer so it is cleaned up now, rather than when it expires.
+                    if (uint64_t timer_id = j->timeout->timer_id) fd_monitor().poke_item(timer_id);
+                }
+            }
             if (status.stopped()) {
                 j->group->set_is_foreground(false);
             }
@@ -483,7 +562,13 @@ static void generate_process_exit_events(const job_ref_t &j,
         for (const auto &p : j->processes) {
             if (p->pid > 0 && p->completed && !p->posted_proc_exit) {
                 p->posted_proc_exit = true;
-                out_evts->push_back(new_event_process_exit(p->pid, p->status.status_value()));
+                auto evt = new_event_process_exit(p->pid, p->status.status_value());
+                std::vector<int32_t> pids{p->pid};
+                int sig = p->status.signal_exited() ? p->status.signal_code() : 0;
+                evt->set_exit_payload(join_strings(p->argv(), L' '), pids,
+                                      p->status.status_value(), sig,
+                                      flatten_proc_usage(p->usage));
+                out_evts->push_back(std::move(evt));
             }
         }
     }
@@ -497,7 +582,15 @@ static void generate_job_exit_events(const job_ref_t &j, std::vector<rust::Box<E
         if (j->posts_job_exit_events()) {
             auto last_pid = j->get_last_pid();
             if (last_pid.has_value()) {
-                out_evts->push_back(new_event_job_exit(*last_pid, j->internal_job_id));
+                auto evt = new_event_job_exit(*last_pid, j->internal_job_id);
+                std::vector<int32_t> pids;
+                for (const auto &p : j->processes) {
+                    if (p->pid > 0) pids.push_back(p->pid);
+                }
+                statuses_t statuses = j->get_statuses().value_or(statuses_t{});
+                evt->set_exit_payload(j->command(), pids, statuses.status, statuses.kill_signal,
+                                      flatten_proc_usage(statuses.total_usage()));
+                out_evts->push_back(std::move(evt));
             }
         }
     }
@@ -671,7 +764,7 @@ static bool process_clean_after_marking(parser_t &parser, bool allow_interactive
 
     // This may be invoked in an exit handler, after the TERM has been torn down
     // Don't try to print in that case (#3222)
-    const bool interactive = allow_interactive && cur_term != nullptr;
+    const bool interactive = allow_interactive && term_has_entry();
 
     // Remove all disowned jobs.
     remove_disowned_jobs(parser.jobs());
@@ -966,6 +1059,7 @@ bool job_t::resume() {
     for (auto &p : this->processes) {
         p->stopped = false;
     }
+    topic_monitor_principal().post(topic_t::job_stopcont);
     return true;
 }
 
@@ -997,6 +1091,95 @@ void proc_wait_any(parser_t &parser) {
     process_clean_after_marking(parser, parser.libdata().is_interactive);
 }
 
+/// The exported variable through which background jobs are handed to a fish started via exec.
+/// Each job is a line of the form "pgid<TAB>pid pid...<TAB>command".
+static const wchar_t *const k_inherited_jobs_var = L"__fish_inherited_jobs";
+
+void hand_off_jobs_for_exec(parser_t &parser) {
+    wcstring serialized;
+    for (const auto &j : jobs_requiring_warning_on_exit(parser)) {
+        wcstring pids;
+        for (const auto &p : j->processes) {
+            if (p->pid <= 0 || p->is_completed()) continue;
+            if (!pids.empty()) pids.push_back(L' ');
+            pids.append(to_string(p->pid));
+        }
+        if (pids.empty()) continue;
+        wcstring command = j->command();
+        std::replace_if(
+            command.begin(), command.end(), [](wchar_t c) { return c == L'\n' || c == L'\t'; },
+            L' ');
+        append_format(serialized, L"%d\t%ls\t%ls\n", j->get_pgid().value_or(0), pids.c_str(),
+                      command.c_str());
+    }
+    if (!serialized.empty()) {
+        parser.vars().set_one(k_inherited_jobs_var, ENV_GLOBAL | ENV_EXPORT, serialized);
+    }
+}
+
+void revoke_jobs_hand_off(parser_t &parser) {
+    parser.vars().remove(k_inherited_jobs_var, ENV_GLOBAL | ENV_EXPORT);
+}
+
+void adopt_inherited_jobs(parser_t &parser) {
+    auto var = parser.vars().get(k_inherited_jobs_var);
+    if (!var) return;
+    wcstring serialized = var->as_string();
+    parser.vars().remove(k_inherited_jobs_var, ENV_GLOBAL | ENV_EXPORT);
+
+    for (const wcstring &line : split_string(serialized, L'\n')) {
+        std::vector<wcstring> fields = split_string(line, L'\t');
+        if (fields.size() != 3) continue;
+        pid_t pgid = fish_wcstoi(fields[0].c_str());
+        if (errno) continue;
+
+        job_t::properties_t props{};
+        props.initial_background = true;
+        props.skip_notification = !parser.is_interactive();
+        auto job = std::make_shared<job_t>(props, fields[2]);
+        for (const wcstring &pidstr : split_string(fields[1], L' ')) {
+            pid_t pid = fish_wcstoi(pidstr.c_str());
+            if (errno || pid <= 0) continue;
+            auto p = make_unique<process_t>();
+            // Capture the generations before checking the process, so its exit is not missed.
+            p->check_generations_before_launch();
+            // Only adopt processes which are still running children of ours. Reap any which
+            // already exited, so they don't linger as zombies.
+            siginfo_t info{};
+            if (waitid(P_PID, pid, &info, WEXITED | WNOHANG | WNOWAIT) != 0) continue;
+            if (info.si_pid == pid) {
+                waitpid(pid, nullptr, WNOHANG);
+                continue;
+            }
+            p->pid = pid;
+            // We only know the job's command line, so use its words as an approximate argv.
+            std::vector<wcstring> argv = split_string(fields[2], L' ');
+            argv.erase(std::remove(argv.begin(), argv.end(), wcstring{}), argv.end());
+            p->actual_cmd = argv.empty() ? fields[2] : argv.front();
+            p->set_argv(std::move(argv));
+            p->mark_launched();
+            job->processes.push_back(std::move(p));
+        }
+        if (job->processes.empty()) continue;
+        job->processes.front()->is_first_in_job = true;
+        job->processes.back()->is_last_in_job = true;
+
+        if (pgid > 0 && pgid != getpgrp()) {
+            auto group = create_job_group_with_job_control_ffi(job->command(), false);
+            group->set_pgid(pgid);
+            job->group = box_to_shared_ptr(std::move(group));
+        } else {
+            job->group = box_to_shared_ptr(create_job_group_ffi(job->command(), true));
+        }
+        job->group->set_is_foreground(false);
+        job->mut_flags().is_group_root = true;
+        job->mark_constructed();
+        parser.job_add(job);
+        FLOGF(proc_job_run, L"Adopted job %d (%ls) from the previous shell", job->job_id(),
+              job->command_wcstr());
+    }
+}
+
 void hup_jobs(const job_list_t &jobs) {
     pid_t fish_pgrp = getpgrp();
     for (const auto &j : jobs) {
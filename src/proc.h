@@ -9,10 +9,12 @@
 #include <sys/wait.h>  // IWYU pragma: keep
 
 #include <atomic>
+#include <chrono>
 #include <cstdint>
 #include <cstdlib>
 #include <deque>
 #include <memory>
+#include <mutex>
 #include <string>
 #include <utility>
 #include <vector>
@@ -20,6 +22,7 @@
 #include "ast.h"
 #include "common.h"
 #include "cxx.h"
+#include "global_safety.h"
 #include "maybe.h"
 #include "parse_tree.h"
 #include "redirection.h"
@@ -284,6 +287,13 @@ class process_t {
     /// launch. This helps us avoid spurious waitpid calls.
     void check_generations_before_launch();
 
+    /// Record that this process is about to be launched, for timing purposes.
+    void mark_launched();
+
+    /// Record the wall time elapsed since launch. If \p rusage is set, also record the resources it
+    /// reports. This is called when the process completes.
+    void record_usage(const struct rusage *rusage);
+
     /// Mark that this process was part of a pipeline which was aborted.
     /// The process was never successfully launched; give it a status of EXIT_FAILURE.
     void mark_aborted_before_launch();
@@ -334,6 +344,12 @@ class process_t {
     /// Reported status value.
     proc_status_t status{};
 
+    /// Monotonic time at which the process was launched, or none if it never was.
+    maybe_t<std::chrono::steady_clock::time_point> launch_time{};
+
+    /// Resources used by this process, set when it completes.
+    proc_usage_t usage{};
+
     /// Last time of cpu time check, in seconds (per timef).
     timepoint_t last_time{0};
 
@@ -363,6 +379,26 @@ struct RustFFIProcList {
     size_t count;
 };
 
+/// State shared between a job with a timeout and the fd monitor timer which enforces it.
+struct job_timeout_t {
+    /// Set when the job is done, so the timeout no longer applies.
+    relaxed_atomic_bool_t finished{false};
+
+    /// Protects pids. The reaper holds it from reaping a process until it is removed from pids, so
+    /// the timer never signals a pid which may have been reused.
+    std::mutex lock;
+
+    /// What the timer signals: the negated process group id if the job has its own group, else
+    /// the pid of each external process which was not reaped yet.
+    std::vector<pid_t> pids;
+
+    /// The fd monitor item id of the pending timer, so it can be cancelled early.
+    relaxed_atomic_t<uint64_t> timer_id{0};
+
+    /// Set when the timeout expired and the job was signalled.
+    relaxed_atomic_bool_t expired{false};
+};
+
 /// A struct representing a job. A job is a pipeline of one or more processes.
 class job_t : noncopyable_t {
    public:
@@ -378,6 +414,10 @@ class job_t : noncopyable_t {
         /// initial state should be.
         bool initial_background{};
 
+        /// Whether the job was backgrounded with &--queue, so it waits for a free background slot
+        /// even if $fish_max_background_jobs is unset.
+        bool queue_background{};
+
         /// Whether the job has the 'time' prefix and so we should print timing for this job.
         bool wants_timing{};
 
@@ -437,6 +477,9 @@ class job_t : noncopyable_t {
     /// All the processes in this job.
     process_list_t processes;
 
+    /// State shared with the timer enforcing $fish_timeout for this job, or null if it has none.
+    std::shared_ptr<job_timeout_t> timeout{};
+
     // The group containing this job.
     // This is never null and not changed after construction.
     job_group_ref_t group{};
@@ -497,6 +540,9 @@ class job_t : noncopyable_t {
     /// specified.
     bool is_initially_background() const { return properties.initial_background; }
 
+    /// \return whether this job waits for a free background slot, because &--queue was specified.
+    bool wants_queue() const { return properties.queue_background; }
+
     /// Mark this job as constructed. The job must not have previously been marked as constructed.
     void mark_constructed();
 
@@ -601,6 +647,10 @@ job_list_t jobs_requiring_warning_on_exit(const parser_t &parser);
 /// jobs_requiring_warning_on_exit().
 void print_exit_warning_for_jobs(const job_list_t &jobs);
 
+/// Print the final list of background jobs as fish exits, saying whether they are being
+/// terminated or left running.
+void print_jobs_left_on_exit(const job_list_t &jobs, bool terminating);
+
 /// Use the procfs filesystem to look up how many jiffies of cpu time was used by a given pid. This
 /// function is only available on systems with the procfs file entry 'stat', i.e. Linux.
 clock_ticks_t proc_get_jiffies(pid_t inpid);
@@ -622,6 +672,16 @@ void hup_jobs(const job_list_t &jobs);
 /// jobs. Used to avoid zombie processes after disown.
 void add_disowned_job(const job_t *j);
 
+/// Record the running background jobs in an exported variable, so that a fish which replaces us
+/// via exec can adopt them. Since exec keeps our pid, their processes remain its children.
+void hand_off_jobs_for_exec(parser_t &parser);
+
+/// Undo hand_off_jobs_for_exec(), because the exec failed.
+void revoke_jobs_hand_off(parser_t &parser);
+
+/// Adopt any background jobs handed off by hand_off_jobs_for_exec() in the fish we replaced.
+void adopt_inherited_jobs(parser_t &parser);
+
 bool have_proc_stat();
 
 #endif
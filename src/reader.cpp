@@ -22,6 +22,7 @@
 #include <stdio.h>
 #include <sys/stat.h>
 #include <sys/types.h>
+#include <sys/wait.h>
 #include <termios.h>
 #include <time.h>
 #include <unistd.h>
@@ -43,12 +44,16 @@
 #include <memory>
 #include <set>
 #include <type_traits>
+#include <unordered_map>
 
 #include "abbrs.h"
 #include "ast.h"
+#include "ast_cache.rs.h"
+#include "autoload.h"
 #include "color.h"
 #include "common.h"
 #include "complete.h"
+#include "crash_handler.rs.h"
 #include "env.h"
 #include "env_dispatch.rs.h"
 #include "event.h"
@@ -69,17 +74,24 @@
 #include "kill.h"
 #include "operation_context.h"
 #include "output.h"
+#include "output.rs.h"
 #include "pager.h"
 #include "parse_constants.h"
 #include "parse_tree.h"
 #include "parse_util.h"
+#include "null_terminated_array.h"
 #include "parser.h"
+#include "postfork.h"
 #include "proc.h"
 #include "reader.h"
 #include "screen.h"
 #include "signals.h"
+#include "terminal_probe.rs.h"
 #include "termsize.h"
+#include "text_object.rs.h"
+#include "timer.rs.h"
 #include "tokenizer.h"
+#include "watchdog.rs.h"
 #include "wcstringutil.h"
 #include "wildcard.h"
 #include "wutil.h"  // IWYU pragma: keep
@@ -87,6 +99,7 @@
 // Name of the variable that tells how long it took, in milliseconds, for the previous
 // interactive command to complete.
 #define ENV_CMD_DURATION L"CMD_DURATION"
+#define ENV_CMD_DURATION_US L"cmd_duration_us"
 
 /// Maximum length of prefix string when printing completion list. Longer prefixes will be
 /// ellipsized.
@@ -147,25 +160,20 @@ void commandline_set_buffer(wcstring text, size_t cursor_pos) {
     state->text = std::move(text);
 }
 
-/// Any time the contents of a buffer changes, we update the generation count. This allows for our
-/// background threads to notice it and skip doing work that they would otherwise have to do.
-static std::atomic<uint32_t> s_generation;
-
-/// Helper to get the generation count
-static inline uint32_t read_generation_count() {
-    return s_generation.load(std::memory_order_relaxed);
+void commandline_set_selection(size_t start, size_t end) {
+    auto state = commandline_state_snapshot();
+    start = std::min(start, state->text.size());
+    end = std::min(std::max(start, end), state->text.size());
+    state->selection = source_range_t{static_cast<uint32_t>(start),
+                                      static_cast<uint32_t>(end - start)};
 }
 
-/// \return an operation context for a background operation..
+/// \return an operation context for a background operation, which is cancelled with \p token.
 /// Crucially the operation context itself does not contain a parser.
 /// It is the caller's responsibility to ensure the environment lives as long as the result.
 static operation_context_t get_bg_context(const std::shared_ptr<environment_t> &env,
-                                          uint32_t generation_count) {
-    cancel_checker_t cancel_checker = [generation_count] {
-        // Cancel if the generation count changed.
-        return generation_count != read_generation_count();
-    };
-    return operation_context_t{nullptr, *env, std::move(cancel_checker), kExpansionLimitBackground};
+                                          const cancel_token_t &token) {
+    return operation_context_t{nullptr, *env, token.checker(), kExpansionLimitBackground};
 }
 
 /// We try to ensure that syntax highlighting completes appropriately before executing what the user
@@ -178,22 +186,32 @@ static constexpr long kHighlightTimeoutForExecutionMs = 250;
 /// These are deliberately leaked to avoid shutdown dtor registration.
 static debounce_t &debounce_autosuggestions() {
     const long kAutosuggestTimeoutMs = 500;
-    static auto res = new_debounce_t(kAutosuggestTimeoutMs);
+    static auto res = new_debounce_t(kAutosuggestTimeoutMs, iothread_priority_t::best_effort);
     return *res;
 }
 
 static debounce_t &debounce_highlighting() {
     const long kHighlightTimeoutMs = 500;
-    static auto res = new_debounce_t(kHighlightTimeoutMs);
+    static auto res = new_debounce_t(kHighlightTimeoutMs, iothread_priority_t::best_effort);
     return *res;
 }
 
 static debounce_t &debounce_history_pager() {
     const long kHistoryPagerTimeoutMs = 500;
-    static auto res = new_debounce_t(kHistoryPagerTimeoutMs);
+    static auto res = new_debounce_t(kHistoryPagerTimeoutMs, iothread_priority_t::user_blocking);
+    return *res;
+}
+
+static debounce_t &debounce_completion_preview() {
+    const long kCompletionPreviewTimeoutMs = 500;
+    static auto res =
+        new_debounce_t(kCompletionPreviewTimeoutMs, iothread_priority_t::user_blocking);
     return *res;
 }
 
+/// The most lines of fish_completion_preview output that are kept.
+static constexpr size_t kCompletionPreviewMaxLines = 10;
+
 bool edit_t::operator==(const edit_t &other) const {
     return cursor_position_before_edit == other.cursor_position_before_edit &&
            offset == other.offset && length == other.length && old == other.old &&
@@ -264,8 +282,17 @@ void editable_line_t::clear() {
     set_position(0);
 }
 
+/// The kinds of edits that are undone together, per $fish_undo_coalesce.
+static uint8_t s_undo_coalesce = undo_coalesce_insert;
+
+void reader_change_undo_coalesce(uint8_t kinds) {
+    ASSERT_IS_MAIN_THREAD();
+    s_undo_coalesce = kinds;
+}
+
 void editable_line_t::push_edit(edit_t edit, bool allow_coalesce) {
     bool is_insertion = edit.length == 0;
+    bool is_deletion = edit.length != 0 && edit.replacement.empty();
     /// Coalescing insertion does not create a new undo entry but adds to the last insertion.
     if (allow_coalesce && is_insertion && want_to_coalesce_insertion_of(edit.replacement)) {
         assert(edit.offset == position());
@@ -278,6 +305,23 @@ void editable_line_t::push_edit(edit_t edit, bool allow_coalesce) {
         return;
     }
 
+    /// Likewise, a deletion next to the last one grows what the last one removed.
+    if (allow_coalesce && is_deletion && want_to_coalesce_deletion(edit)) {
+        edit_t &last_edit = undo_history_.edits.back();
+        wcstring deleted = text_.substr(edit.offset, edit.length);
+        if (edit.offset < last_edit.offset) {
+            // Deleting backwards.
+            last_edit.offset = edit.offset;
+            last_edit.old.insert(0, deleted);
+        } else {
+            last_edit.old.append(deleted);
+        }
+        last_edit.length = last_edit.old.size();
+        apply_edit(&text_, &colors_, edit);
+        set_position(edit.offset);
+        return;
+    }
+
     // Assign a new group id or propagate the old one if we're in a logical grouping of edits
     if (edit_group_level_ != -1) {
         edit.group_id = edit_group_id_;
@@ -299,7 +343,8 @@ void editable_line_t::push_edit(edit_t edit, bool allow_coalesce) {
     set_position(cursor_position_after_edit(edit));
     assert(undo_history_.edits_applied == undo_history_.edits.size());
     undo_history_.may_coalesce =
-        is_insertion && (undo_history_.try_coalesce || edit.replacement.size() == 1);
+        (is_insertion && (undo_history_.try_coalesce || edit.replacement.size() == 1)) ||
+        (is_deletion && allow_coalesce);
     undo_history_.edits_applied++;
     undo_history_.edits.emplace_back(std::move(edit));
 }
@@ -355,8 +400,13 @@ bool editable_line_t::want_to_coalesce_insertion_of(const wcstring &str) const {
     if (!undo_history_.may_coalesce) return false;
     // Only consolidate single character inserts.
     if (str.size() != 1) return false;
+    // Edit groups are coalesced regardless of the user's choice.
+    if (!(s_undo_coalesce & undo_coalesce_insert) && !undo_history_.try_coalesce) return false;
     // Make an undo group after every space.
-    if (str.at(0) == L' ' && !undo_history_.try_coalesce) return false;
+    if (str.at(0) == L' ' && !(s_undo_coalesce & undo_coalesce_words) &&
+        !undo_history_.try_coalesce) {
+        return false;
+    }
     assert(!undo_history_.edits.empty());
     const edit_t &last_edit = undo_history_.edits.back();
     // Don't add to the last edit if it deleted something.
@@ -366,6 +416,22 @@ bool editable_line_t::want_to_coalesce_insertion_of(const wcstring &str) const {
     return true;
 }
 
+/// Whether we want to merge this deletion with the previous edit.
+bool editable_line_t::want_to_coalesce_deletion(const edit_t &edit) const {
+    if (!(s_undo_coalesce & undo_coalesce_delete)) return false;
+    // The previous edit must support coalescing, and not have been undone.
+    if (!undo_history_.may_coalesce) return false;
+    if (undo_history_.edits_applied != undo_history_.edits.size()) return false;
+    assert(!undo_history_.edits.empty());
+    const edit_t &last_edit = undo_history_.edits.back();
+    // Only add to a deletion.
+    if (last_edit.length == 0 || !last_edit.replacement.empty()) return false;
+    // The cursor must still be where the last deletion left it, and this one must be right next
+    // to it: before it for backspace, or after it for delete.
+    if (position() != last_edit.offset) return false;
+    return edit.offset + edit.length == last_edit.offset || edit.offset == last_edit.offset;
+}
+
 // Make the search case-insensitive unless we have an uppercase character.
 static history_search_flags_t smartcase_flags(const wcstring &query) {
     return query == wcstolower(query) ? history_search_ignore_case : 0;
@@ -373,6 +439,28 @@ static history_search_flags_t smartcase_flags(const wcstring &query) {
 
 namespace {
 
+/// \return whether fish_history_sync is 1, so history picks up the commands that other sessions
+/// run.
+static bool history_sync_enabled(const environment_t &vars) {
+    auto sync = vars.get(L"fish_history_sync");
+    return sync && sync->as_string() == L"1";
+}
+
+/// If fish_history_sync is 1, incorporate the commands that other sessions ran since we last
+/// looked.
+static void maybe_sync_history(history_t &history, const environment_t &vars) {
+    if (history_sync_enabled(vars)) history.sync_external_changes();
+}
+
+/// \return the directory whose commands history searches should prefer, which is the current one
+/// if fish_history_scope is "directory". Otherwise return an empty string.
+static wcstring history_scope_cwd(const environment_t &vars) {
+    auto scope = vars.get(L"fish_history_scope");
+    if (!scope || scope->as_string() != L"directory") return {};
+    auto pwd = vars.get(L"PWD");
+    return pwd ? pwd->as_string() : wcstring{};
+}
+
 /// Encapsulation of the reader's history search functionality.
 class reader_history_search_t {
    public:
@@ -541,8 +629,9 @@ class reader_history_search_t {
     bool add_skip(const wcstring &str) { return skips_.insert(str).second; }
 
     /// Reset, beginning a new line or token mode search.
+    /// If \p cwd is not empty, matches run in that directory come first.
     void reset_to_mode(const wcstring &text, const std::shared_ptr<history_t> &hist, mode_t mode,
-                       size_t token_offset) {
+                       size_t token_offset, const wcstring &cwd) {
         assert(mode != inactive && "mode cannot be inactive in this setter");
         skips_ = {text};
         matches_ = {{text, 0}};
@@ -554,6 +643,7 @@ class reader_history_search_t {
         search_ = history_search_t(
             hist, text,
             by_prefix() ? history_search_type_t::prefix : history_search_type_t::contains, flags);
+        search_.prefer_cwd(cwd);
     }
 
     /// Reset to inactive search.
@@ -668,13 +758,23 @@ struct layout_data_t {
     /// Visual selection of the command line, or none if none.
     maybe_t<selection_data_t> selection{};
 
+    /// The cursors of a multi-cursor edit besides the main one.
+    std::vector<size_t> extra_cursors{};
+
     /// String containing the autosuggestion.
     wcstring autosuggestion{};
 
+    /// What the abbreviation before the cursor expands to, if shown.
+    wcstring abbr_preview{};
+
     /// The matching range of the command line from a history search. If non-empty, then highlight
     /// the range within the text.
     maybe_t<source_range_t> history_search_range{};
 
+    /// The range of the command line inserted by the selected completion in the pager, which is
+    /// shown like an autosuggestion until it is accepted.
+    maybe_t<source_range_t> pager_preview_range{};
+
     /// The result of evaluating the left, mode and right prompt commands.
     /// That is, this the text of the prompts, not the commands to produce them.
     wcstring left_prompt_buff{};
@@ -738,6 +838,21 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     /// The selection data. If this is not none, then we have an active selection.
     maybe_t<selection_data_t> selection{};
 
+    /// The cursors besides the main one while editing several lines at once, as offsets into the
+    /// command line in ascending order. Typed and deleted characters apply at each of them.
+    std::vector<size_t> extra_cursors{};
+
+    /// The placeholders of the last abbreviation expansion that Tab has yet to move to, in order.
+    /// They are kept as distances from the end of the command line, so filling in one placeholder
+    /// leaves the ones after it in place.
+    std::vector<size_t> abbr_placeholders{};
+
+    /// What the abbreviation before the cursor would expand to, shown after the command line.
+    wcstring abbr_preview{};
+
+    /// The command lines saved with `commandline --checkpoint`, by name.
+    std::unordered_map<wcstring, commandline_checkpoint_t> checkpoints{};
+
     wcstring left_prompt_buff;
     wcstring mode_prompt_buff;
     /// The output of the last evaluation of the right prompt command.
@@ -747,6 +862,9 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     /// This is the saved command line before modification.
     wcstring cycle_command_line;
     size_t cycle_cursor_pos{0};
+    /// The range of the command line that the selected completion replaced the cycle command line
+    /// with, if any.
+    maybe_t<source_range_t> pager_preview_range{};
 
     /// If set, a key binding or the 'exit' command has asked us to exit our read loop.
     bool exit_loop_requested{false};
@@ -755,10 +873,16 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     bool did_warn_for_bg_jobs{false};
     /// The current contents of the top item in the kill ring.
     wcstring kill_item;
+    /// The register chosen with use-register, and the mapping count when it was chosen. It applies
+    /// to the kills and yanks of that binding and the next one.
+    wcstring pending_register;
+    uint64_t pending_register_mapping{0};
 
     /// A flag which may be set to force re-execing all prompts and re-rendering.
     /// This may come about when a color like $fish_color... has changed.
     bool force_exec_prompt_and_repaint{false};
+    /// Whether the prompt is on the screen, because we are waiting for the user to type.
+    bool prompt_displayed{false};
 
     /// The target character of the last jump command.
     wchar_t last_jump_target{0};
@@ -770,6 +894,13 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     wcstring in_flight_highlight_request;
     wcstring in_flight_autosuggest_request;
 
+    /// Cancels background work on the current contents of the command line, i.e. autosuggestions
+    /// and highlighting. Any time the command line changes, this is cancelled and replaced, so
+    /// that work on the old contents stops right away instead of running to completion.
+    cancel_token_t command_line_token;
+    /// Cancels the in-flight history pager search, when a new one is started.
+    cancel_token_t history_pager_token;
+
     bool is_navigating_pager_contents() const {
         return this->pager.is_navigating_contents() || history_pager_active;
     }
@@ -796,6 +927,13 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
 
     /// Do what we need to do whenever our pager selection changes.
     void pager_selection_changed();
+    /// \return the range of the command line to preview the selected completion in, if any.
+    maybe_t<source_range_t> get_pager_preview_range() const;
+    /// \return the token that the selected completion turns the current one into, to pass to
+    /// fish_completion_preview, or none if there is nothing to preview.
+    maybe_t<wcstring> get_completion_preview_candidate() const;
+    /// Run fish_completion_preview for the selected completion once we are idle.
+    void update_completion_preview();
 
     /// Expand abbreviations at the current cursor position, minus cursor_backtrack.
     bool expand_abbreviation_at_cursor(size_t cursor_backtrack);
@@ -841,6 +979,9 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     void update_buff_pos(editable_line_t *el, maybe_t<size_t> new_pos = none_t());
 
     void kill(editable_line_t *el, size_t begin_idx, size_t length, int mode, int newv);
+    wcstring kill_register();
+    void write_register(const wcstring &name, const wcstring &str);
+    wcstring read_register(const wcstring &name);
     /// Inserts a substring of str given by start, len at the cursor position.
     void insert_string(editable_line_t *el, const wcstring &str);
     /// Erase @length characters starting at @offset.
@@ -877,6 +1018,7 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
                    bool newv);
 
     void run_input_command_scripts(const std::vector<wcstring> &cmds);
+    void insert_paste(wcstring text);
     maybe_t<char_event_t> read_normal_chars(readline_loop_state_t &rls);
     void handle_readline_command(readline_cmd_t cmd, readline_loop_state_t &rls);
 
@@ -906,6 +1048,7 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     bool can_autosuggest() const;
     void autosuggest_completed(autosuggestion_t result);
     void update_autosuggestion();
+    void update_abbr_preview();
     void accept_autosuggestion(
         bool full, bool single = false,
         move_word_style_t style = move_word_style_t::move_word_style_punctuation);
@@ -930,6 +1073,10 @@ class reader_data_t : public std::enable_shared_from_this<reader_data_t> {
     void update_command_line_from_history_search();
     void set_buffer_maintaining_pager(const wcstring &b, size_t pos, bool transient = false);
     void delete_char(bool backward = true);
+    void multi_cursor_from_selection();
+    void select_range(size_t start, size_t end);
+    void insert_at_cursors(const wcstring &str);
+    void delete_at_cursors(bool backward);
 
     /// Called to update the termsize, including $COLUMNS and $LINES, as necessary.
     void update_termsize() { termsize_update_ffi(reinterpret_cast<unsigned char *>(&parser())); }
@@ -1021,8 +1168,24 @@ static void redirect_tty_after_sighup() {
     }
 }
 
+/// Whether readline wants the terminal to report keys like ctrl-i and shift-enter so they can be
+/// told apart from Tab and Enter, see kitty_keys.rs, and whether it currently does. Commands don't
+/// expect this, so it is turned off while they have the terminal.
+static bool s_kitty_keyboard_wanted = false;
+static bool s_kitty_keyboard_on = false;
+
+/// Tell the terminal to start or stop reporting keys with the kitty keyboard protocol, unless it
+/// already does what we want or doesn't support it.
+static void term_set_kitty_keyboard(bool on) {
+    ASSERT_IS_MAIN_THREAD();
+    if (on == s_kitty_keyboard_on || (on && !term_supports_kitty_keyboard())) return;
+    outputter_t::stdoutput().writestr(on ? L"\x1B[>1u" : L"\x1B[<u");
+    s_kitty_keyboard_on = on;
+}
+
 /// Give up control of terminal.
 static void term_donate(bool quiet = false) {
+    term_set_kitty_keyboard(false);
     while (tcsetattr(STDIN_FILENO, TCSANOW, &tty_modes_for_external_cmds) == -1) {
         if (errno == EIO) redirect_tty_output();
         if (errno != EINTR) {
@@ -1066,6 +1229,7 @@ static void term_steal() {
             break;
         }
     }
+    term_set_kitty_keyboard(s_kitty_keyboard_wanted);
 
     termsize_invalidate_tty();
 }
@@ -1158,11 +1322,14 @@ bool reader_data_t::is_repaint_needed(const std::vector<highlight_spec_t> *mcolo
            check(command_line.text() != last.text, L"text") ||
            check(mcolors && *mcolors != last.colors, L"highlight") ||
            check(selection != last.selection, L"selection") ||
+           check(extra_cursors != last.extra_cursors, L"cursors") ||
            check(focused_on_pager != last.focused_on_pager, L"focus") ||
            check(command_line.position() != last.position, L"position") ||
            check(history_search.search_range_if_active() != last.history_search_range,
                  L"history search") ||
+           check(get_pager_preview_range() != last.pager_preview_range, L"pager preview") ||
            check(autosuggestion.text != last.autosuggestion, L"autosuggestion") ||
+           check(abbr_preview != last.abbr_preview, L"abbreviation preview") ||
            check(left_prompt_buff != last.left_prompt_buff, L"left_prompt") ||
            check(mode_prompt_buff != last.mode_prompt_buff, L"mode_prompt") ||
            check(right_prompt_buff != last.right_prompt_buff, L"right_prompt") ||
@@ -1177,9 +1344,12 @@ layout_data_t reader_data_t::make_layout_data() const {
     assert(result.text.size() == result.colors.size());
     result.position = focused_on_pager ? pager.cursor_position() : command_line.position();
     result.selection = selection;
+    result.extra_cursors = extra_cursors;
     result.focused_on_pager = (active_edit_line() == &pager.search_field_line);
     result.history_search_range = history_search.search_range_if_active();
+    result.pager_preview_range = get_pager_preview_range();
     result.autosuggestion = autosuggestion.text;
+    result.abbr_preview = abbr_preview;
     result.left_prompt_buff = left_prompt_buff;
     result.mode_prompt_buff = mode_prompt_buff;
     result.right_prompt_buff = right_prompt_buff;
@@ -1197,6 +1367,16 @@ void reader_data_t::paint_layout(const wchar_t *reason) {
     } else {
         // Combine the command and autosuggestion into one string.
         full_line = combine_command_and_autosuggestion(cmd_line->text(), autosuggestion.text);
+
+        // Without an autosuggestion, show what the abbreviation would expand to in its place.
+        // Only the first line of a longer expansion fits.
+        if (!data.abbr_preview.empty() && full_line.size() == cmd_line->size()) {
+            bool unicode = get_ellipsis_char() == L'\x2026';
+            size_t newline = data.abbr_preview.find(L'\n');
+            full_line.append(unicode ? L" \u2192 " : L" -> ");
+            full_line.append(data.abbr_preview, 0, newline);
+            if (newline != wcstring::npos) full_line.append(get_ellipsis_str());
+        }
     }
 
     // Copy the colors and extend them with autosuggestion color.
@@ -1215,6 +1395,14 @@ void reader_data_t::paint_layout(const wchar_t *reason) {
         }
     }
 
+    // Show the completion selected in the pager like an autosuggestion, until it is accepted.
+    if (!conf.in_silent_mode && data.pager_preview_range) {
+        size_t end = std::min(static_cast<size_t>(data.pager_preview_range->end()), colors.size());
+        for (size_t i = data.pager_preview_range->start; i < end; i++) {
+            colors.at(i) = highlight_role_t::autosuggestion;
+        }
+    }
+
     // Apply any selection.
     if (data.selection.has_value()) {
         highlight_spec_t selection_color = {highlight_role_t::selection,
@@ -1225,6 +1413,13 @@ void reader_data_t::paint_layout(const wchar_t *reason) {
         }
     }
 
+    // Show the extra cursors like a selection of the character under them.
+    for (size_t pos : data.extra_cursors) {
+        if (pos < colors.size() && data.text.at(pos) != L'\n') {
+            colors.at(pos) = {highlight_role_t::selection, highlight_role_t::selection};
+        }
+    }
+
     // Extend our colors with the autosuggestion.
     colors.resize(full_line.size(), highlight_role_t::autosuggestion);
 
@@ -1242,9 +1437,10 @@ void reader_data_t::paint_layout(const wchar_t *reason) {
 /// Internal helper function for handling killing parts of text.
 void reader_data_t::kill(editable_line_t *el, size_t begin_idx, size_t length, int mode, int newv) {
     const wchar_t *begin = el->text().c_str() + begin_idx;
+    wcstring reg = kill_register();
     if (newv) {
         kill_item = wcstring(begin, length);
-        kill_add(kill_item);
+        if (reg.empty()) kill_add(kill_item);
     } else {
         wcstring old = kill_item;
         if (mode == KILL_APPEND) {
@@ -1254,11 +1450,50 @@ void reader_data_t::kill(editable_line_t *el, size_t begin_idx, size_t length, i
             kill_item.append(old);
         }
 
-        kill_replace(old, kill_item);
+        if (reg.empty()) kill_replace(old, kill_item);
     }
+    if (!reg.empty()) write_register(reg, kill_item);
     erase_substring(el, begin_idx, length);
 }
 
+/// \return the register that kills and yanks go to: the one chosen with use-register, or else
+/// $fish_clipboard_register. Empty means the kill ring, and so does the register '"' like in vim.
+wcstring reader_data_t::kill_register() {
+    wcstring reg;
+    if (!pending_register.empty() && inputter.mapping_count() <= pending_register_mapping + 1) {
+        reg = pending_register;
+    } else {
+        pending_register.clear();
+        if (auto var = vars().get(L"fish_clipboard_register")) reg = var->as_string();
+    }
+    return reg == L"\"" ? wcstring{} : reg;
+}
+
+/// Store \p str in the register called \p name. The system clipboard is written with the
+/// fish_clipboard_copy function, which knows about the various clipboard tools and OSC 52.
+void reader_data_t::write_register(const wcstring &name, const wcstring &str) {
+    if (!kill_register_is_clipboard(name)) {
+        kill_set_register(name, str);
+        return;
+    }
+    auto last_statuses = parser().get_last_statuses();
+    parser().eval(L"fish_clipboard_copy " + escape_string(str), io_chain_t{});
+    parser().set_last_statuses(std::move(last_statuses));
+}
+
+/// \return the contents of the register called \p name, reading the system clipboard with the
+/// fish_clipboard_paste function.
+wcstring reader_data_t::read_register(const wcstring &name) {
+    if (!kill_register_is_clipboard(name)) {
+        auto contents = kill_get_register(name);
+        return contents ? contents.acquire() : wcstring{};
+    }
+    scoped_push<bool> not_interactive(&parser().libdata().is_interactive, false);
+    std::vector<wcstring> outputs;
+    (void)exec_subshell(L"fish_clipboard_paste", parser(), outputs, false /* ignore status */);
+    return join_strings(outputs, L'\n');
+}
+
 extern "C" {
 // This is called from a signal handler!
 void reader_handle_sigint() { interrupted = SIGINT; }
@@ -1268,8 +1503,9 @@ void reader_handle_sigint() { interrupted = SIGINT; }
 void reader_data_t::command_line_changed(const editable_line_t *el) {
     ASSERT_IS_MAIN_THREAD();
     if (el == &this->command_line) {
-        // Update the gen count.
-        s_generation.store(1 + read_generation_count(), std::memory_order_relaxed);
+        // Abandon background work on the old contents.
+        command_line_token.cancel();
+        command_line_token = cancel_token_t{};
     } else if (el == &this->pager.search_field_line) {
         if (history_pager_active) {
             fill_history_pager(true, history_search_direction_t::backward);
@@ -1288,10 +1524,40 @@ void reader_data_t::maybe_refilter_pager(const editable_line_t *el) {
     }
 }
 
+/// Fill the history pager from a ranked search, where \p index is a position in the ranking
+/// rather than in the history.
+static history_pager_result_t history_pager_search_ranked(const std::shared_ptr<history_t> &history,
+                                                          history_search_direction_t direction,
+                                                          size_t index, size_t page_size,
+                                                          const wcstring &search_string,
+                                                          const cancel_token_t &token) {
+    size_t start = index;
+    if (direction == history_search_direction_t::forward) {
+        start = index > page_size ? index - page_size : 0;
+        page_size = index - start;
+    }
+    size_t total = 0;
+    std::vector<wcstring> matches = history->search_ranked(
+        search_string, start, page_size, &total, [&] { return token.cancelled(); });
+
+    completion_list_t completions;
+    for (wcstring &match : matches) {
+        completions.push_back(completion_t{
+            std::move(match), L"", string_fuzzy_match_t::exact_match(),
+            COMPLETE_REPLACES_COMMANDLINE | COMPLETE_DONT_ESCAPE | COMPLETE_DONT_SORT});
+    }
+    if (direction == history_search_direction_t::forward) {
+        return {completions, start, start > 0};
+    }
+    size_t end = start + completions.size();
+    return {completions, end, end < total};
+}
+
 static history_pager_result_t history_pager_search(const std::shared_ptr<history_t> &history,
                                                    history_search_direction_t direction,
                                                    size_t history_index,
-                                                   const wcstring &search_string) {
+                                                   const wcstring &search_string,
+                                                   bool ranked, const cancel_token_t &token) {
     // Limit the number of elements to half the screen like we do for completions
     // Note that this is imperfect because we could have a multi-column layout.
     //
@@ -1299,6 +1565,10 @@ static history_pager_result_t history_pager_search(const std::shared_ptr<history
     // but that can't really be helped.
     // (subtract 2 for the search line and the prompt)
     size_t page_size = std::max(termsize_last().height / 2 - 2, (rust::isize)12);
+    if (ranked) {
+        return history_pager_search_ranked(history, direction, history_index, page_size,
+                                           search_string, token);
+    }
 
     completion_list_t completions;
     history_search_t search{history, search_string, history_search_type_t::contains,
@@ -1311,10 +1581,15 @@ static history_pager_result_t history_pager_search(const std::shared_ptr<history
                              smartcase_flags(search_string), history_index};
         next_match_found = search.go_to_next_match(direction);
     }
-    while (completions.size() < page_size && next_match_found) {
+    while (completions.size() < page_size && next_match_found && !token.cancelled()) {
         const history_item_t &item = search.current_item();
+        // Flag commands that failed.
+        wcstring description;
+        if (item.get_exit_status() > 0) {
+            description = format_string(_(L"status %d"), item.get_exit_status());
+        }
         completions.push_back(completion_t{
-            item.str(), L"", string_fuzzy_match_t::exact_match(),
+            item.str(), std::move(description), string_fuzzy_match_t::exact_match(),
             COMPLETE_REPLACES_COMMANDLINE | COMPLETE_DONT_ESCAPE | COMPLETE_DONT_SORT});
 
         next_match_found = search.go_to_next_match(direction);
@@ -1329,6 +1604,7 @@ void reader_data_t::fill_history_pager(bool new_search, history_search_direction
     assert(!new_search || direction == history_search_direction_t::backward);
     size_t index;
     if (new_search) {
+        maybe_sync_history(*history, vars());
         index = 0;
     } else if (direction == history_search_direction_t::forward) {
         index = history_pager_history_index_start;
@@ -1337,13 +1613,20 @@ void reader_data_t::fill_history_pager(bool new_search, history_search_direction
         index = history_pager_history_index_end;
     }
     const wcstring &search_term = pager.search_field_line.text();
+    auto mode = vars().get(L"fish_history_search_mode");
+    bool ranked = mode && mode->as_string() == L"smart";
+    // A new search makes the previous one obsolete.
+    history_pager_token.cancel();
+    history_pager_token = cancel_token_t{};
+    cancel_token_t token = history_pager_token;
     auto shared_this = this->shared_from_this();
     std::function<history_pager_result_t()> func = [=]() {
-        return history_pager_search(shared_this->history, direction, index, search_term);
+        return history_pager_search(shared_this->history, direction, index, search_term, ranked,
+                                    token);
     };
     std::function<void(const history_pager_result_t &)> completion =
         [=](const history_pager_result_t &result) {
-            if (search_term != shared_this->pager.search_field_line.text())
+            if (token.cancelled() || search_term != shared_this->pager.search_field_line.text())
                 return;  // Stale request.
             if (result.matched_commands.empty() && !new_search) {
                 // No more matches, keep the existing ones and flash.
@@ -1385,16 +1668,177 @@ void reader_data_t::pager_selection_changed() {
                                              this->cycle_command_line, &cursor_pos, false);
     }
 
+    // Remember where the completion changed the command line, to preview it.
+    const wcstring &old_cmd_line = this->cycle_command_line;
+    size_t prefix = 0;
+    while (prefix < old_cmd_line.size() && prefix < new_cmd_line.size() &&
+           old_cmd_line.at(prefix) == new_cmd_line.at(prefix)) {
+        prefix++;
+    }
+    size_t suffix = 0;
+    while (suffix < old_cmd_line.size() - prefix && suffix < new_cmd_line.size() - prefix &&
+           old_cmd_line.at(old_cmd_line.size() - 1 - suffix) ==
+               new_cmd_line.at(new_cmd_line.size() - 1 - suffix)) {
+        suffix++;
+    }
+    pager_preview_range = none();
+    if (completion != nullptr && prefix + suffix < new_cmd_line.size()) {
+        pager_preview_range = source_range_t{
+            static_cast<uint32_t>(prefix),
+            static_cast<uint32_t>(new_cmd_line.size() - suffix - prefix)};
+    }
+
     // Only update if something changed, to avoid useless edits in the undo history.
     if (new_cmd_line != command_line.text()) {
         set_buffer_maintaining_pager(new_cmd_line, cursor_pos, true /* transient */);
     }
+    update_completion_preview();
+}
+
+maybe_t<source_range_t> reader_data_t::get_pager_preview_range() const {
+    // The history pager replaces the whole command line, which isn't worth previewing.
+    if (pager.empty() || history_pager_active || !command_line_has_transient_edit) return none();
+    return pager_preview_range;
+}
+
+maybe_t<wcstring> reader_data_t::get_completion_preview_candidate() const {
+    const completion_t *completion = this->pager.selected_completion(this->current_page_rendering);
+    if (completion == nullptr || history_pager_active || completion->replaces_commandline()) {
+        return none();
+    }
+    if (completion->replaces_token()) return completion->completion;
+
+    // The completion only appends to what was typed, which is still escaped.
+    const wchar_t *buff = cycle_command_line.c_str();
+    const wchar_t *begin = nullptr;
+    parse_util_token_extent(buff, cycle_cursor_pos, &begin, nullptr, nullptr, nullptr);
+    wcstring token(begin, buff + cycle_cursor_pos);
+    if (!unescape_string_in_place(&token, UNESCAPE_INCOMPLETE)) return none();
+    return token + completion->completion;
+}
+
+/// Run fish_completion_preview, defined as \p definition, with the arguments \p args in a fish of
+/// its own with the environment \p env. \return the first lines it prints. This is for the
+/// background thread, where we can't run functions ourselves.
+static std::vector<wcstring> run_completion_preview(
+    const wcstring &definition, const std::vector<wcstring> &args,
+    const std::shared_ptr<owning_null_terminated_array_t> &env) {
+    ASSERT_IS_BACKGROUND_THREAD();
+    std::vector<wcstring> lines;
+#if FISH_USE_POSIX_SPAWN
+    maybe_t<autoclose_pipes_t> pipes = make_autoclose_pipes();
+    if (!pipes) return lines;
+
+    const std::string fish_path = get_executable_path("fish");
+    std::vector<std::string> argv_strs{fish_path, "-c",
+                                       wcs2string(definition + L"\nfish_completion_preview $argv"),
+                                       "--"};
+    for (const wcstring &arg : args) argv_strs.push_back(wcs2string(arg));
+    std::vector<char *> argv;
+    for (std::string &arg : argv_strs) argv.push_back(&arg[0]);
+    argv.push_back(nullptr);
+
+    posix_spawn_file_actions_t actions;
+    posix_spawnattr_t attr;
+    posix_spawn_file_actions_init(&actions);
+    posix_spawnattr_init(&attr);
+    posix_spawn_file_actions_addopen(&actions, STDIN_FILENO, "/dev/null", O_RDONLY, 0);
+    posix_spawn_file_actions_adddup2(&actions, pipes->write.fd(), STDOUT_FILENO);
+    posix_spawn_file_actions_addopen(&actions, STDERR_FILENO, "/dev/null", O_WRONLY, 0);
+    // Background threads block all signals, and the preview shouldn't inherit that or our handlers.
+    sigset_t sigmask;
+    sigemptyset(&sigmask);
+    posix_spawnattr_setsigmask(&attr, &sigmask);
+    sigset_t sigdefault;
+    get_signals_with_handlers(&sigdefault);
+    posix_spawnattr_setsigdefault(&attr, &sigdefault);
+    posix_spawnattr_setflags(&attr, POSIX_SPAWN_SETSIGMASK | POSIX_SPAWN_SETSIGDEF);
+
+    pid_t pid = -1;
+    int err = posix_spawn(&pid, fish_path.c_str(), &actions, &attr, argv.data(),
+                          const_cast<char *const *>(env->get()));
+    posix_spawn_file_actions_destroy(&actions);
+    posix_spawnattr_destroy(&attr);
+    pipes->write.close();
+    if (err != 0) {
+        FLOGF(reader, L"Could not run fish_completion_preview: %s", std::strerror(err));
+        return lines;
+    }
+
+    // Read until the preview is done, or has printed all the lines we keep. In that case it is
+    // killed, so a preview which prints forever doesn't keep us waiting.
+    std::string output;
+    char buf[4096];
+    while (static_cast<size_t>(std::count(output.begin(), output.end(), '\n')) <
+           kCompletionPreviewMaxLines) {
+        ssize_t amt = read(pipes->read.fd(), buf, sizeof buf);
+        if (amt < 0 && errno == EINTR) continue;
+        if (amt <= 0) break;
+        output.append(buf, amt);
+    }
+    kill(pid, SIGKILL);
+    while (waitpid(pid, nullptr, 0) < 0 && errno == EINTR) {
+    }
+
+    for (wcstring &line : split_string(str2wcstring(output), L'\n')) {
+        lines.push_back(std::move(line));
+    }
+    // The newline after the last line doesn't start another.
+    if (!lines.empty() && lines.back().empty()) lines.pop_back();
+    if (lines.size() > kCompletionPreviewMaxLines) lines.resize(kCompletionPreviewMaxLines);
+#else
+    UNUSED(definition);
+    UNUSED(args);
+    UNUSED(env);
+#endif
+    return lines;
+}
+
+void reader_data_t::update_completion_preview() {
+    maybe_t<wcstring> candidate = get_completion_preview_candidate();
+    function_properties_ref_t props =
+        candidate ? function_get_props_autoload(L"fish_completion_preview", parser()) : nullptr;
+    if (!props) {
+        pager.set_preview({});
+        return;
+    }
+
+    // Functions can only run on the main thread, so a fish of its own runs the function on the
+    // debounce thread, with the definition and the exported variables we have. That way a slow
+    // preview doesn't block input, and moving through the pager quickly drops the previews for
+    // completions that were only passed over. The old preview stays up until the new one is ready,
+    // so the pager doesn't jump around.
+    const completion_t *selected = pager.selected_completion(current_page_rendering);
+    std::vector<wcstring> args{*candidate};
+    if (!selected->description.empty()) args.push_back(selected->description);
+    wcstring definition = props->annotated_definition(L"fish_completion_preview");
+    auto env = parser().vars().export_arr();
+    std::function<std::vector<wcstring>()> performer = [definition, args, env] {
+        return run_completion_preview(definition, args, env);
+    };
+
+    auto shared_this = this->shared_from_this();
+    wcstring cmdline = command_line.text();
+    std::function<void(const std::vector<wcstring> &)> completion =
+        [shared_this, candidate, cmdline](const std::vector<wcstring> &lines) {
+            reader_data_t *data = shared_this.get();
+            // Drop the preview if another completion was selected or the command line changed.
+            if (data->get_completion_preview_candidate() != candidate ||
+                data->command_line.text() != cmdline) {
+                return;
+            }
+            data->pager.set_preview(lines);
+            data->layout_and_repaint(L"completion-preview");
+        };
+    debounce_perform_with_completion(debounce_completion_preview(), std::move(performer),
+                                     std::move(completion));
 }
 
-/// Expand an abbreviation replacer, which may mean running its function.
-/// \return the replacement, or none to skip it. This may run fish script!
+/// Expand an abbreviation replacer, which may mean running its function. The token is at \p range
+/// in \p cmdline. \return the replacement, or none to skip it. This may run fish script!
 maybe_t<abbrs_replacement_t> expand_replacer(SourceRange range, const wcstring &token,
-                                             const abbrs_replacer_t &repl, parser_t &parser) {
+                                             const wcstring &cmdline, const abbrs_replacer_t &repl,
+                                             parser_t &parser) {
     if (!repl.is_function) {
         // Literal replacement cannot fail.
         FLOGF(abbrs, L"Expanded literal abbreviation <%ls> -> <%ls>", token.c_str(),
@@ -1409,6 +1853,18 @@ maybe_t<abbrs_replacement_t> expand_replacer(SourceRange range, const wcstring &
 
     scoped_push<bool> not_interactive(&parser.libdata().is_interactive, false);
 
+    // The function sees the whole command line with the commandline builtin, with the cursor at
+    // the end of the token, so e.g. `commandline -opc` prints the tokens before it.
+    commandline_state_t saved_state = commandline_get_state();
+    {
+        auto state = commandline_state_snapshot();
+        state->text = cmdline;
+        state->cursor_pos = range.end();
+        state->selection = none();
+        state->initialized = true;
+    }
+    cleanup_t restore_state([&] { *commandline_state_snapshot() = saved_state; });
+
     std::vector<wcstring> outputs{};
     int ret = exec_subshell(cmd, parser, outputs, false /* not apply_exit_status */);
     if (ret != STATUS_CMD_OK) {
@@ -1419,6 +1875,40 @@ maybe_t<abbrs_replacement_t> expand_replacer(SourceRange range, const wcstring &
     return abbrs_replacement_from(range, result, *repl.set_cursor_marker, repl.has_cursor_marker);
 }
 
+/// The results of abbreviation conditions, keyed by their script. Conditions are run at most once
+/// per prompt; this is cleared whenever a new prompt is shown. Main thread only.
+static std::unordered_map<wcstring, bool> &abbr_condition_cache() {
+    ASSERT_IS_MAIN_THREAD();
+    static std::unordered_map<wcstring, bool> cache;
+    return cache;
+}
+
+/// \return the cached result of an abbreviation condition, or none if it has not run this prompt.
+static maybe_t<bool> cached_abbr_condition(const wcstring &condition) {
+    const auto &cache = abbr_condition_cache();
+    auto iter = cache.find(condition);
+    if (iter == cache.end()) return none();
+    return iter->second;
+}
+
+/// \return whether the replacer's condition succeeds, running it if it has not run this prompt.
+/// Replacers without a condition always pass. This may run fish script!
+static bool abbr_condition_passes(const abbrs_replacer_t &repl, parser_t &parser) {
+    if (!repl.has_condition) return true;
+    const wcstring condition = *repl.condition;
+    auto cached = cached_abbr_condition(condition);
+    if (cached.has_value()) return *cached;
+
+    scoped_push<bool> not_interactive(&parser.libdata().is_interactive, false);
+    std::vector<wcstring> outputs{};
+    int ret = exec_subshell(condition, parser, outputs, false /* not apply_exit_status */);
+    bool passes = ret == STATUS_CMD_OK;
+    FLOGF(abbrs, L"Abbreviation condition <%ls> %ls", condition.c_str(),
+          passes ? L"passed" : L"failed");
+    abbr_condition_cache()[condition] = passes;
+    return passes;
+}
+
 // Extract all the token ranges in \p str, along with whether they are an undecorated command.
 // Tokens containing command substitutions are skipped; this ensures tokens are non-overlapping.
 struct positioned_token_t {
@@ -1480,12 +1970,9 @@ static std::vector<positioned_token_t> extract_tokens(const wcstring &str) {
     return result;
 }
 
-/// Expand abbreviations at the given cursor position.
-/// \return the replacement. This does NOT inspect the current reader data.
-maybe_t<abbrs_replacement_t> reader_expand_abbreviation_at_cursor(const wcstring &cmdline,
-                                                                  size_t cursor_pos,
-                                                                  parser_t &parser) {
-    // Find the token containing the cursor. Usually users edit from the end, so walk backwards.
+/// \return the token containing the cursor, which abbreviations would expand.
+static maybe_t<positioned_token_t> token_at_cursor(const wcstring &cmdline, size_t cursor_pos) {
+    // Usually users edit from the end, so walk backwards.
     const auto tokens = extract_tokens(cmdline);
     auto iter = std::find_if(tokens.rbegin(), tokens.rend(), [&](const positioned_token_t &t) {
         return t.range.contains_inclusive(cursor_pos);
@@ -1493,20 +1980,55 @@ maybe_t<abbrs_replacement_t> reader_expand_abbreviation_at_cursor(const wcstring
     if (iter == tokens.rend()) {
         return none();
     }
-    source_range_t range = iter->range;
+    return *iter;
+}
+
+/// Expand abbreviations at the given cursor position.
+/// \return the replacement. This does NOT inspect the current reader data.
+maybe_t<abbrs_replacement_t> reader_expand_abbreviation_at_cursor(const wcstring &cmdline,
+                                                                  size_t cursor_pos,
+                                                                  parser_t &parser) {
+    auto token = token_at_cursor(cmdline, cursor_pos);
+    if (!token) return none();
+    source_range_t range = token->range;
     abbrs_position_t position =
-        iter->is_cmd ? abbrs_position_t::command : abbrs_position_t::anywhere;
+        token->is_cmd ? abbrs_position_t::command : abbrs_position_t::anywhere;
 
     wcstring token_str = cmdline.substr(range.start, range.length);
     auto replacers = abbrs_match(token_str, position);
     for (const auto &replacer : replacers) {
-        if (auto replacement = expand_replacer(range, token_str, replacer, parser)) {
+        if (!abbr_condition_passes(replacer, parser)) continue;
+        if (auto replacement = expand_replacer(range, token_str, cmdline, replacer, parser)) {
             return replacement;
         }
     }
     return none();
 }
 
+/// \return what the abbreviation that ends at \p cursor_pos expands to, with any markers for the
+/// cursor removed. Abbreviations that run a function are not shown, so typing runs no fish script.
+/// For the same reason, conditions are only consulted if they already ran this prompt.
+static maybe_t<wcstring> abbreviation_preview(const wcstring &cmdline, size_t cursor_pos) {
+    auto token = token_at_cursor(cmdline, cursor_pos);
+    if (!token || token->range.end() != cursor_pos) return none();
+    abbrs_position_t position =
+        token->is_cmd ? abbrs_position_t::command : abbrs_position_t::anywhere;
+
+    auto replacers = abbrs_match(cmdline.substr(token->range.start, token->range.length), position);
+    for (const auto &repl : replacers) {
+        if (repl.has_condition) {
+            auto cached = cached_abbr_condition(*repl.condition);
+            if (!cached.has_value()) return none();
+            if (!*cached) continue;
+        }
+        if (repl.is_function) return none();
+        auto replacement = abbrs_replacement_from(token->range, *repl.replacement,
+                                                  *repl.set_cursor_marker, repl.has_cursor_marker);
+        return wcstring(*replacement.text);
+    }
+    return none();
+}
+
 /// Expand abbreviations at the current cursor position, minus the given cursor backtrack. This may
 /// change the command line but does NOT repaint it. This is to allow the caller to coalesce
 /// repaints.
@@ -1526,6 +2048,10 @@ bool reader_data_t::expand_abbreviation_at_cursor(size_t cursor_backtrack) {
             } else {
                 update_buff_pos(el, none());
             }
+            abbr_placeholders.clear();
+            for (size_t pos : replacement->placeholders) {
+                abbr_placeholders.push_back(el->size() - pos);
+            }
             result = true;
         }
     }
@@ -1542,23 +2068,73 @@ int reader_test_and_clear_interrupted() {
     return res;
 }
 
+/// Expand the placeholders in \p format, the value of $fish_title_format: %cwd for the current
+/// directory, %command for the command about to run, %job for the name of the job, %host for the
+/// host name and %% for a percent sign. Unknown placeholders are kept as they are.
+static wcstring expand_title_format(const wcstring &format, const wcstring &cmd,
+                                    const parser_t &parser) {
+    const auto &vars = parser.vars();
+    wcstring result;
+    size_t pos = 0;
+    while (pos < format.size()) {
+        size_t percent = format.find(L'%', pos);
+        result.append(format, pos, percent - pos);
+        if (percent == wcstring::npos) break;
+
+        auto matches = [&](const wchar_t *name) {
+            return format.compare(percent + 1, std::wcslen(name), name) == 0;
+        };
+        if (matches(L"%")) {
+            result.push_back(L'%');
+            pos = percent + 2;
+        } else if (matches(L"cwd")) {
+            auto pwd = vars.get(L"PWD");
+            if (pwd) result.append(replace_home_directory_with_tilde(pwd->as_string(), vars));
+            pos = percent + 4;
+        } else if (matches(L"command")) {
+            result.append(cmd);
+            pos = percent + 8;
+        } else if (matches(L"job")) {
+            // Unlike `status current-command`, this is empty rather than "fish" at the prompt.
+            result.append(parser.libdata().status_vars.command);
+            pos = percent + 4;
+        } else if (matches(L"host")) {
+            auto hostname = vars.get(L"hostname");
+            if (hostname) result.append(hostname->as_string());
+            pos = percent + 5;
+        } else {
+            result.push_back(L'%');
+            pos = percent + 1;
+        }
+    }
+    // A newline in a multi-line command, or a bell, would end the title early.
+    std::replace_if(result.begin(), result.end(), [](wchar_t c) { return c < L' '; }, L' ');
+    return result;
+}
+
 void reader_write_title(const wcstring &cmd, parser_t &parser, bool reset_cursor_position) {
     if (!term_supports_setting_title()) return;
 
     scoped_push<bool> noninteractive{&parser.libdata().is_interactive, false};
     scoped_push<bool> in_title(&parser.libdata().suppress_fish_trace, true);
 
-    wcstring fish_title_command = DEFAULT_TITLE;
-    if (function_exists(L"fish_title", parser)) {
-        fish_title_command = L"fish_title";
-        if (!cmd.empty()) {
-            fish_title_command.append(L" ");
-            fish_title_command.append(escape_string(cmd, ESCAPE_NO_QUOTED | ESCAPE_NO_TILDE));
+    std::vector<wcstring> lst;
+    if (auto format = parser.vars().get_unless_empty(L"fish_title_format")) {
+        // The format takes the place of fish_title, so the common cases don't need a function or
+        // a subshell.
+        wcstring title = expand_title_format(format->as_string(), cmd, parser);
+        if (!title.empty()) lst.push_back(std::move(title));
+    } else {
+        wcstring fish_title_command = DEFAULT_TITLE;
+        if (function_exists(L"fish_title", parser)) {
+            fish_title_command = L"fish_title";
+            if (!cmd.empty()) {
+                fish_title_command.append(L" ");
+                fish_title_command.append(escape_string(cmd, ESCAPE_NO_QUOTED | ESCAPE_NO_TILDE));
+            }
         }
+        (void)exec_subshell(fish_title_command, parser, lst, false /* ignore exit status */);
     }
-
-    std::vector<wcstring> lst;
-    (void)exec_subshell(fish_title_command, parser, lst, false /* ignore exit status */);
     if (!lst.empty()) {
         wcstring title_line = L"\x1B]0;";
         for (const auto &i : lst) {
@@ -1576,6 +2152,30 @@ void reader_write_title(const wcstring &cmd, parser_t &parser, bool reset_cursor
     }
 }
 
+/// Tell terminals with shell integration about the user, host and current directory, so they can
+/// follow it in new tabs and splits.
+static void reader_write_shell_integration(const parser_t &parser) {
+    if (!term_supports_iterm2_reporting()) return;
+    const auto &vars = parser.vars();
+    auto get = [&](const wchar_t *name) {
+        auto var = vars.get(name);
+        return var ? var->as_string() : wcstring{};
+    };
+    wcstring report = format_string(L"\x1B]1337;RemoteHost=%ls@%ls\a\x1B]1337;CurrentDir=%ls\a",
+                                    get(L"USER").c_str(), get(L"hostname").c_str(),
+                                    get(L"PWD").c_str());
+    std::string narrow = wcs2string(report);
+    ignore_result(write_loop(STDOUT_FILENO, narrow.data(), narrow.size()));
+}
+
+/// Mark where the output of a command starts, or where it ends with \p status, for terminals with
+/// shell integration.
+static void reader_write_output_mark(maybe_t<int> status) {
+    if (!term_supports_prompt_marks()) return;
+    std::string mark = status ? "\x1B]133;D;" + std::to_string(*status) + "\a" : "\x1B]133;C\a";
+    ignore_result(write_loop(STDOUT_FILENO, mark.data(), mark.size()));
+}
+
 void reader_data_t::exec_mode_prompt() {
     mode_prompt_buff.clear();
     if (function_exists(MODE_PROMPT_FUNCTION_NAME, parser())) {
@@ -1638,6 +2238,7 @@ void reader_data_t::exec_prompt() {
     // may still be output on the line from the previous command (#2499) and we need our PROMPT_SP
     // hack to work.
     reader_write_title(L"", parser(), false);
+    reader_write_shell_integration(parser());
 
     // Some prompt may have requested an exit (#8033).
     this->exit_loop_requested |= parser().libdata().exit_current_script;
@@ -1777,10 +2378,161 @@ static bool command_ends_history_search(readline_cmd_t c) {
     }
 }
 
+/// Indicates if the given command keeps the extra cursors of a multi-cursor edit. Everything that
+/// isn't typing or deleting characters goes back to a single cursor.
+static bool command_keeps_extra_cursors(readline_cmd_t c) {
+    switch (c) {
+        case readline_cmd_t::backward_delete_char:
+        case readline_cmd_t::delete_char:
+        case readline_cmd_t::expand_abbr:
+        case readline_cmd_t::repaint:
+        case readline_cmd_t::repaint_mode:
+        case readline_cmd_t::force_repaint:
+        case readline_cmd_t::suppress_autosuggestion:
+        case readline_cmd_t::begin_undo_group:
+        case readline_cmd_t::end_undo_group:
+        case readline_cmd_t::func_and:
+        case readline_cmd_t::func_or:
+        case readline_cmd_t::end_selection:
+        case readline_cmd_t::multi_cursor_from_selection:
+            return true;
+        default:
+            return false;
+    }
+}
+
+/// Indicates if the given command keeps the placeholders of an abbreviation that Tab moves to.
+/// Those are the commands used to fill them in.
+static bool command_keeps_abbr_placeholders(readline_cmd_t c) {
+    switch (c) {
+        case readline_cmd_t::complete:
+        case readline_cmd_t::expand_abbr:
+        case readline_cmd_t::self_insert:
+        case readline_cmd_t::self_insert_notfirst:
+        case readline_cmd_t::backward_delete_char:
+        case readline_cmd_t::delete_char:
+        case readline_cmd_t::backward_char:
+        case readline_cmd_t::forward_char:
+        case readline_cmd_t::forward_single_char:
+        case readline_cmd_t::repaint:
+        case readline_cmd_t::repaint_mode:
+        case readline_cmd_t::force_repaint:
+        case readline_cmd_t::suppress_autosuggestion:
+        case readline_cmd_t::begin_undo_group:
+        case readline_cmd_t::end_undo_group:
+        case readline_cmd_t::func_and:
+        case readline_cmd_t::func_or:
+            return true;
+        default:
+            return false;
+    }
+}
+
+/// Put a cursor into the column where the selection starts, on each line the selection spans.
+/// The main cursor goes to the first line, lines that are too short are skipped.
+void reader_data_t::multi_cursor_from_selection() {
+    if (!selection) return;
+    const wcstring &text = command_line.text();
+    auto line_start = [&](size_t pos) {
+        size_t nl = pos == 0 ? wcstring::npos : text.rfind(L'\n', pos - 1);
+        return nl == wcstring::npos ? 0 : nl + 1;
+    };
+
+    size_t first = std::min(selection->begin, command_line.position());
+    size_t last = std::max(selection->begin, command_line.position());
+    size_t column = std::min(selection->begin - line_start(selection->begin),
+                             command_line.position() - line_start(command_line.position()));
+
+    std::vector<size_t> cursors;
+    size_t last_line = line_start(std::min(last, text.size()));
+    for (size_t start = line_start(first); start <= last_line;) {
+        size_t end = text.find(L'\n', start);
+        if (end == wcstring::npos) end = text.size();
+        if (end - start >= column) cursors.push_back(start + column);
+        start = end + 1;
+    }
+
+    selection.reset();
+    if (cursors.empty()) return;
+    update_buff_pos(&command_line, cursors.front());
+    extra_cursors.assign(cursors.begin() + 1, cursors.end());
+}
+
+/// Insert \p str at the main cursor and at each extra cursor, as one undo step.
+void reader_data_t::insert_at_cursors(const wcstring &str) {
+    size_t main_cursor = command_line.position();
+    std::vector<size_t> cursors = extra_cursors;
+    cursors.push_back(main_cursor);
+    std::sort(cursors.begin(), cursors.end());
+
+    // Edit from the back, so the offsets in front stay valid.
+    command_line.begin_edit_group();
+    for (auto iter = cursors.rbegin(); iter != cursors.rend(); ++iter) {
+        command_line.push_edit(edit_t(*iter, 0, str), false /* allow_coalesce */);
+    }
+    command_line.end_edit_group();
+
+    // Each cursor moves past its own insertion and the ones in front of it.
+    extra_cursors.clear();
+    for (size_t i = 0; i < cursors.size(); i++) {
+        size_t pos = cursors.at(i) + (i + 1) * str.size();
+        if (cursors.at(i) == main_cursor) {
+            update_buff_pos(&command_line, pos);
+        } else {
+            extra_cursors.push_back(pos);
+        }
+    }
+    command_line_has_transient_edit = false;
+    suppress_autosuggestion = true;
+}
+
+/// Delete the character before (or after, if not \p backward) the main cursor and each extra
+/// cursor, as one undo step. Newlines are kept, so the cursors stay on their lines.
+void reader_data_t::delete_at_cursors(bool backward) {
+    size_t main_cursor = command_line.position();
+    std::vector<size_t> cursors = extra_cursors;
+    cursors.push_back(main_cursor);
+    std::sort(cursors.begin(), cursors.end());
+
+    const wcstring &text = command_line.text();
+    std::vector<bool> deleted(cursors.size(), false);
+    command_line.begin_edit_group();
+    for (size_t i = cursors.size(); i-- > 0;) {
+        size_t pos = cursors.at(i);
+        if (backward ? pos == 0 : pos >= text.size()) continue;
+        if (backward) pos--;
+        if (text.at(pos) == L'\n') continue;
+        command_line.push_edit(edit_t(pos, 1, L""), false /* allow_coalesce */);
+        deleted.at(i) = true;
+    }
+    command_line.end_edit_group();
+
+    // Each cursor moves back by the characters deleted in front of it.
+    extra_cursors.clear();
+    size_t deleted_before = 0;
+    for (size_t i = 0; i < cursors.size(); i++) {
+        size_t pos = cursors.at(i) - deleted_before;
+        if (deleted.at(i)) {
+            deleted_before++;
+            if (backward) pos--;
+        }
+        if (cursors.at(i) == main_cursor) {
+            update_buff_pos(&command_line, pos);
+        } else {
+            extra_cursors.push_back(pos);
+        }
+    }
+    suppress_autosuggestion = true;
+}
+
 /// Remove the previous character in the character buffer and on the screen using syntax
 /// highlighting, etc.
 void reader_data_t::delete_char(bool backward) {
     editable_line_t *el = active_edit_line();
+    if (el == &command_line && !extra_cursors.empty()) {
+        delete_at_cursors(backward);
+        return;
+    }
 
     size_t pos = el->position();
     if (!backward) {
@@ -1797,7 +2549,9 @@ void reader_data_t::delete_char(bool backward) {
         pos--;
         width = fish_wcwidth(el->text().at(pos));
     } while (width == 0 && pos > 0);
-    erase_substring(el, pos, pos_end - pos);
+    // Deleting one character after another may be undone at once, see $fish_undo_coalesce.
+    el->push_edit(edit_t(pos, pos_end - pos, L""), !history_search.active() /* allow_coalesce */);
+    maybe_refilter_pager(el);
     update_buff_pos(el);
     suppress_autosuggestion = true;
 }
@@ -1806,6 +2560,10 @@ void reader_data_t::delete_char(bool backward) {
 /// using syntax highlighting, etc.
 /// Returns true if the string changed.
 void reader_data_t::insert_string(editable_line_t *el, const wcstring &str) {
+    if (el == &command_line && !extra_cursors.empty()) {
+        if (!str.empty()) insert_at_cursors(str);
+        return;
+    }
     if (!str.empty()) {
         el->push_edit(edit_t(el->position(), 0, str),
                       !history_search.active() /* allow_coalesce */);
@@ -1971,21 +2729,207 @@ void reader_data_t::completion_insert(const wcstring &val, size_t token_end,
     set_buffer_maintaining_pager(new_command_line, cursor);
 }
 
+/// The sources of autosuggestions, see $fish_autosuggestion_providers.
+enum class autosuggestion_provider_t {
+    history,
+    completion,
+    cd,
+    last_argument,
+};
+
+/// A proposed autosuggestion from one provider, and how good it is thought to be, from 0 to 1.
+struct autosuggestion_candidate_t {
+    wcstring text;
+    bool icase;
+    double score;
+};
+
+/// \return the autosuggestion providers in the order of $fish_autosuggestion_providers. Unknown
+/// names are ignored. If the variable is unset, this is history followed by completions.
+static std::vector<autosuggestion_provider_t> get_autosuggestion_providers(
+    const environment_t &vars) {
+    using provider_t = autosuggestion_provider_t;
+    auto var = vars.get(L"fish_autosuggestion_providers");
+    if (!var) return {provider_t::history, provider_t::completion};
+
+    static const std::pair<const wchar_t *, provider_t> names[] = {
+        {L"history", provider_t::history},
+        {L"completion", provider_t::completion},
+        {L"cd", provider_t::cd},
+        {L"last-argument", provider_t::last_argument},
+    };
+    std::vector<provider_t> result;
+    for (const wcstring &name : var->as_list()) {
+        for (const auto &entry : names) {
+            if (name == entry.first && !contains(result, entry.second)) {
+                result.push_back(entry.second);
+            }
+        }
+    }
+    return result;
+}
+
+/// \return the source of the string tokens of the last statement in \p cmdline, if the command
+/// line ends with one, or an empty list if it doesn't.
+static std::vector<wcstring> get_trailing_string_tokens(const wcstring &cmdline) {
+    std::vector<wcstring> result;
+    bool ends_in_string = false;
+    bool after_redirection = false;
+    auto tokenizer = new_tokenizer(cmdline.c_str(), 0);
+    while (auto tok = tokenizer->next()) {
+        ends_in_string = false;
+        if (tok->type_ == token_type_t::string) {
+            // The target of a redirection isn't part of the statement's arguments.
+            if (!after_redirection) {
+                result.push_back(*tok->get_source(cmdline));
+                ends_in_string = tok->offset + tok->length == cmdline.size();
+            }
+            after_redirection = false;
+        } else if (tok->type_ == token_type_t::redirect) {
+            after_redirection = true;
+        } else {
+            result.clear();
+            after_redirection = false;
+        }
+    }
+    if (!ends_in_string) result.clear();
+    return result;
+}
+
+/// Suggest the most recent command from history that starts with the command line. Recent commands
+/// score higher than old ones. With fish_history_scope set to "directory", commands run in the
+/// current directory are preferred.
+static maybe_t<autosuggestion_candidate_t> suggest_from_history(history_t *history,
+                                                                const wcstring &search_string,
+                                                                const wcstring &working_directory,
+                                                                const operation_context_t &ctx) {
+    history_search_t searcher(history, search_string, history_search_type_t::prefix,
+                              history_search_flags_t{});
+    searcher.prefer_cwd(history_scope_cwd(ctx.vars));
+    while (!ctx.check_cancel() &&
+           searcher.go_to_next_match(history_search_direction_t::backward)) {
+        const history_item_t &item = searcher.current_item();
+
+        // Skip items with newlines because they make terrible autosuggestions.
+        if (item.str().find(L'\n') != wcstring::npos) continue;
+
+        if (autosuggest_validate_from_history(item, working_directory, ctx)) {
+            // Even a very old command beats anything a provider further back in the list offers.
+            double age_in_days = std::max(0.0, difftime(time(nullptr), item.timestamp()) / 86400);
+            // History items are case-sensitive, see #3978.
+            return autosuggestion_candidate_t{searcher.current_string(), false /* icase */,
+                                              0.5 + 0.5 / (1 + age_in_days / 30)};
+        }
+    }
+    return none();
+}
+
+/// Suggest the first completion of the command line. A completion scores higher if it is the only
+/// one.
+static maybe_t<autosuggestion_candidate_t> suggest_from_completions(
+    const wcstring &search_string, size_t cursor_pos, const operation_context_t &ctx,
+    std::vector<wcstring> *needs_load) {
+    // Here we do something a little funny. If the line ends with a space, and the cursor is not
+    // at the end, don't use completion autosuggestions. It ends up being pretty weird seeing
+    // stuff get spammed on the right while you go back to edit a line
+    const wchar_t last_char = search_string.at(search_string.size() - 1);
+    const bool cursor_at_end = (cursor_pos == search_string.size());
+    if (!cursor_at_end && iswspace(last_char)) return none();
+
+    // On the other hand, if the line ends with a quote, don't go dumping stuff after the quote.
+    if (std::wcschr(L"'\"", last_char) && cursor_at_end) return none();
+
+    completion_request_options_t complete_flags = completion_request_options_t::autosuggest();
+    completion_list_t completions = complete(search_string, complete_flags, ctx, needs_load);
+    if (completions.empty()) return none();
+
+    completions_sort_and_prioritize(&completions, complete_flags);
+    const completion_t &comp = completions.at(0);
+    size_t cursor = cursor_pos;
+    wcstring text = completion_apply_to_command_line(comp.completion, comp.flags, search_string,
+                                                     &cursor, true /* append only */);
+    // Normal completions are case-insensitive.
+    return autosuggestion_candidate_t{std::move(text), true /* icase */,
+                                      completions.size() == 1 ? 1.0 : 0.75};
+}
+
+/// Suggest a directory for a command line like `cd some/pa`, from the directory the argument is
+/// in. A directory scores higher if it is the only one that matches.
+static maybe_t<autosuggestion_candidate_t> suggest_cd_directory(const wcstring &search_string,
+                                                                size_t cursor_pos,
+                                                                const wcstring &working_directory) {
+    if (cursor_pos != search_string.size()) return none();
+    std::vector<wcstring> tokens = get_trailing_string_tokens(search_string);
+    if (tokens.size() != 2 || tokens.at(0) != L"cd") return none();
+
+    // Only handle plain paths, without quotes, escapes or expansions.
+    const wcstring &arg = tokens.at(1);
+    if (arg.find_first_of(L"\\'\"$~*?{}()[]") != wcstring::npos) return none();
+
+    size_t slash = arg.rfind(L'/');
+    wcstring dir = slash == wcstring::npos ? wcstring{} : arg.substr(0, slash + 1);
+    wcstring base = slash == wcstring::npos ? arg : arg.substr(slash + 1);
+    if (!string_prefixes_string(L"/", dir)) dir.insert(0, working_directory);
+
+    dir_iter_t iter(dir);
+    if (!iter.valid()) return none();
+    maybe_t<wcstring> best;
+    size_t matches = 0;
+    while (const auto *entry = iter.next()) {
+        if (!string_prefixes_string(base, entry->name) || entry->name.size() == base.size()) {
+            continue;
+        }
+        // Hidden directories are only suggested if the argument asks for them.
+        if (entry->name.at(0) == L'.' && base.empty()) continue;
+        if (!entry->is_dir()) continue;
+        matches++;
+        if (!best || entry->name < *best) best = entry->name;
+    }
+    if (!best) return none();
+
+    wcstring text = search_string;
+    text.append(escape_string(best->substr(base.size()), ESCAPE_NO_QUOTED | ESCAPE_NO_TILDE));
+    text.push_back(L'/');
+    return autosuggestion_candidate_t{std::move(text), false /* icase */,
+                                      matches == 1 ? 1.0 : 0.75};
+}
+
+/// Suggest the last argument of the previous command, if the argument being typed is a prefix of
+/// it.
+static maybe_t<autosuggestion_candidate_t> suggest_last_argument(history_t *history,
+                                                                 const wcstring &search_string,
+                                                                 size_t cursor_pos) {
+    if (cursor_pos != search_string.size()) return none();
+    std::vector<wcstring> tokens = get_trailing_string_tokens(search_string);
+    // The command itself is not an argument.
+    if (tokens.size() < 2) return none();
+
+    std::vector<wcstring> previous = get_trailing_string_tokens(history->item_at_index(1).str());
+    if (previous.size() < 2) return none();
+    const wcstring &last_argument = previous.back();
+    const wcstring &arg = tokens.back();
+    if (last_argument.size() <= arg.size() || !string_prefixes_string(arg, last_argument)) {
+        return none();
+    }
+    return autosuggestion_candidate_t{search_string + last_argument.substr(arg.size()),
+                                      false /* icase */, 1.0};
+}
+
 // Returns a function that can be invoked (potentially
 // on a background thread) to determine the autosuggestion
 static std::function<autosuggestion_t(void)> get_autosuggestion_performer(
     parser_t &parser, const wcstring &search_string, size_t cursor_pos,
-    const std::shared_ptr<history_t> &history) {
-    const uint32_t generation_count = read_generation_count();
+    const std::shared_ptr<history_t> &history, const cancel_token_t &token) {
     auto vars = parser.vars().snapshot();
     const wcstring working_directory = vars->get_pwd_slash();
+    const std::vector<autosuggestion_provider_t> providers = get_autosuggestion_providers(*vars);
     // TODO: suspicious use of 'history' here
     // This is safe because histories are immortal, but perhaps
     // this should use shared_ptr
     return [=]() -> autosuggestion_t {
         ASSERT_IS_BACKGROUND_THREAD();
         autosuggestion_t nothing = {};
-        operation_context_t ctx = get_bg_context(vars, generation_count);
+        operation_context_t ctx = get_bg_context(vars, token);
         if (ctx.check_cancel()) {
             return nothing;
         }
@@ -1995,53 +2939,42 @@ static std::function<autosuggestion_t(void)> get_autosuggestion_performer(
             return nothing;
         }
 
-        // Search history for a matching item.
-        history_search_t searcher(history.get(), search_string, history_search_type_t::prefix,
-                                  history_search_flags_t{});
-        while (!ctx.check_cancel() &&
-               searcher.go_to_next_match(history_search_direction_t::backward)) {
-            const history_item_t &item = searcher.current_item();
-
-            // Skip items with newlines because they make terrible autosuggestions.
-            if (item.str().find(L'\n') != wcstring::npos) continue;
-
-            if (autosuggest_validate_from_history(item, working_directory, ctx)) {
-                // The command autosuggestion was handled specially, so we're done.
-                // History items are case-sensitive, see #3978.
-                return autosuggestion_t{searcher.current_string(), search_string,
-                                        false /* icase */};
-            }
-        }
-
-        // Maybe cancel here.
-        if (ctx.check_cancel()) return nothing;
-
-        // Here we do something a little funny. If the line ends with a space, and the cursor is not
-        // at the end, don't use completion autosuggestions. It ends up being pretty weird seeing
-        // stuff get spammed on the right while you go back to edit a line
-        const wchar_t last_char = search_string.at(search_string.size() - 1);
-        const bool cursor_at_end = (cursor_pos == search_string.size());
-        if (!cursor_at_end && iswspace(last_char)) return nothing;
-
-        // On the other hand, if the line ends with a quote, don't go dumping stuff after the quote.
-        if (std::wcschr(L"'\"", last_char) && cursor_at_end) return nothing;
-
-        // Try normal completions.
-        completion_request_options_t complete_flags = completion_request_options_t::autosuggest();
-        std::vector<wcstring> needs_load;
-        completion_list_t completions = complete(search_string, complete_flags, ctx, &needs_load);
-
+        // Ask the providers in order. The score of each one's candidate is weighed by its place in
+        // the list: the first counts fully, the second half, the third a third, and so on. The
+        // candidate with the highest rank wins, the earlier one on a tie.
         autosuggestion_t result{};
         result.search_string = search_string;
-        result.needs_load = std::move(needs_load);
-        result.icase = true;  // normal completions are case-insensitive.
-        if (!completions.empty()) {
-            completions_sort_and_prioritize(&completions, complete_flags);
-            const completion_t &comp = completions.at(0);
-            size_t cursor = cursor_pos;
-            result.text = completion_apply_to_command_line(
-                comp.completion, comp.flags, search_string, &cursor, true /* append only */);
+        double best_rank = 0;
+        for (size_t i = 0; i < providers.size(); i++) {
+            double weight = 1.0 / static_cast<double>(i + 1);
+            // Nothing from here on can beat what we have.
+            if (weight <= best_rank) break;
+            if (ctx.check_cancel()) return nothing;
+
+            maybe_t<autosuggestion_candidate_t> candidate;
+            switch (providers.at(i)) {
+                case autosuggestion_provider_t::history:
+                    candidate =
+                        suggest_from_history(history.get(), search_string, working_directory, ctx);
+                    break;
+                case autosuggestion_provider_t::completion:
+                    candidate = suggest_from_completions(search_string, cursor_pos, ctx,
+                                                         &result.needs_load);
+                    break;
+                case autosuggestion_provider_t::cd:
+                    candidate = suggest_cd_directory(search_string, cursor_pos, working_directory);
+                    break;
+                case autosuggestion_provider_t::last_argument:
+                    candidate = suggest_last_argument(history.get(), search_string, cursor_pos);
+                    break;
+            }
+            if (candidate && candidate->score * weight > best_rank) {
+                best_rank = candidate->score * weight;
+                result.text = std::move(candidate->text);
+                result.icase = candidate->icase;
+            }
         }
+        if (ctx.check_cancel()) return nothing;
         return result;
     };
 }
@@ -2117,7 +3050,8 @@ void reader_data_t::update_autosuggestion() {
     FLOG(reader_render, L"Autosuggesting");
     autosuggestion.clear();
     std::function<autosuggestion_t()> performer =
-        get_autosuggestion_performer(parser(), el.text(), el.position(), history);
+        get_autosuggestion_performer(parser(), el.text(), el.position(), history,
+                                     command_line_token);
     auto shared_this = this->shared_from_this();
     std::function<void(autosuggestion_t)> completion = [shared_this](autosuggestion_t result) {
         shared_this->autosuggest_completed(std::move(result));
@@ -2126,6 +3060,20 @@ void reader_data_t::update_autosuggestion() {
                                      std::move(completion));
 }
 
+/// Show what the abbreviation before the cursor would expand to, while the cursor is at the end of
+/// the command line. $fish_abbr_preview set to 0 turns this off.
+void reader_data_t::update_abbr_preview() {
+    abbr_preview.clear();
+    const editable_line_t &el = command_line;
+    if (!conf.expand_abbrev_ok || el.empty() || el.position() != el.size()) return;
+    if (active_edit_line() != &command_line || history_search.active()) return;
+    auto var = vars().get(L"fish_abbr_preview");
+    if (var && var->as_string() == L"0") return;
+    if (auto preview = abbreviation_preview(el.text(), el.position())) {
+        abbr_preview = preview.acquire();
+    }
+}
+
 // Accept any autosuggestion by replacing the command line with it. If full is true, take the whole
 // thing; if it's false, then respect the passed in style.
 void reader_data_t::accept_autosuggestion(bool full, bool single, move_word_style_t style) {
@@ -2160,6 +3108,7 @@ void reader_data_t::clear_pager() {
     pager.clear();
     history_pager_active = false;
     command_line_has_transient_edit = false;
+    pager_preview_range = none();
 }
 
 void reader_data_t::select_completion_in_direction(selection_motion_t dir,
@@ -2411,6 +3360,7 @@ bool reader_data_t::handle_completions(const completion_list_t &comp, size_t tok
 
     // Update the pager data.
     pager.set_prefix(prefix);
+    pager.set_match_needle(tok);
     pager.set_completions(surviving_completions);
     // Modify the command line to reflect the new pager.
     pager_selection_changed();
@@ -2562,6 +3512,9 @@ static void reader_interactive_init(parser_t &parser) {
     // Ensure interactive signal handling is enabled.
     signal_set_handlers_once(true);
 
+    // Watch for the main thread getting stuck, if asked to.
+    watchdog_start();
+
     // Wait until we own the terminal.
     acquire_tty_or_exit(shell_pgid);
 
@@ -2734,9 +3687,103 @@ void reader_data_t::set_buffer_maintaining_pager(const wcstring &b, size_t pos,
     history_search.reset();
 }
 
+/// With $fish_history_encrypt set to a command, run it whenever it changes, and encrypt the history
+/// with the key that it prints.
+static void update_history_key(parser_t &parser) {
+    ASSERT_IS_MAIN_THREAD();
+    static wcstring last_command;
+    auto var = parser.vars().get(L"fish_history_encrypt");
+    wcstring command = var ? var->as_string() : wcstring{};
+    if (command == last_command) return;
+    last_command = command;
+
+    wcstring key;
+    if (!command.empty()) {
+        std::vector<wcstring> outputs;
+        exec_subshell(command, parser, outputs, false /* apply_exit_status */);
+        if (!outputs.empty()) key = trim(outputs.front());
+    }
+    if (!history_t::set_encryption_key(key) || (key.empty() && !command.empty())) {
+        FLOGF(warning, _(L"fish_history_encrypt did not print a key of 64 hex digits, so history "
+                         L"is not encrypted"));
+    }
+}
+
+/// With $fish_autoload_watch set, pick up edits made since the last check to autoloaded functions
+/// and completions and to conf.d snippets. Changed functions and snippets are sourced again right
+/// away, completions the next time they are used.
+static void reload_changed_config(parser_t &parser) {
+    ASSERT_IS_MAIN_THREAD();
+    // The snippets seen last time, by name. Empty until watching starts; whatever is there then is
+    // taken to be sourced already.
+    static bool watching = false;
+    static std::unordered_map<wcstring, file_id_t> conf_snippets;
+
+    auto watch = parser.vars().get(L"fish_autoload_watch");
+    if (!watch || !bool_from_string(watch->as_string())) {
+        watching = false;
+        conf_snippets.clear();
+        return;
+    }
+
+    for (const wcstring &name : function_reload_changed(parser)) {
+        FLOGF(config, L"Reloaded function '%ls'", name.c_str());
+    }
+    complete_reload_changed();
+
+    // Walk the conf.d directories like config.fish does: the first snippet with a name wins, even
+    // if it is not a readable file.
+    std::vector<wcstring> dirs;
+    if (auto var = parser.vars().get(L"__fish_config_dir")) {
+        dirs.push_back(var->as_string() + L"/conf.d");
+    }
+    if (auto var = parser.vars().get(L"__fish_sysconf_dir")) {
+        dirs.push_back(var->as_string() + L"/conf.d");
+    }
+    if (auto var = parser.vars().get(L"__fish_vendor_confdirs")) {
+        for (const wcstring &dir : var->as_list()) dirs.push_back(dir);
+    }
+
+    std::unordered_map<wcstring, file_id_t> snippets;
+    std::vector<wcstring> to_source;
+    for (const wcstring &dir : dirs) {
+        dir_iter_t iter(dir);
+        if (!iter.valid()) continue;
+        std::vector<wcstring> names;
+        while (const auto *entry = iter.next()) {
+            if (string_suffixes_string(L".fish", entry->name)) names.push_back(entry->name);
+        }
+        std::sort(names.begin(), names.end());
+
+        for (const wcstring &name : names) {
+            if (snippets.count(name) > 0) continue;
+            wcstring path = dir + L"/" + name;
+            struct stat buf;
+            if (wstat(path, &buf) != 0 || !S_ISREG(buf.st_mode) || waccess(path, R_OK) != 0) {
+                snippets[name] = kInvalidFileID;
+                continue;
+            }
+            file_id_t file_id = file_id_t::from_stat(buf);
+            snippets[name] = file_id;
+            auto prev = conf_snippets.find(name);
+            if (watching && (prev == conf_snippets.end() || prev->second != file_id)) {
+                to_source.push_back(std::move(path));
+            }
+        }
+    }
+    conf_snippets = std::move(snippets);
+    watching = true;
+
+    for (const wcstring &path : to_source) {
+        FLOGF(config, L"Sourcing changed snippet '%ls'", path.c_str());
+        autoload_t::perform_autoload(path, parser);
+    }
+}
+
 /// Run the specified command with the correct terminal modes, and while taking care to perform job
-/// notification, set the title, etc.
-static eval_res_t reader_run_command(parser_t &parser, const wcstring &cmd) {
+/// notification, set the title, etc. The time it took is stored in \p out_duration_ms.
+static eval_res_t reader_run_command(parser_t &parser, const wcstring &cmd,
+                                     uint64_t *out_duration_ms) {
     wcstring ft = *tok_command(cmd);
 
     // Provide values for `status current-command` and `status current-commandline`
@@ -2747,22 +3794,33 @@ static eval_res_t reader_run_command(parser_t &parser, const wcstring &cmd) {
         parser.vars().set_one(L"_", ENV_GLOBAL, ft);
     }
 
+    // Leave the command out of crash reports if history wouldn't save it.
+    bool redact = (!cmd.empty() && cmd.front() == L' ') || in_private_mode(parser.vars()) ||
+                  history_ignore_mode(cmd, parser.vars()).has_value();
+    auto term = parser.vars().get(L"TERM");
+    crash_handler_set_state(cmd, redact, term ? term->as_string() : wcstring{});
+
     outputter_t &outp = outputter_t::stdoutput();
     reader_write_title(cmd, parser);
     outp.set_color(rgb_color_t::normal(), rgb_color_t::normal());
+    reader_write_output_mark(none());
     term_donate();
 
-    timepoint_t time_before = timef();
+    // This includes the time for expansions and redirections, which are done by the eval.
+    uint64_t usec_before = timer_monotonic_usec();
     auto eval_res = parser.eval(cmd, io_chain_t{});
     job_reap(parser, true);
+    reader_write_output_mark(parser.get_last_status());
+
+    uint64_t duration_us = timer_monotonic_usec() - usec_before;
+    uint64_t duration_ms = (duration_us + 500) / 1000;
+    *out_duration_ms = duration_ms;
 
     // Update the execution duration iff a command is requested for execution
     // issue - #4926
     if (!ft.empty()) {
-        timepoint_t time_after = timef();
-        double duration = time_after - time_before;
-        long duration_ms = std::round(duration * 1000);
         parser.vars().set_one(ENV_CMD_DURATION, ENV_UNEXPORT, to_string(duration_ms));
+        parser.vars().set_one(ENV_CMD_DURATION_US, ENV_UNEXPORT, to_string(duration_us));
     }
 
     term_steal();
@@ -2815,14 +3873,12 @@ void reader_data_t::highlight_complete(highlight_result_t result) {
 
 // Given text and  whether IO is allowed, return a function that performs highlighting. The function
 // may be invoked on a background thread.
-static std::function<highlight_result_t(void)> get_highlight_performer(parser_t &parser,
-                                                                       const editable_line_t &el,
-                                                                       bool io_ok) {
+static std::function<highlight_result_t(void)> get_highlight_performer(
+    parser_t &parser, const editable_line_t &el, bool io_ok, const cancel_token_t &token) {
     auto vars = parser.vars().snapshot();
-    uint32_t generation_count = read_generation_count();
     return [=]() -> highlight_result_t {
         if (el.text().empty()) return {};
-        operation_context_t ctx = get_bg_context(vars, generation_count);
+        operation_context_t ctx = get_bg_context(vars, token);
         std::vector<highlight_spec_t> colors(el.text().size(), highlight_spec_t{});
         highlight_shell(el.text(), colors, ctx, io_ok, el.position());
         return highlight_result_t{std::move(colors), el.text()};
@@ -2840,7 +3896,7 @@ void reader_data_t::super_highlight_me_plenty() {
 
     FLOG(reader_render, L"Highlighting");
     std::function<highlight_result_t()> highlight_performer =
-        get_highlight_performer(parser(), *el, true /* io_ok */);
+        get_highlight_performer(parser(), *el, true /* io_ok */, command_line_token);
     auto shared_this = this->shared_from_this();
     std::function<void(highlight_result_t)> completion = [shared_this](highlight_result_t result) {
         shared_this->highlight_complete(std::move(result));
@@ -2885,7 +3941,8 @@ void reader_data_t::finish_highlighting_before_exec() {
     if (!current_highlight_ok) {
         // We need to do a quick highlight without I/O.
         auto highlight_no_io =
-            get_highlight_performer(parser(), command_line, false /* io not ok */);
+            get_highlight_performer(parser(), command_line, false /* io not ok */,
+                                    command_line_token);
         this->highlight_complete(highlight_no_io());
     }
 }
@@ -2978,6 +4035,8 @@ static std::shared_ptr<reader_data_t> reader_push_ret(parser_t &parser,
 
 /// Public variant which discards the return value.
 void reader_push(parser_t &parser, const wcstring &history_name, reader_config_t &&conf) {
+    // Non-interactive shells, e.g. one running `read`, set up key bindings here.
+    init_input();
     (void)reader_push_ret(parser, history_name, std::move(conf));
 }
 
@@ -3018,12 +4077,22 @@ void reader_data_t::import_history_if_necessary() {
     }
 }
 
+/// What to do with running background jobs on exit, per $fish_exit_job_policy.
+static exit_job_policy_t s_exit_job_policy = exit_job_policy_t::prompt;
+
+void reader_change_exit_job_policy(uint8_t policy) {
+    ASSERT_IS_MAIN_THREAD();
+    s_exit_job_policy = static_cast<exit_job_policy_t>(policy);
+}
+
 /// Check if we have background jobs that we have not warned about.
 /// If so, print a warning and return true. Otherwise return false.
 static bool try_warn_on_background_jobs(reader_data_t *data) {
     ASSERT_IS_MAIN_THREAD();
     // Have we already warned?
     if (data->did_warn_for_bg_jobs) return false;
+    // Are we supposed to warn?
+    if (s_exit_job_policy != exit_job_policy_t::prompt) return false;
     // Are we the top-level reader?
     if (reader_data_stack.size() > 1) return false;
     // Do we have background jobs?
@@ -3074,15 +4143,38 @@ void reader_data_t::update_commandline_state() const {
 }
 
 void reader_data_t::apply_commandline_state_changes() {
-    // Only the text and cursor position may be changed.
+    // Only the text, cursor position and selection may be changed.
     commandline_state_t state = *commandline_state_snapshot();
+    // Compare the selection before touching the text, because moving the cursor moves it too.
+    auto current = this->get_selection();
+    bool selection_changed =
+        state.selection && (!current || current->start != state.selection->start ||
+                            current->length != state.selection->length);
     if (state.text != this->command_line.text() ||
         state.cursor_pos != this->command_line.position()) {
         // The commandline builtin changed our contents.
         this->clear_pager();
+        this->extra_cursors.clear();
         this->set_buffer_maintaining_pager(state.text, state.cursor_pos);
         this->reset_loop_state = true;
     }
+    if (selection_changed) {
+        // The commandline builtin moved the selection.
+        select_range(state.selection->start, state.selection->end());
+        this->reset_loop_state = true;
+    }
+}
+
+/// Select the command line from \p start up to \p end. The selection is anchored at the start and
+/// the cursor goes to the end, so that moving the cursor afterwards extends it as usual.
+void reader_data_t::select_range(size_t start, size_t end) {
+    start = std::min(start, command_line.size());
+    end = std::min(end, command_line.size());
+    size_t pos = end;
+    if (cursor_selection_mode == cursor_selection_mode_t::inclusive && pos > start) pos--;
+    selection = selection_data_t{};
+    selection->begin = start;
+    update_buff_pos(&command_line, pos);
 }
 
 expand_result_t::result_t reader_data_t::try_expand_wildcard(wcstring wc, size_t position,
@@ -3257,20 +4349,36 @@ static int read_i(parser_t &parser) {
         reader_push_ret(parser, history_session_id(parser.vars()), std::move(conf));
     data->import_history_if_necessary();
 
+    // Ask the terminal what it supports, and hand what the user typed meanwhile to the reader.
+    rust::Vec<uint8_t> typeahead = terminal_probe_ffi();
+    for (wchar_t c : str2wcstring(reinterpret_cast<const char *>(typeahead.data()),
+                                  typeahead.size())) {
+        data->inputter.queue_char(c);
+    }
+    // Tell the terminal about the directory we started in, which cd hasn't.
+    report_cwd_ffi();
+
     while (!check_exit_loop_maybe_warning(data.get())) {
         ++run_count;
+        reload_changed_config(parser);
+        update_history_key(parser);
 
         if (maybe_t<wcstring> mcmd = data->readline(0)) {
             const wcstring command = mcmd.acquire();
             if (command.empty()) {
                 continue;
             }
+            // Files may well have been edited while the prompt was up.
+            reload_changed_config(parser);
 
             data->update_buff_pos(&data->command_line, 0);
             data->command_line.clear();
+            data->checkpoints.clear();
             data->command_line_changed(&data->command_line);
             event_fire_generic(parser, L"fish_preexec", {command});
-            auto eval_res = reader_run_command(parser, command);
+            uint64_t duration_ms;
+            auto eval_res = reader_run_command(parser, command, &duration_ms);
+            int exit_status = eval_res.no_status ? -1 : parser.get_last_status();
             signal_clear_cancel();
             if (!eval_res.no_status) {
                 ++status_count;
@@ -3281,9 +4389,10 @@ static int read_i(parser_t &parser) {
             parser.libdata().exit_current_script = false;
 
             event_fire_generic(parser, L"fish_postexec", {command});
-            // Allow any pending history items to be returned in the history array.
+            // Allow any pending history items to be returned in the history array, and record how
+            // the command ended.
             if (data->history) {
-                data->history->resolve_pending();
+                data->history->finish_pending(exit_status, static_cast<int64_t>(duration_ms));
             }
 
             bool already_warned = data->did_warn_for_bg_jobs;
@@ -3315,7 +4424,22 @@ static int read_i(parser_t &parser) {
         s_exit_state = exit_state_t::running_handlers;
         event_fire_generic(parser, L"fish_exit");
         s_exit_state = exit_state_t::finished_handlers;
-        hup_jobs(parser.jobs());
+
+        // Tell the user what becomes of the remaining jobs, unless they were already warned or
+        // the terminal is gone.
+        exit_job_policy_t policy = s_exit_job_policy;
+        auto bg_jobs = jobs_requiring_warning_on_exit(parser);
+        if (!bg_jobs.empty() && policy != exit_job_policy_t::prompt && !s_sighup_received) {
+            print_jobs_left_on_exit(bg_jobs, policy == exit_job_policy_t::kill);
+        }
+        if (policy == exit_job_policy_t::disown) {
+            // Stopped jobs would be hung up by the kernel once fish is gone, so continue them.
+            for (const auto &j : bg_jobs) {
+                if (j->is_stopped()) j->signal(SIGCONT);
+            }
+        } else {
+            hup_jobs(parser.jobs());
+        }
     }
 
     return 0;
@@ -3367,6 +4491,9 @@ static bool event_is_normal_char(const char_event_t &evt) {
 /// Run a sequence of commands from an input binding.
 void reader_data_t::run_input_command_scripts(const std::vector<wcstring> &cmds) {
     auto last_statuses = parser().get_last_statuses();
+    // The commands may hand the terminal to a program, like vim or fzf, which wouldn't understand
+    // keys reported with the kitty keyboard protocol.
+    term_set_kitty_keyboard(false);
     for (const wcstring &cmd : cmds) {
         update_commandline_state();
         parser().eval(cmd, io_chain_t{});
@@ -3386,9 +4513,55 @@ void reader_data_t::run_input_command_scripts(const std::vector<wcstring> &cmds)
     if (res < 0) {
         wperror(L"tcsetattr");
     }
+    term_set_kitty_keyboard(s_kitty_keyboard_wanted);
     termsize_invalidate_tty();
 }
 
+/// Insert text pasted with bracketed paste. If the user defined fish_paste_filter, it gets the text
+/// as its argument and what it prints is inserted instead, or nothing if it fails.
+void reader_data_t::insert_paste(wcstring text) {
+    // Terminals send line breaks as \r, which would put the cursor back instead.
+    std::replace(text.begin(), text.end(), L'\r', L'\n');
+
+    if (function_exists(L"fish_paste_filter", parser())) {
+        // Let the filter look at the commandline with `commandline`.
+        update_commandline_state();
+        scoped_push<bool> noninteractive{&parser().libdata().is_interactive, false};
+        auto last_statuses = parser().get_last_statuses();
+        std::vector<wcstring> lines;
+        int status = exec_subshell(L"fish_paste_filter " + escape_string(text), parser(), lines,
+                                   false /* don't apply exit status */);
+        parser().set_last_statuses(std::move(last_statuses));
+        apply_commandline_state_changes();
+        if (status != STATUS_CMD_OK) return;
+        text = join_strings(lines, L'\n');
+    }
+
+    editable_line_t *el = active_edit_line();
+    if (el == &command_line && parse_util_get_quote_type(el->text(), el->position()) == L'\'') {
+        // Pasting into a single-quoted token, like when quoting markdown or a commit message. Keep
+        // it one literal token by escaping the quotes and backslashes.
+        wcstring escaped;
+        for (wchar_t c : text) {
+            if (c == L'\'' || c == L'\\') escaped.push_back(L'\\');
+            escaped.push_back(c);
+        }
+        text = std::move(escaped);
+    } else if (el->position() == 0) {
+        // Leading spaces would keep the command out of the history.
+        text.erase(0, text.find_first_not_of(L' '));
+    }
+    if (text.empty()) return;
+
+    // A paste is a single edit, which is undone at once.
+    push_edit(el, edit_t(el->position(), 0, std::move(text)));
+    if (el == &command_line) {
+        command_line_has_transient_edit = false;
+        suppress_autosuggestion = false;
+        clear_pager();
+    }
+}
+
 /// Read normal characters, inserting them into the command line.
 /// \return the next unhandled event.
 maybe_t<char_event_t> reader_data_t::read_normal_chars(readline_loop_state_t &rls) {
@@ -3560,6 +4733,17 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
         case rl::complete:
         case rl::complete_and_search: {
             if (!conf.complete_ok) break;
+            // After an abbreviation with placeholders, Tab moves to the next one.
+            if (c == rl::complete && !abbr_placeholders.empty() &&
+                !is_navigating_pager_contents()) {
+                size_t distance = abbr_placeholders.front();
+                abbr_placeholders.erase(abbr_placeholders.begin());
+                if (distance <= command_line.size()) {
+                    update_buff_pos(&command_line, command_line.size() - distance);
+                    break;
+                }
+                abbr_placeholders.clear();
+            }
             if (is_navigating_pager_contents() ||
                 (!rls.comp.empty() && !rls.complete_did_insert && rls.last_cmd == rl::complete)) {
                 // The user typed complete more than once in a row. If we are not yet fully
@@ -3673,9 +4857,16 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
             break;
         }
         case rl::yank: {
-            wcstring yank_str = kill_yank();
+            wcstring reg = kill_register();
+            wcstring yank_str = reg.empty() ? kill_yank() : read_register(reg);
             insert_string(active_edit_line(), yank_str);
-            rls.yank_len = yank_str.size();
+            // Only the kill ring can be cycled with yank-pop.
+            rls.yank_len = reg.empty() ? yank_str.size() : 0;
+            break;
+        }
+        case rl::use_register: {
+            pending_register = wcstring(1, inputter.function_pop_arg());
+            pending_register_mapping = inputter.mapping_count();
             break;
         }
         case rl::yank_pop: {
@@ -3742,6 +4933,7 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
             bool was_active_before = history_search.active();
 
             if (history_search.is_at_end()) {
+                maybe_sync_history(*history, vars());
                 const editable_line_t *el = &command_line;
                 if (mode == reader_history_search_t::token) {
                     // Searching by token.
@@ -3751,14 +4943,15 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
                     if (begin) {
                         wcstring token(begin, end);
                         history_search.reset_to_mode(token, history, reader_history_search_t::token,
-                                                     begin - buff);
+                                                     begin - buff, history_scope_cwd(vars()));
                     } else {
                         // No current token, refuse to do a token search.
                         history_search.reset();
                     }
                 } else {
                     // Searching by line.
-                    history_search.reset_to_mode(el->text(), history, mode, 0);
+                    history_search.reset_to_mode(el->text(), history, mode, 0,
+                                                 history_scope_cwd(vars()));
 
                     // Skip the autosuggestion in the history unless it was truncated.
                     const wcstring &suggest = autosuggestion.text;
@@ -4164,6 +5357,11 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
             break;
         }
 
+        case rl::multi_cursor_from_selection: {
+            multi_cursor_from_selection();
+            break;
+        }
+
         case rl::swap_selection_start_stop: {
             if (!selection) break;
             size_t tmp = selection->begin;
@@ -4218,6 +5416,24 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
             inputter.function_set_status(success);
             break;
         }
+        case rl::select_inner_object:
+        case rl::select_around_object: {
+            wchar_t object = inputter.function_pop_arg();
+            auto range = text_object_range(command_line.text(), command_line.position(),
+                                           static_cast<uint32_t>(object),
+                                           c == rl::select_around_object);
+            bool success = range.found && active_edit_line() == &command_line;
+            if (success && range.end > range.start) {
+                select_range(range.start, range.end);
+            } else if (success) {
+                // There is nothing in between, like in "()". Leave the cursor there, so changing
+                // the object inserts at the right place.
+                selection.reset();
+                update_buff_pos(&command_line, range.start);
+            }
+            inputter.function_set_status(success);
+            break;
+        }
         case rl::repeat_jump: {
             editable_line_t *el = active_edit_line();
             bool success = false;
@@ -4253,6 +5469,8 @@ void reader_data_t::handle_readline_command(readline_cmd_t c, readline_loop_stat
 
         case rl::expand_abbr: {
             if (expand_abbreviation_at_cursor(1)) {
+                // The extra cursors don't know about the expansion.
+                extra_cursors.clear();
                 inputter.function_set_status(true);
             } else {
                 inputter.function_set_status(false);
@@ -4323,6 +5541,9 @@ void reader_data_t::add_to_history() const {
         } else if (in_private_mode(this->vars())) {
             // Private mode means in-memory only.
             mode = history_persistence_mode_t::memory;
+        } else if (auto ignored = history_ignore_mode(text, this->vars())) {
+            // Commands that match $fish_history_ignore are kept off the disk.
+            mode = *ignored;
         } else {
             mode = history_persistence_mode_t::disk;
         }
@@ -4456,6 +5677,23 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
         }
     }
 
+    // Have the terminal report keys like ctrl-i and shift-enter so they can be told apart from Tab
+    // and Enter. This is undone whenever a command gets the terminal, and when we return.
+    scoped_push<bool> want_kitty_keyboard(&s_kitty_keyboard_wanted, true);
+    term_set_kitty_keyboard(true);
+
+    // With fish_history_sync, pick up the commands that other sessions ran while we ran ours, and
+    // watch for the ones they run while we wait for input. A nested reader, like that of `read`,
+    // watches its own history until it returns.
+    history_watcher_t &watcher = history_watcher_t::default_watcher();
+    std::shared_ptr<history_t> outer_watched = watcher.history();
+    bool sync = history && history_sync_enabled(vars());
+    if (sync) history->sync_external_changes();
+    watcher.watch(sync ? history : nullptr);
+    cleanup_t restore_watch([&] {
+        if (outer_watched) watcher.watch(outer_watched);
+    });
+
     // HACK: Don't abandon line for the first prompt, because
     // if we're started with the terminal it might not have settled,
     // so the width is quite likely to be in flight.
@@ -4475,10 +5713,14 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
     }
     exec_prompt();
 
+    // Abbreviation conditions may depend on state the last command changed.
+    abbr_condition_cache().clear();
+
     /// A helper that kicks off syntax highlighting, autosuggestion computing, and repaints.
     auto color_suggest_repaint_now = [this] {
         if (conf.in == STDIN_FILENO) {
             this->update_autosuggestion();
+            this->update_abbr_preview();
             this->super_highlight_me_plenty();
         }
         if (this->is_repaint_needed()) this->layout_and_repaint(L"toplevel");
@@ -4487,6 +5729,7 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
 
     // Start out as initially dirty.
     force_exec_prompt_and_repaint = true;
+    prompt_displayed = true;
 
     while (!rls.finished && !check_exit_loop_maybe_warning(this)) {
         if (reset_loop_state) {
@@ -4528,8 +5771,9 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
             reader_sighup();
             continue;
         }
-        assert((event_needing_handling->is_char() || event_needing_handling->is_readline()) &&
-               "Should have a char or readline");
+        assert((event_needing_handling->is_char() || event_needing_handling->is_readline() ||
+                event_needing_handling->is_paste()) &&
+               "Should have a char, readline or paste");
 
         if (rls.last_cmd != rl::yank && rls.last_cmd != rl::yank_pop) {
             rls.yank_len = 0;
@@ -4548,6 +5792,9 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
                 clear_pager();
             }
 
+            if (!command_keeps_extra_cursors(readline_cmd)) extra_cursors.clear();
+            if (!command_keeps_abbr_placeholders(readline_cmd)) abbr_placeholders.clear();
+
             handle_readline_command(readline_cmd, rls);
 
             if (history_search.active() && command_ends_history_search(readline_cmd)) {
@@ -4562,6 +5809,10 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
             }
 
             rls.last_cmd = readline_cmd;
+        } else if (event_needing_handling->is_paste()) {
+            extra_cursors.clear();
+            insert_paste(event_needing_handling->get_paste());
+            rls.last_cmd = none();
         } else {
             // Ordinary char.
             wchar_t c = event_needing_handling->get_char();
@@ -4587,6 +5838,8 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
         }
     }
 
+    prompt_displayed = false;
+
     // Redraw the command line. This is what ensures the autosuggestion is hidden, etc. after the
     // user presses enter.
     if (this->is_repaint_needed() || conf.in != STDIN_FILENO)
@@ -4617,6 +5870,8 @@ maybe_t<wcstring> reader_data_t::readline(int nchars_or_0) {
         clear_pager();
     }
 
+    term_set_kitty_keyboard(false);
+
     if (s_exit_state != exit_state_t::finished_handlers) {
         // The order of the two conditions below is important. Try to restore the mode
         // in all cases, but only complain if interactive.
@@ -4698,6 +5953,37 @@ void reader_schedule_prompt_repaint() {
     }
 }
 
+void reader_write_above_prompt(const wcstring &text) {
+    ASSERT_IS_MAIN_THREAD();
+    reader_data_t *data = current_data_or_null();
+    if (!data || !data->prompt_displayed) {
+        // Nothing to make room for.
+        outputter_t &outp = outputter_t::stdoutput();
+        outp.writestr(text);
+        if (text.empty() || text.back() != L'\n') outp.push_back('\n');
+        return;
+    }
+    data->screen.write_above_prompt(text);
+    reader_schedule_prompt_repaint();
+}
+
+bool commandline_set_checkpoint(const wcstring &name, commandline_checkpoint_t checkpoint) {
+    ASSERT_IS_MAIN_THREAD();
+    reader_data_t *data = current_data_or_null();
+    if (!data) return false;
+    data->checkpoints[name] = std::move(checkpoint);
+    return true;
+}
+
+maybe_t<commandline_checkpoint_t> commandline_get_checkpoint(const wcstring &name) {
+    ASSERT_IS_MAIN_THREAD();
+    reader_data_t *data = current_data_or_null();
+    if (!data) return none();
+    auto iter = data->checkpoints.find(name);
+    if (iter == data->checkpoints.end()) return none();
+    return iter->second;
+}
+
 void reader_handle_command(readline_cmd_t cmd) {
     if (reader_data_t *data = current_data_or_null()) {
         readline_loop_state_t rls{};
@@ -4770,12 +6056,26 @@ static int read_ni(parser_t &parser, int fd, const io_chain_t &io) {
         str.erase(0, 1);
     }
 
-    // Parse into an ast and detect errors.
+    // Sourced files are parsed once and then taken from the ast cache, until they change.
+    filename_ref_t filename = parser.libdata().current_filename;
+    bool cacheable = filename && *filename != L"-" && S_ISREG(buf.st_mode);
+    bool cached = false;
     auto errors = new_parse_error_list();
-    auto ast = ast_parse(str, parse_flag_none, &*errors);
-    bool errored = ast->errored();
-    if (!errored) {
-        errored = parse_util_detect_errors(*ast, str, &*errors);
+    auto ast = cacheable ? ast_cache_load(fd, *filename, str, cached)
+                         : ast_parse(str, parse_flag_none, &*errors);
+    if (cacheable && !cached) {
+        ast = ast_parse(str, parse_flag_none, &*errors);
+    }
+    bool errored = false;
+    if (!cached) {
+        // Detect errors, the cache only has asts without any.
+        errored = ast->errored();
+        if (!errored) {
+            errored = parse_util_detect_errors(*ast, str, &*errors);
+        }
+        if (!errored && cacheable) {
+            ast_cache_store(fd, *filename, str, *ast);
+        }
     }
     if (!errored) {
         // Construct a parsed source ref.
@@ -123,6 +123,9 @@ class editable_line_t {
     /// Whether we want to append this string to the previous edit.
     bool want_to_coalesce_insertion_of(const wcstring &str) const;
 
+    /// Whether we want to merge this deletion with the previous edit.
+    bool want_to_coalesce_deletion(const edit_t &edit) const;
+
     /// The command line.
     wcstring text_;
     /// Syntax highlighting.
@@ -174,6 +177,34 @@ void reader_change_cursor_selection_mode(cursor_selection_mode_t selection_mode)
 void reader_change_cursor_selection_mode(uint8_t selection_mode);
 #endif
 
+/// The kinds of edits that are merged with the previous edit of the same kind, so they are undone
+/// at once. This is configured with $fish_undo_coalesce.
+enum undo_coalesce_t : uint8_t {
+    /// Characters typed one after another, up to a space.
+    undo_coalesce_insert = 1 << 0,
+    /// Typed characters across spaces, so a line typed in one go is undone at once.
+    undo_coalesce_words = 1 << 1,
+    /// Characters deleted one after another with backward-delete-char or delete-char.
+    undo_coalesce_delete = 1 << 2,
+};
+
+/// Change which kinds of edits are undone together, as a combination of undo_coalesce_t.
+void reader_change_undo_coalesce(uint8_t kinds);
+
+/// What to do with running background jobs when an interactive fish exits. This is configured with
+/// $fish_exit_job_policy.
+enum class exit_job_policy_t : uint8_t {
+    /// Warn on the first attempt to exit, and hang up the jobs on the second.
+    prompt,
+    /// Hang up the jobs right away.
+    kill,
+    /// Leave the jobs running.
+    disown,
+};
+
+/// Change what happens to running background jobs on exit, as an exit_job_policy_t.
+void reader_change_exit_job_policy(uint8_t policy);
+
 struct EnvDyn;
 /// Enable or disable autosuggestions based on the associated variable.
 void reader_set_autosuggestion_enabled(const env_stack_t &vars);
@@ -191,6 +222,11 @@ void reader_write_title(const wcstring &cmd, parser_t &parser, bool reset_cursor
 /// This may be called in response to e.g. a color variable change.
 void reader_schedule_prompt_repaint();
 
+/// Print \p text on the terminal. If the prompt is displayed, the text goes where the prompt was
+/// and the prompt is repainted below it, so e.g. an event handler can report something without
+/// garbling the command line.
+void reader_write_above_prompt(const wcstring &text);
+
 /// Enqueue an event to the back of the reader's input queue.
 class char_event_t;
 void reader_queue_ch(const char_event_t &ch);
@@ -303,10 +339,28 @@ struct commandline_state_t {
 /// Get the command line state. This may be fetched on a background thread.
 commandline_state_t commandline_get_state();
 
+/// A command line saved with `commandline --checkpoint`.
+struct commandline_checkpoint_t {
+    wcstring text;
+    size_t cursor_pos{0};
+};
+
+/// Save a command line as the checkpoint called \p name, replacing any checkpoint of that name.
+/// Checkpoints belong to the command line being edited and are forgotten when it is executed.
+/// \return false if no command line is being edited.
+bool commandline_set_checkpoint(const wcstring &name, commandline_checkpoint_t checkpoint);
+
+/// \return the checkpoint called \p name, or none if there is none.
+maybe_t<commandline_checkpoint_t> commandline_get_checkpoint(const wcstring &name);
+
 /// Set the command line text and position. This may be called on a background thread; the reader
 /// will pick it up when it is done executing.
 void commandline_set_buffer(wcstring text, size_t cursor_pos = -1);
 
+/// Set the visual selection to the range from \p start up to \p end. Like commandline_set_buffer,
+/// this may be called on a background thread and is picked up by the reader later.
+void commandline_set_selection(size_t start, size_t end);
+
 /// Return the current interactive reads loop count. Useful for determining how many commands have
 /// been executed between invocations of code.
 uint64_t reader_run_count();
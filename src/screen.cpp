@@ -16,32 +16,23 @@
 #include <cstring>
 #include <cwchar>
 
-#if HAVE_CURSES_H
-#include <curses.h>  // IWYU pragma: keep
-#elif HAVE_NCURSES_H
-#include <ncurses.h>
-#elif HAVE_NCURSES_CURSES_H
-#include <ncurses/curses.h>
-#endif
-#if HAVE_TERM_H
-#include <term.h>
-#elif HAVE_NCURSES_TERM_H
-#include <ncurses/term.h>
-#endif
-
 #include <algorithm>
 #include <string>
 #include <vector>
 
+#include "bidi.rs.h"
 #include "common.h"
 #include "env.h"
+#include "env_dispatch.rs.h"
 #include "fallback.h"  // IWYU pragma: keep
 #include "flog.h"
 #include "global_safety.h"
 #include "highlight.h"
 #include "output.h"
+#include "output.rs.h"
 #include "pager.h"
 #include "screen.h"
+#include "terminal_probe.rs.h"
 #include "termsize.h"
 
 /// The number of characters to indent new blocks.
@@ -57,6 +48,23 @@ class scoped_buffer_t : noncopyable_t, nonmovable_t {
 
     ~scoped_buffer_t() { outp_.end_buffering(); }
 };
+
+/// RAII class to have the terminal show everything written in between at once, with synchronized
+/// output (mode 2026), if the terminal supports it. This avoids flicker when redrawing.
+class scoped_synchronized_output_t : noncopyable_t, nonmovable_t {
+    outputter_t &outp_;
+    bool enabled_;
+
+   public:
+    explicit scoped_synchronized_output_t(outputter_t &outp)
+        : outp_(outp), enabled_(term_supports_synchronized_output()) {
+        if (enabled_) outp_.writestr("\x1B[?2026h");
+    }
+
+    ~scoped_synchronized_output_t() {
+        if (enabled_) outp_.writestr("\x1B[?2026l");
+    }
+};
 }  // namespace
 
 // Singleton of the cached escape sequences seen in prompts and similar strings.
@@ -86,6 +94,7 @@ void screen_set_midnight_commander_hack() { midnight_commander_hack = true; }
 /// Returns the number of columns left until the next tab stop, given the current cursor position.
 static size_t next_tab_stop(size_t current_line_width) {
     // Assume tab stops every 8 characters if undefined.
+    int init_tabs = term_get_number_ffi("it");
     size_t tab_width = init_tabs > 0 ? static_cast<size_t>(init_tabs) : 8;
     return ((current_line_width / tab_width) + 1) * tab_width;
 }
@@ -106,7 +115,7 @@ int line_t::wcswidth_min_0(size_t max) const {
 /// physical line on a wrapped logical line; instead we just output it.
 static bool allow_soft_wrap() {
     // Should we be looking at eat_newline_glitch as well?
-    return auto_right_margin;
+    return term_get_flag_ffi("am");
 }
 
 /// Does this look like the escape sequence for setting a screen name?
@@ -227,20 +236,20 @@ static bool is_csi_style_escape_seq(const wchar_t *code, size_t *resulting_lengt
 /// Detect whether the escape sequence sets one of the terminal attributes that affects how text is
 /// displayed other than the color.
 static bool is_visual_escape_seq(const wchar_t *code, size_t *resulting_length) {
-    if (!cur_term) return false;
-    const char *const esc2[] = {
-        enter_bold_mode,     exit_attribute_mode, enter_underline_mode,   exit_underline_mode,
-        enter_standout_mode, exit_standout_mode,  enter_blink_mode,       enter_protected_mode,
-        enter_italics_mode,  exit_italics_mode,   enter_reverse_mode,     enter_shadow_mode,
-        exit_shadow_mode,    enter_standout_mode, exit_standout_mode,     enter_secure_mode,
-        enter_dim_mode,      enter_blink_mode,    enter_alt_charset_mode, exit_alt_charset_mode};
-
-    for (auto p : esc2) {
+    env_ensure_curses_initialized();
+    if (!term_has_entry()) return false;
+    // enter_bold_mode, exit_attribute_mode, enter_underline_mode, exit_underline_mode and so on.
+    const char *const esc2[] = {"bold", "sgr0", "smul",  "rmul", "smso", "rmso",  "blink",
+                                "prot", "sitm", "ritm",  "rev",  "sshm", "rshm",  "invis",
+                                "dim",  "smacs", "rmacs"};
+
+    for (auto name : esc2) {
+        maybe_t<std::string> p = term_get_string(name);
         if (!p) continue;
-        // Test both padded and unpadded version, just to be safe. Most versions of fish_tparm don't
-        // actually seem to do anything these days.
+        // Test both the expanded and the raw version, just to be safe. Expanding a capability
+        // without parameters rarely changes it.
         size_t esc_seq_len =
-            std::max(try_sequence(fish_tparm(const_cast<char *>(p)), code), try_sequence(p, code));
+            std::max(try_sequence(term_expand(*p).c_str(), code), try_sequence(p->c_str(), code));
         if (esc_seq_len) {
             *resulting_length = esc_seq_len;
             return true;
@@ -499,11 +508,23 @@ void screen_t::desired_append_char(wchar_t b, highlight_spec_t c, int indent, si
         int line_no = ++this->desired.cursor.y;
         this->desired.cursor.x = 0;
         size_t indentation = prompt_width + static_cast<size_t>(indent) * INDENT_STEP;
+        // The continuation indicator goes at the end of the space below the prompt, if it fits.
+        // Only the spaces in front of it count as indentation, so it is always drawn.
+        size_t spaces = indentation;
+        bool show_indicator = !continuation_indicator.empty() &&
+                              continuation_indicator_width <= prompt_width;
+        if (show_indicator) spaces = prompt_width - continuation_indicator_width;
         line_t &line = this->desired.line(line_no);
-        line.indentation = indentation;
-        for (size_t i = 0; i < indentation; i++) {
+        line.indentation = spaces;
+        for (size_t i = 0; i < spaces; i++) {
             desired_append_char(L' ', highlight_spec_t{}, indent, prompt_width, 1);
         }
+        if (show_indicator) {
+            desired_append_continuation_indicator();
+            for (size_t i = prompt_width; i < indentation; i++) {
+                desired_append_char(L' ', highlight_spec_t{}, indent, prompt_width, 1);
+            }
+        }
     } else if (b == L'\r') {
         line_t &current = this->desired.line(line_no);
         current.clear();
@@ -523,6 +544,7 @@ void screen_t::desired_append_char(wchar_t b, highlight_spec_t c, int indent, si
             this->desired.add_line();
             this->desired.cursor.y++;
             this->desired.cursor.x = 0;
+            desired_append_continuation_indicator();
         }
 
         line_t &line = this->desired.line(line_no);
@@ -535,10 +557,18 @@ void screen_t::desired_append_char(wchar_t b, highlight_spec_t c, int indent, si
             line.is_soft_wrapped = true;
             this->desired.cursor.x = 0;
             this->desired.cursor.y++;
+            desired_append_continuation_indicator();
         }
     }
 }
 
+void screen_t::desired_append_continuation_indicator() {
+    if (continuation_indicator.empty()) return;
+    line_t &line = this->desired.create_line(this->desired.cursor.y);
+    line.append(continuation_indicator.c_str(), highlight_role_t::continuation);
+    this->desired.cursor.x += static_cast<int>(continuation_indicator_width);
+}
+
 void screen_t::move(int new_x, int new_y) {
     if (this->actual.cursor.x == new_x && this->actual.cursor.y == new_y) return;
 
@@ -562,17 +592,17 @@ void screen_t::move(int new_x, int new_y) {
     int i;
     int x_steps, y_steps;
 
-    const char *str;
+    maybe_t<std::string> str;
     auto &outp = this->outp();
 
     y_steps = new_y - this->actual.cursor.y;
 
     if (y_steps < 0) {
-        str = cursor_up;
+        str = term_get_string("cuu1");
     } else if (y_steps > 0) {
-        str = cursor_down;
-        if ((shell_modes.c_oflag & ONLCR) != 0 &&
-            std::strcmp(str, "\n") == 0) {  // See GitHub issue #4505.
+        str = term_get_string("cud1");
+        if ((shell_modes.c_oflag & ONLCR) != 0 && str &&
+            *str == "\n") {  // See GitHub issue #4505.
             // Most consoles use a simple newline as the cursor down escape.
             // If ONLCR is enabled (which it normally is) this will of course
             // also move the cursor to the beginning of the line.
@@ -594,21 +624,21 @@ void screen_t::move(int new_x, int new_y) {
         x_steps = 0;
     }
 
-    const char *multi_str = nullptr;
+    maybe_t<std::string> multi_str;
     if (x_steps < 0) {
-        str = cursor_left;
-        multi_str = parm_left_cursor;
+        str = term_get_string("cub1");
+        multi_str = term_get_string("cub");
     } else {
-        str = cursor_right;
-        multi_str = parm_right_cursor;
+        str = term_get_string("cuf1");
+        multi_str = term_get_string("cuf");
     }
 
     // Use the bulk ('multi') output for cursor movement if it is supported and it would be shorter
     // Note that this is required to avoid some visual glitches in iTerm (issue #1448).
-    bool use_multi = multi_str != nullptr && multi_str[0] != '\0' &&
-                     abs(x_steps) * std::strlen(str) > std::strlen(multi_str);
-    if (use_multi && cur_term) {
-        char *multi_param = fish_tparm(const_cast<char *>(multi_str), abs(x_steps));
+    bool use_multi = multi_str && !multi_str->empty() &&
+                     (!str || abs(x_steps) * str->size() > multi_str->size());
+    if (use_multi) {
+        std::string multi_param = term_expand(*multi_str, {abs(x_steps)});
         writembs(outp, multi_param);
     } else {
         for (i = 0; i < abs(x_steps); i++) {
@@ -636,8 +666,8 @@ void screen_t::write_char(wchar_t c, size_t width) {
     }
 }
 
-/// Send the specified string through tputs and append the output to the screen's outputter.
-void screen_t::write_mbs(const char *s) { writembs(this->outp(), s); }
+/// Append the specified terminfo string to the screen's outputter, without its padding.
+void screen_t::write_mbs(const maybe_t<std::string> &s) { writembs(this->outp(), s); }
 
 /// Convert a wide string to a multibyte string and append it to the buffer.
 void screen_t::write_str(const wchar_t *s) { this->outp().writestr(s); }
@@ -701,11 +731,16 @@ void screen_t::update(const wcstring &left_prompt, const wcstring &right_prompt,
     highlight_color_resolver_t color_resolver{};
     auto set_color = [&](highlight_spec_t c) {
         this->outp().set_color(color_resolver.resolve_spec(c, false, vars),
-                               color_resolver.resolve_spec(c, true, vars));
+                               color_resolver.resolve_spec(c, true, vars),
+                               color_resolver.resolve_underline_color(c, vars));
     };
 
+    const maybe_t<std::string> clr_eol = term_get_string("el");
+    const maybe_t<std::string> clr_eos = term_get_string("ed");
+
     layout_cache_t &cached_layouts = layout_cache_t::shared;
     const scoped_buffer_t buffering(outp());
+    const scoped_synchronized_output_t synchronized(outp());
 
     // Determine size of left and right prompt. Note these have already been truncated.
     const prompt_layout_t left_prompt_layout = cached_layouts.calc_prompt_layout(left_prompt);
@@ -747,15 +782,23 @@ void screen_t::update(const wcstring &left_prompt, const wcstring &right_prompt,
     // Output the left prompt if it has changed.
     if (left_prompt != this->actual_left_prompt) {
         this->move(0, 0);
+        // Mark where the prompt starts and ends, for terminals with shell integration.
+        // These sequences don't move the cursor.
+        bool prompt_marks = term_supports_prompt_marks();
+        if (prompt_marks) this->write_str(L"\x1B]133;A\a");
+        // Reordering keeps every character on its line, so the line breaks stay valid.
+        const wcstring prompt_text =
+            should_reorder_bidi() ? bidi_reorder_prompt(left_prompt) : left_prompt;
         size_t start = 0;
         for (const size_t line_break : left_prompt_layout.line_breaks) {
-            this->write_str(left_prompt.substr(start, line_break - start));
+            this->write_str(prompt_text.substr(start, line_break - start));
             if (clr_eol) {
                 this->write_mbs(clr_eol);
             }
             start = line_break;
         }
-        this->write_str(left_prompt.substr(start));
+        this->write_str(prompt_text.substr(start));
+        if (prompt_marks) this->write_str(L"\x1B]133;B\a");
         this->actual_left_prompt = left_prompt;
         this->actual.cursor.x = static_cast<int>(left_prompt_width);
     }
@@ -771,7 +814,7 @@ void screen_t::update(const wcstring &left_prompt, const wcstring &right_prompt,
         // If this is the last line, maybe we should clear the screen.
         // Don't issue clr_eos if we think the cursor will end up in the last column - see #6951.
         const bool should_clear_screen_this_line =
-            need_clear_screen && i + 1 == this->desired.line_count() && clr_eos != nullptr &&
+            need_clear_screen && i + 1 == this->desired.line_count() && clr_eos.has_value() &&
             !(this->desired.cursor.x == 0 &&
               this->desired.cursor.y == static_cast<int>(this->desired.line_count()));
 
@@ -939,8 +982,9 @@ void screen_t::update(const wcstring &left_prompt, const wcstring &right_prompt,
 
 /// Returns true if we are using a dumb terminal.
 static bool is_dumb() {
-    if (!cur_term) return true;
-    return !cursor_up || !cursor_down || !cursor_left || !cursor_right;
+    if (!term_has_entry()) return true;
+    return !term_get_string("cuu1") || !term_get_string("cud1") || !term_get_string("cub1") ||
+           !term_get_string("cuf1");
 }
 
 namespace {
@@ -1124,6 +1168,71 @@ static screen_layout_t compute_layout(screen_t *s, size_t screen_width,
     return result;
 }
 
+/// \return the order to show \p len characters of \p text in, see bidi_visual_order() in Rust.
+static rust::Vec<uint32_t> bidi_order(const wchar_t *text, size_t len) {
+    std::vector<uint32_t> chars(text, text + len);
+    return bidi_visual_order(rust::Slice<const uint32_t>(chars.data(), chars.size()));
+}
+
+/// Put the characters of \p line in display order, for terminals that don't handle right-to-left
+/// text. If \p cursor_x is set and the cursor is on a character of the line, it moves along.
+static void bidi_reorder_line(line_t &line, int *cursor_x) {
+    wcstring text;
+    for (const auto &c : line.text) text.push_back(c.character);
+    rust::Vec<uint32_t> order = bidi_order(text.data(), text.size());
+    if (order.empty()) return;
+
+    maybe_t<size_t> cursor_char{};
+    if (cursor_x) {
+        int x = 0;
+        for (size_t i = 0; i < text.size() && x <= *cursor_x; i++) {
+            if (x == *cursor_x) cursor_char = i;
+            x += std::max(0, fish_wcwidth_visible(text.at(i)));
+        }
+    }
+
+    std::vector<line_t::highlighted_char_t> visual;
+    visual.reserve(order.size());
+    int x = 0;
+    for (uint32_t idx : order) {
+        if (cursor_char && *cursor_char == idx) *cursor_x = x;
+        visual.push_back(line.text.at(idx));
+        x += std::max(0, fish_wcwidth_visible(text.at(idx)));
+    }
+    line.text = std::move(visual);
+}
+
+/// Put the text of \p prompt in display order. Escape sequences and line breaks stay where they
+/// are, and the text between them is reordered on its own.
+static wcstring bidi_reorder_prompt(const wcstring &prompt) {
+    wcstring result;
+    result.reserve(prompt.size());
+    size_t i = 0;
+    while (i < prompt.size()) {
+        size_t start = i;
+        maybe_t<size_t> esc_len{};
+        for (; i < prompt.size(); i++) {
+            wchar_t c = prompt.at(i);
+            if (c == L'\n' || c == L'\r' || c == L'\f') break;
+            if (c == L'\x1B' && (esc_len = escape_code_length(prompt.c_str() + i))) break;
+        }
+
+        rust::Vec<uint32_t> order = bidi_order(prompt.data() + start, i - start);
+        if (order.empty()) {
+            result.append(prompt, start, i - start);
+        } else {
+            for (uint32_t idx : order) result.push_back(prompt.at(start + idx));
+        }
+
+        if (i < prompt.size()) {
+            size_t len = esc_len ? *esc_len : 1;
+            result.append(prompt, i, len);
+            i += len;
+        }
+    }
+    return result;
+}
+
 void screen_t::write(const wcstring &left_prompt, const wcstring &right_prompt,
                      const wcstring &commandline, size_t explicit_len,
                      const std::vector<highlight_spec_t> &colors, const std::vector<int> &indent,
@@ -1167,6 +1276,22 @@ void screen_t::write(const wcstring &left_prompt, const wcstring &right_prompt,
     this->autosuggestion_is_truncated =
         !autosuggestion.empty() && autosuggestion != layout.autosuggestion;
 
+    // Get the continuation indicator, without control characters. Leave it out if it would take
+    // up more than half of a line.
+    this->continuation_indicator.clear();
+    this->continuation_indicator_width = 0;
+    if (auto indicator = vars.get(L"fish_continuation_indicator")) {
+        for (wchar_t c : indicator->as_string()) {
+            if (c < L' ' || c == 0x7F) continue;
+            this->continuation_indicator.push_back(c);
+            this->continuation_indicator_width += fish_wcwidth_visible(c);
+        }
+        if (this->continuation_indicator_width * 2 > static_cast<size_t>(screen_width)) {
+            this->continuation_indicator.clear();
+            this->continuation_indicator_width = 0;
+        }
+    }
+
     // Clear the desired screen and set its width.
     this->desired.screen_width = screen_width;
     this->desired.resize(0);
@@ -1206,6 +1331,15 @@ void screen_t::write(const wcstring &left_prompt, const wcstring &right_prompt,
     // above.
     this->desired.cursor = cursor_arr;
 
+    // Lay out right-to-left text, before the pager's lines are added.
+    if (should_reorder_bidi()) {
+        screen_data_t::cursor_t &cursor = this->desired.cursor;
+        for (size_t y = 0; y < this->desired.line_count(); y++) {
+            bool has_cursor = !cursor_is_within_pager && cursor.y == static_cast<int>(y);
+            bidi_reorder_line(this->desired.line(y), has_cursor ? &cursor.x : nullptr);
+        }
+    }
+
     if (cursor_is_within_pager) {
         this->desired.cursor.x = static_cast<int>(cursor_pos);
         this->desired.cursor.y = static_cast<int>(this->desired.line_count());
@@ -1252,6 +1386,28 @@ void screen_t::reset_line(bool repaint_prompt) {
     fstat(STDERR_FILENO, &this->prev_buff_2);
 }
 
+void screen_t::write_above_prompt(const wcstring &text) {
+    // As in reset_line, claim to be below line 0 so moving there goes to the prompt's first line.
+    const size_t prompt_line_count = calc_prompt_lines(this->actual_left_prompt);
+    this->actual.cursor.y += (prompt_line_count - 1);
+    {
+        const scoped_buffer_t buffering(outp());
+        this->move(0, 0);
+        const maybe_t<std::string> clr_eos = term_get_string("ed");
+        if (clr_eos) this->write_mbs(clr_eos);
+        this->write_str(text);
+        if (text.empty() || text.back() != L'\n') this->outp().push_back('\n');
+    }
+
+    this->actual.resize(0);
+    this->actual.cursor = screen_data_t::cursor_t{};
+    this->actual_left_prompt.clear();
+    this->actual_lines_before_reset = 0;
+    this->soft_wrap_location = none();
+    this->need_clear_lines = true;
+    this->save_status();
+}
+
 void screen_t::reset_abandoning_line(int screen_width) {
     this->actual.cursor.y = 0;
     this->actual.resize(0);
@@ -1267,9 +1423,14 @@ void screen_t::reset_abandoning_line(int screen_width) {
     int non_space_width = get_omitted_newline_width();
     // We do `>` rather than `>=` because the code below might require one extra space.
     if (screen_width > non_space_width) {
+        const maybe_t<std::string> enter_dim_mode = term_get_string("dim");
+        const maybe_t<std::string> set_a_foreground = term_get_string("setaf");
+        const maybe_t<std::string> enter_bold_mode = term_get_string("bold");
+        const maybe_t<std::string> exit_attribute_mode = term_get_string("sgr0");
+        const int max_colors = term_get_number_ffi("colors");
         bool justgrey = true;
-        if (cur_term && enter_dim_mode) {
-            std::string dim = fish_tparm(const_cast<char *>(enter_dim_mode));
+        if (enter_dim_mode) {
+            std::string dim = term_expand(*enter_dim_mode);
             if (!dim.empty()) {
                 // Use dim if they have it, so the color will be based on their actual normal
                 // color and the background of the terminal.
@@ -1277,29 +1438,25 @@ void screen_t::reset_abandoning_line(int screen_width) {
                 justgrey = false;
             }
         }
-        if (cur_term && justgrey && set_a_foreground) {
+        if (justgrey && set_a_foreground) {
             if (max_colors >= 238) {
                 // draw the string in a particular grey
-                abandon_line_string.append(
-                    str2wcstring(fish_tparm(const_cast<char *>(set_a_foreground), 237)));
+                abandon_line_string.append(str2wcstring(term_expand(*set_a_foreground, {237})));
             } else if (max_colors >= 9) {
                 // bright black (the ninth color, looks grey)
-                abandon_line_string.append(
-                    str2wcstring(fish_tparm(const_cast<char *>(set_a_foreground), 8)));
+                abandon_line_string.append(str2wcstring(term_expand(*set_a_foreground, {8})));
             } else if (max_colors >= 2 && enter_bold_mode) {
                 // we might still get that color by setting black and going bold for bright
-                abandon_line_string.append(
-                    str2wcstring(fish_tparm(const_cast<char *>(enter_bold_mode))));
-                abandon_line_string.append(
-                    str2wcstring(fish_tparm(const_cast<char *>(set_a_foreground), 0)));
+                abandon_line_string.append(str2wcstring(term_expand(*enter_bold_mode)));
+                abandon_line_string.append(str2wcstring(term_expand(*set_a_foreground, {0})));
             }
         }
 
         abandon_line_string.append(get_omitted_newline_str());
 
-        if (cur_term && exit_attribute_mode) {
-            abandon_line_string.append(str2wcstring(fish_tparm(
-                const_cast<char *>(exit_attribute_mode))));  // normal text ANSI escape sequence
+        if (exit_attribute_mode) {
+            // normal text ANSI escape sequence
+            abandon_line_string.append(str2wcstring(term_expand(*exit_attribute_mode)));
         }
 
         int newline_glitch_width = TERM_HAS_XN ? 0 : 1;
@@ -1318,8 +1475,9 @@ void screen_t::reset_abandoning_line(int screen_width) {
     // line above your prompt. This doesn't make a difference in normal usage, but copying and
     // pasting your terminal log becomes a pain. This commit clears that line, making it an
     // actual empty line.
+    const maybe_t<std::string> clr_eol = term_get_string("el");
     if (!is_dumb() && clr_eol) {
-        abandon_line_string.append(str2wcstring(clr_eol));
+        abandon_line_string.append(str2wcstring(*clr_eol));
     }
 
     const std::string narrow_abandon_line_string = wcs2string(abandon_line_string);
@@ -1332,7 +1490,7 @@ void screen_t::reset_abandoning_line(int screen_width) {
 }
 
 void screen_force_clear_to_end() {
-    if (clr_eos) {
+    if (const maybe_t<std::string> clr_eos = term_get_string("ed")) {
         writembs(outputter_t::stdoutput(), clr_eos);
     }
 }
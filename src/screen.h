@@ -163,6 +163,10 @@ class screen_t {
     /// The screen width must be provided for the PROMPT_SP hack.
     void reset_abandoning_line(int screen_width);
 
+    /// Erase the prompt and command line, print \p text in their place, and forget what the screen
+    /// contains, so the next write draws them again below the text.
+    void write_above_prompt(const wcstring &text);
+
     /// Stat stdout and stderr and save result as the current timestamp.
     /// This is used to avoid reacting to changes that we ourselves made to the screen.
     void save_status();
@@ -180,6 +184,9 @@ class screen_t {
     void desired_append_char(wchar_t b, highlight_spec_t c, int indent, size_t prompt_width,
                              size_t bwidth);
 
+    /// Appends the continuation indicator to the line that the output cursor is on.
+    void desired_append_continuation_indicator();
+
     /// Stat stdout and stderr and compare result to previous result in reader_save_status. Repaint
     /// if modification time has changed.
     void check_status();
@@ -194,8 +201,8 @@ class screen_t {
     /// Convert a wide character to a multibyte string and append it to the buffer.
     void write_char(wchar_t c, size_t width);
 
-    /// Send the specified string through tputs and append the output to the screen's outputter.
-    void write_mbs(const char *s);
+    /// Append the specified terminfo string to the screen's outputter, without its padding.
+    void write_mbs(const maybe_t<std::string> &s);
 
     /// Convert a wide string to a multibyte string and append it to the buffer.
     void write_str(const wchar_t *s);
@@ -215,6 +222,10 @@ class screen_t {
     wcstring actual_left_prompt{};
     /// Last right prompt width.
     size_t last_right_prompt_width{0};
+    /// The marker in front of continued and wrapped lines of the command line, from
+    /// $fish_continuation_indicator, and its width.
+    wcstring continuation_indicator{};
+    size_t continuation_indicator_width{0};
     /// If we support soft wrapping, we can output to this location without any cursor motion.
     maybe_t<screen_data_t::cursor_t> soft_wrap_location{};
     /// This flag is set to true when there is reason to suspect that the parts of the screen lines
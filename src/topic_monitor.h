@@ -18,6 +18,9 @@ struct generation_list_t {
     uint64_t sighupint;
     uint64_t sigchld;
     uint64_t internal_exit;
+    uint64_t sigwinch;
+    uint64_t job_stopcont;
+    uint64_t uvar_change;
 };
 
 #endif
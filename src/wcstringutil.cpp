@@ -141,10 +141,66 @@ bool subsequence_in_string(const wcstring &needle, const wcstring &haystack) {
     return ni == needle.end();
 }
 
+std::vector<size_t> subsequence_match_positions(const wcstring &needle, const wcstring &haystack,
+                                                bool icase) {
+    auto eq = [=](wchar_t a, wchar_t b) { return a == b || (icase && towlower(a) == towlower(b)); };
+    if (needle.empty() || needle.size() > haystack.size()) return {};
+
+    // Like fzf, find where the earliest match ends, then go back from there to find the latest
+    // start, which gives the shortest stretch.
+    size_t ni = 0;
+    size_t end = 0;
+    for (size_t hi = 0; hi < haystack.size() && ni < needle.size(); hi++) {
+        if (eq(needle.at(ni), haystack.at(hi))) {
+            ni++;
+            end = hi;
+        }
+    }
+    if (ni < needle.size()) return {};
+    size_t start = end + 1;
+    for (ni = needle.size(); ni > 0; start--) {
+        if (eq(needle.at(ni - 1), haystack.at(start - 1))) ni--;
+    }
+
+    std::vector<size_t> result;
+    for (size_t hi = start; result.size() < needle.size(); hi++) {
+        if (eq(needle.at(result.size()), haystack.at(hi))) result.push_back(hi);
+    }
+    return result;
+}
+
+/// Score a subsequence match at \p positions in \p haystack, a bit like fzf does: each matched
+/// character counts, more so at the start of a word or right after the previous one, and gaps in
+/// between count against the match.
+static int32_t score_subsequence_match(const wcstring &haystack,
+                                       const std::vector<size_t> &positions) {
+    auto is_word_start = [&](size_t pos) {
+        if (pos == 0) return true;
+        wchar_t prev = haystack.at(pos - 1), cur = haystack.at(pos);
+        return !iswalnum(prev) || (iswlower(prev) && iswupper(cur));
+    };
+    int32_t score = 0;
+    for (size_t i = 0; i < positions.size(); i++) {
+        size_t pos = positions.at(i);
+        score += 16;
+        if (is_word_start(pos)) score += 8;
+        if (i > 0) {
+            size_t gap = pos - positions.at(i - 1) - 1;
+            if (gap == 0) {
+                score += 4;
+            } else {
+                score -= 3 + static_cast<int32_t>(std::min(gap - 1, size_t(64)));
+            }
+        }
+    }
+    return score;
+}
+
 // static
 maybe_t<string_fuzzy_match_t> string_fuzzy_match_t::try_create(const wcstring &string,
                                                                const wcstring &match_against,
-                                                               bool anchor_start) {
+                                                               bool anchor_start,
+                                                               bool scored_subseq) {
     // Helper to lazily compute if case insensitive matches should use icase or smartcase.
     // Use icase if the input contains any uppercase characters, smartcase otherwise.
     auto get_case_fold = [&] {
@@ -194,12 +250,26 @@ maybe_t<string_fuzzy_match_t> string_fuzzy_match_t::try_create(const wcstring &s
         return string_fuzzy_match_t{contain_type_t::substr, get_case_fold()};
     }
 
+    if (scored_subseq) {
+        // subseq samecase, then icase, scored by how the characters line up.
+        for (bool icase : {false, true}) {
+            std::vector<size_t> positions =
+                subsequence_match_positions(string, match_against, icase);
+            if (!positions.empty()) {
+                return string_fuzzy_match_t{contain_type_t::subseq,
+                                            icase ? get_case_fold() : case_fold_t::samecase,
+                                            score_subsequence_match(match_against, positions)};
+            }
+        }
+        return none();
+    }
+
     // subseq samecase
     if (subsequence_in_string(string, match_against)) {
         return string_fuzzy_match_t{contain_type_t::subseq, case_fold_t::samecase};
     }
 
-    // We do not currently test subseq icase.
+    // Unless scored, we do not test subseq icase.
     return none();
 }
 
@@ -215,6 +285,30 @@ uint32_t string_fuzzy_match_t::rank() const {
     return static_cast<uint32_t>(effective_type) * 8 + static_cast<uint32_t>(effective_case);
 }
 
+std::vector<size_t> string_fuzzy_match_t::positions(const wcstring &string,
+                                                    const wcstring &match_against) const {
+    size_t start = 0;
+    switch (type) {
+        case contain_type_t::exact:
+        case contain_type_t::prefix:
+            break;
+        case contain_type_t::substr:
+            start = case_fold == case_fold_t::samecase
+                        ? match_against.find(string)
+                        : ifind(match_against, string, true /* fuzzy */);
+            if (start == wcstring::npos) return {};
+            break;
+        case contain_type_t::subseq:
+            return subsequence_match_positions(string, match_against,
+                                               case_fold != case_fold_t::samecase);
+    }
+    std::vector<size_t> result;
+    for (size_t i = start; i < start + string.size() && i < match_against.size(); i++) {
+        result.push_back(i);
+    }
+    return result;
+}
+
 template <bool Fuzzy, typename T>
 static size_t ifind_impl(const T &haystack, const T &needle) {
     using char_t = typename T::value_type;
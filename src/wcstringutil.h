@@ -37,6 +37,12 @@ bool string_prefixes_string_case_insensitive(const wcstring &proposed_prefix,
 /// Test if a string matches a subsequence of another.
 bool subsequence_in_string(const wcstring &needle, const wcstring &haystack);
 
+/// \return the positions in \p haystack of the characters of \p needle, choosing the shortest
+/// stretch of \p haystack that contains them, or an empty list if \p needle is not a subsequence
+/// of it. If \p icase is set, case is ignored.
+std::vector<size_t> subsequence_match_positions(const wcstring &needle, const wcstring &haystack,
+                                                bool icase);
+
 /// Case-insensitive string search, modeled after std::string::find().
 /// \param fuzzy indicates this is being used for fuzzy matching and case insensitivity is
 /// expanded to include symbolic characters (#3584).
@@ -64,9 +70,14 @@ struct string_fuzzy_match_t {
     };
     case_fold_t case_fold;
 
+    // For scored subsequence matches, how well the matched characters line up with each other and
+    // with the starts of words, like fzf's scoring. Larger is better. This is 0 otherwise.
+    int32_t subseq_score;
+
     // Constructor.
-    constexpr string_fuzzy_match_t(contain_type_t type, case_fold_t case_fold)
-        : type(type), case_fold(case_fold) {}
+    constexpr string_fuzzy_match_t(contain_type_t type, case_fold_t case_fold,
+                                   int32_t subseq_score = 0)
+        : type(type), case_fold(case_fold), subseq_score(subseq_score) {}
 
     // Helper to return an exact match.
     static constexpr string_fuzzy_match_t exact_match() {
@@ -111,20 +122,26 @@ struct string_fuzzy_match_t {
     /// Try creating a fuzzy match for \p string against \p match_against.
     /// \p string is something like "foo" and \p match_against is like "FooBar".
     /// If \p anchor_start is set, then only exact and prefix matches are permitted.
+    /// If \p scored_subseq is set, subsequence matches may also ignore case, and are scored.
     static maybe_t<string_fuzzy_match_t> try_create(const wcstring &string,
                                                     const wcstring &match_against,
-                                                    bool anchor_start);
+                                                    bool anchor_start, bool scored_subseq = false);
 
     /// \return a rank for filtering matches.
     /// Earlier (smaller) ranks are better matches.
     uint32_t rank() const;
+
+    /// \return the positions of the characters in \p match_against that \p string matched, given
+    /// that this is how \p string matched it.
+    std::vector<size_t> positions(const wcstring &string, const wcstring &match_against) const;
 };
 
 /// Cover over string_fuzzy_match_t::try_create().
 inline maybe_t<string_fuzzy_match_t> string_fuzzy_match_string(const wcstring &string,
                                                                const wcstring &match_against,
-                                                               bool anchor_start = false) {
-    return string_fuzzy_match_t::try_create(string, match_against, anchor_start);
+                                                               bool anchor_start = false,
+                                                               bool scored_subseq = false) {
+    return string_fuzzy_match_t::try_create(string, match_against, anchor_start, scored_subseq);
 }
 
 /// Split a string by a separator character.
@@ -11,8 +11,13 @@
 #include <unistd.h>
 
 #include <algorithm>
+#include <atomic>
+#include <chrono>
+#include <condition_variable>
 #include <cwchar>
 #include <functional>
+#include <memory>
+#include <mutex>
 #include <string>
 #include <unordered_set>
 #include <utility>
@@ -24,6 +29,7 @@
 #include "expand.h"
 #include "fallback.h"  // IWYU pragma: keep
 #include "future_feature_flags.h"
+#include "iothread.h"
 #include "maybe.h"
 #include "path.h"
 #include "wcstringutil.h"
@@ -204,7 +210,9 @@ static wildcard_result_t wildcard_complete_internal(const wchar_t *const str, si
     // Maybe we have no more wildcards at all. This includes the empty string.
     if (next_wc_char_pos == wcstring::npos) {
         // Try matching.
-        maybe_t<string_fuzzy_match_t> match = string_fuzzy_match_string(wc, str);
+        maybe_t<string_fuzzy_match_t> match = string_fuzzy_match_string(
+            wc, str, false /* anchor_start */,
+            params.expand_flags.get(expand_flag::fuzzy_subsequence));
         if (!match) return wildcard_result_t::no_match;
 
         // If we're not allowing fuzzy match, then we require a prefix match.
@@ -464,6 +472,45 @@ static bool wildcard_test_flags_then_complete(const wcstring &filepath, const wc
 }
 
 namespace {
+/// The most threads that expand subdirectories in parallel, besides the main thread.
+static constexpr size_t k_max_parallel_expanders = 8;
+
+/// State shared by the threads that expand the subdirectories of a directory in parallel.
+struct parallel_expansion_t {
+    /// One subdirectory, and what was found in it.
+    struct job_t {
+        wcstring path;
+        file_id_t file_id;
+        completion_list_t results;
+        bool did_add{false};
+        bool did_overflow{false};
+    };
+    std::vector<job_t> jobs;
+
+    // What every job needs to create its expander.
+    wcstring working_directory;
+    expand_flags_t flags;
+    std::unordered_set<file_id_t> visited_files;
+    bool has_fuzzy_ancestor{false};
+    wcstring wc_remainder;
+    wcstring prefix;
+    // An empty receiver with the limit for each job.
+    maybe_t<completion_receiver_t> receiver;
+
+    // Protects next_job and jobs_done.
+    std::mutex lock;
+    std::condition_variable cond;
+    // The index of the next job nobody has taken yet.
+    size_t next_job{0};
+    // The number of jobs which are finished.
+    size_t jobs_done{0};
+
+    // Set to make all jobs stop early: on cancellation, or when one of them overflowed.
+    std::atomic<bool> stop{false};
+    // Whether the expansion was cancelled.
+    std::atomic<bool> cancelled{false};
+};
+
 class wildcard_expander_t {
     // A function to call to check cancellation.
     cancel_checker_t cancel_checker;
@@ -485,8 +532,11 @@ class wildcard_expander_t {
     bool did_add{false};
     // Whether some parent expansion is fuzzy, and therefore completions always prepend their prefix
     // This variable is a little suspicious - it should be passed along, not stored here
-    // If we ever try to do parallel wildcard expansion we'll have to remove this
+    // Expanders for parallel jobs get a copy of it.
     bool has_fuzzy_ancestor{false};
+    // Whether we may expand subdirectories in parallel. This is only done on the main thread, and
+    // only once: the expanders of the parallel jobs work serially.
+    bool may_parallelize{false};
 
     /// We are a trailing slash - expand at the end.
     void expand_trailing_slash(const wcstring &base_dir, const wcstring &prefix);
@@ -514,6 +564,15 @@ class wildcard_expander_t {
     void expand_last_segment(const wcstring &base_dir, dir_iter_t &base_dir_iter,
                              const wcstring &wc, const wcstring &prefix);
 
+    /// Expand wc_remainder in each of the directories in \p subdirs, on the iothread pool, and add
+    /// what was found in the order of \p subdirs.
+    void expand_subdirs_in_parallel(std::vector<std::pair<wcstring, file_id_t>> &&subdirs,
+                                    const wchar_t *wc_remainder, const wcstring &prefix);
+
+    /// Take jobs from \p state and run them until there are none left.
+    static void run_parallel_jobs(parallel_expansion_t *state,
+                                  const cancel_checker_t &cancel_checker);
+
     /// Indicate whether we should cancel wildcard expansion. This latches 'interrupt'.
     bool interrupted_or_overflowed() {
         did_interrupt = did_interrupt || cancel_checker();
@@ -651,7 +710,8 @@ class wildcard_expander_t {
         : cancel_checker(std::move(cancel_checker)),
           working_directory(std::move(wd)),
           flags(f),
-          resolved_completions(r) {
+          resolved_completions(r),
+          may_parallelize(is_main_thread()) {
         assert(resolved_completions != nullptr);
 
         // Insert initial completions into our set to avoid duplicates.
@@ -720,7 +780,8 @@ void wildcard_expander_t::expand_intermediate_segment(const wcstring &base_dir,
                                                       const wcstring &wc_segment,
                                                       const wchar_t *wc_remainder,
                                                       const wcstring &prefix) {
-    std::string narrow;
+    // The directories to expand in parallel.
+    std::vector<std::pair<wcstring, file_id_t>> subdirs;
     const dir_iter_t::entry_t *entry{};
     while (!interrupted_or_overflowed() && (entry = base_dir_iter.next())) {
         // Note that it's critical we ignore leading dots here, else we may descend into . and ..
@@ -739,6 +800,14 @@ void wildcard_expander_t::expand_intermediate_segment(const wcstring &base_dir,
         }
 
         const file_id_t file_id = file_id_t::from_stat(*statbuf);
+        wcstring full_path = base_dir + entry->name;
+        full_path.push_back(L'/');
+        if (this->may_parallelize) {
+            if (!this->visited_files.count(file_id)) {
+                subdirs.emplace_back(std::move(full_path), file_id);
+            }
+            continue;
+        }
         if (!this->visited_files.insert(file_id).second) {
             // Symlink loop! This directory was already visited, so skip it.
             continue;
@@ -746,14 +815,125 @@ void wildcard_expander_t::expand_intermediate_segment(const wcstring &base_dir,
 
         // We made it through. Perform normal wildcard expansion on this new directory, starting at
         // our tail_wc, which includes the ANY_STRING_RECURSIVE guy.
-        wcstring full_path = base_dir + entry->name;
-        full_path.push_back(L'/');
         this->expand(full_path, wc_remainder, prefix + wc_segment + L'/');
 
         // Now remove the visited file. This is for #2414: only directories "beneath" us should be
         // considered visited.
         this->visited_files.erase(file_id);
     }
+
+    if (subdirs.size() == 1 && !interrupted_or_overflowed()) {
+        // Nothing to do in parallel here, maybe further down.
+        const file_id_t file_id = subdirs.front().second;
+        this->visited_files.insert(file_id);
+        this->expand(subdirs.front().first, wc_remainder, prefix + wc_segment + L'/');
+        this->visited_files.erase(file_id);
+    } else if (subdirs.size() > 1) {
+        this->expand_subdirs_in_parallel(std::move(subdirs), wc_remainder,
+                                         prefix + wc_segment + L'/');
+    }
+}
+
+void wildcard_expander_t::expand_subdirs_in_parallel(
+    std::vector<std::pair<wcstring, file_id_t>> &&subdirs, const wchar_t *wc_remainder,
+    const wcstring &prefix) {
+    if (interrupted_or_overflowed()) {
+        return;
+    }
+    auto state = std::make_shared<parallel_expansion_t>();
+    for (auto &subdir : subdirs) {
+        parallel_expansion_t::job_t job;
+        job.path = std::move(subdir.first);
+        job.file_id = subdir.second;
+        state->jobs.push_back(std::move(job));
+    }
+    state->working_directory = this->working_directory;
+    state->flags = this->flags;
+    state->visited_files = this->visited_files;
+    state->has_fuzzy_ancestor = this->has_fuzzy_ancestor;
+    state->wc_remainder = wc_remainder;
+    state->prefix = prefix;
+    state->receiver.emplace(this->resolved_completions->subreceiver());
+
+    // The pool threads only look at our flag, only the main thread may check for cancellation.
+    size_t threads = std::min(state->jobs.size() - 1, k_max_parallel_expanders);
+    for (size_t i = 0; i < threads; i++) {
+        iothread_perform([state] {
+            run_parallel_jobs(state.get(), [&] { return state->stop.load(); });
+        });
+    }
+    auto check_cancel = [&] {
+        if (!state->stop && this->cancel_checker()) {
+            state->cancelled = true;
+            state->stop = true;
+        }
+        return state->stop.load();
+    };
+    // Work on jobs ourselves, so we never wait for a busy pool.
+    run_parallel_jobs(state.get(), check_cancel);
+    {
+        std::unique_lock<std::mutex> locker(state->lock);
+        while (state->jobs_done < state->jobs.size()) {
+            state->cond.wait_for(locker, std::chrono::milliseconds(10));
+            check_cancel();
+        }
+    }
+
+    // Everything is done, the pool threads no longer touch the jobs.
+    this->did_interrupt = this->did_interrupt || state->cancelled;
+    for (auto &job : state->jobs) {
+        this->did_add = this->did_add || job.did_add;
+        this->did_overflow = this->did_overflow || job.did_overflow;
+        for (auto &comp : job.results) {
+            if (this->interrupted_or_overflowed()) {
+                return;
+            }
+            if (this->flags & expand_flag::for_completions) {
+                if (!this->resolved_completions->add(std::move(comp))) {
+                    this->did_overflow = true;
+                }
+            } else {
+                this->add_expansion_result(std::move(comp.completion));
+            }
+        }
+    }
+}
+
+void wildcard_expander_t::run_parallel_jobs(parallel_expansion_t *state,
+                                            const cancel_checker_t &cancel_checker) {
+    for (;;) {
+        size_t idx;
+        {
+            scoped_lock locker(state->lock);
+            if (state->next_job == state->jobs.size()) {
+                return;
+            }
+            idx = state->next_job++;
+        }
+        // Until it is done, this job is ours alone.
+        auto &job = state->jobs.at(idx);
+        if (!state->stop) {
+            completion_receiver_t receiver = state->receiver->subreceiver();
+            wildcard_expander_t expander(state->working_directory, state->flags, cancel_checker,
+                                         &receiver);
+            expander.may_parallelize = false;
+            expander.has_fuzzy_ancestor = state->has_fuzzy_ancestor;
+            expander.visited_files = state->visited_files;
+            expander.visited_files.insert(job.file_id);
+            expander.expand(job.path, state->wc_remainder.c_str(), state->prefix);
+            if (expander.did_overflow) {
+                state->stop = true;
+            }
+            job.results = receiver.take();
+            job.did_add = expander.did_add;
+            job.did_overflow = expander.did_overflow;
+        }
+        {
+            scoped_lock locker(state->lock);
+            state->jobs_done++;
+        }
+        state->cond.notify_all();
+    }
 }
 
 void wildcard_expander_t::expand_literal_intermediate_segment_with_fuzz(const wcstring &base_dir,
@@ -770,7 +950,9 @@ void wildcard_expander_t::expand_literal_intermediate_segment_with_fuzz(const wc
             continue;
         }
 
-        const auto match = string_fuzzy_match_string(wc_segment, entry->name);
+        const auto match =
+            string_fuzzy_match_string(wc_segment, entry->name, false /* anchor_start */,
+                                      this->flags.get(expand_flag::fuzzy_subsequence));
         if (!match || match->is_samecase_exact()) continue;
 
         // Note is_dir() may trigger a stat call.
@@ -35,13 +35,14 @@
 #include "fds.h"
 #include "flog.h"
 #include "wcstringutil.h"
+#include "wutil/gettext.rs.h"
 
 using cstring = std::string;
 
 const file_id_t kInvalidFileID{};
 
-/// Map used as cache by wgettext.
-static owning_lock<std::unordered_map<wcstring, wcstring>> wgettext_map;
+/// Map used as cache by wgettext, from the strings translated in Rust to copies of them.
+static owning_lock<std::unordered_map<const wchar_t *, wcstring>> wgettext_map;
 
 wcstring wgetcwd() {
     char cwd[PATH_MAX];
@@ -487,41 +488,23 @@ wcstring wbasename(wcstring path) {
     return path;
 }
 
-// Really init wgettext.
-static void wgettext_really_init() {
-    fish_bindtextdomain(PACKAGE_NAME, LOCALEDIR);
-    fish_textdomain(PACKAGE_NAME);
-}
-
-/// For wgettext: Internal init function. Automatically called when a translation is first
-/// requested.
-static void wgettext_init_if_necessary() {
-    static std::once_flag s_wgettext_init{};
-    std::call_once(s_wgettext_init, wgettext_really_init);
-}
-
 const wcstring &wgettext(const wchar_t *in) {
     // Preserve errno across this since this is often used in printing error messages.
     int err = errno;
-    wcstring key = in;
+    // The translation lives forever, so its address identifies it.
+    const wchar_t *translated = wgettext_ffi(in).str;
 
-    wgettext_init_if_necessary();
     auto wmap = wgettext_map.acquire();
-    wcstring &val = (*wmap)[key];
-    if (val.empty()) {
-        cstring mbs_in = wcs2zstring(key);
-        char *out = fish_gettext(mbs_in.c_str());
-        val = format_string(L"%s", out);
+    auto iter = wmap->find(translated);
+    if (iter == wmap->end()) {
+        iter = wmap->emplace(translated, translated).first;
     }
     errno = err;
 
     // The returned string is stored in the map.
-    // TODO: If we want to shrink the map, this would be a problem.
-    return val;
+    return iter->second;
 }
 
-const wchar_t *wgettext_ptr(const wchar_t *in) { return wgettext(in).c_str(); }
-
 int wmkdir(const wcstring &name, int mode) {
     cstring name_narrow = wcs2zstring(name);
     return mkdir(name_narrow.c_str(), mode);
@@ -111,12 +111,9 @@ std::wstring wdirname(std::wstring path);
 /// Wide character version of basename().
 std::wstring wbasename(std::wstring path);
 
-/// Wide character wrapper around the gettext function. For historic reasons, unlike the real
-/// gettext function, wgettext takes care of setting the correct domain, etc. using the textdomain
-/// and bindtextdomain functions. This should probably be moved out of wgettext, so that wgettext
-/// will be nothing more than a wrapper around gettext, like all other functions in this file.
+/// Translate a message with fish's message catalog for the current locale. This is implemented in
+/// Rust, see wutil/gettext.rs.
 const wcstring &wgettext(const wchar_t *in);
-const wchar_t *wgettext_ptr(const wchar_t *in);
 
 /// Wide character version of mkdir.
 int wmkdir(const wcstring &name, int mode);
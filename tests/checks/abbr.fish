@@ -158,6 +158,16 @@ echo $status
 # CHECKERR: abbr: Invalid function name: no space allowed
 # CHECK: 2
 
+abbr --query banana --condition true
+echo $status
+# CHECKERR: abbr: --condition option requires --add
+# CHECK: 2
+
+abbr --add peach --condition '' pear
+echo $status
+# CHECKERR: abbr: --condition argument cannot be empty
+# CHECK: 2
+
 # Erase all abbreviations
 abbr --erase (abbr --list)
 abbr --show
@@ -166,10 +176,12 @@ abbr --show
 abbr --add nonregex_name foo
 abbr --add regex_name --regex 'A[0-9]B' bar
 abbr --add !! --position anywhere --function replace_history
+abbr --add gst --condition 'git rev-parse --is-inside-work-tree' git status
 abbr --show
 # CHECK: abbr -a -- nonregex_name foo
 # CHECK: abbr -a --regex 'A[0-9]B' -- regex_name bar
 # CHECK: abbr -a --position anywhere --function replace_history -- !!
+# CHECK: abbr -a --condition 'git rev-parse --is-inside-work-tree' -- gst 'git status'
 
 # Confirm that this erases the old uvar
 # (slightly cheating since we haven't imported it as an abbr,
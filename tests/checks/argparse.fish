@@ -516,3 +516,14 @@ begin
     or echo No flag I
     #CHECK: No flag I
 end
+
+# Descriptions don't change the parsing, but must be for a known option
+begin
+    argparse -d 'h=Show help' -d 'name=The name' h/help n/name= -- -h --name foo
+    echo $_flag_h $_flag_name
+    #CHECK: -h foo
+    argparse -d 'nope=Not here' h/help -- -h
+    #CHECKERR: argparse: description 'nope=Not here' is not valid
+    argparse -d 'help' h/help -- -h
+    #CHECKERR: argparse: description 'help' is not valid
+end
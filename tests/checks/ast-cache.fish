@@ -0,0 +1,57 @@
+#RUN: %fish -C 'set -g fish %fish' %s
+
+set -g tmpdir (mktemp -d)
+set -gx XDG_CACHE_HOME $tmpdir/cache
+mkdir -p $XDG_CACHE_HOME/fish
+
+# Run fish without config files, so only our script gets cached, and print which asts came from
+# the cache.
+function run_cached
+    $fish --no-config --debug=ast-construction --debug-output=$tmpdir/log $argv
+    set -l fish_status $status
+    string match -e 'cached ast' <$tmpdir/log | string replace $tmpdir TMP
+    return $fish_status
+end
+
+echo 'function cached; echo cached $argv; end' >$tmpdir/script.fish
+run_cached -c "source $tmpdir/script.fish; cached one"
+# CHECK: cached one
+count $XDG_CACHE_HOME/fish/ast/*
+# CHECK: 1
+
+# The second time, the ast comes from the cache.
+run_cached -c "source $tmpdir/script.fish; cached two"
+# CHECK: cached two
+# CHECK: ast-construction: Using the cached ast of TMP/script.fish
+
+# With other features, the script is parsed again.
+run_cached --features qmark-noglob -c "source $tmpdir/script.fish; cached three"
+# CHECK: cached three
+run_cached -c "source $tmpdir/script.fish; cached four"
+# CHECK: cached four
+run_cached -c "source $tmpdir/script.fish; cached five"
+# CHECK: cached five
+# CHECK: ast-construction: Using the cached ast of TMP/script.fish
+
+# A changed file is parsed again.
+echo 'function cached; echo changed $argv; end' >$tmpdir/script.fish
+run_cached -c "source $tmpdir/script.fish; cached six"
+# CHECK: changed six
+
+# A broken cache file is ignored.
+for f in $XDG_CACHE_HOME/fish/ast/*
+    echo garbage >$f
+end
+run_cached -c "source $tmpdir/script.fish; cached seven"
+# CHECK: changed seven
+
+# Scripts with errors aren't cached, and still report their errors.
+rm $XDG_CACHE_HOME/fish/ast/*
+echo 'echo (' >$tmpdir/broken.fish
+run_cached -c "source $tmpdir/broken.fish" 2>/dev/null
+echo $status
+# CHECK: 1
+count $XDG_CACHE_HOME/fish/ast/*
+# CHECK: 0
+
+rm -r $tmpdir
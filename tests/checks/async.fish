@@ -0,0 +1,41 @@
+#RUN: %fish %s
+
+async out sh -c 'sleep 0.1; echo first; echo second'
+set -q out; or echo not set yet
+# CHECK: not set yet
+await out
+printf '<%s>\n' $out
+# CHECK: <first>
+# CHECK: <second>
+
+# Expanding the variable waits for the command the first time, and keeps $status.
+async implicit sh -c 'sleep 0.1; echo implicit'
+false
+printf '<%s>\n' $implicit $status
+# CHECK: <implicit>
+# CHECK: <1>
+set -q __fish_async_implicit; or echo no longer pending
+# CHECK: no longer pending
+
+# Functions run in the background in a separate fish.
+function produce
+    sleep 0.1
+    echo from function $argv
+end
+async fout produce arg
+set -q fout; or echo function not done yet
+# CHECK: function not done yet
+echo $fout
+# CHECK: from function arg
+
+# A plain wait also waits for async jobs.
+async waited sh -c 'sleep 0.1; echo done'
+wait
+await waited
+echo $waited
+# CHECK: done
+
+await nothing
+# CHECKERR: await: No async command is pending for 'nothing'
+async 'bad-name' true
+# CHECKERR: async: Invalid variable name 'bad-name'
@@ -112,4 +112,74 @@ bind --erase --user --preset \t
 bind \t
 # CHECK: bind --preset \t complete
 
+# Chords with a timeout
+bind -M insert jk --timeout 150 'set fish_bind_mode default'
+bind -M insert jk
+# CHECK: bind -M insert --timeout 150 jk 'set fish_bind_mode default'
+bind -M insert jk --timeout 0 true
+# CHECKERR: bind: 0: invalid integer
+# CHECKERR:
+# CHECKERR: {{.*}}checks/bind.fish (line {{\d+}}):
+# CHECKERR: bind -M insert jk --timeout 0 true
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help bind' for related documentation)
+bind -M insert --timeout 150 jk
+# CHECKERR: bind: invalid option combination
+# CHECKERR:
+# CHECKERR: {{.*}}checks/bind.fish (line {{\d+}}):
+# CHECKERR: bind -M insert --timeout 150 jk
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help bind' for related documentation)
+
+# Modes inheriting from other modes
+bind --new-mode mymode --inherits insert
+bind --new-mode other
+bind --user | string match -v '*backward-delete-char'
+# CHECK: bind --new-mode mymode --inherits insert
+# CHECK: bind --new-mode other
+# CHECK: bind -M bind_mode \cx true
+# CHECK: bind -M insert --timeout 150 jk 'set fish_bind_mode default'
+bind --list-modes
+# CHECK: bind_mode
+# CHECK: default
+# CHECK: insert
+# CHECK: mymode
+# CHECK: other
+bind --new-mode insert --inherits mymode
+# CHECKERR: bind: Mode 'insert' cannot inherit from itself
+bind --inherits default
+# CHECKERR: bind: invalid option combination
+# CHECKERR:
+# CHECKERR: {{.*}}checks/bind.fish (line {{\d+}}):
+# CHECKERR: bind --inherits default
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help bind' for related documentation)
+bind --erase --new-mode other
+bind --erase --new-mode other
+# CHECKERR: bind: No mode named 'other' was defined
+bind --list-modes | string match -e other
+# Nothing
+
+# Keyboard macros. Keys are only recorded interactively, so these are empty.
+bind --stop-recording empty
+# CHECKERR: bind: No macro is being recorded
+bind --record
+bind --record
+# CHECKERR: bind: A macro is already being recorded
+bind --play empty
+# CHECKERR: bind: Cannot play a macro while recording one
+bind --stop-recording empty
+bind --list-macros
+# CHECK: empty
+bind --play empty
+echo $status
+# CHECK: 0
+bind --play missing
+# CHECKERR: bind: No macro named 'missing'
+bind --play 'not valid'
+# CHECKERR: bind: not valid: invalid macro name. See `help identifiers`
+
 exit 0
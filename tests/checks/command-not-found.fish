@@ -1,4 +1,40 @@
 # RUN: %fish -C 'set -g fish %fish' %s
+
+# The builtin suggests similar commands from $PATH and asks the package databases.
+set -l cnf_dir (mktemp -d)
+printf '#!/bin/sh\n' >$cnf_dir/flounder
+printf '#!/bin/sh\necho extra/flowder\n' >$cnf_dir/pkgfile
+printf '#!/bin/sh\nexec /bin/sleep 30\n' >$cnf_dir/nix-locate
+chmod +x $cnf_dir/*
+begin
+    set -lx PATH $cnf_dir
+    set -l fish_command_not_found_backends pkgfile nix-index
+    set -l fish_command_not_found_timeout 1000
+    builtin fish_command_not_found flowder --an-arg
+    echo $status
+    # CHECK: 127
+    # CHECKERR: fish: Unknown command: flowder
+    # CHECKERR: Did you mean: flounder?
+    # CHECKERR: flowder may be found in the following packages:
+    # CHECKERR:   extra/flowder
+
+    # Answers are cached.
+    printf '#!/bin/sh\necho extra/other\n' >$cnf_dir/pkgfile
+    set fish_command_not_found_backends pkgfile
+    builtin fish_command_not_found flowder
+    # CHECKERR: fish: Unknown command: flowder
+    # CHECKERR: Did you mean: flounder?
+    # CHECKERR: flowder may be found in the following packages:
+    # CHECKERR:   extra/flowder
+
+    set fish_command_not_found_backends
+    builtin fish_command_not_found xyzzy
+    # CHECKERR: fish: Unknown command: xyzzy
+end
+rm -r $cnf_dir
+
+builtin fish_command_not_found
+# CHECKERR: fish_command_not_found: expected >= 1 arguments; got 0
 set -g PATH
 $fish -c "nonexistent-command-1234 banana rama"
 #CHECKERR: fish: Unknown command: nonexistent-command-1234
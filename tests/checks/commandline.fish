@@ -17,3 +17,58 @@ or echo Invalid $status
 commandline --input 'echo $$' --is-valid
 or echo Invalid $status
 # CHECK: Invalid 1
+
+commandline --input "echo 'a b' \$c" --tokenize
+# CHECK: echo
+# CHECK: a b
+# CHECK: $c
+
+commandline --input "echo 'a b' \$c" --tokens-raw
+# CHECK: echo
+# CHECK: 'a b'
+# CHECK: $c
+
+set -l ast (commandline --input "echo hi" --ast)
+string match -q '{"type":"job_list",*' -- $ast; and echo job_list
+# CHECK: job_list
+string match -q '*{"type":"token_base","token":"string","range":[0,4],"text":"echo"}*' -- $ast
+and echo command
+# CHECK: command
+string match -q '*{"type":"argument","range":[5,7],"text":"hi"}*' -- $ast; and echo argument
+# CHECK: argument
+
+commandline --input "echo hi" --ast --tokenize
+# CHECKERR: commandline: invalid option combination
+# CHECKERR:
+# CHECKERR: {{.*}}checks/commandline.fish (line {{\d+}}):
+# CHECKERR: commandline --input "echo hi" --ast --tokenize
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help commandline' for related documentation)
+
+commandline --input "echo hi" --insert-at 2 --replace-range 1 2 x
+# CHECKERR: commandline: invalid option combination
+# CHECKERR:
+# CHECKERR: {{.*}}checks/commandline.fish (line {{\d+}}):
+# CHECKERR: commandline --input "echo hi" --insert-at 2 --replace-range 1 2 x
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help commandline' for related documentation)
+
+commandline --input "echo hi" --insert-at two x
+# CHECKERR: commandline: two: invalid integer
+# CHECKERR:
+# CHECKERR: {{.*}}checks/commandline.fish (line {{\d+}}):
+# CHECKERR: commandline --input "echo hi" --insert-at two x
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help commandline' for related documentation)
+
+commandline --input "echo hi" --replace-range 4 2 x
+# CHECKERR: commandline: Range end 2 is before its start 4
+# CHECKERR:
+# CHECKERR: {{.*}}checks/commandline.fish (line {{\d+}}):
+# CHECKERR: commandline --input "echo hi" --replace-range 4 2 x
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help commandline' for related documentation)
@@ -153,6 +153,28 @@ complete -C'echo & complete_test_subcommand '
 complete -C'if while begin begin complete_test_subcommand '
 # CHECK: ok
 
+# The same inside (nested) command substitutions, quoted or not.
+complete -C'echo (not complete_test_subcommand '
+# CHECK: ok
+complete -C'echo $(begin; echo (and complete_test_subcommand '
+# CHECK: ok
+complete -C'echo "foo $(true | or complete_test_subcommand '
+# CHECK: ok
+function complete_test_nested_func
+end
+abbr -a complete_test_nested_abbr echo
+set -g complete_test_nested_var 1
+complete -C'echo (if complete_test_nested_f' | string replace -r '\t.*' ''
+# CHECK: complete_test_nested_func
+complete -C'echo (begin; complete_test_nested_a' | string replace -r '\t.*' ''
+# CHECK: complete_test_nested_abbr
+complete -C'echo (echo $complete_test_nested_v' | string replace -r '\t.*' ''
+# CHECK: $complete_test_nested_var
+abbr -e complete_test_nested_abbr
+# Options given to the keyword itself are still completed.
+complete -C'begin --hel'
+# CHECK: --help{{\t}}Display help and exit
+
 complete -C'for _ in ' | string collect >&- && echo completed some files
 # CHECK: completed some files
 
@@ -548,3 +570,97 @@ complete -C'dotty '
 # CHECK: .abc
 
 rm -r $tmpdir
+
+# Completions of a group are listed together, in the order the groups came up in.
+complete -c grouped -f -g Fruits -a 'pear apple'
+complete -c grouped -f -g Branches -a 'main dev'
+complete -c grouped -f -a 'zebra'
+complete -c grouped -l long -g Options
+complete -C'grouped '
+# CHECK: zebra
+# CHECK: dev
+# CHECK: main
+# CHECK: apple
+# CHECK: pear
+complete --json -C'grouped '
+# CHECK: [{"completion":"zebra","description":""},{"completion":"dev","description":"","group":"Branches"},{"completion":"main","description":"","group":"Branches"},{"completion":"apple","description":"","group":"Fruits"},{"completion":"pear","description":"","group":"Fruits"}]
+complete -c grouped
+# CHECK: complete grouped -l long -g Options
+# CHECK: complete --no-files grouped -a zebra
+# CHECK: complete --no-files grouped -g Branches -a 'main dev'
+# CHECK: complete --no-files grouped -g Fruits -a 'pear apple'
+
+# --no-sort keeps the order the completions came in.
+complete -c nosort -f -a 'beta gamma alpha'
+complete -C'nosort '
+# CHECK: alpha
+# CHECK: beta
+# CHECK: gamma
+complete --no-sort -C'nosort '
+# CHECK: beta
+# CHECK: gamma
+# CHECK: alpha
+complete --no-sort -c nosort
+# CHECKERR: complete: invalid option combination, --no-sort requires --do-complete
+# CHECKERR:
+# CHECKERR: {{.*}}checks/complete.fish (line {{\d+}}):
+# CHECKERR: complete --no-sort -c nosort
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help complete' for related documentation)
+
+# --cache reuses the arguments a completion computed, until the cache is flushed.
+set -g cached_calls 0
+function __cached_args
+    set -g cached_calls (math $cached_calls + 1)
+    echo call$cached_calls
+end
+complete -c cached -f -a '(__cached_args)'
+complete -c cached --cache 60
+complete -C'cached '
+# CHECK: call1
+complete -C'cached '
+# CHECK: call1
+complete -c cached
+# CHECK: complete --no-files cached -a '(__cached_args)'
+# CHECK: complete cached --cache 60
+complete -c cached --flush-cache
+complete -C'cached '
+# CHECK: call2
+complete --flush-cache
+complete -C'cached '
+# CHECK: call3
+complete -c cached --cache 0
+complete -C'cached '
+# CHECK: call4
+complete -C'cached '
+# CHECK: call5
+complete --cache 60
+# CHECKERR: complete: invalid option combination, --cache requires a command
+# CHECKERR:
+# CHECKERR: {{.*}}checks/complete.fish (line {{\d+}}):
+# CHECKERR: complete --cache 60
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help complete' for related documentation)
+
+# Options can come from a function's argparse spec.
+function greet
+    set -l spec x
+    argparse $spec -- $argv
+    argparse -d 'name=Who to greet' -d 'loud=Shout it' -d v=Verbose n/name= loud v '#-count' -- $argv
+end
+complete -c greet --from-argparse greet
+complete -C'greet --'
+# CHECK: --loud{{\t}}Shout it
+# CHECK: --name{{\t}}Who to greet
+complete -c greet
+# CHECK: complete greet --from-argparse greet
+complete --from-argparse greet
+# CHECKERR: complete: invalid option combination, --from-argparse requires a command
+# CHECKERR:
+# CHECKERR: {{.*}}checks/complete.fish (line {{\d+}}):
+# CHECKERR: complete --from-argparse greet
+# CHECKERR: ^
+# CHECKERR:
+# CHECKERR: (Type 'help complete' for related documentation)
@@ -0,0 +1,35 @@
+#RUN: %fish %s
+
+coproc upper tr a-z A-Z
+set -l pid $last_pid
+echo hello >&$upper[1]
+exec --close $upper[1]
+read -l line <&$upper[2]
+echo $line
+#CHECK: HELLO
+wait $pid
+echo $status
+#CHECK: 0
+
+# The pipes are closed when the function returns, which ends the coprocess.
+function run_cat
+    coproc pipe cat
+    echo $last_pid
+end
+wait (run_cat)
+echo waited
+#CHECK: waited
+
+coproc 'not valid' cat
+#CHECKERR: coproc: not valid: invalid variable name. See `help identifiers`
+#CHECKERR: checks/coproc.fish (line {{\d+}}):
+#CHECKERR: coproc 'not valid' cat
+#CHECKERR: ^{{.*}}
+#CHECKERR: (Type 'help coproc' for related documentation)
+
+coproc onlyname
+#CHECKERR: coproc: expected >= 2 arguments; got 1
+#CHECKERR: checks/coproc.fish (line {{\d+}}):
+#CHECKERR: coproc onlyname
+#CHECKERR: ^{{.*}}
+#CHECKERR: (Type 'help coproc' for related documentation)
@@ -0,0 +1,22 @@
+# RUN: %fish -C 'set -g fish %fish' %s
+
+# A fatal signal writes a report to the data directory, and says where it is.
+set -l dir (mktemp -d)
+XDG_DATA_HOME=$dir $fish -c 'echo before; kill -SEGV $fish_pid; echo after' 2>&1 |
+    string replace -r 'fish-crash-\d+' fish-crash-PID | string replace $dir DIR
+# CHECK: before
+# CHECK: fish: crashed. A report was written to DIR/fish/fish-crash-PID.txt
+# CHECKERR: {{.*}}terminated by signal SIGSEGV{{.*}}
+
+set -l report $dir/fish/fish-crash-*.txt
+count $report
+# CHECK: 1
+string match -r '^(fish crash report|version: .*|signal: .*|backtrace:|last command: .*)$' <$report |
+    string replace -r 'version: .*' version
+# CHECK: fish crash report
+# CHECK: version
+# CHECK: signal: SIGSEGV
+# CHECK: backtrace:
+# CHECK: last command: (none)
+
+rm -r $dir
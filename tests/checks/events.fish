@@ -0,0 +1,29 @@
+#RUN: %fish %s
+
+# fish_job_started gets the command line and the pids of the job's external commands.
+function on_start --on-event fish_job_started
+    echo "started: $fish_event_command"
+    echo (count $fish_event_pids) pids
+end
+command true | command true
+# CHECK: started: command true | command true
+# CHECK: 2 pids
+
+sleep 0.1 &
+# CHECK: started: sleep 0.1 &
+# CHECK: 1 pids
+test "$fish_event_pids" = ""; and echo payload is gone
+# CHECK: payload is gone
+wait
+
+# Jobs of only builtins and functions don't emit it.
+echo builtin
+# CHECK: builtin
+
+# Neither do the jobs of the handler.
+functions -e on_start
+function on_start --on-event fish_job_started
+    command echo "handler for $fish_event_command"
+end
+command true
+# CHECK: handler for command true
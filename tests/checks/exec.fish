@@ -10,7 +10,63 @@ not exec cat <nosuchfile
 echo "neg failed: $status"
 #CHECK: neg failed: 0
 
+set -l tmpdir (mktemp -d)
+exec --open logfd $tmpdir/log
+echo first >&$logfd
+echo second >&$logfd
+cat $tmpdir/log
+#CHECK: first
+#CHECK: second
+
+exec --open --read infd $tmpdir/log
+read -l line <&$infd
+echo $line
+#CHECK: first
+exec --close $infd $logfd
+echo $status
+#CHECK: 0
+exec --close $logfd 2>/dev/null
+echo $status
+#CHECK: 1
+
+# Fds opened in a function are closed when it returns.
+function open_in_function
+    exec --open --append fd $argv[1]
+    echo appended >&$fd
+    echo $fd
+end
+set -l leaked (open_in_function $tmpdir/log)
+cat $tmpdir/log
+#CHECK: first
+#CHECK: second
+#CHECK: appended
+exec --close $leaked 2>/dev/null
+echo $status
+#CHECK: 1
+
+# With --inherit, commands get the fd without a redirection.
+exec --open --inherit --read passed $tmpdir/log
+$fish -c "read -l line <&$passed; echo \$line"
+#CHECK: first
+exec --open --read kept $tmpdir/log
+$fish -c "read -l line <&$kept" 2>/dev/null
+or echo not inherited
+#CHECK: not inherited
+exec --close $passed $kept
+
+exec --open 'bad name' $tmpdir/log
+#CHECKERR: exec: bad name: invalid variable name. See `help identifiers`
+exec --read foo $tmpdir/log
+#CHECKERR: exec: invalid option combination, --append, --read and --inherit require --open
+exec --inherit foo $tmpdir/log
+#CHECKERR: exec: invalid option combination, --append, --read and --inherit require --open
+rm -r $tmpdir
+
 # This needs to be last, because it actually runs exec.
+function on_replace --on-event fish_preexec_replace
+    echo replacing with $argv
+end
 exec cat </dev/null
+# CHECK: replacing with cat
 echo "not reached"
 
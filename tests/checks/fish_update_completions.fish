@@ -0,0 +1,33 @@
+#RUN: %fish %s
+
+set -l dir (mktemp -d)
+mkdir $dir/man1 $dir/out
+printf '%s\n' '.TH FOO 1' '.SH NAME' 'foo \- do things' '.SH OPTIONS' '.TP' '\fB\-a\fR, \fB\-\-all\fR' \
+    'Show everything.' '.TP' '\fB\-\-color\fR[=\fIWHEN\fR]' 'Use colors.' >$dir/man1/foo.1
+printf '%s\n' '.TH BAR 1' '.SH NAME' 'bar \- nothing' >$dir/man1/bar.1
+
+fish_update_completions --stdout $dir/man1/foo.1
+# CHECK: # foo
+# CHECK: # Autogenerated from man page {{.*}}/man1/foo.1
+# CHECK: complete -c foo -s a -l all -d 'Show everything'
+# CHECK: complete -c foo -l color -d 'Use colors'
+# CHECK:
+
+fish_update_completions --verbose 1 --directory $dir/out $dir/man1/foo.1 $dir/man1/bar.1
+# CHECKERR: {{.*}}/man1/bar.1 contains no options or is unparsable
+# CHECKERR: Successfully parsed 1 / 2 pages
+ls $dir/out
+# CHECK: foo.fish
+
+# Completions generated earlier are removed, others are not.
+echo 'complete -c mine' >$dir/out/mine.fish
+fish_update_completions --directory $dir/out $dir/man1/bar.1
+ls $dir/out
+# CHECK: mine.fish
+
+fish_update_completions --verbose loud
+# CHECKERR: fish_update_completions: loud: invalid integer
+echo $status
+# CHECK: 2
+
+rm -r $dir
@@ -84,6 +84,24 @@ touch bar foo/bar
 string join \n **/bar | sort
 # CHECK: bar
 # CHECK: foo/bar
+rm -Rf *
+
+# Many directories are expanded in parallel, with the same results.
+for i in (seq 40)
+    mkdir -p wide/$i/deep
+    touch wide/$i/deep/file wide/$i/file
+end
+# A symlink loop is not followed.
+ln -s .. wide/7/deep/up
+count **/file
+# CHECK: 80
+count wide/*/deep/file
+# CHECK: 40
+echo wide/*/deep/file | string split ' ' | head -n 3
+# CHECK: wide/1/deep/file
+# CHECK: wide/2/deep/file
+# CHECK: wide/3/deep/file
+rm -Rf *
 
 # Clean up.
 cd $oldpwd
@@ -62,6 +62,28 @@ builtin history --contains save
 #CHECKERR: history: save: subcommand takes no options
 builtin history -t merge
 #CHECKERR: history: merge: subcommand takes no options
+builtin history clear --show-status
+#CHECKERR: history: clear: subcommand takes no options
+builtin history export xyz
+#CHECKERR: history: export: expected 0 arguments; got 1
+builtin history import --prefix
+#CHECKERR: history: import: subcommand takes no options
+builtin history import abc def
+#CHECKERR: history: import: expected 1 arguments; got 2
+builtin history export --format=csv
+#CHECKERR: history: invalid history format 'csv'
+builtin history clear --status 1
+#CHECKERR: history: clear: subcommand takes no options
+builtin history search --before nonsense
+#CHECKERR: history: invalid time 'nonsense'
+builtin history merge --from 'not valid'
+#CHECKERR: history: invalid history session name 'not valid'
+builtin history search --status -1
+#CHECKERR: history: -1: invalid integer
+builtin history search --regex 'abc['
+#CHECKERR: history: Regular expression compile error: missing terminating ] for character class
+#CHECKERR: history: abc[
+#CHECKERR: history:    ^
 
 # Now do a history command that should succeed so we exit with a zero,
 # success, status.
@@ -70,3 +92,5 @@ builtin history save
 set -g fish_private_mode 1
 builtin history merge
 #CHECKERR: history: can't merge history in private mode
+builtin history import /dev/null
+#CHECKERR: history: can't import history in private mode
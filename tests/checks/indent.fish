@@ -226,6 +226,10 @@ echo < stdin >>appended yes 2>&1 no > stdout maybe 2>&    4 | cat 2>| cat
 ' | $fish_indent
 #CHECK: echo <stdin >>appended yes 2>&1 no >stdout maybe 2>&4 | cat 2>| cat
 
+echo 'gzip a.log &--queue;gzip b.log  &' | $fish_indent
+#CHECK: gzip a.log &--queue
+#CHECK: gzip b.log &
+
 
 # issue 7252
 echo -n '
@@ -432,3 +436,135 @@ echo 'begin
 echo 'multiline-\\
 -word' | $fish_indent --check
 echo $status #CHECK: 0
+
+echo 'if true
+echo hi
+end' | $fish_indent --indent-width=2
+# CHECK: if true
+# CHECK: {{^}}  echo hi
+# CHECK: end
+
+echo 'if true
+echo hi
+end' | $fish_indent --tabs | string escape
+# CHECK: if\ true
+# CHECK: \techo\ hi
+# CHECK: end
+
+$fish_indent --indent-width=0 </dev/null
+# CHECKERR: fish_indent: Invalid width '0'
+
+echo 'echo aaaa | cat | cat | cat' | $fish_indent --max-width=20
+# CHECK: echo aaaa |
+# CHECK: {{^}}    cat |
+# CHECK: {{^}}    cat |
+# CHECK: {{^}}    cat
+
+echo 'test -n aaaa && echo bbbb | cat && true' | $fish_indent --max-width=22
+# CHECK: test -n aaaa &&
+# CHECK: {{^}}    echo bbbb | cat &&
+# CHECK: {{^}}    true
+
+# Jobs which fit, and jobs that already span lines, are left alone.
+echo 'echo aaaa | cat
+echo aaaa | # comment
+cat | cat | cat' | $fish_indent --max-width=20
+# CHECK: echo aaaa | cat
+# CHECK: echo aaaa | # comment
+# CHECK: cat | cat | cat
+
+echo 'begin; echo a; echo b; end' | $fish_indent --collapse-begin
+# CHECK: begin; echo a; echo b; end
+
+echo 'begin; echo a; end
+begin; if true; echo a; end; end' | $fish_indent
+# CHECK: begin
+# CHECK: {{^}}    echo a
+# CHECK: end
+# CHECK: begin
+# CHECK: {{^}}    if true
+# CHECK: {{^}}        echo a
+# CHECK: {{^}}    end
+# CHECK: end
+
+# Nested blocks are not collapsed.
+echo 'begin; if true; echo a; end; end' | $fish_indent --collapse-begin
+# CHECK: begin
+# CHECK: {{^}}    if true
+# CHECK: {{^}}        echo a
+# CHECK: {{^}}    end
+# CHECK: end
+
+# Files can carry their own style for --write and --check.
+set -l tmpdir (mktemp -d)
+printf '%s\n' '#!/usr/bin/env fish' '# fish_indent: --indent-width=2 --collapse-begin' \
+    'if true' 'echo hi' 'end' 'begin; echo a; end' >$tmpdir/style.fish
+$fish_indent --write $tmpdir/style.fish
+cat $tmpdir/style.fish
+# CHECK: #!/usr/bin/env fish
+# CHECK: # fish_indent: --indent-width=2 --collapse-begin
+# CHECK: if true
+# CHECK: {{^}}  echo hi
+# CHECK: end
+# CHECK: begin; echo a; end
+$fish_indent --check $tmpdir/style.fish
+echo $status
+# CHECK: 0
+
+# Options on the command line win.
+$fish_indent --check --indent-width=4 $tmpdir/style.fish
+echo $status
+# CHECKERR: {{.*}}/style.fish
+# CHECK: 1
+
+printf '%s\n' '# fish_indent: --bogus' 'echo hi' >$tmpdir/bogus.fish
+$fish_indent --write $tmpdir/bogus.fish
+echo $status
+# CHECKERR: fish_indent: {{.*}}/bogus.fish: Invalid style option '--bogus'
+# CHECK: 1
+rm -r $tmpdir
+
+echo 'echo  hi' | $fish_indent --diff
+echo $status
+# CHECK: --- -
+# CHECK: +++ -
+# CHECK: @@ -1 +1 @@
+# CHECK: -echo  hi
+# CHECK: +echo hi
+# CHECK: 1
+
+# Directories are searched for fish scripts, skipping hidden ones.
+set -l tmpdir (mktemp -d)
+mkdir -p $tmpdir/sub $tmpdir/.hidden
+printf '%s\n' 'if true' 'echo hi' 'end' >$tmpdir/sub/bad.fish
+printf '%s\n' 'if true' 'echo hi' 'end' >$tmpdir/.hidden/bad.fish
+printf '%s\n' 'if true' 'echo hi' 'end' >$tmpdir/bad.txt
+printf '%s\n' 'echo hi' >$tmpdir/good.fish
+$fish_indent --check $tmpdir
+echo $status
+# CHECKERR: {{.*}}/sub/bad.fish
+# CHECK: 1
+
+$fish_indent --diff $tmpdir/
+echo $status
+# CHECK: --- a/{{.*}}/sub/bad.fish
+# CHECK: +++ b/{{.*}}/sub/bad.fish
+# CHECK: @@ -1,3 +1,3 @@
+# CHECK: if true
+# CHECK: -echo hi
+# CHECK: +    echo hi
+# CHECK: end
+# CHECK: 1
+
+$fish_indent --write $tmpdir
+$fish_indent --check $tmpdir
+echo $status
+# CHECK: 0
+cat $tmpdir/sub/bad.fish $tmpdir/.hidden/bad.fish
+# CHECK: if true
+# CHECK: {{^}}    echo hi
+# CHECK: end
+# CHECK: if true
+# CHECK: {{^}}echo hi
+# CHECK: end
+rm -r $tmpdir
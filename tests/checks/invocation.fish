@@ -79,6 +79,22 @@ string match -rq "echo thisshouldneverbeintheconfig" < $tmp/full.prof
 and echo matched
 # CHECK: matched
 
+# Structured profiles nest function calls and command substitutions.
+$fish --no-config --profile $tmp/json.prof --profile-format json -c 'function f; true; end; f (echo x)'
+string match -rq '"type":"function","command":"f","self_time":\d+,"total_time":\d+,"children":\[{"type":"job","command":"true",' < $tmp/json.prof
+and echo matched
+# CHECK: matched
+string match -rq '"type":"command-substitution","command":"echo x","self_time":\d+,"total_time":\d+,"children":\[{"type":"job","command":"echo x",' < $tmp/json.prof
+and echo matched
+# CHECK: matched
+$fish --no-config --profile $tmp/folded.prof --profile-format folded -c 'function f; true; end; f'
+string match -r '^f;f;true \d+$' < $tmp/folded.prof | string replace -r '\d+$' N
+# CHECK: f;f;true N
+$fish --profile-format bogus -c true
+echo $status
+# CHECKERR: fish: Invalid profile format 'bogus'
+# CHECK: 1
+
 $fish --no-config -c 'echo notprinted; echo foo | exec true; echo banana'
 # CHECKERR: fish: The 'exec' command can not be used in a pipeline
 # CHECKERR: echo notprinted; echo foo | exec true; echo banana
@@ -130,3 +130,133 @@ function thud --on-job-exit $truepid
     echo "thud called"
 end
 # CHECK: thud called
+
+# Exit handlers get information about the job that exited.
+sh -c 'sleep .2; exit 3' &
+set -l shpid $last_pid
+function report_exit --on-process-exit $shpid
+    echo "process: $fish_event_command, status $fish_event_status, signal '$fish_event_signal'"
+    test "$fish_event_pids" = $shpid; and echo pids match
+    test $fish_event_duration -ge 100; and echo took a while
+end
+wait
+# CHECK: process: sh -c sleep .2; exit 3, status 3, signal ''
+# CHECK: pids match
+# CHECK: took a while
+set -q fish_event_status; or echo payload is gone
+# CHECK: payload is gone
+
+# jobs --json describes each job and its processes.
+sleep 1 &
+jobs --json | string match -rq '^\{"id":\d+,"pgid":\d+,"state":"running","command":"sleep 1","processes":\[\{"pid":\d+,"command":"sleep","completed":false,"wall_ms":[\d.]+'
+and echo json ok
+# CHECK: json ok
+kill $last_pid
+
+# --job-scope handles a function's background jobs when it returns.
+function scoped_wait --job-scope wait
+    sh -c 'sleep .2; echo waited for' &
+end
+scoped_wait
+echo returned
+# CHECK: waited for
+# CHECK: returned
+
+function scoped_cancel --job-scope cancel
+    sleep 10 &
+    echo $last_pid
+end
+set -l cancelled (scoped_cancel)
+wait $cancelled
+echo $status
+# CHECK: 143
+
+function scoped_disown --job-scope disown
+    sleep .1 &
+end
+scoped_disown
+jobs -q; or echo no jobs
+# CHECK: no jobs
+
+functions scoped_cancel | string match -r 'function .*'
+# CHECK: function scoped_cancel --job-scope cancel
+
+function scoped_bogus --job-scope bogus
+end
+# CHECKERR: {{.*}}checks/jobs.fish (line {{\d+}}): function: bogus: invalid job scope
+# CHECKERR: function scoped_bogus --job-scope bogus
+# CHECKERR: ^
+functions -q scoped_bogus; or echo not defined
+# CHECK: not defined
+
+# fish_max_background_jobs makes further background launches wait for a free slot.
+begin
+    set -l fish_max_background_jobs 1
+    sleep .3 &
+    set -l first $last_pid
+    sleep .1 &
+    # The first job must have finished before the second could start.
+    contains -- $first (jobs -p); or echo first job done
+end
+# CHECK: first job done
+wait
+
+# So do jobs started with &--queue.
+begin
+    set -l fish_max_background_jobs 1
+    sleep .3 &--queue
+    set -l first $last_pid
+    sleep .1 &--queue
+    contains -- $first (jobs -p); or echo first queued job done
+end
+# CHECK: first queued job done
+wait
+
+# fish_timeout signals commands which run too long, and sets a distinct status.
+fish_timeout=100ms sleep 5
+echo $status
+# CHECK: 120
+fish_timeout=5s sh -c 'exit 3'
+echo $status
+# CHECK: 3
+fish_timeout=bogus true
+# CHECKERR: warning: Invalid value for fish_timeout: 'bogus'
+
+# wait --pid waits for processes that are not our children.
+set -l foreign_pid ($fish -c 'sleep .3 >/dev/null 2>&1 </dev/null &; disown; echo $last_pid')
+wait --pid $foreign_pid
+echo $status
+# CHECK: 0
+sleep .1 &
+wait --pid $last_pid
+echo $status
+# CHECK: 0
+wait --pid 2147483647
+# CHECKERR: wait: Could not find a process with id '2147483647'
+wait --pid notapid
+# CHECKERR: wait: 'notapid' is not a valid process id
+
+# fish_pipe_buffering=line makes external commands write to a terminal, which is relayed as-is.
+fish_pipe_buffering=line sh -c 'test -t 1 && echo tty; printf "a\nb\n"' | string escape
+# CHECK: tty
+# CHECK: a
+# CHECK: b
+sh -c 'test -t 1 || echo pipe' | cat
+# CHECK: pipe
+fish_pipe_buffering=bogus true | true
+# CHECKERR: warning: Invalid value for fish_pipe_buffering: 'bogus'
+
+# fish_nice adds to the niceness of external commands.
+set -l base_niceness (nice)
+fish_nice=3 nice | read -l niceness
+math $niceness - $base_niceness
+# CHECK: 3
+fish_nice=bogus command true
+# CHECKERR: warning: Invalid value for fish_nice: 'bogus'
+
+# A fish started with exec takes over our background jobs.
+# This needs to be last, because it replaces this shell.
+sleep .2 &
+exec $fish -c "jobs -c; wait; jobs -q; or echo waited"
+# CHECK: sleep
+# CHECK: waited
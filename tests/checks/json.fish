@@ -0,0 +1,69 @@
+#RUN: %fish %s
+# Test the --json option of the introspection builtins.
+
+set -g jsontest 1 'a"b'
+set --show --json jsontest
+# CHECK: [{"name":"jsontest","read_only":false,"scopes":[{"scope":"global","exported":false,"path":false,"values":["1","a\"b"]}],"inherited":null}]
+
+set --json -g | string match -q '*{"name":"jsontest","scope":"global","exported":false,"path":false,"values":["1","a\\\\"b"]}*'
+and echo found
+# CHECK: found
+
+set --names --json | string match -q '*"jsontest"*'
+and echo found
+# CHECK: found
+
+set --json jsontest 2
+# CHECKERR: set: invalid option combination
+# CHECKERR:
+# CHECKERR: checks/json.fish (line {{\d+}}):
+# CHECKERR: set --json jsontest 2
+# CHECKERR: ^
+# CHECKERR: (Type 'help set' for related documentation)
+
+function jsonfunc -a first -d 'A function'
+    echo $first
+end
+functions --json jsonfunc | string match -r '"name":"jsonfunc","description":"A function","path":"[^"]*json.fish","line":\d+,"autoloaded":false,"shadow_scope":true,"arguments":\["first"\],"copied_from":null'
+# CHECK: "name":"jsonfunc","description":"A function","path":"{{.*}}json.fish","line":{{\d+}},"autoloaded":false,"shadow_scope":true,"arguments":["first"],"copied_from":null
+
+functions --json | string match -q '*"jsonfunc"*'
+and echo found
+# CHECK: found
+
+abbr -a jsonabbr 'echo hi'
+abbr --show --json
+# CHECK: [{"name":"jsonabbr","regex":null,"position":"command","replacement":"echo hi","function":false,"set_cursor":null,"from_universal":false}]
+abbr --list --json
+# CHECK: ["jsonabbr"]
+abbr --json
+# CHECKERR: abbr: --json option requires --show or --list
+
+bind --json --list-modes | string match -q '*"default"*'
+and echo found
+# CHECK: found
+
+complete -c jsoncmd -l all -d 'Everything'
+complete --json -c jsoncmd
+# CHECK: [{"command":"jsoncmd","path":false,"option_type":"long","option":"all","description":"Everything","group":"","arguments":"","conditions":[],"no_files":false,"force_files":false,"requires_param":false,"keep_order":false}]
+complete --json -C 'jsoncmd --a'
+# CHECK: [{"completion":"jsoncmd --all","description":"Everything"}]
+
+true | false
+status last-pipeline --json | string match -r '^\[\{"status":0,"wall":[\d.]+,"user":[\d.]+,"sys":[\d.]+\},\{"status":1,'
+# CHECK: [{"status":0,"wall":{{[\d.]+}},"user":{{[\d.]+}},"sys":{{[\d.]+}}},{"status":1,
+
+status features --json | string match -q '[{"name":"stderr-nocaret","enabled":*'
+and echo found
+# CHECK: found
+
+status --json
+# CHECK: {"login":false,"job_control":"interactive"}
+
+status current-command --json
+# CHECKERR: status: current-command: --json is not supported
+# CHECKERR:
+# CHECKERR: checks/json.fish (line {{\d+}}):
+# CHECKERR: status current-command --json
+# CHECKERR: ^
+# CHECKERR: (Type 'help status' for related documentation)
@@ -0,0 +1,42 @@
+#RUN: %fish -C 'set -l fish %fish' %s
+
+function lsp_msg
+    printf 'Content-Length: %d\r\n\r\n%s' (string length -- $argv[1]) $argv[1]
+end
+
+# Print each response on a line of its own.
+function lsp_out
+    string replace -ra 'Content-Length: \d+\r' '' | string match -rv '^\r?$'
+end
+
+begin
+    lsp_msg '{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}'
+    lsp_msg '{"jsonrpc":"2.0","method":"initialized","params":{}}'
+    lsp_msg '{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///t.fish","languageId":"fish","version":1,"text":"end\\n"}}}'
+    lsp_msg '{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///t.fish","version":2},"contentChanges":[{"text":"if true;echo hi;end\\n"}]}}'
+    lsp_msg '{"jsonrpc":"2.0","id":2,"method":"textDocument/formatting","params":{"textDocument":{"uri":"file:///t.fish"},"options":{"tabSize":4,"insertSpaces":true}}}'
+    lsp_msg '{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///t.fish","version":3},"contentChanges":[{"text":"function foo\\nend\\nfoo\\nstatus is-log"}]}}'
+    lsp_msg '{"jsonrpc":"2.0","id":"def","method":"textDocument/definition","params":{"textDocument":{"uri":"file:///t.fish"},"position":{"line":2,"character":1}}}'
+    lsp_msg '{"jsonrpc":"2.0","id":4,"method":"textDocument/completion","params":{"textDocument":{"uri":"file:///t.fish"},"position":{"line":3,"character":13}}}'
+    lsp_msg '{"jsonrpc":"2.0","id":5,"method":"textDocument/hover","params":{}}'
+    lsp_msg 'not json'
+    lsp_msg '{"jsonrpc":"2.0","id":6,"method":"shutdown"}'
+    lsp_msg '{"jsonrpc":"2.0","method":"exit"}'
+end | $fish --lsp | lsp_out
+echo $pipestatus[2]
+# CHECK: {"jsonrpc":"2.0","id":1,"result":{"capabilities":{"textDocumentSync":1,"completionProvider":{"triggerCharacters":["-","$"]},"definitionProvider":true,"documentFormattingProvider":true},"serverInfo":{"name":"fish","version":"{{.+}}"}}}
+# CHECK: {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///t.fish","diagnostics":[{"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":3}},"severity":1,"source":"fish","message":"'end' outside of a block"}]}}
+# CHECK: {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///t.fish","diagnostics":[]}}
+# CHECK: {"jsonrpc":"2.0","id":2,"result":[{"range":{"start":{"line":0,"character":0},"end":{"line":1,"character":0}},"newText":"if true\n    echo hi\nend\n"}]}
+# CHECK: {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///t.fish","diagnostics":[]}}
+# CHECK: {"jsonrpc":"2.0","id":"def","result":{"uri":"file:///t.fish","range":{"start":{"line":0,"character":9},"end":{"line":0,"character":12}}}}
+# CHECK: {"jsonrpc":"2.0","id":4,"result":[{"label":"is-login","detail":"Test if this is a login shell","textEdit":{"range":{"start":{"line":3,"character":7},"end":{"line":3,"character":13}},"newText":"is-login"}}]}
+# CHECK: {"jsonrpc":"2.0","id":5,"error":{"code":-32601,"message":"Unknown method 'textDocument/hover'"}}
+# CHECK: {"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"Parse error"}}
+# CHECK: {"jsonrpc":"2.0","id":6,"result":null}
+# CHECK: 0
+
+# Exiting without a shutdown is an error.
+lsp_msg '{"jsonrpc":"2.0","method":"exit"}' | $fish --lsp
+echo $status
+# CHECK: 1
@@ -0,0 +1,44 @@
+#RUN: %fish -C 'set -g fish %fish' %s
+# Non-interactive shells only set up the terminal once something needs it.
+
+set -g tmpdir (mktemp -d)
+
+# Run fish without config files and print how often it set up the terminal.
+function count_curses_setup
+    $fish --no-config --debug=term-support --debug-output=$tmpdir/log $argv
+    # Each setup logs each of the curses variables.
+    string match -e 'curses var TERM ' <$tmpdir/log | count
+end
+
+count_curses_setup -c 'echo simple command'
+# CHECK: simple command
+# CHECK: 0
+
+# Changing the terminal variables doesn't set it up either.
+count_curses_setup -c 'set -gx TERM dumb; set -gx TERMINFO /nonexistent; echo changed'
+# CHECK: changed
+# CHECK: 0
+
+echo 'echo script' >$tmpdir/script.fish
+count_curses_setup $tmpdir/script.fish
+# CHECK: script
+# CHECK: 0
+
+# Reading from a pipe doesn't need the terminal.
+echo input | count_curses_setup -c 'read -l var; echo $var'
+# CHECK: input
+# CHECK: 0
+
+# Builtins which need the terminal set it up, once.
+count_curses_setup -c 'set_color red >/dev/null; set_color normal >/dev/null; echo colored'
+# CHECK: colored
+# CHECK: 1
+count_curses_setup -c 'bind >/dev/null; echo bound'
+# CHECK: bound
+# CHECK: 1
+
+# Once it is set up, changes to the terminal variables set it up again.
+count_curses_setup -c 'set_color normal >/dev/null; set -gx TERM dumb'
+# CHECK: 2
+
+rm -r $tmpdir
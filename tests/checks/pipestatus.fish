@@ -202,3 +202,17 @@ echo 'Not hung'
 #CHECKERR: warning: An error occurred while redirecting file '/not/a/valid/path'
 #CHECKERR: warning: Path '/not' does not exist
 #CHECK: Not hung
+
+# pipestatus_duration has one entry per process, in milliseconds.
+command sleep 0.2 | true | command true
+set -l durations $pipestatus_duration
+count $durations
+#CHECK: 3
+test $durations[1] -ge 150; and echo slow first stage
+#CHECK: slow first stage
+
+# status last-pipeline prints the status and times of each process.
+true | command false
+status last-pipeline | string replace -r '\t.*' ''
+#CHECK: 0
+#CHECK: {{1|255}}
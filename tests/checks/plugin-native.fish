@@ -0,0 +1,68 @@
+#RUN: %fish -C 'set -g plugin %fish_test_plugin' %s
+# Test loading a native plugin, with fish_test_plugin.
+
+plugin load $plugin
+echo $status
+# CHECK: fish_test_plugin loaded
+# CHECK: 0
+
+plugin list
+# CHECK: fish_test_plugin	{{.*}}libfish_test_plugin.so
+# CHECK: 	builtin	plugin_greet
+# CHECK: 	event	--on-event plugin_test_event
+# CHECK: 	event	--on-variable plugin_watched
+
+type -t plugin_greet
+# CHECK: builtin
+builtin -n | string match plugin_greet
+# CHECK: plugin_greet
+
+# The builtin reads $greeting and sets $plugin_greeted.
+set -g greeting Hi there
+plugin_greet world fish
+echo $status
+# CHECK: Hi, world!
+# CHECK: Hi, fish!
+# CHECK: 0
+set -S plugin_greeted
+# CHECK: $plugin_greeted: set in global scope, unexported, with 2 elements
+# CHECK: $plugin_greeted[1]: |world|
+# CHECK: $plugin_greeted[2]: |fish|
+
+set -e greeting
+plugin_greet 'new world'
+# CHECK: Hello, new world!
+echo $plugin_greeted
+# CHECK: new world
+
+plugin_greet
+echo $status
+# CHECKERR: plugin_greet: expected a name
+# CHECK: 2
+
+# Event handlers get the name they were registered for and the arguments of the event.
+emit plugin_test_event a 'b c'
+# CHECK: event: plugin_test_event a b c
+set -g plugin_watched 1
+# CHECK: variable: plugin_watched VARIABLE SET plugin_watched
+emit other_event
+
+plugin load $plugin
+echo $status
+# CHECKERR: plugin: plugin 'fish_test_plugin' is already loaded
+# CHECK: 1
+
+plugin unload fish_test_plugin
+echo $status
+# CHECK: 0
+plugin list
+builtin -n | string match plugin_greet
+functions -a | string match '__fish_plugin_event_*'
+emit plugin_test_event
+set -g plugin_watched 2
+
+# It can be loaded again after it was unloaded.
+plugin load $plugin
+# CHECK: fish_test_plugin loaded
+plugin_greet again
+# CHECK: Hello, again!
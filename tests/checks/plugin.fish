@@ -0,0 +1,56 @@
+#RUN: %fish %s
+# Test the plugin builtin. This works without WebAssembly support, so no plugins are loaded.
+
+plugin list
+echo $status
+# CHECK: 0
+
+plugin segment nothing
+echo $status
+# CHECKERR: plugin: no plugin provides the prompt segment 'nothing'
+# CHECK: 1
+
+plugin complete -- nothing 'nothing -'
+# CHECKERR: plugin: no plugin provides completions for 'nothing'
+
+plugin unload nothing
+# CHECKERR: plugin: no plugin named 'nothing' is loaded
+
+plugin load /no/such/plugin.wasm
+echo $status
+# CHECKERR: plugin: {{.*}}
+# CHECK: 1
+
+plugin list --read-var PATH
+# CHECKERR: plugin: invalid option combination
+# CHECKERR:
+# CHECKERR: checks/plugin.fish (line {{\d+}}):
+# CHECKERR: plugin list --read-var PATH
+# CHECKERR: ^
+# CHECKERR: (Type 'help plugin' for related documentation)
+
+plugin frob
+# CHECKERR: plugin: frob: invalid subcommand
+# CHECKERR:
+# CHECKERR: checks/plugin.fish (line {{\d+}}):
+# CHECKERR: plugin frob
+# CHECKERR: ^
+# CHECKERR: (Type 'help plugin' for related documentation)
+
+plugin segment
+# CHECKERR: plugin: segment: expected 1 arguments; got 0
+
+plugin event nothing VARIABLE SET nothing
+# CHECKERR: plugin: no plugin handles events with 'nothing'
+
+plugin event
+# CHECKERR: plugin: expected >= 1 arguments; got 0
+
+# A file which is neither a WebAssembly module nor a shared library.
+set -l dir (mktemp -d)
+echo 'not a plugin' >$dir/libnothing.so
+plugin load $dir/libnothing.so
+echo $status
+# CHECKERR: plugin: plugin 'nothing': {{.*}}
+# CHECK: 1
+rm -r $dir
@@ -0,0 +1,27 @@
+#RUN: %fish %s
+
+set -l dir (mktemp -d)
+set -l realdir (builtin realpath $dir)
+mkdir $dir/sub
+echo contents >$dir/file
+cd $dir/sub
+
+# External commands start in fish's directory and get redirections onto their own fds, whether
+# they are launched with posix_spawn or fork.
+for spawn in 1 0
+    set -g fish_use_posix_spawn $spawn
+    command pwd -P | string replace -- $realdir DIR
+    command sh -c 'cat <&6' 6<$dir/file
+end
+# CHECK: DIR/sub
+# CHECK: contents
+# CHECK: DIR/sub
+# CHECK: contents
+
+# Also after changing the directory.
+cd ..
+command pwd -P | string replace -- $realdir DIR
+# CHECK: DIR
+
+cd /
+rm -r $dir
@@ -0,0 +1,14 @@
+#RUN: %fish %s
+
+function handler --on-signal USR1
+    echo $argv $fish_event_signal $fish_event_signal_count
+    test $fish_event_signal_number -gt 0
+    and echo number ok
+    test $fish_event_signal_time -gt 1600000000000
+    and echo time ok
+end
+
+kill -USR1 $fish_pid
+#CHECK: SIGUSR1 SIGUSR1 1
+#CHECK: number ok
+#CHECK: time ok
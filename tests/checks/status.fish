@@ -126,3 +126,96 @@ printf "%s\n" (test-stack-trace-copy | string replace \t '<TAB>')[1..4]
 # CHECK: <TAB>called on line {{\d+}} of file {{.*}}/status.fish
 # CHECK: in function 'test-stack-trace-copy'
 # CHECK: <TAB>called on line {{\d+}} of file {{.*}}/status.fish
+
+# status last-job reports the resources used by the last job.
+command sh -c 'true'
+status last-job | string replace -r '\t.*' ''
+# CHECK: wall
+# CHECK: user
+# CHECK: sys
+# CHECK: max-rss
+# CHECK: in-blocks
+# CHECK: out-blocks
+
+# status fds lists the fds fish holds, and what they are for.
+exec --open statusfd /dev/null
+exec --open --inherit passedfd /dev/null
+status fds | string match -r -- "^$statusfd\t.*" | string replace -a \t ' ' | string replace $statusfd N
+# CHECK: N cloexec exec --open statusfd
+status fds | string match -r -- "^$passedfd\t.*" | string replace -a \t ' ' | string replace $passedfd N
+# CHECK: N inherited exec --open passedfd
+exec --close $statusfd $passedfd
+status fds | string match -qr -- "^$statusfd\t"
+or echo closed
+# CHECK: closed
+
+# status multiplexer tells which multiplexer we run in, if any.
+TMUX=/tmp/tmux-1000/default,1234,0 $fish -c 'status multiplexer'
+# CHECK: tmux
+env -u TMUX STY=1234.pts-0.host $fish -c 'status multiplexer'
+# CHECK: screen
+env -u TMUX -u STY -u ZELLIJ $fish -c 'status multiplexer; echo $status'
+# CHECK: 1
+
+# Sequences are wrapped so the multiplexer passes them on.
+TMUX=x __fish_passthrough '\e]9;%s\a' hi | string replace -a \e E | string replace -a \a B
+# CHECK: EPtmux;EE]9;hiBE\
+env -u TMUX STY=x $fish -c "__fish_passthrough '\e]9;%s\a' hi" | string replace -a \e E | string replace -a \a B
+# CHECK: EPE]9;hiBE\
+env -u TMUX -u STY -u ZELLIJ $fish -c "__fish_passthrough '\e]9;%s\a' hi" | string replace -a \e E | string replace -a \a B
+# CHECK: E]9;hiB
+
+# status profile times the commands between start and stop.
+status profile start
+true
+status profile stop --format folded
+# CHECK: true {{\d+}}
+status profile start
+true
+status profile stop --json | string replace -r '"self_time":\d+,"total_time":\d+' T
+# CHECK: [{"type":"job","command":"true",T,"children":[]}]
+status profile stop
+# CHECKERR: status: profile: Not profiling
+status profile start
+status profile start
+# CHECKERR: status: profile: Already profiling
+status profile stop >/dev/null
+status profile bogus
+# CHECKERR: status: profile: Invalid action 'bogus'
+status fds --format json
+# CHECKERR: status: fds: --format is not supported
+# CHECKERR:
+# CHECKERR: checks/status.fish (line {{\d+}}):
+# CHECKERR: status fds --format json
+# CHECKERR: ^
+# CHECKERR: (Type 'help status' for related documentation)
+
+# status autoload shows which file provides a function, and which are shadowed.
+set -l autoload_dir (mktemp -d)
+mkdir $autoload_dir/a $autoload_dir/b
+echo 'function autoloaded; echo b; end' >$autoload_dir/b/autoloaded.fish
+set -l old_function_path $fish_function_path
+set fish_function_path $autoload_dir/a $autoload_dir/b $fish_function_path
+autoloaded
+# CHECK: b
+echo 'function autoloaded; echo a; end' >$autoload_dir/a/autoloaded.fish
+status autoload autoloaded | string replace -a $autoload_dir DIR
+# CHECK: function autoloaded:
+# CHECK:   DIR/a/autoloaded.fish (wins, it is first in $fish_function_path)
+# CHECK:   DIR/b/autoloaded.fish (shadowed by the first file)
+# CHECK:   still loaded from DIR/b/autoloaded.fish until it is loaded again
+# CHECK: completions for autoloaded:
+# CHECK:   no file in $fish_complete_path
+functions --erase autoloaded
+status autoload autoloaded | string replace -a $autoload_dir DIR
+# CHECK: function autoloaded:
+# CHECK:   erased with `functions --erase`, so it won't be autoloaded
+# CHECK:   DIR/a/autoloaded.fish (first in $fish_function_path, but unused)
+# CHECK:   DIR/b/autoloaded.fish (shadowed by the first file)
+# CHECK: completions for autoloaded:
+# CHECK:   no file in $fish_complete_path
+status autoload not-autoloaded-anywhere >/dev/null
+echo $status
+# CHECK: 1
+set fish_function_path $old_function_path
+rm -r $autoload_dir
@@ -0,0 +1,24 @@
+#RUN: %fish %s
+#REQUIRES: command -v tmux
+# disable on github actions because it's flakey
+#REQUIRES: test -z "$CI"
+
+set -g isolated_tmux_fish_extra_args -C '
+    set -g fish_autosuggestion_providers last-argument
+'
+isolated-tmux-start
+mkdir -p walnut/almond
+
+isolated-tmux send-keys 'echo first-argument second-argument' Enter
+tmux-sleep
+isolated-tmux send-keys 'echo sec'
+tmux-sleep
+isolated-tmux capture-pane -p | grep 'prompt 1'
+# CHECK: prompt 1> echo second-argument
+
+isolated-tmux send-keys C-u 'set fish_autosuggestion_providers cd' Enter
+tmux-sleep
+isolated-tmux send-keys 'cd walnut/al'
+tmux-sleep
+isolated-tmux capture-pane -p | grep 'prompt 2'
+# CHECK: prompt 2> cd walnut/almond/
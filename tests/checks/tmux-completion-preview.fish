@@ -0,0 +1,42 @@
+#RUN: %fish %s
+#REQUIRES: command -v tmux
+# disable on github actions because it's flakey
+#REQUIRES: test -z "$CI"
+
+set -g isolated_tmux_fish_extra_args -C '
+    complete -c foo -f -a "apple\tfruit banana\tfruit"
+    complete -c slow -f -a "slowpoke slowcoach"
+    function fish_completion_preview
+        if string match -q "slow*" -- $argv[1]
+            sleep 2
+        end
+        echo preview of $argv
+    end
+'
+isolated-tmux-start
+
+isolated-tmux send-keys 'foo ' Tab Tab
+tmux-sleep
+isolated-tmux capture-pane -p | grep -e 'prompt 0' -e 'preview'
+# CHECK: prompt 0> foo apple
+# CHECK: preview of apple fruit
+
+isolated-tmux send-keys Tab
+tmux-sleep
+isolated-tmux capture-pane -p | grep preview
+# CHECK: preview of banana fruit
+
+# Closing the pager removes the preview.
+isolated-tmux send-keys C-c
+tmux-sleep
+isolated-tmux capture-pane -p | grep -c preview
+# CHECK: 0
+
+# A slow preview doesn't keep us from typing, and is dropped once the command line changed.
+isolated-tmux send-keys 'slow ' Tab Tab C-u 'echo typed'
+tmux-sleep
+isolated-tmux capture-pane -p | grep -e typed -e preview
+# CHECK: prompt 0> echo typed
+sleep 2.5
+isolated-tmux capture-pane -p | grep -c preview
+# CHECK: 0
@@ -0,0 +1,37 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+import os
+import tempfile
+
+tmp = tempfile.mkdtemp()
+funcfile = os.path.join(tmp, "watched.fish")
+
+
+def write_function(body):
+    with open(funcfile, "w") as f:
+        f.write("function watched\n    echo %s\nend\n" % body)
+
+
+write_function("first")
+
+sp = SpawnedProc()
+sp.expect_prompt()
+sp.sendline("set -p fish_function_path " + tmp)
+sp.expect_prompt()
+sp.sendline("set -g fish_autoload_watch 1")
+sp.expect_prompt()
+sp.sendline("watched")
+sp.expect_prompt("first")
+
+# An edited function is used by the very next command.
+write_function("second version")
+sp.sendline("watched")
+sp.expect_prompt("second version")
+
+# A function redefined by hand stays as it is.
+sp.sendline("function watched; echo by hand; end")
+sp.expect_prompt()
+write_function("third")
+sp.sendline("watched")
+sp.expect_prompt("by hand")
+
@@ -210,6 +210,35 @@ sleep(0.200)
 send("hhtTrN\r")
 expect_prompt("\r\nTENT\r\n", unmatched="Couldn't find expected output 'TENT'")
 
+# Test text objects, which read their object character like forward-jump does.
+send("\033")
+sleep(0.200)
+send("ddiecho foo bar baz")
+expect_str("echo foo bar baz")
+send("\033")
+sleep(0.200)
+send("bbdaw\r")
+expect_prompt("\r\nfoo baz\r\n", unmatched="vi daw did not delete the word")
+
+send("\033")
+sleep(0.200)
+send("ddiecho 'a (b)' \"c d\"")
+expect_str("echo 'a (b)' \"c d\"")
+send("\033")
+sleep(0.200)
+send('ci"x\r')
+expect_prompt("\r\na (b) x\r\n", unmatched="vi ci\" did not change the quoted string")
+
+# Test registers: copy a word into register "a" and paste it at the end.
+send("\033")
+sleep(0.200)
+send("ddiecho foo bar")
+expect_str("echo foo bar")
+send("\033")
+sleep(0.200)
+send('b"ayiw$"ap\r')
+expect_prompt("\r\nfoo barbar\r\n", unmatched="vi register a was not pasted")
+
 # Test '~' (togglecase-char)
 # HACK: Deactivated because it keeps failing on CI
 # send("\033")
@@ -0,0 +1,17 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+
+sp = SpawnedProc()
+sendline, expect_prompt = sp.sendline, sp.expect_prompt
+expect_prompt()
+
+sendline("echo $CMD_DURATION $cmd_duration_us")
+expect_prompt("0 0")
+
+# The microseconds agree with the milliseconds.
+sendline("sleep 0.2")
+expect_prompt()
+sendline(
+    "test $cmd_duration_us -ge 200000; and test $CMD_DURATION -eq (math --scale 0 'round($cmd_duration_us / 1000)'); and echo duration ok"
+)
+expect_prompt("duration ok")
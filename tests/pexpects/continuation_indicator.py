@@ -0,0 +1,24 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+
+sp = SpawnedProc()
+send, sendline, expect_prompt, expect_re = sp.send, sp.sendline, sp.expect_prompt, sp.expect_re
+expect_prompt()
+
+sendline("set fish_continuation_indicator '>>'")
+expect_prompt()
+
+# The indicator is drawn in front of the second line, in its own color.
+# Alt-Enter inserts a newline.
+send("echo one\x1b\rtwo")
+expect_re(r">>(\x1b\[[0-9;]*m)*two")
+sendline("")
+expect_prompt("one\r\ntwo")
+
+# Control characters are dropped from it.
+sendline("set fish_continuation_indicator \\a'<<'")
+expect_prompt()
+send("echo three\x1b\rfour")
+expect_re(r"<<(\x1b\[[0-9;]*m)*four")
+sendline("")
+expect_prompt("three\r\nfour")
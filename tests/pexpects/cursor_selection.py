@@ -87,3 +87,10 @@ expect_str("<c>")
 sendline("set fish_cursor_selection_mode unknown")
 sendline("echo" + home + right + select + right + dump)
 expect_str("<c>")
+
+# Turn a selection over two lines into two cursors, and type at both.
+sendline("bind \\cx multi-cursor-from-selection")
+# Alt-Enter inserts a newline, Up moves to the line above.
+sp.send("echoA\x1b\rechoB" + left + select + "\x1b[A" + "\x18" + " ")
+sendline("")
+expect_str("A\r\nB")
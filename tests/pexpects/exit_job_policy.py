@@ -0,0 +1,91 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+import subprocess
+import sys
+import time
+
+sp = SpawnedProc()
+send, sendline, sleep, expect_prompt, expect_re = (
+    sp.send,
+    sp.sendline,
+    sp.sleep,
+    sp.expect_prompt,
+    sp.expect_re,
+)
+expect_prompt()
+
+# With the disown policy, exiting does not warn, and leaves the jobs running.
+send("set fish_exit_job_policy disown\r")
+expect_prompt()
+send("sleep 131 &\r")
+expect_prompt()
+send("exit\r")
+expect_re(
+    """Leaving jobs running:\r
+\r
+   PID  Command\r
+ *\\d+  sleep 131 &\r"""
+)
+
+for t in range(0, 3):
+    proc = subprocess.run(
+        ["pgrep", "-f", "sleep 131"],
+        stdout=subprocess.PIPE,
+        stderr=subprocess.PIPE,
+    )
+    if proc.returncode == 0:
+        break
+    time.sleep(1)
+else:
+    print("Disowned job is not running")
+    sys.exit(1)
+subprocess.run(["pkill", "-f", "sleep 131"])
+
+# With the kill policy, exiting terminates the jobs right away.
+sp = SpawnedProc()
+send, expect_prompt, expect_re = sp.send, sp.expect_prompt, sp.expect_re
+expect_prompt()
+send("set fish_exit_job_policy kill\r")
+expect_prompt()
+send("sleep 132 &\r")
+expect_prompt()
+send("exit\r")
+expect_re(
+    """Terminating jobs:\r
+\r
+   PID  Command\r
+ *\\d+  sleep 132 &\r"""
+)
+
+for t in range(0, 3):
+    proc = subprocess.run(
+        ["pgrep", "-l", "-f", "sleep 132"],
+        stdout=subprocess.PIPE,
+        stderr=subprocess.PIPE,
+    )
+    if proc.returncode != 0:
+        break
+    time.sleep(1)
+else:
+    print("Commands still running")
+    print(proc.stdout)
+    sys.exit(1)
+
+# An invalid policy warns once when it is set, and then behaves like "prompt".
+sp = SpawnedProc()
+send, expect_prompt, expect_re = sp.send, sp.expect_prompt, sp.expect_re
+expect_prompt()
+send("set fish_exit_job_policy bogus\r")
+expect_re("warning: Invalid value for fish_exit_job_policy: bogus")
+expect_prompt()
+send("sleep 133 &\r")
+expect_prompt()
+send("exit\r")
+expect_re("There are still jobs active:")
+if "Invalid value" in sp.spawn.before:
+    print("Warned about fish_exit_job_policy again on exit")
+    sys.exit(1)
+expect_prompt()
+send("exit\r")
+sp.spawn.wait()
+subprocess.run(["pkill", "-f", "sleep 133"])
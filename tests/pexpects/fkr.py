@@ -49,3 +49,29 @@ sleep(0.050)
 send("\x04\x04")
 expect_str("char: \\cD\r\n")
 expect_str("Exiting at your request.\r\n")
+
+# JSON output in stream mode.
+sp = SpawnedProc()
+send, sendline, sleep, expect_prompt, expect_str = (
+    sp.send,
+    sp.sendline,
+    sp.sleep,
+    sp.expect_prompt,
+    sp.expect_str,
+)
+expect_prompt()
+sendline("exec $fish_key_reader --stream --format=json")
+expect_str("Press a key:")
+
+send("\x1b[1;5C")
+expect_str(
+    '{"sequence":"\\\\e\\\\[1\\\\;5C","bytes":[27,91,49,59,53,67],"key":"right","modifiers":["ctrl"]'
+)
+
+# Keys that would end a continuous session are reported like any other.
+sleep(0.050)
+send("\x04")
+sleep(0.050)
+send("\x04")
+expect_str('"bytes":[4],"key":"d","modifiers":["ctrl"]')
+expect_str('"bytes":[4],"key":"d","modifiers":["ctrl"]')
@@ -0,0 +1,24 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+
+# A slow greeting doesn't hold up the prompt. It shows up above the prompt when it's done.
+sp = SpawnedProc(
+    args=["-C", "function fish_greeting; sleep 0.5; echo slow greeting; end"]
+)
+sp.expect_prompt()
+sp.expect_prompt("slow greeting", increment=False)
+sp.sendline("echo still here")
+sp.expect_prompt("still here")
+sp.sendline("exit")
+
+# With $fish_greeting_cache, the next shell reuses the greeting right away.
+cached = "set -g fish_greeting_cache 1; function fish_greeting; echo greeting (random); end"
+sp = SpawnedProc(args=["-C", cached])
+sp.expect_prompt()
+sp.expect_re(r"greeting (\d+)")
+number = sp.spawn.match.group(1)
+sp.sendline("exit")
+
+sp = SpawnedProc(args=["-C", cached])
+sp.expect_re("greeting " + number)
+sp.expect_prompt()
@@ -0,0 +1,50 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+
+sp = SpawnedProc()
+send, sendline, sleep, expect_prompt, expect_re, expect_str = (
+    sp.send,
+    sp.sendline,
+    sp.sleep,
+    sp.expect_prompt,
+    sp.expect_re,
+    sp.expect_str,
+)
+
+# Pretend to be a terminal that speaks the kitty keyboard protocol, by answering its flags query
+# and DA1, which ends the queries.
+expect_str("\x1b[?u")
+expect_str("\x1b[c")
+send("\x1b[?0u\x1b[?62;c")
+
+# fish has the terminal report keys like ctrl-i while it reads the command line.
+expect_str("\x1b[>1u")
+expect_prompt()
+
+sendline("bind -k ctrl-i 'commandline -i ctrl-i-pressed'")
+expect_str("\x1b[<u")
+expect_prompt()
+
+# ctrl-i, as the terminal reports it, runs the binding. The mode is turned off while the binding
+# runs, and on again afterwards.
+send("string upper \x1b[105;5u")
+expect_str("\x1b[<u")
+expect_str("\x1b[>1u")
+sendline("")
+expect_prompt("CTRL-I-PRESSED")
+
+# Commands run from bindings, which might hand the terminal to a program like fzf, don't see the
+# mode either.
+sendline("bind -k ctrl-i 'echo binding-ran'")
+expect_prompt()
+send("\x1b[105;5u")
+expect_str("\x1b[<u")
+expect_str("binding-ran")
+expect_str("\x1b[>1u")
+
+# Plain Tab is still Tab.
+sendline("bind \\t 'commandline -i tab-pressed'")
+expect_prompt()
+send("string upper \t")
+sendline("")
+expect_prompt("TAB-PRESSED")
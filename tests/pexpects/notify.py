@@ -0,0 +1,40 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+
+sp = SpawnedProc()
+send, sendline, expect_prompt, expect_re = (
+    sp.send,
+    sp.sendline,
+    sp.expect_prompt,
+    sp.expect_re,
+)
+expect_prompt()
+
+sendline(
+    "function fish_notify; set -ga notified $argv; end; set -g fish_notify_duration 100"
+)
+expect_prompt()
+
+# A long command notifies once the terminal reports that it is unfocused.
+sendline("sleep 0.2")
+expect_prompt()
+send("\x1b[O")
+sendline("echo notified $notified")
+expect_prompt(r"notified sleep 0.2 \d+ 0\r\n")
+
+# Coming back to the terminal first means no notification.
+send("\x1b[I")
+sendline("set -e notified; sleep 0.2")
+expect_prompt()
+send("\x1b[I")
+send("\x1b[O")
+sendline("echo notified (count $notified)")
+expect_prompt("notified 0")
+
+# Nor for short commands.
+send("\x1b[I")
+sendline("true")
+expect_prompt()
+send("\x1b[O")
+sendline("echo notified (count $notified)")
+expect_prompt("notified 0")
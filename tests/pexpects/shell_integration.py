@@ -0,0 +1,32 @@
+#!/usr/bin/env python3
+import os
+
+from pexpect_helper import SpawnedProc
+
+# Pretend to be a terminal with shell integration, outside of a multiplexer.
+env = os.environ.copy()
+env["TERM_PROGRAM"] = "WezTerm"
+env.pop("TMUX", None)
+env.pop("STY", None)
+
+sp = SpawnedProc(env=env)
+sendline, expect_prompt, expect_str = sp.sendline, sp.expect_prompt, sp.expect_str
+
+# The prompt is marked.
+expect_str("\x1b]133;A\x07")
+expect_prompt()
+
+# So are the output and status of commands.
+sendline("echo output; false")
+expect_str("\x1b]133;C\x07")
+expect_str("output")
+expect_str("\x1b]133;D;1\x07")
+expect_prompt()
+
+# iTerm2 also gets the directory. Setting $fish_shell_integration makes fish detect the
+# terminal again.
+sendline("set -g TERM_PROGRAM iTerm.app; set -g fish_shell_integration")
+expect_prompt()
+sendline("cd /")
+expect_str("\x1b]1337;CurrentDir=/\x07")
+expect_prompt()
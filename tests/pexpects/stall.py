@@ -0,0 +1,40 @@
+#!/usr/bin/env python3
+from pexpect_helper import SpawnedProc
+import sys
+import tempfile
+import time
+
+# Verify that with the stall debug category, a stuck main thread is reported once.
+with tempfile.NamedTemporaryFile(mode="r", encoding="utf8") as tf:
+    sp = SpawnedProc(args=["-d", "stall", "-o", tf.name])
+    send, sendline, sleep, expect_prompt = (
+        sp.send,
+        sp.sendline,
+        sp.sleep,
+        sp.expect_prompt,
+    )
+    expect_prompt()
+
+    # Waiting for a command is not a stall.
+    sendline("sleep 3")
+    expect_prompt()
+    if "main thread has been busy" in tf.read():
+        print("Waiting for a command was reported as a stall")
+        sys.exit(1)
+
+    # A busy loop is.
+    sendline("while true; end")
+    report = ""
+    deadline = time.monotonic() + 10
+    while "main thread has been busy" not in report and time.monotonic() < deadline:
+        sleep(0.2)
+        report += tf.read()
+    send("\x03")
+    expect_prompt()
+
+    if "main thread has been busy" not in report:
+        print("No stall was reported")
+        sys.exit(1)
+    if "(main):" not in report:
+        print("The stall report lacks the main thread:\n" + report)
+        sys.exit(1)
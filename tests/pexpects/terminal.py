@@ -34,6 +34,25 @@ expect_str("20 70")
 sendline("echo $LINES $COLUMNS")
 expect_prompt("20 70")
 
+# Resizing to the size we already have does not repaint, and a real resize repaints once.
+sendline("functions -e on-winch; set -g fish_handle_reflow 1")
+expect_prompt()
+sendline(
+    "set -g prompt_runs 0; functions -c fish_prompt orig_prompt; function fish_prompt; set -g prompt_runs (math $prompt_runs + 1); orig_prompt; end"
+)
+expect_prompt()
+sendline("set -g prompt_runs 0")
+expect_prompt()
+sp.spawn.setwinsize(20, 70)
+sp.spawn.setwinsize(20, 70)
+sleep(0.5)
+sendline("echo runs $prompt_runs")
+expect_prompt("runs 1")
+sp.spawn.setwinsize(30, 60)
+sleep(0.5)
+sendline("echo runs $prompt_runs")
+expect_prompt("runs 3")
+
 sendline("stty -a")
 expect_prompt()
 # Confirm flow control in the shell is disabled - we should ignore the ctrl-s in there.
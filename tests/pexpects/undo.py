@@ -32,3 +32,23 @@ expect_str("echo wor")
 # FIXME see above.
 send("Redo")
 expect_str("echo word")
+
+sendline("")
+expect_prompt("word")
+
+# Going back to a checkpoint, which can be undone in turn.
+sendline("bind Check 'commandline --checkpoint start'; bind Back 'commandline --revert start'")
+expect_prompt()
+
+send("echo one")
+send("Check")
+send(" two")
+expect_str("echo one two")
+send("Back")
+sendline("")
+expect_prompt("one\r\n")
+
+send("echo three")
+send("Back")
+sendline("")
+expect_prompt("No checkpoint named 'start'")
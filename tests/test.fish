@@ -38,6 +38,7 @@ if set -q files_to_test[1]
         --progress $force_color \
         -s fish=../test/root/bin/fish \
         -s fish_test_helper=../test/root/bin/fish_test_helper \
+        -s fish_test_plugin=../test/root/bin/libfish_test_plugin.so \
         $files_to_test
 
     set -l littlecheck_status $status
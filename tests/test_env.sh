@@ -45,6 +45,10 @@ XDG_CONFIG_HOME="$homedir/xdg_config_home"
 export XDG_CONFIG_HOME
 mkdir -p $XDG_CONFIG_HOME/fish || die
 
+XDG_CACHE_HOME="$homedir/xdg_cache_home"
+export XDG_CACHE_HOME
+mkdir -p $XDG_CACHE_HOME/fish || die
+
 XDG_RUNTIME_DIR="$homedir/xdg_runtime_dir"
 export XDG_RUNTIME_DIR
 mkdir -p $XDG_RUNTIME_DIR/fish || die